@@ -0,0 +1,222 @@
+use std::hint::black_box;
+use std::io;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use kdmp_parser::{Gpa, Gva, Gxa, KernelDumpParser, ModuleMap};
+
+/// Where the page tables live at.
+const PML4: u64 = 0x1_000;
+const PDPT: u64 = 0x2_000;
+const PD: u64 = 0x3_000;
+const PT: u64 = 0x4_000;
+/// Where the mapped pages start at in physical memory.
+const DATA: u64 = 0x10_000;
+/// How many pages are mapped.
+const DATA_PAGES: u64 = 0x100;
+/// Where the mapped pages start at in virtual memory.
+const BASE: u64 = 0xffff_f805_1060_0000;
+/// Read sizes for the large read benchmarks.
+const ONE_MEG: usize = 0x10_0000;
+
+/// Build a small BMP dump in memory. It has a 4-level page table hierarchy
+/// mapping [`DATA_PAGES`] pages at [`BASE`], and every page is filled with a
+/// deterministic pattern.
+fn synthetic_dump() -> Vec<u8> {
+    let gva = Gva::new(BASE);
+    let mut pages = vec![
+        (PML4, gva.pml4e_idx(), PDPT),
+        (PDPT, gva.pdpe_idx(), PD),
+        (PD, gva.pde_idx(), PT),
+    ]
+    .into_iter()
+    .map(|(gpa, idx, next)| {
+        let mut page = vec![0; 0x1_000];
+        let idx = usize::try_from(idx).unwrap() * 8;
+        page[idx..idx + 8].copy_from_slice(&(next | 0b11).to_le_bytes());
+
+        (gpa, page)
+    })
+    .collect::<Vec<_>>();
+
+    let mut pt = vec![0; 0x1_000];
+    for page_idx in 0..DATA_PAGES {
+        let pte = (DATA + (page_idx * 0x1_000)) | 0b11;
+        let idx = usize::try_from(gva.pte_idx() + page_idx).unwrap() * 8;
+        pt[idx..idx + 8].copy_from_slice(&pte.to_le_bytes());
+    }
+    pages.push((PT, pt));
+
+    for page_idx in 0..DATA_PAGES {
+        let page = (0..0x1_000u64)
+            .map(|i| (i ^ page_idx) as u8)
+            .collect::<Vec<_>>();
+        pages.push((DATA + (page_idx * 0x1_000), page));
+    }
+
+    // The main header..
+    let mut dump = vec![0; 0x2_000];
+    dump[0x0..0x4].copy_from_slice(b"PAGE");
+    dump[0x4..0x8].copy_from_slice(b"DU64");
+    dump[0x10..0x18].copy_from_slice(&PML4.to_le_bytes());
+    dump[0xf98..0xf9c].copy_from_slice(&5u32.to_le_bytes());
+
+    // ..the bitmap header..
+    let max_pfn = DATA / 0x1_000 + DATA_PAGES;
+    let bitmap_size = (max_pfn + 7) / 8;
+    let first_page = 0x2_000 + 0x38 + bitmap_size;
+    let mut bmp = vec![0; 0x38];
+    bmp[0x0..0x4].copy_from_slice(b"SDMP");
+    bmp[0x4..0x8].copy_from_slice(b"DUMP");
+    bmp[0x20..0x28].copy_from_slice(&first_page.to_le_bytes());
+    bmp[0x28..0x30].copy_from_slice(&(pages.len() as u64).to_le_bytes());
+    bmp[0x30..0x38].copy_from_slice(&(bitmap_size * 8).to_le_bytes());
+    dump.extend_from_slice(&bmp);
+
+    // ..the bitmap itself..
+    pages.sort_by_key(|(gpa, _)| *gpa);
+    let mut bitmap = vec![0u8; bitmap_size.try_into().unwrap()];
+    for (gpa, _) in &pages {
+        let pfn = usize::try_from(gpa / 0x1_000).unwrap();
+        bitmap[pfn / 8] |= 1 << (pfn % 8);
+    }
+    dump.extend_from_slice(&bitmap);
+
+    // ..and the pages, in ascending physical address order.
+    for (_, page) in pages {
+        dump.extend_from_slice(&page);
+    }
+
+    dump
+}
+
+fn parser() -> KernelDumpParser {
+    KernelDumpParser::with_reader(io::Cursor::new(synthetic_dump())).unwrap()
+}
+
+fn small_reads(c: &mut Criterion) {
+    let parser = parser();
+    let mut group = c.benchmark_group("small reads");
+    for size in [1usize, 8, 16] {
+        let mut buffer = vec![0; size];
+        group.bench_function(format!("virt_read {size}"), |b| {
+            let mut gva = Gva::new(BASE);
+            b.iter(|| {
+                parser.virt_read_exact(black_box(gva), &mut buffer).unwrap();
+                gva = Gva::new(BASE + ((gva.u64() + 0x1_08) % (DATA_PAGES * 0x1_000 - 0x10)));
+            })
+        });
+
+        group.bench_function(format!("phys_read {size}"), |b| {
+            let mut gpa = Gpa::new(DATA);
+            b.iter(|| {
+                parser.phys_read_exact(black_box(gpa), &mut buffer).unwrap();
+                gpa = Gpa::new(DATA + ((gpa.u64() + 0x1_08) % (DATA_PAGES * 0x1_000 - 0x10)));
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn large_reads(c: &mut Criterion) {
+    let parser = parser();
+    let mut group = c.benchmark_group("large reads");
+    group.throughput(Throughput::Bytes(ONE_MEG as u64));
+    group.bench_function("virt_read 1mb", |b| {
+        b.iter_batched_ref(
+            || vec![0; ONE_MEG],
+            |buffer| parser.virt_read_exact(black_box(Gva::new(BASE)), buffer),
+            BatchSize::LargeInput,
+        )
+    });
+
+    // A fresh parser has nothing in its translation cache, so every page is
+    // walked.
+    group.bench_function("virt_read 1mb cold", |b| {
+        b.iter_batched_ref(
+            || (self::parser(), vec![0; ONE_MEG]),
+            |(cold, buffer)| cold.virt_read_exact(black_box(Gva::new(BASE)), buffer),
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.bench_function("phys_read 1mb", |b| {
+        b.iter_batched_ref(
+            || vec![0; ONE_MEG],
+            |buffer| parser.phys_read_exact(black_box(Gpa::new(DATA)), buffer),
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.bench_function("virt_read_to_vec 1mb", |b| {
+        b.iter(|| parser.virt_read_to_vec(black_box(Gva::new(BASE)), ONE_MEG as u64))
+    });
+
+    group.finish();
+}
+
+fn translations(c: &mut Criterion) {
+    let parser = parser();
+    c.bench_function("virt_translate", |b| {
+        let mut gva = Gva::new(BASE);
+        b.iter(|| {
+            parser.virt_translate(black_box(gva)).unwrap();
+            gva = Gva::new(BASE + ((gva.u64() + 0x1_08) % (DATA_PAGES * 0x1_000)));
+        })
+    });
+
+    c.bench_function("prefetch_virt", |b| {
+        let range = Gva::new(BASE)..Gva::new(BASE + (DATA_PAGES * 0x1_000));
+        b.iter(|| {
+            let report = parser.prefetch_virt(black_box(range.clone()), None);
+            assert!(report.all_resolved());
+        })
+    });
+}
+
+fn module_lookups(c: &mut Criterion) {
+    // Lay out a few hundred modules, like a process with a lot of DLLs loaded.
+    const MODULES: u64 = 0x200;
+    const MODULE_SIZE: u64 = 0x10_000;
+    let modules = (0..MODULES)
+        .map(|i| {
+            let start = BASE + (i * MODULE_SIZE * 2);
+
+            (
+                Gva::new(start)..Gva::new(start + MODULE_SIZE),
+                format!("module{i}.dll"),
+            )
+        })
+        .collect::<Vec<_>>();
+    let map = modules.iter().cloned().collect::<ModuleMap>();
+    let next =
+        |gva: Gva| Gva::new(BASE + ((gva.u64() - BASE + 0x13_37) % (MODULES * MODULE_SIZE * 2)));
+
+    let mut group = c.benchmark_group("module lookups");
+    group.bench_function("linear", |b| {
+        let mut gva = Gva::new(BASE);
+        b.iter(|| {
+            black_box(modules.iter().find(|(at, _)| at.contains(&gva)));
+            gva = next(gva);
+        })
+    });
+
+    group.bench_function("find", |b| {
+        let mut gva = Gva::new(BASE);
+        b.iter(|| {
+            black_box(map.find(black_box(gva)));
+            gva = next(gva);
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    small_reads,
+    large_reads,
+    translations,
+    module_lookups
+);
+criterion_main!(benches);
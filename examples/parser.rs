@@ -21,6 +21,9 @@ enum ReaderMode {
 struct Args {
     /// The dump path.
     dump_path: PathBuf,
+    /// Dump a summary of the dump.
+    #[arg(short, long)]
+    info: bool,
     /// Dump the dump headers.
     #[arg(long, default_value_t = false)]
     dump_headers: bool,
@@ -96,6 +99,10 @@ fn main() -> Result<()> {
     }
     .context("failed to parse the kernel dump")?;
 
+    if args.info {
+        println!("{}", parser.info());
+    }
+
     if args.dump_headers {
         println!("{:?}", parser.headers());
     }
@@ -109,8 +116,9 @@ fn main() -> Result<()> {
     }
 
     if args.modules {
-        for (at, module) in parser.user_modules().chain(parser.kernel_modules()) {
-            println!("{:#x}-{:#x}: {module}", at.start.u64(), at.end.u64());
+        for module in parser.modules() {
+            let at = &module.at;
+            println!("{:#x}-{:#x}: {}", at.start.u64(), at.end.u64(), module.name);
         }
     }
 
@@ -0,0 +1,258 @@
+//! This contains [`AddressSpace`], a handle to read and translate virtual
+//! memory in an explicit address space: the kernel's (see
+//! [`KernelDumpParser::kernel_space`]) or a process' (see
+//! [`KernelDumpParser::process_space`]).
+//!
+//! The virtual memory methods of [`KernelDumpParser`] itself are shorthand
+//! for the address space of the crashing context, the one of
+//! [`KernelDumpParser::default_dtb`]: a user address read with them is read
+//! in the crashing process.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::{Gva, KernelDumpParser};
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! let eprocess = Gva::new(0xffff_c000_0000_0000);
+//! let process = parser.process_space(eprocess).unwrap();
+//! let mut mz = [0; 2];
+//! process.virt_read_exact(0x7ff6_0000_0000, &mut mz).unwrap();
+//!
+//! // The kernel space refuses the user addresses.
+//! assert!(parser.kernel_space().virt_translate(0x7ff6_0000_0000).is_err());
+//! ```
+use crate::error::Result;
+use crate::gxa::Gxa;
+use crate::nt::KERNEL_SPACE_START;
+use crate::parse::{IoSpan, ReadMode};
+use crate::{AddrTranslationError, FromLeBytes, Gpa, Gva, KernelDumpParser};
+
+/// What a process' address space is picked with; see
+/// [`KernelDumpParser::process_space`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessRef {
+    /// The `_EPROCESS` of the process.
+    Eprocess(Gva),
+    /// The directory table base of the process.
+    Dtb(Gpa),
+}
+
+impl From<Gva> for ProcessRef {
+    fn from(eprocess: Gva) -> Self {
+        Self::Eprocess(eprocess)
+    }
+}
+
+impl From<Gpa> for ProcessRef {
+    fn from(dtb: Gpa) -> Self {
+        Self::Dtb(dtb)
+    }
+}
+
+/// A virtual address space of the dump: a directory table base, and the
+/// parser to read memory with. It is created by
+/// [`KernelDumpParser::kernel_space`] and
+/// [`KernelDumpParser::process_space`], and is cheap to copy around.
+#[derive(Debug, Clone, Copy)]
+pub struct AddressSpace<'parser> {
+    parser: &'parser KernelDumpParser,
+    dtb: Gpa,
+    kernel: bool,
+}
+
+impl<'parser> AddressSpace<'parser> {
+    /// The directory table base of the address space.
+    pub fn dtb(&self) -> Gpa {
+        self.dtb
+    }
+
+    /// Is it the kernel address space, which only has the kernel addresses?
+    pub fn is_kernel(&self) -> bool {
+        self.kernel
+    }
+
+    /// Check that `gva` belongs to the address space.
+    fn check(&self, gva: Gva) -> Result<Gva> {
+        if self.kernel && gva.u64() < KERNEL_SPACE_START {
+            return Err(AddrTranslationError::UserAddress(gva).into());
+        }
+
+        Ok(gva)
+    }
+
+    /// Translate a [`Gva`] into a [`Gpa`]; see
+    /// [`KernelDumpParser::virt_translate_with_dtb`].
+    pub fn virt_translate(&self, gva: impl Into<Gva>) -> Result<Gpa> {
+        let gva = self.check(gva.into())?;
+        self.parser.virt_translate_with_dtb(gva, self.dtb)
+    }
+
+    /// Read virtual memory starting at `gva` into a `buffer`.
+    pub fn virt_read(&self, gva: impl Into<Gva>, buffer: &mut [u8]) -> Result<usize> {
+        let gva = self.check(gva.into())?;
+        self.parser.virt_read_with_dtb(gva, buffer, self.dtb)
+    }
+
+    /// Read an exact amount of virtual memory starting at `gva`.
+    pub fn virt_read_exact(&self, gva: impl Into<Gva>, buffer: &mut [u8]) -> Result<()> {
+        let gva = self.check(gva.into())?;
+        self.parser.virt_read_exact_with_dtb(gva, buffer, self.dtb)
+    }
+
+    /// Read a `T` from virtual memory.
+    pub fn virt_read_struct<T: FromLeBytes>(&self, gva: impl Into<Gva>) -> Result<T> {
+        let gva = self.check(gva.into())?;
+        self.parser.virt_read_struct_with_dtb(gva, self.dtb)
+    }
+
+    /// Read a pointer from virtual memory.
+    pub fn virt_read_ptr(&self, gva: impl Into<Gva>) -> Result<Gva> {
+        self.virt_read_struct::<u64>(gva).map(Gva::new)
+    }
+
+    /// Read `len` bytes of virtual memory starting at `gva` into a new buffer;
+    /// see [`KernelDumpParser::virt_read_to_vec`].
+    pub fn virt_read_to_vec(&self, gva: impl Into<Gva>, len: u64) -> Result<Vec<u8>> {
+        self.virt_read_to_vec_with_mode(gva, len, ReadMode::Strict)
+    }
+
+    /// Read `len` bytes of virtual memory starting at `gva` into a new buffer;
+    /// `mode` decides what happens with the bytes that aren't available in
+    /// the dump.
+    pub fn virt_read_to_vec_with_mode(
+        &self,
+        gva: impl Into<Gva>,
+        len: u64,
+        mode: ReadMode,
+    ) -> Result<Vec<u8>> {
+        let gva = self.check(gva.into())?;
+        self.parser
+            .virt_read_to_vec_with_dtb(gva, len, mode, self.dtb)
+    }
+
+    /// Plan a read of `len` bytes of virtual memory starting at `gva`; see
+    /// [`KernelDumpParser::plan_phys_read`].
+    pub fn plan_virt_read(&self, gva: impl Into<Gva>, len: usize) -> Result<Vec<IoSpan>> {
+        let gva = self.check(gva.into())?;
+        self.parser.plan_virt_read_with_dtb(gva, len, self.dtb)
+    }
+}
+
+impl KernelDumpParser {
+    /// Get the kernel address space. The kernel half is the same in every
+    /// process, so it is translated with [`KernelDumpParser::default_dtb`];
+    /// the user addresses are refused with an
+    /// [`AddrTranslationError::UserAddress`] instead of being read in
+    /// whatever process crashed.
+    pub fn kernel_space(&self) -> AddressSpace<'_> {
+        AddressSpace {
+            parser: self,
+            dtb: self.default_dtb(),
+            kernel: true,
+        }
+    }
+
+    /// Get the address space of a process, picked with its `_EPROCESS` (a
+    /// [`Gva`]) or its directory table base (a [`Gpa`]). The directory table
+    /// base of an `_EPROCESS` is read in the kernel address space.
+    pub fn process_space(&self, process: impl Into<ProcessRef>) -> Result<AddressSpace<'_>> {
+        let dtb = match process.into() {
+            ProcessRef::Eprocess(eprocess) => Gpa::new(self.read_field(
+                self.kernel_space().check(eprocess)?,
+                "_EPROCESS",
+                "DirectoryTableBase",
+            )?),
+            ProcessRef::Dtb(dtb) => dtb,
+        };
+
+        Ok(AddressSpace {
+            parser: self,
+            dtb,
+            kernel: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DumpBuilder;
+    use crate::{KdmpParserError, PxeFlags};
+
+    const PROCESS: u64 = 0xffff_c000_0200_0000;
+    const USER: u64 = 0x1000_0000;
+    /// The directory table base of the process, whose page tables are laid
+    /// out by hand right after it.
+    const PROCESS_DTB: u64 = 0x80_000;
+
+    #[test]
+    fn address_spaces() {
+        let user = PxeFlags::Present | PxeFlags::UserAccessible;
+        let table = |next: u64| (next | user.bits()).to_le_bytes();
+        let parser = KernelDumpParser::from_bytes(
+            DumpBuilder::new()
+                .map_virt(PROCESS, 0x10_000, PxeFlags::Present)
+                .map_virt(USER, 0x11_000, user)
+                .write_virt(PROCESS + 0x28, &PROCESS_DTB.to_le_bytes())
+                .write_virt(USER, b"crashing")
+                .write_phys(PROCESS_DTB, &table(PROCESS_DTB + 0x1_000))
+                .write_phys(PROCESS_DTB + 0x1_000, &table(PROCESS_DTB + 0x2_000))
+                .write_phys(
+                    PROCESS_DTB + 0x2_000 + 0x80 * 8,
+                    &table(PROCESS_DTB + 0x3_000),
+                )
+                .write_phys(PROCESS_DTB + 0x3_000, &table(PROCESS_DTB + 0x4_000))
+                .write_phys(PROCESS_DTB + 0x4_000, b"process")
+                .build(),
+        )
+        .unwrap();
+
+        // The bare methods read in the crashing context..
+        let crashing = parser.process_space(parser.default_dtb()).unwrap();
+        let mut buffer = [0; 8];
+        parser.virt_read_exact(USER, &mut buffer).unwrap();
+        assert_eq!(&buffer, b"crashing");
+        crashing.virt_read_exact(USER, &mut buffer).unwrap();
+        assert_eq!(&buffer, b"crashing");
+        assert_eq!(
+            crashing.virt_translate(USER).unwrap(),
+            parser.virt_translate(USER).unwrap()
+        );
+
+        // ..and a process is picked with its `_EPROCESS` or its directory table
+        // base..
+        let kernel = parser.kernel_space();
+        assert!(kernel.is_kernel());
+        assert_eq!(
+            kernel.virt_read_ptr(PROCESS + 0x28).unwrap(),
+            Gva::new(PROCESS_DTB)
+        );
+        for process in [
+            parser.process_space(Gva::new(PROCESS)).unwrap(),
+            parser.process_space(Gpa::new(PROCESS_DTB)).unwrap(),
+        ] {
+            assert!(!process.is_kernel());
+            assert_eq!(process.dtb(), Gpa::new(PROCESS_DTB));
+            assert_eq!(
+                process.virt_translate(USER).unwrap(),
+                Gpa::new(PROCESS_DTB + 0x4_000)
+            );
+            assert_eq!(process.virt_read_to_vec(USER, 7).unwrap(), b"process");
+            assert!(process.virt_read_ptr(PROCESS).is_err());
+        }
+
+        // ..but the kernel space doesn't have the user addresses.
+        for result in [
+            kernel.virt_translate(USER).map(|_| ()),
+            kernel.virt_read_exact(USER, &mut buffer),
+            parser.process_space(Gva::new(USER)).map(|_| ()),
+        ] {
+            assert!(matches!(
+                result,
+                Err(KdmpParserError::AddrTranslation(
+                    AddrTranslationError::UserAddress(gva)
+                )) if gva == Gva::new(USER)
+            ));
+        }
+    }
+}
@@ -0,0 +1,136 @@
+//! This contains what is needed to annotate ranges of memory with labels, like
+//! the shellcode or the JIT regions that were identified during an
+//! investigation. The annotations are looked up before the modules, so they
+//! show up when finding the module of an address or symbolizing it.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::{Gva, KernelDumpParser};
+//! let mut parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! parser.add_annotation(
+//!     Gva::new(0x1a4_2ea3_0000)..Gva::new(0x1a4_2ea4_0000),
+//!     "stage2".to_string(),
+//! );
+//! assert_eq!(
+//!     parser.symbolize_with_exports(Gva::new(0x1a4_2ea3_0010)).unwrap(),
+//!     "stage2+0x10"
+//! );
+//! ```
+use std::ops::Range;
+
+use crate::gxa::Gva;
+use crate::module::ModuleEntry;
+use crate::KernelDumpParser;
+
+impl KernelDumpParser {
+    /// Annotate the memory in `range` with `label`. The annotations that
+    /// overlap `range` are replaced; empty ranges are ignored.
+    pub fn add_annotation(&mut self, range: Range<Gva>, label: String) {
+        if range.is_empty() {
+            return;
+        }
+
+        self.remove_annotations(range.clone());
+        let annotations = std::mem::take(&mut self.annotations);
+        self.annotations = annotations
+            .entries()
+            .cloned()
+            .chain([ModuleEntry::new(range, label)])
+            .collect();
+    }
+
+    /// Remove the annotations that overlap `range`; it returns how many were
+    /// removed.
+    pub fn remove_annotations(&mut self, range: Range<Gva>) -> usize {
+        let annotations = std::mem::take(&mut self.annotations);
+        let count = annotations.len();
+        self.annotations = annotations
+            .entries()
+            .filter(|annotation| {
+                annotation.at.end <= range.start || range.end <= annotation.at.start
+            })
+            .cloned()
+            .collect();
+
+        count - self.annotations.len()
+    }
+
+    /// Iterate over the annotations, in ascending address order.
+    pub fn annotations(&self) -> impl ExactSizeIterator<Item = (&Range<Gva>, &str)> + '_ {
+        self.annotations.iter()
+    }
+
+    /// Find the annotation that contains `gva`.
+    pub fn find_annotation(&self, gva: Gva) -> Option<(&Range<Gva>, &str)> {
+        self.annotations.find(gva)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::DumpBuilder;
+    use crate::{Gva, KernelDumpParser};
+
+    fn range(start: u64, end: u64) -> std::ops::Range<Gva> {
+        Gva::new(start)..Gva::new(end)
+    }
+
+    #[test]
+    fn annotations() {
+        let nt = 0xffff_f800_0000_0000;
+        let dump = DumpBuilder::new()
+            .module(nt..nt + 0x10_000, "ntoskrnl.exe")
+            .build();
+        let mut parser = KernelDumpParser::from_bytes(dump).unwrap();
+
+        parser.add_annotation(range(0x1_000, 0x3_000), "a".into());
+        parser.add_annotation(range(0x4_000, 0x5_000), "b".into());
+        parser.add_annotation(range(0x5_000, 0x5_000), "empty".into());
+        parser.add_annotation(range(nt + 0x1_000, nt + 0x2_000), "hook".into());
+        assert_eq!(parser.annotations().len(), 3);
+
+        // The annotations win over the modules..
+        assert_eq!(
+            parser.find_module(Gva::new(nt + 0x1_010)).unwrap().1,
+            "hook"
+        );
+        assert_eq!(
+            parser
+                .symbolize_with_exports(Gva::new(nt + 0x1_010))
+                .unwrap(),
+            "hook+0x10"
+        );
+        assert_eq!(
+            parser.find_module(Gva::new(nt + 0x2_000)).unwrap().1,
+            "ntoskrnl.exe"
+        );
+        assert_eq!(parser.find_annotation(Gva::new(0x2_fff)).unwrap().1, "a");
+        assert!(parser.find_annotation(Gva::new(0x3_000)).is_none());
+
+        // ..and the ones that overlap a new one are replaced.
+        parser.add_annotation(range(0x2_000, 0x4_800), "c".into());
+        assert_eq!(
+            parser
+                .annotations()
+                .map(|(at, label)| (at.clone(), label))
+                .collect::<Vec<_>>(),
+            [
+                (range(0x2_000, 0x4_800), "c"),
+                (range(nt + 0x1_000, nt + 0x2_000), "hook")
+            ]
+        );
+
+        // They can be saved and reloaded.
+        #[cfg(feature = "json")]
+        {
+            let json = parser.export_annotations_json();
+            let mut reloaded = KernelDumpParser::from_bytes(DumpBuilder::new().build()).unwrap();
+            assert_eq!(reloaded.import_annotations_json(&json).unwrap(), 2);
+            assert!(reloaded.annotations().eq(parser.annotations()));
+        }
+
+        assert_eq!(parser.remove_annotations(range(0, nt)), 1);
+        assert_eq!(parser.annotations().len(), 1);
+    }
+}
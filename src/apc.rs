@@ -0,0 +1,151 @@
+//! This contains what is needed to inspect the APCs queued to a thread (see
+//! [`Apc`]).
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::{Gva, KernelDumpParser};
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! let ethread = Gva::new(0xffff_c000_0000_0000);
+//! for apc in parser.apcs(ethread).unwrap() {
+//!     println!(
+//!         "{:?}: {} ({:?})",
+//!         apc.mode,
+//!         apc.kernel_routine,
+//!         apc.module.as_deref().unwrap_or("?")
+//!     );
+//! }
+//! ```
+use crate::error::Result;
+use crate::gxa::Gxa;
+use crate::trace::trace_debug;
+use crate::{Gva, KernelDumpParser};
+
+/// The APC queues of a thread, indexed by mode.
+const APC_QUEUES: [(&str, ApcMode); 2] = [
+    ("ApcState.ApcListHead[0]", ApcMode::Kernel),
+    ("ApcState.ApcListHead[1]", ApcMode::User),
+];
+
+/// Maximum number of APCs we'll read off a queue.
+const MAX_APCS: usize = 0x1_000;
+
+/// The queue an APC is in (`nt!_MODE`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApcMode {
+    /// `KernelMode`.
+    Kernel,
+    /// `UserMode`.
+    User,
+}
+
+/// An APC queued to a thread (`nt!_KAPC`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Apc {
+    /// The `KernelRoutine` of the APC.
+    pub kernel_routine: Gva,
+    /// The `NormalRoutine` of the APC; special kernel APCs don't have one.
+    pub normal_routine: Option<Gva>,
+    /// The queue the APC is in.
+    pub mode: ApcMode,
+    /// Name of the module the normal routine belongs to, or the one the kernel
+    /// routine belongs to if there is no normal routine.
+    pub module: Option<String>,
+}
+
+impl KernelDumpParser {
+    /// Get the APCs queued to the `_ETHREAD` at `ethread`, kernel ones first.
+    /// A queue that can't be walked (unreadable memory, a cycle, etc.) doesn't
+    /// prevent walking the other one.
+    pub fn apcs(&self, ethread: Gva) -> Result<Vec<Apc>> {
+        let link = self.profile().offset("_KAPC", "ApcListEntry")?;
+        let mut apcs = Vec::new();
+        for (queue, mode) in APC_QUEUES {
+            let head = self.field_addr(ethread, "_KTHREAD", queue)?;
+            for apc in self.walk_list(head, link, MAX_APCS) {
+                match apc.and_then(|apc| self.read_apc(apc, mode)) {
+                    Ok(apc) => apcs.push(apc),
+                    Err(e) => {
+                        trace_debug!("failed walking the {mode:?} apcs of {ethread}: {e}");
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(apcs)
+    }
+
+    /// Read the `_KAPC` at `apc`.
+    fn read_apc(&self, apc: Gva, mode: ApcMode) -> Result<Apc> {
+        let kernel_routine = Gva::new(self.read_field(apc, "_KAPC", "KernelRoutine")?);
+        let normal_routine = Some(Gva::new(self.read_field(apc, "_KAPC", "NormalRoutine")?))
+            .filter(|routine| routine.u64() != 0);
+
+        Ok(Apc {
+            kernel_routine,
+            normal_routine,
+            mode,
+            module: self
+                .find_module(normal_routine.unwrap_or(kernel_routine))
+                .map(|(_, name)| name.to_string()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DumpBuilder;
+    use crate::PxeFlags;
+
+    const ETHREAD: u64 = 0xffff_c000_0100_0000;
+    const APCS: u64 = 0xffff_c000_0200_0000;
+    const NT: u64 = 0xffff_f800_0400_0000;
+    const DRIVER: u64 = 0xffff_f800_0500_0000;
+
+    #[test]
+    fn apcs() {
+        // Two APCs are in the kernel queue; the user queue links to a page that
+        // isn't mapped.
+        let (a, b) = (APCS, APCS + 0x100);
+        let kernel = ETHREAD + 0x98;
+        let mut builder = DumpBuilder::new()
+            .module(NT..NT + 0x1_000, "ntoskrnl.exe")
+            .module(DRIVER..DRIVER + 0x1_000, "\\SystemRoot\\evil.sys")
+            .map_virt(ETHREAD, 0x10_000, PxeFlags::Present)
+            .map_virt(APCS, 0x11_000, PxeFlags::Present)
+            .write_virt(kernel, &(a + 0x10).to_le_bytes())
+            .write_virt(kernel + 8, &(b + 0x10).to_le_bytes())
+            .write_virt(kernel + 0x10, &0xdead_0000u64.to_le_bytes());
+        for (apc, kernel_routine, normal_routine, flink, blink) in [
+            (a, NT + 0x10, 0u64, b + 0x10, kernel),
+            (b, NT + 0x20, DRIVER + 0x30, kernel, a + 0x10),
+        ] {
+            builder = builder
+                .write_virt(apc + 0x10, &flink.to_le_bytes())
+                .write_virt(apc + 0x18, &blink.to_le_bytes())
+                .write_virt(apc + 0x20, &kernel_routine.to_le_bytes())
+                .write_virt(apc + 0x30, &normal_routine.to_le_bytes());
+        }
+
+        let parser = KernelDumpParser::from_bytes(builder.build()).unwrap();
+        assert_eq!(parser.apcs(Gva::new(ETHREAD)).unwrap(), [
+            Apc {
+                kernel_routine: Gva::new(NT + 0x10),
+                normal_routine: None,
+                mode: ApcMode::Kernel,
+                module: Some("ntoskrnl.exe".into())
+            },
+            Apc {
+                kernel_routine: Gva::new(NT + 0x20),
+                normal_routine: Some(Gva::new(DRIVER + 0x30)),
+                mode: ApcMode::Kernel,
+                module: Some("\\SystemRoot\\evil.sys".into())
+            }
+        ]);
+
+        // A thread that isn't mapped doesn't have any APCs.
+        assert_eq!(parser.apcs(Gva::new(APCS + 0x10_000)).unwrap(), []);
+    }
+}
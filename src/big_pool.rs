@@ -0,0 +1,256 @@
+//! This contains what is needed to enumerate the big pool allocations (see
+//! [`BigPoolEntry`]): the allocations of a page or more, which don't have a
+//! pool header but are tracked in `nt!PoolBigPageTable` with their tag, their
+//! size and their address.
+//!
+//! The table isn't exported, nor in the `KDDEBUGGER_DATA_BLOCK`, so it is
+//! resolved with [`KernelDumpParser::resolve_symbol`]: it needs the PDB of
+//! `nt` (see [`KernelDumpParser::load_pdb`]).
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::{Gva, KernelDumpParser};
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! for entry in parser.big_pool_tagged(b"Proc").unwrap() {
+//!     println!("{}: {:#x} bytes", entry.va, entry.size);
+//! }
+//!
+//! let gva = Gva::new(0xffff_c000_0100_0040);
+//! if let Some(entry) = parser.containing_allocation(gva).unwrap() {
+//!     println!("{gva} is in a {:?} allocation", entry.key);
+//! }
+//! ```
+use crate::error::Result;
+use crate::gxa::Gxa;
+use crate::parse::ReadMode;
+use crate::{Gva, KernelDumpParser};
+
+/// The low bit of the `Va` of the free entries of the table.
+const POOL_BIG_TABLE_ENTRY_FREE: u64 = 1;
+
+/// The high bit of a pool tag, set on the allocations that can only be freed
+/// with their tag.
+const PROTECTED_POOL: u8 = 0x80;
+
+/// How many entries of the table are read at once.
+const ENTRIES_PER_READ: u64 = 0x400;
+
+/// Maximum number of entries we'll read off the table.
+const MAX_ENTRIES: u64 = 0x100_0000;
+
+/// The pool a big pool allocation comes from, out of its `nt!_POOL_TYPE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolType {
+    /// `NonPagedPool`, which is executable.
+    NonPaged,
+    /// `NonPagedPoolNx`.
+    NonPagedNx,
+    /// `PagedPool`.
+    Paged,
+    /// `NonPagedPoolSession`, which is executable.
+    NonPagedSession,
+    /// `NonPagedPoolSessionNx`.
+    NonPagedSessionNx,
+    /// `PagedPoolSession`.
+    PagedSession,
+}
+
+impl PoolType {
+    /// `POOL_NX_ALLOCATION`.
+    const NX: u64 = 0x200;
+    /// `BASE_POOL_TYPE_MASK`: the paged pool types are odd.
+    const PAGED: u64 = 1;
+    /// `SESSION_POOL_MASK`.
+    const SESSION: u64 = 0x20;
+
+    /// Get the pool out of a `nt!_POOL_TYPE`; the flags that don't say which
+    /// pool it is (cache aligned, must succeed, etc.) are ignored.
+    fn from_raw(raw: u64) -> Self {
+        match (
+            raw & Self::PAGED != 0,
+            raw & Self::SESSION != 0,
+            raw & Self::NX != 0,
+        ) {
+            (true, false, _) => Self::Paged,
+            (true, true, _) => Self::PagedSession,
+            (false, false, false) => Self::NonPaged,
+            (false, false, true) => Self::NonPagedNx,
+            (false, true, false) => Self::NonPagedSession,
+            (false, true, true) => Self::NonPagedSessionNx,
+        }
+    }
+}
+
+/// A big pool allocation (`nt!_POOL_TRACKER_BIG_PAGES`); see
+/// [`KernelDumpParser::big_pool`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BigPoolEntry {
+    /// Where the allocation is.
+    pub va: Gva,
+    /// The tag of the allocation, without its protected bit.
+    pub key: [u8; 4],
+    /// Size of the allocation in bytes.
+    pub size: u64,
+    pub pool_type: PoolType,
+}
+
+impl BigPoolEntry {
+    /// Is `gva` in the allocation?
+    pub fn contains(&self, gva: Gva) -> bool {
+        gva >= self.va && gva.u64() - self.va.u64() < self.size
+    }
+}
+
+impl KernelDumpParser {
+    /// Get the big pool allocations, out of `nt!PoolBigPageTable`. The
+    /// entries of the table that can't be read are left out, like the free
+    /// ones.
+    pub fn big_pool(&self) -> Result<Vec<BigPoolEntry>> {
+        let table = self.virt_read_ptr(self.resolve_symbol("nt!PoolBigPageTable")?)?;
+        let entries =
+            self.virt_read_struct::<u64>(self.resolve_symbol("nt!PoolBigPageTableSize")?)?;
+
+        self.read_big_pool(table, entries)
+    }
+
+    /// Get the big pool allocations tagged with `tag`; the protected bit of
+    /// the tag is ignored.
+    pub fn big_pool_tagged(&self, tag: &[u8; 4]) -> Result<Vec<BigPoolEntry>> {
+        let mut tag = *tag;
+        tag[3] &= !PROTECTED_POOL;
+        let mut entries = self.big_pool()?;
+        entries.retain(|entry| entry.key == tag);
+
+        Ok(entries)
+    }
+
+    /// Get the big pool allocation `gva` is in, if any.
+    pub fn containing_allocation(&self, gva: Gva) -> Result<Option<BigPoolEntry>> {
+        Ok(self
+            .big_pool()?
+            .into_iter()
+            .find(|entry| entry.contains(gva)))
+    }
+
+    /// Read the `entries` entries of the big pool table at `table`.
+    fn read_big_pool(&self, table: Gva, entries: u64) -> Result<Vec<BigPoolEntry>> {
+        const TYPE_NAME: &str = "_POOL_TRACKER_BIG_PAGES";
+        let profile = self.profile();
+        let entry_size = profile.size(TYPE_NAME)?;
+        let mut allocations = Vec::new();
+        let mut idx = 0;
+        let entries = entries.min(MAX_ENTRIES);
+        while idx < entries {
+            let count = (entries - idx).min(ENTRIES_PER_READ);
+            // The entries that aren't in the dump are zeroes, which are skipped like
+            // the empty ones.
+            let Some(chunk) = idx
+                .checked_mul(entry_size)
+                .and_then(|offset| table.u64().checked_add(offset))
+                .and_then(|chunk| {
+                    self.virt_read_to_vec_with_mode(
+                        Gva::new(chunk),
+                        count * entry_size,
+                        ReadMode::ZeroFill,
+                    )
+                    .ok()
+                })
+            else {
+                break;
+            };
+
+            for entry in chunk.chunks_exact(entry_size as usize) {
+                let va = profile.decode_field(entry, TYPE_NAME, "Va")?;
+                if va == 0 || va & POOL_BIG_TABLE_ENTRY_FREE != 0 {
+                    continue;
+                }
+
+                let mut key = (profile.decode_field(entry, TYPE_NAME, "Key")? as u32).to_le_bytes();
+                key[3] &= !PROTECTED_POOL;
+                allocations.push(BigPoolEntry {
+                    va: Gva::new(va),
+                    key,
+                    size: profile.decode_field(entry, TYPE_NAME, "NumberOfBytes")?,
+                    pool_type: PoolType::from_raw(
+                        profile.decode_field(entry, TYPE_NAME, "PoolType")?,
+                    ),
+                });
+            }
+
+            idx += count;
+        }
+
+        Ok(allocations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DumpBuilder;
+    use crate::{KdmpParserError, PxeFlags};
+
+    const TABLE: u64 = 0xffff_c000_0100_0000;
+
+    /// Encode a `_POOL_TRACKER_BIG_PAGES`.
+    fn entry(va: u64, key: &[u8; 4], pool_type: u64, size: u64) -> Vec<u8> {
+        let mut entry = vec![0; 0x20];
+        entry[0x0..0x8].copy_from_slice(&va.to_le_bytes());
+        entry[0x8..0xc].copy_from_slice(key);
+        entry[0xc..0x10].copy_from_slice(&((pool_type << 8) as u32).to_le_bytes());
+        entry[0x10..0x18].copy_from_slice(&size.to_le_bytes());
+
+        entry
+    }
+
+    #[test]
+    fn big_pool() {
+        let table = [
+            entry(0xffff_c000_0200_0000, b"Proc", 0x200, 0x2_000),
+            entry(0xffff_c000_0300_0001, b"Free", 0x200, 0x1_000),
+            entry(0, b"\0\0\0\0", 0, 0),
+            entry(0xffff_c000_0400_0000, b"Pro\xe3", 0x21, 0x1_800),
+        ]
+        .concat();
+        let parser = KernelDumpParser::from_bytes(
+            DumpBuilder::new()
+                .map_virt(TABLE, 0x10_000, PxeFlags::Present)
+                .write_virt(TABLE, &table)
+                .build(),
+        )
+        .unwrap();
+
+        // The free and the empty entries are skipped, and the entries past the
+        // dump are zeroes..
+        let entries = parser.read_big_pool(Gva::new(TABLE), 0x1_000).unwrap();
+        assert_eq!(entries, [
+            BigPoolEntry {
+                va: Gva::new(0xffff_c000_0200_0000),
+                key: *b"Proc",
+                size: 0x2_000,
+                pool_type: PoolType::NonPagedNx,
+            },
+            BigPoolEntry {
+                va: Gva::new(0xffff_c000_0400_0000),
+                key: *b"Proc",
+                size: 0x1_800,
+                pool_type: PoolType::PagedSession,
+            }
+        ]);
+
+        // ..and an address is attributed to the allocation it is in.
+        assert!(entries[1].contains(Gva::new(0xffff_c000_0400_17ff)));
+        assert!(!entries[1].contains(Gva::new(0xffff_c000_0400_1800)));
+        assert!(!entries[1].contains(Gva::new(0xffff_c000_03ff_ffff)));
+        assert_eq!(PoolType::from_raw(0), PoolType::NonPaged);
+        assert_eq!(PoolType::from_raw(0x5), PoolType::Paged);
+        assert_eq!(PoolType::from_raw(0x220), PoolType::NonPagedSessionNx);
+
+        // The table can't be found without the symbols of `nt`.
+        assert!(matches!(
+            parser.big_pool(),
+            Err(KdmpParserError::UnknownSymbol(_))
+        ));
+    }
+}
@@ -0,0 +1,245 @@
+//! `kdmp` is a command-line tool to look into kernel crash-dumps; it is built
+//! with the `cli` feature.
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
+use kdmp_parser::{Gpa, Gva, Gxa, KernelDumpParser, PageSource};
+
+/// Size of a page of physical memory.
+const PAGE_SIZE: usize = 0x1_000;
+
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// The dump path.
+    dump_path: PathBuf,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Summarize the headers, the bugcheck and the context of the dump.
+    Info,
+    /// List the kernel & user modules, with the PDB they have been built
+    /// with.
+    Modules,
+    /// Hexdump memory.
+    Read {
+        /// The address is a virtual address; this is the default.
+        #[arg(long, conflicts_with = "phys")]
+        virt: bool,
+        /// The address is a physical address.
+        #[arg(long)]
+        phys: bool,
+        /// The address to read from, in hexadecimal.
+        #[arg(value_parser = parse_hex)]
+        addr: u64,
+        /// The number of bytes to read, in hexadecimal.
+        #[arg(value_parser = parse_hex)]
+        len: u64,
+    },
+    /// Translate a virtual address into a physical address, and into the
+    /// offset of the dump file where its content is.
+    Translate {
+        /// The virtual address, in hexadecimal.
+        #[arg(value_parser = parse_hex)]
+        addr: u64,
+    },
+    /// Search the physical memory for bytes.
+    Search {
+        /// The bytes, in hexadecimal, like `4d5a9000`.
+        pattern: String,
+        /// The maximum number of matches to print.
+        #[arg(long, default_value_t = 100)]
+        limit: usize,
+    },
+    /// Write the physical memory of the dump into a raw image, where every
+    /// page is at the offset of its physical address.
+    ExportRaw {
+        /// Where to write the image.
+        out: PathBuf,
+    },
+}
+
+/// Convert an hexadecimal string to a `u64`.
+fn parse_hex(s: &str) -> Result<u64> {
+    u64::from_str_radix(s.trim_start_matches("0x"), 16).context("failed to convert string to u64")
+}
+
+/// Convert an hexadecimal string, like `4d 5a 90`, to bytes.
+fn parse_pattern(s: &str) -> Result<Vec<u8>> {
+    let digits = s.chars().filter(|c| !c.is_whitespace()).collect::<String>();
+    if digits.is_empty() || digits.len() % 2 != 0 {
+        bail!("the pattern needs an even number of hexadecimal digits");
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|idx| u8::from_str_radix(&digits[idx..idx + 2], 16).context("invalid pattern"))
+        .collect()
+}
+
+fn info(parser: &KernelDumpParser) {
+    println!("{}", parser.info());
+    let context = parser.context_record();
+    println!(
+        "rax={:016x} rbx={:016x} rcx={:016x}",
+        context.rax, context.rbx, context.rcx
+    );
+    println!(
+        "rdx={:016x} rsi={:016x} rdi={:016x}",
+        context.rdx, context.rsi, context.rdi
+    );
+    println!(
+        "rip={:016x} rsp={:016x} rbp={:016x}",
+        context.rip, context.rsp, context.rbp
+    );
+    println!(
+        " r8={:016x}  r9={:016x} r10={:016x}",
+        context.r8, context.r9, context.r10
+    );
+    println!(
+        "r11={:016x} r12={:016x} r13={:016x}",
+        context.r11, context.r12, context.r13
+    );
+    println!("r14={:016x} r15={:016x}", context.r14, context.r15);
+    println!(
+        "cs={:04x} ss={:04x} efl={:08x}",
+        context.seg_cs, context.seg_ss, context.eflags
+    );
+}
+
+fn modules(parser: &KernelDumpParser) {
+    for module in parser.modules() {
+        let pdb = match parser.module_pdb_id(module) {
+            Ok(Some(pdb)) => pdb.to_string(),
+            Ok(None) | Err(_) => "-".to_string(),
+        };
+
+        println!(
+            "{:#018x}-{:#018x}: {} {pdb}",
+            module.at.start.u64(),
+            module.at.end.u64(),
+            module.name
+        );
+    }
+}
+
+fn read(parser: &KernelDumpParser, phys: bool, addr: u64, len: u64) -> Result<()> {
+    let len = usize::try_from(len)?;
+    let hexdump = if phys {
+        parser.hexdump_phys(Gpa::new(addr), len)
+    } else {
+        parser.hexdump_virt(Gva::new(addr), len)
+    }
+    .with_context(|| format!("failed to read {addr:#x}"))?;
+
+    print!("{hexdump}");
+
+    Ok(())
+}
+
+fn translate(parser: &KernelDumpParser, addr: u64) -> Result<()> {
+    let gva = Gva::new(addr);
+    let gpa = parser
+        .virt_translate(gva)
+        .with_context(|| format!("failed to translate {gva}"))?;
+    match parser.phys_locate(gpa) {
+        Ok((PageSource::Dump, offset)) => println!("{gva} -> {gpa} (file offset {offset:#x})"),
+        Ok((source, offset)) => println!("{gva} -> {gpa} (offset {offset:#x} in {source})"),
+        Err(e) => println!("{gva} -> {gpa} ({e})"),
+    }
+
+    Ok(())
+}
+
+fn search(parser: &KernelDumpParser, pattern: &str, limit: usize) -> Result<()> {
+    let pattern = parse_pattern(pattern)?;
+    // The end of the previous page is kept around, to find the matches that
+    // straddle two contiguous pages.
+    let mut window = Vec::with_capacity(PAGE_SIZE + pattern.len());
+    let mut window_start = 0;
+    let mut found = 0;
+    // The pages that can't be read are skipped, and counted.
+    let mut unreadable = 0;
+    'pages: for (gpa, _) in parser.physmem() {
+        let contiguous = window_start + window.len() as u64 == gpa.u64();
+        if !contiguous {
+            window.clear();
+        }
+
+        let keep = window.len().min(pattern.len() - 1);
+        window.drain(..window.len() - keep);
+        window_start = gpa.u64() - keep as u64;
+
+        let mut page = [0; PAGE_SIZE];
+        if parser.phys_read_exact(gpa, &mut page).is_err() {
+            unreadable += 1;
+            window.clear();
+            continue;
+        }
+
+        window.extend_from_slice(&page);
+
+        for (offset, candidate) in window.windows(pattern.len()).enumerate() {
+            if candidate != pattern {
+                continue;
+            }
+
+            println!("{}", Gpa::new(window_start + offset as u64));
+            found += 1;
+            if found == limit {
+                break 'pages;
+            }
+        }
+    }
+
+    if unreadable != 0 {
+        eprintln!("{unreadable} pages couldn't be read and were skipped");
+    }
+
+    Ok(())
+}
+
+fn export_raw(parser: &KernelDumpParser, out: &Path) -> Result<()> {
+    let file = File::create(out).with_context(|| format!("failed to create {out:?}"))?;
+    let mut writer = BufWriter::new(file);
+    let mut page = [0; PAGE_SIZE];
+    // The pages are read in the order of the dump file, which is faster than
+    // seeking back and forth in it.
+    for (gpa, _) in parser.physmem_file_order() {
+        parser.phys_read_exact(gpa, &mut page)?;
+        writer.seek(SeekFrom::Start(gpa.u64()))?;
+        writer.write_all(&page)?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let parser =
+        KernelDumpParser::new(&args.dump_path).context("failed to parse the kernel dump")?;
+
+    match args.command {
+        Command::Info => info(&parser),
+        Command::Modules => modules(&parser),
+        Command::Read {
+            virt: _,
+            phys,
+            addr,
+            len,
+        } => read(&parser, phys, addr, len)?,
+        Command::Translate { addr } => translate(&parser, addr)?,
+        Command::Search { pattern, limit } => search(&parser, &pattern, limit)?,
+        Command::ExportRaw { out } => export_raw(&parser, &out)?,
+    }
+
+    Ok(())
+}
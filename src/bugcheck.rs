@@ -0,0 +1,483 @@
+//! This contains what is needed to decode the parameters of the bugcheck of a
+//! dump (see [`BugCheckDetails`]): what they mean depends on the stop code,
+//! like `!analyze` knows.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::{BugCheckDetails, KernelDumpParser};
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! let details = parser.bugcheck_details();
+//! println!("{}", details.name().unwrap_or("unknown bugcheck"));
+//! if let BugCheckDetails::PageFault { address, caller, .. } = details {
+//!     println!("{address} was referenced by {caller:?}");
+//! }
+//! ```
+use std::fmt::{self, Display};
+
+use crate::gxa::Gxa;
+use crate::{Gva, KernelDumpParser};
+
+/// The names of the stop codes.
+const NAMES: &[(u32, &str)] = &[
+    (0xa, "IRQL_NOT_LESS_OR_EQUAL"),
+    (0x19, "BAD_POOL_HEADER"),
+    (0x1a, "MEMORY_MANAGEMENT"),
+    (0x1e, "KMODE_EXCEPTION_NOT_HANDLED"),
+    (0x3b, "SYSTEM_SERVICE_EXCEPTION"),
+    (0x50, "PAGE_FAULT_IN_NONPAGED_AREA"),
+    (0x7a, "KERNEL_DATA_INPAGE_ERROR"),
+    (0x7e, "SYSTEM_THREAD_EXCEPTION_NOT_HANDLED"),
+    (0x7f, "UNEXPECTED_KERNEL_MODE_TRAP"),
+    (0x8e, "KERNEL_MODE_EXCEPTION_NOT_HANDLED"),
+    (0x9f, "DRIVER_POWER_STATE_FAILURE"),
+    (0xbe, "ATTEMPTED_WRITE_TO_READONLY_MEMORY"),
+    (0xc2, "BAD_POOL_CALLER"),
+    (0xc4, "DRIVER_VERIFIER_DETECTED_VIOLATION"),
+    (0xd1, "DRIVER_IRQL_NOT_LESS_OR_EQUAL"),
+    (0xd5, "DRIVER_PAGE_FAULT_IN_FREED_SPECIAL_POOL"),
+    (0xe2, "MANUALLY_INITIATED_CRASH"),
+    (0xef, "CRITICAL_PROCESS_DIED"),
+    (0xf4, "CRITICAL_OBJECT_TERMINATION"),
+    (0xfc, "ATTEMPTED_EXECUTE_OF_NOEXECUTE_MEMORY"),
+    (0x101, "CLOCK_WATCHDOG_TIMEOUT"),
+    (0x124, "WHEA_UNCORRECTABLE_ERROR"),
+    (0x133, "DPC_WATCHDOG_VIOLATION"),
+    (0x139, "KERNEL_SECURITY_CHECK_FAILURE"),
+    (0x161, "LIVE_SYSTEM_DUMP"),
+    (0x1000_007e, "SYSTEM_THREAD_EXCEPTION_NOT_HANDLED_M"),
+    (0x1000_008e, "KERNEL_MODE_EXCEPTION_NOT_HANDLED_M"),
+    (0xdead_dead, "MANUALLY_INITIATED_CRASH1"),
+];
+
+/// The name of the stop `code`, like `PAGE_FAULT_IN_NONPAGED_AREA`, if it is
+/// one of the ones [`BugCheckDetails`] decodes.
+pub fn bugcheck_name(code: u32) -> Option<&'static str> {
+    NAMES
+        .iter()
+        .find(|(known, _)| *known == code)
+        .map(|(_, name)| *name)
+}
+
+/// How memory was accessed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+    /// A value the stop code doesn't document.
+    Other(u64),
+}
+
+/// An address of code, and where it is relative to the symbols of the module
+/// it belongs to (see [`KernelDumpParser::symbolize`]).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SymbolizedAddress {
+    pub address: Gva,
+    /// Like `nt!KeBugCheckEx+0x12`, if the address is in a module.
+    pub symbol: Option<String>,
+}
+
+/// Format a [`SymbolizedAddress`] like `nt!KeBugCheckEx+0x12 (0xfffff800...)`,
+/// or as the address alone if it isn't in a module.
+impl Display for SymbolizedAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.symbol {
+            Some(symbol) => write!(f, "{symbol} ({:#x})", self.address.u64()),
+            None => write!(f, "{:#x}", self.address.u64()),
+        }
+    }
+}
+
+/// The parameters of a bugcheck, decoded according to its stop code; see
+/// [`KernelDumpParser::bugcheck_details`]. The pointers that are documented
+/// as optional are `None` when they are zero.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BugCheckDetails {
+    /// `IRQL_NOT_LESS_OR_EQUAL` (`0xa`) and `DRIVER_IRQL_NOT_LESS_OR_EQUAL`
+    /// (`0xd1`): pageable memory was referenced at too high an IRQL.
+    IrqlNotLessOrEqual {
+        code: u32,
+        /// The memory that was referenced.
+        address: Gva,
+        /// The IRQL at the time of the reference.
+        irql: u8,
+        access: Access,
+        /// The code that referenced the memory.
+        caller: SymbolizedAddress,
+    },
+    /// `PAGE_FAULT_IN_NONPAGED_AREA` (`0x50`) and
+    /// `DRIVER_PAGE_FAULT_IN_FREED_SPECIAL_POOL` (`0xd5`): invalid memory was
+    /// referenced.
+    PageFault {
+        code: u32,
+        /// The memory that was referenced.
+        address: Gva,
+        access: Access,
+        /// The code that referenced the memory, if it is known.
+        caller: Option<SymbolizedAddress>,
+    },
+    /// `KMODE_EXCEPTION_NOT_HANDLED` (`0x1e`), `SYSTEM_SERVICE_EXCEPTION`
+    /// (`0x3b`), `SYSTEM_THREAD_EXCEPTION_NOT_HANDLED` (`0x7e`) and
+    /// `KERNEL_MODE_EXCEPTION_NOT_HANDLED` (`0x8e`): an exception wasn't
+    /// handled. What the stop code doesn't record is `None`.
+    ExceptionNotHandled {
+        code: u32,
+        /// The `NTSTATUS` of the exception, like `0xc0000005`.
+        exception_code: u32,
+        /// Where the exception happened.
+        address: SymbolizedAddress,
+        /// The `_EXCEPTION_RECORD`.
+        exception_record: Option<Gva>,
+        /// The `_CONTEXT`.
+        context_record: Option<Gva>,
+        /// The `_KTRAP_FRAME`.
+        trap_frame: Option<Gva>,
+    },
+    /// `KERNEL_SECURITY_CHECK_FAILURE` (`0x139`): a `__fastfail` happened in
+    /// the kernel.
+    SecurityCheckFailure {
+        /// The `FAST_FAIL_` code, like `3` for a corrupted `LIST_ENTRY`.
+        fast_fail_code: u64,
+        trap_frame: Option<Gva>,
+        exception_record: Option<Gva>,
+    },
+    /// `DPC_WATCHDOG_VIOLATION` (`0x133`) with `0`: a single DPC ran for too
+    /// long.
+    DpcTimeout {
+        /// For how long it ran, in ticks.
+        ticks: u64,
+        /// For how long it was allowed to run, in ticks.
+        limit: u64,
+    },
+    /// `DPC_WATCHDOG_VIOLATION` (`0x133`) with `1`: the DPCs ran for too long
+    /// in total.
+    DpcWatchdogTimeout {
+        /// The watchdog period, in ticks.
+        period: u64,
+        /// The `nt!DPC_WATCHDOG_GLOBAL_TRIAGE_BLOCK`.
+        triage_block: Option<Gva>,
+    },
+    /// `CLOCK_WATCHDOG_TIMEOUT` (`0x101`): a processor didn't get its clock
+    /// interrupt in time.
+    ClockWatchdogTimeout {
+        /// The timeout, in ticks.
+        interval: u64,
+        /// The `_KPRCB` of the processor that hung.
+        prcb: Gva,
+        /// The index of the processor that hung.
+        processor: u32,
+    },
+    /// `ATTEMPTED_WRITE_TO_READONLY_MEMORY` (`0xbe`) and
+    /// `ATTEMPTED_EXECUTE_OF_NOEXECUTE_MEMORY` (`0xfc`).
+    ProtectionViolation {
+        code: u32,
+        /// The memory that was referenced.
+        address: Gva,
+        /// The PTE that maps it.
+        pte: u64,
+    },
+    /// `KERNEL_DATA_INPAGE_ERROR` (`0x7a`): a page couldn't be read back in.
+    InpageError {
+        /// The `NTSTATUS` of the I/O.
+        status: u32,
+        /// The memory that was referenced.
+        address: Gva,
+    },
+    /// `UNEXPECTED_KERNEL_MODE_TRAP` (`0x7f`).
+    UnexpectedKernelModeTrap {
+        /// The trap, like `8` for a double fault.
+        trap: u64,
+    },
+    /// `CRITICAL_PROCESS_DIED` (`0xef`).
+    CriticalProcessDied {
+        /// The `_EPROCESS`, or the `_ETHREAD` if `thread` is set.
+        object: Gva,
+        thread: bool,
+    },
+    /// `CRITICAL_OBJECT_TERMINATION` (`0xf4`).
+    CriticalObjectTermination {
+        /// `3` for a process, `6` for a thread.
+        object_type: u64,
+        object: Gva,
+        /// The name of the image of the process, an ANSI string.
+        image_name: Option<Gva>,
+        /// Why it terminated, an ANSI string.
+        message: Option<Gva>,
+    },
+    /// `WHEA_UNCORRECTABLE_ERROR` (`0x124`): the hardware reported a fatal
+    /// error.
+    WheaUncorrectableError {
+        /// The type of the source, like `0` for a machine-check exception.
+        error_source: u64,
+        /// The `_WHEA_ERROR_RECORD`.
+        error_record: Gva,
+        /// `MCi_STATUS` for machine-check exceptions.
+        status: u64,
+    },
+    /// `BAD_POOL_HEADER` (`0x19`), `MEMORY_MANAGEMENT` (`0x1a`),
+    /// `DRIVER_POWER_STATE_FAILURE` (`0x9f`), `BAD_POOL_CALLER` (`0xc2`) and
+    /// `DRIVER_VERIFIER_DETECTED_VIOLATION` (`0xc4`): what the other
+    /// parameters mean depends on the first one.
+    Subcode {
+        code: u32,
+        subcode: u64,
+        parameters: [u64; 3],
+    },
+    /// `MANUALLY_INITIATED_CRASH` (`0xe2`), `MANUALLY_INITIATED_CRASH1`
+    /// (`0xdeaddead`) and `LIVE_SYSTEM_DUMP` (`0x161`): the dump was asked
+    /// for.
+    ManuallyInitiated { code: u32 },
+    /// A stop code that isn't decoded.
+    Raw { code: u32, parameters: [u64; 4] },
+}
+
+impl BugCheckDetails {
+    /// The stop code.
+    pub fn code(&self) -> u32 {
+        use BugCheckDetails as B;
+        match self {
+            B::IrqlNotLessOrEqual { code, .. }
+            | B::PageFault { code, .. }
+            | B::ExceptionNotHandled { code, .. }
+            | B::ProtectionViolation { code, .. }
+            | B::Subcode { code, .. }
+            | B::ManuallyInitiated { code }
+            | B::Raw { code, .. } => *code,
+            B::SecurityCheckFailure { .. } => 0x139,
+            B::DpcTimeout { .. } | B::DpcWatchdogTimeout { .. } => 0x133,
+            B::ClockWatchdogTimeout { .. } => 0x101,
+            B::InpageError { .. } => 0x7a,
+            B::UnexpectedKernelModeTrap { .. } => 0x7f,
+            B::CriticalProcessDied { .. } => 0xef,
+            B::CriticalObjectTermination { .. } => 0xf4,
+            B::WheaUncorrectableError { .. } => 0x124,
+        }
+    }
+
+    /// The name of the stop code; see [`bugcheck_name`].
+    pub fn name(&self) -> Option<&'static str> {
+        bugcheck_name(self.code())
+    }
+}
+
+/// A pointer that is `None` when it is zero.
+fn pointer(value: u64) -> Option<Gva> {
+    (value != 0).then_some(Gva::new(value))
+}
+
+impl KernelDumpParser {
+    /// Decode the bugcheck parameters of the dump header according to its
+    /// stop code; the addresses of code are symbolized with
+    /// [`KernelDumpParser::symbolize`].
+    pub fn bugcheck_details(&self) -> BugCheckDetails {
+        let headers = self.headers();
+        let code = headers.bug_check_code;
+        let parameters = headers.bug_check_code_parameters;
+        let [p1, p2, p3, p4] = parameters;
+
+        use BugCheckDetails as B;
+        match code {
+            0xa | 0xd1 => B::IrqlNotLessOrEqual {
+                code,
+                address: Gva::new(p1),
+                irql: p2 as u8,
+                access: if p3 & 0b1000 != 0 {
+                    Access::Execute
+                } else if p3 & 1 != 0 {
+                    Access::Write
+                } else {
+                    Access::Read
+                },
+                caller: self.symbolized(p4),
+            },
+            0x50 | 0xd5 => B::PageFault {
+                code,
+                address: Gva::new(p1),
+                access: match p2 {
+                    0 => Access::Read,
+                    1 => Access::Write,
+                    2 | 10 => Access::Execute,
+                    other => Access::Other(other),
+                },
+                caller: (p3 != 0).then(|| self.symbolized(p3)),
+            },
+            0x1e => B::ExceptionNotHandled {
+                code,
+                exception_code: p1 as u32,
+                address: self.symbolized(p2),
+                exception_record: None,
+                context_record: None,
+                trap_frame: None,
+            },
+            0x3b => B::ExceptionNotHandled {
+                code,
+                exception_code: p1 as u32,
+                address: self.symbolized(p2),
+                exception_record: None,
+                context_record: pointer(p3),
+                trap_frame: None,
+            },
+            0x7e | 0x1000_007e => B::ExceptionNotHandled {
+                code,
+                exception_code: p1 as u32,
+                address: self.symbolized(p2),
+                exception_record: pointer(p3),
+                context_record: pointer(p4),
+                trap_frame: None,
+            },
+            0x8e | 0x1000_008e => B::ExceptionNotHandled {
+                code,
+                exception_code: p1 as u32,
+                address: self.symbolized(p2),
+                exception_record: None,
+                context_record: None,
+                trap_frame: pointer(p3),
+            },
+            0x139 => B::SecurityCheckFailure {
+                fast_fail_code: p1,
+                trap_frame: pointer(p2),
+                exception_record: pointer(p3),
+            },
+            0x133 if p1 == 0 => B::DpcTimeout {
+                ticks: p2,
+                limit: p3,
+            },
+            0x133 if p1 == 1 => B::DpcWatchdogTimeout {
+                period: p2,
+                triage_block: pointer(p3),
+            },
+            0x101 => B::ClockWatchdogTimeout {
+                interval: p1,
+                prcb: Gva::new(p3),
+                processor: p4 as u32,
+            },
+            0xbe | 0xfc => B::ProtectionViolation {
+                code,
+                address: Gva::new(p1),
+                pte: p2,
+            },
+            0x7a => B::InpageError {
+                status: p2 as u32,
+                address: Gva::new(p4),
+            },
+            0x7f => B::UnexpectedKernelModeTrap { trap: p1 },
+            0xef => B::CriticalProcessDied {
+                object: Gva::new(p1),
+                thread: p2 == 1,
+            },
+            0xf4 => B::CriticalObjectTermination {
+                object_type: p1,
+                object: Gva::new(p2),
+                image_name: pointer(p3),
+                message: pointer(p4),
+            },
+            0x124 => B::WheaUncorrectableError {
+                error_source: p1,
+                error_record: Gva::new(p2),
+                status: (p3 << 32) | (p4 & 0xffff_ffff),
+            },
+            0x19 | 0x1a | 0x9f | 0xc2 | 0xc4 => B::Subcode {
+                code,
+                subcode: p1,
+                parameters: [p2, p3, p4],
+            },
+            0xe2 | 0x161 | 0xdead_dead => B::ManuallyInitiated { code },
+            _ => B::Raw { code, parameters },
+        }
+    }
+
+    /// Symbolize the address of code `address`.
+    fn symbolized(&self, address: u64) -> SymbolizedAddress {
+        let address = Gva::new(address);
+
+        SymbolizedAddress {
+            address,
+            symbol: self.symbolize(address),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DumpBuilder;
+    use crate::PxeFlags;
+
+    const NT: u64 = 0xffff_f800_0000_0000;
+
+    fn decode(code: u32, parameters: [u64; 4]) -> BugCheckDetails {
+        let dump = DumpBuilder::new()
+            .map_virt(NT, 0x10_000, PxeFlags::Present)
+            .write_virt(NT, b"MZ")
+            .module(NT..NT + 0x1_000, "ntoskrnl.exe")
+            .bug_check(code, parameters)
+            .build();
+
+        KernelDumpParser::from_bytes(dump)
+            .unwrap()
+            .bugcheck_details()
+    }
+
+    #[test]
+    fn bugcheck_details() {
+        // The code that faulted is symbolized..
+        let page_fault = decode(0x50, [0xffff_8000_1337_0000, 1, NT + 0x10, 2]);
+        assert_eq!(page_fault, BugCheckDetails::PageFault {
+            code: 0x50,
+            address: Gva::new(0xffff_8000_1337_0000),
+            access: Access::Write,
+            caller: Some(SymbolizedAddress {
+                address: Gva::new(NT + 0x10),
+                symbol: Some("nt+0x10".into())
+            }),
+        });
+        assert_eq!(page_fault.name(), Some("PAGE_FAULT_IN_NONPAGED_AREA"));
+        let BugCheckDetails::PageFault { caller, .. } = page_fault else {
+            unreachable!()
+        };
+        assert_eq!(caller.unwrap().to_string(), "nt+0x10 (0xfffff80000000010)");
+
+        // ..unless it isn't in a module.
+        assert_eq!(
+            decode(0xd1, [0x28, 2, 0b1000, 0x1337]),
+            BugCheckDetails::IrqlNotLessOrEqual {
+                code: 0xd1,
+                address: Gva::new(0x28),
+                irql: 2,
+                access: Access::Execute,
+                caller: SymbolizedAddress {
+                    address: Gva::new(0x1337),
+                    symbol: None
+                },
+            }
+        );
+
+        assert!(matches!(
+            decode(0x1000_007e, [0xc000_0005, NT, 0x1_000, 0]),
+            BugCheckDetails::ExceptionNotHandled {
+                exception_code: 0xc000_0005,
+                exception_record: Some(_),
+                context_record: None,
+                ..
+            }
+        ));
+        assert_eq!(
+            decode(0x133, [1, 0x1e00, 0xffff_f805_1234_5678, 0]),
+            BugCheckDetails::DpcWatchdogTimeout {
+                period: 0x1e00,
+                triage_block: Some(Gva::new(0xffff_f805_1234_5678))
+            }
+        );
+        assert_eq!(
+            decode(0x124, [0, 0x1_000, 0xbe00_0000, 0x0080_0400]).code(),
+            0x124
+        );
+        let raw = decode(0x1337, [1, 2, 3, 4]);
+        assert_eq!(raw, BugCheckDetails::Raw {
+            code: 0x1337,
+            parameters: [1, 2, 3, 4]
+        });
+        assert_eq!(raw.name(), None);
+    }
+}
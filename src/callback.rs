@@ -0,0 +1,541 @@
+//! This contains what is needed to enumerate the callbacks the drivers have
+//! registered with the kernel (see [`Callback`]): the process, thread and
+//! image notify routines, the registry callbacks and the object callbacks of
+//! the process and thread types.
+//!
+//! The arrays and the lists they are in aren't exported, nor in the
+//! `KDDEBUGGER_DATA_BLOCK`, so they are found in the code of the exported
+//! routines that register the callbacks: the `lea reg, [rip + disp32]` that
+//! point to them, in the routines or in the first routines they call. The
+//! object callbacks hang off the object types, which are exported.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::KernelDumpParser;
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! for callback in parser.kernel_callbacks().unwrap() {
+//!     println!("{}: {}", callback.kind, callback.symbol.unwrap_or_default());
+//! }
+//! ```
+use std::fmt::{self, Display};
+
+use crate::error::Result;
+use crate::gxa::Gxa;
+use crate::module::ModuleEntry;
+use crate::nt::{KERNEL_SPACE_START, NT_EXPORT_NAME};
+use crate::trace::trace_debug;
+use crate::{Gva, KdmpParserError, KernelDumpParser};
+
+/// How many callbacks the notify arrays have room for.
+///
+/// ```text
+/// kd> dt nt!PspCreateProcessNotifyRoutine
+/// [64] _EX_CALLBACK
+/// ```
+const NOTIFY_ARRAY_LEN: usize = 64;
+
+/// The low bits of an `_EX_FAST_REF` are a reference count.
+const EX_FAST_REF_MASK: u64 = !0xf;
+
+/// ```text
+/// kd> dt nt!_EX_CALLBACK_ROUTINE_BLOCK
+///    +0x000 RundownProtect   : _EX_RUNDOWN_REF
+///    +0x008 Function         : Ptr64     long
+///    +0x010 Context          : Ptr64 Void
+/// ```
+const CALLBACK_BLOCK_FUNCTION: u64 = 0x8;
+
+/// The `Function` of the entries linked off `nt!CallbackListHead`, which
+/// aren't in the public symbols; the `LIST_ENTRY` is their first field.
+const REGISTRY_CALLBACK_FUNCTION: u64 = 0x28;
+
+/// ```text
+/// kd> dt nt!_OBJECT_TYPE CallbackList
+///    +0x0c8 CallbackList     : _LIST_ENTRY
+/// ```
+const OBJECT_TYPE_CALLBACK_LIST: u64 = 0xc8;
+
+/// The `PreOperation` and the `PostOperation` of the entries linked off
+/// `_OBJECT_TYPE.CallbackList`, which aren't in the public symbols; the
+/// `LIST_ENTRY` is their first field.
+const OBJECT_CALLBACK_PRE_OPERATION: u64 = 0x28;
+const OBJECT_CALLBACK_POST_OPERATION: u64 = 0x30;
+
+/// How many bytes of a routine are looked at for the `lea`s.
+const ROUTINE_SCAN_SIZE: usize = 0x200;
+
+/// How many of the routines a routine calls are looked at for the `lea`s.
+const MAX_CALLEES: usize = 4;
+
+/// Maximum number of callbacks we'll read off a list.
+const MAX_LIST_CALLBACKS: usize = 0x1_000;
+
+/// What a callback is called for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CallbackKind {
+    /// `PsSetCreateProcessNotifyRoutine`.
+    CreateProcess,
+    /// `PsSetCreateThreadNotifyRoutine`.
+    CreateThread,
+    /// `PsSetLoadImageNotifyRoutine`.
+    LoadImage,
+    /// `CmRegisterCallback`.
+    Registry,
+    /// `ObRegisterCallbacks` before an operation on a process handle.
+    ProcessHandlePre,
+    /// `ObRegisterCallbacks` after an operation on a process handle.
+    ProcessHandlePost,
+    /// `ObRegisterCallbacks` before an operation on a thread handle.
+    ThreadHandlePre,
+    /// `ObRegisterCallbacks` after an operation on a thread handle.
+    ThreadHandlePost,
+}
+
+impl Display for CallbackKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::CreateProcess => "create process",
+            Self::CreateThread => "create thread",
+            Self::LoadImage => "load image",
+            Self::Registry => "registry",
+            Self::ProcessHandlePre => "process handle (pre)",
+            Self::ProcessHandlePost => "process handle (post)",
+            Self::ThreadHandlePre => "thread handle (pre)",
+            Self::ThreadHandlePost => "thread handle (post)",
+        })
+    }
+}
+
+/// A callback registered with the kernel; see
+/// [`KernelDumpParser::kernel_callbacks`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Callback {
+    pub kind: CallbackKind,
+    /// The routine the kernel calls.
+    pub routine: Gva,
+    /// The name of the module the routine is in, `None` if it isn't in any,
+    /// which is suspicious.
+    pub module: Option<String>,
+    /// The routine symbolized with [`KernelDumpParser::symbolize`].
+    pub symbol: Option<String>,
+}
+
+/// The targets of the `lea reg, [rip + disp32]` (first) and of the
+/// `call`/`jmp rel32` (second) in `code`, which is at `address`. The
+/// instructions aren't decoded, so some of the targets are bogus.
+pub(crate) fn rip_relative_targets(code: &[u8], address: u64) -> (Vec<u64>, Vec<u64>) {
+    let disp32 = |offset: usize| {
+        code.get(offset..offset + 4)
+            .map(|disp| i64::from(i32::from_le_bytes(disp.try_into().unwrap())))
+    };
+
+    let (mut leas, mut branches) = (Vec::new(), Vec::new());
+    for (idx, window) in code.windows(3).enumerate() {
+        let target = |len: usize, disp: i64| {
+            address
+                .wrapping_add((idx + len) as u64)
+                .wrapping_add(disp as u64)
+        };
+
+        match window {
+            [0x48 | 0x4c, 0x8d, modrm] if modrm & 0xc7 == 0x05 => {
+                if let Some(disp) = disp32(idx + 3) {
+                    leas.push(target(7, disp));
+                }
+            }
+            [0xe8 | 0xe9, ..] => {
+                if let Some(disp) = disp32(idx + 1) {
+                    branches.push(target(5, disp));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    (leas, branches)
+}
+
+impl KernelDumpParser {
+    /// Enumerate the callbacks the drivers have registered with the kernel,
+    /// with the module their routine is in; the routines that aren't in any
+    /// module are kept, as they are what rootkits look like.
+    ///
+    /// Where the callbacks are is found in the code of the exported routines
+    /// of `nt` that register them; the kinds whose callbacks can't be found
+    /// are skipped, and it fails if none can be found.
+    pub fn kernel_callbacks(&self) -> Result<Vec<Callback>> {
+        let nt = self.nt_module()?;
+        let exports = self.module_exports(&nt)?;
+        let export = |name: &str| {
+            exports
+                .iter()
+                .find(|export| export.name == name && export.forwarder.is_none())
+                .map(|export| export.address)
+        };
+
+        let mut found = false;
+        let mut callbacks = Vec::new();
+        for (name, kind) in [
+            (
+                "PsSetCreateProcessNotifyRoutine",
+                CallbackKind::CreateProcess,
+            ),
+            ("PsSetCreateThreadNotifyRoutine", CallbackKind::CreateThread),
+            ("PsSetLoadImageNotifyRoutine", CallbackKind::LoadImage),
+        ] {
+            match export(name).and_then(|routine| self.find_notify_array(&nt, routine)) {
+                Some(array) => {
+                    found = true;
+                    callbacks.extend(self.notify_callbacks(array, kind));
+                }
+                None => trace_debug!("failed finding the array {name} registers into"),
+            }
+        }
+
+        match export("CmUnRegisterCallback")
+            .and_then(|routine| self.find_callback_list(&nt, routine))
+        {
+            Some(head) => {
+                found = true;
+                callbacks.extend(self.registry_callbacks(head));
+            }
+            None => trace_debug!("failed finding nt!CallbackListHead"),
+        }
+
+        for (name, pre, post) in [
+            (
+                "PsProcessType",
+                CallbackKind::ProcessHandlePre,
+                CallbackKind::ProcessHandlePost,
+            ),
+            (
+                "PsThreadType",
+                CallbackKind::ThreadHandlePre,
+                CallbackKind::ThreadHandlePost,
+            ),
+        ] {
+            match export(name).and_then(|ptr| self.virt_read_ptr(ptr).ok()) {
+                Some(object_type) => {
+                    found = true;
+                    callbacks.extend(self.object_callbacks(object_type, pre, post));
+                }
+                None => trace_debug!("failed reading nt!{name}"),
+            }
+        }
+
+        if !found {
+            return Err(KdmpParserError::NotFound("the kernel callbacks"));
+        }
+
+        Ok(callbacks)
+    }
+
+    /// The module entry of `nt`.
+    pub(crate) fn nt_module(&self) -> Result<ModuleEntry> {
+        let base = self.nt_base().map_or_else(|| self.find_nt_base(), Ok)?;
+        let size = self.pe_headers(base)?.size_of_image;
+        let end = base
+            .u64()
+            .checked_add(size.into())
+            .ok_or(KdmpParserError::Overflow("nt end"))?;
+
+        Ok(ModuleEntry::new(base..Gva::new(end), NT_EXPORT_NAME))
+    }
+
+    /// The addresses in `nt` the `lea`s of `routine`, and then of the first
+    /// routines it calls, point to.
+    fn lea_targets(&self, nt: &ModuleEntry, routine: Gva) -> Vec<Gva> {
+        let scan = |routine: Gva| {
+            let mut code = vec![0; ROUTINE_SCAN_SIZE];
+            let len = self.virt_read(routine, &mut code).unwrap_or_default();
+
+            rip_relative_targets(&code[..len], routine.u64())
+        };
+
+        let (mut targets, callees) = scan(routine);
+        for callee in callees
+            .into_iter()
+            .filter(|&callee| nt.at.contains(&Gva::new(callee)))
+            .take(MAX_CALLEES)
+        {
+            targets.extend(scan(Gva::new(callee)).0);
+        }
+
+        targets
+            .into_iter()
+            .map(Gva::new)
+            .filter(|target| nt.at.contains(target))
+            .collect()
+    }
+
+    /// Find the notify array `routine` registers callbacks into: the first
+    /// array its code points to that has callbacks, and only callbacks, one of
+    /// which at least is in a module.
+    fn find_notify_array(&self, nt: &ModuleEntry, routine: Gva) -> Option<Gva> {
+        self.lea_targets(nt, routine).into_iter().find(|&array| {
+            let Ok(entries) = self.virt_read_struct::<[u64; NOTIFY_ARRAY_LEN]>(array) else {
+                return false;
+            };
+
+            let routines = entries
+                .iter()
+                .filter(|&&entry| entry != 0)
+                .map(|&entry| self.notify_routine(entry))
+                .collect::<Option<Vec<_>>>()
+                .unwrap_or_default();
+
+            routines
+                .iter()
+                .any(|&routine| self.find_module_entry(routine).is_some())
+        })
+    }
+
+    /// Find the list `routine` unregisters callbacks from: the first list
+    /// head its code points to whose first entry is a callback.
+    fn find_callback_list(&self, nt: &ModuleEntry, routine: Gva) -> Option<Gva> {
+        self.lea_targets(nt, routine).into_iter().find(|&head| {
+            let Ok([flink, _]) = self.virt_read_struct::<[u64; 2]>(head) else {
+                return false;
+            };
+
+            let Ok([_, blink]) = self.virt_read_struct::<[u64; 2]>(Gva::new(flink)) else {
+                return false;
+            };
+
+            blink == head.u64()
+                && flink != head.u64()
+                && self
+                    .list_routine(Gva::new(flink), REGISTRY_CALLBACK_FUNCTION)
+                    .is_some_and(|routine| self.find_module_entry(routine).is_some())
+        })
+    }
+
+    /// Decode the `_EX_FAST_REF` of a notify array into the routine of the
+    /// `_EX_CALLBACK_ROUTINE_BLOCK` it points to; `None` if it isn't in kernel
+    /// space.
+    fn notify_routine(&self, entry: u64) -> Option<Gva> {
+        let block = Gva::new(entry & EX_FAST_REF_MASK);
+        let function = block.u64().checked_add(CALLBACK_BLOCK_FUNCTION)?;
+        let routine = self.virt_read_ptr(Gva::new(function)).ok()?;
+
+        (routine.u64() >= KERNEL_SPACE_START).then_some(routine)
+    }
+
+    /// Read the routine at `offset` of the list entry `entry`; `None` if it
+    /// isn't in kernel space.
+    fn list_routine(&self, entry: Gva, offset: u64) -> Option<Gva> {
+        let routine = self
+            .virt_read_ptr(Gva::new(entry.u64().checked_add(offset)?))
+            .ok()?;
+
+        (routine.u64() >= KERNEL_SPACE_START).then_some(routine)
+    }
+
+    /// Describe the callback `routine`.
+    fn callback(&self, kind: CallbackKind, routine: Gva) -> Callback {
+        Callback {
+            kind,
+            routine,
+            module: self
+                .find_module_entry(routine)
+                .map(|module| module.name.clone()),
+            symbol: self.symbolize(routine),
+        }
+    }
+
+    /// The callbacks of the notify array at `array`; the entries that can't
+    /// be decoded are skipped.
+    fn notify_callbacks(&self, array: Gva, kind: CallbackKind) -> Vec<Callback> {
+        let Ok(entries) = self.virt_read_struct::<[u64; NOTIFY_ARRAY_LEN]>(array) else {
+            trace_debug!("failed reading the notify array at {array}");
+            return Vec::new();
+        };
+
+        entries
+            .into_iter()
+            .filter(|&entry| entry != 0)
+            .filter_map(|entry| self.notify_routine(entry))
+            .map(|routine| self.callback(kind, routine))
+            .collect()
+    }
+
+    /// The callbacks linked off `nt!CallbackListHead`; the callbacks read
+    /// before the list turns out to be broken are returned.
+    fn registry_callbacks(&self, head: Gva) -> Vec<Callback> {
+        let mut callbacks = Vec::new();
+        for entry in self.walk_list(head, 0, MAX_LIST_CALLBACKS) {
+            let Ok(entry) = entry else {
+                trace_debug!("failed walking the registry callbacks");
+                break;
+            };
+
+            if let Some(routine) = self.list_routine(entry, REGISTRY_CALLBACK_FUNCTION) {
+                callbacks.push(self.callback(CallbackKind::Registry, routine));
+            }
+        }
+
+        callbacks
+    }
+
+    /// The callbacks linked off the `CallbackList` of the `_OBJECT_TYPE` at
+    /// `object_type`; the callbacks read before the list turns out to be
+    /// broken are returned.
+    fn object_callbacks(
+        &self,
+        object_type: Gva,
+        pre: CallbackKind,
+        post: CallbackKind,
+    ) -> Vec<Callback> {
+        let Some(head) = object_type
+            .u64()
+            .checked_add(OBJECT_TYPE_CALLBACK_LIST)
+            .map(Gva::new)
+        else {
+            return Vec::new();
+        };
+
+        let mut callbacks = Vec::new();
+        for entry in self.walk_list(head, 0, MAX_LIST_CALLBACKS) {
+            let Ok(entry) = entry else {
+                trace_debug!("failed walking the callbacks of the object type at {object_type}");
+                break;
+            };
+
+            for (offset, kind) in [
+                (OBJECT_CALLBACK_PRE_OPERATION, pre),
+                (OBJECT_CALLBACK_POST_OPERATION, post),
+            ] {
+                if let Some(routine) = self.list_routine(entry, offset) {
+                    callbacks.push(self.callback(kind, routine));
+                }
+            }
+        }
+
+        callbacks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DumpBuilder;
+    use crate::PxeFlags;
+
+    const NT: u64 = 0xffff_f800_0000_0000;
+    const DRIVER: u64 = 0xffff_f800_1000_0000;
+    const POOL: u64 = 0xffff_c000_0000_0000;
+
+    #[test]
+    fn rip_relative() {
+        // `call` then `lea r13, [rip + 0x100]` and `lea rcx, [rip - 0x10]`.
+        let code = [
+            0xe8, 0xfb, 0x0f, 0x00, 0x00, 0x4c, 0x8d, 0x2d, 0x00, 0x01, 0x00, 0x00, 0x48, 0x8d,
+            0x0d, 0xf0, 0xff, 0xff, 0xff,
+        ];
+        assert_eq!(
+            rip_relative_targets(&code, 0x1_000),
+            (vec![0x1_10c, 0x1_003], vec![0x2_000])
+        );
+
+        // The truncated instructions are ignored.
+        assert_eq!(
+            rip_relative_targets(&code[..10], 0x1_000),
+            (vec![], vec![0x2_000])
+        );
+    }
+
+    #[test]
+    fn callbacks() {
+        // The first routine calls the one that has the `lea` of the array..
+        let routine = NT + 0x1_000;
+        let array = NT + 0x2_000;
+        let mut code = vec![0xe8];
+        code.extend_from_slice(&0xfbu32.to_le_bytes());
+        code.resize(0x100, 0xcc);
+        code.extend_from_slice(&[0x4c, 0x8d, 0x2d]);
+        code.extend_from_slice(&(array - (routine + 0x107)).to_le_bytes()[..4]);
+
+        // ..and the second one has the `lea` of the array, which isn't a list,
+        // before the one of the list head.
+        let unregister = NT + 0x1_800;
+        let head = NT + 0x3_000;
+        let mut unregister_code = vec![0x48, 0x8d, 0x0d];
+        unregister_code.extend_from_slice(&(array - (unregister + 7)).to_le_bytes()[..4]);
+        unregister_code.extend_from_slice(&[0x48, 0x8d, 0x0d]);
+        unregister_code.extend_from_slice(&(head - (unregister + 14)).to_le_bytes()[..4]);
+
+        // The array has a callback, with a reference count; the registry
+        // callbacks and the object callbacks have one callback.
+        let block = POOL;
+        let registry = POOL + 0x100;
+        let object_type = POOL + 0x200;
+        let object_callback = POOL + 0x400;
+        let dump = DumpBuilder::new()
+            .map_virt(NT, 0x10_000, PxeFlags::Present)
+            .map_virt(NT + 0x1_000, 0x11_000, PxeFlags::Present)
+            .map_virt(NT + 0x2_000, 0x12_000, PxeFlags::Present)
+            .map_virt(NT + 0x3_000, 0x13_000, PxeFlags::Present)
+            .map_virt(DRIVER, 0x14_000, PxeFlags::Present)
+            .map_virt(POOL, 0x15_000, PxeFlags::Present)
+            .write_virt(NT, b"MZ")
+            .write_virt(DRIVER, b"MZ")
+            .module(NT..NT + 0x4_000, "ntoskrnl.exe")
+            .module(DRIVER..DRIVER + 0x1_000, "evil.sys")
+            .write_virt(routine, &code)
+            .write_virt(unregister, &unregister_code)
+            .write_virt(array + 0x8, &(block | 0xf).to_le_bytes())
+            .write_virt(block + 0x8, &(DRIVER + 0x10).to_le_bytes())
+            .write_virt(head, &registry.to_le_bytes())
+            .write_virt(head + 0x8, &registry.to_le_bytes())
+            .write_virt(registry, &head.to_le_bytes())
+            .write_virt(registry + 0x8, &head.to_le_bytes())
+            .write_virt(registry + 0x28, &(DRIVER + 0x20).to_le_bytes())
+            .write_virt(object_type + 0xc8, &object_callback.to_le_bytes())
+            .write_virt(object_type + 0xd0, &object_callback.to_le_bytes())
+            .write_virt(object_callback, &(object_type + 0xc8).to_le_bytes())
+            .write_virt(object_callback + 0x8, &(object_type + 0xc8).to_le_bytes())
+            .write_virt(object_callback + 0x28, &(DRIVER + 0x30).to_le_bytes())
+            .write_virt(object_callback + 0x30, &(POOL + 0x800).to_le_bytes())
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+        let nt = ModuleEntry::new(Gva::new(NT)..Gva::new(NT + 0x4_000), "ntoskrnl.exe");
+
+        assert_eq!(
+            parser.find_notify_array(&nt, Gva::new(routine)),
+            Some(Gva::new(array))
+        );
+        assert_eq!(
+            parser.find_callback_list(&nt, Gva::new(unregister)),
+            Some(Gva::new(head))
+        );
+
+        let callback = |kind, offset: u64| Callback {
+            kind,
+            routine: Gva::new(DRIVER + offset),
+            module: Some("evil.sys".into()),
+            symbol: Some(format!("evil+{offset:#x}")),
+        };
+        assert_eq!(
+            parser.notify_callbacks(Gva::new(array), CallbackKind::LoadImage),
+            [callback(CallbackKind::LoadImage, 0x10)]
+        );
+        assert_eq!(parser.registry_callbacks(Gva::new(head)), [callback(
+            CallbackKind::Registry,
+            0x20
+        )]);
+        assert_eq!(
+            parser.object_callbacks(
+                Gva::new(object_type),
+                CallbackKind::ProcessHandlePre,
+                CallbackKind::ProcessHandlePost
+            ),
+            [callback(CallbackKind::ProcessHandlePre, 0x30), Callback {
+                kind: CallbackKind::ProcessHandlePost,
+                routine: Gva::new(POOL + 0x800),
+                module: None,
+                symbol: None
+            }]
+        );
+    }
+}
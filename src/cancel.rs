@@ -0,0 +1,129 @@
+//! This contains [`CancellationToken`], which stops the scans that can run
+//! for a long time on big dumps; they check it before every page they walk,
+//! and fail with [`KdmpParserError::Cancelled`] once it is cancelled.
+//!
+//! The scans that go over every page of the dump, or over every page table of
+//! an address space, have a `_with_cancellation` variant:
+//! - [`crate::KernelDumpParser::find_dtb_candidates_with_cancellation`],
+//! - [`crate::KernelDumpParser::classify_pages_with_cancellation`],
+//! - [`crate::KernelDumpParser::memory_map_with_cancellation`],
+//! - [`crate::KernelDumpParser::page_table_pages_with_cancellation`],
+//! - [`crate::KernelDumpParser::pte_anomalies_with_cancellation`],
+//! - `KernelDumpParser::content_hash_with_cancellation`, with the `sha2`
+//!   feature.
+//!
+//! [`crate::KernelDumpParser::carve_pe`] is lazy instead: it reads a page each
+//! time it is advanced, so its caller decides when to stop; the first image it
+//! finds makes it walk the kernel page tables once, though.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::{CancellationToken, KdmpParserError, KernelDumpParser};
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! // Hand the token to another thread, which cancels it when the deadline is
+//! // hit.
+//! let token = CancellationToken::new();
+//! match parser.find_dtb_candidates_with_cancellation(&token) {
+//!     Ok(candidates) => println!("{} candidates", candidates.len()),
+//!     Err(KdmpParserError::Cancelled) => println!("timed out"),
+//!     Err(e) => println!("failed: {e}"),
+//! }
+//! ```
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::error::Result;
+use crate::KdmpParserError;
+
+/// A flag that cancels the scans it is handed to; it can be cancelled from
+/// another thread.
+#[derive(Debug, Default)]
+pub struct CancellationToken {
+    cancelled: AtomicBool,
+}
+
+impl CancellationToken {
+    /// Create a token that isn't cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the scans using the token.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Has the token been cancelled?
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Fail with [`KdmpParserError::Cancelled`] if the token has been
+    /// cancelled.
+    pub(crate) fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(KdmpParserError::Cancelled);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gxa::Gva;
+    use crate::testing::DumpBuilder;
+    use crate::{KernelDumpParser, PxeFlags};
+
+    #[test]
+    fn cancel() {
+        // Three pages mapped by three different page tables.
+        let kernel = 0xffff_f800_0000_0000u64;
+        let dump = DumpBuilder::new()
+            .map_virt(kernel, 0x10_000, PxeFlags::Present)
+            .map_virt(kernel + 0x20_0000, 0x11_000, PxeFlags::Present)
+            .map_virt(kernel + 0x40_0000, 0x12_000, PxeFlags::Present)
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+
+        let token = CancellationToken::new();
+        assert!(!token.is_cancelled());
+        assert!(parser.memory_map_with_cancellation(None, &token).is_ok());
+
+        // Cancelling the token mid-walk stops it before the next table..
+        let mut mapped = Vec::new();
+        let res = parser.for_each_kernel_mapping(parser.default_dtb(), &token, &mut |mapping| {
+            mapped.push(mapping.gva);
+            token.cancel();
+
+            Ok(())
+        });
+        assert!(matches!(res, Err(KdmpParserError::Cancelled)));
+        assert_eq!(mapped, [Gva::new(kernel)]);
+
+        // ..and the scans don't start with a cancelled token.
+        assert!(token.is_cancelled());
+        assert!(matches!(
+            parser.find_dtb_candidates_with_cancellation(&token),
+            Err(KdmpParserError::Cancelled)
+        ));
+        assert!(matches!(
+            parser.classify_pages_with_cancellation(None, &token),
+            Err(KdmpParserError::Cancelled)
+        ));
+        assert!(matches!(
+            parser.page_table_pages_with_cancellation(None, &token),
+            Err(KdmpParserError::Cancelled)
+        ));
+        assert!(matches!(
+            parser.pte_anomalies_with_cancellation(None, &token),
+            Err(KdmpParserError::Cancelled)
+        ));
+        #[cfg(feature = "sha2")]
+        assert!(matches!(
+            parser.content_hash_with_cancellation(crate::HashAlgorithm::Sha256, &token),
+            Err(KdmpParserError::Cancelled)
+        ));
+    }
+}
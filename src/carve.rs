@@ -0,0 +1,185 @@
+//! This contains what is needed to carve the images out of the physical memory
+//! of a dump (see [`CarvedPe`]), without relying on any of the structures of
+//! the kernel; this finds the images that were mapped manually and that no list
+//! knows about.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::KernelDumpParser;
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! for pe in parser.carve_pe() {
+//!     println!(
+//!         "{}: {:?} ({:#x} bytes) mapped at {:?}",
+//!         pe.gpa, pe.export_name, pe.size_of_image, pe.gva
+//!     );
+//! }
+//! ```
+use std::cell::OnceCell;
+use std::collections::HashMap;
+
+use crate::error::Result;
+use crate::gxa::Gpa;
+use crate::pe::PeHeaders;
+use crate::structs::Page;
+use crate::trace::trace_debug;
+use crate::{Gva, KernelDumpParser};
+
+/// An image whose headers were found in a physical page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CarvedPe {
+    /// The physical page the headers are in.
+    pub gpa: Gpa,
+    /// The kernel address the page is mapped at, if it is mapped in the
+    /// kernel half of the address space of the dump.
+    pub gva: Option<Gva>,
+    /// `SizeOfImage` of the image.
+    pub size_of_image: u32,
+    /// `Machine` of the image.
+    pub machine: u16,
+    /// `TimeDateStamp` of the image.
+    pub timestamp: u32,
+    /// The name the image exports itself as; it can only be read when the
+    /// image is mapped in the kernel, as its export directory isn't in the
+    /// page of its headers.
+    pub export_name: Option<String>,
+}
+
+impl KernelDumpParser {
+    /// Scan every physical page of the dump for the headers of an image: a
+    /// DOS header, a NT signature where `e_lfanew` points to, and an optional
+    /// header whose fields look like the ones of an image Windows would load.
+    ///
+    /// The images are streamed in the order of their physical address, a page
+    /// at a time. The first time an image is found, the kernel half of the
+    /// page tables is walked to be able to tell where the images are mapped
+    /// at; this costs a bit of memory per page mapped in the kernel. A page
+    /// that can't be read is skipped.
+    pub fn carve_pe(&self) -> impl Iterator<Item = CarvedPe> + '_ {
+        let mapped = OnceCell::new();
+        let mut page = vec![0; Page::size() as usize];
+
+        self.physmem().filter_map(move |(gpa, _)| {
+            let headers = match self.carve_page(gpa, &mut page) {
+                Ok(headers) => headers?,
+                Err(e) => {
+                    trace_debug!("failed carving {gpa}: {e}");
+                    return None;
+                }
+            };
+
+            let gva = mapped
+                .get_or_init(|| self.kernel_reverse_map())
+                .get(&gpa)
+                .copied();
+            let export_name = gva.and_then(|gva| self.export_dll_name(gva).ok());
+
+            Some(CarvedPe {
+                gpa,
+                gva,
+                size_of_image: headers.size_of_image,
+                machine: headers.machine,
+                timestamp: headers.time_date_stamp,
+                export_name,
+            })
+        })
+    }
+
+    /// Parse the headers of the image at the start of the physical page at
+    /// `gpa`, if there is one.
+    fn carve_page(&self, gpa: Gpa, page: &mut [u8]) -> Result<Option<PeHeaders>> {
+        // Most pages aren't images, so only read the whole page if it could be one.
+        let mut magic = [0; 2];
+        self.phys_read_exact(gpa, &mut magic)?;
+        if &magic != b"MZ" {
+            return Ok(None);
+        }
+
+        self.phys_read_exact(gpa, page)?;
+
+        Ok(PeHeaders::parse(page)
+            .ok()
+            .filter(|headers| headers.plausible()))
+    }
+
+    /// Map the physical pages mapped in the kernel half of the address space
+    /// to the lowest address they are mapped at.
+    fn kernel_reverse_map(&self) -> HashMap<Gpa, Gva> {
+        let mut mapped = HashMap::new();
+        let res = self.for_each_kernel_page(&mut |gva, gpa| {
+            mapped.entry(gpa).or_insert(gva);
+
+            Ok(())
+        });
+
+        if let Err(e) = res {
+            trace_debug!("failed walking the kernel page tables: {e}");
+        }
+
+        mapped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DumpBuilder;
+    use crate::PxeFlags;
+
+    const DRIVER: u64 = 0xffff_f800_0500_0000;
+
+    /// The headers of an image that is `size_of_image` bytes long and that
+    /// exports itself as `name`.
+    fn headers(size_of_image: u32, name: &[u8]) -> Vec<u8> {
+        let mut page = vec![0; 0x400];
+        page[0..2].copy_from_slice(b"MZ");
+        page[0x3c..0x40].copy_from_slice(&0x80u32.to_le_bytes());
+        page[0x80..0x84].copy_from_slice(b"PE\0\0");
+        page[0x84..0x86].copy_from_slice(&0x8664u16.to_le_bytes());
+        page[0x88..0x8c].copy_from_slice(&0x1234u32.to_le_bytes());
+        page[0x98..0x9a].copy_from_slice(&0x20bu16.to_le_bytes());
+        page[0xd0..0xd4].copy_from_slice(&size_of_image.to_le_bytes());
+        page[0xd4..0xd8].copy_from_slice(&0x400u32.to_le_bytes());
+        page[0x104..0x108].copy_from_slice(&16u32.to_le_bytes());
+        page[0x108..0x10c].copy_from_slice(&0x300u32.to_le_bytes());
+        page[0x10c..0x110].copy_from_slice(&0x28u32.to_le_bytes());
+        page[0x30c..0x310].copy_from_slice(&0x340u32.to_le_bytes());
+        page[0x340..0x340 + name.len()].copy_from_slice(name);
+
+        page
+    }
+
+    #[test]
+    fn carve() {
+        // An image is mapped in the kernel, another one is only in physical
+        // memory, and a page starts with a DOS header but isn't an image.
+        let mut garbage = headers(0x2_000, b"garbage.sys\0");
+        garbage[0xd0..0xd4].copy_from_slice(&0u32.to_le_bytes());
+        let dump = DumpBuilder::new()
+            .map_virt(DRIVER, 0x10_000, PxeFlags::Present)
+            .write_virt(DRIVER, &headers(0x2_000, b"evil.sys\0"))
+            .write_phys(0x11_000, &headers(0x3_000, b"unmapped.sys\0"))
+            .write_phys(0x12_000, &garbage)
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+
+        assert_eq!(parser.carve_pe().collect::<Vec<_>>(), [
+            CarvedPe {
+                gpa: Gpa::new(0x10_000),
+                gva: Some(Gva::new(DRIVER)),
+                size_of_image: 0x2_000,
+                machine: 0x8664,
+                timestamp: 0x1234,
+                export_name: Some("evil.sys".into())
+            },
+            CarvedPe {
+                gpa: Gpa::new(0x11_000),
+                gva: None,
+                size_of_image: 0x3_000,
+                machine: 0x8664,
+                timestamp: 0x1234,
+                export_name: None
+            }
+        ]);
+    }
+}
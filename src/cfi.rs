@@ -0,0 +1,781 @@
+//! This contains a quick rootkit sweep (see [`CfiReport`]): it checks that the
+//! pointers the kernel transfers control through point where they are
+//! expected to:
+//! - the entries of the IDT of every processor point to `nt` or `hal`,
+//! - the services of `nt!KeServiceDescriptorTable` point to `nt`, and the ones
+//!   of the `win32k` table of `nt!KeServiceDescriptorTableShadow` to `win32k`,
+//! - the IRP handlers of the drivers of `\Driver` and `\FileSystem` point to
+//!   the driver, or to `nt`,
+//! - the callbacks registered with the kernel point to a module,
+//! - `MSR_LSTAR` of every processor points to `nt!KiSystemCall64`.
+//!
+//! The service tables aren't exported; they are found in the code of
+//! `nt!KiSystemServiceRepeat`, which `MSR_LSTAR` leads to. Each check is done
+//! independently: a check that can't be done, for example because the dump
+//! doesn't have what it needs, doesn't prevent the others from being done.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::KernelDumpParser;
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! let report = parser.control_flow_integrity_report();
+//! for finding in &report.findings {
+//!     println!("{finding}");
+//! }
+//!
+//! for (check, e) in &report.skipped {
+//!     println!("couldn't check the {check}: {e}");
+//! }
+//! ```
+use std::fmt::{self, Display};
+
+use crate::callback::rip_relative_targets;
+use crate::error::Result;
+use crate::export::module_short_name;
+use crate::gxa::Gxa;
+use crate::module::ModuleEntry;
+use crate::processor::KPCR_PRCB;
+use crate::structs::UnicodeString;
+use crate::trace::trace_debug;
+use crate::{Gva, KdmpParserError, KernelDumpParser, ReadMode};
+
+/// Where the `_KPCR` points to the IDT of its processor.
+///
+/// ```text
+/// kd> dt nt!_KPCR IdtBase
+///    +0x038 IdtBase          : Ptr64 _KIDTENTRY64
+/// ```
+const KPCR_IDT_BASE: u64 = 0x38;
+
+/// Number of entries of an IDT.
+const IDT_ENTRIES: u64 = 0x100;
+
+/// ```text
+/// kd> dt nt!_KIDTENTRY64
+///    +0x000 OffsetLow        : Uint2B
+///    +0x004 Present          : Pos 15, 1 Bit
+///    +0x006 OffsetMiddle     : Uint2B
+///    +0x008 OffsetHigh       : Uint4B
+/// ```
+const IDT_ENTRY_SIZE: usize = 0x10;
+const IDT_ENTRY_PRESENT: u16 = 1 << 15;
+
+/// Maximum number of services a service table can have.
+const MAX_SERVICES: u64 = 0x1_000;
+
+/// The `win32k` table is the second `_KSERVICE_TABLE_DESCRIPTOR` of
+/// `nt!KeServiceDescriptorTableShadow`, and its services are numbered after
+/// the ones of `nt`.
+const SERVICE_TABLE_DESCRIPTOR_SIZE: u64 = 0x20;
+const WIN32K_FIRST_SERVICE: usize = 0x1_000;
+
+/// How many bytes of the code `MSR_LSTAR` leads to are looked at for
+/// `nt!KiSystemServiceRepeat`.
+const SYSCALL_SCAN_SIZE: u64 = 0x1_000;
+
+/// How many of the routines that code jumps to are looked at.
+const MAX_SYSCALL_BRANCHES: usize = 8;
+
+/// The routines of `nt` `MSR_LSTAR` points to, the latter with KVA shadowing.
+const SYSCALL_HANDLERS: [&str; 2] = ["KiSystemCall64", "KiSystemCall64Shadow"];
+
+/// ```text
+/// kd> dt nt!_DRIVER_OBJECT
+///    +0x000 Type             : Int2B
+///    +0x002 Size             : Int2B
+///    +0x018 DriverStart      : Ptr64 Void
+///    +0x020 DriverSize       : Uint4B
+///    +0x038 DriverName       : _UNICODE_STRING
+///    +0x070 MajorFunction    : [28] Ptr64     long
+/// ```
+const DRIVER_OBJECT_START: u64 = 0x18;
+const DRIVER_OBJECT_SIZE_OF_IMAGE: u64 = 0x20;
+const DRIVER_OBJECT_NAME: u64 = 0x38;
+const DRIVER_OBJECT_MAJOR_FUNCTION: u64 = 0x70;
+const DRIVER_OBJECT_SIZE: u16 = 0x150;
+
+/// `IO_TYPE_DRIVER`, the `Type` of the driver objects.
+const IO_TYPE_DRIVER: u16 = 4;
+
+/// The names of the entries of `MajorFunction`.
+const IRP_MJ_NAMES: [&str; 28] = [
+    "IRP_MJ_CREATE",
+    "IRP_MJ_CREATE_NAMED_PIPE",
+    "IRP_MJ_CLOSE",
+    "IRP_MJ_READ",
+    "IRP_MJ_WRITE",
+    "IRP_MJ_QUERY_INFORMATION",
+    "IRP_MJ_SET_INFORMATION",
+    "IRP_MJ_QUERY_EA",
+    "IRP_MJ_SET_EA",
+    "IRP_MJ_FLUSH_BUFFERS",
+    "IRP_MJ_QUERY_VOLUME_INFORMATION",
+    "IRP_MJ_SET_VOLUME_INFORMATION",
+    "IRP_MJ_DIRECTORY_CONTROL",
+    "IRP_MJ_FILE_SYSTEM_CONTROL",
+    "IRP_MJ_DEVICE_CONTROL",
+    "IRP_MJ_INTERNAL_DEVICE_CONTROL",
+    "IRP_MJ_SHUTDOWN",
+    "IRP_MJ_LOCK_CONTROL",
+    "IRP_MJ_CLEANUP",
+    "IRP_MJ_CREATE_MAILSLOT",
+    "IRP_MJ_QUERY_SECURITY",
+    "IRP_MJ_SET_SECURITY",
+    "IRP_MJ_POWER",
+    "IRP_MJ_SYSTEM_CONTROL",
+    "IRP_MJ_DEVICE_CHANGE",
+    "IRP_MJ_QUERY_QUOTA",
+    "IRP_MJ_SET_QUOTA",
+    "IRP_MJ_PNP",
+];
+
+/// A check of [`KernelDumpParser::control_flow_integrity_report`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CfiCheck {
+    /// The entries of the IDT of every processor.
+    Idt,
+    /// The services of the system service tables.
+    ServiceTables,
+    /// The IRP handlers of the drivers.
+    IrpHandlers,
+    /// The callbacks registered with the kernel; see
+    /// [`KernelDumpParser::kernel_callbacks`].
+    KernelCallbacks,
+    /// `MSR_LSTAR` of every processor.
+    SyscallMsr,
+}
+
+impl Display for CfiCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Idt => "IDT",
+            Self::ServiceTables => "system service tables",
+            Self::IrpHandlers => "IRP handlers",
+            Self::KernelCallbacks => "kernel callbacks",
+            Self::SyscallMsr => "syscall MSR",
+        })
+    }
+}
+
+/// A pointer that doesn't point where it is expected to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CfiFinding {
+    /// The check that found it.
+    pub check: CfiCheck,
+    /// Where the pointer is, like `IDT vector 0xe of processor 1`.
+    pub location: String,
+    /// Where the pointer points to.
+    pub address: Gva,
+    /// Where it is expected to point to, like `nt or hal`.
+    pub expected: String,
+    /// `address` symbolized with [`KernelDumpParser::symbolize`]; `None` if it
+    /// isn't in any module.
+    pub actual: Option<String>,
+}
+
+impl Display for CfiFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.address)?;
+        match &self.actual {
+            Some(actual) => write!(f, " ({actual})")?,
+            None => write!(f, " (no module)")?,
+        }
+
+        write!(f, " instead of {}", self.expected)
+    }
+}
+
+/// The result of [`KernelDumpParser::control_flow_integrity_report`].
+#[derive(Debug, Default)]
+pub struct CfiReport {
+    /// The pointers that don't point where they are expected to.
+    pub findings: Vec<CfiFinding>,
+    /// The checks that have been done.
+    pub completed: Vec<CfiCheck>,
+    /// The checks that couldn't be done, and why.
+    pub skipped: Vec<(CfiCheck, KdmpParserError)>,
+}
+
+impl CfiReport {
+    /// Has nothing been found by the checks that have been done?
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+}
+
+/// Get the target of an IDT entry, if it is present.
+fn idt_entry_target(entry: &[u8]) -> Option<u64> {
+    let u16_at = |offset: usize| u16::from_le_bytes([entry[offset], entry[offset + 1]]);
+    if u16_at(4) & IDT_ENTRY_PRESENT == 0 {
+        return None;
+    }
+
+    let high = u32::from_le_bytes(entry[8..12].try_into().unwrap());
+
+    Some(u64::from(u16_at(0)) | u64::from(u16_at(6)) << 16 | u64::from(high) << 32)
+}
+
+/// Find the `lea r10, [rip + disp32]` followed by `lea r11, [rip + disp32]`
+/// of `nt!KiSystemServiceRepeat` in `code`, which is at `address`, and return
+/// their targets: `nt!KeServiceDescriptorTable` and
+/// `nt!KeServiceDescriptorTableShadow`.
+fn service_table_leas(code: &[u8], address: u64) -> Option<(u64, u64)> {
+    code.windows(14).enumerate().find_map(|(idx, window)| {
+        if window[..3] != [0x4c, 0x8d, 0x15] || window[7..10] != [0x4c, 0x8d, 0x1d] {
+            return None;
+        }
+
+        let target = |end: usize| {
+            let disp = i32::from_le_bytes(window[end - 4..end].try_into().unwrap());
+
+            address
+                .wrapping_add((idx + end) as u64)
+                .wrapping_add(i64::from(disp) as u64)
+        };
+
+        Some((target(7), target(14)))
+    })
+}
+
+impl KernelDumpParser {
+    /// Check the pointers the kernel transfers control through, and report
+    /// the ones that don't point where they are expected to; see
+    /// [`CfiCheck`] for what is checked. The checks that can't be done are
+    /// reported as skipped.
+    pub fn control_flow_integrity_report(&self) -> CfiReport {
+        let mut report = CfiReport::default();
+        for (check, findings) in [
+            (CfiCheck::Idt, self.idt_findings()),
+            (CfiCheck::ServiceTables, self.service_table_findings()),
+            (CfiCheck::IrpHandlers, self.irp_handler_findings()),
+            (CfiCheck::KernelCallbacks, self.callback_findings()),
+            (CfiCheck::SyscallMsr, self.syscall_msr_findings()),
+        ] {
+            match findings {
+                Ok(findings) => {
+                    report.completed.push(check);
+                    report.findings.extend(findings);
+                }
+                Err(e) => report.skipped.push((check, e)),
+            }
+        }
+
+        report
+    }
+
+    /// Is `gva` in one of the modules that go by `names` in symbols?
+    fn in_modules(&self, gva: Gva, names: &[&str]) -> bool {
+        self.find_module_entry(gva).is_some_and(|module| {
+            let short_name = module_short_name(&module.name);
+
+            names
+                .iter()
+                .any(|name| short_name.eq_ignore_ascii_case(name))
+        })
+    }
+
+    /// Describe the pointer to `address` at `location`.
+    fn cfi_finding(
+        &self,
+        check: CfiCheck,
+        location: String,
+        address: Gva,
+        expected: impl Into<String>,
+    ) -> CfiFinding {
+        CfiFinding {
+            check,
+            location,
+            address,
+            expected: expected.into(),
+            actual: self.symbolize(address),
+        }
+    }
+
+    /// The entries of the IDTs that don't point to `nt` or `hal`; the
+    /// processors whose IDT can't be read are skipped.
+    fn idt_findings(&self) -> Result<Vec<CfiFinding>> {
+        let mut read = false;
+        let mut findings = Vec::new();
+        for (processor, prcb) in self.prcbs().into_iter().enumerate() {
+            let Some(idt_base) = prcb
+                .and_then(|prcb| prcb.u64().checked_sub(KPCR_PRCB))
+                .and_then(|pcr| pcr.checked_add(KPCR_IDT_BASE))
+            else {
+                continue;
+            };
+
+            let entries = self
+                .virt_read_ptr(idt_base)
+                .and_then(|idt| self.virt_read_to_vec(idt, IDT_ENTRIES * IDT_ENTRY_SIZE as u64));
+            let Ok(entries) = entries else {
+                trace_debug!("failed reading the IDT of processor {processor}");
+                continue;
+            };
+
+            read = true;
+            for (vector, entry) in entries.chunks_exact(IDT_ENTRY_SIZE).enumerate() {
+                let Some(target) = idt_entry_target(entry).map(Gva::new) else {
+                    continue;
+                };
+
+                if !self.in_modules(target, &["nt", "hal"]) {
+                    findings.push(self.cfi_finding(
+                        CfiCheck::Idt,
+                        format!("IDT vector {vector:#x} of processor {processor}"),
+                        target,
+                        "nt or hal",
+                    ));
+                }
+            }
+        }
+
+        if !read {
+            return Err(KdmpParserError::NotFound("an IDT"));
+        }
+
+        Ok(findings)
+    }
+
+    /// The services of the service tables that don't point to the module they
+    /// belong to. The `win32k` table is skipped if it can't be read, as it is
+    /// in session space.
+    fn service_table_findings(&self) -> Result<Vec<CfiFinding>> {
+        let mut candidates = self
+            .lstars()
+            .into_iter()
+            .map(|(_, lstar)| lstar)
+            .collect::<Vec<_>>();
+        candidates.extend(self.syscall_handlers());
+
+        let (table, shadow) = candidates
+            .into_iter()
+            .find_map(|candidate| self.find_service_tables(candidate))
+            .ok_or(KdmpParserError::NotFound("nt!KeServiceDescriptorTable"))?;
+
+        let mut findings = self.service_table(table, "nt", 0, &["nt"])?;
+        let win32k = shadow
+            .u64()
+            .checked_add(SERVICE_TABLE_DESCRIPTOR_SIZE)
+            .ok_or(KdmpParserError::Overflow("win32k service table"))
+            .and_then(|table| {
+                self.service_table(Gva::new(table), "win32k", WIN32K_FIRST_SERVICE, &[
+                    "win32k",
+                    "win32kbase",
+                    "win32kfull",
+                ])
+            });
+
+        match win32k {
+            Ok(win32k) => findings.extend(win32k),
+            Err(e) => trace_debug!("failed reading the win32k service table: {e}"),
+        }
+
+        Ok(findings)
+    }
+
+    /// Find `nt!KeServiceDescriptorTable` and
+    /// `nt!KeServiceDescriptorTableShadow` in the code at `routine`, or in the
+    /// code of the first routines of `nt` it jumps to.
+    fn find_service_tables(&self, routine: Gva) -> Option<(Gva, Gva)> {
+        let scan = |routine: Gva| {
+            self.virt_read_to_vec_with_mode(routine, SYSCALL_SCAN_SIZE, ReadMode::Partial)
+                .unwrap_or_default()
+        };
+
+        let code = scan(routine);
+        let (_, branches) = rip_relative_targets(&code, routine.u64());
+        let found = service_table_leas(&code, routine.u64()).or_else(|| {
+            branches
+                .into_iter()
+                .map(Gva::new)
+                .filter(|&branch| self.in_modules(branch, &["nt"]))
+                .take(MAX_SYSCALL_BRANCHES)
+                .find_map(|branch| service_table_leas(&scan(branch), branch.u64()))
+        });
+
+        found.map(|(table, shadow)| (Gva::new(table), Gva::new(shadow)))
+    }
+
+    /// The services of the `_KSERVICE_TABLE_DESCRIPTOR` at `descriptor` that
+    /// aren't in the modules `expected`. The table has the services of
+    /// `module`, numbered from `first_service`.
+    fn service_table(
+        &self,
+        descriptor: Gva,
+        module: &str,
+        first_service: usize,
+        expected: &[&str],
+    ) -> Result<Vec<CfiFinding>> {
+        let [base, _count, limit, _number] = self.virt_read_struct::<[u64; 4]>(descriptor)?;
+        if limit > MAX_SERVICES {
+            return Err(KdmpParserError::InvalidData("too many services"));
+        }
+
+        let entries = self.virt_read_to_vec(base, limit * 4)?;
+        let mut findings = Vec::new();
+        for (idx, entry) in entries.chunks_exact(4).enumerate() {
+            // The low 4 bits of an entry are the number of arguments passed on the stack.
+            let offset = i32::from_le_bytes(entry.try_into().unwrap()) >> 4;
+            let target = Gva::new(base.wrapping_add(i64::from(offset) as u64));
+            if !self.in_modules(target, expected) {
+                findings.push(self.cfi_finding(
+                    CfiCheck::ServiceTables,
+                    format!("{module} service {:#x}", first_service + idx),
+                    target,
+                    expected.join(" or "),
+                ));
+            }
+        }
+
+        Ok(findings)
+    }
+
+    /// The IRP handlers of the drivers of `\Driver` and `\FileSystem` that
+    /// point neither to their driver nor to `nt`; the driver objects that
+    /// can't be read are skipped.
+    fn irp_handler_findings(&self) -> Result<Vec<CfiFinding>> {
+        let mut findings = Vec::new();
+        for driver in self.root_directory_objects(&["Driver", "FileSystem"])? {
+            match self.driver_irp_handler_findings(driver) {
+                Ok(driver_findings) => findings.extend(driver_findings),
+                Err(e) => trace_debug!("failed reading the driver object at {driver}: {e}"),
+            }
+        }
+
+        Ok(findings)
+    }
+
+    /// The IRP handlers of the `_DRIVER_OBJECT` at `driver` that point neither
+    /// to its image nor to `nt`; nothing if it isn't a driver object.
+    fn driver_irp_handler_findings(&self, driver: Gva) -> Result<Vec<CfiFinding>> {
+        let field = |offset: u64| {
+            driver
+                .u64()
+                .checked_add(offset)
+                .map(Gva::new)
+                .ok_or(KdmpParserError::Overflow("driver object field"))
+        };
+
+        let [kind, size] = self.virt_read_struct::<[u16; 2]>(driver)?;
+        if kind != IO_TYPE_DRIVER || size != DRIVER_OBJECT_SIZE {
+            return Ok(Vec::new());
+        }
+
+        let start = self.virt_read_ptr(field(DRIVER_OBJECT_START)?)?;
+        let size_of_image = self.virt_read_struct::<u32>(field(DRIVER_OBJECT_SIZE_OF_IMAGE)?)?;
+        let image = start..Gva::new(start.u64().saturating_add(size_of_image.into()));
+        let name = self.virt_read_struct::<UnicodeString>(field(DRIVER_OBJECT_NAME)?)?;
+        let name = self
+            .try_virt_read_unicode_string(&name)?
+            .unwrap_or_else(|| format!("driver object {driver}"));
+        let owner = self
+            .find_module_entry(start)
+            .map_or(name.as_str(), |module: &ModuleEntry| {
+                module_short_name(&module.name)
+            });
+
+        let handlers = self
+            .virt_read_struct::<[u64; IRP_MJ_NAMES.len()]>(field(DRIVER_OBJECT_MAJOR_FUNCTION)?)?;
+        let mut findings = Vec::new();
+        for (handler, irp) in handlers.into_iter().map(Gva::new).zip(IRP_MJ_NAMES) {
+            // The handlers that aren't set point to `nt!IopInvalidDeviceRequest`.
+            if handler.u64() == 0 || image.contains(&handler) || self.in_modules(handler, &["nt"]) {
+                continue;
+            }
+
+            findings.push(self.cfi_finding(
+                CfiCheck::IrpHandlers,
+                format!("{irp} of {name}"),
+                handler,
+                format!("{owner} or nt"),
+            ));
+        }
+
+        Ok(findings)
+    }
+
+    /// The callbacks registered with the kernel that aren't in any module.
+    fn callback_findings(&self) -> Result<Vec<CfiFinding>> {
+        Ok(self
+            .kernel_callbacks()?
+            .into_iter()
+            .filter(|callback| callback.module.is_none())
+            .map(|callback| {
+                self.cfi_finding(
+                    CfiCheck::KernelCallbacks,
+                    format!("{} callback", callback.kind),
+                    callback.routine,
+                    "a module",
+                )
+            })
+            .collect())
+    }
+
+    /// The `MSR_LSTAR`s that don't point to `nt!KiSystemCall64`, or to `nt` if
+    /// it isn't exported.
+    fn syscall_msr_findings(&self) -> Result<Vec<CfiFinding>> {
+        let lstars = self.lstars();
+        if lstars.is_empty() {
+            return Err(KdmpParserError::NotFound("MSR_LSTAR"));
+        }
+
+        let handlers = self.syscall_handlers();
+        let expected = if handlers.is_empty() {
+            "nt"
+        } else {
+            "nt!KiSystemCall64"
+        };
+
+        Ok(lstars
+            .into_iter()
+            .filter(|(_, lstar)| {
+                if handlers.is_empty() {
+                    !self.in_modules(*lstar, &["nt"])
+                } else {
+                    !handlers.contains(lstar)
+                }
+            })
+            .map(|(processor, lstar)| {
+                self.cfi_finding(
+                    CfiCheck::SyscallMsr,
+                    format!("MSR_LSTAR of processor {processor}"),
+                    lstar,
+                    expected,
+                )
+            })
+            .collect())
+    }
+
+    /// The `MSR_LSTAR` saved in the `_KPRCB` of the processors, by processor;
+    /// the ones that can't be read, or that aren't set, are skipped.
+    fn lstars(&self) -> Vec<(usize, Gva)> {
+        self.prcbs()
+            .into_iter()
+            .enumerate()
+            .filter_map(|(processor, prcb)| {
+                let lstar = self
+                    .read_field(prcb?, "_KPRCB", "ProcessorState.SpecialRegisters.MsrLStar")
+                    .ok()?;
+
+                (lstar != 0).then_some((processor, Gva::new(lstar)))
+            })
+            .collect()
+    }
+
+    /// The addresses of the routines of `nt` `MSR_LSTAR` points to, if they
+    /// are exported.
+    fn syscall_handlers(&self) -> Vec<Gva> {
+        let nt = self
+            .kernel_module_entries()
+            .find(|module| module_short_name(&module.name) == "nt")
+            .cloned()
+            .map_or_else(|| self.nt_module(), Ok);
+        let Ok(exports) = nt.and_then(|nt| self.module_exports(&nt)) else {
+            return Vec::new();
+        };
+
+        exports
+            .into_iter()
+            .filter(|export| {
+                export.forwarder.is_none() && SYSCALL_HANDLERS.contains(&export.name.as_str())
+            })
+            .map(|export| export.address)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{DumpBuilder, KdDebuggerData};
+    use crate::PxeFlags;
+
+    const NT: u64 = 0xffff_f800_0000_0000;
+    const DRIVER: u64 = NT + 0x10_0000;
+    const KDBG: u64 = 0xffff_f800_2000_0000;
+    const POOL: u64 = 0xffff_c000_0000_0000;
+    const PCR: u64 = POOL + 0x10_000;
+
+    /// An IDT entry that points to `target`.
+    fn idt_entry(target: u64) -> [u8; IDT_ENTRY_SIZE] {
+        let mut entry = [0; IDT_ENTRY_SIZE];
+        entry[..2].copy_from_slice(&(target as u16).to_le_bytes());
+        entry[4..6].copy_from_slice(&IDT_ENTRY_PRESENT.to_le_bytes());
+        entry[6..8].copy_from_slice(&((target >> 16) as u16).to_le_bytes());
+        entry[8..12].copy_from_slice(&((target >> 32) as u32).to_le_bytes());
+
+        entry
+    }
+
+    #[test]
+    fn report() {
+        // Both processors share an IDT, one of whose entries points to the pool;
+        // the entries that aren't present are ignored.
+        let idt = POOL;
+        let mut not_present = idt_entry(DRIVER);
+        not_present[5] = 0;
+        let prcbs = [PCR + KPCR_PRCB, PCR + 0x1_000 + KPCR_PRCB];
+        let mut builder = DumpBuilder::new()
+            .processors(2)
+            .module(NT..NT + 0x4_000, "ntoskrnl.exe")
+            .module(DRIVER..DRIVER + 0x1_000, "evil.sys")
+            .map_virt(KDBG, 0x10_000, PxeFlags::Present)
+            .kd_debugger_data(KDBG, KdDebuggerData {
+                prcbs: prcbs.to_vec(),
+                ..Default::default()
+            })
+            .map_virt(idt, 0x11_000, PxeFlags::Present)
+            .write_virt(idt, &idt_entry(NT + 0x10))
+            .write_virt(idt + 0x10, &idt_entry(POOL + 0x800))
+            .write_virt(idt + 0x20, &not_present);
+        for (idx, prcb) in prcbs.into_iter().enumerate() {
+            builder = builder
+                .map_virt(
+                    prcb & !0xfff,
+                    0x12_000 + (idx as u64 * 0x1_000),
+                    PxeFlags::Present,
+                )
+                .write_virt(prcb - KPCR_PRCB + KPCR_IDT_BASE, &idt.to_le_bytes());
+        }
+
+        // The first processor's `MSR_LSTAR` jumps to `nt!KiSystemServiceRepeat`,
+        // the second one's points to the driver.
+        let (lstar, repeat) = (NT + 0x100, NT + 0x800);
+        let (table, shadow, services) = (NT + 0x2_000, NT + 0x2_040, NT + 0x3_000);
+        let mut jmp = vec![0xe9];
+        jmp.extend_from_slice(&((repeat - (lstar + 5)) as u32).to_le_bytes());
+        let mut leas = vec![0x4c, 0x8d, 0x15];
+        leas.extend_from_slice(&((table - (repeat + 7)) as u32).to_le_bytes());
+        leas.extend_from_slice(&[0x4c, 0x8d, 0x1d]);
+        leas.extend_from_slice(&((shadow - (repeat + 14)) as u32).to_le_bytes());
+
+        // The second service points to the driver.
+        let mut descriptor = services.to_le_bytes().to_vec();
+        descriptor.extend_from_slice(&[0; 8]);
+        descriptor.extend_from_slice(&2u64.to_le_bytes());
+        let hook = ((DRIVER + 0x20 - services) as i32) << 4;
+        let dump = builder
+            .write_virt(prcbs[0] + 0x100, &lstar.to_le_bytes())
+            .write_virt(prcbs[1] + 0x100, &(DRIVER + 0x10).to_le_bytes())
+            .map_virt(NT, 0x20_000, PxeFlags::Present)
+            .map_virt(NT + 0x2_000, 0x22_000, PxeFlags::Present)
+            .map_virt(NT + 0x3_000, 0x23_000, PxeFlags::Present)
+            .write_virt(lstar, &jmp)
+            .write_virt(repeat, &leas)
+            .write_virt(table, &descriptor)
+            .write_virt(services, &(0x10i32 << 4).to_le_bytes())
+            .write_virt(services + 4, &hook.to_le_bytes())
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+
+        let report = parser.control_flow_integrity_report();
+        let findings = report
+            .findings
+            .iter()
+            .map(|finding| {
+                (
+                    finding.check,
+                    finding.location.as_str(),
+                    finding.address.u64(),
+                    finding.actual.as_deref(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(findings, [
+            (
+                CfiCheck::Idt,
+                "IDT vector 0x1 of processor 0",
+                POOL + 0x800,
+                None
+            ),
+            (
+                CfiCheck::Idt,
+                "IDT vector 0x1 of processor 1",
+                POOL + 0x800,
+                None
+            ),
+            (
+                CfiCheck::ServiceTables,
+                "nt service 0x1",
+                DRIVER + 0x20,
+                Some("evil+0x20")
+            ),
+            (
+                CfiCheck::SyscallMsr,
+                "MSR_LSTAR of processor 1",
+                DRIVER + 0x10,
+                Some("evil+0x10")
+            ),
+        ]);
+        assert_eq!(report.findings[3].expected, "nt");
+        assert_eq!(report.completed, [
+            CfiCheck::Idt,
+            CfiCheck::ServiceTables,
+            CfiCheck::SyscallMsr
+        ]);
+
+        // There is no object namespace, nor exports to find the callbacks with.
+        let skipped = report
+            .skipped
+            .iter()
+            .map(|(check, _)| *check)
+            .collect::<Vec<_>>();
+        assert_eq!(skipped, [CfiCheck::IrpHandlers, CfiCheck::KernelCallbacks]);
+    }
+
+    #[test]
+    fn irp_handlers() {
+        let driver = POOL;
+        let name = POOL + 0x200;
+        let utf16 = "\\Driver\\evil"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect::<Vec<_>>();
+        let mut object = vec![0; DRIVER_OBJECT_SIZE as usize];
+        object[..2].copy_from_slice(&IO_TYPE_DRIVER.to_le_bytes());
+        object[2..4].copy_from_slice(&DRIVER_OBJECT_SIZE.to_le_bytes());
+        object[0x18..0x20].copy_from_slice(&DRIVER.to_le_bytes());
+        object[0x20..0x24].copy_from_slice(&0x1_000u32.to_le_bytes());
+        object[0x38..0x3a].copy_from_slice(&(utf16.len() as u16).to_le_bytes());
+        object[0x3a..0x3c].copy_from_slice(&(utf16.len() as u16).to_le_bytes());
+        object[0x40..0x48].copy_from_slice(&name.to_le_bytes());
+
+        // `IRP_MJ_CREATE` is handled by the driver, `IRP_MJ_CLOSE` by `nt`, and
+        // `IRP_MJ_DEVICE_CONTROL` by the pool.
+        for (idx, handler) in [(0, DRIVER + 0x10), (2, NT + 0x10), (14, POOL + 0x800)] {
+            let offset = 0x70 + (idx * 8);
+            object[offset..offset + 8].copy_from_slice(&handler.to_le_bytes());
+        }
+
+        let dump = DumpBuilder::new()
+            .module(NT..NT + 0x4_000, "ntoskrnl.exe")
+            .module(DRIVER..DRIVER + 0x1_000, "evil.sys")
+            .map_virt(POOL, 0x10_000, PxeFlags::Present)
+            .write_virt(driver, &object)
+            .write_virt(name, &utf16)
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+
+        assert_eq!(
+            parser
+                .driver_irp_handler_findings(Gva::new(driver))
+                .unwrap(),
+            [CfiFinding {
+                check: CfiCheck::IrpHandlers,
+                location: "IRP_MJ_DEVICE_CONTROL of \\Driver\\evil".into(),
+                address: Gva::new(POOL + 0x800),
+                expected: "evil or nt".into(),
+                actual: None
+            }]
+        );
+
+        // Something that isn't a driver object has no handlers.
+        assert_eq!(
+            parser
+                .driver_irp_handler_findings(Gva::new(POOL + 0x8))
+                .unwrap(),
+            []
+        );
+    }
+}
@@ -0,0 +1,308 @@
+//! This contains [`PageClassification`] which partitions the physical pages
+//! of a dump by how the page tables reference them; it tells for example how
+//! much user memory a dump captured.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::KernelDumpParser;
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! let classification = parser.classify_pages(None).unwrap();
+//! println!("{classification}");
+//! println!(
+//!     "{:#x} bytes of user memory",
+//!     classification.user_mapped.bytes()
+//! );
+//! ```
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{self, Display};
+
+use crate::cancel::CancellationToken;
+use crate::error::Result;
+use crate::gxa::{Gpa, Gxa};
+use crate::pxe::Pxe;
+use crate::structs::Page;
+use crate::{KdmpParserError, KernelDumpParser};
+
+/// Index of the first PML4 entry that maps kernel space.
+const KERNEL_PML4E: usize = 0x100;
+
+/// How a physical page is referenced, from the weakest to the strongest; a
+/// page referenced in several ways is classified by the strongest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Class {
+    Unmapped,
+    Kernel,
+    User,
+    PageTable,
+}
+
+/// Physical pages that belong to the same bucket of a
+/// [`PageClassification`], in ascending order.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PageBucket {
+    gpas: Vec<Gpa>,
+}
+
+impl PageBucket {
+    /// Number of pages in the bucket.
+    pub fn pages(&self) -> u64 {
+        self.gpas.len() as u64
+    }
+
+    /// Number of bytes in the bucket.
+    pub fn bytes(&self) -> u64 {
+        self.pages() * Page::size()
+    }
+
+    /// Iterate over the pages of the bucket.
+    pub fn gpas(&self) -> impl ExactSizeIterator<Item = Gpa> + '_ {
+        self.gpas.iter().copied()
+    }
+}
+
+/// The physical pages of a dump partitioned by how the page tables reference
+/// them; every page of the dump is in exactly one bucket.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PageClassification {
+    /// Pages mapped in kernel space (and not in user space).
+    pub kernel_mapped: PageBucket,
+    /// Pages mapped in user space.
+    pub user_mapped: PageBucket,
+    /// Pages that aren't mapped.
+    pub unmapped_present: PageBucket,
+    /// Pages that hold page tables.
+    pub page_table_pages: PageBucket,
+}
+
+impl Display for PageClassification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let buckets = [
+            ("Kernel mapped", &self.kernel_mapped),
+            ("User mapped", &self.user_mapped),
+            ("Unmapped", &self.unmapped_present),
+            ("Page tables", &self.page_table_pages),
+        ];
+
+        for (idx, (name, bucket)) in buckets.into_iter().enumerate() {
+            if idx != 0 {
+                writeln!(f)?;
+            }
+
+            write!(
+                f,
+                "{name:<16}: {:#x} pages ({:#x} bytes)",
+                bucket.pages(),
+                bucket.bytes()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Walks the page tables and records how every physical page is referenced.
+struct Classifier<'parser> {
+    parser: &'parser KernelDumpParser,
+    cancel: &'parser CancellationToken,
+    /// The class of the pages of the dump that are referenced; the others
+    /// aren't mapped.
+    classes: BTreeMap<Gpa, Class>,
+    /// The tables already walked; the same table can be reached more than
+    /// once, through the self-referencing entry for example.
+    walked: HashSet<(Gpa, u8, bool)>,
+    table: Vec<u8>,
+}
+
+impl<'parser> Classifier<'parser> {
+    fn new(parser: &'parser KernelDumpParser, cancel: &'parser CancellationToken) -> Self {
+        Self {
+            parser,
+            cancel,
+            classes: BTreeMap::new(),
+            walked: HashSet::new(),
+            table: vec![0; Page::size() as usize],
+        }
+    }
+
+    /// Classify the pages of `[gpa, gpa + size)` that are in the dump.
+    fn mark(&mut self, gpa: Gpa, size: u64, class: Class) {
+        let end = Gpa::new(gpa.u64().saturating_add(size));
+        for (&gpa, _) in self.parser.physmem.range(gpa..end) {
+            let current = self.classes.entry(gpa).or_insert(Class::Unmapped);
+            *current = (*current).max(class);
+        }
+    }
+
+    /// Walk the table at `gpa`; `level` is 4 for a PML4 and 1 for a page table.
+    fn walk(&mut self, gpa: Gpa, level: u8, user: bool) -> Result<()> {
+        if !self.walked.insert((gpa, level, user)) {
+            return Ok(());
+        }
+
+        self.cancel.check()?;
+
+        // The tables that aren't in the dump can't be walked.
+        match self.parser.phys_read_exact(gpa, &mut self.table) {
+            Ok(()) => {}
+            Err(KdmpParserError::AddrTranslation(..) | KdmpParserError::PartialPhysRead) => {
+                return Ok(())
+            }
+            Err(e) => return Err(e),
+        }
+
+        self.mark(gpa, Page::size(), Class::PageTable);
+        let entries = self
+            .table
+            .chunks_exact(8)
+            .map(|entry| Pxe::from(u64::from_le_bytes(entry.try_into().unwrap())))
+            .collect::<Vec<_>>();
+
+        for (idx, pxe) in entries.into_iter().enumerate() {
+            let user = if level == 4 { idx < KERNEL_PML4E } else { user };
+            let class = if user { Class::User } else { Class::Kernel };
+            let base = pxe.pfn.gpa();
+            match level {
+                // The transition entries can be read, so they count as mapped.
+                1 if pxe.present() || pxe.transition() => self.mark(base, Page::size(), class),
+                1 => {}
+                _ if !pxe.present() => {}
+                3 if pxe.large_page() => self.mark(base, 0x4000_0000, class),
+                2 if pxe.large_page() => self.mark(base, 0x20_0000, class),
+                _ => self.walk(base, level - 1, user)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn classification(self) -> PageClassification {
+        let mut classification = PageClassification::default();
+        for &gpa in self.parser.physmem.keys() {
+            let class = self.classes.get(&gpa).copied();
+            let bucket = match class.unwrap_or(Class::Unmapped) {
+                Class::Unmapped => &mut classification.unmapped_present,
+                Class::Kernel => &mut classification.kernel_mapped,
+                Class::User => &mut classification.user_mapped,
+                Class::PageTable => &mut classification.page_table_pages,
+            };
+
+            bucket.gpas.push(gpa);
+        }
+
+        classification
+    }
+}
+
+impl KernelDumpParser {
+    /// Partition the physical pages of the dump by how the page tables of
+    /// `dtb` (the directory table base of the dump if `None`) reference them:
+    /// the pages holding the page tables, the pages mapped in user space, the
+    /// ones mapped in kernel space only, and the ones that aren't mapped. The
+    /// whole virtual address space is walked.
+    pub fn classify_pages(&self, dtb: Option<Gpa>) -> Result<PageClassification> {
+        self.classify_pages_with_cancellation(dtb, &CancellationToken::new())
+    }
+
+    /// Partition the physical pages of the dump by how the page tables of
+    /// `dtb` reference them, like [`KernelDumpParser::classify_pages`], until
+    /// `cancel` is cancelled.
+    pub fn classify_pages_with_cancellation(
+        &self,
+        dtb: Option<Gpa>,
+        cancel: &CancellationToken,
+    ) -> Result<PageClassification> {
+        let dtb = dtb.unwrap_or(self.default_dtb()).page_align();
+        let mut classifier = Classifier::new(self, cancel);
+        classifier.walk(dtb, 4, false)?;
+
+        Ok(classifier.classification())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::{FromLeBytes, Header64};
+    use crate::testing::DumpBuilder;
+    use crate::PxeFlags;
+
+    fn gpas(bucket: &PageBucket) -> Vec<u64> {
+        bucket.gpas().map(|gpa| gpa.u64()).collect()
+    }
+
+    #[test]
+    fn classify() {
+        let kernel = 0xffff_f800_0000_0000;
+        let user = 0x7ff6_0000_0000;
+        let dump = DumpBuilder::new()
+            .write_phys(0x10_000, &[1])
+            .write_phys(0x11_000, &[1])
+            .write_phys(0x12_000, &[1])
+            .write_phys(0x13_000, &[1])
+            .write_phys(0x40_0000, &[1])
+            .write_phys(0x40_1000, &[1])
+            .map_virt(kernel, 0x10_000, PxeFlags::Present)
+            .map_virt(kernel + 0x1_000, 0x11_000, PxeFlags::Transition)
+            // Mapped in both halves, so it counts as user memory.
+            .map_virt(kernel + 0x2_000, 0x12_000, PxeFlags::Present)
+            .map_virt(user, 0x12_000, PxeFlags::Present | PxeFlags::UserAccessible)
+            // Mapped, but not in the dump.
+            .map_virt(user + 0x1_000, 0x14_000, PxeFlags::Present)
+            .map_virt_large(kernel + 0x20_0000, 0x40_0000, PxeFlags::Present)
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+
+        let classification = parser.classify_pages(None).unwrap();
+        assert_eq!(gpas(&classification.kernel_mapped), [
+            0x10_000, 0x11_000, 0x40_0000, 0x40_1000
+        ]);
+        assert_eq!(gpas(&classification.user_mapped), [0x12_000]);
+        assert_eq!(gpas(&classification.unmapped_present), [0x13_000]);
+        // A PML4, and a PDPT, a PD and a PT in each half.
+        assert_eq!(classification.page_table_pages.pages(), 7);
+        assert_eq!(classification.page_table_pages.bytes(), 7 * 0x1_000);
+        assert_eq!(
+            classification.to_string(),
+            "\
+Kernel mapped   : 0x4 pages (0x4000 bytes)
+User mapped     : 0x1 pages (0x1000 bytes)
+Unmapped        : 0x1 pages (0x1000 bytes)
+Page tables     : 0x7 pages (0x7000 bytes)"
+        );
+
+        // Nothing is mapped by a directory table base that isn't in the dump.
+        let classification = parser.classify_pages(Some(Gpa::new(0x1_000_000))).unwrap();
+        assert_eq!(
+            classification.unmapped_present.pages(),
+            parser.physmem_len()
+        );
+    }
+
+    #[test]
+    fn sparse() {
+        // A full dump with the PML4 at 0x2_000, and a page at 2PB: the classes
+        // aren't laid out up to the highest page.
+        let high = 1u64 << 51;
+        let mut dump = vec![0; Header64::SIZE + 0x2_000];
+        dump[0x0..0x4].copy_from_slice(b"PAGE");
+        dump[0x4..0x8].copy_from_slice(b"DU64");
+        dump[0x10..0x18].copy_from_slice(&0x2_000u64.to_le_bytes());
+        dump[0x88..0x8c].copy_from_slice(&2u32.to_le_bytes());
+        dump[0x90..0x98].copy_from_slice(&2u64.to_le_bytes());
+        for (idx, base_page) in [2, high / 0x1_000].into_iter().enumerate() {
+            let offset = 0x98 + (idx * 0x10);
+            dump[offset..offset + 8].copy_from_slice(&base_page.to_le_bytes());
+            dump[offset + 8..offset + 0x10].copy_from_slice(&1u64.to_le_bytes());
+        }
+
+        dump[0xf98..0xf9c].copy_from_slice(&1u32.to_le_bytes());
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+
+        let classification = parser.classify_pages(None).unwrap();
+        assert_eq!(gpas(&classification.page_table_pages), [0x2_000]);
+        assert_eq!(gpas(&classification.unmapped_present), [high]);
+        assert_eq!(classification.kernel_mapped.pages(), 0);
+        assert_eq!(classification.user_mapped.pages(), 0);
+    }
+}
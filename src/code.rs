@@ -0,0 +1,178 @@
+//! This contains what is needed to fetch code out of a dump without tripping
+//! on the pages that are missing from it and, with the `iced` feature, to
+//! disassemble it.
+use crate::error::Result;
+use crate::gxa::Gxa;
+use crate::structs::Page;
+use crate::{Gva, KdmpParserError, KernelDumpParser};
+
+/// Code bytes fetched by [`KernelDumpParser::virt_read_code`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CodeBytes {
+    /// The bytes that were asked for; the ones past `valid_len` are zero.
+    pub bytes: Vec<u8>,
+    /// How many bytes at the start of `bytes` were read off the dump.
+    pub valid_len: usize,
+}
+
+impl CodeBytes {
+    /// The bytes that were read off the dump.
+    pub fn valid(&self) -> &[u8] {
+        &self.bytes[..self.valid_len]
+    }
+
+    /// Were all the bytes read off the dump?
+    pub fn is_complete(&self) -> bool {
+        self.valid_len == self.bytes.len()
+    }
+}
+
+impl KernelDumpParser {
+    /// Read up to `max_len` bytes of code at `gva`. The read stops at the first
+    /// page that isn't available in the dump instead of failing, and the
+    /// returned [`CodeBytes`] says how many bytes could be read.
+    pub fn virt_read_code(&self, gva: Gva, max_len: usize) -> Result<CodeBytes> {
+        let mut code = CodeBytes {
+            bytes: vec![0; max_len],
+            valid_len: 0,
+        };
+
+        let mut addr = gva;
+        while code.valid_len < max_len {
+            let left_in_page = (Page::size() - addr.offset()) as usize;
+            let len = left_in_page.min(max_len - code.valid_len);
+            let chunk = &mut code.bytes[code.valid_len..code.valid_len + len];
+            match self.virt_read_exact(addr, chunk) {
+                Err(
+                    KdmpParserError::AddrTranslation(..)
+                    | KdmpParserError::PartialVirtRead
+                    | KdmpParserError::PartialPhysRead,
+                ) => break,
+                res => res?,
+            }
+
+            code.valid_len += len;
+            let Some(next) = addr.u64().checked_add(len as u64) else {
+                break;
+            };
+
+            addr = Gva::new(next);
+        }
+
+        Ok(code)
+    }
+}
+
+#[cfg(feature = "iced")]
+mod iced {
+    use std::collections::HashMap;
+
+    use iced_x86::{
+        Decoder, DecoderError, DecoderOptions, Formatter, Instruction, IntelFormatter,
+        SymbolResolver, SymbolResult,
+    };
+
+    use crate::error::Result;
+    use crate::gxa::Gxa;
+    use crate::{Gva, KernelDumpParser};
+
+    /// The longest x86 instruction.
+    const MAX_INSTRUCTION_LEN: usize = 15;
+
+    /// An instruction disassembled by [`KernelDumpParser::disassemble_at`].
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct DisassembledInstruction {
+        /// Where the instruction is at.
+        pub address: Gva,
+        /// The bytes of the instruction.
+        pub bytes: Vec<u8>,
+        /// The instruction in Intel syntax, like `call nt!KeBugCheckEx`.
+        pub text: String,
+    }
+
+    /// Resolve the addresses the instructions refer to with the symbols that
+    /// were computed ahead of time.
+    struct Symbols(HashMap<u64, String>);
+
+    impl SymbolResolver for Symbols {
+        fn symbol(
+            &mut self,
+            _instruction: &Instruction,
+            _operand: u32,
+            _instruction_operand: Option<u32>,
+            address: u64,
+            _address_size: u32,
+        ) -> Option<SymbolResult<'_>> {
+            self.0
+                .get(&address)
+                .map(|symbol| SymbolResult::with_str(address, symbol))
+        }
+    }
+
+    impl KernelDumpParser {
+        /// Disassemble up to `count` 64-bit instructions at `gva`. Branch
+        /// targets and RIP-relative operands are symbolized with
+        /// [`KernelDumpParser::symbolize`]. The disassembly stops early if it
+        /// runs into a page that isn't available in the dump.
+        pub fn disassemble_at(
+            &self,
+            gva: Gva,
+            count: usize,
+        ) -> Result<Vec<DisassembledInstruction>> {
+            let code = self.virt_read_code(gva, count.saturating_mul(MAX_INSTRUCTION_LEN))?;
+            let mut decoder = Decoder::with_ip(64, code.valid(), gva.u64(), DecoderOptions::NONE);
+            let mut instructions = Vec::with_capacity(count);
+            while instructions.len() < count && decoder.can_decode() {
+                let instruction = decoder.decode();
+                // An instruction that straddles the end of the code that could be read
+                // can't be decoded.
+                if decoder.last_error() == DecoderError::NoMoreBytes {
+                    break;
+                }
+
+                instructions.push(instruction);
+            }
+
+            let mut symbols = HashMap::new();
+            for instruction in &instructions {
+                let targets = [
+                    instruction.near_branch_target(),
+                    if instruction.is_ip_rel_memory_operand() {
+                        instruction.ip_rel_memory_address()
+                    } else {
+                        0
+                    },
+                ];
+
+                for target in targets.into_iter().filter(|&target| target != 0) {
+                    if let Some(symbol) = self.symbolize(Gva::new(target)) {
+                        symbols.insert(target, symbol);
+                    }
+                }
+            }
+
+            let mut formatter =
+                IntelFormatter::with_options(Some(Box::new(Symbols(symbols))), None);
+            let code = code.valid();
+            let disassembled = instructions
+                .iter()
+                .map(|instruction| {
+                    let mut text = String::new();
+                    formatter.format(instruction, &mut text);
+                    let start = (instruction.ip() - gva.u64()) as usize;
+
+                    DisassembledInstruction {
+                        address: Gva::new(instruction.ip()),
+                        bytes: code[start..start + instruction.len()].to_vec(),
+                        text,
+                    }
+                })
+                .collect();
+
+            Ok(disassembled)
+        }
+    }
+}
+
+#[cfg(feature = "iced")]
+pub use iced::DisassembledInstruction;
@@ -0,0 +1,108 @@
+//! This contains what is needed to recognize the containers dumps are passed
+//! around in (see [`ContainerKind`]). [`KernelDumpParser::new`] decompresses a
+//! gzip container when the `flate2` feature is enabled, and a zstd one when
+//! the `zstd` feature is; the other containers are reported with
+//! [`KdmpParserError::CompressedContainer`], for the caller to extract the
+//! dump out of them.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::{KdmpParserError, KernelDumpParser};
+//! match KernelDumpParser::new(&"full.dmp.zst") {
+//!     Ok(parser) => println!("{parser:?}"),
+//!     Err(KdmpParserError::CompressedContainer(kind)) => println!("extract the {kind} first"),
+//!     Err(e) => println!("{e}"),
+//! }
+//! ```
+//!
+//! [`KernelDumpParser::new`]: crate::KernelDumpParser::new
+//! [`KdmpParserError::CompressedContainer`]: crate::KdmpParserError::CompressedContainer
+use std::fmt::{self, Display};
+
+/// The format of a container a dump can be in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContainerKind {
+    /// A gzip stream (`.gz`).
+    Gzip,
+    /// A Zstandard frame (`.zst`).
+    Zstd,
+    /// A Microsoft cabinet (`.cab`).
+    Cab,
+    /// A zip archive (`.zip`).
+    Zip,
+}
+
+impl ContainerKind {
+    /// Number of bytes [`ContainerKind::sniff`] needs to recognize every
+    /// container.
+    pub const MAGIC_SIZE: usize = 4;
+
+    /// Recognize the container that starts with `magic`, if any.
+    pub fn sniff(magic: &[u8]) -> Option<Self> {
+        match magic.get(..Self::MAGIC_SIZE)? {
+            [0x1f, 0x8b, ..] => Some(Self::Gzip),
+            [0x28, 0xb5, 0x2f, 0xfd] => Some(Self::Zstd),
+            b"MSCF" => Some(Self::Cab),
+            b"PK\x03\x04" => Some(Self::Zip),
+            _ => None,
+        }
+    }
+}
+
+impl Display for ContainerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Gzip => write!(f, "gzip stream"),
+            Self::Zstd => write!(f, "zstd frame"),
+            Self::Cab => write!(f, "cabinet"),
+            Self::Zip => write!(f, "zip archive"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{KdmpParserError, KernelDumpParser};
+
+    #[test]
+    fn sniff() {
+        assert_eq!(
+            ContainerKind::sniff(&[0x1f, 0x8b, 8, 0]),
+            Some(ContainerKind::Gzip)
+        );
+        assert_eq!(
+            ContainerKind::sniff(&[0x28, 0xb5, 0x2f, 0xfd, 0]),
+            Some(ContainerKind::Zstd)
+        );
+        assert_eq!(ContainerKind::sniff(b"MSCF"), Some(ContainerKind::Cab));
+        assert_eq!(
+            ContainerKind::sniff(b"PK\x03\x04"),
+            Some(ContainerKind::Zip)
+        );
+        assert_eq!(ContainerKind::sniff(b"PAGEDU64"), None);
+        assert_eq!(ContainerKind::sniff(&[0x1f, 0x8b]), None);
+    }
+
+    #[test]
+    fn container() {
+        let mut zip = vec![0; 0x3_000];
+        zip[..4].copy_from_slice(b"PK\x03\x04");
+        let path = std::env::temp_dir().join(format!("kdmp-parser-zip-{}.zip", std::process::id()));
+        std::fs::write(&path, &zip).unwrap();
+        let parser = KernelDumpParser::new(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            parser,
+            Err(KdmpParserError::CompressedContainer(ContainerKind::Zip))
+        ));
+
+        // A container is recognized whichever way the dump is read.
+        zip[..4].copy_from_slice(b"MSCF");
+        assert!(matches!(
+            KernelDumpParser::from_bytes(zip),
+            Err(KdmpParserError::CompressedContainer(ContainerKind::Cab))
+        ));
+    }
+}
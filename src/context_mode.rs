@@ -0,0 +1,231 @@
+//! This contains what is needed to know whether the context of a dump is the
+//! one of kernel or of user code (see [`ContextMode`]), and to find the
+//! context of the other mode in the trap frame of the thread that crashed
+//! (see [`TrapFrame`]).
+//!
+//! When an exception of user code ends up in a bugcheck, the context of the
+//! dump headers can be the one of the user code while the kernel stack tells
+//! what happened, or the other way around.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::{ContextMode, KernelDumpParser};
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! println!("the context is the one of {} code", parser.context_mode());
+//! if let Some(trap_frame) = parser.alternate_context() {
+//!     println!("the {} code was at {:#x}", trap_frame.mode(), trap_frame.rip);
+//! }
+//! ```
+use std::fmt::{self, Display};
+
+use crate::error::Result;
+use crate::teb::available;
+use crate::{Gva, KernelDumpParser};
+
+/// The first address of the upper half of the canonical addresses, where
+/// the kernel lives.
+const KERNEL_HALF: u64 = 0xffff_8000_0000_0000;
+
+/// The last address of the lower half of the canonical addresses, where the
+/// user code lives.
+const USER_HALF_END: u64 = 0x0000_7fff_ffff_ffff;
+
+/// Which code a context is the one of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContextMode {
+    /// The requested privilege level of `CS` is 0, and `RIP` is in the upper
+    /// half of the address space.
+    Kernel,
+    /// The requested privilege level of `CS` is 3, and `RIP` is in the lower
+    /// half of the address space.
+    User,
+    /// `CS` and `RIP` disagree, or `RIP` isn't canonical; the context can't
+    /// be trusted.
+    Unknown,
+}
+
+impl ContextMode {
+    /// Tell which code a context is the one of out of its `CS` selector and
+    /// its `RIP`.
+    pub fn from_registers(seg_cs: u16, rip: u64) -> Self {
+        match (seg_cs & 3, rip) {
+            (0, KERNEL_HALF..) => Self::Kernel,
+            (3, ..=USER_HALF_END) => Self::User,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+impl Display for ContextMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Kernel => "kernel",
+            Self::User => "user",
+            Self::Unknown => "unknown",
+        })
+    }
+}
+
+/// The registers a `_KTRAP_FRAME` saves when a thread enters the kernel;
+/// the non-volatile registers other than `RBP` aren't always saved, so they
+/// are left out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TrapFrame {
+    /// Where the trap frame is.
+    pub address: Gva,
+    pub rax: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub rbp: u64,
+    pub rip: u64,
+    pub rsp: u64,
+    pub seg_cs: u16,
+    pub seg_ss: u16,
+    pub eflags: u32,
+}
+
+impl TrapFrame {
+    /// Which code the trap frame is the context of.
+    pub fn mode(&self) -> ContextMode {
+        ContextMode::from_registers(self.seg_cs, self.rip)
+    }
+}
+
+impl KernelDumpParser {
+    /// Which code the context of the dump headers is the one of; see
+    /// [`ContextMode::from_registers`].
+    pub fn context_mode(&self) -> ContextMode {
+        let context = self.context_record();
+
+        ContextMode::from_registers(context.seg_cs, context.rip)
+    }
+
+    /// Read the trap frame the `_KTHREAD` at `ethread` (or the thread that
+    /// crashed if `None`) points to, which is the context it entered the
+    /// kernel with. `None` if it doesn't have one, or if it can't be read.
+    pub fn trap_frame(&self, ethread: Option<Gva>) -> Result<Option<TrapFrame>> {
+        let thread = self.thread_or_crashing(ethread)?;
+        let Some(address) = available(self.read_field(thread, "_KTHREAD", "TrapFrame"))?
+            .filter(|&address| address != 0)
+            .map(Gva::new)
+        else {
+            return Ok(None);
+        };
+
+        let field = |name| self.read_field(address, "_KTRAP_FRAME", name);
+        let read = || -> Result<TrapFrame> {
+            Ok(TrapFrame {
+                address,
+                rax: field("Rax")?,
+                rcx: field("Rcx")?,
+                rdx: field("Rdx")?,
+                r8: field("R8")?,
+                r9: field("R9")?,
+                r10: field("R10")?,
+                r11: field("R11")?,
+                rbp: field("Rbp")?,
+                rip: field("Rip")?,
+                rsp: field("Rsp")?,
+                seg_cs: field("SegCs")? as u16,
+                seg_ss: field("SegSs")? as u16,
+                eflags: field("EFlags")? as u32,
+            })
+        };
+
+        available(read())
+    }
+
+    /// The context of the thread that crashed in the mode that isn't the one
+    /// of the dump headers (see [`KernelDumpParser::context_mode`]): its
+    /// trap frame, if it is the context of the other mode. When the mode of
+    /// the dump headers is unknown, it is the trap frame if its own mode is
+    /// known.
+    pub fn alternate_context(&self) -> Option<TrapFrame> {
+        let trap_frame = self.trap_frame(None).ok()??;
+        let mode = trap_frame.mode();
+
+        (mode != ContextMode::Unknown && mode != self.context_mode()).then_some(trap_frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{DumpBuilder, KdDebuggerData, Register};
+    use crate::PxeFlags;
+
+    const KDBG: u64 = 0xffff_f800_0100_0000;
+    const PRCB: u64 = 0xffff_f800_0200_0000;
+    const THREAD: u64 = 0xffff_f800_0300_0000;
+    const NT: u64 = 0xffff_f800_0500_0000;
+
+    /// Build a dump whose context is `cs:rip`, and whose crashing thread
+    /// entered the kernel from user code.
+    fn crashed_at(seg_cs: u16, rip: u64) -> KernelDumpParser {
+        let trap_frame = THREAD + 0x800;
+        let mut dump = DumpBuilder::new()
+            .register(Register::Rsp, 0x1337)
+            .register(Register::Rip, rip)
+            .map_virt(KDBG, 0x10_000, PxeFlags::Present)
+            .map_virt(PRCB, 0x11_000, PxeFlags::Present)
+            .map_virt(THREAD, 0x12_000, PxeFlags::Present)
+            .kd_debugger_data(KDBG, KdDebuggerData {
+                prcbs: vec![PRCB],
+                offset_prcb_proc_state_special_reg: 0x40,
+                offset_prcb_context: 0x100,
+                ..Default::default()
+            })
+            .write_virt(PRCB + 0x8, &THREAD.to_le_bytes())
+            .write_virt(PRCB + 0x100, &(PRCB + 0x800).to_le_bytes())
+            .write_virt(PRCB + 0x800 + 0x98, &0x1337u64.to_le_bytes())
+            .write_virt(THREAD + 0x90, &trap_frame.to_le_bytes())
+            .write_virt(trap_frame + 0x38, &0x42u64.to_le_bytes())
+            .write_virt(trap_frame + 0x168, &0x7ff6_1234_0000u64.to_le_bytes())
+            .write_virt(trap_frame + 0x170, &0x33u16.to_le_bytes())
+            .write_virt(trap_frame + 0x178, &0x246u32.to_le_bytes())
+            .write_virt(trap_frame + 0x180, &0x9f_f000u64.to_le_bytes())
+            .write_virt(trap_frame + 0x188, &0x2bu16.to_le_bytes())
+            .build();
+        dump[0x348 + 0x38..0x348 + 0x3a].copy_from_slice(&seg_cs.to_le_bytes());
+
+        KernelDumpParser::from_bytes(dump).unwrap()
+    }
+
+    #[test]
+    fn context_mode() {
+        assert_eq!(ContextMode::from_registers(0x10, NT), ContextMode::Kernel);
+        assert_eq!(
+            ContextMode::from_registers(0x33, 0x7ff6_1234_0000),
+            ContextMode::User
+        );
+        assert_eq!(ContextMode::from_registers(0x33, NT), ContextMode::Unknown);
+        assert_eq!(
+            ContextMode::from_registers(0x10, 0x0000_8000_0000_0000),
+            ContextMode::Unknown
+        );
+
+        // The dump has the kernel context, and the trap frame has the user one..
+        let parser = crashed_at(0x10, NT);
+        assert_eq!(parser.context_mode(), ContextMode::Kernel);
+        let trap_frame = parser.alternate_context().unwrap();
+        assert_eq!(trap_frame.mode(), ContextMode::User);
+        assert_eq!(trap_frame.address, Gva::new(THREAD + 0x800));
+        assert_eq!(trap_frame.rax, 0x42);
+        assert_eq!(trap_frame.rsp, 0x9f_f000);
+        assert_eq!((trap_frame.seg_ss, trap_frame.eflags), (0x2b, 0x246));
+        assert_eq!(
+            parser.trap_frame(Some(Gva::new(THREAD))).unwrap(),
+            Some(trap_frame)
+        );
+
+        // ..but when the dump already has the user context, there is no other one.
+        let parser = crashed_at(0x33, 0x7ff6_1234_5678);
+        assert_eq!(parser.context_mode(), ContextMode::User);
+        assert_eq!(parser.alternate_context(), None);
+    }
+}
@@ -0,0 +1,174 @@
+//! This contains what is needed to know where the context of a dump comes
+//! from (see [`ContextSource`]).
+//!
+//! Some dump writers leave the context of the headers zeroed; the context
+//! the `_KPRCB` of the processor that crashed points to is used instead when
+//! it can be read, and [`KernelDumpParser::try_context_record`] is an error
+//! when neither can.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::{ContextSource, KernelDumpParser};
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! if let ContextSource::Prcb(processor) = parser.context_source() {
+//!     println!("the context comes from the _KPRCB of processor {processor}");
+//! }
+//! let rip = parser.try_context_record().unwrap().rip;
+//! ```
+use std::fmt::{self, Display};
+
+use crate::error::Result;
+use crate::gxa::Gxa;
+use crate::structs::{Context, FromLeBytes, Header64};
+use crate::{Gva, KdmpParserError, KernelDumpParser};
+
+/// Where the context of a dump comes from; see
+/// [`KernelDumpParser::context_source`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContextSource {
+    /// The dump headers.
+    Header,
+    /// The `_KPRCB` of the processor of this number, as the context of the
+    /// headers isn't valid.
+    Prcb(u32),
+    /// Nowhere: the context of the headers isn't valid, and the one of the
+    /// processor that crashed couldn't be read.
+    None,
+}
+
+impl Display for ContextSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Header => write!(f, "the dump headers"),
+            Self::Prcb(processor) => write!(f, "the _KPRCB of processor {processor}"),
+            Self::None => write!(f, "nowhere"),
+        }
+    }
+}
+
+/// Does `context` look like the context of a processor? A zeroed one, or one
+/// without `RIP` or `CS`, doesn't.
+pub(crate) fn looks_valid(context: &Context) -> bool {
+    context.rip != 0 && context.seg_cs != 0
+}
+
+/// Decode the context of `headers`, and tell whether it can be used.
+pub(crate) fn header_context(headers: &Header64) -> (Box<Context>, ContextSource) {
+    let context = Box::new(Context::from_le_bytes(&headers.context_record_buffer));
+    let source = if looks_valid(&context) {
+        ContextSource::Header
+    } else {
+        ContextSource::None
+    };
+
+    (context, source)
+}
+
+impl KernelDumpParser {
+    /// Where [`KernelDumpParser::context_record`] comes from: the dump
+    /// headers, or the `_KPRCB` of the processor that crashed when the
+    /// context of the headers is zeroed (or doesn't have `RIP` or `CS`).
+    pub fn context_source(&self) -> ContextSource {
+        self.context_source
+    }
+
+    /// Get the context record like [`KernelDumpParser::context_record`], but
+    /// it is a [`KdmpParserError::NoValidContext`] when it is the invalid
+    /// context of the headers (see [`ContextSource::None`]).
+    pub fn try_context_record(&self) -> Result<&Context> {
+        match self.context_source {
+            ContextSource::None => Err(KdmpParserError::NoValidContext),
+            ContextSource::Header | ContextSource::Prcb(_) => Ok(self.context_record()),
+        }
+    }
+
+    /// Read the context the `_KPRCB` at `prcb` points to (its
+    /// `ProcessorState.ContextFrame`); it is `None` if it can't be read.
+    pub(crate) fn prcb_context(&self, prcb: Gva) -> Result<Option<Context>> {
+        let Some(kd_debugger_data_block) = &self.kd_debugger_data_block else {
+            return Ok(None);
+        };
+
+        let context_addr = prcb
+            .u64()
+            .checked_add(kd_debugger_data_block.offset_prcb_context.into())
+            .ok_or(KdmpParserError::Overflow("offset_prcb"))?;
+        let Some(context_addr) = self.try_virt_read_struct::<u64>(context_addr)? else {
+            return Ok(None);
+        };
+
+        self.try_virt_read_struct::<Context>(context_addr)
+    }
+
+    /// Use `context`, the one of the processor `idx` whose `_KPRCB` is at
+    /// `prcb`, if the context of the headers isn't valid.
+    pub(crate) fn fall_back_on_prcb_context(&mut self, idx: u32, prcb: Gva, context: Context) {
+        if self.context_source == ContextSource::None && looks_valid(&context) {
+            *self.context = context;
+            self.context_source = ContextSource::Prcb(self.processor_number(idx, prcb));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{DumpBuilder, KdDebuggerData, Register};
+    use crate::PxeFlags;
+
+    const KDBG: u64 = 0xffff_f800_0100_0000;
+    const PRCB: u64 = 0xffff_f800_0200_0000;
+
+    /// Build a dump with one processor whose `_KPRCB` points to a context
+    /// with `rip`.
+    fn with_prcb_context(rip: u64) -> DumpBuilder {
+        let context = PRCB + 0x800;
+        DumpBuilder::new()
+            .map_virt(KDBG, 0x10_000, PxeFlags::Present)
+            .map_virt(PRCB, 0x11_000, PxeFlags::Present)
+            .kd_debugger_data(KDBG, KdDebuggerData {
+                prcbs: vec![PRCB],
+                offset_prcb_number: 0x24,
+                offset_prcb_context: 0x400,
+                ..Default::default()
+            })
+            .write_virt(PRCB + 0x24, &3u32.to_le_bytes())
+            .write_virt(PRCB + 0x400, &context.to_le_bytes())
+            .write_virt(context + 0x38, &0x10u16.to_le_bytes())
+            .write_virt(context + 0x98, &0x1337u64.to_le_bytes())
+            .write_virt(context + 0xf8, &rip.to_le_bytes())
+    }
+
+    #[test]
+    fn context_source() {
+        // The context of the headers is used when it is valid..
+        let mut dump = with_prcb_context(0xfffff805_10877000)
+            .register(Register::Rip, 0xfffff805_10870000)
+            .register(Register::Rsp, 0x1337)
+            .build();
+        dump[0x348 + 0x38..0x348 + 0x3a].copy_from_slice(&0x10u16.to_le_bytes());
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+        assert_eq!(parser.context_source(), ContextSource::Header);
+        assert_eq!(
+            parser.try_context_record().unwrap().rip,
+            0xfffff805_10870000
+        );
+
+        // ..and the one of the `_KPRCB` when it is zeroed..
+        let parser =
+            KernelDumpParser::from_bytes(with_prcb_context(0xfffff805_10877000).build()).unwrap();
+        assert_eq!(parser.context_source(), ContextSource::Prcb(3));
+        assert_eq!(parser.crashing_prcb(), Some(Gva::new(PRCB)));
+        assert_eq!(parser.context_record().rip, 0xfffff805_10877000);
+        assert_eq!(parser.context_record().rsp, 0x1337);
+
+        // ..unless it isn't valid either.
+        let parser = KernelDumpParser::from_bytes(with_prcb_context(0).build()).unwrap();
+        assert_eq!(parser.context_source(), ContextSource::None);
+        assert!(matches!(
+            parser.try_context_record(),
+            Err(KdmpParserError::NoValidContext)
+        ));
+    }
+}
@@ -0,0 +1,276 @@
+//! This contains what is needed to decompress the dumps that are in a gzip
+//! stream (with `flate2`, when the `flate2` feature is enabled) or in a zstd
+//! frame (with `zstd`, when the `zstd` feature is enabled). The dump is
+//! decompressed in memory, or into a temporary file when
+//! [`crate::ParserOptions::spill_decompressed`] is set; either way, at most
+//! [`crate::ParserOptions::max_decompressed_size`] bytes are decompressed.
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::error::Result;
+use crate::{ContainerKind, KdmpParserError};
+
+/// Size of the chunks the decompressed dump is written with.
+const CHUNK_SIZE: usize = 0x10_000;
+
+/// Get a reader that decompresses the `kind` container `reader` reads, if
+/// one of the enabled backends supports it.
+pub(crate) fn decompressor(
+    kind: ContainerKind,
+    reader: impl Read + 'static,
+) -> Result<Option<Box<dyn Read>>> {
+    let reader = io::BufReader::new(reader);
+    Ok(match kind {
+        #[cfg(feature = "flate2")]
+        ContainerKind::Gzip => Some(Box::new(flate2::bufread::MultiGzDecoder::new(reader))),
+        #[cfg(feature = "zstd")]
+        ContainerKind::Zstd => Some(Box::new(zstd::stream::read::Decoder::with_buffer(reader)?)),
+        _ => {
+            let _ = reader;
+
+            None
+        }
+    })
+}
+
+/// Copy what `reader` decompresses into `writer`, failing with
+/// [`KdmpParserError::ReadLimitExceeded`] when there is more than `limit`
+/// bytes of it.
+fn copy_bounded(mut reader: impl Read, mut writer: impl Write, limit: u64) -> Result<()> {
+    let mut buffer = vec![0; CHUNK_SIZE];
+    let mut written = 0u64;
+    loop {
+        let len = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(KdmpParserError::InvalidData(
+                    "the compressed dump is truncated",
+                ))
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        written += len as u64;
+        if written > limit {
+            return Err(KdmpParserError::ReadLimitExceeded {
+                requested: written,
+                limit,
+            });
+        }
+
+        writer.write_all(&buffer[..len])?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Decompress what `reader` reads in memory.
+pub(crate) fn to_memory(reader: impl Read, limit: u64) -> Result<Vec<u8>> {
+    let mut dump = Vec::new();
+    copy_bounded(reader, &mut dump, limit)?;
+
+    Ok(dump)
+}
+
+/// A temporary file a dump is decompressed into; it is removed when dropped.
+#[derive(Debug)]
+pub(crate) struct SpillFile {
+    file: Option<File>,
+    path: PathBuf,
+}
+
+impl SpillFile {
+    /// Create a new temporary file.
+    fn create() -> Result<Self> {
+        static COUNT: AtomicU64 = AtomicU64::new(0);
+        loop {
+            let path = std::env::temp_dir().join(format!(
+                "kdmp-parser-{}-{}.dmp",
+                std::process::id(),
+                COUNT.fetch_add(1, Ordering::Relaxed)
+            ));
+
+            match OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(file) => {
+                    return Ok(Self {
+                        file: Some(file),
+                        path,
+                    })
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn file(&mut self) -> &mut File {
+        self.file
+            .as_mut()
+            .expect("the file is only taken when dropped")
+    }
+}
+
+impl Read for SpillFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file().read(buf)
+    }
+}
+
+impl Seek for SpillFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.file().seek(pos)
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        // The file has to be closed before it can be removed on Windows.
+        drop(self.file.take());
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Decompress what `reader` reads into a temporary file.
+pub(crate) fn to_spill_file(reader: impl Read, limit: u64) -> Result<SpillFile> {
+    let mut spill = SpillFile::create()?;
+    copy_bounded(reader, io::BufWriter::new(spill.file()), limit)?;
+    spill.rewind()?;
+
+    Ok(spill)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DumpBuilder;
+    use crate::{Gpa, KernelDumpParser, ParserOptions};
+
+    /// Compress `data` in a `kind` container.
+    fn compress(kind: ContainerKind, data: &[u8]) -> Vec<u8> {
+        match kind {
+            #[cfg(feature = "flate2")]
+            ContainerKind::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+                encoder.write_all(data).unwrap();
+
+                encoder.finish().unwrap()
+            }
+            #[cfg(feature = "zstd")]
+            ContainerKind::Zstd => zstd::encode_all(data, 1).unwrap(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// The containers the enabled backends decompress.
+    fn containers() -> Vec<ContainerKind> {
+        [ContainerKind::Gzip, ContainerKind::Zstd]
+            .into_iter()
+            .filter(|kind| decompressor(*kind, io::empty()).unwrap().is_some())
+            .collect()
+    }
+
+    #[test]
+    fn decompress() {
+        let text = (0..0x1_000)
+            .map(|idx| format!("PAGEDU64 {} ", idx * idx % 97))
+            .collect::<String>();
+        for kind in containers() {
+            let stream = compress(kind, text.as_bytes());
+            let decompress = |stream: &[u8], limit| {
+                to_memory(
+                    decompressor(kind, io::Cursor::new(stream.to_vec()))
+                        .unwrap()
+                        .unwrap(),
+                    limit,
+                )
+            };
+
+            assert_eq!(decompress(&stream, u64::MAX).unwrap(), text.as_bytes());
+
+            // The decompressed data is bounded..
+            assert!(matches!(
+                decompress(&stream, 0x100),
+                Err(KdmpParserError::ReadLimitExceeded { limit: 0x100, .. })
+            ));
+
+            // ..and the streams that are corrupted or truncated aren't decompressed.
+            let mut corrupted = stream.clone();
+            let len = corrupted.len();
+            corrupted[len - 4] ^= 1;
+            assert!(decompress(&corrupted, u64::MAX).is_err(), "{kind}");
+            assert!(decompress(&stream[..stream.len() / 2], u64::MAX).is_err());
+        }
+    }
+
+    #[test]
+    fn spill_file() {
+        let spill = to_spill_file(&b"spilled"[..], 0x100).unwrap();
+        let path = spill.path.clone();
+        let mut content = String::new();
+        let mut spill = spill;
+        spill.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "spilled");
+        drop(spill);
+        assert!(!path.exists());
+
+        assert!(matches!(
+            to_spill_file(&b"spilled"[..], 6),
+            Err(KdmpParserError::ReadLimitExceeded { limit: 6, .. })
+        ));
+    }
+
+    #[test]
+    fn containers_are_decompressed() {
+        let dump = DumpBuilder::new()
+            .write_phys(0x1_0000, b"compressed")
+            .build();
+        for kind in containers() {
+            let path = std::env::temp_dir().join(format!(
+                "kdmp-parser-container-{}-{kind:?}.dmp",
+                std::process::id()
+            ));
+            fs::write(&path, compress(kind, &dump)).unwrap();
+            let parse = |spill_decompressed, max_decompressed_size| {
+                let options = ParserOptions {
+                    spill_decompressed,
+                    max_decompressed_size,
+                    ..Default::default()
+                };
+
+                KernelDumpParser::new_with_options(&path, options)
+            };
+
+            let parsers = [parse(false, u64::MAX), parse(true, u64::MAX)];
+            let limited = [parse(false, 0x1_000), parse(true, 0x1_000)];
+            fs::remove_file(&path).unwrap();
+
+            for parser in parsers {
+                let mut buffer = [0; 10];
+                parser
+                    .unwrap()
+                    .phys_read_exact(Gpa::new(0x1_0000), &mut buffer)
+                    .unwrap();
+                assert_eq!(&buffer, b"compressed");
+            }
+
+            for parser in limited {
+                assert!(matches!(
+                    parser,
+                    Err(KdmpParserError::ReadLimitExceeded { limit: 0x1_000, .. })
+                ));
+            }
+        }
+    }
+}
@@ -0,0 +1,289 @@
+//! This contains what is needed to inspect the DPCs queued on the processors
+//! (see [`Dpc`]) and the pending timers (see [`KTimer`]), which is what
+//! watchdog bugchecks like `DPC_WATCHDOG_VIOLATION` are about.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::KernelDumpParser;
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! for dpc in parser.dpcs().unwrap() {
+//!     println!(
+//!         "{}: {} ({:?})",
+//!         dpc.processor,
+//!         dpc.routine,
+//!         dpc.module.as_deref().unwrap_or("?")
+//!     );
+//! }
+//! ```
+use std::collections::HashSet;
+
+use crate::error::Result;
+use crate::gxa::Gxa;
+use crate::trace::trace_debug;
+use crate::{Gva, KdmpParserError, KernelDumpParser};
+
+/// `_KOBJECTS.DpcObject`.
+const DPC_OBJECT: u64 = 0x13;
+
+/// `_KOBJECTS.ThreadedDpcObject`.
+const THREADED_DPC_OBJECT: u64 = 0x18;
+
+/// The DPC queues of a processor: the normal one and the threaded one.
+const DPC_QUEUES: [&str; 2] = ["DpcData[0].DpcList.ListHead", "DpcData[1].DpcList.ListHead"];
+
+/// Number of `_KTIMER_TABLE_ENTRY` in the timer table of a processor
+/// (`[2][256]`).
+const TIMER_TABLE_ENTRIES: u64 = 2 * 256;
+
+/// Maximum number of DPCs we'll read off a queue.
+const MAX_DPCS: usize = 0x1_000;
+
+/// Maximum number of timers we'll read off a timer table entry.
+const MAX_TIMERS: usize = 0x1_000;
+
+/// A DPC queued on a processor (`nt!_KDPC`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dpc {
+    /// The `DeferredRoutine` of the DPC.
+    pub routine: Gva,
+    /// The `DeferredContext` of the DPC.
+    pub context: Gva,
+    /// Index of the processor the DPC is queued on.
+    pub processor: u32,
+    /// The `Importance` of the DPC.
+    pub importance: u8,
+    /// Name of the module the routine belongs to.
+    pub module: Option<String>,
+}
+
+/// A pending timer (`nt!_KTIMER`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KTimer {
+    /// When the timer expires, in 100ns intervals.
+    pub due_time: u64,
+    /// The `DeferredRoutine` of the DPC queued when the timer expires.
+    pub dpc_routine: Option<Gva>,
+    /// The period of the timer in milliseconds; zero if it isn't periodic.
+    pub period: u32,
+}
+
+impl KernelDumpParser {
+    /// Get the DPCs queued on every processor, in both the normal and the
+    /// threaded queues. A queue that can't be walked (unreadable memory, a
+    /// cycle, etc.) doesn't prevent walking the others.
+    pub fn dpcs(&self) -> Result<Vec<Dpc>> {
+        self.ki_processor_block()?;
+        let mut dpcs = Vec::new();
+        for (prcb, idx) in self.prcbs().into_iter().zip(0..) {
+            let Some(prcb) = prcb else {
+                continue;
+            };
+
+            let processor = self.processor_number(idx, prcb);
+            for queue in DPC_QUEUES {
+                if let Err(e) = self.walk_dpc_queue(prcb, queue, processor, &mut dpcs) {
+                    trace_debug!("failed walking {queue} of the processor {processor}: {e}");
+                }
+            }
+        }
+
+        Ok(dpcs)
+    }
+
+    /// Get the pending timers out of the timer table of every processor. An
+    /// entry of a table that can't be walked doesn't prevent walking the
+    /// others.
+    ///
+    /// Since Windows 8.1, the `Dpc` pointer of the timers is obfuscated with
+    /// `nt!KiWaitNever` and `nt!KiWaitAlways`, which aren't exported; the
+    /// `dpc_routine` of a timer is only available when its pointer points to a
+    /// DPC.
+    pub fn timers(&self) -> Result<Vec<KTimer>> {
+        self.ki_processor_block()?;
+        let entries = self
+            .profile()
+            .offset("_KPRCB", "TimerTable.TimerEntries[0][0].Entry")?;
+        let entry_size = self.profile().size("_KTIMER_TABLE_ENTRY")?;
+        let link = self.profile().offset("_KTIMER", "TimerListEntry")?;
+        let mut timers = Vec::new();
+        for prcb in self.prcbs().into_iter().flatten() {
+            for idx in 0..TIMER_TABLE_ENTRIES {
+                let head = (idx * entry_size)
+                    .checked_add(entries)
+                    .and_then(|offset| prcb.u64().checked_add(offset))
+                    .map(Gva::new)
+                    .ok_or(KdmpParserError::Overflow("timer table entry"))?;
+
+                for timer in self.walk_list(head, link, MAX_TIMERS) {
+                    match timer.and_then(|timer| self.read_timer(timer)) {
+                        Ok(timer) => timers.push(timer),
+                        Err(e) => {
+                            trace_debug!("failed walking the timers at {head}: {e}");
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(timers)
+    }
+
+    /// Walk the singly-linked DPC queue `queue` of the `_KPRCB` at `prcb`.
+    fn walk_dpc_queue(
+        &self,
+        prcb: Gva,
+        queue: &str,
+        processor: u32,
+        dpcs: &mut Vec<Dpc>,
+    ) -> Result<()> {
+        let link = self.profile().offset("_KDPC", "DpcListEntry")?;
+        let mut visited = HashSet::new();
+        let mut entry = Gva::new(self.read_field(prcb, "_KPRCB", queue)?);
+        while entry.u64() != 0 {
+            if !visited.insert(entry) {
+                return Err(KdmpParserError::ListCycle(entry));
+            }
+
+            if visited.len() > MAX_DPCS {
+                return Err(KdmpParserError::ListTooLong(MAX_DPCS));
+            }
+
+            let dpc = entry
+                .u64()
+                .checked_sub(link)
+                .map(Gva::new)
+                .ok_or(KdmpParserError::Overflow("dpc list entry"))?;
+            let routine = Gva::new(self.read_field(dpc, "_KDPC", "DeferredRoutine")?);
+            dpcs.push(Dpc {
+                routine,
+                context: Gva::new(self.read_field(dpc, "_KDPC", "DeferredContext")?),
+                processor,
+                importance: self.read_field(dpc, "_KDPC", "Importance")? as u8,
+                module: self.find_module(routine).map(|(_, name)| name.to_string()),
+            });
+
+            // `DpcListEntry` is a `_SINGLE_LIST_ENTRY`, so its first field is `Next`.
+            entry = self.virt_read_ptr(entry)?;
+        }
+
+        Ok(())
+    }
+
+    /// Read the `_KTIMER` at `timer`.
+    fn read_timer(&self, timer: Gva) -> Result<KTimer> {
+        let dpc = Gva::new(self.read_field(timer, "_KTIMER", "Dpc")?);
+        let is_dpc = dpc.u64() != 0
+            && matches!(
+                self.read_field(dpc, "_KDPC", "Type"),
+                Ok(DPC_OBJECT | THREADED_DPC_OBJECT)
+            );
+        let dpc_routine = is_dpc
+            .then(|| self.read_field(dpc, "_KDPC", "DeferredRoutine").ok())
+            .flatten()
+            .map(Gva::new);
+
+        Ok(KTimer {
+            due_time: self.read_field(timer, "_KTIMER", "DueTime")?,
+            dpc_routine,
+            period: self.read_field(timer, "_KTIMER", "Period")? as u32,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{DumpBuilder, KdDebuggerData};
+    use crate::PxeFlags;
+
+    const KDBG: u64 = 0xffff_f800_0100_0000;
+    const PRCB: u64 = 0xffff_f800_0200_0000;
+    const OBJECTS: u64 = 0xffff_f800_0300_0000;
+    const NT: u64 = 0xffff_f800_0400_0000;
+    const DRIVER: u64 = 0xffff_f800_0500_0000;
+
+    #[test]
+    fn dpcs_and_timers() {
+        // The first processor has two DPCs in its normal queue and one in its
+        // threaded queue, which links to itself; the second one can't be read.
+        let (a, b, c) = (OBJECTS, OBJECTS + 0x100, OBJECTS + 0x200);
+        let (timer, obfuscated) = (OBJECTS + 0x400, OBJECTS + 0x500);
+        let head = PRCB + 0x3b48 + (3 * 0x20);
+        let mut builder = DumpBuilder::new()
+            .processors(2)
+            .module(NT..NT + 0x1_000, "ntoskrnl.exe")
+            .module(DRIVER..DRIVER + 0x1_000, "\\SystemRoot\\evil.sys")
+            .map_virt(KDBG, 0x10_000, PxeFlags::Present)
+            .map_virt(PRCB + 0x3_000, 0x11_000, PxeFlags::Present)
+            .map_virt(OBJECTS, 0x12_000, PxeFlags::Present)
+            .kd_debugger_data(KDBG, KdDebuggerData {
+                prcbs: vec![PRCB, PRCB + 0x10_000],
+                ..Default::default()
+            })
+            .write_virt(PRCB + 0x3200, &(a + 8).to_le_bytes())
+            .write_virt(PRCB + 0x3228, &(c + 8).to_le_bytes());
+        for (dpc, importance, routine, context, next) in [
+            (a, 1u8, NT + 0x100, 0x1234u64, b + 8),
+            (b, 2, DRIVER + 0x10, 0, 0),
+            (c, 1, NT + 0x200, 0, c + 8),
+        ] {
+            builder = builder
+                .write_virt(dpc, &[DPC_OBJECT as u8, importance])
+                .write_virt(dpc + 0x8, &next.to_le_bytes())
+                .write_virt(dpc + 0x18, &routine.to_le_bytes())
+                .write_virt(dpc + 0x20, &context.to_le_bytes());
+        }
+
+        // Two timers are linked in the fourth entry of the timer table; the `Dpc` of
+        // the second one is obfuscated.
+        for (at, due_time, dpc, period, flink, blink) in [
+            (timer, 0x1_000u64, b, 0x10u32, obfuscated + 0x20, head),
+            (obfuscated, 0x2_000, 0xdead, 0, head, timer + 0x20),
+        ] {
+            builder = builder
+                .write_virt(at + 0x18, &due_time.to_le_bytes())
+                .write_virt(at + 0x20, &flink.to_le_bytes())
+                .write_virt(at + 0x28, &blink.to_le_bytes())
+                .write_virt(at + 0x30, &dpc.to_le_bytes())
+                .write_virt(at + 0x3c, &period.to_le_bytes());
+        }
+
+        let dump = builder
+            .write_virt(head, &(timer + 0x20).to_le_bytes())
+            .write_virt(head + 8, &(obfuscated + 0x20).to_le_bytes())
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+
+        let dpc = |routine, context, importance, module: &str| Dpc {
+            routine: Gva::new(routine),
+            context: Gva::new(context),
+            processor: 0,
+            importance,
+            module: Some(module.into()),
+        };
+        assert_eq!(parser.dpcs().unwrap(), [
+            dpc(NT + 0x100, 0x1234, 1, "ntoskrnl.exe"),
+            dpc(DRIVER + 0x10, 0, 2, "\\SystemRoot\\evil.sys"),
+            dpc(NT + 0x200, 0, 1, "ntoskrnl.exe"),
+        ]);
+        assert_eq!(parser.timers().unwrap(), [
+            KTimer {
+                due_time: 0x1_000,
+                dpc_routine: Some(Gva::new(DRIVER + 0x10)),
+                period: 0x10
+            },
+            KTimer {
+                due_time: 0x2_000,
+                dpc_routine: None,
+                period: 0
+            }
+        ]);
+
+        // Without `nt!KiProcessorBlock`, the processors can't be found.
+        let parser = KernelDumpParser::from_bytes(DumpBuilder::new().build()).unwrap();
+        assert!(matches!(parser.dpcs(), Err(KdmpParserError::NotFound(_))));
+        assert!(matches!(parser.timers(), Err(KdmpParserError::NotFound(_))));
+    }
+}
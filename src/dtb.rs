@@ -0,0 +1,182 @@
+//! This contains what is needed to rescue a dump whose headers have the wrong
+//! directory table base, like the ones written by a capture tool that grabbed
+//! `cr3` in the wrong context: [`KernelDumpParser::find_dtb_candidates`] looks
+//! for the PML4s of the dump, and [`KernelDumpParser::set_default_dtb`] makes
+//! the translations use one of them.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::KernelDumpParser;
+//! let mut parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! if parser.kernel_modules().next().is_none() {
+//!     if let Some(candidate) = parser.find_dtb_candidates().unwrap().first() {
+//!         parser.set_default_dtb(candidate.gpa).unwrap();
+//!     }
+//! }
+//! ```
+use std::cmp::Ordering;
+
+use crate::cancel::CancellationToken;
+use crate::error::Result;
+use crate::memory_map::KERNEL_PML4E;
+use crate::pxe::PxeFlags;
+use crate::{Gpa, Gva, KernelDumpParser};
+
+/// A page of the dump that looks like a PML4, see
+/// [`KernelDumpParser::find_dtb_candidates`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DtbCandidate {
+    /// Where the PML4 is.
+    pub gpa: Gpa,
+    /// How plausible it is that the PML4 is the one of the kernel, between 0
+    /// and 1.
+    pub score: f32,
+}
+
+impl KernelDumpParser {
+    /// Find the pages of the dump that look like the PML4 of the kernel: the
+    /// ones with an entry in the kernel half that points back at them, like
+    /// the self-referencing entry Windows maps the page tables with.
+    ///
+    /// Half of the score of a candidate is the share of its other present
+    /// entries that point to pages of the dump, and the other half is the
+    /// share of the addresses of the headers (`PsLoadedModuleList` and
+    /// `KdDebuggerDataBlock`) it translates. The candidates are sorted by
+    /// decreasing score. Every page of the dump is read, so this is as slow
+    /// as reading the whole dump.
+    pub fn find_dtb_candidates(&self) -> Result<Vec<DtbCandidate>> {
+        self.find_dtb_candidates_with_cancellation(&CancellationToken::new())
+    }
+
+    /// Find the pages of the dump that look like the PML4 of the kernel, like
+    /// [`KernelDumpParser::find_dtb_candidates`], until `cancel` is cancelled.
+    pub fn find_dtb_candidates_with_cancellation(
+        &self,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<DtbCandidate>> {
+        let addresses = [
+            self.headers().ps_loaded_module_list,
+            self.headers().kd_debugger_data_block,
+        ]
+        .into_iter()
+        .filter(|&address| address != 0)
+        .map(Gva::new)
+        .collect::<Vec<_>>();
+
+        let mut candidates = Vec::new();
+        for (gpa, _) in self.physmem() {
+            cancel.check()?;
+            let Some(entries) = self.read_table(gpa)? else {
+                continue;
+            };
+
+            let self_referencing = |idx: usize| {
+                let pxe = &entries[idx];
+
+                pxe.present()
+                    && pxe.flags.contains(PxeFlags::Writable)
+                    && !pxe.flags.contains(PxeFlags::UserAccessible)
+                    && pxe.pfn.gpa() == gpa
+            };
+
+            let Some(self_idx) = (KERNEL_PML4E..entries.len()).find(|&idx| self_referencing(idx))
+            else {
+                continue;
+            };
+
+            // A PML4 that only maps itself can't be the one of the kernel.
+            let present = entries
+                .iter()
+                .enumerate()
+                .filter(|&(idx, pxe)| idx != self_idx && pxe.present())
+                .map(|(_, pxe)| pxe)
+                .collect::<Vec<_>>();
+            if present.is_empty() {
+                continue;
+            }
+
+            let in_dump = present
+                .iter()
+                .filter(|pxe| self.phys_offset(pxe.pfn.gpa()).is_ok())
+                .count();
+            let translated = addresses
+                .iter()
+                .filter(|&&address| self.walk_page_tables(address, gpa).is_ok())
+                .count();
+
+            let mut score = 0.5 * (in_dump as f32 / present.len() as f32);
+            if !addresses.is_empty() {
+                score += 0.5 * (translated as f32 / addresses.len() as f32);
+            }
+
+            candidates.push(DtbCandidate { gpa, score });
+        }
+
+        candidates.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(Ordering::Equal)
+                .then(a.gpa.cmp(&b.gpa))
+        });
+
+        Ok(candidates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::testing::DumpBuilder;
+    use crate::{Gpa, Gxa, KernelDumpParser, PxeFlags};
+
+    #[test]
+    fn dtb_candidates() {
+        // A page that looks like a PML4, but whose only other entry points to a page
+        // that isn't in the dump.
+        let decoy = 0x50_000u64;
+        let mut decoy_page = vec![0; 0x1_000];
+        decoy_page[0x0..0x8].copy_from_slice(&(0x7f_0000_0000u64 | 0b11).to_le_bytes());
+        decoy_page[0x800..0x808].copy_from_slice(&(decoy | 0b11).to_le_bytes());
+
+        let nt = 0xffff_f800_0000_0000;
+        let mut dump = DumpBuilder::new()
+            .map_virt(nt, 0x10_000, PxeFlags::Present)
+            .write_phys(decoy, &decoy_page)
+            .map_virt(nt + 0x1_000, decoy, PxeFlags::Present)
+            .module(nt..nt + 0x1_000, "ntoskrnl.exe")
+            .build();
+
+        // Give the PML4 its self-referencing entry..
+        let parser = KernelDumpParser::from_bytes(dump.clone()).unwrap();
+        let dtb = parser.default_dtb();
+        let self_entry = parser
+            .phys_translate(Gpa::new(dtb.u64() + 0x1ed * 8))
+            .unwrap() as usize;
+        dump[self_entry..self_entry + 8].copy_from_slice(&(dtb.u64() | 0b11).to_le_bytes());
+
+        // ..and make the headers point to the decoy.
+        dump[0x10..0x18].copy_from_slice(&decoy.to_le_bytes());
+        let mut parser = KernelDumpParser::with_reader(Cursor::new(dump)).unwrap();
+        assert_eq!(parser.default_dtb(), Gpa::new(decoy));
+        assert_eq!(parser.kernel_modules().count(), 0);
+
+        let candidates = parser.find_dtb_candidates().unwrap();
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].gpa, dtb.page_align());
+        assert_eq!(candidates[0].score, 1.0);
+        assert_eq!(candidates[1].gpa, Gpa::new(decoy));
+        assert_eq!(candidates[1].score, 0.0);
+
+        parser.set_default_dtb(candidates[0].gpa).unwrap();
+        assert_eq!(parser.default_dtb(), dtb.page_align());
+        assert_eq!(
+            parser
+                .kernel_modules()
+                .map(|(_, name)| name)
+                .collect::<Vec<_>>(),
+            ["ntoskrnl.exe"]
+        );
+    }
+}
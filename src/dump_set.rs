@@ -0,0 +1,486 @@
+//! This contains [`DumpSet`], which holds several dumps to query them
+//! uniformly: read the same memory in all of them, find the dumps where it
+//! differs, and find the modules and processes that aren't the same in all of
+//! them.
+//!
+//! The dumps of a set don't have to come from the same boot: an address can
+//! be an offset in a module ([`SetAddress::Module`]), which is resolved
+//! against the base the module has in each dump.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::{DumpSet, SetAddress};
+//! let set = DumpSet::open(["hang1.dmp", "hang2.dmp", "hang3.dmp"]).unwrap();
+//! let address = "nt+0x123".parse::<SetAddress>().unwrap();
+//! let comparison = set.compare_virt(address, 0x10);
+//! for (bytes, dumps) in &comparison.contents {
+//!     println!("{dumps:?}: {bytes:02x?}");
+//! }
+//! for diff in set.process_diffs() {
+//!     println!("{} ({}) is only in {:?}", diff.name, diff.pid, diff.present_in);
+//! }
+//! ```
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::error::Result;
+use crate::export::module_short_name;
+use crate::gxa::Gxa;
+use crate::{Gva, KdmpParserError, KernelDumpParser, ModuleEntry};
+
+/// Maximum number of processes we'll walk the process list for.
+const MAX_PROCESSES: usize = 0x10_000;
+
+/// The index of a dump in a [`DumpSet`].
+pub type DumpId = usize;
+
+/// An address in the dumps of a [`DumpSet`].
+///
+/// It parses from `nt+0x123`, `nt`, or `0xfffff805_10877000`; the numbers are
+/// hexadecimal, with or without their `0x` prefix.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SetAddress {
+    /// The same virtual address in every dump.
+    Absolute(Gva),
+    /// An offset in the module that goes by `name` in symbols (like `nt`).
+    Module { name: String, offset: u64 },
+}
+
+impl From<Gva> for SetAddress {
+    fn from(gva: Gva) -> Self {
+        Self::Absolute(gva)
+    }
+}
+
+impl From<u64> for SetAddress {
+    fn from(gva: u64) -> Self {
+        Self::Absolute(Gva::new(gva))
+    }
+}
+
+impl FromStr for SetAddress {
+    type Err = KdmpParserError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let hex = |s: &str| {
+            let digits = s.trim().trim_start_matches("0x").replace('_', "");
+            u64::from_str_radix(&digits, 16).ok()
+        };
+
+        let (module, offset) = match s.split_once('+') {
+            Some((module, offset)) => (
+                module.trim(),
+                hex(offset).ok_or(KdmpParserError::InvalidData("invalid offset"))?,
+            ),
+            None => match hex(s) {
+                Some(gva) => return Ok(Self::Absolute(Gva::new(gva))),
+                None => (s.trim(), 0),
+            },
+        };
+
+        if module.is_empty() {
+            return Err(KdmpParserError::InvalidData("invalid module name"));
+        }
+
+        Ok(Self::Module {
+            name: module.to_string(),
+            offset,
+        })
+    }
+}
+
+/// The result of [`DumpSet::compare_virt`].
+#[derive(Debug)]
+pub struct VirtComparison {
+    /// The distinct contents of the memory, each with the dumps that have it;
+    /// they are in the order of the first dump that has them.
+    pub contents: Vec<(Vec<u8>, Vec<DumpId>)>,
+    /// The dumps where the memory can't be read, and why.
+    pub unreadable: Vec<(DumpId, KdmpParserError)>,
+}
+
+impl VirtComparison {
+    /// Is the memory readable, and the same, in every dump?
+    pub fn identical(&self) -> bool {
+        self.contents.len() <= 1 && self.unreadable.is_empty()
+    }
+
+    /// The offsets of the bytes that aren't the same in all the contents.
+    pub fn differing_offsets(&self) -> Vec<usize> {
+        let Some(((first, _), others)) = self.contents.split_first() else {
+            return Vec::new();
+        };
+
+        (0..first.len())
+            .filter(|&idx| others.iter().any(|(bytes, _)| bytes[idx] != first[idx]))
+            .collect()
+    }
+}
+
+/// A module that isn't the same in all the dumps of a [`DumpSet`]: a dump
+/// doesn't have it, or it isn't at the same place, or it isn't the same
+/// binary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleDiff {
+    /// The name the module goes by in symbols, in lowercase.
+    pub name: String,
+    /// The entry of the module in each dump, `None` if the dump doesn't have
+    /// it.
+    pub entries: Vec<(DumpId, Option<ModuleEntry>)>,
+}
+
+/// A process that isn't in all the dumps of a [`DumpSet`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessDiff {
+    /// `UniqueProcessId` of the process.
+    pub pid: u64,
+    /// `ImageFileName` of the process.
+    pub name: String,
+    /// The dumps the process is in.
+    pub present_in: Vec<DumpId>,
+}
+
+/// Several dumps queried together; a dump is known by its [`DumpId`], the
+/// order it has been added in.
+#[derive(Default)]
+pub struct DumpSet {
+    dumps: Vec<KernelDumpParser>,
+    /// The bases of the modules registered with
+    /// [`DumpSet::set_module_base`], by dump and by name.
+    bases: HashMap<(DumpId, String), Gva>,
+}
+
+impl DumpSet {
+    /// Create an empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse the dumps at `paths` into a set, in that order.
+    pub fn open<P: AsRef<Path>>(paths: impl IntoIterator<Item = P>) -> Result<Self> {
+        let mut set = Self::new();
+        for path in paths {
+            set.add(KernelDumpParser::new(&path)?);
+        }
+
+        Ok(set)
+    }
+
+    /// Add a dump to the set.
+    pub fn add(&mut self, parser: KernelDumpParser) -> DumpId {
+        self.dumps.push(parser);
+
+        self.dumps.len() - 1
+    }
+
+    /// Number of dumps in the set.
+    pub fn len(&self) -> usize {
+        self.dumps.len()
+    }
+
+    /// Is the set empty?
+    pub fn is_empty(&self) -> bool {
+        self.dumps.is_empty()
+    }
+
+    /// Get the dump `id`.
+    pub fn get(&self, id: DumpId) -> Option<&KernelDumpParser> {
+        self.dumps.get(id)
+    }
+
+    /// The dumps of the set.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = (DumpId, &KernelDumpParser)> + '_ {
+        self.dumps.iter().enumerate()
+    }
+
+    /// Register that `module` is at `base` in the dump `id`, for when its
+    /// module list doesn't say; it wins over the module list.
+    pub fn set_module_base(&mut self, id: DumpId, module: &str, base: Gva) {
+        let name = module_short_name(module).to_ascii_lowercase();
+        self.bases.insert((id, name), base);
+    }
+
+    /// Resolve `address` in the dump `id`.
+    pub fn resolve(&self, id: DumpId, address: &SetAddress) -> Result<Gva> {
+        let parser = self.get(id).ok_or(KdmpParserError::NotFound("the dump"))?;
+        let (name, offset) = match address {
+            SetAddress::Absolute(gva) => return Ok(*gva),
+            SetAddress::Module { name, offset } => (name, *offset),
+        };
+
+        let name = module_short_name(name);
+        let base = match self.bases.get(&(id, name.to_ascii_lowercase())) {
+            Some(&base) => base,
+            None => parser
+                .modules()
+                .find(|module| module_short_name(&module.name).eq_ignore_ascii_case(name))
+                .map(|module| module.at.start)
+                .ok_or(KdmpParserError::NotFound("the module"))?,
+        };
+
+        base.u64()
+            .checked_add(offset)
+            .map(Gva::new)
+            .ok_or(KdmpParserError::Overflow("module offset"))
+    }
+
+    /// Read `len` bytes of virtual memory at `address` in every dump.
+    pub fn read_all_virt(
+        &self,
+        address: impl Into<SetAddress>,
+        len: usize,
+    ) -> Vec<(DumpId, Result<Vec<u8>>)> {
+        let address = address.into();
+
+        self.iter()
+            .map(|(id, parser)| {
+                let read = self
+                    .resolve(id, &address)
+                    .and_then(|gva| parser.virt_read_to_vec(gva, len as u64));
+
+                (id, read)
+            })
+            .collect()
+    }
+
+    /// Read `len` bytes of virtual memory at `address` in every dump, and
+    /// group the dumps by what they have there.
+    pub fn compare_virt(&self, address: impl Into<SetAddress>, len: usize) -> VirtComparison {
+        let mut comparison = VirtComparison {
+            contents: Vec::new(),
+            unreadable: Vec::new(),
+        };
+
+        for (id, read) in self.read_all_virt(address, len) {
+            let bytes = match read {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    comparison.unreadable.push((id, e));
+                    continue;
+                }
+            };
+
+            match comparison
+                .contents
+                .iter_mut()
+                .find(|(content, _)| *content == bytes)
+            {
+                Some((_, dumps)) => dumps.push(id),
+                None => comparison.contents.push((bytes, vec![id])),
+            }
+        }
+
+        comparison
+    }
+
+    /// The modules that aren't the same in all the dumps, sorted by name. The
+    /// modules are told apart by the name they go by in symbols; they are the
+    /// same if they are at the same place and have the same `SizeOfImage` and
+    /// `TimeDateStamp`.
+    pub fn module_diffs(&self) -> Vec<ModuleDiff> {
+        let mut modules = BTreeMap::<String, Vec<Option<ModuleEntry>>>::new();
+        for (id, parser) in self.iter() {
+            for module in parser.modules() {
+                let name = module_short_name(&module.name).to_ascii_lowercase();
+                let entries = modules
+                    .entry(name)
+                    .or_insert_with(|| vec![None; self.len()]);
+                if entries[id].is_none() {
+                    entries[id] = Some(module.clone());
+                }
+            }
+        }
+
+        let key =
+            |module: &ModuleEntry| (module.at.clone(), module.size_of_image, module.timestamp);
+        modules
+            .into_iter()
+            .filter(|(_, entries)| {
+                let first = entries[0].as_ref().map(key);
+                entries
+                    .iter()
+                    .any(|entry| first.is_none() || entry.as_ref().map(key) != first)
+            })
+            .map(|(name, entries)| ModuleDiff {
+                name,
+                entries: entries.into_iter().enumerate().collect(),
+            })
+            .collect()
+    }
+
+    /// The processes of the process lists that aren't in all the dumps, sorted
+    /// by `UniqueProcessId`. The processes are told apart by their
+    /// `UniqueProcessId` and their `ImageFileName`; the processes read before
+    /// a process list turns out to be broken are kept.
+    pub fn process_diffs(&self) -> Vec<ProcessDiff> {
+        let mut processes = BTreeMap::<(u64, String), Vec<DumpId>>::new();
+        for (id, parser) in self.iter() {
+            for process in process_list(parser) {
+                let dumps = processes.entry(process).or_default();
+                if dumps.last() != Some(&id) {
+                    dumps.push(id);
+                }
+            }
+        }
+
+        processes
+            .into_iter()
+            .filter(|(_, dumps)| dumps.len() != self.len())
+            .map(|((pid, name), present_in)| ProcessDiff {
+                pid,
+                name,
+                present_in,
+            })
+            .collect()
+    }
+}
+
+/// The `UniqueProcessId` and the `ImageFileName` of the processes linked in
+/// `PsActiveProcessHead`.
+fn process_list(parser: &KernelDumpParser) -> Vec<(u64, String)> {
+    let Ok(links) = parser.profile().offset("_EPROCESS", "ActiveProcessLinks") else {
+        return Vec::new();
+    };
+
+    let head = Gva::new(parser.headers().ps_active_process_head);
+    let mut processes = Vec::new();
+    for eprocess in parser.walk_list(head, links, MAX_PROCESSES) {
+        let Ok(eprocess) = eprocess else {
+            break;
+        };
+
+        let read = || -> Result<_> {
+            let pid = parser.read_field(eprocess, "_EPROCESS", "UniqueProcessId")?;
+            let name = parser.field_addr(eprocess, "_EPROCESS", "ImageFileName")?;
+            let name = parser.virt_read_struct::<[u8; 15]>(name)?;
+            let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+
+            Ok((pid, String::from_utf8_lossy(&name[..len]).into_owned()))
+        };
+
+        if let Ok(process) = read() {
+            processes.push(process);
+        }
+    }
+
+    processes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DumpBuilder;
+    use crate::PxeFlags;
+
+    const DATA: u64 = 0xffff_f800_1000_0000;
+    const HEAD: u64 = 0xffff_f800_2000_0000;
+
+    /// Build a dump whose `nt` is at `nt`, and whose processes are `pids`.
+    fn dump(nt: u64, data: u8, pids: &[u64]) -> KernelDumpParser {
+        let mut builder = DumpBuilder::new()
+            .module(nt..nt + 0x1_000, "ntoskrnl.exe")
+            .map_virt(nt, 0x10_000, PxeFlags::Present)
+            .write_virt(nt + 0x100, &[data; 4])
+            .map_virt(DATA, 0x11_000, PxeFlags::Present)
+            .write_virt(DATA, &[0x42, data])
+            .map_virt(HEAD, 0x12_000, PxeFlags::Present);
+
+        // The processes are linked one after the other, after the head.
+        let links = |idx: usize| match idx {
+            0 => HEAD,
+            idx => HEAD + (idx as u64 * 0x1_000) + 0x448,
+        };
+        let count = pids.len() + 1;
+        for idx in 0..count {
+            let entry = links(idx);
+            let (flink, blink) = (links((idx + 1) % count), links((idx + count - 1) % count));
+            if idx != 0 {
+                let eprocess = entry - 0x448;
+                builder = builder
+                    .map_virt(
+                        eprocess,
+                        0x12_000 + (idx as u64 * 0x1_000),
+                        PxeFlags::Present,
+                    )
+                    .write_virt(eprocess + 0x440, &pids[idx - 1].to_le_bytes())
+                    .write_virt(eprocess + 0x5a8, b"a.exe\0");
+            }
+
+            builder = builder
+                .write_virt(entry, &flink.to_le_bytes())
+                .write_virt(entry + 8, &blink.to_le_bytes());
+        }
+
+        let mut dump = builder.build();
+        dump[0x28..0x30].copy_from_slice(&HEAD.to_le_bytes());
+
+        KernelDumpParser::from_bytes(dump).unwrap()
+    }
+
+    #[test]
+    fn parse_address() {
+        assert_eq!(
+            "nt+0x123".parse::<SetAddress>().unwrap(),
+            SetAddress::Module {
+                name: "nt".into(),
+                offset: 0x123
+            }
+        );
+        assert_eq!(
+            "0xfffff805_10877000".parse::<SetAddress>().unwrap(),
+            SetAddress::Absolute(Gva::new(0xfffff805_10877000))
+        );
+        assert!("+0x10".parse::<SetAddress>().is_err());
+        assert!("nt+zz".parse::<SetAddress>().is_err());
+    }
+
+    #[test]
+    fn dump_set() {
+        let (nt1, nt2) = (0xffff_f805_0000_0000, 0xffff_f806_0000_0000);
+        let mut set = DumpSet::new();
+        set.add(dump(nt1, 0xaa, &[4, 8]));
+        set.add(dump(nt2, 0xaa, &[4]));
+        set.add(dump(nt2, 0xbb, &[4]));
+
+        // `nt` isn't at the same place in every dump..
+        let comparison = set.compare_virt("nt+0x100".parse::<SetAddress>().unwrap(), 4);
+        assert_eq!(comparison.contents, [
+            (vec![0xaa; 4], vec![0, 1]),
+            (vec![0xbb; 4], vec![2])
+        ]);
+        assert!(!comparison.identical());
+        assert_eq!(comparison.differing_offsets(), [0, 1, 2, 3]);
+        let diffs = set.module_diffs();
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].name, "nt");
+        assert_eq!(
+            diffs[0].entries[1].1.as_ref().unwrap().at.start,
+            Gva::new(nt2)
+        );
+
+        // ..unless its base is registered..
+        set.set_module_base(2, "ntoskrnl.exe", Gva::new(DATA - 0x100));
+        let comparison = set.compare_virt("nt+0x100".parse::<SetAddress>().unwrap(), 2);
+        assert_eq!(comparison.contents, [
+            (vec![0xaa; 2], vec![0, 1]),
+            (vec![0x42, 0xbb], vec![2])
+        ]);
+        assert_eq!(comparison.differing_offsets(), [0, 1]);
+
+        // ..the memory that's missing in a dump is reported..
+        let reads = set.read_all_virt(DATA + 0x1_000, 1);
+        assert!(reads.iter().all(|(_, read)| read.is_err()));
+        let comparison = set.compare_virt(DATA, 1);
+        assert!(comparison.identical());
+        assert_eq!(comparison.contents, [(vec![0x42], vec![0, 1, 2])]);
+
+        // ..and so are the processes that aren't in every dump.
+        assert_eq!(set.process_diffs(), [ProcessDiff {
+            pid: 8,
+            name: "a.exe".into(),
+            present_in: vec![0]
+        }]);
+    }
+}
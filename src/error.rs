@@ -1,12 +1,13 @@
 // Axel '0vercl0k' Souchet - March 19 2024
 //! This is the error type used across the codebase.
 use std::fmt::Display;
+use std::ops::Range;
 use std::{io, string};
 
 use thiserror::Error;
 
 use crate::structs::{DUMP_HEADER64_EXPECTED_SIGNATURE, DUMP_HEADER64_EXPECTED_VALID_DUMP};
-use crate::{Gpa, Gva};
+use crate::{ContainerKind, DumpAttributes, ExclusionReason, Gpa, Gva, ModuleEntry};
 pub type Result<R> = std::result::Result<R, KdmpParserError>;
 
 #[derive(Debug)]
@@ -21,6 +22,33 @@ pub enum PxeNotPresent {
 pub enum AddrTranslationError {
     Virt(Gva, PxeNotPresent),
     Phys(Gpa),
+    /// The translation of the `Gva` led to a `Gpa` past the physical memory
+    /// of the machine; see [`crate::KernelDumpParser::max_physical_address`].
+    ImplausiblePhysicalAddress(Gva, Gpa),
+    /// The `Gpa` isn't in the dump because its writer left it out; see
+    /// [`crate::KernelDumpParser::excluded_ranges`].
+    Excluded(Gpa, ExclusionReason),
+    /// The `Gva` is a user address, and it was translated in the kernel
+    /// address space; see [`crate::KernelDumpParser::kernel_space`].
+    UserAddress(Gva),
+}
+
+impl AddrTranslationError {
+    /// The stable code of the error; see [`KdmpParserError::code`].
+    pub fn code(&self) -> u32 {
+        match self {
+            AddrTranslationError::Virt(..) => 100,
+            AddrTranslationError::Phys(_) => 101,
+            AddrTranslationError::ImplausiblePhysicalAddress(..) => 102,
+            AddrTranslationError::Excluded(..) => 103,
+            AddrTranslationError::UserAddress(_) => 104,
+        }
+    }
+
+    /// The category of the error.
+    pub fn category(&self) -> ErrorCategory {
+        ErrorCategory::Translation
+    }
 }
 
 impl Display for AddrTranslationError {
@@ -32,6 +60,121 @@ impl Display for AddrTranslationError {
             AddrTranslationError::Phys(gpa) => {
                 f.write_fmt(format_args!("phys to offset translation of {gpa}"))
             }
+            AddrTranslationError::ImplausiblePhysicalAddress(gva, gpa) => f.write_fmt(
+                format_args!("virt to phys translation of {gva}: {gpa} is past physical memory"),
+            ),
+            AddrTranslationError::Excluded(gpa, reason) => f.write_fmt(format_args!(
+                "phys to offset translation of {gpa}: {reason}"
+            )),
+            AddrTranslationError::UserAddress(gva) => f.write_fmt(format_args!(
+                "virt to phys translation of {gva}: user address in the kernel address space"
+            )),
+        }
+    }
+}
+
+/// The broad kind of a [`KdmpParserError`], to aggregate errors without
+/// matching on every variant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// Reading the dump file failed.
+    Io,
+    /// The dump, or the data in its memory, is malformed.
+    Format,
+    /// An address couldn't be translated, or the memory it points to isn't in
+    /// the dump.
+    Translation,
+    /// A limit of the parser, or of the host, was hit.
+    Limit,
+    /// The dump doesn't have, or the parser doesn't support, what was asked.
+    Unsupported,
+}
+
+/// Something unexpected that was found while parsing a dump. In lenient mode
+/// the parser works around it and records it (see
+/// [`crate::KernelDumpParser::warnings`]); in strict mode it is an error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// Two entries of a module list overlap, which indicates a corrupted list.
+    /// The second module is left out.
+    OverlappingModules {
+        first: (Range<Gva>, String),
+        second: (Range<Gva>, String),
+    },
+    /// The module list of the headers couldn't be read, so the kernel modules
+    /// were recovered from `nt`, found by scanning memory.
+    ModuleListRecovered { nt_base: Gva },
+    /// The name of the module list entry at `entry` isn't valid UTF-16, or is
+    /// only partially available; `name` is what could be decoded.
+    InvalidModuleName { entry: Gva, name: String },
+    /// A module list has two entries for the same module (same base and name),
+    /// which happens when a driver is unloaded and reloaded. The second entry
+    /// is left out.
+    DuplicateModule {
+        first: Box<ModuleEntry>,
+        second: Box<ModuleEntry>,
+    },
+    /// The module list at `head` is malformed (see `reason`), so only its
+    /// first `kept` entries were read.
+    InvalidModuleList {
+        head: Gva,
+        kept: usize,
+        reason: &'static str,
+    },
+    /// The index the dump was opened with couldn't be used (see `reason`), so
+    /// the dump was parsed instead; see
+    /// [`crate::KernelDumpParser::open_with_index`].
+    InvalidIndex { reason: &'static str },
+    /// The dump already has `pages` pages of the supplemental `range`, so they
+    /// were kept; see
+    /// [`crate::KernelDumpParser::add_supplemental_physmem`].
+    SupplementalConflict { range: Range<Gpa>, pages: u64 },
+    /// The `header` of the dump has `extra_bytes` trailing bytes the parser
+    /// doesn't know about, which happens with the dumps written by builds
+    /// newer than it; they were ignored. Parse in strict mode to detect the
+    /// format changes.
+    UnknownHeaderExtension {
+        header: &'static str,
+        extra_bytes: u64,
+    },
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Warning::OverlappingModules {
+                first: (first_at, first),
+                second: (second_at, second),
+            } => f.write_fmt(format_args!(
+                "module {second} ({}-{}) overlaps with {first} ({}-{})",
+                second_at.start, second_at.end, first_at.start, first_at.end
+            )),
+            Warning::ModuleListRecovered { nt_base } => f.write_fmt(format_args!(
+                "the module list couldn't be read, the kernel modules were recovered from nt at {nt_base}"
+            )),
+            Warning::InvalidModuleName { entry, name } => f.write_fmt(format_args!(
+                "the name of the module entry at {entry} is invalid, it was decoded as {name:?}"
+            )),
+            Warning::DuplicateModule { first, second } => f.write_fmt(format_args!(
+                "module {} ({}-{}) is listed twice, the duplicate spans {}-{}",
+                first.name, first.at.start, first.at.end, second.at.start, second.at.end
+            )),
+            Warning::InvalidModuleList { head, kept, reason } => f.write_fmt(format_args!(
+                "the module list at {head} is invalid ({reason}), only its first {kept} entries were read"
+            )),
+            Warning::InvalidIndex { reason } => f.write_fmt(format_args!(
+                "the index couldn't be used ({reason}), the dump was parsed instead"
+            )),
+            Warning::SupplementalConflict { range, pages } => f.write_fmt(format_args!(
+                "the dump already has {pages} pages of the supplemental range {}-{}, they were kept",
+                range.start, range.end
+            )),
+            Warning::UnknownHeaderExtension {
+                header,
+                extra_bytes,
+            } => f.write_fmt(format_args!(
+                "the {header} has {extra_bytes:#x} unknown trailing bytes, they were ignored"
+            )),
         }
     }
 }
@@ -50,6 +193,8 @@ pub enum KdmpParserError {
     InvalidData(&'static str),
     #[error("unsupported dump type {0:#x}")]
     UnknownDumpType(u32),
+    #[error("unsupported page size {0:#x}")]
+    UnsupportedPageSize(u64),
     #[error("duplicate gpa found in physmem map for {0}")]
     DuplicateGpa(Gpa),
     #[error("header's signature looks wrong: {0:#x} vs {DUMP_HEADER64_EXPECTED_SIGNATURE:#x}")]
@@ -66,6 +211,340 @@ pub enum KdmpParserError {
     PartialPhysRead,
     #[error("partial virtual memory read")]
     PartialVirtRead,
+    #[error("cycle detected in list at {0}")]
+    ListCycle(Gva),
+    #[error("list entry {0} has a blink that doesn't point to the previous entry")]
+    ListBlinkMismatch(Gva),
+    #[error("list has more than {0} entries")]
+    ListTooLong(usize),
+    #[error("null pointer at step {step} of a pointer chain")]
+    NullPointer { step: usize },
+    #[error("pointer chain failed at step {step} (chain: {chain:x?}): {source}")]
+    DerefChain {
+        step: usize,
+        chain: Vec<Gva>,
+        source: Box<KdmpParserError>,
+    },
+    #[error("could not find {0}")]
+    NotFound(&'static str),
+    #[error("registry key not found: {0}")]
+    RegistryKeyNotFound(String),
+    #[error("{type_name}{} is missing from the profile", field.as_ref().map(|f| format!(".{f}")).unwrap_or_default())]
+    ProfileMissing {
+        type_name: String,
+        field: Option<String>,
+    },
+    #[error("{0}")]
+    Strict(Warning),
+    #[error("{0} is not available in the dump")]
+    Unavailable(&'static str),
+    #[cfg(feature = "json")]
+    #[error("invalid json: {0}")]
+    InvalidJson(#[from] serde_json::Error),
+    #[error("{0:#x} doesn't fit in the address space of this host")]
+    OffsetTooLarge(u64),
+    #[error("reading {requested:#x} bytes exceeds the limit of {limit:#x} bytes")]
+    ReadLimitExceeded { requested: u64, limit: u64 },
+    #[cfg(feature = "object")]
+    #[error("object: {0}")]
+    Object(#[from] object::Error),
+    #[error("unsupported hibernation file: {0}")]
+    UnsupportedHibernation(&'static str),
+    #[error("the dump is in a {0}, extract it first")]
+    CompressedContainer(ContainerKind),
     #[error("memory translation: {0}")]
     AddrTranslation(#[from] AddrTranslationError),
+    #[error("the scan has been cancelled")]
+    Cancelled,
+    #[error("the memory of the dump is encrypted ({attributes:?}), decrypt it first")]
+    EncryptedDump {
+        dump_type: u32,
+        attributes: DumpAttributes,
+    },
+    #[error("no valid context: the one of the headers is zeroed, and no processor has one")]
+    NoValidContext,
+    #[error("the pdb isn't the one of {module}: it is {found}, the module's is {expected}")]
+    PdbMismatch {
+        module: String,
+        expected: String,
+        found: String,
+    },
+    #[error("unknown symbol {0}")]
+    UnknownSymbol(String),
+    #[error("the pages of the dump are framed by an unknown crash dump filter (marker {0:?})")]
+    UnknownDumpFilter(String),
+    #[cfg(feature = "pdb")]
+    #[error("invalid pdb: {0}")]
+    InvalidPdb(#[from] ::pdb::Error),
+}
+
+impl KdmpParserError {
+    /// The stable code of the error, to aggregate errors without depending on
+    /// their message. The codes are part of the API: a variant keeps its code
+    /// from one version to the next, and the code of a variant that is removed
+    /// isn't reused. A [`KdmpParserError::AddrTranslation`] has the code of
+    /// the [`AddrTranslationError`] it wraps, which are in the 100s.
+    pub fn code(&self) -> u32 {
+        match self {
+            KdmpParserError::InvalidUnicodeString => 1,
+            KdmpParserError::Utf16(_) => 2,
+            KdmpParserError::Overflow(_) => 3,
+            KdmpParserError::Io(_) => 4,
+            KdmpParserError::InvalidData(_) => 5,
+            KdmpParserError::UnknownDumpType(_) => 6,
+            KdmpParserError::UnsupportedPageSize(_) => 7,
+            KdmpParserError::DuplicateGpa(_) => 8,
+            KdmpParserError::InvalidSignature(_) => 9,
+            KdmpParserError::InvalidValidDump(_) => 10,
+            KdmpParserError::PhysAddrOverflow(..) => 11,
+            KdmpParserError::PageOffsetOverflow(..) => 12,
+            KdmpParserError::BitmapPageOffsetOverflow(..) => 13,
+            KdmpParserError::PartialPhysRead => 14,
+            KdmpParserError::PartialVirtRead => 15,
+            KdmpParserError::ListCycle(_) => 16,
+            KdmpParserError::ListBlinkMismatch(_) => 17,
+            KdmpParserError::ListTooLong(_) => 18,
+            KdmpParserError::NullPointer { .. } => 19,
+            KdmpParserError::DerefChain { .. } => 20,
+            KdmpParserError::NotFound(_) => 21,
+            KdmpParserError::RegistryKeyNotFound(_) => 22,
+            KdmpParserError::ProfileMissing { .. } => 23,
+            KdmpParserError::Strict(_) => 24,
+            KdmpParserError::Unavailable(_) => 25,
+            #[cfg(feature = "json")]
+            KdmpParserError::InvalidJson(_) => 26,
+            KdmpParserError::OffsetTooLarge(_) => 27,
+            KdmpParserError::ReadLimitExceeded { .. } => 28,
+            #[cfg(feature = "object")]
+            KdmpParserError::Object(_) => 29,
+            KdmpParserError::UnsupportedHibernation(_) => 30,
+            KdmpParserError::CompressedContainer(_) => 31,
+            KdmpParserError::Cancelled => 32,
+            KdmpParserError::EncryptedDump { .. } => 33,
+            KdmpParserError::NoValidContext => 34,
+            KdmpParserError::PdbMismatch { .. } => 35,
+            KdmpParserError::UnknownSymbol(_) => 36,
+            KdmpParserError::UnknownDumpFilter(_) => 37,
+            #[cfg(feature = "pdb")]
+            KdmpParserError::InvalidPdb(_) => 38,
+            KdmpParserError::AddrTranslation(e) => e.code(),
+        }
+    }
+
+    /// The category of the error.
+    pub fn category(&self) -> ErrorCategory {
+        use ErrorCategory as C;
+        match self {
+            KdmpParserError::Io(_) => C::Io,
+            KdmpParserError::InvalidUnicodeString
+            | KdmpParserError::Utf16(_)
+            | KdmpParserError::Overflow(_)
+            | KdmpParserError::InvalidData(_)
+            | KdmpParserError::DuplicateGpa(_)
+            | KdmpParserError::InvalidSignature(_)
+            | KdmpParserError::InvalidValidDump(_)
+            | KdmpParserError::PhysAddrOverflow(..)
+            | KdmpParserError::PageOffsetOverflow(..)
+            | KdmpParserError::BitmapPageOffsetOverflow(..)
+            | KdmpParserError::ListCycle(_)
+            | KdmpParserError::ListBlinkMismatch(_)
+            | KdmpParserError::Strict(_)
+            | KdmpParserError::NoValidContext => C::Format,
+            #[cfg(feature = "object")]
+            KdmpParserError::Object(_) => C::Format,
+            #[cfg(feature = "json")]
+            KdmpParserError::InvalidJson(_) => C::Format,
+            #[cfg(feature = "pdb")]
+            KdmpParserError::InvalidPdb(_) => C::Format,
+            KdmpParserError::PartialPhysRead
+            | KdmpParserError::PartialVirtRead
+            | KdmpParserError::NullPointer { .. }
+            | KdmpParserError::DerefChain { .. } => C::Translation,
+            KdmpParserError::AddrTranslation(e) => e.category(),
+            KdmpParserError::ListTooLong(_)
+            | KdmpParserError::OffsetTooLarge(_)
+            | KdmpParserError::ReadLimitExceeded { .. }
+            | KdmpParserError::Cancelled => C::Limit,
+            KdmpParserError::UnknownDumpType(_)
+            | KdmpParserError::UnsupportedPageSize(_)
+            | KdmpParserError::NotFound(_)
+            | KdmpParserError::RegistryKeyNotFound(_)
+            | KdmpParserError::ProfileMissing { .. }
+            | KdmpParserError::Unavailable(_)
+            | KdmpParserError::UnsupportedHibernation(_)
+            | KdmpParserError::CompressedContainer(_)
+            | KdmpParserError::EncryptedDump { .. }
+            | KdmpParserError::PdbMismatch { .. }
+            | KdmpParserError::UnknownSymbol(_)
+            | KdmpParserError::UnknownDumpFilter(_) => C::Unsupported,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    /// One error of every variant, with the category it belongs to.
+    fn errors() -> Vec<(KdmpParserError, ErrorCategory)> {
+        use ErrorCategory as C;
+        use KdmpParserError as E;
+        let gva = Gva::new(0);
+        let errors = vec![
+            (E::InvalidUnicodeString, C::Format),
+            (
+                E::Utf16(String::from_utf16(&[0xd800]).unwrap_err()),
+                C::Format,
+            ),
+            (E::Overflow(""), C::Format),
+            (E::Io(io::ErrorKind::Other.into()), C::Io),
+            (E::InvalidData(""), C::Format),
+            (E::UnknownDumpType(0), C::Unsupported),
+            (E::UnsupportedPageSize(0), C::Unsupported),
+            (E::DuplicateGpa(Gpa::new(0)), C::Format),
+            (E::InvalidSignature(0), C::Format),
+            (E::InvalidValidDump(0), C::Format),
+            (E::PhysAddrOverflow(0, 0), C::Format),
+            (E::PageOffsetOverflow(0, 0), C::Format),
+            (E::BitmapPageOffsetOverflow(0, 0), C::Format),
+            (E::PartialPhysRead, C::Translation),
+            (E::PartialVirtRead, C::Translation),
+            (E::ListCycle(gva), C::Format),
+            (E::ListBlinkMismatch(gva), C::Format),
+            (E::ListTooLong(0), C::Limit),
+            (E::NullPointer { step: 0 }, C::Translation),
+            (
+                E::DerefChain {
+                    step: 0,
+                    chain: Vec::new(),
+                    source: Box::new(E::PartialVirtRead),
+                },
+                C::Translation,
+            ),
+            (E::NotFound(""), C::Unsupported),
+            (E::RegistryKeyNotFound(String::new()), C::Unsupported),
+            (
+                E::ProfileMissing {
+                    type_name: String::new(),
+                    field: None,
+                },
+                C::Unsupported,
+            ),
+            (
+                E::Strict(Warning::ModuleListRecovered { nt_base: gva }),
+                C::Format,
+            ),
+            (E::Unavailable(""), C::Unsupported),
+            (E::OffsetTooLarge(0), C::Limit),
+            (E::Cancelled, C::Limit),
+            (E::NoValidContext, C::Format),
+            (
+                E::PdbMismatch {
+                    module: String::new(),
+                    expected: String::new(),
+                    found: String::new(),
+                },
+                C::Unsupported,
+            ),
+            (E::UnknownSymbol(String::new()), C::Unsupported),
+            (E::UnknownDumpFilter(String::new()), C::Unsupported),
+            (
+                E::EncryptedDump {
+                    dump_type: 0,
+                    attributes: DumpAttributes::empty(),
+                },
+                C::Unsupported,
+            ),
+            (E::UnsupportedHibernation(""), C::Unsupported),
+            (E::CompressedContainer(ContainerKind::Cab), C::Unsupported),
+            (
+                E::ReadLimitExceeded {
+                    requested: 0,
+                    limit: 0,
+                },
+                C::Limit,
+            ),
+            (
+                E::AddrTranslation(AddrTranslationError::Virt(gva, PxeNotPresent::Pte)),
+                C::Translation,
+            ),
+            (
+                E::AddrTranslation(AddrTranslationError::Phys(Gpa::new(0))),
+                C::Translation,
+            ),
+            (
+                E::AddrTranslation(AddrTranslationError::ImplausiblePhysicalAddress(
+                    gva,
+                    Gpa::new(0),
+                )),
+                C::Translation,
+            ),
+            (
+                E::AddrTranslation(AddrTranslationError::Excluded(
+                    Gpa::new(0),
+                    ExclusionReason::Filtered,
+                )),
+                C::Translation,
+            ),
+            (
+                E::AddrTranslation(AddrTranslationError::UserAddress(gva)),
+                C::Translation,
+            ),
+        ];
+
+        #[cfg(feature = "object")]
+        let errors = {
+            let mut errors = errors;
+            errors.push((
+                E::Object(object::File::parse(&[][..]).unwrap_err()),
+                C::Format,
+            ));
+
+            errors
+        };
+
+        #[cfg(feature = "json")]
+        let errors = {
+            let mut errors = errors;
+            errors.push((
+                E::InvalidJson(serde_json::from_str::<u64>("").unwrap_err()),
+                C::Format,
+            ));
+
+            errors
+        };
+
+        #[cfg(feature = "pdb")]
+        let errors = {
+            let mut errors = errors;
+            errors.push((
+                E::InvalidPdb(::pdb::Error::UnrecognizedFileFormat),
+                C::Format,
+            ));
+
+            errors
+        };
+
+        errors
+    }
+
+    #[test]
+    fn codes() {
+        let errors = errors();
+        let codes = errors.iter().map(|(e, _)| e.code()).collect::<HashSet<_>>();
+        assert_eq!(codes.len(), errors.len());
+
+        for (e, category) in &errors {
+            assert_eq!(e.category(), *category, "{e:?}");
+        }
+
+        // The codes don't change.
+        assert_eq!(KdmpParserError::Overflow("").code(), 3);
+        assert_eq!(
+            KdmpParserError::AddrTranslation(AddrTranslationError::Phys(Gpa::new(0))).code(),
+            101
+        );
+    }
 }
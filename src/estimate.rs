@@ -0,0 +1,258 @@
+//! This contains what is needed to estimate the memory parsing a dump takes
+//! before parsing it (see [`ResourceEstimate`]), to schedule parses in
+//! memory-capped environments.
+//!
+//! Only the headers, and the metadata that say where the pages of physical
+//! memory are (the runs, the bitmap or the page ranges), are read: the
+//! physical memory map isn't built.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::KernelDumpParser;
+//! let estimate = KernelDumpParser::estimate_resources(&"full.dmp").unwrap();
+//! if estimate.peak_open_bytes < 512 * 1_024 * 1_024 {
+//!     let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! }
+//! ```
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek};
+use std::mem;
+use std::path::Path;
+
+use crate::error::Result;
+use crate::map::Reader;
+use crate::page_table_cache::TABLE_BYTES;
+use crate::parse::{check_header, check_page_size, memory_mapped};
+use crate::structs::{
+    read_struct, BmpHeader64, FromLeBytes, FullRdmpHeader64, Header64, KernelRdmpHeader64, Page,
+    PfnRange, PhysmemDesc, PhysmemRun,
+};
+use crate::{DumpType, KdmpParserError, KernelDumpParser, ParserOptions};
+
+/// Upper bound of the heap the physical memory map takes per [`Page::size()`]
+/// page: it is a `BTreeMap<Gpa, u64>` whose nodes, but the root, are at least
+/// half full.
+const INDEX_ENTRY_BYTES: u64 = 48;
+
+/// Heap the physical memory map takes when it isn't empty: its root node.
+const INDEX_ROOT_BYTES: u64 = 192;
+
+/// Upper bound of the heap parsing a dump takes besides the physical memory
+/// map and the cache of the page tables: the headers, the module lists, and
+/// the structures they are read with.
+const OPEN_OVERHEAD_BYTES: u64 = 1_024 * 1_024;
+
+/// Size of the chunks the bitmap of a [`DumpType::Bmp`] dump is read in.
+const BITMAP_CHUNK_SIZE: usize = 0x1_0000;
+
+/// What opening a dump is expected to take; see
+/// [`KernelDumpParser::estimate_resources`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceEstimate {
+    /// Number of [`Page::size()`] pages of physical memory in the dump.
+    pub pages: u64,
+    /// Upper bound of the heap the physical memory map takes.
+    pub index_bytes: u64,
+    /// Upper bound of the heap that is allocated at any time while the dump
+    /// is opened, and that stays allocated once it is: the physical memory
+    /// map, the cache of the page tables once it is full (see
+    /// [`ParserOptions::page_table_cache_entries`]), and what parsing reads.
+    pub peak_open_bytes: u64,
+    /// Address space the dump file is mapped in: the whole file when it is
+    /// memory mapped, nothing when it is read through the file (past 4GB).
+    /// This isn't heap, and the pages of the mapping that are read are backed
+    /// by the page cache.
+    pub mapped_bytes: u64,
+    /// Size of the dump file.
+    pub file_size: u64,
+    pub dump_type: DumpType,
+}
+
+impl KernelDumpParser {
+    /// Estimate what opening the dump at `dump_path` takes with
+    /// [`KernelDumpParser::new`], without opening it.
+    pub fn estimate_resources<P>(dump_path: &P) -> Result<ResourceEstimate>
+    where
+        P: AsRef<Path>,
+    {
+        Self::estimate_resources_with_options(dump_path, ParserOptions::default())
+    }
+
+    /// Estimate what opening the dump at `dump_path` takes with
+    /// [`KernelDumpParser::new_with_options`] and `options`, without opening
+    /// it. The dump is checked like it is when it is opened, so a dump that
+    /// doesn't parse is an error; a dump in a container is a
+    /// [`KdmpParserError::CompressedContainer`], as it is decompressed in
+    /// memory first.
+    pub fn estimate_resources_with_options<P>(
+        dump_path: &P,
+        options: ParserOptions,
+    ) -> Result<ResourceEstimate>
+    where
+        P: AsRef<Path>,
+    {
+        let mut reader = BufReader::new(File::open(dump_path)?);
+        let mut raw_header = vec![0; Header64::SIZE];
+        reader.read_exact(&mut raw_header)?;
+        let (headers, dump_type) = check_header(&raw_header)?;
+        check_page_size(options.page_size)?;
+        let file_size = reader.seek(io::SeekFrom::End(0))?;
+        reader.seek(io::SeekFrom::Start(Header64::SIZE as u64))?;
+
+        let dump_pages = match dump_type {
+            DumpType::Full => full_pages(&headers)?,
+            DumpType::Bmp => bmp_pages(&mut reader)?,
+            DumpType::KernelMemory | DumpType::KernelAndUserMemory | DumpType::CompleteMemory => {
+                rdmp_pages(dump_type, &mut reader)?
+            }
+        };
+
+        // Every page of the dump is indexed as the 4K pages it is made of.
+        let pages = dump_pages.saturating_mul(options.page_size / Page::size());
+        let index_bytes = if pages == 0 {
+            0
+        } else {
+            pages
+                .saturating_mul(INDEX_ENTRY_BYTES)
+                .max(INDEX_ROOT_BYTES)
+        };
+
+        let page_table_cache_bytes =
+            (options.page_table_cache_entries as u64).saturating_mul(TABLE_BYTES);
+
+        Ok(ResourceEstimate {
+            pages,
+            index_bytes,
+            peak_open_bytes: index_bytes
+                .saturating_add(page_table_cache_bytes)
+                .saturating_add(OPEN_OVERHEAD_BYTES),
+            mapped_bytes: if memory_mapped(file_size) {
+                file_size
+            } else {
+                0
+            },
+            file_size,
+            dump_type,
+        })
+    }
+}
+
+/// Number of pages in the runs of a [`DumpType::Full`] dump.
+fn full_pages(headers: &Header64) -> Result<u64> {
+    let mut run_cursor = io::Cursor::new(headers.physical_memory_block_buffer);
+    let physmem_desc = PhysmemDesc::read(&mut run_cursor)?;
+    let mut pages = 0u64;
+    for _ in 0..physmem_desc.number_of_runs {
+        let run = read_struct::<PhysmemRun>(&mut run_cursor)?;
+        pages = pages.saturating_add(run.page_count);
+    }
+
+    Ok(pages)
+}
+
+/// Number of bits set in the bitmap of a [`DumpType::Bmp`] dump.
+fn bmp_pages(reader: &mut impl Reader) -> Result<u64> {
+    let bmp_header = read_struct::<BmpHeader64>(reader)?;
+    if !bmp_header.looks_good() {
+        return Err(KdmpParserError::InvalidData(
+            "bmp header doesn't look right",
+        ));
+    }
+
+    let mut left = bmp_header.pages / 8;
+    let mut chunk = vec![0; BITMAP_CHUNK_SIZE];
+    let mut pages = 0u64;
+    while left > 0 {
+        let chunk = &mut chunk[..usize::try_from(left)
+            .unwrap_or(usize::MAX)
+            .min(BITMAP_CHUNK_SIZE)];
+        reader.read_exact(chunk)?;
+        pages += chunk
+            .iter()
+            .map(|byte| u64::from(byte.count_ones()))
+            .sum::<u64>();
+        left -= chunk.len() as u64;
+    }
+
+    Ok(pages)
+}
+
+/// Number of pages in the page ranges of a [`DumpType::KernelMemory`] /
+/// [`DumpType::KernelAndUserMemory`] or [`DumpType::CompleteMemory`] dump;
+/// they are walked like they are when the dump is parsed.
+fn rdmp_pages(dump_type: DumpType, reader: &mut impl Reader) -> Result<u64> {
+    let (metadata_size, total_number_of_pages) = if dump_type == DumpType::CompleteMemory {
+        let full_hdr = read_struct::<FullRdmpHeader64>(reader)?;
+        if !full_hdr.hdr.looks_good() {
+            return Err(KdmpParserError::InvalidData(
+                "FullRdmpHeader64 doesn't look right",
+            ));
+        }
+
+        (
+            full_hdr.hdr.metadata_size,
+            Some(full_hdr.total_number_of_pages),
+        )
+    } else {
+        let kernel_hdr = read_struct::<KernelRdmpHeader64>(reader)?;
+        if !kernel_hdr.hdr.looks_good() {
+            return Err(KdmpParserError::InvalidData(
+                "RdmpHeader64 doesn't look right",
+            ));
+        }
+
+        (kernel_hdr.hdr.metadata_size, None)
+    };
+
+    let mut pages = 0u64;
+    for _ in 0..metadata_size / mem::size_of::<PfnRange>() as u64 {
+        if total_number_of_pages.is_some_and(|total| pages >= total) {
+            break;
+        }
+
+        let pfn_range = read_struct::<PfnRange>(reader)?;
+        if pfn_range.page_file_number == 0 {
+            break;
+        }
+
+        pages = pages.saturating_add(pfn_range.number_of_pages);
+    }
+
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::testing::DumpBuilder;
+
+    #[test]
+    fn estimate() {
+        let nt = 0xffff_f800_0000_0000;
+        let dump = DumpBuilder::new().module(nt..nt + 0x1_000, "nt").build();
+        let path =
+            std::env::temp_dir().join(format!("kdmp-parser-estimate-{}.dmp", std::process::id()));
+        File::create(&path).unwrap().write_all(&dump).unwrap();
+
+        let estimate = KernelDumpParser::estimate_resources(&path).unwrap();
+        let parser = KernelDumpParser::new(&path).unwrap();
+        assert_eq!(estimate.dump_type, DumpType::Bmp);
+        assert_eq!(estimate.pages, parser.physmem_len());
+        assert_eq!(estimate.index_bytes, estimate.pages * INDEX_ENTRY_BYTES);
+        assert_eq!(estimate.file_size, dump.len() as u64);
+        assert_eq!(estimate.mapped_bytes, estimate.file_size);
+
+        // A dump that doesn't parse can't be estimated either.
+        let mut corrupt = dump.clone();
+        corrupt[0x2000..0x2004].copy_from_slice(b"XXXX");
+        File::create(&path).unwrap().write_all(&corrupt).unwrap();
+        assert!(matches!(
+            KernelDumpParser::estimate_resources(&path),
+            Err(KdmpParserError::InvalidData(_))
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,426 @@
+//! This contains what is needed to tell apart the physical memory that isn't
+//! in a dump because its writer left it out on purpose from the memory that
+//! is simply missing: see [`KernelDumpParser::excluded_ranges`].
+//!
+//! The memory descriptor of the header lists the RAM of the machine, while
+//! the pages of a complete dump are the ones its writer saved; the RAM it
+//! didn't save (the pages of the secure kernel and of the hypervisor, the ones
+//! a dump filter driver removed, or the ones that didn't fit in the dump file)
+//! is excluded. Reading excluded memory fails with
+//! [`AddrTranslationError::Excluded`] instead of
+//! [`AddrTranslationError::Phys`].
+//!
+//! [`KernelDumpParser::missing_ranges`] goes further, and lists all of the
+//! memory of the machine the dump doesn't have, along with why.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::KernelDumpParser;
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! for (range, reason) in parser.excluded_ranges() {
+//!     println!("{}..{}: {reason}", range.start, range.end);
+//! }
+//! ```
+use std::fmt::{self, Display};
+use std::ops::Range;
+
+use crate::gxa::Gxa;
+use crate::structs::{DumpType, Page};
+use crate::{AddrTranslationError, DumpAttributes, Gpa, KernelDumpParser};
+
+/// Why a range of physical memory isn't in the dump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExclusionReason {
+    /// The writer of the dump left it out: it belongs to the secure kernel or
+    /// to the hypervisor, or a dump filter driver removed it.
+    Filtered,
+    /// It is past the last page that fit in the dump file (see
+    /// [`DumpAttributes::InsufficientDumpfileSize`]).
+    InsufficientDumpfileSize,
+}
+
+impl Display for ExclusionReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ExclusionReason::Filtered => "excluded by the writer of the dump",
+            ExclusionReason::InsufficientDumpfileSize => "past the end of the dump file",
+        })
+    }
+}
+
+/// Why a range of physical memory of the machine isn't in the dump; see
+/// [`KernelDumpParser::missing_ranges`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MissingReason {
+    /// The dump type doesn't save it: the kernel dumps only have the kernel
+    /// memory (and the memory of the processes they were asked for).
+    OutOfScope,
+    /// The writer of the dump left it out (see [`ExclusionReason::Filtered`]).
+    Filtered,
+    /// It didn't fit in the dump file (see
+    /// [`ExclusionReason::InsufficientDumpfileSize`]), or it hasn't been
+    /// written to it yet (see [`KernelDumpParser::open_incomplete`]).
+    Truncated,
+    /// Nothing says why: it might not be RAM at all.
+    Unknown,
+}
+
+impl Display for MissingReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MissingReason::OutOfScope => "not saved by this type of dump",
+            MissingReason::Filtered => "excluded by the writer of the dump",
+            MissingReason::Truncated => "past the end of the dump file",
+            MissingReason::Unknown => "missing for an unknown reason",
+        })
+    }
+}
+
+/// A range of physical memory of the machine that isn't in the dump, and why.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MissingRange {
+    pub gpa: Range<Gpa>,
+    pub reason: MissingReason,
+}
+
+impl KernelDumpParser {
+    /// The ranges of the physical memory of the machine that the dump doesn't
+    /// have, sorted and coalesced, along with why. The physical memory of the
+    /// machine is the one the memory descriptor of the header lists, or else
+    /// everything up to [`KernelDumpParser::max_physical_address`].
+    pub fn missing_ranges(&self) -> impl Iterator<Item = MissingRange> {
+        let mut missing = Vec::<MissingRange>::new();
+        let mut push = |gpa: Range<Gpa>, reason: MissingReason| {
+            if gpa.start >= gpa.end {
+                return;
+            }
+
+            match missing.last_mut() {
+                Some(last) if last.gpa.end == gpa.start && last.reason == reason => {
+                    last.gpa.end = gpa.end;
+                }
+                _ => missing.push(MissingRange { gpa, reason }),
+            }
+        };
+
+        let scope = self.scope_reason();
+        let hole_reason = |gpa: Gpa| match self.exclusion_reason(gpa) {
+            Some(ExclusionReason::Filtered) => MissingReason::Filtered,
+            Some(ExclusionReason::InsufficientDumpfileSize) => MissingReason::Truncated,
+            None => scope,
+        };
+
+        for Range { start, end } in self.physical_layout() {
+            // The pages that aren't written yet are missing too.
+            let mut pages = self
+                .physmem
+                .range(start..end)
+                .map(|(&page, _)| (page, true))
+                .chain(
+                    self.unwritten
+                        .range(start..end)
+                        .map(|(&page, _)| (page, false)),
+                )
+                .collect::<Vec<_>>();
+            pages.sort_unstable();
+            let mut cursor = start;
+            for (page, written) in pages {
+                push(cursor..page, hole_reason(cursor));
+                cursor = page.next_aligned_page();
+                if !written {
+                    push(page..cursor, MissingReason::Truncated);
+                }
+            }
+
+            push(cursor..end, hole_reason(cursor));
+        }
+
+        missing.into_iter()
+    }
+
+    /// The physical memory of the machine, sorted: the runs of the memory
+    /// descriptor, or everything up to the last physical address.
+    fn physical_layout(&self) -> Vec<Range<Gpa>> {
+        let mut runs = self
+            .memory_descriptor()
+            .map(|descriptor| {
+                descriptor
+                    .runs
+                    .iter()
+                    .filter_map(|run| {
+                        let start = run.base_page.u64().checked_mul(Page::size())?;
+                        let size = run.page_count.checked_mul(Page::size())?;
+
+                        Some(Gpa::new(start)..Gpa::new(start.checked_add(size)?))
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        if runs.is_empty() {
+            return self
+                .max_physical_address()
+                .and_then(|last| last.u64().checked_add(1))
+                .map(|end| vec![Gpa::new(0)..Gpa::new(end)])
+                .unwrap_or_default();
+        }
+
+        runs.sort_by_key(|run| run.start);
+
+        runs
+    }
+
+    /// Why the memory a dump doesn't have is missing, when it isn't
+    /// excluded: the kernel dumps (and the summary bitmap dumps) don't save
+    /// the user memory by design.
+    fn scope_reason(&self) -> MissingReason {
+        match self.dump_type() {
+            DumpType::KernelMemory | DumpType::KernelAndUserMemory => MissingReason::OutOfScope,
+            DumpType::Bmp => match self.raw().bmp_header() {
+                Ok(Some(header)) if header.summary() => MissingReason::OutOfScope,
+                _ => MissingReason::Unknown,
+            },
+            DumpType::Full | DumpType::CompleteMemory => MissingReason::Unknown,
+        }
+    }
+
+    /// The ranges of physical memory that the memory descriptor of the header
+    /// lists, but that the dump doesn't have, along with why. Only the full
+    /// and the complete dumps save all of the RAM; the kernel dumps leave out
+    /// the user pages by design, and the bitmap dumps don't have a
+    /// descriptor, so they don't have any.
+    pub fn excluded_ranges(&self) -> Vec<(Range<Gpa>, ExclusionReason)> {
+        self.exclusions().to_vec()
+    }
+
+    /// Why `gpa` isn't in the dump, if it is in an excluded range.
+    pub(crate) fn exclusion_reason(&self, gpa: Gpa) -> Option<ExclusionReason> {
+        let ranges = self.exclusions();
+        let idx = ranges.partition_point(|(range, _)| range.end <= gpa);
+
+        ranges
+            .get(idx)
+            .filter(|(range, _)| range.contains(&gpa))
+            .map(|(_, reason)| *reason)
+    }
+
+    /// The error of a `gpa` that isn't in the dump.
+    pub(crate) fn missing_page(&self, gpa: Gpa) -> AddrTranslationError {
+        match self.exclusion_reason(gpa) {
+            Some(reason) => AddrTranslationError::Excluded(gpa, reason),
+            None => AddrTranslationError::Phys(gpa),
+        }
+    }
+
+    fn exclusions(&self) -> &[(Range<Gpa>, ExclusionReason)] {
+        self.excluded_ranges
+            .get_or_init(|| self.find_excluded_ranges())
+    }
+
+    /// Go through the runs of the memory descriptor, and collect the ranges
+    /// in between the pages of the dump. What is past the last page of the
+    /// dump didn't fit in it if the header says so.
+    fn find_excluded_ranges(&self) -> Vec<(Range<Gpa>, ExclusionReason)> {
+        if !matches!(self.dump_type(), DumpType::Full | DumpType::CompleteMemory) {
+            return Vec::new();
+        }
+
+        let Some(descriptor) = self.memory_descriptor() else {
+            return Vec::new();
+        };
+
+        let truncated = self.attributes().is_some_and(|attributes| {
+            attributes.contains(DumpAttributes::InsufficientDumpfileSize)
+        });
+        // The pages that aren't written yet are in the dump, they just haven't
+        // landed in the file.
+        let last_page = self
+            .physmem
+            .keys()
+            .next_back()
+            .max(self.unwritten.keys().next_back())
+            .copied();
+        let reason = |start: Gpa| {
+            if truncated && last_page.map_or(true, |last| start > last) {
+                ExclusionReason::InsufficientDumpfileSize
+            } else {
+                ExclusionReason::Filtered
+            }
+        };
+
+        let mut ranges: Vec<(Range<Gpa>, ExclusionReason)> = Vec::new();
+        let mut exclude = |range: Range<Gpa>| {
+            if range.start >= range.end {
+                return;
+            }
+
+            let reason = reason(range.start);
+            match ranges.last_mut() {
+                Some((last, last_reason)) if last.end == range.start && *last_reason == reason => {
+                    last.end = range.end;
+                }
+                _ => ranges.push((range, reason)),
+            }
+        };
+
+        for run in &descriptor.runs {
+            let Some(start) = run.base_page.u64().checked_mul(Page::size()) else {
+                continue;
+            };
+
+            let Some(end) = run
+                .page_count
+                .checked_mul(Page::size())
+                .and_then(|size| start.checked_add(size))
+            else {
+                continue;
+            };
+
+            let (start, end) = (Gpa::new(start), Gpa::new(end));
+            let mut pages = self
+                .physmem
+                .range(start..end)
+                .chain(self.unwritten.range(start..end))
+                .map(|(&page, _)| page)
+                .collect::<Vec<_>>();
+            pages.sort_unstable();
+            let mut cursor = start;
+            for page in pages {
+                exclude(cursor..page);
+                cursor = page.next_aligned_page();
+            }
+
+            exclude(cursor..end);
+        }
+
+        ranges.sort_by_key(|(range, _)| range.start);
+
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DumpBuilder;
+    use crate::{KdmpParserError, Result};
+
+    /// A complete dump whose descriptor has two runs: of one page at `0x2_000`
+    /// and of three pages at `0x4_000`; the dump only has the pages of
+    /// `present`.
+    fn dump(present: &[u64], attributes: u32) -> Vec<u8> {
+        // The ranges of the pages are followed by an empty one.
+        let metadata_size = (present.len() as u64 + 1) * 0x10;
+        let mut dump = vec![0; 0x2_100 + (present.len() * 0x1_000)];
+        dump[0x0..0x4].copy_from_slice(b"PAGE");
+        dump[0x4..0x8].copy_from_slice(b"DU64");
+        dump[0x88..0x8c].copy_from_slice(&2u32.to_le_bytes());
+        dump[0x90..0x98].copy_from_slice(&4u64.to_le_bytes());
+        for (idx, (base_page, page_count)) in [(2u64, 1u64), (4, 3)].into_iter().enumerate() {
+            let offset = 0x98 + (idx * 0x10);
+            dump[offset..offset + 8].copy_from_slice(&base_page.to_le_bytes());
+            dump[offset + 8..offset + 0x10].copy_from_slice(&page_count.to_le_bytes());
+        }
+
+        dump[0xf98..0xf9c].copy_from_slice(&0xau32.to_le_bytes());
+        dump[0x1050..0x1054].copy_from_slice(&attributes.to_le_bytes());
+        dump[0x2_000..0x2_004].copy_from_slice(&0x40u32.to_le_bytes());
+        dump[0x2_004..0x2_00c].copy_from_slice(b"RDMPDUMP");
+        dump[0x2_010..0x2_018].copy_from_slice(&metadata_size.to_le_bytes());
+        dump[0x2_018..0x2_020].copy_from_slice(&(0x2_020 + metadata_size).to_le_bytes());
+        dump[0x2_028..0x2_030].copy_from_slice(&(present.len() as u64).to_le_bytes());
+        for (idx, pfn) in present.iter().enumerate() {
+            let offset = 0x2_030 + (idx * 0x10);
+            dump[offset..offset + 8].copy_from_slice(&pfn.to_le_bytes());
+            dump[offset + 8..offset + 0x10].copy_from_slice(&1u64.to_le_bytes());
+        }
+
+        dump
+    }
+
+    fn read(parser: &KernelDumpParser, gpa: u64) -> Result<()> {
+        parser.phys_read_exact(Gpa::new(gpa), &mut [0; 8])
+    }
+
+    #[test]
+    fn excluded_ranges() {
+        // The page in the middle of the second run has been left out..
+        let parser = KernelDumpParser::from_bytes(dump(&[2, 4, 6], 0)).unwrap();
+        assert_eq!(parser.excluded_ranges(), vec![(
+            Gpa::new(0x5_000)..Gpa::new(0x6_000),
+            ExclusionReason::Filtered
+        )]);
+        assert!(read(&parser, 0x6_000).is_ok());
+        assert!(matches!(
+            read(&parser, 0x5_008),
+            Err(KdmpParserError::AddrTranslation(
+                AddrTranslationError::Excluded(gpa, ExclusionReason::Filtered)
+            )) if gpa == Gpa::new(0x5_008)
+        ));
+
+        // ..while the memory that isn't RAM is missing.
+        assert!(matches!(
+            read(&parser, 0x3_000),
+            Err(KdmpParserError::AddrTranslation(
+                AddrTranslationError::Phys(_)
+            ))
+        ));
+
+        // The pages past the last one didn't fit in a dump file that was too
+        // small.
+        let parser = KernelDumpParser::from_bytes(dump(&[4], 0b100)).unwrap();
+        assert_eq!(parser.excluded_ranges(), vec![
+            (
+                Gpa::new(0x2_000)..Gpa::new(0x3_000),
+                ExclusionReason::Filtered
+            ),
+            (
+                Gpa::new(0x5_000)..Gpa::new(0x7_000),
+                ExclusionReason::InsufficientDumpfileSize
+            ),
+        ]);
+    }
+
+    #[test]
+    fn missing_ranges() {
+        let missing = |parser: &KernelDumpParser| {
+            parser
+                .missing_ranges()
+                .map(|range| (range.gpa.start.u64()..range.gpa.end.u64(), range.reason))
+                .collect::<Vec<_>>()
+        };
+
+        // The holes of a complete dump are in its runs..
+        let parser = KernelDumpParser::from_bytes(dump(&[2, 4, 6], 0)).unwrap();
+        assert_eq!(missing(&parser), [(
+            0x5_000..0x6_000,
+            MissingReason::Filtered
+        )]);
+        let parser = KernelDumpParser::from_bytes(dump(&[4], 0b100)).unwrap();
+        assert_eq!(missing(&parser), [
+            (0x2_000..0x3_000, MissingReason::Filtered),
+            (0x5_000..0x7_000, MissingReason::Truncated)
+        ]);
+
+        // ..while a summary dump doesn't have a descriptor, and leaves out
+        // everything but the kernel memory.
+        let parser = KernelDumpParser::from_bytes(
+            DumpBuilder::new()
+                .write_phys(0x1_000, b"kernel")
+                .write_phys(0x10_000, b"kernel")
+                .build(),
+        )
+        .unwrap();
+        let missing = missing(&parser);
+        assert_eq!(missing[0], (0..0x1_000, MissingReason::OutOfScope));
+        assert!(missing
+            .iter()
+            .all(|(range, reason)| *reason == MissingReason::OutOfScope
+                && !range.contains(&0x10_000)));
+        assert_eq!(
+            missing.last().unwrap().0.end,
+            parser.max_physical_address().unwrap().u64() + 1
+        );
+    }
+}
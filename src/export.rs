@@ -0,0 +1,392 @@
+//! This contains what is needed to parse the export table of the modules
+//! mapped in memory, and to symbolize addresses with it.
+use std::sync::Arc;
+
+use crate::error::Result;
+use crate::gxa::Gxa;
+use crate::module::ModuleEntry;
+use crate::pe::{u16_at, u32_at, IMAGE_DIRECTORY_ENTRY_EXPORT};
+use crate::{Gva, KdmpParserError, KernelDumpParser};
+
+/// ```text
+/// kd> dt nt!_IMAGE_EXPORT_DIRECTORY
+///    +0x00c Name             : Uint4B
+///    +0x010 Base             : Uint4B
+///    +0x014 NumberOfFunctions : Uint4B
+///    +0x018 NumberOfNames    : Uint4B
+///    +0x01c AddressOfFunctions : Uint4B
+///    +0x020 AddressOfNames   : Uint4B
+///    +0x024 AddressOfNameOrdinals : Uint4B
+/// ```
+const EXPORT_DIRECTORY_NAME: usize = 0xc;
+const EXPORT_DIRECTORY_BASE: usize = 0x10;
+const EXPORT_DIRECTORY_NUMBER_OF_FUNCTIONS: usize = 0x14;
+const EXPORT_DIRECTORY_NUMBER_OF_NAMES: usize = 0x18;
+const EXPORT_DIRECTORY_ADDRESS_OF_FUNCTIONS: usize = 0x1c;
+const EXPORT_DIRECTORY_ADDRESS_OF_NAMES: usize = 0x20;
+const EXPORT_DIRECTORY_ADDRESS_OF_NAME_ORDINALS: usize = 0x24;
+
+/// Maximum number of exports we'll read off a module; ordinals are 16-bit.
+const MAX_EXPORTS: u32 = 0x1_0000;
+
+/// Maximum length of an export name, or of a forwarder.
+const MAX_NAME_LEN: usize = 0x200;
+
+/// An export of a module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Export {
+    /// Name of the export; exports that only have an ordinal are named
+    /// `#ordinal`.
+    pub name: String,
+    /// Ordinal of the export.
+    pub ordinal: u16,
+    /// Address of the export. For a forwarded export, this is where its
+    /// forwarder string is.
+    pub address: Gva,
+    /// The export this one is forwarded to, like `NTDLL.RtlAllocateHeap`.
+    pub forwarder: Option<String>,
+}
+
+/// The export directory of an image; it usually also has the arrays and the
+/// names it refers to.
+struct ExportDirectory {
+    base: Gva,
+    rva: u32,
+    data: Vec<u8>,
+}
+
+impl ExportDirectory {
+    /// Does `rva` point inside of the export directory?
+    fn contains(&self, rva: u32) -> bool {
+        rva >= self.rva && u64::from(rva) < u64::from(self.rva) + self.data.len() as u64
+    }
+
+    /// Get `len` bytes at `rva`; from the export directory if they're inside of
+    /// it, or read from the image.
+    fn read(&self, parser: &KernelDumpParser, rva: u32, len: u32) -> Result<Vec<u8>> {
+        let start = rva.wrapping_sub(self.rva) as usize;
+        if self.contains(rva) {
+            if let Some(data) = self.data.get(start..start.saturating_add(len as usize)) {
+                return Ok(data.to_vec());
+            }
+        }
+
+        parser.pe_read(self.base, rva, len)
+    }
+
+    /// Read the NULL terminated string at `rva`.
+    fn cstr(&self, parser: &KernelDumpParser, rva: u32) -> Result<String> {
+        let data = if self.contains(rva) {
+            let start = (rva - self.rva) as usize;
+            let end = self.data.len().min(start + MAX_NAME_LEN);
+
+            self.data[start..end].to_vec()
+        } else {
+            let addr = self
+                .base
+                .u64()
+                .checked_add(rva.into())
+                .map(Gva::new)
+                .ok_or(KdmpParserError::Overflow("export name"))?;
+            let mut data = vec![0; MAX_NAME_LEN];
+            let len = parser.virt_read(addr, &mut data)?;
+            data.truncate(len);
+
+            data
+        };
+
+        let len = data
+            .iter()
+            .position(|&c| c == 0)
+            .ok_or(KdmpParserError::InvalidData("unterminated export name"))?;
+
+        Ok(String::from_utf8_lossy(&data[..len]).into_owned())
+    }
+}
+
+/// Decode an array of `u32`s.
+fn u32s(data: &[u8]) -> Vec<u32> {
+    data.chunks_exact(4)
+        .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+        .collect()
+}
+
+/// The name a module goes by in symbols: its file name without extension,
+/// and `nt` for the kernel.
+pub(crate) fn module_short_name(name: &str) -> &str {
+    let file_name = name.rsplit(['\\', '/']).next().unwrap_or(name);
+    let stem = file_name
+        .rsplit_once('.')
+        .map_or(file_name, |(stem, _)| stem);
+
+    let lowercase = stem.to_ascii_lowercase();
+    if lowercase.starts_with("ntoskrnl") || lowercase.starts_with("ntkrnl") {
+        "nt"
+    } else {
+        stem
+    }
+}
+
+/// Format `gva` relative to the export that precedes it in `exports`, which is
+/// sorted by address.
+fn symbolize(module: &ModuleEntry, exports: &[Export], gva: Gva) -> String {
+    let name = module_short_name(&module.name);
+    // Forwarded exports aren't code, so they're skipped.
+    let idx = exports.partition_point(|export| export.address <= gva);
+    let preceding = exports[..idx]
+        .iter()
+        .rev()
+        .find(|export| export.forwarder.is_none());
+    let (symbol, offset) = match preceding {
+        Some(export) => (
+            format!("{name}!{}", export.name),
+            gva.u64() - export.address.u64(),
+        ),
+        None => (name.to_string(), gva.u64() - module.at.start.u64()),
+    };
+
+    match offset {
+        0 => symbol,
+        offset => format!("{symbol}+{offset:#x}"),
+    }
+}
+
+impl KernelDumpParser {
+    /// Parse the export table of a module.
+    ///
+    /// The export tables are cached, so parsing the one of a module again is
+    /// cheap.
+    pub fn module_exports(&self, module: &ModuleEntry) -> Result<Vec<Export>> {
+        Ok(self.cached_module_exports(module)?.to_vec())
+    }
+
+    /// Symbolize `gva` using the exports of the module it belongs to, like
+    /// `nt!KeBugCheckEx+0x12`. If no export precedes `gva`, it is formatted
+    /// relative to the module, like `nt+0x1337`. The annotation that contains
+    /// `gva` wins over the modules, like `stage2+0x10`.
+    pub fn symbolize_with_exports(&self, gva: Gva) -> Option<String> {
+        // The annotations win over the modules.
+        if let Some((at, label)) = self.find_annotation(gva) {
+            return Some(match gva.u64() - at.start.u64() {
+                0 => label.to_string(),
+                offset => format!("{label}+{offset:#x}"),
+            });
+        }
+
+        let module = self.find_module_entry(gva)?;
+        let exports = self.cached_module_exports(module).unwrap_or_default();
+
+        Some(symbolize(module, &exports, gva))
+    }
+
+    /// Symbolize `gva` like [`KernelDumpParser::symbolize_with_exports`], but
+    /// with the symbols of the PDB of its module when one is loaded (with
+    /// `load_pdb` and the `pdb` feature), which name the functions that
+    /// aren't exported, like `nt!KiSwapContext+0x76`.
+    pub fn symbolize(&self, gva: Gva) -> Option<String> {
+        #[cfg(feature = "pdb")]
+        if self.find_annotation(gva).is_none() {
+            if let Some(symbol) = self
+                .find_module_entry(gva)
+                .and_then(|module| self.symbolize_with_pdb(module, gva))
+            {
+                return Some(symbol);
+            }
+        }
+
+        self.symbolize_with_exports(gva)
+    }
+
+    /// Resolve `symbol`, like `nt!PsLoadedModuleList`, to its address. The
+    /// module is named like in the symbols [`KernelDumpParser::symbolize`]
+    /// returns; the symbol comes from the PDB of the module when one is
+    /// loaded, and from its exports otherwise. It is a
+    /// [`KdmpParserError::UnknownSymbol`] when it can't be found.
+    pub fn resolve_symbol(&self, symbol: &str) -> Result<Gva> {
+        let unknown = || KdmpParserError::UnknownSymbol(symbol.to_string());
+        let (module_name, name) = symbol.split_once('!').ok_or_else(unknown)?;
+        let module = self.module_named(module_name).ok_or_else(unknown)?;
+        #[cfg(feature = "pdb")]
+        if let Some(gva) = self.resolve_with_pdb(module, name) {
+            return Ok(gva);
+        }
+
+        self.cached_module_exports(module)
+            .unwrap_or_default()
+            .iter()
+            .find(|export| export.forwarder.is_none() && export.name == name)
+            .map(|export| export.address)
+            .ok_or_else(unknown)
+    }
+
+    /// Find the user or kernel module named `name`, like `nt` or
+    /// `ntoskrnl.exe`; see [`module_short_name`].
+    pub(crate) fn module_named(&self, name: &str) -> Option<&ModuleEntry> {
+        let name = module_short_name(name);
+
+        self.modules()
+            .find(|module| module_short_name(&module.name).eq_ignore_ascii_case(name))
+    }
+
+    /// Get the export table of a module, sorted by address, out of the cache
+    /// or parse it.
+    fn cached_module_exports(&self, module: &ModuleEntry) -> Result<Arc<[Export]>> {
+        let key = module.at.start;
+        if let Some(exports) = self.exports.lock().unwrap().get(&key) {
+            return Ok(Arc::clone(exports));
+        }
+
+        let mut exports = self.parse_module_exports(module)?;
+        exports.sort_by_key(|export| export.address);
+        let exports = Arc::<[Export]>::from(exports);
+        self.exports
+            .lock()
+            .unwrap()
+            .insert(key, Arc::clone(&exports));
+
+        Ok(exports)
+    }
+
+    /// Read the export directory of the image mapped at `base`.
+    fn export_directory(&self, base: Gva) -> Result<ExportDirectory> {
+        let headers = self.pe_headers(base)?;
+        let directory = headers
+            .data_directory(IMAGE_DIRECTORY_ENTRY_EXPORT)
+            .ok_or(KdmpParserError::NotFound("the export directory"))?;
+
+        if directory.rva.saturating_add(directory.size) > headers.size_of_image {
+            return Err(KdmpParserError::InvalidData("invalid export directory"));
+        }
+
+        Ok(ExportDirectory {
+            base,
+            rva: directory.rva,
+            data: self.pe_read(base, directory.rva, directory.size)?,
+        })
+    }
+
+    /// Get the name the image mapped at `base` exports itself as, like
+    /// `ntoskrnl.exe`.
+    pub(crate) fn export_dll_name(&self, base: Gva) -> Result<String> {
+        let directory = self.export_directory(base)?;
+        let name = u32_at(&directory.data, EXPORT_DIRECTORY_NAME)?;
+
+        directory.cstr(self, name)
+    }
+
+    fn parse_module_exports(&self, module: &ModuleEntry) -> Result<Vec<Export>> {
+        let base = module.at.start;
+        let directory = self.export_directory(base)?;
+        let data = &directory.data;
+        let ordinal_base = u32_at(data, EXPORT_DIRECTORY_BASE)?;
+        let number_of_functions = u32_at(data, EXPORT_DIRECTORY_NUMBER_OF_FUNCTIONS)?;
+        let number_of_names = u32_at(data, EXPORT_DIRECTORY_NUMBER_OF_NAMES)?;
+        if number_of_functions > MAX_EXPORTS || number_of_names > number_of_functions {
+            return Err(KdmpParserError::InvalidData("invalid export counts"));
+        }
+
+        let functions = u32s(&directory.read(
+            self,
+            u32_at(data, EXPORT_DIRECTORY_ADDRESS_OF_FUNCTIONS)?,
+            number_of_functions * 4,
+        )?);
+        let names = u32s(&directory.read(
+            self,
+            u32_at(data, EXPORT_DIRECTORY_ADDRESS_OF_NAMES)?,
+            number_of_names * 4,
+        )?);
+        let name_ordinals = directory.read(
+            self,
+            u32_at(data, EXPORT_DIRECTORY_ADDRESS_OF_NAME_ORDINALS)?,
+            number_of_names * 2,
+        )?;
+
+        // Names are indexed by the index of the function they export.
+        let mut function_names = vec![None; functions.len()];
+        for (idx, &name) in names.iter().enumerate() {
+            let function = usize::from(u16_at(&name_ordinals, idx * 2)?);
+            let slot = function_names
+                .get_mut(function)
+                .ok_or(KdmpParserError::InvalidData("invalid export name ordinal"))?;
+            *slot = Some(directory.cstr(self, name)?);
+        }
+
+        let mut exports = Vec::with_capacity(functions.len());
+        for (idx, (&rva, name)) in functions.iter().zip(function_names).enumerate() {
+            // Unused slots of the table are zero.
+            if rva == 0 {
+                continue;
+            }
+
+            let ordinal = ordinal_base.wrapping_add(idx as u32) as u16;
+            let forwarder = if directory.contains(rva) {
+                Some(directory.cstr(self, rva)?)
+            } else {
+                None
+            };
+
+            exports.push(Export {
+                name: name.unwrap_or_else(|| format!("#{ordinal}")),
+                ordinal,
+                address: Gva::new(base.u64().wrapping_add(rva.into())),
+                forwarder,
+            });
+        }
+
+        Ok(exports)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn export(name: &str, address: u64) -> Export {
+        Export {
+            name: name.into(),
+            ordinal: 0,
+            address: Gva::new(address),
+            forwarder: None,
+        }
+    }
+
+    #[test]
+    fn short_names() {
+        assert_eq!(
+            module_short_name("\\SystemRoot\\system32\\ntoskrnl.exe"),
+            "nt"
+        );
+        assert_eq!(module_short_name("ntkrnlmp.exe"), "nt");
+        assert_eq!(
+            module_short_name("C:\\Windows\\System32\\KERNEL32.DLL"),
+            "KERNEL32"
+        );
+        assert_eq!(module_short_name("hal"), "hal");
+    }
+
+    #[test]
+    fn symbolize_nearest() {
+        let module = ModuleEntry::new(
+            Gva::new(0x1_000)..Gva::new(0x9_000),
+            "\\SystemRoot\\system32\\ntoskrnl.exe",
+        );
+        let exports = [
+            export("KeBugCheck", 0x2_000),
+            export("KeBugCheckEx", 0x2_100),
+        ];
+
+        assert_eq!(symbolize(&module, &exports, Gva::new(0x1_337)), "nt+0x337");
+        assert_eq!(
+            symbolize(&module, &exports, Gva::new(0x2_000)),
+            "nt!KeBugCheck"
+        );
+        assert_eq!(
+            symbolize(&module, &exports, Gva::new(0x2_0ff)),
+            "nt!KeBugCheck+0xff"
+        );
+        assert_eq!(
+            symbolize(&module, &exports, Gva::new(0x2_112)),
+            "nt!KeBugCheckEx+0x12"
+        );
+    }
+}
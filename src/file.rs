@@ -0,0 +1,564 @@
+//! This contains what is needed to find the `nt!_FILE_OBJECT`s that were
+//! alive when the dump was taken, and to recover the content of the files that
+//! were cached in memory.
+//!
+//! File objects are found by scanning physical memory for pool allocations
+//! tagged `File`. The content of a file can live in two places: the views of
+//! the cache manager (the `_VACB`s of its `_SHARED_CACHE_MAP`) and the
+//! prototype PTEs of its data section (the `_SUBSECTION`s of its
+//! `_CONTROL_AREA`). Both are used, and the pages that can't be found in either
+//! are zero-filled.
+use std::cmp::min;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::ops::Range;
+
+use crate::error::Result;
+use crate::gxa::Gxa;
+use crate::structs::{Page, UnicodeString};
+use crate::{Gpa, Gva, KdmpParserError, KernelDumpParser, Pxe};
+
+const FILE_OBJECT: &str = "_FILE_OBJECT";
+const SECTION_OBJECT_POINTERS: &str = "_SECTION_OBJECT_POINTERS";
+const SHARED_CACHE_MAP: &str = "_SHARED_CACHE_MAP";
+const VACB: &str = "_VACB";
+const SUBSECTION: &str = "_SUBSECTION";
+
+/// Pool tag of the file objects. The high bit of the tag (the 'protected' bit)
+/// might be set.
+const POOL_TAG_FILE: &[u8; 4] = b"File";
+
+/// ```text
+/// kd> dt nt!_POOL_HEADER
+///    +0x002 BlockSize        : Pos 0, 8 Bits
+///    +0x004 PoolTag          : Uint4B
+/// ```
+const POOL_HEADER_SIZE: usize = 0x10;
+const POOL_HEADER_BLOCK_SIZE: usize = 0x2;
+const POOL_HEADER_POOL_TAG: usize = 0x4;
+
+/// Pool allocations are made of blocks of this size.
+const POOL_BLOCK_SIZE: usize = 0x10;
+
+/// `_FILE_OBJECT.Type` (`IO_TYPE_FILE`).
+const IO_TYPE_FILE: u64 = 5;
+
+/// `_SHARED_CACHE_MAP.NodeTypeCode` (`CACHE_NTC_SHARED_CACHE_MAP`).
+const CACHE_NTC_SHARED_CACHE_MAP: u64 = 0x2ff;
+
+/// Every `_VACB` maps 256KB of a file. The `_VACB` pointers are stored in a
+/// flat array for sections up to 32MB, and in a tree of 128 entries blocks for
+/// larger sections.
+const VACB_OFFSET_SHIFT: u32 = 18;
+const VACB_MAPPING_GRANULARITY: u64 = 1 << VACB_OFFSET_SHIFT;
+const VACB_LEVEL_SHIFT: u32 = 7;
+const VACB_LEVEL_MASK: u64 = (1 << VACB_LEVEL_SHIFT) - 1;
+const VACB_SIZE_OF_FIRST_LEVEL: u64 = 1 << (VACB_OFFSET_SHIFT + VACB_LEVEL_SHIFT);
+
+/// `_SUBSECTION.StartingSector` is in units of sectors.
+const SECTOR_SIZE: u64 = 0x200;
+
+/// Bit of a PTE that is set when it is a prototype PTE.
+const PTE_PROTOTYPE: u64 = 1 << 10;
+
+/// Maximum number of subsections we'll walk in a control area.
+const MAX_SUBSECTIONS: usize = 0x10_000;
+
+/// A file object found in the dump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileObject {
+    /// Address of the `_FILE_OBJECT`.
+    pub address: Gva,
+    /// Name of the file, if it could be read.
+    pub name: Option<String>,
+    /// Does the file have cached content that
+    /// [`KernelDumpParser::file_cache_extract`] could recover?
+    pub cached: bool,
+}
+
+/// The outcome of a [`KernelDumpParser::file_cache_extract`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ExtractReport {
+    /// Size of the file, which is how many bytes were written.
+    pub size: u64,
+    /// Amount of bytes that were recovered.
+    pub recovered: u64,
+    /// Parts of the file that couldn't be recovered and that were
+    /// zero-filled.
+    pub missing: Vec<Range<u64>>,
+}
+
+impl ExtractReport {
+    /// Ratio of the file that was recovered, between `0.0` and `1.0`.
+    pub fn coverage(&self) -> f64 {
+        match self.size {
+            0 => 1.0,
+            size => self.recovered as f64 / size as f64,
+        }
+    }
+
+    /// Record a part of the file that couldn't be recovered; it's merged with
+    /// the previous one if they're contiguous.
+    fn push_missing(&mut self, range: Range<u64>) {
+        match self.missing.last_mut() {
+            Some(last) if last.end == range.start => last.end = range.end,
+            _ => self.missing.push(range),
+        }
+    }
+}
+
+/// The cache manager state of a file.
+struct CacheMap {
+    /// Address of the `_SHARED_CACHE_MAP`.
+    address: Gva,
+    file_size: u64,
+    section_size: u64,
+    /// Address of the array (or of the tree) of `_VACB` pointers.
+    vacbs: Gva,
+}
+
+/// Is this an error caused by memory that isn't in the dump?
+fn is_missing(e: &KdmpParserError) -> bool {
+    matches!(
+        e,
+        KdmpParserError::AddrTranslation(..)
+            | KdmpParserError::PartialVirtRead
+            | KdmpParserError::PartialPhysRead
+    )
+}
+
+/// Turn the errors caused by memory that isn't in the dump into `None`.
+fn present<T>(res: Result<T>) -> Result<Option<T>> {
+    match res {
+        Ok(value) => Ok(Some(value)),
+        Err(e) if is_missing(&e) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Is this the tag of a file object pool allocation?
+fn is_file_pool_tag(tag: &[u8]) -> bool {
+    tag[..3] == POOL_TAG_FILE[..3] && tag[3] & 0x7f == POOL_TAG_FILE[3]
+}
+
+/// Get the number of levels of the `_VACB` tree of a section; `0` means that
+/// the `_VACB` pointers are in a flat array.
+fn vacb_levels(section_size: u64) -> u32 {
+    if section_size <= VACB_SIZE_OF_FIRST_LEVEL {
+        return 0;
+    }
+
+    let mut levels = 0;
+    let mut blocks = (section_size - 1) >> (VACB_OFFSET_SHIFT + VACB_LEVEL_SHIFT);
+    while blocks != 0 {
+        levels += 1;
+        blocks >>= VACB_LEVEL_SHIFT;
+    }
+
+    levels
+}
+
+/// Compute the address of the entry `index` of an array of pointers.
+fn ptr_entry(base: Gva, index: u64) -> Result<Gva> {
+    index
+        .checked_mul(8)
+        .and_then(|offset| base.u64().checked_add(offset))
+        .map(Gva::new)
+        .ok_or(KdmpParserError::Overflow("pointer array entry"))
+}
+
+impl KernelDumpParser {
+    /// Find the file objects that were alive when the dump was taken.
+    ///
+    /// Finding them requires scanning physical memory, so this can take a
+    /// little while on large dumps.
+    pub fn file_objects(&self) -> Result<Vec<FileObject>> {
+        let mut page = vec![0; Page::size() as usize];
+        let mut addresses = BTreeSet::new();
+        for (gpa, _) in self.physmem() {
+            if self.phys_read(gpa, &mut page)? != page.len() {
+                continue;
+            }
+
+            for offset in (0..page.len()).step_by(POOL_BLOCK_SIZE) {
+                let tag = offset + POOL_HEADER_POOL_TAG;
+                if !is_file_pool_tag(&page[tag..tag + POOL_TAG_FILE.len()]) {
+                    continue;
+                }
+
+                // The `_FILE_OBJECT` is somewhere in the allocation, after its
+                // `_OBJECT_HEADER` and the optional headers.
+                let block_size = usize::from(page[offset + POOL_HEADER_BLOCK_SIZE]);
+                let end = min(offset + block_size * POOL_BLOCK_SIZE, page.len());
+                for body in (offset + POOL_HEADER_SIZE..end).step_by(POOL_BLOCK_SIZE) {
+                    let body_gpa = Gpa::new(gpa.u64() + body as u64);
+                    if let Some(address) = self.file_object_address(&page[body..], body_gpa)? {
+                        addresses.insert(address);
+                        break;
+                    }
+                }
+            }
+        }
+
+        addresses
+            .into_iter()
+            .map(|address| self.file_object(address))
+            .collect()
+    }
+
+    /// Recover the content of a file from the memory that was used to cache
+    /// it, and write it to `writer`. The parts of the file that can't be
+    /// recovered are zero-filled and listed in the [`ExtractReport`].
+    pub fn file_cache_extract(
+        &self,
+        file_object: Gva,
+        mut writer: impl Write,
+    ) -> Result<ExtractReport> {
+        if self.read_field(file_object, FILE_OBJECT, "Type")? != IO_TYPE_FILE {
+            return Err(KdmpParserError::InvalidData("not a file object"));
+        }
+
+        let (cache, data_section) = self.file_sections(file_object)?;
+        if cache.is_none() && data_section.is_none() {
+            return Err(KdmpParserError::NotFound("the cached content of the file"));
+        }
+
+        let cache = match cache {
+            Some(cache) => present(self.cache_map(cache))?.flatten(),
+            None => None,
+        };
+
+        let ptes = match data_section {
+            Some(control_area) => self.subsection_ptes(control_area)?,
+            None => BTreeMap::new(),
+        };
+
+        // The cache manager knows the size of the file; otherwise, ask the file system,
+        // and as a last resort use what the data section covers.
+        let size = match &cache {
+            Some(cache) => Some(cache.file_size),
+            None => self.fcb_file_size(file_object)?,
+        }
+        .or_else(|| {
+            ptes.last_key_value()
+                .map(|(&page, _)| (page + 1) * Page::size())
+        })
+        .unwrap_or_default();
+
+        let mut report = ExtractReport {
+            size,
+            ..Default::default()
+        };
+
+        let mut page = vec![0; Page::size() as usize];
+        for offset in (0..size).step_by(page.len()) {
+            let len = min(size - offset, Page::size());
+            let found = self.cached_page(cache.as_ref(), offset, &mut page)?
+                || self.section_page(&ptes, offset, &mut page)?;
+
+            if found {
+                report.recovered += len;
+            } else {
+                page.fill(0);
+                report.push_missing(offset..offset + len);
+            }
+
+            writer.write_all(&page[..len as usize])?;
+        }
+
+        Ok(report)
+    }
+
+    /// Check if the data at `gpa` looks like a `_FILE_OBJECT` and find its
+    /// virtual address; `data` is its content until the end of its physical
+    /// page.
+    ///
+    /// The `LIST_ENTRY`s of a `_FILE_OBJECT` are usually empty, which means
+    /// they point to themselves and give us its virtual address.
+    fn file_object_address(&self, data: &[u8], gpa: Gpa) -> Result<Option<Gva>> {
+        let profile = self.profile();
+        let read_u16 = |offset: u64| {
+            data.get(offset as usize..offset as usize + 2)
+                .map(|b| u64::from(u16::from_le_bytes([b[0], b[1]])))
+        };
+
+        if read_u16(profile.offset(FILE_OBJECT, "Type")?) != Some(IO_TYPE_FILE)
+            || read_u16(profile.offset(FILE_OBJECT, "Size")?) != Some(profile.size(FILE_OBJECT)?)
+        {
+            return Ok(None);
+        }
+
+        for list in ["Event.Header.WaitListHead", "IrpList"] {
+            let offset = profile.offset(FILE_OBJECT, list)?;
+            let Some(entry) = data.get(offset as usize..offset as usize + 16) else {
+                continue;
+            };
+
+            let flink = u64::from_le_bytes(entry[..8].try_into().unwrap());
+            let blink = u64::from_le_bytes(entry[8..].try_into().unwrap());
+            let Some(address) = flink.checked_sub(offset).map(Gva::new) else {
+                continue;
+            };
+
+            if flink != blink {
+                continue;
+            }
+
+            match self.virt_translate(address) {
+                Ok(translated) if translated == gpa => return Ok(Some(address)),
+                Ok(_) | Err(KdmpParserError::AddrTranslation(..)) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Describe the file object at `address`.
+    fn file_object(&self, address: Gva) -> Result<FileObject> {
+        let name = self.field_addr(address, FILE_OBJECT, "FileName")?;
+        let name = match present(self.virt_read_struct::<UnicodeString>(name))? {
+            Some(name) => match self.try_virt_read_unicode_string(&name) {
+                Ok(name) => name,
+                Err(KdmpParserError::InvalidUnicodeString | KdmpParserError::Utf16(..)) => None,
+                Err(e) if is_missing(&e) => None,
+                Err(e) => return Err(e),
+            },
+            None => None,
+        };
+
+        let (cache, data_section) = self.file_sections(address)?;
+
+        Ok(FileObject {
+            address,
+            name,
+            cached: cache.is_some() || data_section.is_some(),
+        })
+    }
+
+    /// Get the `_SHARED_CACHE_MAP` and the data section `_CONTROL_AREA` of a
+    /// file, if it has them.
+    fn file_sections(&self, file_object: Gva) -> Result<(Option<Gva>, Option<Gva>)> {
+        let Some(pointers) =
+            present(self.read_field(file_object, FILE_OBJECT, "SectionObjectPointer"))?
+        else {
+            return Ok((None, None));
+        };
+
+        if pointers == 0 {
+            return Ok((None, None));
+        }
+
+        let pointer = |field| -> Result<Option<Gva>> {
+            Ok(
+                present(self.read_field(pointers.into(), SECTION_OBJECT_POINTERS, field))?
+                    .filter(|&pointer| pointer != 0)
+                    .map(Gva::new),
+            )
+        };
+
+        Ok((pointer("SharedCacheMap")?, pointer("DataSectionObject")?))
+    }
+
+    /// Read a `_SHARED_CACHE_MAP`.
+    fn cache_map(&self, address: Gva) -> Result<Option<CacheMap>> {
+        if self.read_field(address, SHARED_CACHE_MAP, "NodeTypeCode")? != CACHE_NTC_SHARED_CACHE_MAP
+        {
+            return Ok(None);
+        }
+
+        Ok(Some(CacheMap {
+            address,
+            file_size: self.read_field(address, SHARED_CACHE_MAP, "FileSize")?,
+            section_size: self.read_field(address, SHARED_CACHE_MAP, "SectionSize")?,
+            vacbs: self.read_field(address, SHARED_CACHE_MAP, "Vacbs")?.into(),
+        }))
+    }
+
+    /// Get the size of a file from the `_FSRTL_COMMON_FCB_HEADER` of the file
+    /// system.
+    fn fcb_file_size(&self, file_object: Gva) -> Result<Option<u64>> {
+        let Some(fcb) = present(self.read_field(file_object, FILE_OBJECT, "FsContext"))? else {
+            return Ok(None);
+        };
+
+        if fcb == 0 {
+            return Ok(None);
+        }
+
+        present(self.read_field(fcb.into(), "_FSRTL_COMMON_FCB_HEADER", "FileSize"))
+    }
+
+    /// Find the `_VACB` that maps the file at `offset`.
+    fn vacb(&self, cache: &CacheMap, offset: u64) -> Result<Option<Gva>> {
+        let levels = vacb_levels(cache.section_size);
+        let mut table = cache.vacbs;
+        for level in (1..=levels).rev() {
+            let index =
+                (offset >> (VACB_OFFSET_SHIFT + VACB_LEVEL_SHIFT * level)) & VACB_LEVEL_MASK;
+            table = self.virt_read_ptr(ptr_entry(table, index)?)?;
+            if table.u64() == 0 {
+                return Ok(None);
+            }
+        }
+
+        let index = match levels {
+            0 => offset >> VACB_OFFSET_SHIFT,
+            _ => (offset >> VACB_OFFSET_SHIFT) & VACB_LEVEL_MASK,
+        };
+
+        let vacb = self.virt_read_ptr(ptr_entry(table, index)?)?;
+        if vacb.u64() == 0 {
+            return Ok(None);
+        }
+
+        // Make sure the `_VACB` maps this part of this file; the low bits of the
+        // offset are used for other things.
+        let view = offset & !(VACB_MAPPING_GRANULARITY - 1);
+        let file_offset = self.read_field(vacb, VACB, "FileOffset")?;
+        if self.read_field(vacb, VACB, "SharedCacheMap")? != cache.address.u64()
+            || file_offset & !(VACB_MAPPING_GRANULARITY - 1) != view
+        {
+            return Ok(None);
+        }
+
+        Ok(Some(vacb))
+    }
+
+    /// Read the page of the file at `offset` from the views of the cache
+    /// manager.
+    fn cached_page(&self, cache: Option<&CacheMap>, offset: u64, page: &mut [u8]) -> Result<bool> {
+        let Some(cache) = cache else {
+            return Ok(false);
+        };
+
+        if offset >= cache.section_size {
+            return Ok(false);
+        }
+
+        let Some(vacb) = present(self.vacb(cache, offset))?.flatten() else {
+            return Ok(false);
+        };
+
+        let Some(base) = present(self.read_field(vacb, VACB, "BaseAddress"))? else {
+            return Ok(false);
+        };
+
+        let Some(addr) = base.checked_add(offset % VACB_MAPPING_GRANULARITY) else {
+            return Ok(false);
+        };
+
+        Ok(present(self.virt_read_exact(addr, page))?.is_some())
+    }
+
+    /// Map the pages of a file to the prototype PTEs of the subsections of its
+    /// data section.
+    fn subsection_ptes(&self, control_area: Gva) -> Result<BTreeMap<u64, Gva>> {
+        let mut ptes = BTreeMap::new();
+        let mut subsection = control_area
+            .u64()
+            .checked_add(self.profile().size("_CONTROL_AREA")?)
+            .map(Gva::new)
+            .ok_or(KdmpParserError::Overflow("subsection"))?;
+
+        for _ in 0..MAX_SUBSECTIONS {
+            // If a subsection can't be read, we keep what we've found so far.
+            let read = |field| present(self.read_field(subsection, SUBSECTION, field));
+            let Some(owner) = read("ControlArea")? else {
+                break;
+            };
+
+            if owner != control_area.u64() {
+                break;
+            }
+
+            let (Some(base), Some(count), Some(sector), Some(next)) = (
+                read("SubsectionBase")?,
+                read("PtesInSubsection")?,
+                read("StartingSector")?,
+                read("NextSubsection")?,
+            ) else {
+                break;
+            };
+
+            let first = sector * SECTOR_SIZE / Page::size();
+            for index in 0..count {
+                ptes.insert(first + index, ptr_entry(base.into(), index)?);
+            }
+
+            if next == 0 {
+                break;
+            }
+
+            subsection = next.into();
+        }
+
+        Ok(ptes)
+    }
+
+    /// Read the page of the file at `offset` from the prototype PTEs of its
+    /// data section. The page is read if the PTE is valid or in transition.
+    fn section_page(
+        &self,
+        ptes: &BTreeMap<u64, Gva>,
+        offset: u64,
+        page: &mut [u8],
+    ) -> Result<bool> {
+        let Some(&pte) = ptes.get(&(offset / Page::size())) else {
+            return Ok(false);
+        };
+
+        let Some(raw) = present(self.virt_read_struct::<u64>(pte))? else {
+            return Ok(false);
+        };
+
+        let pxe = Pxe::from(raw);
+        let in_transition = pxe.transition() && raw & PTE_PROTOTYPE == 0;
+        if !pxe.present() && !in_transition {
+            return Ok(false);
+        }
+
+        Ok(present(self.phys_read_exact(pxe.pfn.gpa(), page))?.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels() {
+        assert_eq!(vacb_levels(0), 0);
+        assert_eq!(vacb_levels(0x10_0000), 0);
+        assert_eq!(vacb_levels(VACB_SIZE_OF_FIRST_LEVEL), 0);
+        assert_eq!(vacb_levels(VACB_SIZE_OF_FIRST_LEVEL + 1), 1);
+        assert_eq!(vacb_levels(VACB_SIZE_OF_FIRST_LEVEL << VACB_LEVEL_SHIFT), 1);
+        assert_eq!(
+            vacb_levels((VACB_SIZE_OF_FIRST_LEVEL << VACB_LEVEL_SHIFT) + 1),
+            2
+        );
+    }
+
+    #[test]
+    fn report() {
+        let mut report = ExtractReport {
+            size: 0x4_000,
+            recovered: 0x1_000,
+            ..Default::default()
+        };
+        report.push_missing(0x1_000..0x2_000);
+        report.push_missing(0x2_000..0x3_000);
+        report.push_missing(0x3_800..0x4_000);
+        assert_eq!(report.missing, vec![0x1_000..0x3_000, 0x3_800..0x4_000]);
+        assert_eq!(report.coverage(), 0.25);
+
+        assert_eq!(ExtractReport::default().coverage(), 1.0);
+    }
+
+    #[test]
+    fn pool_tag() {
+        assert!(is_file_pool_tag(b"File"));
+        assert!(is_file_pool_tag(b"Fil\xe5"));
+        assert!(!is_file_pool_tag(b"Filx"));
+    }
+}
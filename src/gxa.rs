@@ -13,8 +13,44 @@
 //! let page_aligned_gva = gva.page_align();
 //! let page_offset = gva.offset();
 //! ```
+//!
+//! # Conversions
+//!
+//! Both convert from and into a [`u64`], and the APIs of
+//! [`KernelDumpParser`] that read memory take an `impl Into<Gva>` (or an
+//! `impl Into<Gpa>`), so a [`u64`] can be passed as is:
+//!
+//! ```no_run
+//! # use kdmp_parser::KernelDumpParser;
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! let mut buffer = [0; 8];
+//! parser.virt_read_exact(0xfffff802_11223344, &mut buffer).unwrap();
+//! ```
+//!
+//! A [`Gpa`] doesn't convert into a [`Gva`] though, nor the other way around,
+//! so one can't be passed where the other is expected:
+//!
+//! ```compile_fail
+//! # use kdmp_parser::{Gpa, Gva};
+//! let gva: Gva = Gpa::new(0x1000).into();
+//! ```
+//!
+//! ```compile_fail
+//! # use kdmp_parser::{Gpa, KernelDumpParser};
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! parser.virt_read_exact(Gpa::new(0x1000), &mut [0; 8]).unwrap();
+//! ```
+//!
+//! ```compile_fail
+//! # use kdmp_parser::{Gva, KernelDumpParser};
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! parser.phys_read_exact(Gva::new(0x1000), &mut [0; 8]).unwrap();
+//! ```
+//!
+//! [`KernelDumpParser`]: crate::KernelDumpParser
 use std::fmt::Display;
-use std::ops::AddAssign;
+use std::marker::PhantomData;
+use std::ops::{AddAssign, Range};
 
 use crate::pxe::Pfn;
 use crate::structs::Page;
@@ -420,3 +456,227 @@ impl Display for Gva {
         write!(f, "Gva:{:#x}", self.0)
     }
 }
+
+/// A bunch of useful methods to manipulate ranges of addresses of any kind,
+/// like stepping through the pages they span without being off by a page.
+///
+/// # Examples
+///
+/// ```
+/// # use kdmp_parser::{Gxa, GxaRange, Gva};
+/// # fn main() {
+/// let range = Gva::new(0x1_ff0)..Gva::new(0x3_010);
+/// assert_eq!(range.len_bytes(), 0x1_020);
+/// assert_eq!(range.page_count(), 3);
+/// assert_eq!(range.pages().collect::<Vec<_>>(), [
+///     Gva::new(0x1_000),
+///     Gva::new(0x2_000),
+///     Gva::new(0x3_000)
+/// ]);
+/// # }
+/// ```
+pub trait GxaRange<T: Gxa> {
+    /// Number of bytes in the range; an inverted range is empty.
+    fn len_bytes(&self) -> u64;
+
+    /// The page-aligned addresses of the pages the range spans; the pages
+    /// the range starts and ends in are there once, even if the range only
+    /// covers a part of them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kdmp_parser::{Gxa, GxaRange, Gpa};
+    /// # fn main() {
+    /// let range = Gpa::new(0x1_000)..Gpa::new(0x1_001);
+    /// assert_eq!(range.pages().collect::<Vec<_>>(), [Gpa::new(0x1_000)]);
+    /// let empty = Gpa::new(0x1_000)..Gpa::new(0x1_000);
+    /// assert_eq!(empty.pages().count(), 0);
+    /// let top = Gpa::new(u64::MAX - 0x1_fff)..Gpa::new(u64::MAX);
+    /// assert_eq!(top.pages().last(), Some(Gpa::new(u64::MAX).page_align()));
+    /// assert_eq!(top.page_count(), 2);
+    /// # }
+    /// ```
+    fn pages(&self) -> RangePages<T>;
+
+    /// Number of pages the range spans; see [`GxaRange::pages`].
+    fn page_count(&self) -> u64;
+
+    /// Split the range in consecutive ranges of `size` bytes; the last one is
+    /// shorter if `size` doesn't divide the length of the range.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is 0.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kdmp_parser::{Gxa, GxaRange, Gva};
+    /// # fn main() {
+    /// let range = Gva::new(0x1_000)..Gva::new(0x1_500);
+    /// assert_eq!(range.chunks(0x200).collect::<Vec<_>>(), [
+    ///     Gva::new(0x1_000)..Gva::new(0x1_200),
+    ///     Gva::new(0x1_200)..Gva::new(0x1_400),
+    ///     Gva::new(0x1_400)..Gva::new(0x1_500)
+    /// ]);
+    /// # }
+    /// ```
+    fn chunks(&self, size: u64) -> RangeChunks<T>;
+
+    /// The addresses both ranges have, if any.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kdmp_parser::{Gxa, GxaRange, Gva};
+    /// # fn main() {
+    /// let range = Gva::new(0x1_000)..Gva::new(0x3_000);
+    /// assert_eq!(
+    ///     range.intersection(&(Gva::new(0x2_000)..Gva::new(0x4_000))),
+    ///     Some(Gva::new(0x2_000)..Gva::new(0x3_000))
+    /// );
+    /// assert_eq!(
+    ///     range.intersection(&(Gva::new(0x3_000)..Gva::new(0x4_000))),
+    ///     None
+    /// );
+    /// # }
+    /// ```
+    fn intersection(&self, other: &Range<T>) -> Option<Range<T>>;
+
+    /// Do both ranges have addresses in common?
+    fn overlaps(&self, other: &Range<T>) -> bool {
+        self.intersection(other).is_some()
+    }
+
+    /// Does the range contain all of `other`? An empty range is contained in
+    /// any range.
+    fn contains_range(&self, other: &Range<T>) -> bool;
+}
+
+impl<T: Gxa> GxaRange<T> for Range<T> {
+    fn len_bytes(&self) -> u64 {
+        self.end.u64().saturating_sub(self.start.u64())
+    }
+
+    fn pages(&self) -> RangePages<T> {
+        // The last page is the one of the last byte of the range, which keeps
+        // the ranges that end at the top of the address space iterable.
+        let next = self.start.page_align().u64();
+        let last = (self.len_bytes() != 0).then(|| T::from(self.end.u64() - 1).page_align().u64());
+
+        RangePages {
+            next,
+            last,
+            _marker: PhantomData,
+        }
+    }
+
+    fn page_count(&self) -> u64 {
+        let pages = self.pages();
+
+        pages
+            .last
+            .map_or(0, |last| ((last - pages.next) / Page::size()) + 1)
+    }
+
+    fn chunks(&self, size: u64) -> RangeChunks<T> {
+        assert!(size != 0, "the chunks can't be empty");
+
+        RangeChunks {
+            next: self.start.u64(),
+            end: self.end.u64().max(self.start.u64()),
+            size,
+            _marker: PhantomData,
+        }
+    }
+
+    fn intersection(&self, other: &Range<T>) -> Option<Range<T>> {
+        let start = self.start.u64().max(other.start.u64());
+        let end = self.end.u64().min(other.end.u64());
+
+        (start < end).then(|| T::from(start)..T::from(end))
+    }
+
+    fn contains_range(&self, other: &Range<T>) -> bool {
+        other.len_bytes() == 0
+            || (self.start.u64() <= other.start.u64() && other.end.u64() <= self.end.u64())
+    }
+}
+
+/// Iterator over the pages of a range; see [`GxaRange::pages`].
+#[derive(Debug, Clone)]
+pub struct RangePages<T> {
+    next: u64,
+    /// The last page, or `None` once there's no page left.
+    last: Option<u64>,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Gxa> Iterator for RangePages<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let last = self.last?;
+        let page = self.next;
+        if page == last {
+            self.last = None;
+        } else {
+            self.next += Page::size();
+        }
+
+        Some(T::from(page))
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        let last = self.last?;
+        match (n as u64)
+            .checked_mul(Page::size())
+            .and_then(|delta| self.next.checked_add(delta))
+        {
+            Some(page) if page <= last => {
+                self.next = page;
+                self.next()
+            }
+            _ => {
+                self.last = None;
+                None
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let pages = self
+            .last
+            .map_or(0, |last| ((last - self.next) / Page::size()) + 1);
+
+        match usize::try_from(pages) {
+            Ok(pages) => (pages, Some(pages)),
+            Err(_) => (usize::MAX, None),
+        }
+    }
+}
+
+/// Iterator over the chunks of a range; see [`GxaRange::chunks`].
+#[derive(Debug, Clone)]
+pub struct RangeChunks<T> {
+    next: u64,
+    end: u64,
+    size: u64,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Gxa> Iterator for RangeChunks<T> {
+    type Item = Range<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next == self.end {
+            return None;
+        }
+
+        let start = self.next;
+        self.next = start.saturating_add(self.size).min(self.end);
+
+        Some(T::from(start)..T::from(self.next))
+    }
+}
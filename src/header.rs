@@ -0,0 +1,479 @@
+//! This contains what is needed to interpret the fields of the dump header
+//! that describe the machine and the writer of the dump: its comment (see
+//! [`KernelDumpParser::comment`]), its [`ProductType`], its [`SuiteMask`], its
+//! [`DumpAttributes`] and its [`MemoryDescriptor`]; and the drivers it has
+//! been written through (see [`KernelDumpParser::filter_chain`]).
+//!
+//! A crash dump filter could wrap every page it writes in a header of its
+//! own, which would shift the pages away from where the dump headers say
+//! they are. Such a framing is detected when the dump is parsed: the pages
+//! all start with the same marker. No framing is documented, so a dump that
+//! has one fails with [`KdmpParserError::UnknownDumpFilter`] instead of being
+//! read wrong.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::{KernelDumpParser, ProductType};
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! if parser.comment().is_some_and(|comment| comment.contains("LiveKd")) {
+//!     println!("this is a live dump");
+//! }
+//!
+//! if parser.product_type() != Some(ProductType::WinNt) {
+//!     println!("this is a server");
+//! }
+//! ```
+use bitflags::bitflags;
+
+use crate::error::Result;
+use crate::pxe::Pfn;
+use crate::structs::{FromLeBytes, PhysmemDesc, PhysmemRun};
+use crate::{DumpType, KdmpParserError, KernelDumpParser};
+
+/// What the fields of the header that haven't been written are filled with.
+const UNSET: u32 = 0x45_47_41_50; // 'EGAP'
+
+/// The prefix of the names the drivers of the crash dump stack are loaded
+/// under, like `dump_dumpfve.sys`.
+const DUMP_STACK_PREFIX: &str = "dump_";
+
+/// Number of pages whose start is compared to detect a per-page framing.
+const FRAMING_SAMPLES: usize = 16;
+
+/// Fewest pages a per-page framing is detected with.
+const MIN_FRAMING_SAMPLES: usize = 4;
+
+/// Size of the marker a per-page framing is recognized by.
+const FRAMING_MARKER_SIZE: usize = 8;
+
+/// The kind of Windows the machine runs (`VER_NT_*`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProductType {
+    /// `VER_NT_WORKSTATION`, a client.
+    WinNt,
+    /// `VER_NT_DOMAIN_CONTROLLER`, a server that is a domain controller.
+    LanManNt,
+    /// `VER_NT_SERVER`, a server.
+    Server,
+    /// A value that isn't any of the above.
+    Other(u32),
+}
+
+impl From<u32> for ProductType {
+    fn from(value: u32) -> Self {
+        match value {
+            1 => Self::WinNt,
+            2 => Self::LanManNt,
+            3 => Self::Server,
+            value => Self::Other(value),
+        }
+    }
+}
+
+bitflags! {
+    /// The product suites available on the machine (`VER_SUITE_*`).
+    #[derive(Debug, Clone, Copy, Eq, Hash, PartialEq, Default)]
+    pub struct SuiteMask : u32 {
+        const SmallBusiness = 1 << 0;
+        const Enterprise = 1 << 1;
+        const BackOffice = 1 << 2;
+        const Communications = 1 << 3;
+        const Terminal = 1 << 4;
+        const SmallBusinessRestricted = 1 << 5;
+        const EmbeddedNt = 1 << 6;
+        const DataCenter = 1 << 7;
+        const SingleUserTs = 1 << 8;
+        const Personal = 1 << 9;
+        const Blade = 1 << 10;
+        const EmbeddedRestricted = 1 << 11;
+        const SecurityAppliance = 1 << 12;
+        const StorageServer = 1 << 13;
+        const ComputeServer = 1 << 14;
+        const WhServer = 1 << 15;
+        const MultiUserTs = 1 << 17;
+    }
+}
+
+bitflags! {
+    /// How the dump was written (the `Attributes` of the header).
+    #[derive(Debug, Clone, Copy, Eq, Hash, PartialEq, Default)]
+    pub struct DumpAttributes : u32 {
+        /// The machine crashed while hibernating or resuming.
+        const HiberCrash = 1 << 0;
+        const DumpDevicePowerOff = 1 << 1;
+        /// The dump file was too small for the whole dump.
+        const InsufficientDumpfileSize = 1 << 2;
+        const KernelGeneratedTriageDump = 1 << 3;
+        /// The dump is a live dump, taken without crashing the machine.
+        const LiveDumpGeneratedDump = 1 << 4;
+        const DumpIsGeneratedOffline = 1 << 5;
+        const FilterDumpFile = 1 << 6;
+        const EarlyBootCrash = 1 << 7;
+        /// The memory of the dump is encrypted, with a key protected by the
+        /// certificate configured with `DumpEncryption`.
+        const EncryptedDumpData = 1 << 8;
+        /// The memory of the dump was encrypted, and has been decrypted.
+        const DecryptedDump = 1 << 9;
+    }
+}
+
+impl DumpAttributes {
+    /// Is the memory of the dump still encrypted?
+    pub fn encrypted(&self) -> bool {
+        self.contains(Self::EncryptedDumpData) && !self.contains(Self::DecryptedDump)
+    }
+}
+
+/// Get the [`DumpAttributes`] of the header out of its `Attributes`, if they
+/// have been written.
+pub(crate) fn attributes(attributes: u32) -> Option<DumpAttributes> {
+    written(attributes).map(DumpAttributes::from_bits_retain)
+}
+
+/// Decode the `Comment` of the header: it is a NULL terminated string that is
+/// trimmed, and decoded lossily. It is `None` if it is empty or hasn't been
+/// written.
+pub(crate) fn decode_comment(comment: &[u8]) -> Option<String> {
+    let unset = comment
+        .chunks(4)
+        .all(|chunk| chunk == &UNSET.to_le_bytes()[..chunk.len()]);
+    if unset {
+        return None;
+    }
+
+    let len = comment
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(comment.len());
+    let comment = String::from_utf8_lossy(&comment[..len]);
+    let comment = comment.trim();
+
+    (!comment.is_empty()).then(|| comment.to_string())
+}
+
+/// A run of consecutive physical pages of a [`MemoryDescriptor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DescriptorRun {
+    /// The first page of the run.
+    pub base_page: Pfn,
+    /// Number of pages of the run.
+    pub page_count: u64,
+}
+
+/// The `PHYSICAL_MEMORY_DESCRIPTOR` of the dump header, as it is written and
+/// as `.dumpdebug` shows it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryDescriptor {
+    /// `NumberOfRuns`. The header only has room for 42 runs, so the dumps
+    /// with more don't parse.
+    pub number_of_runs: u32,
+    /// `NumberOfPages`.
+    pub number_of_pages: u64,
+    /// The runs; as many as `number_of_runs` says there are and that fit in
+    /// the header.
+    pub runs: Vec<DescriptorRun>,
+}
+
+/// Get a field of the header, if it has been written.
+fn written(value: u32) -> Option<u32> {
+    (value != UNSET).then_some(value)
+}
+
+impl KernelDumpParser {
+    /// The `Comment` of the dump header, that some dump writers fill with
+    /// where the dump comes from. It is trimmed, and decoded lossily.
+    pub fn comment(&self) -> Option<&str> {
+        self.comment.as_deref()
+    }
+
+    /// The `ProductType` of the dump header, if it has been written.
+    pub fn product_type(&self) -> Option<ProductType> {
+        written(self.headers().product_type)
+            .filter(|&product_type| product_type != 0)
+            .map(ProductType::from)
+    }
+
+    /// The `SuiteMask` of the dump header, if it has been written.
+    pub fn suite_mask(&self) -> Option<SuiteMask> {
+        written(self.headers().suite_mask).map(SuiteMask::from_bits_retain)
+    }
+
+    /// The `Attributes` of the dump header, if they have been written.
+    pub fn attributes(&self) -> Option<DumpAttributes> {
+        attributes(self.headers().attributes)
+    }
+
+    /// The drivers of the crash dump stack the dump has been written through,
+    /// in the order they have been loaded (the order of
+    /// `nt!PsLoadedModuleList`), like `storport.sys`, `stornvme.sys` and
+    /// `dumpfve.sys` (the filter of BitLocker): the kernel modules that are
+    /// loaded under the `dump_` prefix, without it.
+    ///
+    /// The filters only transform what is written to the page file, which is
+    /// undone when the dump is extracted out of it, so the dump doesn't need
+    /// them to be parsed; a filter that frames the pages of the dump fails
+    /// the parsing instead (see [`KdmpParserError::UnknownDumpFilter`]).
+    pub fn filter_chain(&self) -> Vec<String> {
+        self.kernel_modules
+            .entries_in_list_order()
+            .filter_map(|module| {
+                let file_name = module.name.rsplit(['\\', '/']).next()?;
+                let prefix = file_name.get(..DUMP_STACK_PREFIX.len())?;
+
+                prefix
+                    .eq_ignore_ascii_case(DUMP_STACK_PREFIX)
+                    .then(|| file_name[DUMP_STACK_PREFIX.len()..].to_string())
+            })
+            .collect()
+    }
+
+    /// Check that the pages of the dump aren't framed by a crash dump filter:
+    /// pages spread over the dump don't all start with the same marker. A
+    /// marker that is a single byte repeated, like the zeroes of the pages
+    /// that haven't been written yet, isn't one.
+    pub(crate) fn check_page_framing(&self) -> Result<()> {
+        let stride = (self.physmem.len() / FRAMING_SAMPLES).max(1);
+        let mut marker = None;
+        let mut samples = 0;
+        for &offset in self.physmem.values().step_by(stride).take(FRAMING_SAMPLES) {
+            let mut start = [0; FRAMING_MARKER_SIZE];
+            if self.read_at(offset, &mut start)? != start.len()
+                || start.iter().all(|&byte| byte == start[0])
+                || marker.is_some_and(|marker| marker != start)
+            {
+                return Ok(());
+            }
+
+            marker = Some(start);
+            samples += 1;
+        }
+
+        match marker {
+            Some(marker) if samples >= MIN_FRAMING_SAMPLES => Err(
+                KdmpParserError::UnknownDumpFilter(marker.escape_ascii().to_string()),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// The physical memory descriptor of the dump header, as it is written,
+    /// if it has been. Bitmap dumps don't have one: the pages they have are
+    /// described by their bitmap instead (see [`KernelDumpParser::physmem`]).
+    pub fn memory_descriptor(&self) -> Option<MemoryDescriptor> {
+        if self.dump_type() == DumpType::Bmp {
+            return None;
+        }
+
+        let buffer = &self.headers().physical_memory_block_buffer;
+        let desc = PhysmemDesc::from_le_bytes(buffer);
+        let number_of_runs = written(desc.number_of_runs)?;
+        let runs = buffer[PhysmemDesc::SIZE..]
+            .chunks_exact(PhysmemRun::SIZE)
+            .take(number_of_runs as usize)
+            .map(|run| {
+                let run = PhysmemRun::from_le_bytes(run);
+
+                DescriptorRun {
+                    base_page: Pfn::new(run.base_page),
+                    page_count: run.page_count,
+                }
+            })
+            .collect();
+
+        Some(MemoryDescriptor {
+            number_of_runs,
+            number_of_pages: desc.number_of_pages,
+            runs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DumpBuilder;
+
+    #[test]
+    fn header_fields() {
+        let mut dump = DumpBuilder::new().build();
+        dump[0xfb0..0xfc6].copy_from_slice(b"  LiveKd \xff collector\0\0");
+        dump[0x1040..0x1044].copy_from_slice(&3u32.to_le_bytes());
+        dump[0x1044..0x1048].copy_from_slice(&0x110u32.to_le_bytes());
+        let parser = KernelDumpParser::from_bytes(dump.clone()).unwrap();
+        assert_eq!(parser.comment(), Some("LiveKd \u{fffd} collector"));
+        assert_eq!(parser.product_type(), Some(ProductType::Server));
+        assert_eq!(
+            parser.suite_mask(),
+            Some(SuiteMask::Terminal | SuiteMask::SingleUserTs)
+        );
+
+        // The fields that haven't been written are filled with `PAGE`.
+        for offset in (0xfb0..0x1030).chain(0x1040..0x1048).step_by(4) {
+            dump[offset..offset + 4].copy_from_slice(b"PAGE");
+        }
+
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+        assert_eq!(parser.comment(), None);
+        assert_eq!(parser.product_type(), None);
+        assert_eq!(parser.suite_mask(), None);
+        assert_eq!(parser.attributes(), Some(DumpAttributes::empty()));
+
+        assert_eq!(decode_comment(&[0; 128]), None);
+        assert_eq!(decode_comment(b"PAGEPA"), None);
+        assert_eq!(ProductType::from(7), ProductType::Other(7));
+    }
+
+    #[test]
+    fn filter_chain() {
+        let nt = 0xffff_f800_0000_0000;
+        let dump = DumpBuilder::new()
+            .module(nt..nt + 0x1_000, "ntoskrnl.exe")
+            .module(
+                nt + 0x1_000..nt + 0x2_000,
+                "\\SystemRoot\\System32\\Drivers\\dump_storport.sys",
+            )
+            .module(nt + 0x2_000..nt + 0x3_000, "dump_stornvme.sys")
+            .module(nt + 0x3_000..nt + 0x4_000, "DUMP_DUMPFVE.SYS")
+            .module(nt + 0x4_000..nt + 0x5_000, "storport.sys")
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+        assert_eq!(parser.filter_chain(), [
+            "storport.sys",
+            "stornvme.sys",
+            "DUMPFVE.SYS"
+        ]);
+
+        // The filters are in the order they have been loaded, not in the order of
+        // their addresses.
+        let dump = DumpBuilder::new()
+            .module(nt..nt + 0x1_000, "ntoskrnl.exe")
+            .module(nt + 0x3_000..nt + 0x4_000, "dump_storport.sys")
+            .module(nt + 0x1_000..nt + 0x2_000, "dump_stornvme.sys")
+            .module(nt + 0x2_000..nt + 0x3_000, "dump_dumpfve.sys")
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+        assert_eq!(parser.filter_chain(), [
+            "storport.sys",
+            "stornvme.sys",
+            "dumpfve.sys"
+        ]);
+    }
+
+    #[test]
+    fn page_framing() {
+        // Every page of the dump starts with the same marker..
+        let mut builder = DumpBuilder::new();
+        for pfn in 0x100..0x120 {
+            builder = builder.write_phys(pfn * 0x1_000, &[0xff; 8]);
+        }
+
+        let dump = builder.build();
+        let mut framed = dump.clone();
+        for (_, offset) in KernelDumpParser::from_bytes(dump.clone())
+            .unwrap()
+            .physmem()
+        {
+            let offset = offset as usize;
+            framed[offset..offset + 8].copy_from_slice(b"FVEPAGE\x01");
+        }
+
+        assert!(matches!(
+            KernelDumpParser::from_bytes(framed),
+            Err(KdmpParserError::UnknownDumpFilter(filter)) if filter == "FVEPAGE\\x01"
+        ));
+
+        // ..but the pages that start with the same byte repeated aren't framed..
+        assert!(KernelDumpParser::from_bytes(dump).is_ok());
+
+        // ..and neither are the dumps with too few pages to tell.
+        let dump = DumpBuilder::new().build();
+        let mut framed = dump.clone();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+        assert!(parser.physmem_len() < MIN_FRAMING_SAMPLES as u64);
+        for (_, offset) in parser.physmem() {
+            let offset = offset as usize;
+            framed[offset..offset + 8].copy_from_slice(b"FVEPAGE\x01");
+        }
+
+        assert!(KernelDumpParser::from_bytes(framed).is_ok());
+    }
+
+    #[test]
+    fn memory_descriptor() {
+        let parser = KernelDumpParser::from_bytes(DumpBuilder::new().build()).unwrap();
+        assert_eq!(parser.memory_descriptor(), None);
+
+        // A full dump with two runs, of one and two pages.
+        let mut dump = vec![0; 0x2_000 + 0x3_000];
+        dump[0x0..0x4].copy_from_slice(b"PAGE");
+        dump[0x4..0x8].copy_from_slice(b"DU64");
+        dump[0x88..0x8c].copy_from_slice(&2u32.to_le_bytes());
+        dump[0x90..0x98].copy_from_slice(&3u64.to_le_bytes());
+        for (idx, (base_page, page_count)) in [(2u64, 1u64), (4, 2)].into_iter().enumerate() {
+            let offset = 0x98 + (idx * 0x10);
+            dump[offset..offset + 8].copy_from_slice(&base_page.to_le_bytes());
+            dump[offset + 8..offset + 0x10].copy_from_slice(&page_count.to_le_bytes());
+        }
+
+        dump[0xf98..0xf9c].copy_from_slice(&1u32.to_le_bytes());
+        let parser = KernelDumpParser::from_bytes(dump.clone()).unwrap();
+        assert_eq!(
+            parser.memory_descriptor(),
+            Some(MemoryDescriptor {
+                number_of_runs: 2,
+                number_of_pages: 3,
+                runs: vec![
+                    DescriptorRun {
+                        base_page: Pfn::new(2),
+                        page_count: 1
+                    },
+                    DescriptorRun {
+                        base_page: Pfn::new(4),
+                        page_count: 2
+                    },
+                ]
+            })
+        );
+
+        // A kernel dump, with a single page, that hasn't written it.
+        let mut dump = dump[..0x4_000].to_vec();
+        dump[0x88..0x8c].copy_from_slice(b"PAGE");
+        dump[0xf98..0xf9c].copy_from_slice(&8u32.to_le_bytes());
+        dump[0x2_000..0x2_004].copy_from_slice(&0x40u32.to_le_bytes());
+        dump[0x2_004..0x2_00c].copy_from_slice(b"RDMPDUMP");
+        dump[0x2_010..0x2_018].copy_from_slice(&0x30u64.to_le_bytes());
+        dump[0x2_018..0x2_020].copy_from_slice(&0x2_050u64.to_le_bytes());
+        dump[0x2_030..0x2_038].copy_from_slice(&2u64.to_le_bytes());
+        dump[0x2_038..0x2_040].copy_from_slice(&1u64.to_le_bytes());
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+        assert_eq!(parser.dump_type(), DumpType::KernelMemory);
+        assert_eq!(parser.memory_descriptor(), None);
+    }
+
+    #[test]
+    fn encrypted() {
+        let mut dump = DumpBuilder::new().build();
+        dump[0x1050..0x1054].copy_from_slice(&0x111u32.to_le_bytes());
+        assert!(matches!(
+            KernelDumpParser::from_bytes(dump.clone()),
+            Err(KdmpParserError::EncryptedDump { dump_type: 5, attributes })
+                if attributes == DumpAttributes::EncryptedDumpData
+                    | DumpAttributes::LiveDumpGeneratedDump
+                    | DumpAttributes::HiberCrash
+        ));
+
+        // The dumps that have been decrypted can be parsed..
+        dump[0x1050..0x1054].copy_from_slice(&0x311u32.to_le_bytes());
+        let parser = KernelDumpParser::from_bytes(dump.clone()).unwrap();
+        assert!(parser
+            .attributes()
+            .unwrap()
+            .contains(DumpAttributes::DecryptedDump));
+
+        // ..and so can the ones that haven't written their attributes.
+        dump[0x1050..0x1054].copy_from_slice(b"PAGE");
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+        assert_eq!(parser.attributes(), None);
+    }
+}
@@ -0,0 +1,286 @@
+//! This contains what is needed to enumerate the heaps of a process (see
+//! [`HeapInfo`]): the NT heaps and the segment heaps that `PEB.ProcessHeaps`
+//! points to, with the ranges of their segments and how much memory they
+//! have committed. The allocations themselves aren't walked.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::{Gpa, Gva, KernelDumpParser};
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! let (peb, dtb) = (Gva::new(0x7ff6_0000_0000), Gpa::new(0x1ad000));
+//! for heap in parser.process_heaps(peb, dtb).unwrap() {
+//!     println!("{} {:?}: {:#x} bytes committed", heap.base, heap.kind, heap.commit);
+//!     for segment in &heap.segments {
+//!         println!("  {}-{}", segment.start, segment.end);
+//!     }
+//! }
+//! ```
+use std::collections::HashSet;
+use std::ops::Range;
+
+use crate::error::Result;
+use crate::gxa::{Gpa, Gxa};
+use crate::structs::Page;
+use crate::teb::available;
+use crate::trace::trace_debug;
+use crate::{Gva, KdmpParserError, KernelDumpParser};
+
+/// The `SegmentSignature` of the NT heaps.
+const NT_HEAP_SIGNATURE: u32 = 0xffee_ffee;
+
+/// The `Signature` of the segment heaps.
+const SEGMENT_HEAP_SIGNATURE: u32 = 0xddee_ddee;
+
+/// Maximum number of heaps we'll read off `PEB.ProcessHeaps`.
+const MAX_HEAPS: usize = 0x400;
+
+/// Maximum number of segments we'll read off a segment list.
+const MAX_SEGMENTS: usize = 0x1_000;
+
+/// The kind of a heap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HeapKind {
+    /// The legacy heap, a `_HEAP`.
+    Nt,
+    /// The segment heap of Windows 10, a `_SEGMENT_HEAP`.
+    Segment,
+}
+
+/// A heap of a process; see [`KernelDumpParser::process_heaps`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeapInfo {
+    /// Where the heap is, which is its handle.
+    pub base: Gva,
+    pub kind: HeapKind,
+    /// The memory the segments of the heap reserve.
+    pub segments: Vec<Range<Gva>>,
+    /// How many bytes the heap has committed.
+    pub commit: u64,
+}
+
+impl KernelDumpParser {
+    /// Enumerate the heaps `PEB.ProcessHeaps` points to, where `peb` is read
+    /// in the address space `dtb`.
+    ///
+    /// The heaps whose signature isn't known, or that can't be read, are
+    /// skipped; the segments read before a segment list turns out to be
+    /// broken are kept.
+    pub fn process_heaps(&self, peb: Gva, dtb: Gpa) -> Result<Vec<HeapInfo>> {
+        let count = self.read_field_with_dtb(peb, "_PEB", "NumberOfHeaps", dtb)? as usize;
+        if count > MAX_HEAPS {
+            return Err(KdmpParserError::InvalidData("too many heaps"));
+        }
+
+        let heaps = Gva::new(self.read_field_with_dtb(peb, "_PEB", "ProcessHeaps", dtb)?);
+        let mut handles = vec![0; count * 8];
+        self.virt_read_exact_with_dtb(heaps, &mut handles, dtb)?;
+
+        let mut infos = Vec::new();
+        for handle in handles.chunks_exact(8) {
+            let base = Gva::new(u64::from_le_bytes(handle.try_into().unwrap()));
+            match available(self.heap_info(base, dtb))? {
+                Some(Some(info)) => infos.push(info),
+                Some(None) => trace_debug!("the heap at {base} has an unknown signature"),
+                None => trace_debug!("failed reading the heap at {base}"),
+            }
+        }
+
+        Ok(infos)
+    }
+
+    /// Decode the heap at `base`; `None` if its signature isn't known.
+    fn heap_info(&self, base: Gva, dtb: Gpa) -> Result<Option<HeapInfo>> {
+        let nt_signature =
+            self.read_field_with_dtb(base, "_HEAP_SEGMENT", "SegmentSignature", dtb)?;
+        if nt_signature as u32 == NT_HEAP_SIGNATURE {
+            return self.nt_heap_info(base, dtb).map(Some);
+        }
+
+        let signature = self.read_field_with_dtb(base, "_SEGMENT_HEAP", "Signature", dtb)?;
+        if signature as u32 == SEGMENT_HEAP_SIGNATURE {
+            return self.segment_heap_info(base, dtb).map(Some);
+        }
+
+        Ok(None)
+    }
+
+    /// Decode the NT heap at `base`: its segments are `_HEAP_SEGMENT`s linked
+    /// in its `SegmentList`, the first one being the heap itself.
+    fn nt_heap_info(&self, base: Gva, dtb: Gpa) -> Result<HeapInfo> {
+        let head = self.field_addr(base, "_HEAP", "SegmentList")?;
+        let entry_offset = self.profile().offset("_HEAP_SEGMENT", "SegmentListEntry")?;
+
+        let mut segments = Vec::new();
+        let mut commit = 0u64;
+        for segment in self.walk_segments(head, entry_offset, dtb) {
+            let field = |name| self.read_field_with_dtb(segment, "_HEAP_SEGMENT", name, dtb);
+            let read = || -> Result<_> {
+                Ok((
+                    field("BaseAddress")?,
+                    field("NumberOfPages")?,
+                    field("NumberOfUnCommittedPages")?,
+                ))
+            };
+
+            let Some((start, pages, uncommitted)) = available(read())? else {
+                trace_debug!("failed reading the heap segment at {segment}");
+                break;
+            };
+
+            let end = pages
+                .checked_mul(Page::size())
+                .and_then(|size| start.checked_add(size))
+                .ok_or(KdmpParserError::Overflow("heap segment"))?;
+            segments.push(Gva::new(start)..Gva::new(end));
+            commit += pages.saturating_sub(uncommitted) * Page::size();
+        }
+
+        Ok(HeapInfo {
+            base,
+            kind: HeapKind::Nt,
+            segments,
+            commit,
+        })
+    }
+
+    /// Decode the segment heap at `base`: its segments are linked in the
+    /// `SegmentListHead` of its two segment contexts, and are as large as
+    /// their `SegmentMask` says.
+    fn segment_heap_info(&self, base: Gva, dtb: Gpa) -> Result<HeapInfo> {
+        let field = |name: &str| self.read_field_with_dtb(base, "_SEGMENT_HEAP", name, dtb);
+        let commit = field("MemStats.TotalCommittedPages")?
+            .checked_mul(Page::size())
+            .ok_or(KdmpParserError::Overflow("heap commit"))?;
+
+        let mut segments = Vec::new();
+        for context in ["SegContexts[0]", "SegContexts[1]"] {
+            let size = (!field(&format!("{context}.SegmentMask"))?).wrapping_add(1);
+            let head =
+                self.field_addr(base, "_SEGMENT_HEAP", &format!("{context}.SegmentListHead"))?;
+
+            // The `ListEntry` is the first field of `_HEAP_PAGE_SEGMENT`.
+            for segment in self.walk_segments(head, 0, dtb) {
+                let end = segment
+                    .u64()
+                    .checked_add(size)
+                    .ok_or(KdmpParserError::Overflow("heap segment"))?;
+                segments.push(segment..Gva::new(end));
+            }
+        }
+
+        Ok(HeapInfo {
+            base,
+            kind: HeapKind::Segment,
+            segments,
+            commit,
+        })
+    }
+
+    /// Walk the `LIST_ENTRY` list at `head` in the address space `dtb`, and
+    /// return the address of its records. The walk stops at the first entry
+    /// that can't be read, or that has already been visited.
+    fn walk_segments(&self, head: Gva, entry_offset: u64, dtb: Gpa) -> Vec<Gva> {
+        let mut records = Vec::new();
+        let mut visited = HashSet::new();
+        let mut entry = self
+            .virt_read_struct_with_dtb::<u64>(head, dtb)
+            .map(Gva::new);
+        while let Ok(entry_addr) = entry {
+            if entry_addr == head {
+                break;
+            }
+
+            if !visited.insert(entry_addr) || visited.len() > MAX_SEGMENTS {
+                trace_debug!("the segment list at {head} is broken");
+                break;
+            }
+
+            records.push(Gva::new(entry_addr.u64().wrapping_sub(entry_offset)));
+            entry = self
+                .virt_read_struct_with_dtb::<u64>(entry_addr, dtb)
+                .map(Gva::new);
+        }
+
+        if let Err(e) = entry {
+            trace_debug!("failed walking the segment list at {head}: {e}");
+        }
+
+        records
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DumpBuilder;
+    use crate::PxeFlags;
+
+    const PEB: u64 = 0x7ff6_0000_0000;
+    const NT_HEAP: u64 = 0x1_0000_0000;
+    const SEGMENT_HEAP: u64 = 0x2_0000_0000;
+
+    #[test]
+    fn process_heaps() {
+        let user = PxeFlags::Present | PxeFlags::UserAccessible;
+        let mut handles = Vec::new();
+        for handle in [NT_HEAP, SEGMENT_HEAP, 0x3_0000_0000] {
+            handles.extend_from_slice(&handle.to_le_bytes());
+        }
+
+        // The NT heap has a second segment, further away..
+        let second = NT_HEAP + 0x10_000;
+        let nt_list = NT_HEAP + 0x120;
+        let dump = DumpBuilder::new()
+            .map_virt(PEB, 0x10_000, user)
+            .map_virt(NT_HEAP, 0x11_000, user)
+            .map_virt(second, 0x12_000, user)
+            .map_virt(SEGMENT_HEAP, 0x13_000, user)
+            .write_virt(PEB + 0xe8, &3u32.to_le_bytes())
+            .write_virt(PEB + 0xf0, &(PEB + 0x800).to_le_bytes())
+            .write_virt(PEB + 0x800, &handles)
+            .write_virt(NT_HEAP + 0x10, &NT_HEAP_SIGNATURE.to_le_bytes())
+            .write_virt(NT_HEAP + 0x30, &NT_HEAP.to_le_bytes())
+            .write_virt(NT_HEAP + 0x38, &0x10u32.to_le_bytes())
+            .write_virt(NT_HEAP + 0x50, &0xcu32.to_le_bytes())
+            .write_virt(nt_list, &(NT_HEAP + 0x18).to_le_bytes())
+            .write_virt(NT_HEAP + 0x18, &(second + 0x18).to_le_bytes())
+            .write_virt(second + 0x18, &nt_list.to_le_bytes())
+            .write_virt(second + 0x30, &second.to_le_bytes())
+            .write_virt(second + 0x38, &0x100u32.to_le_bytes())
+            .write_virt(second + 0x50, &0xffu32.to_le_bytes())
+            // ..the segment heap has one segment in its first context, and none in
+            // the second one..
+            .write_virt(SEGMENT_HEAP + 0x10, &SEGMENT_HEAP_SIGNATURE.to_le_bytes())
+            .write_virt(SEGMENT_HEAP + 0xc8, &0x20u64.to_le_bytes())
+            .write_virt(
+                SEGMENT_HEAP + 0x100,
+                &0xffff_ffff_fff0_0000u64.to_le_bytes(),
+            )
+            .write_virt(SEGMENT_HEAP + 0x148, &0x5_0000_0000u64.to_le_bytes())
+            .write_virt(SEGMENT_HEAP + 0x208, &(SEGMENT_HEAP + 0x208).to_le_bytes())
+            // ..and the last heap can't be read.
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+
+        // The segment of the segment heap can't be read, so its list ends there.
+        let dtb = Gpa::new(parser.headers().directory_table_base);
+        assert_eq!(parser.process_heaps(Gva::new(PEB), dtb).unwrap(), [
+            HeapInfo {
+                base: Gva::new(NT_HEAP),
+                kind: HeapKind::Nt,
+                segments: vec![
+                    Gva::new(NT_HEAP)..Gva::new(NT_HEAP + 0x10_000),
+                    Gva::new(second)..Gva::new(second + 0x100_000)
+                ],
+                commit: 0x5_000,
+            },
+            HeapInfo {
+                base: Gva::new(SEGMENT_HEAP),
+                kind: HeapKind::Segment,
+                segments: vec![Gva::new(0x5_0000_0000)..Gva::new(0x5_0010_0000)],
+                commit: 0x20_000,
+            }
+        ]);
+    }
+}
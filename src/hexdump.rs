@@ -0,0 +1,214 @@
+//! This contains what is needed to format memory like WinDbg's `db` command
+//! does: 16 bytes per line, preceded by their address and followed by their
+//! ASCII representation. The bytes that can't be read are shown as `??`.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::{Gva, KernelDumpParser};
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! print!("{}", parser.hexdump_virt(Gva::new(0xfffff805_108776a0), 0x20).unwrap());
+//! // fffff805`108776a0  cc c3 cc cc cc cc cc cc-0f 1f 84 00 00 00 00 00  ................
+//! // fffff805`108776b0  48 83 ec 38 48 8b 05 ??-?? ?? ?? ?? ?? ?? ?? ??  H..8H..?????????
+//! ```
+use std::fmt::{self, Write};
+use std::io;
+
+use crate::error::Result;
+use crate::gxa::Gxa;
+use crate::structs::Page;
+use crate::{ErrorCategory, Gpa, Gva, KernelDumpParser};
+
+/// How many bytes are shown per line.
+const BYTES_PER_LINE: usize = 16;
+
+/// Read the bytes of `line`, which starts at `addr`, with `read`; the ones
+/// that can't be read are left `None`. It is read page per page, so that a
+/// page that isn't in the dump doesn't hide the other one.
+fn read_line(
+    addr: u64,
+    line: &mut [Option<u8>],
+    read: &impl Fn(u64, &mut [u8]) -> Result<()>,
+) -> Result<()> {
+    let mut idx = 0;
+    while idx < line.len() {
+        let Some(chunk_addr) = addr.checked_add(idx as u64) else {
+            break;
+        };
+
+        let page_left = (Page::size() - (chunk_addr % Page::size())) as usize;
+        let len = page_left.min(line.len() - idx);
+        let mut buffer = [0; BYTES_PER_LINE];
+        match read(chunk_addr, &mut buffer[..len]) {
+            Ok(()) => {
+                for (byte, value) in line[idx..idx + len].iter_mut().zip(buffer) {
+                    *byte = Some(value);
+                }
+            }
+            Err(e) if e.category() == ErrorCategory::Translation => {}
+            Err(e) => return Err(e),
+        }
+
+        idx += len;
+    }
+
+    Ok(())
+}
+
+/// Write the `line` of bytes that starts at `addr`.
+fn write_line(out: &mut impl Write, addr: u64, line: &[Option<u8>]) -> fmt::Result {
+    write!(out, "{:08x}`{:08x} ", addr >> 32, addr & 0xffff_ffff)?;
+    for idx in 0..BYTES_PER_LINE {
+        let separator = if idx == BYTES_PER_LINE / 2 { '-' } else { ' ' };
+        match line.get(idx) {
+            Some(Some(byte)) => write!(out, "{separator}{byte:02x}")?,
+            Some(None) => write!(out, "{separator}??")?,
+            None => out.write_str("   ")?,
+        }
+    }
+
+    out.write_str("  ")?;
+    for byte in line {
+        out.write_char(match byte {
+            Some(byte @ 0x20..=0x7e) => char::from(*byte),
+            Some(_) => '.',
+            None => '?',
+        })?;
+    }
+
+    out.write_char('\n')
+}
+
+/// Write the hexdump of the `len` bytes at `addr` into `out`, reading them
+/// with `read`.
+fn write_hexdump(
+    out: &mut impl Write,
+    addr: u64,
+    len: usize,
+    read: impl Fn(u64, &mut [u8]) -> Result<()>,
+) -> Result<()> {
+    let mut offset = 0;
+    while offset < len {
+        let Some(line_addr) = addr.checked_add(offset as u64) else {
+            break;
+        };
+
+        let mut line = [None; BYTES_PER_LINE];
+        let line = &mut line[..(len - offset).min(BYTES_PER_LINE)];
+        read_line(line_addr, line, &read)?;
+        write_line(out, line_addr, line)
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "the hexdump couldn't be written"))?;
+        offset += line.len();
+    }
+
+    Ok(())
+}
+
+impl KernelDumpParser {
+    /// Format the `len` bytes of virtual memory at `gva` like WinDbg's `db`
+    /// command does; the bytes that can't be read are shown as `??`. `len`
+    /// can't exceed [`crate::ParserOptions::max_read_size`].
+    pub fn hexdump_virt(&self, gva: impl Into<Gva>, len: usize) -> Result<String> {
+        let mut hexdump = String::new();
+        self.check_read_size(len as u64)?;
+        self.write_hexdump_virt(&mut hexdump, gva, len)?;
+
+        Ok(hexdump)
+    }
+
+    /// Format the `len` bytes of physical memory at `gpa` like
+    /// [`KernelDumpParser::hexdump_virt`] does.
+    pub fn hexdump_phys(&self, gpa: impl Into<Gpa>, len: usize) -> Result<String> {
+        let mut hexdump = String::new();
+        self.check_read_size(len as u64)?;
+        self.write_hexdump_phys(&mut hexdump, gpa, len)?;
+
+        Ok(hexdump)
+    }
+
+    /// Write the hexdump of the `len` bytes of virtual memory at `gva` into
+    /// `out`, a line at a time; see [`KernelDumpParser::hexdump_virt`].
+    pub fn write_hexdump_virt(
+        &self,
+        out: &mut impl Write,
+        gva: impl Into<Gva>,
+        len: usize,
+    ) -> Result<()> {
+        write_hexdump(out, gva.into().u64(), len, |addr, buffer| {
+            self.virt_read_exact(Gva::new(addr), buffer)
+        })
+    }
+
+    /// Write the hexdump of the `len` bytes of physical memory at `gpa` into
+    /// `out`, a line at a time; see [`KernelDumpParser::hexdump_phys`].
+    pub fn write_hexdump_phys(
+        &self,
+        out: &mut impl Write,
+        gpa: impl Into<Gpa>,
+        len: usize,
+    ) -> Result<()> {
+        write_hexdump(out, gpa.into().u64(), len, |addr, buffer| {
+            self.phys_read_exact(Gpa::new(addr), buffer)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DumpBuilder;
+    use crate::{KdmpParserError, ParserOptions, PxeFlags};
+
+    const VIRT: u64 = 0xffff_f805_1087_7000;
+
+    #[test]
+    fn hexdump() {
+        let parser = KernelDumpParser::from_bytes(
+            DumpBuilder::new()
+                .map_virt(VIRT, 0x10_000, PxeFlags::Present)
+                .write_virt(VIRT + 0xff0, b"\xcc\xc3Hello, world!\x00")
+                .build(),
+        )
+        .unwrap();
+
+        // The last line is shorter, and the bytes past the page aren't in the
+        // dump..
+        assert_eq!(
+            parser.hexdump_virt(VIRT + 0xff0, 0x15).unwrap(),
+            concat!(
+                "fffff805`10877ff0  cc c3 48 65 6c 6c 6f 2c-20 77 6f 72 6c 64 21 00  ..Hello, world!.\n",
+                "fffff805`10878000  ?? ?? ?? ?? ??                                   ?????\n",
+            )
+        );
+        assert_eq!(
+            parser.hexdump_phys(0x10_ff8, 8).unwrap(),
+            "00000000`00010ff8  20 77 6f 72 6c 64 21 00                           world!.\n"
+        );
+        assert_eq!(parser.hexdump_virt(VIRT, 0).unwrap(), "");
+
+        // ..and it can be written anywhere..
+        let mut hexdump = String::from("> ");
+        parser
+            .write_hexdump_phys(&mut hexdump, Gpa::new(0x10_ffe), 4)
+            .unwrap();
+        assert_eq!(
+            hexdump,
+            "> 00000000`00010ffe  21 00 ?? ??                                      !.??\n"
+        );
+
+        // ..but it can't take more than the parser reads at once.
+        let options = ParserOptions {
+            max_read_size: 0x1_000,
+            ..Default::default()
+        };
+        let parser = KernelDumpParser::with_options(
+            std::io::Cursor::new(DumpBuilder::new().build()),
+            options,
+        )
+        .unwrap();
+        assert!(matches!(
+            parser.hexdump_phys(0, 0x1_001),
+            Err(KdmpParserError::ReadLimitExceeded { .. })
+        ));
+    }
+}
@@ -0,0 +1,700 @@
+//! This contains what is needed to parse the hibernation file
+//! (`hiberfil.sys`) of Windows 8 and later (see [`HibernationParser`]). The
+//! pages it saved are presented as a dump to [`KernelDumpParser`], so every API
+//! works on a hibernation file like it does on a dump.
+//!
+//! The file starts with a `PO_MEMORY_IMAGE` header that says where the two
+//! restore sets are: the boot one, then the kernel one. A restore set is a
+//! sequence of compression sets of at most 16 pages each:
+//!
+//! ```text
+//! +0x000 Header           : Uint4B
+//!          bits 0-7   : number of page descriptors
+//!          bits 8-29  : size of the compressed data
+//!          bit 30     : compressed with LZ77+Huffman rather than plain LZ77
+//! +0x004 Descriptors      : [n] Uint8B
+//!          bits 0-3   : number of pages, minus one
+//!          bits 4-63  : pfn of the first page
+//!        CompressedData   : [size] UChar
+//! ```
+//!
+//! The data of a set that is as large as its pages isn't compressed.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::HibernationParser;
+//! let hibernation = HibernationParser::new(&"hiberfil.sys").unwrap();
+//! println!("{} pages saved", hibernation.pages());
+//!
+//! let parser = hibernation.into_parser().unwrap();
+//! for (at, name) in parser.kernel_modules() {
+//!     println!("{name} at {at:?}");
+//! }
+//! ```
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{self, Read, Seek};
+use std::path::Path;
+
+use crate::error::Result;
+use crate::gxa::{Gpa, Gxa};
+use crate::map::Reader;
+use crate::structs::{BmpHeader64, FromLeBytes, Header64, Page};
+use crate::trace::trace_debug;
+use crate::xpress::{decompress_huffman, decompress_lz77};
+use crate::{KdmpParserError, KernelDumpParser, ParserOptions};
+
+/// Signatures of a hibernation file the system can resume from.
+const SIGNATURES: [&[u8; 4]; 4] = [b"HIBR", b"hibr", b"WAKE", b"wake"];
+
+/// Maximum number of pages of a compression set.
+const MAX_SET_PAGES: u64 = 16;
+
+/// The pages the low stub is looked for in: the first megabyte.
+const LOW_STUB_PAGES: u64 = 0x100;
+
+/// The fields of the `PO_MEMORY_IMAGE` header of a hibernation file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HibernationHeader {
+    /// `Signature` of the header: `HIBR`, or `WAKE` for a file being resumed
+    /// from.
+    pub signature: u32,
+    /// `PageSize` of the header.
+    pub page_size: u32,
+    /// `SystemTime` of the header, when the system hibernated.
+    pub system_time: u64,
+    /// `FirstBootRestorePage` of the header, the page the boot restore set
+    /// starts at.
+    pub first_boot_restore_page: u64,
+    /// `FirstKernelRestorePage` of the header, the page the kernel restore set
+    /// starts at.
+    pub first_kernel_restore_page: u64,
+}
+
+impl HibernationHeader {
+    /// Decode the header out of the first page of the file.
+    fn parse(page: &[u8]) -> Self {
+        // ```text
+        // kd> dt nt!PO_MEMORY_IMAGE Signature PageSize SystemTime FirstBootRestorePage FirstKernelRestorePage
+        //    +0x000 Signature        : Uint4B
+        //    +0x018 PageSize         : Uint4B
+        //    +0x020 SystemTime       : _LARGE_INTEGER
+        //    +0x068 FirstBootRestorePage : Uint8B
+        //    +0x070 FirstKernelRestorePage : Uint8B
+        // ```
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(page[offset..offset + 4].try_into().unwrap());
+        let u64_at =
+            |offset: usize| u64::from_le_bytes(page[offset..offset + 8].try_into().unwrap());
+
+        Self {
+            signature: u32_at(0x0),
+            page_size: u32_at(0x18),
+            system_time: u64_at(0x20),
+            first_boot_restore_page: u64_at(0x68),
+            first_kernel_restore_page: u64_at(0x70),
+        }
+    }
+}
+
+/// A compression set of a restore set.
+#[derive(Debug, Clone, Copy)]
+struct CompressionSet {
+    /// Offset of the compressed data in the file.
+    data: u64,
+    /// Size of the compressed data.
+    compressed_size: u32,
+    /// Number of pages the data decompresses to.
+    pages: u64,
+    /// Is the data compressed with LZ77+Huffman?
+    huffman: bool,
+}
+
+impl CompressionSet {
+    /// Offset of the set that follows in the file.
+    fn end(&self) -> u64 {
+        self.data + u64::from(self.compressed_size)
+    }
+
+    /// Decompress the `data` of the set.
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let size = (self.pages * Page::size()) as usize;
+        if data.len() == size {
+            Ok(data.to_vec())
+        } else if self.huffman {
+            decompress_huffman(data, size)
+        } else {
+            decompress_lz77(data, size)
+        }
+    }
+}
+
+/// The descriptors of a compression set: the pfn of the first page and the
+/// number of pages of each.
+type Descriptors = Vec<(u64, u64)>;
+
+/// Where a page is in the file.
+#[derive(Debug, Clone, Copy)]
+struct PageLocation {
+    /// Index of the compression set the page is in.
+    set: usize,
+    /// Index of the page in the set.
+    idx: u64,
+}
+
+/// A parser for the hibernation file of Windows 8 and later, which presents
+/// the pages it saved as a dump (see [`HibernationParser::into_parser`]).
+///
+/// The pages are decompressed when they are read, a compression set at a time.
+pub struct HibernationParser {
+    /// The hibernation file.
+    reader: Box<dyn Reader + Send>,
+    /// The header of the file.
+    header: HibernationHeader,
+    /// The compression sets of the restore sets.
+    sets: Vec<CompressionSet>,
+    /// The pages saved in the file, by pfn.
+    pages: Vec<(u64, PageLocation)>,
+    /// The directory table base found in the low stub, if any.
+    directory_table_base: Option<Gpa>,
+    /// The headers of the dump the pages are presented as; the pages follow
+    /// them, in the order of their pfn.
+    dump_headers: Vec<u8>,
+    /// Where the dump is being read at.
+    position: u64,
+    /// The last compression set that was decompressed, and its pages.
+    cache: Option<(usize, Vec<u8>)>,
+}
+
+impl Debug for HibernationParser {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HibernationParser")
+            .field("header", &self.header)
+            .field("sets", &self.sets.len())
+            .field("pages", &self.pages.len())
+            .field("directory_table_base", &self.directory_table_base)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HibernationParser {
+    /// Create an instance from a file path.
+    pub fn new<P>(path: &P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Self::with_reader(File::open(path)?)
+    }
+
+    /// Create an instance from a [`Reader`] over a hibernation file.
+    ///
+    /// Only the format of Windows 8 and later is supported; the file of an
+    /// older Windows, or whose header has been wiped after the system resumed
+    /// from it, is a [`KdmpParserError::UnsupportedHibernation`].
+    pub fn with_reader(mut reader: impl Reader + Send + 'static) -> Result<Self> {
+        let mut page = vec![0; Page::size() as usize];
+        reader.read_exact(&mut page)?;
+        let header = HibernationHeader::parse(&page);
+        if header.signature == 0 {
+            return Err(KdmpParserError::UnsupportedHibernation(
+                "the header has been wiped after resuming",
+            ));
+        }
+
+        if !SIGNATURES.contains(&&header.signature.to_le_bytes()) {
+            return Err(KdmpParserError::UnsupportedHibernation("unknown signature"));
+        }
+
+        if u64::from(header.page_size) != Page::size() {
+            return Err(KdmpParserError::UnsupportedPageSize(
+                header.page_size.into(),
+            ));
+        }
+
+        // The restore sets of the older formats aren't made of compression sets, so the
+        // first ones are where it shows.
+        let legacy = KdmpParserError::UnsupportedHibernation(
+            "only the format of Windows 8 and later is supported",
+        );
+        let boot = header.first_boot_restore_page;
+        let kernel = header.first_kernel_restore_page;
+        if boot == 0 || kernel <= boot {
+            return Err(legacy);
+        }
+
+        let mut sets = Vec::new();
+        let mut pages = BTreeMap::new();
+        for (first, last) in [(boot, Some(kernel)), (kernel, None)] {
+            let mut offset = first * Page::size();
+            let end = last.map_or(u64::MAX, |last| last * Page::size());
+            let first_set = sets.len();
+            while offset < end {
+                let Some((set, descriptors)) = read_set(&mut reader, offset)? else {
+                    break;
+                };
+
+                // A page that is in a set that follows the end of the restore set is stale,
+                // so the first set a page is in wins.
+                let set_idx = sets.len();
+                let mut idx = 0;
+                for (pfn, count) in descriptors {
+                    for pfn in pfn..pfn + count {
+                        pages
+                            .entry(pfn)
+                            .or_insert(PageLocation { set: set_idx, idx });
+                        idx += 1;
+                    }
+                }
+
+                offset = set.end();
+                sets.push(set);
+            }
+
+            if sets.len() == first_set {
+                return Err(legacy);
+            }
+        }
+
+        trace_debug!(
+            "indexed {} pages in {} compression sets",
+            pages.len(),
+            sets.len()
+        );
+
+        let mut parser = Self {
+            reader: Box::new(reader),
+            header,
+            sets,
+            pages: pages.into_iter().collect(),
+            directory_table_base: None,
+            dump_headers: Vec::new(),
+            position: 0,
+            cache: None,
+        };
+
+        parser.directory_table_base = parser.find_low_stub()?;
+        parser.dump_headers = parser.dump_headers();
+
+        Ok(parser)
+    }
+
+    /// The header of the file.
+    pub fn header(&self) -> &HibernationHeader {
+        &self.header
+    }
+
+    /// Number of pages saved in the file.
+    pub fn pages(&self) -> u64 {
+        self.pages.len() as u64
+    }
+
+    /// The directory table base of the kernel, found in the low stub
+    /// (`PROCESSOR_START_BLOCK`) the processors start from. The parser
+    /// translates virtual addresses with it.
+    pub fn directory_table_base(&self) -> Option<Gpa> {
+        self.directory_table_base
+    }
+
+    /// Parse the pages saved in the file as a dump, with the default options.
+    pub fn into_parser(self) -> Result<KernelDumpParser> {
+        self.into_parser_with_options(ParserOptions::default())
+    }
+
+    /// Parse the pages saved in the file as a dump, with `options`; the
+    /// pages of a hibernation file are always [`Page::size`] bytes, whatever
+    /// [`ParserOptions::page_size`] is.
+    ///
+    /// The dump has no KDDEBUGGER_DATA_BLOCK, so the kernel modules are
+    /// recovered from the image of `nt` and the user modules aren't known.
+    pub fn into_parser_with_options(self, options: ParserOptions) -> Result<KernelDumpParser> {
+        let options = ParserOptions {
+            page_size: Page::size(),
+            ..options
+        };
+
+        KernelDumpParser::with_options(self, options)
+    }
+
+    /// Find the low stub in the first megabyte of physical memory, and get the
+    /// directory table base out of it.
+    fn find_low_stub(&mut self) -> Result<Option<Gpa>> {
+        let low_pages = self.pages.partition_point(|(pfn, _)| *pfn < LOW_STUB_PAGES);
+        for idx in 0..low_pages {
+            let page = self.page(idx)?;
+            let u64_at =
+                |offset: usize| u64::from_le_bytes(page[offset..offset + 8].try_into().unwrap());
+
+            // ```text
+            // kd> dt nt!_PROCESSOR_START_BLOCK CompletionFlag LmTarget ProcessorState.SpecialRegisters.Cr3
+            //    +0x000 Jmp              : _PROCESSOR_START_BLOCK_JMP
+            //    +0x070 LmTarget         : Ptr64 Void
+            //    +0x090 ProcessorState   : _KPROCESSOR_STATE
+            //       +0x010 Cr3              : Uint8B
+            // ```
+            let jmp = u64_at(0x0) & 0xffff_ffff_ffff_00ff;
+            let lm_target = u64_at(0x70) & 0xffff_f800_0000_0003;
+            let cr3 = u64_at(0xa0);
+            if jmp == 0x1_0006_00e9
+                && lm_target == 0xffff_f800_0000_0000
+                && cr3 & !0xff_ffff_f000 == 0
+            {
+                return Ok(Some(Gpa::new(cr3)));
+            }
+        }
+
+        trace_debug!("found no low stub");
+
+        Ok(None)
+    }
+
+    /// Lay out the headers of a BMP dump that has the pages saved in the file.
+    fn dump_headers(&self) -> Vec<u8> {
+        let mut headers = vec![0; Header64::SIZE];
+        headers[0x0..0x4].copy_from_slice(b"PAGE");
+        headers[0x4..0x8].copy_from_slice(b"DU64");
+        headers[0x8..0xc].copy_from_slice(&0xfu32.to_le_bytes());
+        let dtb = self.directory_table_base.map_or(0, |dtb| dtb.u64());
+        headers[0x10..0x18].copy_from_slice(&dtb.to_le_bytes());
+        headers[0x30..0x34].copy_from_slice(&0x8664u32.to_le_bytes());
+        headers[0x34..0x38].copy_from_slice(&1u32.to_le_bytes());
+        headers[0xf98..0xf9c].copy_from_slice(&5u32.to_le_bytes());
+        headers[0xfa8..0xfb0].copy_from_slice(&self.header.system_time.to_le_bytes());
+
+        let max_pfn = self.pages.last().map_or(0, |(pfn, _)| *pfn);
+        let bitmap_size = ((max_pfn / 64) + 1) * 8;
+        let first_page = (Header64::SIZE + BmpHeader64::SIZE) as u64 + bitmap_size;
+        let mut bmp = vec![0; BmpHeader64::SIZE];
+        bmp[0x0..0x4].copy_from_slice(b"SDMP");
+        bmp[0x4..0x8].copy_from_slice(b"DUMP");
+        bmp[0x20..0x28].copy_from_slice(&first_page.to_le_bytes());
+        bmp[0x28..0x30].copy_from_slice(&(self.pages.len() as u64).to_le_bytes());
+        bmp[0x30..0x38].copy_from_slice(&(bitmap_size * 8).to_le_bytes());
+        headers.extend_from_slice(&bmp);
+
+        let mut bitmap = vec![0u8; bitmap_size as usize];
+        for (pfn, _) in &self.pages {
+            bitmap[(pfn / 8) as usize] |= 1 << (pfn % 8);
+        }
+
+        headers.extend_from_slice(&bitmap);
+        let size = headers.len() as u64 + (self.pages.len() as u64 * Page::size());
+        headers[0xfa0..0xfa8].copy_from_slice(&size.to_le_bytes());
+
+        headers
+    }
+
+    /// Get the content of the `idx`th page saved in the file.
+    fn page(&mut self, idx: usize) -> Result<&[u8]> {
+        let (_, location) = self.pages[idx];
+        if !matches!(self.cache, Some((set, _)) if set == location.set) {
+            let set = self.sets[location.set];
+            let mut data = vec![0; set.compressed_size as usize];
+            self.reader.seek(io::SeekFrom::Start(set.data))?;
+            self.reader.read_exact(&mut data)?;
+            self.cache = Some((location.set, set.decompress(&data)?));
+        }
+
+        let (_, pages) = self.cache.as_ref().unwrap();
+        let offset = (location.idx * Page::size()) as usize;
+
+        Ok(&pages[offset..offset + Page::size() as usize])
+    }
+
+    /// Size of the dump the pages are presented as.
+    fn dump_size(&self) -> u64 {
+        self.dump_headers.len() as u64 + (self.pages.len() as u64 * Page::size())
+    }
+}
+
+/// Read the compression set at `offset`: the set, and the pfn and the number
+/// of pages of its descriptors. It is `None` if there is no valid set there.
+fn read_set(
+    reader: &mut impl Reader,
+    offset: u64,
+) -> Result<Option<(CompressionSet, Descriptors)>> {
+    let mut header = [0; 4];
+    reader.seek(io::SeekFrom::Start(offset))?;
+    match reader.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+
+    let header = u32::from_le_bytes(header);
+    let descriptor_count = u64::from(header & 0xff);
+    let compressed_size = (header >> 8) & 0x3f_ffff;
+    let huffman = (header >> 30) & 1 == 1;
+    if descriptor_count == 0 || descriptor_count > MAX_SET_PAGES || compressed_size == 0 {
+        return Ok(None);
+    }
+
+    let mut descriptors = vec![0; (descriptor_count * 8) as usize];
+    match reader.read_exact(&mut descriptors) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+
+    let descriptors = descriptors
+        .chunks_exact(8)
+        .map(|descriptor| {
+            let descriptor = u64::from_le_bytes(descriptor.try_into().unwrap());
+
+            (descriptor >> 4, (descriptor & 0xf) + 1)
+        })
+        .collect::<Vec<_>>();
+
+    let pages = descriptors.iter().map(|(_, count)| count).sum::<u64>();
+    if pages > MAX_SET_PAGES || u64::from(compressed_size) > pages * Page::size() {
+        return Ok(None);
+    }
+
+    let data = offset + 4 + (descriptor_count * 8);
+    let set = CompressionSet {
+        data,
+        compressed_size,
+        pages,
+        huffman,
+    };
+
+    Ok(Some((set, descriptors)))
+}
+
+/// Read the dump the pages are presented as.
+impl Read for HibernationParser {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let headers_size = self.dump_headers.len() as u64;
+        if self.position < headers_size {
+            let mut headers = &self.dump_headers[self.position as usize..];
+            let len = headers.read(buf)?;
+            self.position += len as u64;
+
+            return Ok(len);
+        }
+
+        let offset = self.position - headers_size;
+        let idx = offset / Page::size();
+        if idx >= self.pages.len() as u64 {
+            return Ok(0);
+        }
+
+        let page_offset = (offset % Page::size()) as usize;
+        let page = self
+            .page(idx as usize)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        let len = buf.len().min(page.len() - page_offset);
+        buf[..len].copy_from_slice(&page[page_offset..page_offset + len]);
+        self.position += len as u64;
+
+        Ok(len)
+    }
+}
+
+/// Seek in the dump the pages are presented as.
+impl Seek for HibernationParser {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            io::SeekFrom::Start(offset) => Some(offset),
+            io::SeekFrom::End(offset) => self.dump_size().checked_add_signed(offset),
+            io::SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+
+        self.position = position
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before the start"))?;
+
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DumpBuilder;
+    use crate::{Gva, Pfn, PxeFlags};
+
+    const DRIVER: u64 = 0xffff_f800_0500_0000;
+
+    /// Compress `data` with the plain LZ77 format; only the runs of a byte are
+    /// matched.
+    fn lz77(data: &[u8]) -> Vec<u8> {
+        let mut output = Vec::new();
+        let mut flags_at = 0;
+        let mut flag_count = 0;
+        let mut half_byte = None;
+        let mut idx = 0;
+        while idx < data.len() {
+            if flag_count == 32 {
+                flag_count = 0;
+            }
+
+            if flag_count == 0 {
+                flags_at = output.len();
+                output.extend_from_slice(&[0; 4]);
+            }
+
+            let run = match idx {
+                0 => 0,
+                _ => data[idx..]
+                    .iter()
+                    .take_while(|&&byte| byte == data[idx - 1])
+                    .count(),
+            };
+
+            if run < 3 {
+                output.push(data[idx]);
+                idx += 1;
+                flag_count += 1;
+                continue;
+            }
+
+            let flags = u32::from_le_bytes(output[flags_at..flags_at + 4].try_into().unwrap());
+            let flags = flags | (1 << (31 - flag_count));
+            output[flags_at..flags_at + 4].copy_from_slice(&flags.to_le_bytes());
+            let length = run - 3;
+            if length < 7 {
+                output.extend_from_slice(&(length as u16).to_le_bytes());
+            } else {
+                output.extend_from_slice(&7u16.to_le_bytes());
+                let nibble = (length - 7).min(15) as u8;
+                match half_byte.take() {
+                    Some(at) => output[at] |= nibble << 4,
+                    None => {
+                        half_byte = Some(output.len());
+                        output.push(nibble);
+                    }
+                }
+
+                if nibble == 15 {
+                    match length - 7 - 15 {
+                        rest @ 0..=254 => output.push(rest as u8),
+                        _ => {
+                            output.push(255);
+                            output.extend_from_slice(&(length as u16).to_le_bytes());
+                        }
+                    }
+                }
+            }
+
+            idx += run;
+            flag_count += 1;
+        }
+
+        output
+    }
+
+    /// Build a hibernation file whose boot restore set has the first page of
+    /// `pages`, and whose kernel restore set has the others; a stale set
+    /// follows the kernel one.
+    fn hibernation_file(pages: &[(u64, Vec<u8>)]) -> Vec<u8> {
+        let mut file = vec![0; Page::size() as usize];
+        file[0x0..0x4].copy_from_slice(b"HIBR");
+        file[0x18..0x1c].copy_from_slice(&0x1_000u32.to_le_bytes());
+        file[0x20..0x28].copy_from_slice(&0x1337u64.to_le_bytes());
+        file[0x68..0x70].copy_from_slice(&1u64.to_le_bytes());
+        file[0x70..0x78].copy_from_slice(&2u64.to_le_bytes());
+
+        let push_set = |file: &mut Vec<u8>, pages: &[(u64, Vec<u8>)]| {
+            let data = pages
+                .iter()
+                .flat_map(|(_, page)| page.iter().copied())
+                .collect::<Vec<_>>();
+            let compressed = lz77(&data);
+            let data = if compressed.len() < data.len() {
+                compressed
+            } else {
+                data
+            };
+
+            let header = pages.len() as u32 | ((data.len() as u32) << 8);
+            file.extend_from_slice(&header.to_le_bytes());
+            for (pfn, _) in pages {
+                file.extend_from_slice(&(pfn << 4).to_le_bytes());
+            }
+
+            file.extend_from_slice(&data);
+        };
+
+        push_set(&mut file, &pages[..1]);
+        file.resize(2 * Page::size() as usize, 0);
+        for set in pages[1..].chunks(MAX_SET_PAGES as usize) {
+            push_set(&mut file, set);
+        }
+
+        push_set(&mut file, &[(pages[1].0, vec![
+            0xaa;
+            Page::size() as usize
+        ])]);
+
+        file
+    }
+
+    #[test]
+    fn hibernation() {
+        // The low stub points to the page tables of the dump.
+        let mut low_stub = vec![0; Page::size() as usize];
+        low_stub[0x0..0x8].copy_from_slice(&0x1_0006_12e9u64.to_le_bytes());
+        low_stub[0x70..0x78].copy_from_slice(&0xffff_f800_0123_4000u64.to_le_bytes());
+        let dump = DumpBuilder::new()
+            .write_phys(0x1_000, &low_stub)
+            .map_virt(DRIVER, 0x10_000, PxeFlags::Present)
+            .write_virt(DRIVER, b"hibernated")
+            .build();
+        let dump = KernelDumpParser::from_bytes(dump).unwrap();
+        let dtb = dump.headers().directory_table_base;
+
+        let mut pages = dump
+            .physmem()
+            .map(|(gpa, _)| {
+                let mut page = vec![0; Page::size() as usize];
+                dump.phys_read_exact(gpa, &mut page).unwrap();
+
+                (gpa.pfn(), page)
+            })
+            .collect::<Vec<_>>();
+        let (_, low_stub) = pages.iter_mut().find(|(pfn, _)| *pfn == 1).unwrap();
+        low_stub[0xa0..0xa8].copy_from_slice(&dtb.to_le_bytes());
+
+        let hibernation =
+            HibernationParser::with_reader(io::Cursor::new(hibernation_file(&pages))).unwrap();
+        assert_eq!(hibernation.header().system_time, 0x1337);
+        assert_eq!(hibernation.pages(), pages.len() as u64);
+        assert_eq!(hibernation.directory_table_base(), Some(Gpa::new(dtb)));
+
+        let parser = hibernation.into_parser().unwrap();
+        assert_eq!(parser.physmem().len(), pages.len());
+        for (pfn, page) in &pages {
+            let mut buffer = vec![0; Page::size() as usize];
+            parser
+                .phys_read_exact(Gpa::from_pfn(Pfn::new(*pfn)), &mut buffer)
+                .unwrap();
+            assert_eq!(&buffer, page);
+        }
+
+        let mut buffer = [0; 10];
+        parser
+            .virt_read_exact(Gva::new(DRIVER), &mut buffer)
+            .unwrap();
+        assert_eq!(&buffer, b"hibernated");
+    }
+
+    #[test]
+    fn unsupported() {
+        let pages = [(1, vec![0; Page::size() as usize]), (2, vec![1; 0x1_000])];
+        let mut file = hibernation_file(&pages);
+        file[0..4].copy_from_slice(&[0; 4]);
+        assert!(matches!(
+            HibernationParser::with_reader(io::Cursor::new(file.clone())),
+            Err(KdmpParserError::UnsupportedHibernation(_))
+        ));
+
+        // The restore sets of a legacy file start with a table.
+        file[0..4].copy_from_slice(b"HIBR");
+        file[0x2_000..0x2_004].copy_from_slice(&0u32.to_le_bytes());
+        assert!(matches!(
+            HibernationParser::with_reader(io::Cursor::new(file)),
+            Err(KdmpParserError::UnsupportedHibernation(_))
+        ));
+    }
+}
@@ -0,0 +1,57 @@
+//! This exposes the modules mapped in a dump as [`object::File`]s, which
+//! gives access to their sections, their debug directory, etc.
+use crate::error::Result;
+use crate::gxa::Gxa;
+use crate::module::ModuleEntry;
+use crate::pe::unmap_sections;
+use crate::{KdmpParserError, KernelDumpParser, ReadMode};
+
+impl KernelDumpParser {
+    /// Parse a module mapped in the dump with the [`object`] crate.
+    ///
+    /// The module's virtual range is copied out of the dump the first time
+    /// it is needed; the pages that aren't in the dump are zero-filled. The
+    /// section headers of the copy are rewritten so that the raw data of each
+    /// section is where it was mapped.
+    pub fn module_object(&self, module: &ModuleEntry) -> Result<object::File<'_>> {
+        let image = self.module_image(module)?;
+
+        Ok(object::File::parse(image)?)
+    }
+
+    /// Get the copy of a module out of the cache, or make it.
+    fn module_image(&self, module: &ModuleEntry) -> Result<&[u8]> {
+        // Only the modules of the dump have a slot in the cache.
+        let known = self
+            .find_module_entry(module.at.start)
+            .is_some_and(|entry| entry == module);
+        let slot = self
+            .module_images
+            .get(&module.at.start)
+            .filter(|_| known)
+            .ok_or(KdmpParserError::NotFound("the module in the dump"))?;
+
+        if let Some(image) = slot.get() {
+            return Ok(image);
+        }
+
+        let image = self.copy_module(module)?;
+
+        Ok(slot.get_or_init(|| image))
+    }
+
+    /// Copy the virtual range of a module, and make it look like a file.
+    fn copy_module(&self, module: &ModuleEntry) -> Result<Vec<u8>> {
+        let size = module.at.end.u64().saturating_sub(module.at.start.u64());
+        if size == 0 {
+            return Err(KdmpParserError::InvalidData("invalid module size"));
+        }
+
+        // The pages that aren't in the dump are zero-filled.
+        let mut image =
+            self.virt_read_to_vec_with_mode(module.at.start, size, ReadMode::ZeroFill)?;
+        unmap_sections(&mut image)?;
+
+        Ok(image)
+    }
+}
@@ -0,0 +1,334 @@
+//! This contains what is needed to parse a dump that is still being written:
+//! see [`KernelDumpParser::open_incomplete`] and [`KernelDumpParser::refresh`].
+//!
+//! The headers and the metadata that say where the pages are (the runs, the
+//! bitmap or the page ranges) are written first, so they have to be in the
+//! file already; the pages that aren't written yet are left out of the
+//! physical memory map until a refresh finds them there. Reading them fails
+//! like reading any page that isn't in the dump.
+//!
+//! The `WriterStatus` of the header says whether the dump is entirely written:
+//! it is `STATUS_SUCCESS` once the writer is done, and another status (like
+//! `STATUS_PENDING`) until then. While it isn't done, the file can have been
+//! preallocated to its full size, so its length says nothing: the file is
+//! written front to back, and only the pages that end before the last byte
+//! that isn't zero are considered written. That can leave pages that are zero
+//! filled out until the writer is done, never pages that aren't written.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::KernelDumpParser;
+//! let mut parser = KernelDumpParser::open_incomplete(&"full.dmp").unwrap();
+//! while !parser.is_complete() {
+//!     std::thread::sleep(std::time::Duration::from_secs(1));
+//!     let delta = parser.refresh().unwrap();
+//!     println!("{} new pages, {} to go", delta.new_pages, delta.pending_pages);
+//! }
+//! ```
+use std::cmp::min;
+use std::fs::File;
+use std::io::{self, Read, Seek};
+use std::mem;
+use std::path::Path;
+
+use crate::error::Result;
+use crate::structs::{FromLeBytes, Header64, Page, PhysmemMap};
+use crate::supplement::is_supplemental;
+use crate::{KdmpParserError, KernelDumpParser, ParserOptions};
+
+/// What a [`KernelDumpParser::refresh`] found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RefreshDelta {
+    /// Number of [`Page::size()`] pages that landed in the file since the
+    /// last refresh, and that can be read now.
+    pub new_pages: u64,
+    /// Number of [`Page::size()`] pages that still aren't in the file.
+    pub pending_pages: u64,
+    /// Size of the dump file.
+    pub file_size: u64,
+}
+
+/// The `WriterStatus` of the header of a dump that is entirely written.
+const STATUS_SUCCESS: u32 = 0;
+
+/// Does the header say that the writer is done writing the dump?
+pub(crate) fn writer_done(headers: &Header64) -> bool {
+    headers.writer_status == STATUS_SUCCESS
+}
+
+/// Find where the bytes written so far end in `reader`, a dump file of
+/// `file_size` bytes that is known to be written up to `written`. If the
+/// writer is done, it is the whole file. Otherwise, the file is written front
+/// to back but can be preallocated: the pages past `written` are probed with
+/// a binary search for the last byte that isn't zero. A page that is zero
+/// filled can make it stop short, it never goes past what is written.
+pub(crate) fn written_end<R: Read + Seek + ?Sized>(
+    reader: &mut R,
+    written: u64,
+    file_size: u64,
+    writing: bool,
+) -> Result<u64> {
+    if !writing {
+        return Ok(file_size);
+    }
+
+    let (mut start, mut end) = (written.min(file_size), file_size);
+    let mut page = vec![0; Page::size() as usize];
+    while start < end {
+        let middle = start + (end - start) / 2;
+        let page = &mut page[..min(Page::size(), end - middle) as usize];
+        reader.seek(io::SeekFrom::Start(middle))?;
+        reader.read_exact(page)?;
+        match page.iter().rposition(|&byte| byte != 0) {
+            Some(last) => start = middle + last as u64 + 1,
+            None => end = middle,
+        }
+    }
+
+    Ok(start)
+}
+
+/// Does the page at `offset` end before `written_end`?
+fn written(offset: u64, written_end: u64) -> bool {
+    offset
+        .checked_add(Page::size())
+        .is_some_and(|end| end <= written_end)
+}
+
+/// Take the pages of `physmem` that don't end before `written` out of it,
+/// and return them.
+pub(crate) fn split_unwritten(physmem: &mut PhysmemMap, written_end: u64) -> PhysmemMap {
+    let mut unwritten = PhysmemMap::new();
+    physmem.retain(|&gpa, &mut offset| {
+        if written(offset, written_end) {
+            return true;
+        }
+
+        unwritten.insert(gpa, offset);
+
+        false
+    });
+
+    unwritten
+}
+
+impl KernelDumpParser {
+    /// Open the dump at `dump_path` while it is still being written, like
+    /// [`KernelDumpParser::new`]. The pages that aren't in the file yet can't
+    /// be read until [`KernelDumpParser::refresh`] finds them there.
+    pub fn open_incomplete<P>(dump_path: &P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Self::open_incomplete_with_options(dump_path, ParserOptions::default())
+    }
+
+    /// Open the dump at `dump_path` while it is still being written, parsing
+    /// it with `options`; see [`KernelDumpParser::open_incomplete`]. The file
+    /// is read rather than memory mapped, as it grows. The modules are found
+    /// with the pages that are in the file when it is opened; call
+    /// [`KernelDumpParser::set_default_dtb`] to look for them again.
+    pub fn open_incomplete_with_options<P>(dump_path: &P, options: ParserOptions) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Self::parse(File::open(dump_path)?, options, None, true)
+    }
+
+    /// Look at the dump file again, and add the pages that landed in it since
+    /// it was opened (or since the last refresh) to the physical memory map.
+    /// The pages that were already there don't move; a page is only added
+    /// once all of its bytes are written, and the header is read again to
+    /// know if the writer is done (see the [module documentation](self)).
+    ///
+    /// The dump wins over the supplemental sources for the pages it now has
+    /// (see [`KernelDumpParser::add_supplemental_physmem`]).
+    pub fn refresh(&mut self) -> Result<RefreshDelta> {
+        let file_size = self.reader.lock().unwrap().seek(io::SeekFrom::End(0))?;
+        if file_size < self.file_size {
+            return Err(KdmpParserError::InvalidData(
+                "the dump file shrank while it was being written",
+            ));
+        }
+
+        if self.writing {
+            let mut header = vec![0; Header64::SIZE];
+            if self.read_at(0, &mut header)? != header.len() {
+                return Err(KdmpParserError::InvalidData(
+                    "the dump file shrank while it was being written",
+                ));
+            }
+
+            self.writing = !writer_done(&Header64::from_le_bytes(&header));
+        }
+
+        self.file_size = file_size;
+        self.written = written_end(
+            &mut **self.reader.lock().unwrap(),
+            self.written,
+            file_size,
+            self.writing,
+        )?;
+
+        // What is left after taking the pages that aren't written yet out are
+        // the ones that landed.
+        let pending = split_unwritten(&mut self.unwritten, self.written);
+        let landed = mem::replace(&mut self.unwritten, pending);
+        let mut supplemented = 0;
+        for (&gpa, &offset) in &landed {
+            if self
+                .physmem
+                .insert(gpa, offset)
+                .is_some_and(is_supplemental)
+            {
+                supplemented += 1;
+            }
+        }
+
+        // The page tables read out of the supplemental sources aren't the
+        // ones of the dump.
+        self.supplemental_pages -= supplemented;
+        if supplemented != 0 {
+            self.page_tables.clear();
+        }
+
+        Ok(RefreshDelta {
+            new_pages: landed.len() as u64,
+            pending_pages: self.unwritten.len() as u64,
+            file_size,
+        })
+    }
+
+    /// Is every page of the dump in the dump file, and does the header say
+    /// that the writer is done?
+    pub fn is_complete(&self) -> bool {
+        !self.writing && self.unwritten.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::testing::DumpBuilder;
+    use crate::{AddrTranslationError, Gpa, MissingRange, MissingReason};
+
+    #[test]
+    fn refresh() {
+        let dump = DumpBuilder::new()
+            .write_phys(0x1_000, b"first")
+            .write_phys(0x10_0000, b"second")
+            .build();
+        let path =
+            std::env::temp_dir().join(format!("kdmp-parser-incomplete-{}.dmp", std::process::id()));
+
+        // The last page is half written..
+        let mut file = File::create(&path).unwrap();
+        file.write_all(&dump[..dump.len() - 0x800]).unwrap();
+        let mut parser = KernelDumpParser::open_incomplete(&path).unwrap();
+        assert!(!parser.is_complete());
+        assert!(parser.missing_ranges().any(|range| range
+            == MissingRange {
+                gpa: Gpa::new(0x10_0000)..Gpa::new(0x10_1000),
+                reason: MissingReason::Truncated,
+            }));
+        let mut buffer = [0; 5];
+        parser
+            .phys_read_exact(Gpa::new(0x1_000), &mut buffer)
+            .unwrap();
+        assert_eq!(&buffer, b"first");
+        let mut buffer = [0; 6];
+        assert!(matches!(
+            parser.phys_read_exact(Gpa::new(0x10_0000), &mut buffer),
+            Err(KdmpParserError::AddrTranslation(
+                AddrTranslationError::Phys(_)
+            ))
+        ));
+
+        // ..and isn't indexed until all of it is there..
+        file.write_all(&dump[dump.len() - 0x800..dump.len() - 1])
+            .unwrap();
+        assert_eq!(parser.refresh().unwrap(), RefreshDelta {
+            new_pages: 0,
+            pending_pages: 1,
+            file_size: dump.len() as u64 - 1,
+        });
+        file.write_all(&dump[dump.len() - 1..]).unwrap();
+        assert_eq!(parser.refresh().unwrap(), RefreshDelta {
+            new_pages: 1,
+            pending_pages: 0,
+            file_size: dump.len() as u64,
+        });
+        assert!(parser.is_complete());
+        parser
+            .phys_read_exact(Gpa::new(0x10_0000), &mut buffer)
+            .unwrap();
+        assert_eq!(&buffer, b"second");
+
+        // ..and the file can't shrink.
+        file.set_len(0x2_000).unwrap();
+        assert!(matches!(
+            parser.refresh(),
+            Err(KdmpParserError::InvalidData(_))
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn preallocated() {
+        /// Where the `WriterStatus` is in the header.
+        const WRITER_STATUS: usize = 0x1048;
+        const STATUS_PENDING: u32 = 0x103;
+        let mut dump = DumpBuilder::new()
+            .write_phys(0x1_000, b"first")
+            .write_phys(0x10_0000, b"second")
+            .build();
+        let second = KernelDumpParser::from_bytes(dump.clone())
+            .unwrap()
+            .phys_translate(Gpa::new(0x10_0000))
+            .unwrap() as usize;
+        dump[WRITER_STATUS..WRITER_STATUS + 4].copy_from_slice(&STATUS_PENDING.to_le_bytes());
+        let path = std::env::temp_dir().join(format!(
+            "kdmp-parser-preallocated-{}.dmp",
+            std::process::id()
+        ));
+
+        // The file has its full size from the start, but the writer isn't done..
+        let mut file = File::create(&path).unwrap();
+        file.set_len(dump.len() as u64).unwrap();
+        file.write_all(&dump[..second]).unwrap();
+        let mut parser = KernelDumpParser::open_incomplete(&path).unwrap();
+        assert!(!parser.is_complete());
+        let mut buffer = [0; 6];
+        assert!(matches!(
+            parser.phys_read_exact(Gpa::new(0x10_0000), &mut buffer),
+            Err(KdmpParserError::AddrTranslation(
+                AddrTranslationError::Phys(_)
+            ))
+        ));
+
+        // ..so the pages being there isn't enough..
+        file.write_all(&dump[second..]).unwrap();
+        parser.refresh().unwrap();
+        assert!(!parser.is_complete());
+
+        // ..until the header says so.
+        file.seek(io::SeekFrom::Start(WRITER_STATUS as u64))
+            .unwrap();
+        file.write_all(&0u32.to_le_bytes()).unwrap();
+        let delta = parser.refresh().unwrap();
+        assert_eq!(delta.pending_pages, 0);
+        assert!(parser.is_complete());
+        parser
+            .phys_read_exact(Gpa::new(0x10_0000), &mut buffer)
+            .unwrap();
+        assert_eq!(&buffer, b"second");
+        parser
+            .phys_read_exact(Gpa::new(0x1_000), &mut buffer[..5])
+            .unwrap();
+        assert_eq!(&buffer[..5], b"first");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
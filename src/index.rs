@@ -0,0 +1,663 @@
+//! This contains what is needed to save what parsing a dump derives from it,
+//! like its physical memory map and its modules, to an index file, and to
+//! reopen the dump with it without parsing it again (see
+//! [`KernelDumpParser::save_index`] and [`KernelDumpParser::open_with_index`]).
+//!
+//! The index starts with a magic, the version of its format and a hash of the
+//! headers and of the size of the dump it has been saved for (along with the
+//! options it has been parsed with); a hash of its content ends it. An index
+//! that doesn't match the dump, or that is corrupted, is ignored.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::{KernelDumpParser, Warning};
+//! let parser = KernelDumpParser::open_with_index(&"full.dmp", &"full.idx").unwrap();
+//! if parser
+//!     .warnings()
+//!     .iter()
+//!     .any(|warning| matches!(warning, Warning::InvalidIndex { .. }))
+//! {
+//!     parser.save_index(&"full.idx").unwrap();
+//! }
+//! ```
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::error::{Result, Warning};
+use crate::gxa::Gxa;
+use crate::module::{ModuleEntry, ModuleMap, ModuleOrigin};
+use crate::parse::{EXTENDED_HEADERS, INVALID_MODULE_LIST_REASONS};
+use crate::structs::{FromLeBytes, KdDebuggerData64, Page, PhysmemMap};
+use crate::supplement::is_supplemental;
+use crate::utf16::StringPolicy;
+use crate::{Gpa, Gva, KernelDumpParser, ParserOptions};
+
+/// The magic an index starts with.
+const MAGIC: &[u8; 8] = b"KDMPIDX\0";
+
+/// The version of the format of the index; an index of another version is
+/// ignored.
+const VERSION: u32 = 2;
+
+/// Size of the hash that ends an index.
+const CHECKSUM_SIZE: usize = 8;
+
+/// Hash `bytes` with FNV-1a, starting from `hash`.
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100_0000_01b3)
+    })
+}
+
+/// The basis of the FNV-1a hashes.
+const FNV1A_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Identify a dump, by its headers and its size, and the options that change
+/// what parsing it derives; an index is only used for the dump, and the
+/// options, it has been saved for.
+pub(crate) fn fingerprint(raw_header: &[u8], file_size: u64, options: &ParserOptions) -> u64 {
+    let string_policy = match options.string_policy {
+        StringPolicy::Lossy => 0u8,
+        StringPolicy::Strict => 1,
+    };
+
+    [
+        &file_size.to_le_bytes()[..],
+        &options.page_size.to_le_bytes(),
+        &(options.max_modules as u64).to_le_bytes(),
+        &[u8::from(options.strict), string_policy],
+    ]
+    .into_iter()
+    .fold(fnv1a(FNV1A_BASIS, raw_header), fnv1a)
+}
+
+/// What parsing a dump derives from it; see [`KernelDumpParser::save_index`].
+pub(crate) struct Index {
+    pub(crate) physmem: PhysmemMap,
+    dtb: Gpa,
+    max_physical_address: Option<Gpa>,
+    kernel_modules: Vec<ModuleEntry>,
+    user_modules: Vec<ModuleEntry>,
+    nt_base: Option<Gva>,
+    /// The raw bytes of the KDDEBUGGER_DATA_BLOCK.
+    kd_debugger_data_block: Option<Vec<u8>>,
+    crashing_prcb: Option<(u32, Gva)>,
+    warnings: Vec<Warning>,
+    /// The translations cached when the index was saved.
+    tlb: Vec<((Gpa, Gva), Gpa)>,
+}
+
+/// Serialize the content of an index.
+#[derive(Default)]
+struct Encoder {
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn len(&mut self, len: usize) {
+        self.u64(len as u64);
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.len(bytes.len());
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    fn option<T>(&mut self, value: Option<T>, encode: impl FnOnce(&mut Self, T)) {
+        match value {
+            Some(value) => {
+                self.u8(1);
+                encode(self, value);
+            }
+            None => self.u8(0),
+        }
+    }
+
+    fn range(&mut self, range: &Range<Gva>) {
+        self.u64(range.start.u64());
+        self.u64(range.end.u64());
+    }
+
+    fn module(&mut self, module: &ModuleEntry) {
+        self.range(&module.at);
+        self.bytes(module.name.as_bytes());
+        self.option(module.entry_point, |e, gva| e.u64(gva.u64()));
+        self.u32(module.size_of_image);
+        self.option(module.timestamp, Self::u32);
+        self.option(module.checksum, Self::u32);
+        self.option(module.load_count, Self::u16);
+        match module.origin {
+            ModuleOrigin::Kernel => self.u8(0),
+            ModuleOrigin::User { process } => {
+                self.u8(1);
+                self.option(process, Self::u64);
+            }
+        }
+    }
+
+    fn modules<'a>(&mut self, modules: impl ExactSizeIterator<Item = &'a ModuleEntry>) {
+        self.len(modules.len());
+        for module in modules {
+            self.module(module);
+        }
+    }
+
+    fn warning(&mut self, warning: &Warning) {
+        match warning {
+            Warning::OverlappingModules { first, second } => {
+                self.u8(0);
+                for (range, name) in [first, second] {
+                    self.range(range);
+                    self.bytes(name.as_bytes());
+                }
+            }
+            Warning::ModuleListRecovered { nt_base } => {
+                self.u8(1);
+                self.u64(nt_base.u64());
+            }
+            Warning::InvalidModuleName { entry, name } => {
+                self.u8(2);
+                self.u64(entry.u64());
+                self.bytes(name.as_bytes());
+            }
+            Warning::DuplicateModule { first, second } => {
+                self.u8(3);
+                self.module(first);
+                self.module(second);
+            }
+            Warning::InvalidModuleList { head, kept, reason } => {
+                self.u8(4);
+                self.u64(head.u64());
+                self.len(*kept);
+                self.bytes(reason.as_bytes());
+            }
+            Warning::UnknownHeaderExtension {
+                header,
+                extra_bytes,
+            } => {
+                self.u8(5);
+                self.bytes(header.as_bytes());
+                self.u64(*extra_bytes);
+            }
+            // They are about how the dump was opened, not about the dump file.
+            Warning::InvalidIndex { .. } | Warning::SupplementalConflict { .. } => unreachable!(),
+        }
+    }
+}
+
+/// Deserialize the content of an index; `None` means it is truncated.
+struct Decoder<'bytes> {
+    bytes: &'bytes [u8],
+}
+
+impl<'bytes> Decoder<'bytes> {
+    fn take(&mut self, len: usize) -> Option<&'bytes [u8]> {
+        if len > self.bytes.len() {
+            return None;
+        }
+
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+
+        Some(taken)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn len(&mut self) -> Option<usize> {
+        usize::try_from(self.u64()?).ok()
+    }
+
+    fn bytes(&mut self) -> Option<&'bytes [u8]> {
+        let len = self.len()?;
+
+        self.take(len)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        String::from_utf8(self.bytes()?.to_vec()).ok()
+    }
+
+    fn option<T>(&mut self, decode: impl FnOnce(&mut Self) -> Option<T>) -> Option<Option<T>> {
+        match self.u8()? {
+            0 => Some(None),
+            1 => decode(self).map(Some),
+            _ => None,
+        }
+    }
+
+    /// Decode `len` elements; the length isn't trusted to reserve memory.
+    fn vec<T>(&mut self, mut decode: impl FnMut(&mut Self) -> Option<T>) -> Option<Vec<T>> {
+        let len = self.len()?;
+        let mut elements = Vec::with_capacity(len.min(self.bytes.len()));
+        for _ in 0..len {
+            elements.push(decode(self)?);
+        }
+
+        Some(elements)
+    }
+
+    fn range(&mut self) -> Option<Range<Gva>> {
+        Some(Gva::new(self.u64()?)..Gva::new(self.u64()?))
+    }
+
+    fn module(&mut self) -> Option<ModuleEntry> {
+        Some(ModuleEntry {
+            at: self.range()?,
+            name: self.string()?,
+            entry_point: self.option(|d| d.u64().map(Gva::new))?,
+            size_of_image: self.u32()?,
+            timestamp: self.option(Self::u32)?,
+            checksum: self.option(Self::u32)?,
+            load_count: self.option(Self::u16)?,
+            origin: match self.u8()? {
+                0 => ModuleOrigin::Kernel,
+                1 => ModuleOrigin::User {
+                    process: self.option(Self::u64)?,
+                },
+                _ => return None,
+            },
+        })
+    }
+
+    fn warning(&mut self) -> Option<Warning> {
+        Some(match self.u8()? {
+            0 => Warning::OverlappingModules {
+                first: (self.range()?, self.string()?),
+                second: (self.range()?, self.string()?),
+            },
+            1 => Warning::ModuleListRecovered {
+                nt_base: Gva::new(self.u64()?),
+            },
+            2 => Warning::InvalidModuleName {
+                entry: Gva::new(self.u64()?),
+                name: self.string()?,
+            },
+            3 => Warning::DuplicateModule {
+                first: Box::new(self.module()?),
+                second: Box::new(self.module()?),
+            },
+            4 => Warning::InvalidModuleList {
+                head: Gva::new(self.u64()?),
+                kept: self.len()?,
+                reason: {
+                    let reason = self.bytes()?;
+                    INVALID_MODULE_LIST_REASONS
+                        .into_iter()
+                        .find(|known| known.as_bytes() == reason)?
+                },
+            },
+            5 => Warning::UnknownHeaderExtension {
+                header: {
+                    let header = self.bytes()?;
+                    EXTENDED_HEADERS
+                        .into_iter()
+                        .find(|known| known.as_bytes() == header)?
+                },
+                extra_bytes: self.u64()?,
+            },
+            _ => return None,
+        })
+    }
+}
+
+impl Index {
+    /// Read the index at `path`, if it has been saved for the dump, and with
+    /// the options, `fingerprint` identifies; otherwise, why it can't be used.
+    pub(crate) fn load(path: &Path, fingerprint: u64) -> std::result::Result<Self, &'static str> {
+        let bytes = fs::read(path).map_err(|_| "it couldn't be read")?;
+        let Some(content_size) = bytes.len().checked_sub(CHECKSUM_SIZE) else {
+            return Err("it is truncated");
+        };
+
+        let (content, checksum) = bytes.split_at(content_size);
+        let mut decoder = Decoder { bytes: content };
+        if decoder.take(MAGIC.len()) != Some(&MAGIC[..]) {
+            return Err("it isn't an index");
+        }
+
+        if decoder.u32() != Some(VERSION) {
+            return Err("its version isn't supported");
+        }
+
+        if fnv1a(FNV1A_BASIS, content).to_le_bytes() != checksum {
+            return Err("it is corrupted");
+        }
+
+        if decoder.u64() != Some(fingerprint) {
+            return Err("it has been saved for another dump, or with other options");
+        }
+
+        Self::decode(&mut decoder).ok_or("it is corrupted")
+    }
+
+    fn decode(d: &mut Decoder) -> Option<Self> {
+        let dtb = Gpa::new(d.u64()?);
+        let max_physical_address = d.option(|d| d.u64().map(Gpa::new))?;
+
+        // The physical memory map is saved as runs of contiguous pages.
+        let mut physmem = PhysmemMap::new();
+        for _ in 0..d.len()? {
+            let (gpa, offset, pages) = (d.u64()?, d.u64()?, d.u64()?);
+            for page in 0..pages {
+                let delta = page.checked_mul(Page::size())?;
+                physmem.insert(
+                    Gpa::new(gpa.checked_add(delta)?),
+                    offset.checked_add(delta)?,
+                );
+            }
+        }
+
+        let kernel_modules = d.vec(Decoder::module)?;
+        let user_modules = d.vec(Decoder::module)?;
+        let nt_base = d.option(|d| d.u64().map(Gva::new))?;
+        let kd_debugger_data_block = d.option(|d| d.bytes().map(<[u8]>::to_vec))?;
+        if kd_debugger_data_block
+            .as_ref()
+            .is_some_and(|block| block.len() != KdDebuggerData64::SIZE)
+        {
+            return None;
+        }
+
+        let crashing_prcb = d.option(|d| Some((d.u32()?, Gva::new(d.u64()?))))?;
+        let warnings = d.vec(Decoder::warning)?;
+        let tlb =
+            d.vec(|d| Some(((Gpa::new(d.u64()?), Gva::new(d.u64()?)), Gpa::new(d.u64()?))))?;
+
+        if !d.bytes.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            physmem,
+            dtb,
+            max_physical_address,
+            kernel_modules,
+            user_modules,
+            nt_base,
+            kd_debugger_data_block,
+            crashing_prcb,
+            warnings,
+            tlb,
+        })
+    }
+}
+
+impl KernelDumpParser {
+    /// Open the dump at `dump_path` like [`KernelDumpParser::new`], but
+    /// restore what parsing it derives (its physical memory map, its modules,
+    /// the KDDEBUGGER_DATA_BLOCK, ...) from the index at `index_path`, saved
+    /// by [`KernelDumpParser::save_index`], instead of parsing it again.
+    ///
+    /// An index that can't be read, that is corrupted, or that has been saved
+    /// for another dump or with other options is ignored: the dump is parsed,
+    /// and a [`Warning::InvalidIndex`] is recorded.
+    pub fn open_with_index<P, Q>(dump_path: &P, index_path: &Q) -> Result<Self>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        Self::open_with_index_and_options(dump_path, index_path, ParserOptions::default())
+    }
+
+    /// Open the dump at `dump_path` with `options` and the index at
+    /// `index_path`; see [`KernelDumpParser::open_with_index`].
+    pub fn open_with_index_and_options<P, Q>(
+        dump_path: &P,
+        index_path: &Q,
+        options: ParserOptions,
+    ) -> Result<Self>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        Self::open(dump_path.as_ref(), options, Some(index_path.as_ref()))
+    }
+
+    /// Save what parsing the dump has derived from it to the index at
+    /// `index_path`, to reopen it with [`KernelDumpParser::open_with_index`].
+    /// The translations cached so far are saved as well, to seed the cache of
+    /// the parsers that reopen it.
+    pub fn save_index<P>(&self, index_path: &P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let mut e = Encoder::default();
+        e.bytes.extend_from_slice(MAGIC);
+        e.u32(VERSION);
+        e.u64(fingerprint(&self.raw_header, self.file_size, &self.options));
+        e.u64(self.dtb.u64());
+        e.option(self.max_physical_address, |e, gpa| e.u64(gpa.u64()));
+
+        // Coalesce the physical memory map in runs of contiguous pages; the
+        // supplemental pages aren't in the dump file, so they are left out.
+        let mut runs = Vec::<(u64, u64, u64)>::new();
+        for (&gpa, &offset) in &self.physmem {
+            if is_supplemental(offset) {
+                continue;
+            }
+
+            match runs.last_mut() {
+                Some((start, start_offset, pages))
+                    if *start + (*pages * Page::size()) == gpa.u64()
+                        && *start_offset + (*pages * Page::size()) == offset =>
+                {
+                    *pages += 1;
+                }
+                _ => runs.push((gpa.u64(), offset, 1)),
+            }
+        }
+
+        e.len(runs.len());
+        for (gpa, offset, pages) in runs {
+            e.u64(gpa);
+            e.u64(offset);
+            e.u64(pages);
+        }
+
+        e.modules(self.kernel_modules.entries_in_list_order());
+        e.modules(self.user_modules.entries_in_list_order());
+        e.option(self.nt_base, |e, gva| e.u64(gva.u64()));
+        let kd_debugger_data_block = match self.kd_debugger_data_block {
+            Some(_) => {
+                let mut block = vec![0; KdDebuggerData64::SIZE];
+                self.virt_read_exact(self.headers().kd_debugger_data_block, &mut block)?;
+
+                Some(block)
+            }
+            None => None,
+        };
+        e.option(kd_debugger_data_block, |e, block| e.bytes(&block));
+        e.option(self.crashing_prcb, |e, (idx, prcb)| {
+            e.u32(idx);
+            e.u64(prcb.u64());
+        });
+
+        let warnings = self
+            .warnings
+            .iter()
+            .filter(|warning| {
+                !matches!(
+                    warning,
+                    Warning::InvalidIndex { .. } | Warning::SupplementalConflict { .. }
+                )
+            })
+            .collect::<Vec<_>>();
+        e.len(warnings.len());
+        for warning in warnings {
+            e.warning(warning);
+        }
+
+        let tlb = self.tlb.lock().unwrap();
+        e.len(tlb.len());
+        for (&(dtb, gva), &gpa) in tlb.iter() {
+            e.u64(dtb.u64());
+            e.u64(gva.u64());
+            e.u64(gpa.u64());
+        }
+
+        let checksum = fnv1a(FNV1A_BASIS, &e.bytes);
+        e.u64(checksum);
+
+        Ok(fs::write(index_path, e.bytes)?)
+    }
+
+    /// Restore what parsing the dump derives from `index`; the physical
+    /// memory map has been restored already.
+    pub(crate) fn restore_index(&mut self, index: Index) {
+        self.dtb = index.dtb;
+        if self.max_physical_address.is_none() {
+            self.max_physical_address = index.max_physical_address;
+        }
+
+        // The modules have been checked when the index was saved, so building
+        // their maps doesn't warn.
+        self.kernel_modules = ModuleMap::build(index.kernel_modules).0;
+        self.user_modules = ModuleMap::build(index.user_modules).0;
+        #[cfg(feature = "object")]
+        for module in self
+            .kernel_modules
+            .entries()
+            .chain(self.user_modules.entries())
+        {
+            self.module_images.entry(module.at.start).or_default();
+        }
+
+        self.nt_base = index.nt_base;
+        if let Some(block) = index.kd_debugger_data_block {
+            let block = Box::new(KdDebuggerData64::from_le_bytes(&block));
+            self.profile.apply_kd_debugger_data_block(&block);
+            self.kd_debugger_data_block = Some(block);
+        }
+
+        self.crashing_prcb = index.crashing_prcb;
+        if let Some((idx, prcb)) = self.crashing_prcb {
+            if let Ok(Some(context)) = self.prcb_context(prcb) {
+                self.fall_back_on_prcb_context(idx, prcb, context);
+            }
+        }
+
+        self.warnings = index.warnings;
+        self.tlb.lock().unwrap().extend(index.tlb);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::testing::DumpBuilder;
+    use crate::PxeFlags;
+
+    /// A path in the temporary directory, unique to the test.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("kdmp-parser-{name}-{}", std::process::id()))
+    }
+
+    #[test]
+    fn index() {
+        let nt = 0xffff_f800_0000_0000;
+        let dump = DumpBuilder::new()
+            .map_virt(nt, 0x10_000, PxeFlags::Present)
+            .write_phys(0x10_000, b"MZ")
+            .module(nt..nt + 0x1_000, "ntoskrnl.exe")
+            .module(nt..nt + 0x1_000, "ntoskrnl.exe")
+            .build();
+        let (dump_path, index_path) = (temp_path("index.dmp"), temp_path("index.idx"));
+        fs::write(&dump_path, &dump).unwrap();
+
+        // There's no index yet, so the dump is parsed..
+        let parser = KernelDumpParser::open_with_index(&dump_path, &index_path).unwrap();
+        assert!(matches!(parser.warnings(), [
+            Warning::InvalidIndex {
+                reason: "it couldn't be read"
+            },
+            Warning::DuplicateModule { .. }
+        ]));
+        assert!(parser.virt_translate(Gva::new(nt)).is_ok());
+        parser.save_index(&index_path).unwrap();
+
+        // ..and then it is restored from the index.
+        let indexed = KernelDumpParser::open_with_index(&dump_path, &index_path).unwrap();
+        assert!(matches!(indexed.warnings(), [
+            Warning::DuplicateModule { .. }
+        ]));
+        assert!(indexed.physmem().eq(parser.physmem()));
+        assert!(indexed
+            .kernel_module_entries()
+            .eq(parser.kernel_module_entries()));
+        assert_eq!(
+            indexed.max_physical_address(),
+            parser.max_physical_address()
+        );
+        assert_eq!(
+            indexed.tlb.lock().unwrap().len(),
+            parser.tlb.lock().unwrap().len()
+        );
+        assert_eq!(indexed.find_module(Gva::new(nt)).unwrap().1, "ntoskrnl.exe");
+
+        // An index saved with other options isn't used..
+        let options = ParserOptions {
+            max_modules: 1,
+            ..Default::default()
+        };
+        let other = KernelDumpParser::open_with_index_and_options(&dump_path, &index_path, options)
+            .unwrap();
+        assert!(matches!(other.warnings(), [
+            Warning::InvalidIndex {
+                reason: "it has been saved for another dump, or with other options"
+            },
+            ..
+        ]));
+
+        // ..and neither is a corrupted one.
+        let mut index = fs::read(&index_path).unwrap();
+        let last = index.len() - 1 - CHECKSUM_SIZE;
+        index[last] ^= 1;
+        fs::write(&index_path, &index).unwrap();
+        let corrupted = KernelDumpParser::open_with_index(&dump_path, &index_path).unwrap();
+        assert!(matches!(corrupted.warnings(), [
+            Warning::InvalidIndex {
+                reason: "it is corrupted"
+            },
+            ..
+        ]));
+        assert!(corrupted.physmem().eq(parser.physmem()));
+
+        fs::remove_file(&dump_path).unwrap();
+        fs::remove_file(&index_path).unwrap();
+    }
+}
@@ -0,0 +1,167 @@
+//! This contains [`DumpInfo`], a summary of a crash-dump that is meant to be
+//! displayed to a user; think `.dump /i` in WinDbg.
+use std::fmt::{self, Display};
+
+use crate::gxa::Gxa;
+use crate::structs::{DumpType, Page};
+use crate::{Gpa, Gva, KernelDumpParser};
+
+/// A summary of a crash-dump. Use its [`Display`] implementation to get a
+/// human readable version of it.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use kdmp_parser::KernelDumpParser;
+/// let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+/// println!("{}", parser.info());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DumpInfo {
+    /// Which type of dump is it?
+    pub dump_type: DumpType,
+    /// Size of the dump file in bytes.
+    pub file_size: u64,
+    /// Number of physical pages available in the dump.
+    pub pages: u64,
+    /// Number of runs of physically contiguous pages.
+    pub runs: u64,
+    /// Number of processors of the machine.
+    pub processors: u32,
+    /// Major version of the operating system (`0xf` for free builds, `0xc` for
+    /// checked builds).
+    pub major_version: u32,
+    /// Build number of the operating system.
+    pub build_number: u32,
+    /// The bugcheck code.
+    pub bugcheck_code: u32,
+    /// The bugcheck parameters.
+    pub bugcheck_parameters: [u64; 4],
+    /// Directory table base of the context that crashed.
+    pub directory_table_base: Gpa,
+    /// Base address of `nt`, if it could be found.
+    pub nt_base: Option<Gva>,
+    /// Number of kernel modules.
+    pub kernel_modules: usize,
+    /// Number of user modules.
+    pub user_modules: usize,
+    /// Number of annotations.
+    pub annotations: usize,
+}
+
+impl DumpInfo {
+    /// Build the summary of a dump.
+    pub(crate) fn new(parser: &KernelDumpParser) -> Self {
+        let headers = parser.headers();
+        let mut runs = 0;
+        let mut next_gpa = None;
+        for (gpa, _) in parser.physmem() {
+            if next_gpa != Some(gpa) {
+                runs += 1;
+            }
+
+            next_gpa = gpa.u64().checked_add(Page::size()).map(Gpa::new);
+        }
+
+        Self {
+            dump_type: parser.dump_type(),
+            file_size: parser.file_size(),
+            pages: parser.physmem_len(),
+            runs,
+            processors: headers.number_processors,
+            major_version: headers.major_version,
+            build_number: headers.minor_version,
+            bugcheck_code: headers.bug_check_code,
+            bugcheck_parameters: headers.bug_check_code_parameters,
+            directory_table_base: Gpa::new(headers.directory_table_base),
+            nt_base: parser.nt_base(),
+            kernel_modules: parser.kernel_modules().len(),
+            user_modules: parser.user_modules().len(),
+            annotations: parser.annotations().len(),
+        }
+    }
+}
+
+impl Display for DumpInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [p0, p1, p2, p3] = self.bugcheck_parameters;
+        writeln!(f, "{:<16}: {:?}", "Dump type", self.dump_type)?;
+        writeln!(f, "{:<16}: {:#x} bytes", "File size", self.file_size)?;
+        writeln!(f, "{:<16}: {:#x}", "Physical pages", self.pages)?;
+        writeln!(f, "{:<16}: {:#x}", "Physical runs", self.runs)?;
+        writeln!(f, "{:<16}: {}", "Processors", self.processors)?;
+        writeln!(
+            f,
+            "{:<16}: {} ({:#x})",
+            "Build", self.build_number, self.major_version
+        )?;
+        writeln!(f, "{:<16}: {:#x}", "Bugcheck code", self.bugcheck_code)?;
+        writeln!(
+            f,
+            "{:<16}: {p0:#018x} {p1:#018x} {p2:#018x} {p3:#018x}",
+            "Bugcheck params"
+        )?;
+        writeln!(
+            f,
+            "{:<16}: {:#018x}",
+            "Dtb",
+            self.directory_table_base.u64()
+        )?;
+        match self.nt_base {
+            Some(nt_base) => writeln!(f, "{:<16}: {:#018x}", "Nt base", nt_base.u64())?,
+            None => writeln!(f, "{:<16}: unknown", "Nt base")?,
+        };
+        writeln!(f, "{:<16}: {}", "Kernel modules", self.kernel_modules)?;
+        writeln!(f, "{:<16}: {}", "User modules", self.user_modules)?;
+        write!(f, "{:<16}: {}", "Annotations", self.annotations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let info = DumpInfo {
+            dump_type: DumpType::Bmp,
+            file_size: 0x1_000,
+            pages: 0x10,
+            runs: 2,
+            processors: 4,
+            major_version: 0xf,
+            build_number: 19041,
+            bugcheck_code: 0xe2,
+            bugcheck_parameters: [1, 2, 3, 4],
+            directory_table_base: Gpa::new(0x1ad000),
+            nt_base: Some(Gva::new(0xfffff805_10600000)),
+            kernel_modules: 0x10,
+            user_modules: 0,
+            annotations: 1,
+        };
+
+        assert_eq!(
+            info.to_string(),
+            "\
+Dump type       : Bmp
+File size       : 0x1000 bytes
+Physical pages  : 0x10
+Physical runs   : 0x2
+Processors      : 4
+Build           : 19041 (0xf)
+Bugcheck code   : 0xe2
+Bugcheck params : 0x0000000000000001 0x0000000000000002 0x0000000000000003 0x0000000000000004
+Dtb             : 0x00000000001ad000
+Nt base         : 0xfffff80510600000
+Kernel modules  : 16
+User modules    : 0
+Annotations     : 1"
+        );
+
+        let info = DumpInfo {
+            nt_base: None,
+            ..info
+        };
+        assert!(info.to_string().contains("Nt base         : unknown\n"));
+    }
+}
@@ -0,0 +1,262 @@
+//! This contains what is needed to find the code of a module that has been
+//! patched in memory (see [`Patch`]), like inline hooks, by comparing it to
+//! the file it has been loaded from.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::{Gxa, KernelDumpParser};
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! let reference = std::fs::read("ntoskrnl.exe").unwrap();
+//! let nt = parser
+//!     .kernel_module_entries()
+//!     .find(|module| module.name.ends_with("ntoskrnl.exe"))
+//!     .unwrap();
+//!
+//! for patch in parser.module_integrity(nt, &reference).unwrap() {
+//!     println!(
+//!         "{:#x}: {:02x?} -> {:02x?}",
+//!         patch.gva.u64(),
+//!         patch.original,
+//!         patch.current
+//!     );
+//! }
+//! ```
+use crate::error::Result;
+use crate::gxa::Gxa;
+use crate::pe::{
+    apply_relocations, section_headers, PeHeaders, IMAGE_DIRECTORY_ENTRY_BASERELOC,
+    IMAGE_DIRECTORY_ENTRY_IAT, IMAGE_SCN_MEM_DISCARDABLE, IMAGE_SCN_MEM_EXECUTE,
+};
+use crate::structs::Page;
+use crate::{Gva, KdmpParserError, KernelDumpParser, ModuleEntry};
+
+/// Bytes of the code of a module that differ from the file it has been loaded
+/// from; see [`KernelDumpParser::module_integrity`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Patch {
+    /// Where the bytes are in memory.
+    pub gva: Gva,
+    /// Where the bytes are in the file.
+    pub file_offset: u64,
+    /// The bytes in the file, relocated.
+    pub original: Vec<u8>,
+    /// The bytes in memory.
+    pub current: Vec<u8>,
+}
+
+impl KernelDumpParser {
+    /// Compare the code of `module` to `reference`, the file it has been
+    /// loaded from (off the disk, or off a symbol server). The executable
+    /// sections of `reference` are relocated to where the module is loaded,
+    /// so that the fixups of the loader don't show up, and the bytes that
+    /// differ are returned, coalesced in [`Patch`]es sorted by address.
+    ///
+    /// The import address table, that the loader fills, is left out, and so
+    /// are the discardable sections, that are freed once the module is
+    /// initialized. The pages that aren't in the dump are skipped.
+    pub fn module_integrity(&self, module: &ModuleEntry, reference: &[u8]) -> Result<Vec<Patch>> {
+        let headers = PeHeaders::parse(reference)?;
+        let size_of_image = module.at.end.u64() - module.at.start.u64();
+        if u64::from(headers.size_of_image) != size_of_image {
+            return Err(KdmpParserError::InvalidData(
+                "the reference isn't the image of the module",
+            ));
+        }
+
+        // Lay out the reference like the loader maps it..
+        let sections = section_headers(reference)?;
+        let mut image = vec![0; headers.size_of_image as usize];
+        let size_of_headers = (headers.size_of_headers as usize)
+            .min(reference.len())
+            .min(image.len());
+        image[..size_of_headers].copy_from_slice(&reference[..size_of_headers]);
+        for section in &sections {
+            let rva = section.virtual_address as usize;
+            let file_offset = section.pointer_to_raw_data as usize;
+            let len = (section.size_of_raw_data as usize)
+                .min(reference.len().saturating_sub(file_offset))
+                .min(image.len().saturating_sub(rva));
+            if len == 0 {
+                continue;
+            }
+
+            image[rva..rva + len].copy_from_slice(&reference[file_offset..file_offset + len]);
+        }
+
+        // ..and relocate it where the module is.
+        if let Some(directory) = headers.data_directory(IMAGE_DIRECTORY_ENTRY_BASERELOC) {
+            let start = directory.rva as usize;
+            let relocations = start
+                .checked_add(directory.size as usize)
+                .and_then(|end| image.get(start..end))
+                .ok_or(KdmpParserError::InvalidData("invalid relocation directory"))?
+                .to_vec();
+            let delta = module.at.start.u64().wrapping_sub(headers.image_base);
+            apply_relocations(&mut image, &relocations, delta)?;
+        }
+
+        let iat = headers
+            .data_directory(IMAGE_DIRECTORY_ENTRY_IAT)
+            .map(|directory| directory.rva..directory.rva.saturating_add(directory.size));
+        let in_iat = |rva: u32| iat.as_ref().is_some_and(|iat| iat.contains(&rva));
+
+        let mut patches = Vec::<Patch>::new();
+        let mut page = vec![0; Page::size() as usize];
+        for section in sections.iter().filter(|section| {
+            section.characteristics & IMAGE_SCN_MEM_EXECUTE != 0
+                && section.characteristics & IMAGE_SCN_MEM_DISCARDABLE == 0
+        }) {
+            // Only the bytes that are in the file can be compared.
+            let size = match section.virtual_size {
+                0 => section.size_of_raw_data,
+                size => size.min(section.size_of_raw_data),
+            };
+
+            let start = section.virtual_address;
+            let end = start.saturating_add(size).min(headers.size_of_image);
+            let mut rva = start;
+            while rva < end {
+                let gva = Gva::new(module.at.start.u64() + u64::from(rva));
+                let len = ((Page::size() - gva.offset()) as u32).min(end - rva);
+                let current = &mut page[..len as usize];
+                match self.virt_read_exact(gva, current) {
+                    Err(
+                        KdmpParserError::AddrTranslation(..)
+                        | KdmpParserError::PartialVirtRead
+                        | KdmpParserError::PartialPhysRead,
+                    ) => {
+                        rva += len;
+                        continue;
+                    }
+                    res => res?,
+                }
+
+                let original = &image[rva as usize..(rva + len) as usize];
+                for (idx, (&original, &current)) in original.iter().zip(current.iter()).enumerate()
+                {
+                    let byte_rva = rva + idx as u32;
+                    if original == current || in_iat(byte_rva) {
+                        continue;
+                    }
+
+                    let gva = Gva::new(module.at.start.u64() + u64::from(byte_rva));
+                    match patches.last_mut() {
+                        Some(last) if last.gva.u64() + last.current.len() as u64 == gva.u64() => {
+                            last.original.push(original);
+                            last.current.push(current);
+                        }
+                        _ => patches.push(Patch {
+                            gva,
+                            file_offset: u64::from(section.pointer_to_raw_data)
+                                + u64::from(byte_rva - start),
+                            original: vec![original],
+                            current: vec![current],
+                        }),
+                    }
+                }
+
+                rva += len;
+            }
+        }
+
+        patches.sort_by_key(|patch| patch.gva);
+
+        Ok(patches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::DumpBuilder;
+    use crate::{Gva, KdmpParserError, KernelDumpParser, PxeFlags};
+
+    /// A file with a `.text` section that spans two pages at 0x1000, whose
+    /// import address table is at 0x1080 and that has a 64-bit address at
+    /// 0x1010 to relocate, and a `.reloc` section at 0x3000.
+    fn reference() -> Vec<u8> {
+        let mut file = vec![0; 0x1_800];
+        file[0..2].copy_from_slice(b"MZ");
+        file[0x3c..0x40].copy_from_slice(&0x80u32.to_le_bytes());
+        file[0x80..0x84].copy_from_slice(b"PE\0\0");
+        file[0x84..0x86].copy_from_slice(&0x8664u16.to_le_bytes());
+        file[0x86..0x88].copy_from_slice(&2u16.to_le_bytes());
+        file[0x94..0x96].copy_from_slice(&0xf0u16.to_le_bytes());
+        file[0x98..0x9a].copy_from_slice(&0x20bu16.to_le_bytes());
+        file[0xb0..0xb8].copy_from_slice(&0x1_4000_0000u64.to_le_bytes());
+        file[0xd0..0xd4].copy_from_slice(&0x4_000u32.to_le_bytes());
+        file[0xd4..0xd8].copy_from_slice(&0x400u32.to_le_bytes());
+        file[0x104..0x108].copy_from_slice(&16u32.to_le_bytes());
+        for (idx, rva, size) in [(5, 0x3_000u32, 0xcu32), (12, 0x1_080, 0x10)] {
+            let directory = 0x108 + (idx * 8);
+            file[directory..directory + 4].copy_from_slice(&rva.to_le_bytes());
+            file[directory + 4..directory + 8].copy_from_slice(&size.to_le_bytes());
+        }
+
+        let sections = [
+            (
+                &b".text"[..],
+                0x1_100u32,
+                0x1_000u32,
+                0x1_200u32,
+                0x400u32,
+                0x6000_0020u32,
+            ),
+            (b".reloc", 0xc, 0x3_000, 0x200, 0x1_600, 0x4200_0040),
+        ];
+
+        for (idx, (name, size, rva, raw_size, raw, characteristics)) in
+            sections.into_iter().enumerate()
+        {
+            let section = 0x188 + (idx * 0x28);
+            file[section..section + name.len()].copy_from_slice(name);
+            file[section + 0x8..section + 0xc].copy_from_slice(&size.to_le_bytes());
+            file[section + 0xc..section + 0x10].copy_from_slice(&rva.to_le_bytes());
+            file[section + 0x10..section + 0x14].copy_from_slice(&raw_size.to_le_bytes());
+            file[section + 0x14..section + 0x18].copy_from_slice(&raw.to_le_bytes());
+            file[section + 0x24..section + 0x28].copy_from_slice(&characteristics.to_le_bytes());
+        }
+
+        file[0x400..0x404].copy_from_slice(&[0x48, 0x89, 0x5c, 0x24]);
+        file[0x410..0x418].copy_from_slice(&0x1_4000_1000u64.to_le_bytes());
+        file[0x1_600..0x1_604].copy_from_slice(&0x1_000u32.to_le_bytes());
+        file[0x1_604..0x1_608].copy_from_slice(&0xcu32.to_le_bytes());
+        file[0x1_608..0x1_60a].copy_from_slice(&0xa010u16.to_le_bytes());
+
+        file
+    }
+
+    #[test]
+    fn module_integrity() {
+        let nt = 0xffff_f800_0000_0000u64;
+        let mut text = vec![0; 0x1_000];
+        text[0..4].copy_from_slice(&[0xe9, 0x89, 0x5c, 0x24]);
+        text[0x10..0x18].copy_from_slice(&(nt + 0x1_000).to_le_bytes());
+        text[0x80..0x88].copy_from_slice(&0xffff_f800_1337_0000u64.to_le_bytes());
+
+        // The second page of `.text` isn't in the dump.
+        let dump = DumpBuilder::new()
+            .write_phys(0x10_000, &text)
+            .map_virt(nt + 0x1_000, 0x10_000, PxeFlags::Present)
+            .module(nt..nt + 0x4_000, "hooked.sys")
+            .build();
+
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+        let module = parser.kernel_module_entries().next().unwrap();
+        let reference = reference();
+        let patches = parser.module_integrity(module, &reference).unwrap();
+        assert_eq!(patches.len(), 1);
+        assert_eq!(patches[0].gva, Gva::new(nt + 0x1_000));
+        assert_eq!(patches[0].file_offset, 0x400);
+        assert_eq!(patches[0].original, [0x48]);
+        assert_eq!(patches[0].current, [0xe9]);
+
+        // A reference of another size isn't the image of the module.
+        let mut other = reference;
+        other[0xd0..0xd4].copy_from_slice(&0x5_000u32.to_le_bytes());
+        assert!(matches!(
+            parser.module_integrity(module, &other),
+            Err(KdmpParserError::InvalidData(_))
+        ));
+    }
+}
@@ -0,0 +1,539 @@
+//! This contains what is needed to export the modules and the annotations of
+//! a dump to JSON, and to import them from JSON, with `serde_json`. The schema
+//! is the one of the `modules_N.json` files the regression tests use: an array
+//! of objects that have a `start`, an `end` (hexadecimal strings with a `0x`
+//! prefix) and a `name`.
+//!
+//! ```text
+//! [
+//!     {
+//!         "start": "0xfffff80510610000",
+//!         "end": "0xfffff805106b3000",
+//!         "name": "hal.dll"
+//!     }
+//! ]
+//! ```
+//!
+//! Layouts of structures can be imported into a [`Profile`] as well, from an
+//! object whose keys are the type names; the types of the fields are `u8`,
+//! `u16`, `u32`, `u64`, `pointer`, `unicode_string`, `list_entry`, and the
+//! arrays of those, like `u32[4]`.
+//!
+//! ```text
+//! {
+//!     "_MY_DRIVER_CONTEXT": {
+//!         "size": "0x28",
+//!         "fields": {
+//!             "Links": { "offset": "0x0", "type": "list_entry" },
+//!             "Name": { "offset": "0x10", "type": "unicode_string" },
+//!             "Slots": { "offset": "0x20", "type": "u16[4]" }
+//!         }
+//!     }
+//! }
+//! ```
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::ops::Range;
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::Result;
+use crate::gxa::{Gva, Gxa};
+use crate::module::{ModuleEntry, ModuleMap};
+use crate::nt::KERNEL_SPACE_START;
+use crate::profile::{FieldKind, FieldLayout, Profile, StructLayout};
+use crate::{KdmpParserError, KernelDumpParser};
+
+/// How modules are named when exported.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ModuleNames {
+    /// The path of the module, like `\SystemRoot\system32\ntoskrnl.exe`.
+    #[default]
+    FullPath,
+    /// The file name of the module, like `ntoskrnl.exe`.
+    FileName,
+}
+
+/// The hexadecimal strings with a `0x` prefix the schemas use for numbers;
+/// they are written with 16 digits, like the addresses of the fixtures.
+mod hex {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        value: &u64,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{value:#018x}"))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<u64, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        value
+            .strip_prefix("0x")
+            .and_then(|hex| u64::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| D::Error::custom("expected an hexadecimal number with a 0x prefix"))
+    }
+}
+
+/// A module (or an annotation) as it is written in JSON.
+#[derive(Serialize, Deserialize)]
+struct JsonModule<'a> {
+    #[serde(with = "hex")]
+    start: u64,
+    #[serde(with = "hex")]
+    end: u64,
+    #[serde(borrow)]
+    name: std::borrow::Cow<'a, str>,
+}
+
+/// A layout of structure as it is written in JSON.
+#[derive(Deserialize)]
+struct JsonLayout {
+    #[serde(default, with = "hex")]
+    size: u64,
+    #[serde(default)]
+    fields: HashMap<String, JsonField>,
+}
+
+/// A field of a [`JsonLayout`].
+#[derive(Deserialize)]
+struct JsonField {
+    #[serde(with = "hex")]
+    offset: u64,
+    #[serde(rename = "type", deserialize_with = "kind")]
+    kind: FieldKind,
+}
+
+/// Get the [`FieldKind`] of a type name, like `u16[4]`.
+fn parse_kind(value: &str) -> Option<FieldKind> {
+    if let Some((kind, count)) = value
+        .strip_suffix(']')
+        .and_then(|value| value.rsplit_once('['))
+    {
+        return Some(FieldKind::Array {
+            kind: Box::new(parse_kind(kind)?),
+            count: count.parse().ok()?,
+        });
+    }
+
+    Some(match value {
+        "u8" => FieldKind::U8,
+        "u16" => FieldKind::U16,
+        "u32" => FieldKind::U32,
+        "u64" => FieldKind::U64,
+        "pointer" => FieldKind::Pointer,
+        "unicode_string" => FieldKind::UnicodeString,
+        "list_entry" => FieldKind::ListEntry,
+        _ => return None,
+    })
+}
+
+fn kind<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<FieldKind, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    parse_kind(&value).ok_or_else(|| D::Error::custom(format!("unknown field type {value:?}")))
+}
+
+/// Parse an array of modules.
+fn parse_modules(json: &str) -> Result<Vec<ModuleEntry>> {
+    serde_json::from_str::<Vec<JsonModule>>(json)?
+        .into_iter()
+        .map(|module| {
+            if module.start > module.end {
+                return Err(serde_json::Error::custom("the module ends before it starts").into());
+            }
+
+            Ok(ModuleEntry::new(
+                Gva::new(module.start)..Gva::new(module.end),
+                module.name.into_owned(),
+            ))
+        })
+        .collect()
+}
+
+/// Turn an error of `serde_json` writing JSON into a [`KdmpParserError`]:
+/// the I/O errors are kept as such.
+fn write_error(e: serde_json::Error) -> KdmpParserError {
+    if e.is_io() {
+        io::Error::from(e).into()
+    } else {
+        e.into()
+    }
+}
+
+impl Profile {
+    /// Load a profile for `build` from JSON; it only has the layouts of the
+    /// JSON, not the default ones.
+    pub fn from_json(build: u32, json: &str) -> Result<Self> {
+        let mut profile = Self::empty(build);
+        profile.import_json(json)?;
+
+        Ok(profile)
+    }
+
+    /// Import layouts of structures from JSON; they are added with
+    /// [`Profile::set_layout`], replacing the ones the profile had for the
+    /// same types. It returns how many layouts were imported.
+    pub fn import_json(&mut self, json: &str) -> Result<usize> {
+        let layouts = serde_json::from_str::<HashMap<String, JsonLayout>>(json)?;
+        let count = layouts.len();
+        for (type_name, layout) in layouts {
+            let fields = layout
+                .fields
+                .into_iter()
+                .map(|(name, field)| {
+                    (name, FieldLayout {
+                        offset: field.offset,
+                        kind: field.kind,
+                    })
+                })
+                .collect();
+
+            self.set_layout(type_name, StructLayout {
+                size: layout.size,
+                fields,
+            });
+        }
+
+        Ok(count)
+    }
+}
+
+/// Write `modules` to JSON into `writer`.
+fn write_json<'a>(
+    writer: impl Write,
+    modules: impl Iterator<Item = (&'a Range<Gva>, &'a str)>,
+) -> Result<()> {
+    let modules = modules
+        .map(|(at, name)| JsonModule {
+            start: at.start.u64(),
+            end: at.end.u64(),
+            name: name.into(),
+        })
+        .collect::<Vec<_>>();
+
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(b"    ");
+    let mut serializer = serde_json::Serializer::with_formatter(writer, formatter);
+    modules.serialize(&mut serializer).map_err(write_error)
+}
+
+impl KernelDumpParser {
+    /// Export the user and kernel modules to JSON, sorted by start address.
+    pub fn export_modules_json(&self, names: ModuleNames) -> String {
+        let mut json = Vec::new();
+        self.write_modules_json(&mut json, names)
+            .expect("writing to a vector can't fail");
+
+        String::from_utf8(json).expect("the json is built out of strings")
+    }
+
+    /// Export the user and kernel modules to JSON into `writer`, sorted by
+    /// start address.
+    pub fn write_modules_json(&self, writer: impl Write, names: ModuleNames) -> Result<()> {
+        let mut modules = self
+            .user_modules()
+            .chain(self.kernel_modules())
+            .collect::<Vec<_>>();
+        modules.sort_by_key(|(at, _)| at.start);
+
+        let modules = modules.into_iter().map(|(at, name)| {
+            let name = match names {
+                ModuleNames::FullPath => name,
+                ModuleNames::FileName => name.rsplit_once('\\').map_or(name, |(_, name)| name),
+            };
+
+            (at, name)
+        });
+
+        write_json(writer, modules)
+    }
+
+    /// Export the annotations to JSON, sorted by start address.
+    pub fn export_annotations_json(&self) -> String {
+        let mut json = Vec::new();
+        write_json(&mut json, self.annotations()).expect("writing to a vector can't fail");
+
+        String::from_utf8(json).expect("the json is built out of strings")
+    }
+
+    /// Import annotations from JSON, like the ones exported by
+    /// [`KernelDumpParser::export_annotations_json`]; they are added with
+    /// [`KernelDumpParser::add_annotation`]. It returns how many annotations
+    /// were imported.
+    pub fn import_annotations_json(&mut self, json: &str) -> Result<usize> {
+        let annotations = parse_modules(json)?;
+        let count = annotations.len();
+        for annotation in annotations {
+            self.add_annotation(annotation.at, annotation.name);
+        }
+
+        Ok(count)
+    }
+
+    /// Import modules from JSON; this is useful when the module lists of the
+    /// dump can't be read but the modules have been recovered another way.
+    /// The modules in kernel space augment the kernel modules and the others
+    /// the user modules; a module that overlaps an imported one is replaced.
+    /// It returns how many modules were imported.
+    pub fn import_modules_json(&mut self, json: &str) -> Result<usize> {
+        let modules = parse_modules(json)?;
+        let count = modules.len();
+        let (kernel, user) = modules
+            .into_iter()
+            .partition::<Vec<_>, _>(|module| module.at.start.u64() >= KERNEL_SPACE_START);
+
+        let kernel_modules = std::mem::take(&mut self.kernel_modules);
+        self.kernel_modules = self.merge_modules(kernel_modules, kernel)?;
+        let user_modules = std::mem::take(&mut self.user_modules);
+        self.user_modules = self.merge_modules(user_modules, user)?;
+
+        Ok(count)
+    }
+
+    /// Merge `imported` into `modules`, replacing the modules they overlap.
+    fn merge_modules(
+        &mut self,
+        modules: ModuleMap,
+        imported: Vec<ModuleEntry>,
+    ) -> Result<ModuleMap> {
+        if imported.is_empty() {
+            return Ok(modules);
+        }
+
+        let overlaps = |at: &Range<Gva>| {
+            imported
+                .iter()
+                .any(|module| at.start < module.at.end && module.at.start < at.end)
+        };
+
+        let mut merged = Vec::with_capacity(modules.len() + imported.len());
+        for module in modules.entries_in_list_order() {
+            if !overlaps(&module.at) {
+                merged.push(module.clone());
+                continue;
+            }
+
+            // Forget what was cached about the replaced module.
+            self.exports.lock().unwrap().remove(&module.at.start);
+            #[cfg(feature = "object")]
+            self.module_images.remove(&module.at.start);
+        }
+
+        for module in &imported {
+            self.exports.lock().unwrap().remove(&module.at.start);
+            #[cfg(feature = "object")]
+            self.module_images.remove(&module.at.start);
+        }
+
+        merged.extend(imported);
+
+        self.build_module_map(merged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DumpBuilder;
+
+    #[test]
+    fn parse() {
+        let modules = parse_modules(
+            r#" [
+    {"start": "0x1000", "end": "0x2000", "name": "a\\b\u0063.dll", "extra": "x"},
+    {"name": "d.dll", "end": "0x5000", "start": "0x4000"}
+] "#,
+        )
+        .unwrap();
+
+        assert_eq!(modules, [
+            ModuleEntry::new(Gva::new(0x1_000)..Gva::new(0x2_000), r"a\bc.dll"),
+            ModuleEntry::new(Gva::new(0x4_000)..Gva::new(0x5_000), "d.dll"),
+        ]);
+        assert!(parse_modules("[]").unwrap().is_empty());
+    }
+
+    #[test]
+    fn invalid() {
+        for json in [
+            "",
+            "{}",
+            "[",
+            "[{}]",
+            r#"[{"start": "0x1000", "end": "0x2000"}]"#,
+            r#"[{"start": "1000", "end": "0x2000", "name": "a"}]"#,
+            r#"[{"start": "0x2000", "end": "0x1000", "name": "a"}]"#,
+            r#"[{"start": "0x1000", "end": "0x2000", "name": "a"},]"#,
+            r#"[{"start": "0x1000" "end": "0x2000", "name": "a"}]"#,
+            r#"[] []"#,
+        ] {
+            assert!(
+                matches!(parse_modules(json), Err(KdmpParserError::InvalidJson(_))),
+                "{json}"
+            );
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        // Importing a fixture in a dump that has no modules and exporting it back
+        // gives the same modules.
+        for fixture in [
+            include_str!("../tests/modules_1.json"),
+            include_str!("../tests/modules_2.json"),
+            include_str!("../tests/modules_3.json"),
+        ] {
+            let mut parser = KernelDumpParser::from_bytes(DumpBuilder::new().build()).unwrap();
+            let count = parser.import_modules_json(fixture).unwrap();
+            assert_eq!(
+                count,
+                parser.user_modules().len() + parser.kernel_modules().len()
+            );
+
+            let json = parser.export_modules_json(ModuleNames::FileName);
+            assert_eq!(
+                parse_modules(&json).unwrap(),
+                parse_modules(fixture).unwrap()
+            );
+        }
+
+        // The addresses of the kernel modules have as many digits in the fixtures.
+        let fixture = include_str!("../tests/modules_1.json");
+        let mut parser = KernelDumpParser::from_bytes(DumpBuilder::new().build()).unwrap();
+        parser.import_modules_json(fixture).unwrap();
+        assert_eq!(parser.export_modules_json(ModuleNames::FileName), fixture);
+    }
+
+    #[test]
+    fn import() {
+        let nt = 0xffff_f800_0000_0000;
+        let dump = DumpBuilder::new()
+            .module(nt..nt + 0x10_000, r"\SystemRoot\system32\ntoskrnl.exe")
+            .module(
+                nt + 0x10_000..nt + 0x20_000,
+                r"\SystemRoot\system32\hal.dll",
+            )
+            .build();
+        let mut parser = KernelDumpParser::from_bytes(dump).unwrap();
+        assert_eq!(
+            parser.export_modules_json(ModuleNames::FullPath),
+            r#"[
+    {
+        "start": "0xfffff80000000000",
+        "end": "0xfffff80000010000",
+        "name": "\\SystemRoot\\system32\\ntoskrnl.exe"
+    },
+    {
+        "start": "0xfffff80000010000",
+        "end": "0xfffff80000020000",
+        "name": "\\SystemRoot\\system32\\hal.dll"
+    }
+]"#
+        );
+
+        // `hal.dll` is replaced by two modules, and a user module is added.
+        let count = parser
+            .import_modules_json(
+                r#"[
+    {"start": "0xfffff80000010000", "end": "0xfffff80000018000", "name": "a.sys"},
+    {"start": "0xfffff8000001c000", "end": "0xfffff80000030000", "name": "b.sys"},
+    {"start": "0x00007ff600000000", "end": "0x00007ff600010000", "name": "c.exe"}
+]"#,
+            )
+            .unwrap();
+        assert_eq!(count, 3);
+        assert_eq!(
+            parser.find_module(Gva::new(nt + 0x10)).unwrap().1,
+            r"\SystemRoot\system32\ntoskrnl.exe"
+        );
+        assert_eq!(
+            parser.find_module(Gva::new(nt + 0x10_010)).unwrap().1,
+            "a.sys"
+        );
+        assert!(parser.find_module(Gva::new(nt + 0x19_000)).is_none());
+        assert_eq!(
+            parser
+                .symbolize_with_exports(Gva::new(nt + 0x1c_010))
+                .unwrap(),
+            "b+0x10"
+        );
+        assert_eq!(
+            parser.find_module(Gva::new(0x7ff6_0000_1337)).unwrap().1,
+            "c.exe"
+        );
+        assert_eq!(parser.kernel_modules().len(), 3);
+        assert_eq!(parser.user_modules().len(), 1);
+    }
+
+    #[test]
+    fn profile() {
+        let mut profile = Profile::new(19_041);
+        let count = profile
+            .import_json(
+                r#"{
+    "_MY_DRIVER_CONTEXT": {
+        "size": "0x28",
+        "fields": {
+            "Links": {"offset": "0x0", "type": "list_entry"},
+            "Slots": {"type": "u16[4]", "offset": "0x20", "comment": "x"}
+        }
+    },
+    "_EPROCESS": {"size": "0x10", "fields": {}}
+}"#,
+            )
+            .unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(
+            profile.layout("_MY_DRIVER_CONTEXT").unwrap(),
+            &StructLayout::new(0x28)
+                .with_field("Links", 0, FieldKind::ListEntry)
+                .with_field("Slots", 0x20, FieldKind::Array {
+                    kind: Box::new(FieldKind::U16),
+                    count: 4
+                })
+        );
+        // The imported layouts replace the ones of the profile.
+        assert!(profile.offset("_EPROCESS", "ActiveProcessLinks").is_err());
+
+        // A profile can be loaded from JSON alone, for the builds the default
+        // profile doesn't know.
+        let profile = Profile::from_json(
+            9_600,
+            r#"{"_EPROCESS": {"size": "0x4d0", "fields": {
+                "ActiveProcessLinks": {"offset": "0x2e8", "type": "list_entry"},
+                "UniqueProcessId": {"offset": "0x2e0", "type": "pointer"}
+            }}}"#,
+        )
+        .unwrap();
+        assert_eq!(profile.build(), 9_600);
+        assert_eq!(
+            profile.layout("_EPROCESS").unwrap(),
+            &StructLayout::new(0x4d0)
+                .with_field("ActiveProcessLinks", 0x2e8, FieldKind::ListEntry)
+                .with_field("UniqueProcessId", 0x2e0, FieldKind::Pointer)
+        );
+        assert!(profile.layout("_PEB32").is_none());
+        assert!(matches!(
+            Profile::from_json(9_600, "{"),
+            Err(KdmpParserError::InvalidJson(_))
+        ));
+
+        for json in [
+            "[]",
+            r#"{"_A": {"fields": {"B": {"offset": "0x0"}}}}"#,
+            r#"{"_A": {"fields": {"B": {"offset": "0x0", "type": "i32"}}}}"#,
+            r#"{"_A": {"fields": {"B": {"offset": "0x0", "type": "u8[x]"}}}}"#,
+            r#"{"_A": {"size": "16"}}"#,
+        ] {
+            assert!(
+                matches!(
+                    Profile::new(19_041).import_json(json),
+                    Err(KdmpParserError::InvalidJson(_))
+                ),
+                "{json}"
+            );
+        }
+    }
+}
@@ -1,17 +1,162 @@
 // Axel '0vercl0k' Souchet - February 25 2024
 #![doc = include_str!("../README.md")]
+mod address_space;
+mod annotation;
+mod apc;
+mod big_pool;
 mod bits;
+mod bugcheck;
+mod callback;
+mod cancel;
+mod carve;
+mod cfi;
+mod classify;
+mod code;
+mod container;
+mod context_mode;
+mod context_source;
+#[cfg(any(feature = "flate2", feature = "zstd"))]
+mod decompress;
+mod dpc;
+mod dtb;
+mod dump_set;
 mod error;
+mod estimate;
+mod exclusion;
+mod export;
+mod file;
 mod gxa;
+mod header;
+mod heap;
+mod hexdump;
+#[cfg(feature = "hibernation")]
+mod hibernation;
+#[cfg(feature = "object")]
+mod image;
+mod incomplete;
+mod index;
+mod info;
+mod integrity;
+#[cfg(feature = "json")]
+mod json;
+mod list;
 mod map;
+mod memory_map;
+mod module;
+mod module_source;
+mod net;
+mod nt;
+mod nt_globals;
+mod object;
+mod page_table_cache;
+mod page_tables;
 mod parse;
+mod pdb;
+#[cfg(feature = "pdb")]
+mod pdb_symbols;
+mod pe;
+mod pfn;
+mod physmem_pages;
+mod probe;
+mod processor;
+mod profile;
 mod pxe;
+pub mod raw;
+mod read_failure;
+mod recovery;
+mod registers;
+mod registry;
+mod section_protection;
+mod stack;
+mod stats;
 mod structs;
+mod supplement;
+mod teb;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod token;
+mod trace;
+mod unwind;
+mod utf16;
+mod verify;
+mod version;
+mod well_known;
+mod work_item;
+mod wow64;
+#[cfg(feature = "hibernation")]
+mod xpress;
+mod xstate;
 
+pub use address_space::{AddressSpace, ProcessRef};
+pub use apc::{Apc, ApcMode};
+pub use big_pool::{BigPoolEntry, PoolType};
 pub use bits::Bits;
-pub use error::{AddrTranslationError, KdmpParserError, PxeNotPresent, Result};
-pub use gxa::{Gpa, Gva, Gxa};
+pub use bugcheck::{bugcheck_name, Access, BugCheckDetails, SymbolizedAddress};
+pub use callback::{Callback, CallbackKind};
+pub use cancel::CancellationToken;
+pub use carve::CarvedPe;
+pub use cfi::{CfiCheck, CfiFinding, CfiReport};
+pub use classify::{PageBucket, PageClassification};
+pub use code::CodeBytes;
+#[cfg(feature = "iced")]
+pub use code::DisassembledInstruction;
+pub use container::ContainerKind;
+pub use context_mode::{ContextMode, TrapFrame};
+pub use context_source::ContextSource;
+pub use dpc::{Dpc, KTimer};
+pub use dtb::DtbCandidate;
+pub use dump_set::{DumpId, DumpSet, ModuleDiff, ProcessDiff, SetAddress, VirtComparison};
+pub use error::{
+    AddrTranslationError, ErrorCategory, KdmpParserError, PxeNotPresent, Result, Warning,
+};
+pub use estimate::ResourceEstimate;
+pub use exclusion::{ExclusionReason, MissingRange, MissingReason};
+pub use export::Export;
+pub use file::{ExtractReport, FileObject};
+pub use gxa::{Gpa, Gva, Gxa, GxaRange, RangeChunks, RangePages};
+pub use header::{DescriptorRun, DumpAttributes, MemoryDescriptor, ProductType, SuiteMask};
+pub use heap::{HeapInfo, HeapKind};
+#[cfg(feature = "hibernation")]
+pub use hibernation::{HibernationHeader, HibernationParser};
+pub use incomplete::RefreshDelta;
+pub use info::DumpInfo;
+pub use integrity::Patch;
+#[cfg(feature = "json")]
+pub use json::ModuleNames;
+pub use list::ListWalker;
 pub use map::{MappedFileReader, Reader};
-pub use parse::KernelDumpParser;
+pub use memory_map::{MemoryRegion, Protection, RegionKind};
+pub use module::{ModuleEntry, ModuleMap, ModuleOrigin};
+pub use module_source::{ModuleDiscrepancy, ModuleSource};
+pub use net::{Connection, Protocol, TcpState};
+pub use nt_globals::NtGlobals;
+pub use object::ObjectInfo;
+pub use page_tables::{PtPage, PteAnomaly, WalkLevel};
+pub use parse::{IoSpan, KernelDumpParser, ParserOptions, PrefetchReport, ReadMode, ReadRequest};
+pub use pdb::PdbId;
+pub use pfn::{PageState, PfnEntry};
+pub use physmem_pages::PhysmemPages;
+pub use probe::{probe, probe_bytes, DetectedFormat, ProbeResult, Verdict};
+pub use processor::{CpuState, Msrs};
+pub use profile::{FieldKind, FieldLayout, FieldValue, Profile, StructLayout, StructValue};
 pub use pxe::{Pfn, Pxe, PxeFlags};
-pub use structs::DumpType;
+pub use read_failure::{ReadFailureExplanation, SoftwarePte};
+pub use recovery::RecoveryNote;
+pub use registers::{Cr0, Cr4, Rflags};
+pub use registry::{Hive, Key, RegValue};
+pub use section_protection::SectionProtection;
+pub use stack::{StackFrame, ThreadStackDump};
+pub use stats::ParserStats;
+pub use structs::{DumpType, FromLeBytes, LeCursor};
+pub use supplement::PageSource;
+pub use teb::TebInfo;
+pub use token::{privilege_names, TokenInfo};
+pub use unwind::{StackState, UnwoundFrame};
+pub use utf16::StringPolicy;
+#[cfg(feature = "sha2")]
+pub use verify::HashAlgorithm;
+pub use verify::{Check, CheckOutcome, CoherenceMismatch, CoherenceReport, VerifyReport};
+pub use version::VersionInfo;
+pub use well_known::WellKnown;
+pub use work_item::{WorkItem, WorkQueueType};
+pub use xstate::{XSaveState, ZmmState};
@@ -0,0 +1,131 @@
+//! This contains [`ListWalker`], an iterator that walks doubly-linked
+//! `LIST_ENTRY` lists found in memory.
+use std::collections::HashSet;
+
+use crate::error::Result;
+use crate::gxa::Gxa;
+use crate::structs::ListEntry;
+use crate::{Gva, KdmpParserError, KernelDumpParser};
+
+/// An iterator over the records linked in a `LIST_ENTRY` list. It is created
+/// by [`KernelDumpParser::walk_list`].
+///
+/// The walk stops at the first error and yields it: a memory read failure, a
+/// cycle, an entry whose `Blink` doesn't point back to the previous entry, or
+/// a list longer than the maximum number of entries.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use kdmp_parser::{KernelDumpParser, Gva};
+/// let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+/// let head = Gva::new(parser.headers().ps_loaded_module_list);
+/// // `InLoadOrderLinks` is at offset 0 in `_LDR_DATA_TABLE_ENTRY`.
+/// for entry in parser.walk_list(head, 0, 0x1_000) {
+///     println!("{}", entry.unwrap());
+/// }
+/// ```
+pub struct ListWalker<'parser> {
+    parser: &'parser KernelDumpParser,
+    /// Address of the list head.
+    head: Gva,
+    /// Offset of the `LIST_ENTRY` in the records.
+    entry_offset: u64,
+    /// Maximum number of records to yield.
+    max_entries: usize,
+    /// Do we verify that the `Blink` of an entry points back to the previous
+    /// entry?
+    check_blink: bool,
+    /// Address of the previous `LIST_ENTRY`.
+    prev: Gva,
+    /// Address of the next `LIST_ENTRY`, `None` if the walk hasn't started or
+    /// if it is over.
+    next: Option<Gva>,
+    /// Has the head been read yet?
+    started: bool,
+    /// The `LIST_ENTRY`s that have been visited so far.
+    visited: HashSet<Gva>,
+}
+
+impl<'parser> ListWalker<'parser> {
+    pub(crate) fn new(
+        parser: &'parser KernelDumpParser,
+        head: Gva,
+        entry_offset: u64,
+        max_entries: usize,
+    ) -> Self {
+        Self {
+            parser,
+            head,
+            entry_offset,
+            max_entries,
+            check_blink: true,
+            prev: head,
+            next: None,
+            started: false,
+            visited: HashSet::new(),
+        }
+    }
+
+    /// Enable or disable the `Blink` verification; it is enabled by default.
+    pub fn check_blink(mut self, check_blink: bool) -> Self {
+        self.check_blink = check_blink;
+
+        self
+    }
+
+    /// Move the walk forward and return the address of the next record.
+    fn step(&mut self) -> Result<Option<Gva>> {
+        if !self.started {
+            self.started = true;
+            let head = self.parser.virt_read_struct::<ListEntry>(self.head)?;
+            self.next = Some(head.flink.into());
+        }
+
+        let Some(entry_addr) = self.next.take() else {
+            return Ok(None);
+        };
+
+        // We'll walk it until we hit the starting point (it is circular).
+        if entry_addr == self.head {
+            return Ok(None);
+        }
+
+        if self.visited.len() >= self.max_entries {
+            return Err(KdmpParserError::ListTooLong(self.max_entries));
+        }
+
+        if !self.visited.insert(entry_addr) {
+            return Err(KdmpParserError::ListCycle(entry_addr));
+        }
+
+        let entry = self.parser.virt_read_struct::<ListEntry>(entry_addr)?;
+        if self.check_blink && entry.blink != self.prev.u64() {
+            return Err(KdmpParserError::ListBlinkMismatch(entry_addr));
+        }
+
+        let record_addr = entry_addr
+            .u64()
+            .checked_sub(self.entry_offset)
+            .ok_or(KdmpParserError::Overflow("list entry offset"))?;
+
+        self.prev = entry_addr;
+        self.next = Some(entry.flink.into());
+
+        Ok(Some(record_addr.into()))
+    }
+}
+
+impl Iterator for ListWalker<'_> {
+    type Item = Result<Gva>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let res = self.step();
+        // Once we've hit an error, the walk is over.
+        if res.is_err() {
+            self.next = None;
+        }
+
+        res.transpose()
+    }
+}
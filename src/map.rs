@@ -3,17 +3,71 @@
 //! Unix and Windows (cf [`memory_map_file`] / [`unmap_memory_mapped_file`]).
 use std::fmt::Debug;
 use std::io::{Read, Seek};
+use std::marker::PhantomData;
+use std::sync::Arc;
 use std::{convert, fs, io, path, ptr, slice};
 
 pub trait Reader: Read + Seek {}
 
 impl<T> Reader for T where T: Read + Seek {}
 
-/// A memory mapped file reader is basically a slice of bytes over the memory
-/// mapping and a cursor to be able to access the region.
+/// The memory mapping of a file; it is unmapped once every
+/// [`MappedFileReader`] and parser sharing it is gone. Its bytes are borrowed
+/// from it, so they can't be used once it has been unmapped.
+#[derive(Clone)]
+pub(crate) struct Mapping(Arc<MappedRegion>);
+
+/// Where a file is mapped.
+struct MappedRegion {
+    base: *const u8,
+    len: usize,
+}
+
+// SAFETY: The mapping is read-only and nothing mutates it, so its bytes can be
+// read from any thread.
+unsafe impl Send for MappedRegion {}
+unsafe impl Sync for MappedRegion {}
+
+impl AsRef<[u8]> for Mapping {
+    fn as_ref(&self) -> &[u8] {
+        // SAFETY: The region is a read-only mapping of `len` bytes that stays
+        // mapped for as long as `self` lives, which the returned slice can't
+        // outlive.
+        unsafe { slice::from_raw_parts(self.0.base, self.0.len) }
+    }
+}
+
+/// Bytes held in memory that are shared between the reader of a dump and the
+/// parser, like the [`Mapping`] of a memory mapped file.
+#[derive(Clone)]
+pub(crate) struct SharedBytes(Arc<dyn AsRef<[u8]> + Send + Sync>);
+
+impl SharedBytes {
+    pub(crate) fn new(bytes: impl AsRef<[u8]> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(bytes))
+    }
+}
+
+impl AsRef<[u8]> for SharedBytes {
+    fn as_ref(&self) -> &[u8] {
+        (*self.0).as_ref()
+    }
+}
+
+/// Unmap the region using OS-provided APIs.
+impl Drop for MappedRegion {
+    fn drop(&mut self) {
+        // SAFETY: Same as `Mapping::as_ref`; nothing borrows the region anymore.
+        let mapped_file = unsafe { slice::from_raw_parts(self.base, self.len) };
+        unmap_memory_mapped_file(mapped_file).expect("failed to unmap")
+    }
+}
+
+/// A memory mapped file reader is basically the memory mapping and a cursor
+/// to be able to access the region.
 pub struct MappedFileReader<'map> {
-    mapped_file: &'map [u8],
-    cursor: io::Cursor<&'map [u8]>,
+    cursor: io::Cursor<Mapping>,
+    _mapped_file: PhantomData<&'map [u8]>,
 }
 
 impl<'map> Debug for MappedFileReader<'map> {
@@ -33,12 +87,22 @@ impl<'map> MappedFileReader<'map> {
 
         // ..and memory map it using the underlying OS-provided APIs.
         let mapped_file = memory_map_file(file)?;
+        let mapping = Mapping(Arc::new(MappedRegion {
+            base: mapped_file.as_ptr(),
+            len: mapped_file.len(),
+        }));
 
         Ok(Self {
-            mapped_file,
-            cursor: io::Cursor::new(mapped_file),
+            cursor: io::Cursor::new(mapping),
+            _mapped_file: PhantomData,
         })
     }
+
+    /// The mapping of the file, which stays mapped for as long as it is
+    /// shared.
+    pub(crate) fn mapping(&self) -> Mapping {
+        self.cursor.get_ref().clone()
+    }
 }
 
 impl<'map> Read for MappedFileReader<'map> {
@@ -53,12 +117,19 @@ impl<'map> Seek for MappedFileReader<'map> {
     }
 }
 
-/// Drop the [`MappedFileReader`]. In the case we memory mapped the file, we
-/// need to drop the mapping using OS-provided APIs.
-impl<'map> Drop for MappedFileReader<'map> {
-    fn drop(&mut self) {
-        unmap_memory_mapped_file(self.mapped_file).expect("failed to unmap")
-    }
+/// Convert the size of a file into the size of its mapping; it can't be bigger
+/// than what [`slice::from_raw_parts`] wants (at most [`isize::MAX`] bytes),
+/// which 32-bit hosts can hit.
+fn mappable_size(size: u64) -> io::Result<usize> {
+    isize::try_from(size)
+        .ok()
+        .and_then(|size| usize::try_from(size).ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "file is too large to be memory mapped",
+            )
+        })
 }
 
 #[cfg(windows)]
@@ -123,6 +194,10 @@ mod windows {
         // Grab the underlying HANDLE.
         let file_handle = file.as_raw_handle();
 
+        // Grab the size of the underlying file, this will be the size of the
+        // view.
+        let size = mappable_size(file.metadata()?.len())?;
+
         // Create the mapping.
         let mapping_handle = unsafe {
             CreateFileMappingA(
@@ -140,10 +215,6 @@ mod windows {
             return Err(io::Error::last_os_error());
         }
 
-        // Grab the size of the underlying file, this will be the size of the
-        // view.
-        let size = file.metadata()?.len().try_into().unwrap();
-
         // Map the view in the address space.
         let base = unsafe { MapViewOfFile(mapping_handle, FILE_MAP_READ, 0, 0, size) };
 
@@ -162,11 +233,6 @@ mod windows {
             CloseHandle(mapping_handle);
         }
 
-        // Make sure the size is not bigger than what [`slice::from_raw_parts`] wants.
-        if size > isize::MAX.try_into().unwrap() {
-            panic!("slice is too large");
-        }
-
         // Create the slice over the mapping.
         // SAFETY: This is safe because:
         //   - It is a byte slice, so we don't need to care about the alignment.
@@ -225,7 +291,7 @@ mod unix {
 
         // Grab the size of the underlying file. This will be the size of the
         // memory mapped region.
-        let size = file.metadata()?.len().try_into().unwrap();
+        let size = mappable_size(file.metadata()?.len())?;
 
         // Mmap the file.
         let ret = unsafe { mmap(ptr::null_mut(), size, PROT_READ, MAP_SHARED, file_fd, 0) };
@@ -235,11 +301,6 @@ mod unix {
             return Err(io::Error::last_os_error());
         }
 
-        // Make sure the size is not bigger than what [`slice::from_raw_parts`] wants.
-        if size > isize::MAX.try_into().unwrap() {
-            panic!("slice is too large");
-        }
-
         // Create the slice over the mapping.
         // SAFETY: This is safe because:
         //   - It is a byte slice, so we don't need to care about the alignment.
@@ -0,0 +1,527 @@
+//! This contains what is needed to lay out the kernel half of an address
+//! space, like `!address` does (see [`MemoryRegion`]): the pages mapped next
+//! to each other with the same protection and usage are coalesced in regions.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::{KernelDumpParser, RegionKind};
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! println!("{}", parser.memory_map_report(None));
+//!
+//! for region in parser.memory_map(None).unwrap() {
+//!     if region.kind == RegionKind::Unknown && region.protection.executable {
+//!         println!("executable memory that no module owns: {region}");
+//!     }
+//! }
+//! ```
+use std::fmt::{self, Display, Write};
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::cancel::CancellationToken;
+use crate::error::Result;
+use crate::gxa::{Gpa, Gxa};
+use crate::pxe::{Pxe, PxeFlags};
+use crate::structs::Page;
+use crate::trace::trace_debug;
+use crate::{Gva, KdmpParserError, KernelDumpParser};
+
+/// Index of the first PML4 entry that maps kernel space.
+pub(crate) const KERNEL_PML4E: usize = 0x100;
+
+/// Number of regions [`KernelDumpParser::memory_map_report`] shows at most.
+const MAX_REPORT_REGIONS: usize = 0x400;
+
+/// What the pages of a kernel address space can be accessed for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Protection {
+    /// Can the pages be written?
+    pub writable: bool,
+    /// Can the pages be executed?
+    pub executable: bool,
+}
+
+impl Protection {
+    /// The protection of an entry, restricted by the one of the tables above
+    /// it.
+    pub(crate) fn restrict(self, pxe: &Pxe) -> Self {
+        Self {
+            writable: self.writable && pxe.flags.contains(PxeFlags::Writable),
+            executable: self.executable && !pxe.flags.contains(PxeFlags::NoExecute),
+        }
+    }
+}
+
+/// Format [`Protection`] like `rwx`.
+impl Display for Protection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let writable = if self.writable { 'w' } else { '-' };
+        let executable = if self.executable { 'x' } else { '-' };
+
+        write!(f, "r{writable}{executable}")
+    }
+}
+
+/// What a [`MemoryRegion`] is used for.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RegionKind {
+    /// The image of a kernel module, named after its entry in the module list.
+    Module(String),
+    /// The page tables, mapped by the self-referencing entry of the PML4.
+    PageTables,
+    /// The kernel stack of the `_KTHREAD` at this address; only the threads
+    /// that are running or idling on a processor are known.
+    Stack(Gva),
+    /// Large pages that aren't any of the above.
+    LargePages,
+    /// Pages that aren't any of the above.
+    Unknown,
+}
+
+impl Display for RegionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Module(name) => write!(f, "{name}"),
+            Self::PageTables => write!(f, "page tables"),
+            Self::Stack(thread) => write!(f, "stack of thread {:#x}", thread.u64()),
+            Self::LargePages => write!(f, "large pages"),
+            Self::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// Pages mapped next to each other in the kernel half of an address space,
+/// that have the same protection and usage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryRegion {
+    /// Address of the first page.
+    pub start: Gva,
+    /// Size of the region in bytes.
+    pub size: u64,
+    /// Protection of the pages.
+    pub protection: Protection,
+    /// What the pages are used for.
+    pub kind: RegionKind,
+}
+
+impl MemoryRegion {
+    /// Address of the last byte of the region; the last region of the
+    /// address space ends at the very last address.
+    pub fn end(&self) -> Gva {
+        Gva::new(self.start.u64() + (self.size - 1))
+    }
+
+    /// Does the region end where `start` is?
+    fn ends_at(&self, start: Gva) -> bool {
+        self.start.u64().wrapping_add(self.size) == start.u64()
+    }
+}
+
+/// Format [`MemoryRegion`] as a line of
+/// [`KernelDumpParser::memory_map_report`].
+impl Display for MemoryRegion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:#018x} {:#018x} {:#14x} {} {}",
+            self.start.u64(),
+            self.end().u64(),
+            self.size,
+            self.protection,
+            self.kind
+        )
+    }
+}
+
+/// A page, or a large page, mapped in the kernel half of an address space.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct KernelMapping {
+    pub gva: Gva,
+    pub gpa: Gpa,
+    pub size: u64,
+    pub protection: Protection,
+    pub large: bool,
+}
+
+/// What stays the same during a walk of the kernel half of an address space.
+struct KernelWalk<'a> {
+    /// The PML4 of the address space.
+    dtb: Gpa,
+    cancel: &'a CancellationToken,
+    f: &'a mut dyn FnMut(&KernelMapping) -> Result<()>,
+}
+
+impl KernelDumpParser {
+    /// Lay out the kernel half of the address space whose PML4 is at `dtb`,
+    /// or the one of the dump header if it is `None`. The regions are sorted
+    /// by address, and a region is made of the pages mapped next to each
+    /// other that have the same protection and usage (see [`RegionKind`]).
+    ///
+    /// The page tables that aren't in the dump aren't walked, so what they
+    /// map is missing; the transition pages are counted as mapped.
+    pub fn memory_map(&self, dtb: Option<Gpa>) -> Result<Vec<MemoryRegion>> {
+        self.memory_map_with_cancellation(dtb, &CancellationToken::new())
+    }
+
+    /// Lay out the kernel half of the address space whose PML4 is at `dtb`,
+    /// like [`KernelDumpParser::memory_map`], until `cancel` is cancelled.
+    pub fn memory_map_with_cancellation(
+        &self,
+        dtb: Option<Gpa>,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<MemoryRegion>> {
+        let dtb = dtb.unwrap_or_else(|| self.default_dtb()).page_align();
+        let stacks = self.known_stacks();
+
+        let mut regions = Vec::<MemoryRegion>::new();
+        self.for_each_kernel_mapping(dtb, cancel, &mut |mapping| {
+            let kind = if let Some(module) = self.find_module_entry(mapping.gva) {
+                RegionKind::Module(module.name.clone())
+            } else if let Some((_, thread)) = stacks
+                .iter()
+                .find(|(stack, _)| stack.contains(&mapping.gva.u64()))
+            {
+                RegionKind::Stack(*thread)
+            } else if mapping.large {
+                RegionKind::LargePages
+            } else {
+                RegionKind::Unknown
+            };
+
+            match regions.last_mut() {
+                Some(last)
+                    if last.ends_at(mapping.gva)
+                        && last.protection == mapping.protection
+                        && last.kind == kind =>
+                {
+                    last.size += mapping.size;
+                }
+                _ => regions.push(MemoryRegion {
+                    start: mapping.gva,
+                    size: mapping.size,
+                    protection: mapping.protection,
+                    kind,
+                }),
+            }
+
+            Ok(())
+        })?;
+
+        if let Some(page_tables) = self.page_tables_region(dtb)? {
+            let idx = regions.partition_point(|region| region.start < page_tables.start);
+            regions.insert(idx, page_tables);
+        }
+
+        Ok(regions)
+    }
+
+    /// Format [`KernelDumpParser::memory_map`] as a table, one region per
+    /// line. Only the first regions are shown when there are too many of them,
+    /// followed by how many are left out.
+    pub fn memory_map_report(&self, dtb: Option<Gpa>) -> String {
+        let regions = match self.memory_map(dtb) {
+            Ok(regions) => regions,
+            Err(e) => return format!("failed laying out the address space: {e}\n"),
+        };
+
+        let mut report = format!(
+            "{:<18} {:<18} {:>14} {} {}\n",
+            "Start", "End", "Size", "Prot", "Usage"
+        );
+        for region in regions.iter().take(MAX_REPORT_REGIONS) {
+            let _ = writeln!(report, "{region}");
+        }
+
+        if regions.len() > MAX_REPORT_REGIONS {
+            let _ = writeln!(
+                report,
+                "... {} more regions",
+                regions.len() - MAX_REPORT_REGIONS
+            );
+        }
+
+        report
+    }
+
+    /// Call `f` with every page, and every large page, mapped in the kernel
+    /// half of the address space whose PML4 is at `dtb`, in the order of
+    /// their address. The self-referencing entry of the PML4 isn't walked,
+    /// and the walk stops when `cancel` is cancelled.
+    pub(crate) fn for_each_kernel_mapping(
+        &self,
+        dtb: Gpa,
+        cancel: &CancellationToken,
+        f: &mut dyn FnMut(&KernelMapping) -> Result<()>,
+    ) -> Result<()> {
+        let protection = Protection {
+            writable: true,
+            executable: true,
+        };
+
+        let mut walk = KernelWalk { dtb, cancel, f };
+
+        self.walk_kernel_table(&mut walk, dtb, 4, 0, protection)
+    }
+
+    /// Call `f` with the address of every page that is mapped in the kernel
+    /// half of the default address space of the dump, and the physical page
+    /// it maps; the pages of the large pages are visited one by one.
+    pub(crate) fn for_each_kernel_page(
+        &self,
+        f: &mut dyn FnMut(Gva, Gpa) -> Result<()>,
+    ) -> Result<()> {
+        let dtb = self.default_dtb().page_align();
+
+        self.for_each_kernel_mapping(dtb, &CancellationToken::new(), &mut |mapping| {
+            for offset in (0..mapping.size).step_by(Page::size() as usize) {
+                f(
+                    Gva::new(mapping.gva.u64() + offset),
+                    Gpa::new(mapping.gpa.u64() + offset),
+                )?;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// The protection of the page, or large page, `gva` is in and its size, if
+    /// it is mapped in the address space whose PML4 is at `dtb`. Like for
+    /// [`KernelDumpParser::memory_map`], the transition pages are counted as
+    /// mapped, and what the page tables missing from the dump map isn't.
+    pub(crate) fn page_protection(&self, gva: Gva, dtb: Gpa) -> Result<Option<(Protection, u64)>> {
+        let mut protection = Protection {
+            writable: true,
+            executable: true,
+        };
+
+        let indices = [
+            gva.pml4e_idx(),
+            gva.pdpe_idx(),
+            gva.pde_idx(),
+            gva.pte_idx(),
+        ];
+        let mut table = dtb;
+        for (level, idx) in (1..=4u32).rev().zip(indices) {
+            let pxe = match self.phys_read_pxe(Gpa::new(table.u64() + (idx * 8))) {
+                Ok(pxe) => pxe,
+                Err(KdmpParserError::AddrTranslation(..) | KdmpParserError::PartialPhysRead) => {
+                    return Ok(None)
+                }
+                Err(e) => return Err(e),
+            };
+
+            protection = protection.restrict(&pxe);
+            let size = 1 << (12 + (9 * (level - 1)));
+            match level {
+                1 if pxe.present() || pxe.transition() => return Ok(Some((protection, size))),
+                _ if !pxe.present() => return Ok(None),
+                2 | 3 if pxe.large_page() => return Ok(Some((protection, size))),
+                _ => table = pxe.pfn.gpa(),
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Walk the table at `table`, that maps the addresses starting at `base`
+    /// with at most `protection`; `level` is 4 for a PML4 and 1 for a page
+    /// table.
+    fn walk_kernel_table(
+        &self,
+        walk: &mut KernelWalk<'_>,
+        table: Gpa,
+        level: u8,
+        base: u64,
+        protection: Protection,
+    ) -> Result<()> {
+        walk.cancel.check()?;
+
+        // The tables that aren't in the dump can't be walked.
+        let Some(entries) = self.read_table(table)? else {
+            return Ok(());
+        };
+
+        let shift = 12 + (9 * u32::from(level - 1));
+        let first = if level == 4 { KERNEL_PML4E } else { 0 };
+        for (idx, pxe) in entries.iter().enumerate().skip(first) {
+            let mut gva = base | ((idx as u64) << shift);
+            // Kernel addresses are canonical.
+            if level == 4 {
+                gva |= 0xffff_0000_0000_0000;
+            }
+
+            let mapping = KernelMapping {
+                gva: Gva::new(gva),
+                gpa: pxe.pfn.gpa(),
+                size: 1 << shift,
+                protection: protection.restrict(pxe),
+                large: level > 1,
+            };
+
+            match level {
+                1 if pxe.present() || pxe.transition() => (walk.f)(&mapping)?,
+                _ if !pxe.present() => {}
+                1 => {}
+                2 | 3 if pxe.large_page() => (walk.f)(&mapping)?,
+                4 if mapping.gpa == walk.dtb => {}
+                _ => {
+                    self.walk_kernel_table(walk, mapping.gpa, level - 1, gva, mapping.protection)?
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Read the entries of the table at `table`, if it is in the dump.
+    pub(crate) fn read_table(&self, table: Gpa) -> Result<Option<Arc<[Pxe]>>> {
+        match self.page_table(table) {
+            Err(KdmpParserError::AddrTranslation(..)) => Ok(None),
+            result => result,
+        }
+    }
+
+    /// The region the self-referencing entry of the PML4 at `dtb` maps the
+    /// page tables in, if it has one.
+    fn page_tables_region(&self, dtb: Gpa) -> Result<Option<MemoryRegion>> {
+        let Some(entries) = self.read_table(dtb)? else {
+            return Ok(None);
+        };
+
+        let protection = Protection {
+            writable: true,
+            executable: true,
+        };
+
+        Ok(entries
+            .iter()
+            .enumerate()
+            .skip(KERNEL_PML4E)
+            .find(|(_, pxe)| pxe.present() && pxe.pfn.gpa() == dtb)
+            .map(|(idx, pxe)| MemoryRegion {
+                start: Gva::new(0xffff_0000_0000_0000 | ((idx as u64) << 39)),
+                size: 1 << 39,
+                protection: protection.restrict(pxe),
+                kind: RegionKind::PageTables,
+            }))
+    }
+
+    /// The kernel stacks of the threads running or idling on a processor.
+    fn known_stacks(&self) -> Vec<(Range<u64>, Gva)> {
+        let mut threads = Vec::new();
+        for prcb in self.prcbs().into_iter().flatten() {
+            for name in ["CurrentThread", "IdleThread"] {
+                if let Ok(thread) = self.read_field(prcb, "_KPRCB", name) {
+                    if thread != 0 && !threads.contains(&thread) {
+                        threads.push(thread);
+                    }
+                }
+            }
+        }
+
+        threads
+            .into_iter()
+            .filter_map(|thread| {
+                let thread = Gva::new(thread);
+                let field = |name| self.read_field(thread, "_KTHREAD", name);
+                match (field("StackLimit"), field("StackBase")) {
+                    (Ok(limit), Ok(base)) => Some((limit..base, thread)),
+                    (Err(e), _) | (_, Err(e)) => {
+                        trace_debug!("failed reading the stack of {thread}: {e}");
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{DumpBuilder, KdDebuggerData, MODULE_LIST_BASE};
+
+    const KDBG: u64 = 0xffff_f800_0100_0000;
+    const PRCB: u64 = 0xffff_f800_0100_1000;
+    const THREAD: u64 = 0xffff_f800_0100_2000;
+    const STACK: u64 = 0xffff_f800_0200_0000;
+    const DRIVER: u64 = 0xffff_f800_0300_0000;
+    const LARGE: u64 = 0xffff_f800_0400_0000;
+
+    #[test]
+    fn memory_map() {
+        let mut prcb = vec![0; 0x20];
+        prcb[0x8..0x10].copy_from_slice(&THREAD.to_le_bytes());
+        prcb[0x18..0x20].copy_from_slice(&THREAD.to_le_bytes());
+        let mut thread = vec![0; 0x40];
+        thread[0x30..0x38].copy_from_slice(&STACK.to_le_bytes());
+        thread[0x38..0x40].copy_from_slice(&(STACK + 0x2_000).to_le_bytes());
+
+        let rw = PxeFlags::Present | PxeFlags::Writable | PxeFlags::NoExecute;
+        let dump = DumpBuilder::new()
+            .map_virt(KDBG, 0x10_000, rw)
+            .map_virt(PRCB, 0x12_000, rw)
+            .map_virt(THREAD, 0x13_000, rw)
+            .map_virt(STACK, 0x14_000, rw)
+            .map_virt(STACK + 0x1_000, 0x15_000, rw)
+            .map_virt(DRIVER, 0x16_000, PxeFlags::Present)
+            .map_virt(DRIVER + 0x1_000, 0x17_000, rw)
+            .map_virt_large(LARGE, 0x20_0000, PxeFlags::Present)
+            .module(DRIVER..DRIVER + 0x2_000, "driver.sys")
+            .kd_debugger_data(KDBG, KdDebuggerData {
+                prcbs: vec![PRCB],
+                ..Default::default()
+            })
+            .write_virt(PRCB, &prcb)
+            .write_virt(THREAD, &thread)
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+
+        let rw = Protection {
+            writable: true,
+            executable: false,
+        };
+        let rx = Protection {
+            writable: false,
+            executable: true,
+        };
+        let rwx = Protection {
+            writable: true,
+            executable: true,
+        };
+        let regions = parser
+            .memory_map(None)
+            .unwrap()
+            .into_iter()
+            .map(|region| {
+                (
+                    region.start.u64(),
+                    region.size,
+                    region.protection,
+                    region.kind,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(regions, [
+            (KDBG, 0x3_000, rw, RegionKind::Unknown),
+            (STACK, 0x2_000, rw, RegionKind::Stack(Gva::new(THREAD))),
+            (DRIVER, 0x1_000, rx, RegionKind::Module("driver.sys".into())),
+            (
+                DRIVER + 0x1_000,
+                0x1_000,
+                rw,
+                RegionKind::Module("driver.sys".into())
+            ),
+            (LARGE, 0x20_0000, rx, RegionKind::LargePages),
+            (MODULE_LIST_BASE, 0x1_000, rwx, RegionKind::Unknown),
+        ]);
+
+        let report = parser.memory_map_report(None);
+        assert_eq!(report.lines().count(), 7);
+        assert!(report.contains(
+            "0xfffff80002000000 0xfffff80002001fff         0x2000 rw- stack of thread \
+             0xfffff80001002000"
+        ));
+    }
+}
@@ -0,0 +1,426 @@
+//! This contains [`ModuleMap`], which stores the modules loaded in an address
+//! space and finds which module an address belongs to, and [`ModuleEntry`]
+//! which describes a module.
+//!
+//! # Examples
+//!
+//! ```
+//! # use kdmp_parser::{Gva, ModuleMap};
+//! let modules = ModuleMap::from_iter([
+//!     (Gva::new(0x1_000)..Gva::new(0x2_000), "a.dll".to_string()),
+//!     (Gva::new(0x4_000)..Gva::new(0x8_000), "b.dll".to_string()),
+//! ]);
+//! assert_eq!(modules.find(Gva::new(0x4_337)).unwrap().1, "b.dll");
+//! assert!(modules.find(Gva::new(0x2_000)).is_none());
+//! ```
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::error::Warning;
+use crate::gxa::Gxa;
+use crate::nt::KERNEL_SPACE_START;
+use crate::Gva;
+
+/// Where a module has been found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ModuleOrigin {
+    /// In the kernel module list.
+    Kernel,
+    /// In the module lists of a process.
+    User {
+        /// The `UniqueProcessId` of the process, if it is known.
+        process: Option<u64>,
+    },
+}
+
+/// A module, as described by its `_KLDR_DATA_TABLE_ENTRY` (drivers) or its
+/// `_LDR_DATA_TABLE_ENTRY` (user modules). The fields that aren't available
+/// for a module are `None`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleEntry {
+    /// The range of where the module lives at.
+    pub at: Range<Gva>,
+    /// Path to the module, or its name if no path is available.
+    pub name: String,
+    /// Address of the entry point of the module.
+    pub entry_point: Option<Gva>,
+    /// `SizeOfImage` of the module.
+    pub size_of_image: u32,
+    /// `TimeDateStamp` of the module; along with `SizeOfImage` it identifies
+    /// the binary on a symbol server.
+    pub timestamp: Option<u32>,
+    /// `CheckSum` of the module.
+    pub checksum: Option<u32>,
+    /// Number of times the module has been loaded.
+    pub load_count: Option<u16>,
+    /// Where the module has been found.
+    pub origin: ModuleOrigin,
+}
+
+impl ModuleEntry {
+    /// Create an entry that only has a range and a name; it is a kernel
+    /// module if it is in kernel space, and a user module of an unknown
+    /// process otherwise.
+    pub fn new(at: Range<Gva>, name: impl Into<String>) -> Self {
+        let origin = if at.start.u64() >= KERNEL_SPACE_START {
+            ModuleOrigin::Kernel
+        } else {
+            ModuleOrigin::User { process: None }
+        };
+
+        Self {
+            size_of_image: (at.end.u64().saturating_sub(at.start.u64())) as u32,
+            at,
+            name: name.into(),
+            entry_point: None,
+            timestamp: None,
+            checksum: None,
+            load_count: None,
+            origin,
+        }
+    }
+}
+
+/// The modules of an address space, sorted by address. Modules don't overlap,
+/// which allows to find the module an address belongs to with a binary search.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ModuleMap {
+    modules: Vec<ModuleEntry>,
+    /// The indices of `modules`, in the order of the list they come from.
+    list_order: Vec<usize>,
+}
+
+impl ModuleMap {
+    /// Build a map out of a list of modules. Only the first occurrence of a
+    /// module (same base and name) is kept, the others are reported as
+    /// [`Warning::DuplicateModule`]. Then, a module that overlaps a module
+    /// that comes before it in the address space is left out, and reported as
+    /// a [`Warning::OverlappingModules`].
+    pub(crate) fn build(modules: impl IntoIterator<Item = ModuleEntry>) -> (Self, Vec<Warning>) {
+        let mut warnings = Vec::new();
+        let mut sorted = Vec::<(usize, ModuleEntry)>::new();
+        let mut seen = HashMap::<_, usize>::new();
+        for module in modules {
+            let key = (module.at.start, module.name.clone());
+            match seen.get(&key) {
+                Some(&idx) => warnings.push(Warning::DuplicateModule {
+                    first: Box::new(sorted[idx].1.clone()),
+                    second: Box::new(module),
+                }),
+                None => {
+                    seen.insert(key, sorted.len());
+                    sorted.push((sorted.len(), module));
+                }
+            }
+        }
+
+        // The sort is stable, so the order of the list breaks the ties.
+        sorted.sort_by_key(|(_, module)| (module.at.start, module.at.end));
+
+        let mut map = Self::default();
+        let mut positions = Vec::new();
+        for (position, module) in sorted {
+            match map.modules.last() {
+                // Empty modules don't overlap anything, but two modules can't start at the
+                // same address.
+                Some(last) if module.at.start < last.at.end || module.at.start == last.at.start => {
+                    warnings.push(Warning::OverlappingModules {
+                        first: (last.at.clone(), last.name.clone()),
+                        second: (module.at, module.name),
+                    });
+                }
+                _ => {
+                    map.modules.push(module);
+                    positions.push(position);
+                }
+            }
+        }
+
+        map.list_order = (0..map.modules.len()).collect();
+        map.list_order.sort_by_key(|&idx| positions[idx]);
+
+        (map, warnings)
+    }
+
+    /// Find the module that contains `gva`.
+    pub fn find(&self, gva: Gva) -> Option<(&Range<Gva>, &str)> {
+        self.find_entry(gva)
+            .map(|module| (&module.at, module.name.as_str()))
+    }
+
+    /// Find the entry of the module that contains `gva`.
+    pub fn find_entry(&self, gva: Gva) -> Option<&ModuleEntry> {
+        // Find the last module that starts at or before `gva`..
+        let idx = self
+            .modules
+            .partition_point(|module| module.at.start <= gva)
+            .checked_sub(1)?;
+
+        // ..and check if `gva` is inside of it.
+        let module = &self.modules[idx];
+
+        module.at.contains(&gva).then_some(module)
+    }
+
+    /// Iterate over the modules, in ascending address order.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = (&Range<Gva>, &str)> + '_ {
+        self.modules
+            .iter()
+            .map(|module| (&module.at, module.name.as_str()))
+    }
+
+    /// Iterate over the module entries, in ascending address order.
+    pub fn entries(&self) -> impl ExactSizeIterator<Item = &ModuleEntry> + '_ {
+        self.modules.iter()
+    }
+
+    /// Iterate over the module entries, in the order of the list the map has
+    /// been built from (for the kernel modules, the order they have been
+    /// loaded in).
+    pub fn entries_in_list_order(&self) -> impl ExactSizeIterator<Item = &ModuleEntry> + '_ {
+        self.list_order.iter().map(|&idx| &self.modules[idx])
+    }
+
+    /// Number of modules.
+    pub fn len(&self) -> usize {
+        self.modules.len()
+    }
+
+    /// Is the map empty?
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty()
+    }
+}
+
+/// Build a map out of a list of modules; modules that overlap the previous one
+/// are left out.
+impl FromIterator<(Range<Gva>, String)> for ModuleMap {
+    fn from_iter<T: IntoIterator<Item = (Range<Gva>, String)>>(iter: T) -> Self {
+        iter.into_iter()
+            .map(|(at, name)| ModuleEntry::new(at, name))
+            .collect()
+    }
+}
+
+/// Build a map out of a list of module entries; modules that overlap the
+/// previous one are left out.
+impl FromIterator<ModuleEntry> for ModuleMap {
+    fn from_iter<T: IntoIterator<Item = ModuleEntry>>(iter: T) -> Self {
+        Self::build(iter).0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn module(start: u64, end: u64, name: &str) -> (Range<Gva>, String) {
+        (Gva::new(start)..Gva::new(end), name.into())
+    }
+
+    fn entry(start: u64, end: u64, name: &str) -> ModuleEntry {
+        ModuleEntry::new(Gva::new(start)..Gva::new(end), name)
+    }
+
+    #[test]
+    fn find() {
+        let modules = ModuleMap::from_iter([
+            module(0x5_000, 0x6_000, "c"),
+            module(0x1_000, 0x2_000, "a"),
+            module(0x2_000, 0x3_000, "b"),
+        ]);
+
+        assert!(modules.find(Gva::new(0x0)).is_none());
+        assert_eq!(modules.find(Gva::new(0x1_000)).unwrap().1, "a");
+        assert_eq!(modules.find(Gva::new(0x1_fff)).unwrap().1, "a");
+        assert_eq!(modules.find(Gva::new(0x2_000)).unwrap().1, "b");
+        assert!(modules.find(Gva::new(0x3_000)).is_none());
+        assert_eq!(modules.find(Gva::new(0x5_800)).unwrap().1, "c");
+        assert!(modules.find(Gva::new(u64::MAX)).is_none());
+        assert_eq!(modules.iter().map(|(_, name)| name).collect::<Vec<_>>(), [
+            "a", "b", "c"
+        ]);
+        assert_eq!(
+            modules
+                .entries_in_list_order()
+                .map(|module| module.name.as_str())
+                .collect::<Vec<_>>(),
+            ["c", "a", "b"]
+        );
+    }
+
+    #[test]
+    fn overlaps() {
+        let (modules, warnings) = ModuleMap::build([
+            entry(0x1_000, 0x3_000, "a"),
+            entry(0x2_000, 0x4_000, "b"),
+            entry(0x3_000, 0x4_000, "c"),
+        ]);
+
+        assert_eq!(modules.len(), 2);
+        assert_eq!(warnings, vec![Warning::OverlappingModules {
+            first: module(0x1_000, 0x3_000, "a"),
+            second: module(0x2_000, 0x4_000, "b"),
+        }]);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn origin() {
+        use crate::testing::DumpBuilder;
+        use crate::KernelDumpParser;
+
+        // The kernel modules come from the kernel module list..
+        let nt = 0xffff_f800_0000_0000;
+        let dump = DumpBuilder::new()
+            .module(nt..nt + 0x1_000, "ntoskrnl.exe")
+            .build();
+        let mut parser = KernelDumpParser::from_bytes(dump).unwrap();
+
+        // ..and the process of the user modules that don't come from the dump
+        // isn't known.
+        parser
+            .import_modules_json(
+                r#"[{"start": "0x7ff600000000", "end": "0x7ff600010000", "name": "a.exe"}]"#,
+            )
+            .unwrap();
+        assert_eq!(
+            parser
+                .modules()
+                .map(|module| (module.name.as_str(), module.origin))
+                .collect::<Vec<_>>(),
+            [
+                ("a.exe", ModuleOrigin::User { process: None }),
+                ("ntoskrnl.exe", ModuleOrigin::Kernel)
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicates() {
+        use crate::testing::DumpBuilder;
+        use crate::KernelDumpParser;
+
+        let nt = 0xffff_f800_0000_0000;
+        let dump = DumpBuilder::new()
+            .module(nt..nt + 0x1_000, "ntoskrnl.exe")
+            .module(nt + 0x1_000..nt + 0x2_000, "a.sys")
+            .module(nt + 0x1_000..nt + 0x3_000, "a.sys")
+            .module(nt + 0x3_000..nt + 0x3_000, "b.sys")
+            .module(nt + 0x3_000..nt + 0x3_000, "c.sys")
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+        // The builder zeroes the fields of the entries it doesn't know about.
+        let ldr_entry = |start, end, name| ModuleEntry {
+            timestamp: Some(0),
+            checksum: Some(0),
+            load_count: Some(0),
+            ..entry(start, end, name)
+        };
+
+        assert_eq!(
+            parser
+                .kernel_modules()
+                .map(|(at, name)| (at.start.u64(), name))
+                .collect::<Vec<_>>(),
+            [
+                (nt, "ntoskrnl.exe"),
+                (nt + 0x1_000, "a.sys"),
+                (nt + 0x3_000, "b.sys")
+            ]
+        );
+        assert_eq!(parser.warnings(), [
+            Warning::DuplicateModule {
+                first: Box::new(ldr_entry(nt + 0x1_000, nt + 0x2_000, "a.sys")),
+                second: Box::new(ldr_entry(nt + 0x1_000, nt + 0x3_000, "a.sys")),
+            },
+            Warning::OverlappingModules {
+                first: module(nt + 0x3_000, nt + 0x3_000, "b.sys"),
+                second: module(nt + 0x3_000, nt + 0x3_000, "c.sys"),
+            }
+        ]);
+    }
+
+    #[test]
+    fn invalid_module_list() {
+        use std::io::Cursor;
+
+        use crate::testing::{DumpBuilder, MODULE_LIST_BASE};
+        use crate::{KdmpParserError, KernelDumpParser, ParserOptions};
+
+        let nt = 0xffff_f800_0000_0000;
+        let dump = DumpBuilder::new()
+            .module(nt..nt + 0x1_000, "ntoskrnl.exe")
+            .module(nt + 0x1_000..nt + 0x2_000, "a.sys")
+            .module(nt + 0x2_000..nt + 0x3_000, "b.sys")
+            .build();
+        let parse = |dump: &Vec<u8>, options| {
+            KernelDumpParser::with_options(Cursor::new(dump.clone()), options)
+        };
+        let names = |parser: &KernelDumpParser| {
+            parser
+                .kernel_modules()
+                .map(|(_, name)| name.to_string())
+                .collect::<Vec<_>>()
+        };
+        let head = Gva::new(MODULE_LIST_BASE);
+
+        // A list with more entries than the maximum is cut short..
+        let options = ParserOptions {
+            max_modules: 2,
+            ..Default::default()
+        };
+        let parser = parse(&dump, options).unwrap();
+        assert_eq!(names(&parser), ["ntoskrnl.exe", "a.sys"]);
+        assert!(matches!(
+            parser.warnings(),
+            [Warning::InvalidModuleList { head: h, kept: 2, .. }] if *h == head
+        ));
+
+        // ..unless parsing is strict.
+        let strict = ParserOptions {
+            strict: true,
+            ..options
+        };
+        assert!(matches!(
+            parse(&dump, strict),
+            Err(KdmpParserError::Strict(Warning::InvalidModuleList {
+                kept: 2,
+                ..
+            }))
+        ));
+
+        // An entry whose name is longer than its buffer, or that spans past the end of
+        // the address space, cuts the list short too.
+        // ```text
+        // kd> dt nt!_KLDR_DATA_TABLE_ENTRY DllBase FullDllName
+        //    +0x030 DllBase          : Ptr64 Void
+        //    +0x048 FullDllName      : _UNICODE_STRING
+        // ```
+        let second_entry = MODULE_LIST_BASE + 0x10 + 0xa0;
+        let offset = |parser: &KernelDumpParser, field: u64| {
+            let gpa = parser
+                .virt_translate(Gva::new(second_entry + field))
+                .unwrap();
+
+            parser.phys_translate(gpa).unwrap() as usize
+        };
+
+        let parser = parse(&dump, ParserOptions::default()).unwrap();
+        let name_length = offset(&parser, 0x48);
+        let dll_base = offset(&parser, 0x30);
+        for (offset, patch) in [
+            (name_length, 0xfffeu16.to_le_bytes().to_vec()),
+            (dll_base, u64::MAX.to_le_bytes().to_vec()),
+        ] {
+            let mut dump = dump.clone();
+            dump[offset..offset + patch.len()].copy_from_slice(&patch);
+            let parser = parse(&dump, ParserOptions::default()).unwrap();
+            assert_eq!(names(&parser), ["ntoskrnl.exe"]);
+            assert!(matches!(parser.warnings(), [Warning::InvalidModuleList {
+                kept: 1,
+                ..
+            }]));
+        }
+    }
+}
@@ -0,0 +1,327 @@
+//! This contains what is needed to find the kernel modules without trusting
+//! `nt!PsLoadedModuleList` (see [`ModuleSource`]), and to diff what the
+//! different sources report (see [`ModuleDiscrepancy`]); a driver that unlinked
+//! itself from the list shows up in the diff.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::KernelDumpParser;
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! for discrepancy in parser.module_discrepancies().unwrap() {
+//!     println!("{discrepancy}");
+//! }
+//! ```
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+
+use crate::error::Result;
+use crate::gxa::Gxa;
+use crate::pe::PeHeaders;
+use crate::structs::{Page, UnicodeString};
+use crate::trace::trace_debug;
+use crate::{Gva, KdmpParserError, KernelDumpParser, ModuleEntry};
+
+/// Number of entries of `nt!MmUnloadedDrivers` (`MI_UNLOADED_DRIVERS`).
+const MI_UNLOADED_DRIVERS: u64 = 50;
+
+/// Where the kernel modules are found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ModuleSource {
+    /// `nt!PsLoadedModuleList`, the list every other API of the parser uses.
+    LoadedModuleList,
+    /// `nt!MmUnloadedDrivers`, the drivers that have been unloaded; their
+    /// memory isn't supposed to hold an image anymore. The kernel loader
+    /// doesn't keep its entries in a hash table like the user one does, so
+    /// this is the other list it maintains.
+    UnloadedDrivers,
+    /// The images whose headers are found in the kernel memory of the dump.
+    PeScan,
+}
+
+impl ModuleSource {
+    /// Is a module found by this source supposed to be loaded?
+    fn loaded(self) -> bool {
+        !matches!(self, Self::UnloadedDrivers)
+    }
+}
+
+/// A kernel module that some of the sources that report loaded modules (see
+/// [`ModuleSource`]) find and others don't.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleDiscrepancy {
+    /// The module, as reported by the first source that found it.
+    pub module: ModuleEntry,
+    /// The sources that found the module.
+    pub found_in: Vec<ModuleSource>,
+    /// The sources that report loaded modules and didn't find it.
+    pub missing_from: Vec<ModuleSource>,
+}
+
+impl Display for ModuleDiscrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "module {:?} ({}-{}) is in {:?} but not in {:?}",
+            self.module.name,
+            self.module.at.start,
+            self.module.at.end,
+            self.found_in,
+            self.missing_from
+        )
+    }
+}
+
+impl KernelDumpParser {
+    /// Get the kernel modules that `source` finds. The modules are in the
+    /// order the source finds them:
+    /// - [`ModuleSource::LoadedModuleList`] is what
+    ///   [`KernelDumpParser::kernel_module_entries`] returns,
+    /// - [`ModuleSource::UnloadedDrivers`] needs the KDDEBUGGER_DATA_BLOCK,
+    /// - [`ModuleSource::PeScan`] walks the kernel half of the page tables and
+    ///   looks for image headers at the start of every page; the modules are
+    ///   named after the name their export directory has, if any.
+    pub fn kernel_modules_via(&self, source: ModuleSource) -> Result<Vec<ModuleEntry>> {
+        match source {
+            ModuleSource::LoadedModuleList => Ok(self.kernel_module_entries().cloned().collect()),
+            ModuleSource::UnloadedDrivers => self.unloaded_drivers(),
+            ModuleSource::PeScan => self.scan_kernel_images(),
+        }
+    }
+
+    /// Diff the kernel modules that every [`ModuleSource`] finds, by base
+    /// address. A module is reported when a source that reports loaded
+    /// modules finds it, and another one doesn't; the unloaded drivers only
+    /// tell where a module was found. A source that can't be used is left
+    /// out.
+    ///
+    /// The header of an image can be paged out, so a module missing from
+    /// [`ModuleSource::PeScan`] isn't necessarily suspicious; a module missing
+    /// from [`ModuleSource::LoadedModuleList`] is.
+    pub fn module_discrepancies(&self) -> Result<Vec<ModuleDiscrepancy>> {
+        let sources = [
+            ModuleSource::LoadedModuleList,
+            ModuleSource::UnloadedDrivers,
+            ModuleSource::PeScan,
+        ];
+
+        let mut available = Vec::new();
+        let mut modules = BTreeMap::<Gva, (ModuleEntry, Vec<ModuleSource>)>::new();
+        for source in sources {
+            let entries = match self.kernel_modules_via(source) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    trace_debug!("failed finding the modules via {source:?}: {e}");
+                    continue;
+                }
+            };
+
+            available.push(source);
+            for entry in entries {
+                let (_, found_in) = modules
+                    .entry(entry.at.start)
+                    .or_insert_with(|| (entry, Vec::new()));
+                if !found_in.contains(&source) {
+                    found_in.push(source);
+                }
+            }
+        }
+
+        Ok(modules
+            .into_values()
+            .filter_map(|(module, found_in)| {
+                let missing_from = available
+                    .iter()
+                    .copied()
+                    .filter(|source| source.loaded() && !found_in.contains(source))
+                    .collect::<Vec<_>>();
+                let loaded = found_in.iter().any(|source| source.loaded());
+
+                (loaded && !missing_from.is_empty()).then_some(ModuleDiscrepancy {
+                    module,
+                    found_in,
+                    missing_from,
+                })
+            })
+            .collect())
+    }
+
+    /// Read the entries of `nt!MmUnloadedDrivers`.
+    fn unloaded_drivers(&self) -> Result<Vec<ModuleEntry>> {
+        let kdbg = self.kd_debugger_data_block()?;
+        let drivers = self.virt_read_ptr(Gva::new(kdbg.mm_unloaded_drivers))?;
+        if drivers.u64() == 0 {
+            return Err(KdmpParserError::NotFound("nt!MmUnloadedDrivers"));
+        }
+
+        let size = self.profile().size("_UNLOADED_DRIVERS")?;
+        let mut modules = Vec::new();
+        for idx in 0..MI_UNLOADED_DRIVERS {
+            let entry = drivers
+                .u64()
+                .checked_add(idx * size)
+                .map(Gva::new)
+                .ok_or(KdmpParserError::Overflow("unloaded driver"))?;
+            let start = self.read_field(entry, "_UNLOADED_DRIVERS", "StartAddress")?;
+            if start == 0 {
+                continue;
+            }
+
+            let end = self.read_field(entry, "_UNLOADED_DRIVERS", "EndAddress")?;
+            let name = self.field_addr(entry, "_UNLOADED_DRIVERS", "Name")?;
+            let name = self.virt_read_struct::<UnicodeString>(name)?;
+            let name = self
+                .try_virt_read_unicode_string(&name)?
+                .unwrap_or_default();
+            modules.push(ModuleEntry::new(Gva::new(start)..Gva::new(end), name));
+        }
+
+        Ok(modules)
+    }
+
+    /// Find the images whose headers are at the start of a kernel page.
+    fn scan_kernel_images(&self) -> Result<Vec<ModuleEntry>> {
+        let mut modules = Vec::new();
+        let mut page = vec![0; Page::size() as usize];
+        self.for_each_kernel_page(&mut |gva, gpa| {
+            let mut magic = [0; 2];
+            match self.phys_read_exact(gpa, &mut magic) {
+                Ok(()) if &magic == b"MZ" => {}
+                Ok(())
+                | Err(KdmpParserError::AddrTranslation(..) | KdmpParserError::PartialPhysRead) => {
+                    return Ok(())
+                }
+                Err(e) => return Err(e),
+            }
+
+            self.phys_read_exact(gpa, &mut page)?;
+            let Some(headers) = PeHeaders::parse(&page)
+                .ok()
+                .filter(|headers| headers.plausible())
+            else {
+                return Ok(());
+            };
+
+            let end = gva
+                .u64()
+                .checked_add(headers.size_of_image.into())
+                .ok_or(KdmpParserError::Overflow("image end"))?;
+            let name = self.export_dll_name(gva).unwrap_or_default();
+            let mut module = ModuleEntry::new(gva..Gva::new(end), name);
+            module.entry_point = (headers.address_of_entry_point != 0)
+                .then(|| Gva::new(gva.u64() + u64::from(headers.address_of_entry_point)));
+            module.timestamp = Some(headers.time_date_stamp);
+            module.checksum = Some(headers.check_sum);
+            trace_debug!("found an image at {gva}");
+            modules.push(module);
+
+            Ok(())
+        })?;
+
+        Ok(modules)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{DumpBuilder, KdDebuggerData};
+    use crate::PxeFlags;
+
+    const KDBG: u64 = 0xffff_f800_0100_0000;
+    const UNLOADED: u64 = 0xffff_f800_0200_0000;
+    const NT: u64 = 0xffff_f800_0400_0000;
+    const LISTED: u64 = 0xffff_f800_0500_0000;
+    const HIDDEN: u64 = 0xffff_f800_0600_0000;
+    const GONE: u64 = 0xffff_f800_0700_0000;
+
+    /// The headers of an image that is `size_of_image` bytes long.
+    fn headers(size_of_image: u32) -> Vec<u8> {
+        let mut page = vec![0; 0x200];
+        page[0..2].copy_from_slice(b"MZ");
+        page[0x3c..0x40].copy_from_slice(&0x80u32.to_le_bytes());
+        page[0x80..0x84].copy_from_slice(b"PE\0\0");
+        page[0x84..0x86].copy_from_slice(&0x8664u16.to_le_bytes());
+        page[0x88..0x8c].copy_from_slice(&0x1234u32.to_le_bytes());
+        page[0x98..0x9a].copy_from_slice(&0x20bu16.to_le_bytes());
+        page[0xd0..0xd4].copy_from_slice(&size_of_image.to_le_bytes());
+        page[0xd4..0xd8].copy_from_slice(&0x400u32.to_le_bytes());
+
+        page
+    }
+
+    #[test]
+    fn discrepancies() {
+        // `nt` is both listed and mapped, `LISTED` is listed but its headers
+        // aren't mapped, `HIDDEN` is mapped but unlinked, and `GONE` has been
+        // unloaded but is still mapped.
+        let mut unloaded = vec![0; 0x28];
+        unloaded[0x10..0x18].copy_from_slice(&GONE.to_le_bytes());
+        unloaded[0x18..0x20].copy_from_slice(&(GONE + 0x2_000).to_le_bytes());
+        let dump = DumpBuilder::new()
+            .module(NT..NT + 0x2_000, "ntoskrnl.exe")
+            .module(LISTED..LISTED + 0x1_000, "listed.sys")
+            .map_virt(KDBG, 0x10_000, PxeFlags::Present)
+            .map_virt(UNLOADED, 0x11_000, PxeFlags::Present)
+            .map_virt(NT, 0x12_000, PxeFlags::Present)
+            .map_virt(HIDDEN, 0x13_000, PxeFlags::Present)
+            .map_virt(GONE, 0x14_000, PxeFlags::Present)
+            .kd_debugger_data(KDBG, KdDebuggerData {
+                mm_unloaded_drivers: UNLOADED,
+                ..Default::default()
+            })
+            .write_virt(UNLOADED, &(UNLOADED + 0x100).to_le_bytes())
+            .write_virt(UNLOADED + 0x100, &unloaded)
+            .write_virt(NT, &headers(0x2_000))
+            .write_virt(HIDDEN, &headers(0x3_000))
+            .write_virt(GONE, &headers(0x2_000))
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+
+        let starts = |source| {
+            parser
+                .kernel_modules_via(source)
+                .unwrap()
+                .into_iter()
+                .map(|module| module.at.start.u64())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(starts(ModuleSource::LoadedModuleList), [NT, LISTED]);
+        assert_eq!(starts(ModuleSource::UnloadedDrivers), [GONE]);
+        assert_eq!(starts(ModuleSource::PeScan), [NT, HIDDEN, GONE]);
+
+        let hidden = &parser.kernel_modules_via(ModuleSource::PeScan).unwrap()[1];
+        assert_eq!(hidden.at.end, Gva::new(HIDDEN + 0x3_000));
+        assert_eq!(hidden.timestamp, Some(0x1234));
+
+        let discrepancies = parser.module_discrepancies().unwrap();
+        let summary = discrepancies
+            .iter()
+            .map(|d| {
+                (
+                    d.module.at.start.u64(),
+                    d.found_in.clone(),
+                    d.missing_from.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        use ModuleSource::*;
+        assert_eq!(summary, [
+            (LISTED, vec![LoadedModuleList], vec![PeScan]),
+            (HIDDEN, vec![PeScan], vec![LoadedModuleList]),
+            (GONE, vec![UnloadedDrivers, PeScan], vec![LoadedModuleList]),
+        ]);
+        assert_eq!(
+            discrepancies[0].to_string(),
+            "module \"listed.sys\" (Gva:0xfffff80005000000-Gva:0xfffff80005001000) is in \
+             [LoadedModuleList] but not in [PeScan]"
+        );
+
+        // Without the KDDEBUGGER_DATA_BLOCK, the unloaded drivers can't be found.
+        let parser = KernelDumpParser::from_bytes(DumpBuilder::new().build()).unwrap();
+        assert!(matches!(
+            parser.kernel_modules_via(ModuleSource::UnloadedDrivers),
+            Err(KdmpParserError::NotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,287 @@
+//! This contains what is needed to enumerate the network endpoints (TCP
+//! connections & listeners, and UDP endpoints) that were open when the dump
+//! was taken.
+//!
+//! `tcpip.sys` keeps track of its endpoints in `tcpip!PartitionTable` and in
+//! its port pools, but those aren't reachable without its symbols. Instead,
+//! the endpoints are found by scanning physical memory for the pool
+//! allocations tagged `TcpE`, `TcpL` and `UdpA`, and are decoded using the
+//! [`crate::Profile`]. The allocations that don't decode to a valid endpoint
+//! (freed ones for example) are skipped.
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use crate::error::Result;
+use crate::gxa::Gxa;
+use crate::structs::Page;
+use crate::{Gva, KdmpParserError, KernelDumpParser};
+
+/// ```text
+/// kd> dt nt!_POOL_HEADER
+///    +0x004 PoolTag          : Uint4B
+/// ```
+const POOL_HEADER_SIZE: usize = 0x10;
+const POOL_HEADER_POOL_TAG: usize = 0x4;
+
+/// Pool allocations are 16 bytes aligned.
+const POOL_ALIGNMENT: usize = 0x10;
+
+/// Values of `_INETAF.AddressFamily`.
+const AF_INET: u64 = 2;
+const AF_INET6: u64 = 23;
+
+/// The transport protocol of a [`Connection`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+/// The state of a TCP connection (`tcpip!_TCP_STATE`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TcpState {
+    Closed,
+    Listen,
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+    DeleteTcb,
+}
+
+impl TryFrom<u64> for TcpState {
+    type Error = KdmpParserError;
+
+    fn try_from(value: u64) -> Result<Self> {
+        Ok(match value {
+            0 => Self::Closed,
+            1 => Self::Listen,
+            2 => Self::SynSent,
+            3 => Self::SynReceived,
+            4 => Self::Established,
+            5 => Self::FinWait1,
+            6 => Self::FinWait2,
+            7 => Self::CloseWait,
+            8 => Self::Closing,
+            9 => Self::LastAck,
+            10 => Self::TimeWait,
+            11 => Self::DeleteTcb,
+            _ => return Err(KdmpParserError::InvalidData("unknown tcp state")),
+        })
+    }
+}
+
+/// A network endpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Connection {
+    pub protocol: Protocol,
+    /// The local address & port.
+    pub local: SocketAddr,
+    /// The remote address & port; only TCP connections have one.
+    pub remote: Option<SocketAddr>,
+    /// State of the connection; UDP endpoints don't have one.
+    pub state: Option<TcpState>,
+    /// Pid of the process that owns the endpoint.
+    pub owning_pid: u64,
+}
+
+/// The kinds of endpoint allocations.
+#[derive(Debug, Clone, Copy)]
+enum Endpoint {
+    Tcp,
+    TcpListener,
+    Udp,
+}
+
+impl Endpoint {
+    /// Get the kind of endpoint allocated with a pool tag. The high bit of the
+    /// tag (the 'protected' bit) might be set.
+    fn from_pool_tag(tag: &[u8]) -> Option<Self> {
+        let tag = [tag[0], tag[1], tag[2], tag[3] & 0x7f];
+        match &tag {
+            b"TcpE" => Some(Self::Tcp),
+            b"TcpL" => Some(Self::TcpListener),
+            b"UdpA" => Some(Self::Udp),
+            _ => None,
+        }
+    }
+
+    /// Name of the structure describing the endpoint.
+    fn type_name(self) -> &'static str {
+        match self {
+            Self::Tcp => "_TCP_ENDPOINT",
+            Self::TcpListener => "_TCP_LISTENER",
+            Self::Udp => "_UDP_ENDPOINT",
+        }
+    }
+}
+
+/// Is this an error caused by memory that isn't in the dump, or by an
+/// allocation that isn't a valid endpoint?
+fn is_invalid(e: &KdmpParserError) -> bool {
+    matches!(
+        e,
+        KdmpParserError::AddrTranslation(..)
+            | KdmpParserError::PartialVirtRead
+            | KdmpParserError::PartialPhysRead
+            | KdmpParserError::InvalidData(..)
+    )
+}
+
+/// Is this an address in kernel space?
+fn is_kernel_address(addr: u64) -> bool {
+    addr >> 47 == 0x1_ff_ff
+}
+
+/// Ports are stored in network byte order.
+fn port(raw: u64) -> u16 {
+    (raw as u16).swap_bytes()
+}
+
+impl KernelDumpParser {
+    /// Get the network endpoints that were open when the dump was taken.
+    ///
+    /// Finding them requires scanning physical memory, so this can take a
+    /// little while on large dumps.
+    pub fn connections(&self) -> Result<Vec<Connection>> {
+        let mut page = vec![0; Page::size() as usize];
+        let mut connections = Vec::new();
+        for (gpa, _) in self.physmem() {
+            if self.phys_read(gpa, &mut page)? != page.len() {
+                continue;
+            }
+
+            for offset in (0..page.len()).step_by(POOL_ALIGNMENT) {
+                let tag = offset + POOL_HEADER_POOL_TAG;
+                let Some(endpoint) = Endpoint::from_pool_tag(&page[tag..tag + 4]) else {
+                    continue;
+                };
+
+                // The endpoint directly follows the pool header.
+                let data = &page[offset + POOL_HEADER_SIZE..];
+                match self.connection(endpoint, data) {
+                    Ok(connection) => connections.push(connection),
+                    Err(e) if is_invalid(&e) => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        Ok(connections)
+    }
+
+    /// Decode an endpoint; `data` is its content until the end of its
+    /// physical page.
+    fn connection(&self, endpoint: Endpoint, data: &[u8]) -> Result<Connection> {
+        let type_name = endpoint.type_name();
+        let field = |name| self.profile().decode_field(data, type_name, name);
+        let owner = field("Owner")?;
+        if !is_kernel_address(owner) {
+            return Err(KdmpParserError::InvalidData("invalid endpoint owner"));
+        }
+
+        let owning_pid = self.read_field(owner.into(), "_EPROCESS", "UniqueProcessId")?;
+        let family = self.read_field(field("InetAF")?.into(), "_INETAF", "AddressFamily")?;
+        let (protocol, local, remote, state) = match endpoint {
+            Endpoint::Tcp => {
+                let state = TcpState::try_from(field("State")?)?;
+                let addr_info = Gva::new(field("AddrInfo")?);
+                let local = self.read_field(addr_info, "_ADDRINFO", "Local")?;
+                let local = self.read_field(local.into(), "_LOCAL_ADDRESS", "pData")?;
+                let local = self.read_ip(family, self.virt_read_ptr(local)?)?;
+                let remote = self.read_field(addr_info, "_ADDRINFO", "Remote")?;
+                let remote = self.read_ip(family, remote.into())?;
+                let remote = SocketAddr::new(remote, port(field("RemotePort")?));
+
+                (
+                    Protocol::Tcp,
+                    SocketAddr::new(local, port(field("LocalPort")?)),
+                    Some(remote),
+                    Some(state),
+                )
+            }
+            Endpoint::TcpListener | Endpoint::Udp => {
+                let local = self.read_local_address(family, field("LocalAddr")?.into())?;
+                let local = SocketAddr::new(local, port(field("Port")?));
+                match endpoint {
+                    Endpoint::TcpListener => (Protocol::Tcp, local, None, Some(TcpState::Listen)),
+                    _ => (Protocol::Udp, local, None, None),
+                }
+            }
+        };
+
+        Ok(Connection {
+            protocol,
+            local,
+            remote,
+            state,
+            owning_pid,
+        })
+    }
+
+    /// Read the address of a listener or of a UDP endpoint from its
+    /// `_LOCAL_ADDRESS_WIN10_UDP`; endpoints bound to every interface don't
+    /// have one.
+    fn read_local_address(&self, family: u64, local: Gva) -> Result<IpAddr> {
+        if local.u64() == 0 {
+            return match family {
+                AF_INET => Ok(Ipv4Addr::UNSPECIFIED.into()),
+                AF_INET6 => Ok(Ipv6Addr::UNSPECIFIED.into()),
+                _ => Err(KdmpParserError::InvalidData("unknown address family")),
+            };
+        }
+
+        let address = self.read_field(local, "_LOCAL_ADDRESS_WIN10_UDP", "pData")?;
+
+        self.read_ip(family, address.into())
+    }
+
+    /// Read an IPv4 or an IPv6 address.
+    fn read_ip(&self, family: u64, address: Gva) -> Result<IpAddr> {
+        match family {
+            AF_INET => {
+                let mut octets = [0; 4];
+                self.virt_read_exact(address, &mut octets)?;
+
+                Ok(Ipv4Addr::from(octets).into())
+            }
+            AF_INET6 => {
+                let mut octets = [0; 16];
+                self.virt_read_exact(address, &mut octets)?;
+
+                Ok(Ipv6Addr::from(octets).into())
+            }
+            _ => Err(KdmpParserError::InvalidData("unknown address family")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tcp_state() {
+        assert_eq!(TcpState::try_from(4).unwrap(), TcpState::Established);
+        assert_eq!(TcpState::try_from(11).unwrap(), TcpState::DeleteTcb);
+        assert!(TcpState::try_from(12).is_err());
+    }
+
+    #[test]
+    fn pool_tag() {
+        assert!(matches!(
+            Endpoint::from_pool_tag(b"TcpE"),
+            Some(Endpoint::Tcp)
+        ));
+        assert!(matches!(
+            Endpoint::from_pool_tag(b"Udp\xc1"),
+            Some(Endpoint::Udp)
+        ));
+        assert!(Endpoint::from_pool_tag(b"File").is_none());
+        assert_eq!(port(0x3500), 53);
+    }
+}
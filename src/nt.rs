@@ -0,0 +1,69 @@
+//! This contains what is needed to locate `nt` without relying on the module
+//! list of the dump, which is randomized by KASLR.
+use crate::error::Result;
+use crate::gxa::Gxa;
+use crate::structs::Page;
+use crate::{Gva, KdmpParserError, KernelDumpParser};
+
+/// Where the kernel address space starts at.
+pub(crate) const KERNEL_SPACE_START: u64 = 0xffff_8000_0000_0000;
+
+/// How far back from a known kernel address we'll look for `nt`'s headers.
+const NT_SEARCH_WINDOW: u64 = 0x400_0000;
+
+/// The name `nt` exports itself as.
+pub(crate) const NT_EXPORT_NAME: &str = "ntoskrnl.exe";
+
+impl KernelDumpParser {
+    /// Locate the base of `nt` by walking back page by page from addresses
+    /// that are known to be inside of it (the instruction pointer of the
+    /// context, the exception address, etc.), looking for an image that
+    /// exports itself as `ntoskrnl.exe`.
+    ///
+    /// This doesn't need the module list or the `KDDEBUGGER_DATA_BLOCK` of
+    /// the dump.
+    pub fn find_nt_base(&self) -> Result<Gva> {
+        let candidates = [
+            self.context_record().rip,
+            self.exception_record().exception_address,
+            self.headers().ps_loaded_module_list,
+            self.headers().kd_debugger_data_block,
+        ];
+
+        for start in candidates {
+            if start < KERNEL_SPACE_START {
+                continue;
+            }
+
+            let start = Gva::new(start).page_align();
+            let lowest = start
+                .u64()
+                .saturating_sub(NT_SEARCH_WINDOW)
+                .max(KERNEL_SPACE_START);
+            let mut page = start.u64();
+            while page >= lowest {
+                if self.is_nt(Gva::new(page))? {
+                    return Ok(Gva::new(page));
+                }
+
+                page -= Page::size();
+            }
+        }
+
+        Err(KdmpParserError::NotFound("nt"))
+    }
+
+    /// Is the page at `base` the start of `nt`?
+    fn is_nt(&self, base: Gva) -> Result<bool> {
+        let mut magic = [0; 2];
+        if self.try_virt_read_exact(base, &mut magic)?.is_none() || &magic != b"MZ" {
+            return Ok(false);
+        }
+
+        match self.export_dll_name(base) {
+            Ok(name) => Ok(name.eq_ignore_ascii_case(NT_EXPORT_NAME)),
+            Err(KdmpParserError::Io(e)) => Err(KdmpParserError::Io(e)),
+            Err(_) => Ok(false),
+        }
+    }
+}
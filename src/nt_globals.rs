@@ -0,0 +1,266 @@
+//! This contains what is needed to resolve the globals of `nt` that describe
+//! the processors (see [`NtGlobals`]): `nt!KiProcessorBlock`,
+//! `nt!KeNumberProcessors` and `nt!KiInitialPCR`. They aren't exported, so
+//! they come from the `KDDEBUGGER_DATA_BLOCK` when the dump has one, and from
+//! the pointers that tie the `_KPCR`s and the `_KPRCB`s together otherwise.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::KernelDumpParser;
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! let globals = parser.nt_globals();
+//! for (idx, prcb) in globals.prcbs.iter().enumerate() {
+//!     println!("processor {idx}: {prcb:?}");
+//! }
+//! ```
+use crate::error::Result;
+use crate::gxa::{Gxa, GxaRange};
+use crate::processor::KPCR_PRCB;
+use crate::structs::Page;
+use crate::{Gva, KdmpParserError, KernelDumpParser};
+
+/// Where the `_KPCR` points to itself.
+///
+/// ```text
+/// kd> dt nt!_KPCR Self CurrentPrcb
+///    +0x018 Self             : Ptr64 _KPCR
+///    +0x020 CurrentPrcb      : Ptr64 _KPRCB
+/// ```
+const KPCR_SELF: u64 = 0x18;
+
+/// Where the `_KPCR` points to its `_KPRCB`.
+const KPCR_CURRENT_PRCB: u64 = 0x20;
+
+/// The globals of `nt` that describe the processors; see
+/// [`KernelDumpParser::nt_globals`]. What couldn't be resolved is `None`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct NtGlobals {
+    /// `nt!KiProcessorBlock`: the array of the `_KPRCB` pointers of the
+    /// processors.
+    pub ki_processor_block: Option<Gva>,
+    /// `nt!KeNumberProcessors`, from the dump headers.
+    pub number_processors: u32,
+    /// `nt!KiInitialPCR`: the `_KPCR` of the first processor.
+    pub initial_pcr: Option<Gva>,
+    /// The `_KPRCB` of every processor, out of `nt!KiProcessorBlock`; the ones
+    /// that can't be read are `None`.
+    pub prcbs: Vec<Option<Gva>>,
+}
+
+/// Read the 8-byte aligned slot at `offset` of `page`.
+fn slot(page: &[u8], offset: usize) -> Option<u64> {
+    page.get(offset..offset + 8)
+        .map(|slot| u64::from_le_bytes(slot.try_into().unwrap()))
+}
+
+impl KernelDumpParser {
+    /// Resolve the globals of `nt` that describe the processors. They are
+    /// resolved the first time they are needed, in order:
+    /// - `nt!KiProcessorBlock` comes from the `KDDEBUGGER_DATA_BLOCK`, or from
+    ///   the PDB of `nt` when one is loaded (see
+    ///   [`KernelDumpParser::resolve_symbol`]); without either, it is the array
+    ///   in `nt` whose entries are `_KPRCB`s of `_KPCR`s that point to
+    ///   themselves (`gs:[0x18]`) and to them (`gs:[0x20]`), starting with the
+    ///   one of `nt!KiInitialPCR`,
+    /// - `nt!KiInitialPCR` is the `_KPCR` of the first `_KPRCB`, or the one in
+    ///   `nt` if `nt!KiProcessorBlock` isn't known.
+    ///
+    /// What can't be resolved is left out.
+    pub fn nt_globals(&self) -> &NtGlobals {
+        self.nt_globals.get_or_init(|| self.resolve_nt_globals())
+    }
+
+    /// Get `nt!KiProcessorBlock`, which the features that walk the
+    /// processors need.
+    pub(crate) fn ki_processor_block(&self) -> Result<Gva> {
+        self.nt_globals()
+            .ki_processor_block
+            .ok_or(KdmpParserError::NotFound("nt!KiProcessorBlock"))
+    }
+
+    fn resolve_nt_globals(&self) -> NtGlobals {
+        let number_processors = self.headers().number_processors;
+        let mut ki_processor_block = self
+            .kd_debugger_data_block()
+            .ok()
+            .map(|kdbg| Gva::new(kdbg.ki_processor_block))
+            .filter(|block| block.u64() != 0)
+            .or_else(|| self.resolve_symbol("nt!KiProcessorBlock").ok());
+
+        let mut initial_pcr = None;
+        if ki_processor_block.is_none() {
+            initial_pcr = self.find_initial_pcr();
+            ki_processor_block =
+                initial_pcr.and_then(|pcr| self.find_processor_block(pcr, number_processors));
+        }
+
+        let prcbs = (0..number_processors)
+            .map(|idx| {
+                ki_processor_block
+                    .and_then(|block| block.u64().checked_add(u64::from(idx) * 8))
+                    .and_then(|ptr| self.virt_read_ptr(Gva::new(ptr)).ok())
+                    .filter(|prcb| prcb.u64() != 0)
+            })
+            .collect::<Vec<_>>();
+
+        let initial_pcr = initial_pcr.or_else(|| {
+            let prcb = prcbs.first().copied().flatten()?;
+            let pcr = Gva::new(prcb.u64().checked_sub(KPCR_PRCB)?);
+
+            self.is_pcr(pcr).then_some(pcr)
+        });
+
+        NtGlobals {
+            ki_processor_block,
+            number_processors,
+            initial_pcr,
+            prcbs,
+        }
+    }
+
+    /// Is there a `_KPCR` at `pcr`: does it point to itself, and to its
+    /// `_KPRCB`?
+    fn is_pcr(&self, pcr: Gva) -> bool {
+        let Some(own) = pcr.u64().checked_add(KPCR_SELF) else {
+            return false;
+        };
+
+        let Ok([own, prcb]) = self.virt_read_struct::<[u64; 2]>(Gva::new(own)) else {
+            return false;
+        };
+
+        own == pcr.u64() && Some(prcb) == pcr.u64().checked_add(KPCR_PRCB)
+    }
+
+    /// The pages of `nt` that are in the dump, along with their content.
+    pub(crate) fn nt_pages(&self) -> impl Iterator<Item = (Gva, Vec<u8>)> + '_ {
+        let image = self
+            .nt_base()
+            .or_else(|| self.find_nt_base().ok())
+            .and_then(|base| {
+                let size = self.pe_headers(base).ok()?.size_of_image;
+
+                Some(base..Gva::new(base.u64().checked_add(size.into())?))
+            })
+            .unwrap_or_default();
+
+        image.pages().filter_map(|page| {
+            let mut content = vec![0; Page::size() as usize];
+            self.virt_read_exact(page, &mut content)
+                .ok()
+                .map(|_| (page, content))
+        })
+    }
+
+    /// Find `nt!KiInitialPCR`: the `_KPCR` in `nt` that points to itself and
+    /// to its `_KPRCB`.
+    fn find_initial_pcr(&self) -> Option<Gva> {
+        self.nt_pages().find_map(|(page, content)| {
+            (0..content.len()).step_by(8).find_map(|offset| {
+                let pcr = page.u64() + offset as u64;
+                let own = slot(&content, offset + KPCR_SELF as usize)?;
+                let prcb = slot(&content, offset + KPCR_CURRENT_PRCB as usize)?;
+
+                (own == pcr && Some(prcb) == pcr.checked_add(KPCR_PRCB)).then_some(Gva::new(pcr))
+            })
+        })
+    }
+
+    /// Find `nt!KiProcessorBlock`: the array in `nt` that starts with the
+    /// `_KPRCB` of `initial_pcr`, followed by the ones of the other processors.
+    fn find_processor_block(&self, initial_pcr: Gva, number_processors: u32) -> Option<Gva> {
+        let first = initial_pcr.u64().checked_add(KPCR_PRCB)?;
+        let is_processor_block = |block: u64| {
+            (1..u64::from(number_processors)).all(|idx| {
+                block
+                    .checked_add(idx * 8)
+                    .and_then(|entry| self.virt_read_struct::<u64>(Gva::new(entry)).ok())
+                    .and_then(|prcb| prcb.checked_sub(KPCR_PRCB))
+                    .is_some_and(|pcr| self.is_pcr(Gva::new(pcr)))
+            })
+        };
+
+        self.nt_pages().find_map(|(page, content)| {
+            (0..content.len()).step_by(8).find_map(|offset| {
+                let block = page.u64() + offset as u64;
+                let is_candidate = slot(&content, offset) == Some(first)
+                    && block != initial_pcr.u64() + KPCR_CURRENT_PRCB;
+
+                (is_candidate && is_processor_block(block)).then_some(Gva::new(block))
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DumpBuilder;
+    use crate::PxeFlags;
+
+    const NT: u64 = 0xffff_f800_0000_0000;
+    const PCR: u64 = 0xffff_f800_0100_0000;
+
+    /// A `nt` whose headers say it is `0x3_000` bytes long.
+    fn nt_headers() -> Vec<u8> {
+        let mut headers = vec![0; 0x1_000];
+        headers[..2].copy_from_slice(b"MZ");
+        headers[0x3c..0x40].copy_from_slice(&0x80u32.to_le_bytes());
+        headers[0x80..0x84].copy_from_slice(b"PE\0\0");
+        headers[0x84..0x86].copy_from_slice(&0x8664u16.to_le_bytes());
+        headers[0x98..0x9a].copy_from_slice(&0x20bu16.to_le_bytes());
+        headers[0xd0..0xd4].copy_from_slice(&0x3_000u32.to_le_bytes());
+        headers[0xd4..0xd8].copy_from_slice(&0x400u32.to_le_bytes());
+
+        headers
+    }
+
+    /// Lay out a `_KPCR` at `pcr`.
+    fn pcr(builder: DumpBuilder, pcr: u64) -> DumpBuilder {
+        builder
+            .write_virt(pcr + KPCR_SELF, &pcr.to_le_bytes())
+            .write_virt(pcr + KPCR_CURRENT_PRCB, &(pcr + KPCR_PRCB).to_le_bytes())
+    }
+
+    #[test]
+    fn nt_globals() {
+        // Without a KDDEBUGGER_DATA_BLOCK, `nt!KiInitialPCR` is in `nt` and the
+        // array that points to its `_KPRCB` and to the one of the other
+        // processor is `nt!KiProcessorBlock`; a lone pointer to the first
+        // `_KPRCB` isn't.
+        let initial_pcr = NT + 0x1_000;
+        let block = NT + 0x2_100;
+        let builder = DumpBuilder::new()
+            .processors(2)
+            .map_virt(NT, 0x10_000, PxeFlags::Present)
+            .map_virt(NT + 0x1_000, 0x11_000, PxeFlags::Present)
+            .map_virt(NT + 0x2_000, 0x12_000, PxeFlags::Present)
+            .map_virt(PCR, 0x13_000, PxeFlags::Present)
+            .write_virt(NT, &nt_headers())
+            .write_virt(NT + 0x2_000, &(initial_pcr + KPCR_PRCB).to_le_bytes())
+            .write_virt(block, &(initial_pcr + KPCR_PRCB).to_le_bytes())
+            .write_virt(block + 8, &(PCR + KPCR_PRCB).to_le_bytes());
+        let dump = pcr(pcr(builder, initial_pcr), PCR).build();
+
+        let mut parser = KernelDumpParser::from_bytes(dump).unwrap();
+        parser.nt_base = Some(Gva::new(NT));
+        assert_eq!(parser.nt_globals(), &NtGlobals {
+            ki_processor_block: Some(Gva::new(block)),
+            number_processors: 2,
+            initial_pcr: Some(Gva::new(initial_pcr)),
+            prcbs: vec![
+                Some(Gva::new(initial_pcr + KPCR_PRCB)),
+                Some(Gva::new(PCR + KPCR_PRCB))
+            ],
+        });
+
+        // What can't be resolved is left out.
+        let parser = KernelDumpParser::from_bytes(DumpBuilder::new().build()).unwrap();
+        assert_eq!(parser.nt_globals(), &NtGlobals {
+            number_processors: 1,
+            prcbs: vec![None],
+            ..Default::default()
+        });
+    }
+}
@@ -0,0 +1,353 @@
+//! This contains what is needed to go from a pointer to a kernel object to its
+//! type and its name in the object namespace.
+//!
+//! The type of an object is encoded in the `TypeIndex` field of its
+//! `nt!_OBJECT_HEADER`. Since Windows 10 1607, it is XOR'd with
+//! `nt!ObHeaderCookie` and with the second byte of the header address. The
+//! cookie isn't available in the KDDEBUGGER_DATA_BLOCK, so it is solved using
+//! the `Type` object type (which is an object of type `Type`); the names of the
+//! types are found by walking the `\ObjectTypes` directory.
+use std::collections::HashMap;
+
+use crate::error::Result;
+use crate::gxa::Gxa;
+use crate::structs::UnicodeString;
+use crate::{Gva, KdmpParserError, KernelDumpParser};
+
+/// ```text
+/// kd> dt nt!_OBJECT_HEADER
+///    +0x018 TypeIndex        : UChar
+///    +0x01a InfoMask         : UChar
+///    +0x030 Body             : _QUAD
+/// ```
+const OBJECT_HEADER_TYPE_INDEX: u64 = 0x18;
+const OBJECT_HEADER_INFO_MASK: u64 = 0x1a;
+const OBJECT_HEADER_BODY: u64 = 0x30;
+
+/// The optional headers that precede an `_OBJECT_HEADER` are described by its
+/// `InfoMask`. They're laid out in reverse order: the
+/// `_OBJECT_HEADER_NAME_INFO` is right before the `_OBJECT_HEADER_CREATOR_INFO`
+/// (if there's one), which is right before the `_OBJECT_HEADER`.
+const INFO_MASK_CREATOR_INFO: u8 = 0x1;
+const INFO_MASK_NAME_INFO: u8 = 0x2;
+const CREATOR_INFO_SIZE: u64 = 0x20;
+const NAME_INFO_SIZE: u64 = 0x20;
+
+/// ```text
+/// kd> dt nt!_OBJECT_HEADER_NAME_INFO
+///    +0x000 Directory        : Ptr64 _OBJECT_DIRECTORY
+///    +0x008 Name             : _UNICODE_STRING
+/// ```
+const NAME_INFO_DIRECTORY: u64 = 0x0;
+const NAME_INFO_NAME: u64 = 0x8;
+
+/// ```text
+/// kd> dt nt!_OBJECT_TYPE Name Index
+///    +0x010 Name             : _UNICODE_STRING
+///    +0x028 Index            : UChar
+/// ```
+const OBJECT_TYPE_NAME: u64 = 0x10;
+const OBJECT_TYPE_INDEX: u64 = 0x28;
+
+/// ```text
+/// kd> dt nt!_OBJECT_DIRECTORY HashBuckets
+///    +0x000 HashBuckets      : [37] Ptr64 _OBJECT_DIRECTORY_ENTRY
+/// kd> dt nt!_OBJECT_DIRECTORY_ENTRY
+///    +0x000 ChainLink        : Ptr64 _OBJECT_DIRECTORY_ENTRY
+///    +0x008 Object           : Ptr64 Void
+/// ```
+const DIRECTORY_BUCKETS: u64 = 37;
+const DIRECTORY_ENTRY_OBJECT: u64 = 0x8;
+
+/// Maximum number of entries we'll walk in a directory.
+const MAX_DIRECTORY_ENTRIES: usize = 0x10_000;
+
+/// Maximum depth of the object namespace we'll walk.
+const MAX_DIRECTORY_DEPTH: usize = 0x40;
+
+/// Type, name and location of a kernel object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectInfo {
+    /// Name of the type of the object, like `Device` or `Event`.
+    pub type_name: String,
+    /// Name of the object, if it has one.
+    pub name: Option<String>,
+    /// Path of the directory the object lives in, like `\Device`.
+    pub directory_path: Option<String>,
+}
+
+impl ObjectInfo {
+    /// Full path of the object in the object namespace, like
+    /// `\Device\HarddiskVolume1`.
+    pub fn path(&self) -> Option<String> {
+        let name = self.name.as_ref()?;
+        let directory = self.directory_path.as_ref()?;
+
+        Some(match directory.as_str() {
+            "\\" => format!("\\{name}"),
+            _ => format!("{directory}\\{name}"),
+        })
+    }
+}
+
+/// The object types of the dump.
+pub(crate) struct ObjectTypes {
+    /// Name of the types indexed by their type index.
+    names: HashMap<u8, String>,
+    /// The `nt!ObHeaderCookie`, if there's one.
+    cookie: Option<u8>,
+}
+
+impl ObjectTypes {
+    /// Decode the `TypeIndex` field of the `_OBJECT_HEADER` at `header`.
+    fn type_index(&self, raw: u8, header: Gva) -> u8 {
+        match self.cookie {
+            Some(cookie) => raw ^ cookie ^ (header.u64() >> 8) as u8,
+            None => raw,
+        }
+    }
+}
+
+/// Compute the address of a field at `offset` from `base`.
+fn field(base: Gva, offset: u64) -> Result<Gva> {
+    base.u64()
+        .checked_add(offset)
+        .map(Gva::new)
+        .ok_or(KdmpParserError::Overflow("object field"))
+}
+
+/// Get the address of the `_OBJECT_HEADER` of an object.
+fn object_header(object: Gva) -> Result<Gva> {
+    object
+        .u64()
+        .checked_sub(OBJECT_HEADER_BODY)
+        .map(Gva::new)
+        .ok_or(KdmpParserError::Overflow("object header"))
+}
+
+impl KernelDumpParser {
+    /// Get the type, the name and the path of a kernel object.
+    pub fn object_info(&self, object: Gva) -> Result<ObjectInfo> {
+        let types = self.object_types()?;
+        let header = object_header(object)?;
+        let raw = self.virt_read_struct::<u8>(field(header, OBJECT_HEADER_TYPE_INDEX)?)?;
+        let type_name = types
+            .names
+            .get(&types.type_index(raw, header))
+            .cloned()
+            .ok_or(KdmpParserError::InvalidData("unknown object type index"))?;
+
+        let Some((directory, name)) = self.object_name_info(object)? else {
+            return Ok(ObjectInfo {
+                type_name,
+                name: None,
+                directory_path: None,
+            });
+        };
+
+        let directory_path = match directory.u64() {
+            0 => None,
+            _ => self.object_directory_path(directory)?,
+        };
+
+        Ok(ObjectInfo {
+            type_name,
+            name: Some(name),
+            directory_path,
+        })
+    }
+
+    /// Get the object types; they are only built once.
+    fn object_types(&self) -> Result<&ObjectTypes> {
+        if let Some(types) = self.object_types.get() {
+            return Ok(types);
+        }
+
+        let types = self.build_object_types()?;
+
+        Ok(self.object_types.get_or_init(|| types))
+    }
+
+    /// Walk the `\ObjectTypes` directory to find the name of every type and
+    /// solve the header cookie.
+    fn build_object_types(&self) -> Result<ObjectTypes> {
+        let kdbg = self.kd_debugger_data_block()?;
+        let root = self.virt_read_ptr(kdbg.obp_root_directory_object)?;
+        let mut object_types = None;
+        for object in self.object_directory_entries(root)? {
+            if let Some((_, name)) = self.object_name_info(object)? {
+                if name == "ObjectTypes" {
+                    object_types = Some(object);
+                    break;
+                }
+            }
+        }
+
+        let object_types = object_types.ok_or(KdmpParserError::NotFound("\\ObjectTypes"))?;
+        let mut names = HashMap::new();
+        for object_type in self.object_directory_entries(object_types)? {
+            let index = self.virt_read_struct::<u8>(field(object_type, OBJECT_TYPE_INDEX)?)?;
+            let name =
+                self.virt_read_struct::<UnicodeString>(field(object_type, OBJECT_TYPE_NAME)?)?;
+            if let Some(name) = self.try_virt_read_unicode_string(&name)? {
+                names.insert(index, name);
+            }
+        }
+
+        // The `Type` object type is an object of type `Type`, and the root directory is
+        // an object of type `Directory`. This gives us two objects for which we know
+        // both the encoded & decoded type indices.
+        let type_type = self.virt_read_ptr(kdbg.obp_type_object_type)?;
+        let type_index = self.virt_read_struct::<u8>(field(type_type, OBJECT_TYPE_INDEX)?)?;
+        let directory_index = names
+            .iter()
+            .find_map(|(&index, name)| (name == "Directory").then_some(index))
+            .ok_or(KdmpParserError::NotFound("the Directory object type"))?;
+
+        let type_header = object_header(type_type)?;
+        let type_raw =
+            self.virt_read_struct::<u8>(field(type_header, OBJECT_HEADER_TYPE_INDEX)?)?;
+        let root_header = object_header(root)?;
+        let root_raw =
+            self.virt_read_struct::<u8>(field(root_header, OBJECT_HEADER_TYPE_INDEX)?)?;
+
+        // Either the type indices aren't encoded (before 1607), or they are and we can
+        // solve for the cookie.
+        let cookie = type_raw ^ type_index ^ (type_header.u64() >> 8) as u8;
+        [None, Some(cookie)]
+            .into_iter()
+            .map(|cookie| ObjectTypes {
+                names: names.clone(),
+                cookie,
+            })
+            .find(|types| {
+                types.type_index(type_raw, type_header) == type_index
+                    && types.type_index(root_raw, root_header) == directory_index
+            })
+            .ok_or(KdmpParserError::InvalidData(
+                "could not decode the object type indices",
+            ))
+    }
+
+    /// Get the objects in the directories named `names` at the root of the
+    /// object namespace, like `Driver`.
+    pub(crate) fn root_directory_objects(&self, names: &[&str]) -> Result<Vec<Gva>> {
+        let root = self.virt_read_ptr(self.kd_debugger_data_block()?.obp_root_directory_object)?;
+        let mut objects = Vec::new();
+        for object in self.object_directory_entries(root)? {
+            if let Some((_, name)) = self.object_name_info(object)? {
+                if names.contains(&name.as_str()) {
+                    objects.extend(self.object_directory_entries(object)?);
+                }
+            }
+        }
+
+        Ok(objects)
+    }
+
+    /// Get the objects in an `_OBJECT_DIRECTORY`.
+    fn object_directory_entries(&self, directory: Gva) -> Result<Vec<Gva>> {
+        let mut objects = Vec::new();
+        for bucket in 0..DIRECTORY_BUCKETS {
+            let mut entry = self.virt_read_ptr(field(directory, bucket * 8)?)?;
+            while entry.u64() != 0 {
+                if objects.len() >= MAX_DIRECTORY_ENTRIES {
+                    return Err(KdmpParserError::InvalidData("too many directory entries"));
+                }
+
+                objects.push(self.virt_read_ptr(field(entry, DIRECTORY_ENTRY_OBJECT)?)?);
+                entry = self.virt_read_ptr(entry)?;
+            }
+        }
+
+        Ok(objects)
+    }
+
+    /// Get the directory & the name of an object from its
+    /// `_OBJECT_HEADER_NAME_INFO`, if it has one.
+    fn object_name_info(&self, object: Gva) -> Result<Option<(Gva, String)>> {
+        let header = object_header(object)?;
+        let info_mask = self.virt_read_struct::<u8>(field(header, OBJECT_HEADER_INFO_MASK)?)?;
+        if info_mask & INFO_MASK_NAME_INFO == 0 {
+            return Ok(None);
+        }
+
+        let mut offset = NAME_INFO_SIZE;
+        if info_mask & INFO_MASK_CREATOR_INFO != 0 {
+            offset += CREATOR_INFO_SIZE;
+        }
+
+        let name_info = header
+            .u64()
+            .checked_sub(offset)
+            .map(Gva::new)
+            .ok_or(KdmpParserError::Overflow("object name info"))?;
+        let directory = self.virt_read_ptr(field(name_info, NAME_INFO_DIRECTORY)?)?;
+        let name = self.virt_read_struct::<UnicodeString>(field(name_info, NAME_INFO_NAME)?)?;
+
+        Ok(self
+            .try_virt_read_unicode_string(&name)?
+            .map(|name| (directory, name)))
+    }
+
+    /// Build the path of a directory by walking its parents up to the root.
+    fn object_directory_path(&self, mut directory: Gva) -> Result<Option<String>> {
+        let root = self.virt_read_ptr(self.kd_debugger_data_block()?.obp_root_directory_object)?;
+        let mut names = Vec::new();
+        while directory != root {
+            if names.len() >= MAX_DIRECTORY_DEPTH {
+                return Ok(None);
+            }
+
+            // If a parent doesn't have a name, the directory isn't reachable from the
+            // root.
+            let Some((parent, name)) = self.object_name_info(directory)? else {
+                return Ok(None);
+            };
+
+            names.push(name);
+            directory = parent;
+        }
+
+        names.reverse();
+
+        Ok(Some(format!("\\{}", names.join("\\"))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn type_index() {
+        let types = ObjectTypes {
+            names: HashMap::new(),
+            cookie: Some(0x9c),
+        };
+        let header = Gva::new(0xffff_a50f_3e4b_1a50);
+        assert_eq!(types.type_index(0x2b ^ 0x9c ^ 0x1a, header), 0x2b);
+
+        let types = ObjectTypes {
+            names: HashMap::new(),
+            cookie: None,
+        };
+        assert_eq!(types.type_index(0x2b, header), 0x2b);
+    }
+
+    #[test]
+    fn path() {
+        let mut info = ObjectInfo {
+            type_name: "Device".into(),
+            name: Some("HarddiskVolume1".into()),
+            directory_path: Some("\\Device".into()),
+        };
+        assert_eq!(info.path().unwrap(), "\\Device\\HarddiskVolume1");
+
+        info.directory_path = Some("\\".into());
+        assert_eq!(info.path().unwrap(), "\\HarddiskVolume1");
+
+        info.name = None;
+        assert!(info.path().is_none());
+    }
+}
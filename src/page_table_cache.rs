@@ -0,0 +1,215 @@
+//! This contains the cache of the page tables: the decoded entries of the
+//! paging structures the translations read, indexed by their [`Gpa`] (see
+//! [`PageTableCache`]).
+//!
+//! Unlike the translation cache, which remembers the translation of a page,
+//! it is shared by the walks of unrelated addresses, which go through the same
+//! upper level tables; walking whole address spaces, or translating ranges,
+//! reads every table once. It holds at most
+//! [`ParserOptions::page_table_cache_entries`] tables and evicts the least
+//! recently used ones, and its hits and misses are counted in
+//! [`crate::ParserStats`].
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::{KernelDumpParser, ParserOptions};
+//! let options = ParserOptions {
+//!     page_table_cache_entries: 0x1_000,
+//!     collect_stats: true,
+//!     ..Default::default()
+//! };
+//! let parser = KernelDumpParser::new_with_options(&"full.dmp", options).unwrap();
+//! let _ = parser.memory_map(None);
+//! let stats = parser.stats();
+//! println!(
+//!     "{} hits, {} misses",
+//!     stats.page_table_cache_hits, stats.page_table_cache_misses
+//! );
+//! ```
+use std::collections::{BTreeMap, HashMap};
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use crate::error::Result;
+use crate::structs::Page;
+use crate::{Gpa, KernelDumpParser, Pxe};
+
+/// Upper bound of the heap a cached table takes: its decoded entries, and
+/// its slots in the maps that index it; see [`crate::ResourceEstimate`].
+pub(crate) const TABLE_BYTES: u64 = (Page::size() / 8) * mem::size_of::<Pxe>() as u64 + 0x80;
+
+/// The tables of a [`PageTableCache`], and when they were last used.
+#[derive(Debug, Default)]
+struct Lru {
+    tables: HashMap<Gpa, (Arc<[Pxe]>, u64)>,
+    /// The tables, by when they were last used.
+    recency: BTreeMap<u64, Gpa>,
+    clock: u64,
+}
+
+/// A least recently used cache of the decoded page tables, that can be used by
+/// concurrent reads.
+#[derive(Debug)]
+pub(crate) struct PageTableCache {
+    capacity: usize,
+    lru: Mutex<Lru>,
+}
+
+impl PageTableCache {
+    /// Create a cache that holds at most `capacity` tables; it doesn't hold
+    /// any if it is zero.
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            lru: Default::default(),
+        }
+    }
+
+    /// Is anything cached at all?
+    pub(crate) fn enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Get the entries of the table at `table`, if they are cached.
+    fn get(&self, table: Gpa) -> Option<Arc<[Pxe]>> {
+        let mut lru = self.lru.lock().unwrap();
+        let Lru {
+            tables,
+            recency,
+            clock,
+        } = &mut *lru;
+        let (entries, used) = tables.get_mut(&table)?;
+        recency.remove(used);
+        *clock += 1;
+        *used = *clock;
+        recency.insert(*clock, table);
+
+        Some(entries.clone())
+    }
+
+    /// Cache the entries of the table at `table`, evicting the least recently
+    /// used table if the cache is full.
+    fn insert(&self, table: Gpa, entries: Arc<[Pxe]>) {
+        if !self.enabled() {
+            return;
+        }
+
+        let mut lru = self.lru.lock().unwrap();
+        lru.clock += 1;
+        let used = lru.clock;
+        if let Some((_, previous)) = lru.tables.insert(table, (entries, used)) {
+            lru.recency.remove(&previous);
+        }
+
+        lru.recency.insert(used, table);
+        while lru.tables.len() > self.capacity {
+            let Some((_, evicted)) = lru.recency.pop_first() else {
+                break;
+            };
+
+            lru.tables.remove(&evicted);
+        }
+    }
+
+    /// Forget every table, when the pages they were read from change.
+    pub(crate) fn clear(&self) {
+        *self.lru.lock().unwrap() = Lru::default();
+    }
+
+    /// Number of tables cached.
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.lru.lock().unwrap().tables.len()
+    }
+}
+
+impl KernelDumpParser {
+    /// Read the entries of the paging structure at `table`, a page aligned
+    /// [`Gpa`], through the cache of the page tables. It is `None` if the page
+    /// is cut short by the end of the dump file.
+    pub(crate) fn page_table(&self, table: Gpa) -> Result<Option<Arc<[Pxe]>>> {
+        if let Some(entries) = self.page_tables.get(table) {
+            self.count(|stats| &stats.page_table_cache_hits, 1);
+
+            return Ok(Some(entries));
+        }
+
+        self.count(|stats| &stats.page_table_cache_misses, 1);
+        let mut page = vec![0; Page::size() as usize];
+        if self.read_at(self.phys_offset(table)?, &mut page)? != page.len() {
+            return Ok(None);
+        }
+
+        let entries = page
+            .chunks_exact(8)
+            .map(|entry| Pxe::from(u64::from_le_bytes(entry.try_into().unwrap())))
+            .collect::<Arc<[Pxe]>>();
+        self.page_tables.insert(table, entries.clone());
+
+        Ok(Some(entries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::testing::DumpBuilder;
+    use crate::{Gva, ParserOptions, PxeFlags};
+
+    #[test]
+    fn lru() {
+        let cache = PageTableCache::new(2);
+        let entries = Arc::<[Pxe]>::from(vec![Pxe::default(); 512]);
+        for table in [0x1_000, 0x2_000] {
+            cache.insert(Gpa::new(table), entries.clone());
+        }
+
+        // Using the first table makes the second one the least recently used..
+        assert!(cache.get(Gpa::new(0x1_000)).is_some());
+        cache.insert(Gpa::new(0x3_000), entries.clone());
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(Gpa::new(0x2_000)).is_none());
+        assert!(cache.get(Gpa::new(0x1_000)).is_some());
+
+        // ..and nothing is cached without room.
+        let cache = PageTableCache::new(0);
+        cache.insert(Gpa::new(0x1_000), entries);
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn page_table_cache() {
+        // Two pages in two different page tables share their upper level tables.
+        let gva = 0xffff_f800_0000_0000u64;
+        let dump = DumpBuilder::new()
+            .map_virt(gva, 0x1_000, PxeFlags::Present)
+            .map_virt(gva + 0x20_0000, 0x2_000, PxeFlags::Present)
+            .write_virt(gva, b"first")
+            .write_virt(gva + 0x20_0000, b"second")
+            .build();
+        let options = ParserOptions {
+            collect_stats: true,
+            ..Default::default()
+        };
+        let parser = KernelDumpParser::with_options(Cursor::new(dump), options).unwrap();
+        parser.reset_stats();
+        let mut buffer = [0; 5];
+        parser.virt_read_exact(Gva::new(gva), &mut buffer).unwrap();
+        assert_eq!(&buffer, b"first");
+        let mut buffer = [0; 6];
+        parser
+            .virt_read_exact(Gva::new(gva + 0x20_0000), &mut buffer)
+            .unwrap();
+        assert_eq!(&buffer, b"second");
+
+        // The PML4 was cached when the dump was parsed, the PDPT and the PD on
+        // the first read; only the second page table is new.
+        let stats = parser.stats();
+        assert_eq!(stats.page_table_cache_hits, 4);
+        assert_eq!(stats.page_table_cache_misses, 4);
+        assert_eq!(parser.page_tables.len(), 5);
+    }
+}
@@ -0,0 +1,438 @@
+//! This contains what is needed to audit the paging structures of an address
+//! space: the pages they are made of (see
+//! [`KernelDumpParser::page_table_pages`]) and the entries that look like they
+//! have been tampered with (see [`PteAnomaly`]).
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::KernelDumpParser;
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! for anomaly in parser.pte_anomalies(None).unwrap() {
+//!     println!("{anomaly}");
+//! }
+//! ```
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+
+use crate::cancel::CancellationToken;
+use crate::error::Result;
+use crate::gxa::Gxa;
+use crate::memory_map::Protection;
+use crate::pxe::{Pxe, PxeFlags};
+use crate::structs::Page;
+use crate::{Gpa, Gva, KernelDumpParser};
+
+/// The level of a paging structure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum WalkLevel {
+    Pml4,
+    Pdpt,
+    Pd,
+    Pt,
+}
+
+impl WalkLevel {
+    /// Number of bits of the addresses an entry of the level maps.
+    fn shift(self) -> u32 {
+        match self {
+            Self::Pml4 => 39,
+            Self::Pdpt => 30,
+            Self::Pd => 21,
+            Self::Pt => 12,
+        }
+    }
+
+    /// The level of the tables the entries of the level point to.
+    fn next(self) -> Option<Self> {
+        match self {
+            Self::Pml4 => Some(Self::Pdpt),
+            Self::Pdpt => Some(Self::Pd),
+            Self::Pd => Some(Self::Pt),
+            Self::Pt => None,
+        }
+    }
+}
+
+impl Display for WalkLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pml4 => write!(f, "PML4"),
+            Self::Pdpt => write!(f, "PDPT"),
+            Self::Pd => write!(f, "PD"),
+            Self::Pt => write!(f, "PT"),
+        }
+    }
+}
+
+/// A physical page used as a paging structure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PtPage {
+    /// Where the structure is.
+    pub gpa: Gpa,
+    /// What the structure is.
+    pub level: WalkLevel,
+    /// The first address the structure maps.
+    pub referenced_by: Gva,
+}
+
+/// An entry of the paging structures that is suspicious.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PteAnomaly {
+    /// A kernel page, at `gva`, that is both writable and executable.
+    WritableExecutable { gva: Gva, gpa: Gpa },
+    /// A kernel page, at `gva`, that can be accessed from user-mode.
+    UserAccessibleKernel { gva: Gva, gpa: Gpa },
+    /// An entry of a `level` structure, that maps `gva`, pointing past the
+    /// physical memory of the machine.
+    BeyondPhysicalMemory {
+        gva: Gva,
+        level: WalkLevel,
+        gpa: Gpa,
+    },
+    /// A physical page that is mapped at several kernel addresses with
+    /// different protections.
+    ConflictingAliases {
+        gpa: Gpa,
+        mappings: Vec<(Gva, Protection)>,
+    },
+}
+
+impl Display for PteAnomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WritableExecutable { gva, gpa } => {
+                write!(f, "{gva} ({gpa}) is writable and executable")
+            }
+            Self::UserAccessibleKernel { gva, gpa } => {
+                write!(
+                    f,
+                    "{gva} ({gpa}) is a kernel page accessible from user-mode"
+                )
+            }
+            Self::BeyondPhysicalMemory { gva, level, gpa } => write!(
+                f,
+                "the {level} entry mapping {gva} points past physical memory at {gpa}"
+            ),
+            Self::ConflictingAliases { gpa, mappings } => {
+                write!(f, "{gpa} is mapped with different protections at")?;
+                for (gva, protection) in mappings {
+                    write!(f, " {gva} ({protection})")?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A page, or a large page, mapped in an address space.
+struct Leaf {
+    gva: Gva,
+    gpa: Gpa,
+    size: u64,
+    protection: Protection,
+    user: bool,
+}
+
+/// A walk of the paging structures of the address space whose PML4 is at
+/// `dtb`, and what it found; `max` is the last plausible physical address.
+struct Walk {
+    dtb: Gpa,
+    max: Option<Gpa>,
+    tables: Vec<PtPage>,
+    leaves: Vec<Leaf>,
+    anomalies: Vec<PteAnomaly>,
+}
+
+/// Make `addr` canonical by extending its 47th bit.
+fn canonical(addr: u64) -> Gva {
+    Gva::new(if addr & (1 << 47) != 0 {
+        addr | 0xffff_0000_0000_0000
+    } else {
+        addr
+    })
+}
+
+impl KernelDumpParser {
+    /// List every physical page used as a paging structure by the address
+    /// space whose PML4 is at `dtb`, or the default one if it is `None`. The
+    /// self-referencing entry of the PML4 isn't followed, and the structures
+    /// that aren't in the dump are listed but not walked.
+    pub fn page_table_pages(&self, dtb: Option<Gpa>) -> Result<Vec<PtPage>> {
+        self.page_table_pages_with_cancellation(dtb, &CancellationToken::new())
+    }
+
+    /// List every physical page used as a paging structure by the address
+    /// space whose PML4 is at `dtb`, like
+    /// [`KernelDumpParser::page_table_pages`], until `cancel` is cancelled.
+    pub fn page_table_pages_with_cancellation(
+        &self,
+        dtb: Option<Gpa>,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<PtPage>> {
+        Ok(self.walk_address_space(dtb, cancel)?.tables)
+    }
+
+    /// Find the suspicious entries of the paging structures of the address
+    /// space whose PML4 is at `dtb`, or the default one if it is `None`:
+    /// kernel pages that are writable and executable or accessible from
+    /// user-mode, entries that point past physical memory and physical pages
+    /// mapped in the kernel with different protections.
+    pub fn pte_anomalies(&self, dtb: Option<Gpa>) -> Result<Vec<PteAnomaly>> {
+        self.pte_anomalies_with_cancellation(dtb, &CancellationToken::new())
+    }
+
+    /// Find the suspicious entries of the paging structures of the address
+    /// space whose PML4 is at `dtb`, like [`KernelDumpParser::pte_anomalies`],
+    /// until `cancel` is cancelled.
+    pub fn pte_anomalies_with_cancellation(
+        &self,
+        dtb: Option<Gpa>,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<PteAnomaly>> {
+        let mut walk = self.walk_address_space(dtb, cancel)?;
+        let mut aliases = HashMap::<Gpa, Vec<(Gva, Protection)>>::new();
+        for leaf in walk.leaves.iter().filter(|leaf| leaf.gva.u64() >> 63 == 1) {
+            let (gva, gpa) = (leaf.gva, leaf.gpa);
+            if leaf.protection.writable && leaf.protection.executable {
+                walk.anomalies
+                    .push(PteAnomaly::WritableExecutable { gva, gpa });
+            }
+
+            if leaf.user {
+                walk.anomalies
+                    .push(PteAnomaly::UserAccessibleKernel { gva, gpa });
+            }
+
+            for offset in (0..leaf.size).step_by(Page::size() as usize) {
+                aliases
+                    .entry(Gpa::new(gpa.u64() + offset))
+                    .or_default()
+                    .push((Gva::new(gva.u64() + offset), leaf.protection));
+            }
+        }
+
+        let mut conflicts = aliases
+            .into_iter()
+            .filter(|(_, mappings)| {
+                mappings
+                    .iter()
+                    .any(|(_, protection)| *protection != mappings[0].1)
+            })
+            .collect::<Vec<_>>();
+        conflicts.sort_unstable_by_key(|(gpa, _)| *gpa);
+        walk.anomalies.extend(
+            conflicts
+                .into_iter()
+                .map(|(gpa, mappings)| PteAnomaly::ConflictingAliases { gpa, mappings }),
+        );
+
+        Ok(walk.anomalies)
+    }
+
+    /// Walk the paging structures of the address space whose PML4 is at
+    /// `dtb`, or the default one if it is `None`.
+    fn walk_address_space(&self, dtb: Option<Gpa>, cancel: &CancellationToken) -> Result<Walk> {
+        let dtb = dtb.unwrap_or_else(|| self.default_dtb()).page_align();
+        let protection = Protection {
+            writable: true,
+            executable: true,
+        };
+
+        let mut walk = Walk {
+            dtb,
+            max: self.max_physical_address(),
+            tables: Vec::new(),
+            leaves: Vec::new(),
+            anomalies: Vec::new(),
+        };
+        self.walk_structure(
+            dtb,
+            WalkLevel::Pml4,
+            0,
+            (protection, true),
+            cancel,
+            &mut walk,
+        )?;
+
+        Ok(walk)
+    }
+
+    /// Walk the `level` structure at `table`, that maps the addresses
+    /// starting at `base` with at most `access` (its protection, and whether
+    /// it is accessible from user-mode).
+    fn walk_structure(
+        &self,
+        table: Gpa,
+        level: WalkLevel,
+        base: u64,
+        access: (Protection, bool),
+        cancel: &CancellationToken,
+        walk: &mut Walk,
+    ) -> Result<()> {
+        cancel.check()?;
+        walk.tables.push(PtPage {
+            gpa: table,
+            level,
+            referenced_by: canonical(base),
+        });
+
+        // The structures that aren't in the dump can't be walked.
+        let Some(entries) = self.read_table(table)? else {
+            return Ok(());
+        };
+
+        for (idx, pxe) in entries.iter().enumerate() {
+            let gpa = pxe.pfn.gpa();
+            if !pxe.present() || (level == WalkLevel::Pml4 && gpa == walk.dtb) {
+                continue;
+            }
+
+            let addr = base | ((idx as u64) << level.shift());
+            let gva = canonical(addr);
+            if walk.max.is_some_and(|max| gpa > max) {
+                walk.anomalies
+                    .push(PteAnomaly::BeyondPhysicalMemory { gva, level, gpa });
+                continue;
+            }
+
+            let access = (
+                access.0.restrict(pxe),
+                access.1 && pxe.flags.contains(PxeFlags::UserAccessible),
+            );
+            match level.next() {
+                Some(next) if !is_large(level, pxe) => {
+                    self.walk_structure(gpa, next, addr, access, cancel, walk)?
+                }
+                _ => walk.leaves.push(Leaf {
+                    gva,
+                    gpa,
+                    size: 1 << level.shift(),
+                    protection: access.0,
+                    user: access.1,
+                }),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Does the entry `pxe` of a `level` structure map a large page?
+fn is_large(level: WalkLevel, pxe: &Pxe) -> bool {
+    matches!(level, WalkLevel::Pdpt | WalkLevel::Pd) && pxe.large_page()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::AddrTranslationError;
+    use crate::testing::DumpBuilder;
+    use crate::{KdmpParserError, ParserOptions};
+
+    #[test]
+    fn page_tables() {
+        let nt = 0xffff_f800_0000_0000;
+        let rwx = PxeFlags::Present | PxeFlags::Writable;
+        let user = 0x7ff0_0000_0000;
+        let dump = DumpBuilder::new()
+            .map_virt(nt, 0x10_000, PxeFlags::Present)
+            .map_virt(nt + 0x1_000, 0x11_000, rwx)
+            .map_virt(nt + 0x2_000, 0x10_000, rwx | PxeFlags::NoExecute)
+            .map_virt(nt + 0x3_000, 0x12_000, rwx | PxeFlags::UserAccessible)
+            .map_virt(user, 0x13_000, PxeFlags::Present | PxeFlags::UserAccessible)
+            .map_virt(user + 0x1_000, 0x100_0000_0000, PxeFlags::Present)
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+        let dtb = parser.default_dtb().page_align();
+
+        // A PML4, and a PDPT, a PD and a PT for both halves.
+        let pages = parser.page_table_pages(None).unwrap();
+        assert_eq!(pages.len(), 7);
+        assert_eq!(pages[0], PtPage {
+            gpa: dtb,
+            level: WalkLevel::Pml4,
+            referenced_by: Gva::new(0)
+        });
+        assert_eq!(
+            pages
+                .iter()
+                .filter(|page| page.level == WalkLevel::Pt)
+                .map(|page| page.referenced_by)
+                .collect::<Vec<_>>(),
+            [Gva::new(user), Gva::new(nt)]
+        );
+
+        let anomalies = parser.pte_anomalies(None).unwrap();
+        assert_eq!(anomalies, [
+            PteAnomaly::BeyondPhysicalMemory {
+                gva: Gva::new(user + 0x1_000),
+                level: WalkLevel::Pt,
+                gpa: Gpa::new(0x100_0000_0000)
+            },
+            PteAnomaly::WritableExecutable {
+                gva: Gva::new(nt + 0x1_000),
+                gpa: Gpa::new(0x11_000)
+            },
+            PteAnomaly::WritableExecutable {
+                gva: Gva::new(nt + 0x3_000),
+                gpa: Gpa::new(0x12_000)
+            },
+            PteAnomaly::UserAccessibleKernel {
+                gva: Gva::new(nt + 0x3_000),
+                gpa: Gpa::new(0x12_000)
+            },
+            PteAnomaly::ConflictingAliases {
+                gpa: Gpa::new(0x10_000),
+                mappings: vec![
+                    (Gva::new(nt), Protection {
+                        writable: false,
+                        executable: true
+                    }),
+                    (Gva::new(nt + 0x2_000), Protection {
+                        writable: true,
+                        executable: false
+                    }),
+                ]
+            }
+        ]);
+        assert!(anomalies[0]
+            .to_string()
+            .contains("points past physical memory"));
+    }
+
+    #[test]
+    fn max_physical_address() {
+        let user = 0x7ff0_0000_0000;
+        let dump = DumpBuilder::new()
+            .map_virt(user, 0x10_000, PxeFlags::Present)
+            .map_virt(user + 0x1_000, 0x100_0000_0000, PxeFlags::Present)
+            .build();
+
+        // The bitmap covers the pages up to the last one of the dump.
+        let parser = KernelDumpParser::from_bytes(dump.clone()).unwrap();
+        let max = parser.max_physical_address().unwrap();
+        assert!(max >= Gpa::new(0x10_fff) && max < Gpa::new(0x100_0000_0000));
+        assert!(parser.virt_translate(Gva::new(user)).is_ok());
+        assert!(matches!(
+            parser.virt_translate(Gva::new(user + 0x1_000)),
+            Err(KdmpParserError::AddrTranslation(
+                AddrTranslationError::ImplausiblePhysicalAddress(gva, gpa)
+            )) if gva == Gva::new(user + 0x1_000) && gpa == Gpa::new(0x100_0000_0000)
+        ));
+
+        // The check can be turned off.
+        let options = ParserOptions {
+            max_physical_address: Some(Gpa::new(u64::MAX)),
+            ..Default::default()
+        };
+        let parser = KernelDumpParser::with_options(std::io::Cursor::new(dump), options).unwrap();
+        assert_eq!(parser.max_physical_address(), Some(Gpa::new(u64::MAX)));
+        assert_eq!(
+            parser.virt_translate(Gva::new(user + 0x1_000)).unwrap(),
+            Gpa::new(0x100_0000_0000)
+        );
+        assert!(parser.pte_anomalies(None).unwrap().is_empty());
+    }
+}
@@ -1,141 +1,414 @@
 // Axel '0vercl0k' Souchet - February 25 2024
 //! This has all the parsing logic for parsing kernel crash-dumps.
-use core::slice;
-use std::cell::RefCell;
 use std::cmp::min;
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::fs::File;
+use std::io::Read;
 use std::ops::Range;
 use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
 use std::{io, mem};
 
 use crate::bits::Bits;
-use crate::error::{PxeNotPresent, Result};
+use crate::context_source::{self, ContextSource};
+#[cfg(any(feature = "flate2", feature = "zstd"))]
+use crate::decompress;
+use crate::error::{PxeNotPresent, Result, Warning};
+use crate::exclusion::ExclusionReason;
+use crate::export::Export;
 use crate::gxa::Gxa;
-use crate::map::{MappedFileReader, Reader};
+use crate::index::{self, Index};
+use crate::info::DumpInfo;
+use crate::list::ListWalker;
+use crate::map::{MappedFileReader, Reader, SharedBytes};
+use crate::module::{ModuleEntry, ModuleMap, ModuleOrigin};
+use crate::nt::NT_EXPORT_NAME;
+use crate::nt_globals::NtGlobals;
+use crate::object::ObjectTypes;
+use crate::page_table_cache::PageTableCache;
+#[cfg(feature = "pdb")]
+use crate::pdb_symbols::PdbSymbols;
+use crate::physmem_pages::{self, ResidentBytes};
+use crate::profile::Profile;
+use crate::recovery::{self, RecoveryNote};
+use crate::stats::Counters;
 use crate::structs::{
-    read_struct, BmpHeader64, Context, DumpType, ExceptionRecord64, FullRdmpHeader64, Header64,
-    KdDebuggerData64, KernelRdmpHeader64, LdrDataTableEntry, ListEntry, Page, PfnRange,
-    PhysmemDesc, PhysmemMap, PhysmemRun, UnicodeString, DUMP_HEADER64_EXPECTED_SIGNATURE,
+    read_struct, BmpHeader64, Context, DumpType, ExceptionRecord64, FromLeBytes, FullRdmpHeader64,
+    Header64, KdDebuggerData64, KernelRdmpHeader64, LdrDataTableEntry, Page, PfnRange, PhysmemDesc,
+    PhysmemMap, PhysmemRun, UnicodeString, DUMP_HEADER64_EXPECTED_SIGNATURE,
     DUMP_HEADER64_EXPECTED_VALID_DUMP,
 };
-use crate::{AddrTranslationError, Gpa, Gva, KdmpParserError, Pfn, Pxe};
+use crate::supplement::{DumpPages, PageSource, Supplement};
+use crate::trace::{trace_debug, trace_span, trace_warn};
+use crate::utf16::{self, StringPolicy};
+use crate::{
+    header, incomplete, AddrTranslationError, ContainerKind, DumpAttributes, Gpa, Gva,
+    KdmpParserError, Pxe,
+};
+
+/// Largest page size a dump can be written with, the size of a large page.
+const MAX_PAGE_SIZE: u64 = 0x20_0000;
+
+fn gpa_from_bitmap(bitmap_idx: u64, bit_idx: usize, page_size: u64) -> Option<Gpa> {
+    let page = bitmap_idx
+        .checked_mul(8)?
+        .checked_add(bit_idx.try_into().ok()?)?;
+
+    page.checked_mul(page_size).map(Gpa::new)
+}
+
+fn gpa_from_pfn_range(pfn_range: &PfnRange, page_idx: u64, page_size: u64) -> Option<Gpa> {
+    pfn_range
+        .page_file_number
+        .checked_add(page_idx)?
+        .checked_mul(page_size)
+        .map(Gpa::new)
+}
+
+/// Make sure the dump can be indexed with pages of `page_size` bytes: it has
+/// to be a power of two, and a page of the dump has to hold whole
+/// [`Page::size()`] pages.
+pub(crate) fn check_page_size(page_size: u64) -> Result<()> {
+    if page_size.is_power_of_two() && (Page::size()..=MAX_PAGE_SIZE).contains(&page_size) {
+        Ok(())
+    } else {
+        Err(KdmpParserError::UnsupportedPageSize(page_size))
+    }
+}
+
+/// Parse the dump header `raw_header` and make sure it is one of a dump that
+/// can be parsed.
+pub(crate) fn check_header(raw_header: &[u8]) -> Result<(Box<Header64>, DumpType)> {
+    let headers = Box::new(Header64::from_le_bytes(raw_header));
+    if headers.signature != DUMP_HEADER64_EXPECTED_SIGNATURE {
+        if let Some(kind) = ContainerKind::sniff(raw_header) {
+            return Err(KdmpParserError::CompressedContainer(kind));
+        }
+
+        return Err(KdmpParserError::InvalidSignature(headers.signature));
+    }
+
+    if headers.valid_dump != DUMP_HEADER64_EXPECTED_VALID_DUMP {
+        return Err(KdmpParserError::InvalidValidDump(headers.valid_dump));
+    }
+
+    // The memory of an encrypted dump (and its bitmap) can't be read.
+    if let Some(attributes) =
+        header::attributes(headers.attributes).filter(DumpAttributes::encrypted)
+    {
+        return Err(KdmpParserError::EncryptedDump {
+            dump_type: headers.dump_type,
+            attributes,
+        });
+    }
+
+    // Grab the dump type and make sure it is one we support.
+    let dump_type = DumpType::try_from(headers.dump_type)?;
+
+    Ok((headers, dump_type))
+}
+
+/// Is a dump file of `size` bytes memory mapped when it is opened? We'll
+/// assume that if you are opening a dump file larger than 4gb, you don't want
+/// it memory mapped. Files that don't fit in the address space (on 32-bit
+/// hosts) can't be memory mapped either.
+pub(crate) fn memory_mapped(size: u64) -> bool {
+    const FOUR_GIGS: u64 = 1_024 * 1_024 * 1_024 * 4;
 
-fn gpa_from_bitmap(bitmap_idx: u64, bit_idx: usize) -> Option<Gpa> {
-    let pfn = Pfn::new(
-        bitmap_idx
-            .checked_mul(8)?
-            .checked_add(bit_idx.try_into().ok()?)?,
-    );
+    size <= FOUR_GIGS && isize::try_from(size).is_ok()
+}
+
+/// Index the page of `page_size` bytes of the dump that is at `offset` in the
+/// file, and that holds the physical memory at `gpa`. It is indexed as the
+/// [`Page::size()`] pages it is made of, so that reading physical memory
+/// doesn't depend on the page size of the dump. It returns the first page that
+/// was already indexed, if any.
+fn index_page(
+    physmem: &mut PhysmemMap,
+    gpa: Gpa,
+    offset: u64,
+    page_size: u64,
+) -> Result<Option<Gpa>> {
+    let mut duplicate = None;
+    for delta in (0..page_size).step_by(Page::size() as usize) {
+        let (Some(gpa), Some(offset)) = (gpa.u64().checked_add(delta), offset.checked_add(delta))
+        else {
+            return Err(KdmpParserError::Overflow("dump page"));
+        };
+
+        let gpa = Gpa::new(gpa);
+        if physmem.insert(gpa, offset).is_some() {
+            duplicate = duplicate.or(Some(gpa));
+        }
+    }
 
-    Some(pfn.gpa())
+    Ok(duplicate)
 }
 
-fn gpa_from_pfn_range(pfn_range: &PfnRange, page_idx: u64) -> Option<Gpa> {
-    let offset = page_idx.checked_mul(Page::size())?;
+/// Does a read of `len` bytes starting at `addr` fit in a single page?
+fn fits_in_page(addr: impl Gxa, len: usize) -> bool {
+    addr.offset()
+        .checked_add(len as u64)
+        .is_some_and(|end| end <= Page::size())
+}
 
-    Some(Pfn::new(pfn_range.page_file_number).gpa_with_offset(offset))
+/// The entries of a module list.
+type ModuleList = Vec<ModuleEntry>;
+
+/// The reasons a module list can be found invalid for; see
+/// [`Warning::InvalidModuleList`].
+const MODULE_LIST_TOO_LONG: &str = "it has more entries than ParserOptions::max_modules";
+const MODULE_NAME_TOO_LONG: &str = "the name of an entry is longer than its buffer";
+const MODULE_WRAPS_AROUND: &str = "an entry spans past the end of the address space";
+pub(crate) const INVALID_MODULE_LIST_REASONS: [&str; 3] = [
+    MODULE_LIST_TOO_LONG,
+    MODULE_NAME_TOO_LONG,
+    MODULE_WRAPS_AROUND,
+];
+
+/// The headers a [`Warning::UnknownHeaderExtension`] is recorded for.
+const RDMP_HEADER: &str = "RDMP header";
+const RDMP_METADATA: &str = "RDMP metadata";
+pub(crate) const EXTENDED_HEADERS: [&str; 2] = [RDMP_HEADER, RDMP_METADATA];
+
+/// Read a field of a module list entry that isn't part of
+/// [`LdrDataTableEntry`]. It is `None` if the profile doesn't describe it, or
+/// if it can't be read.
+fn try_read_ldr_field(
+    parser: &KernelDumpParser,
+    entry_addr: Gva,
+    ldr_type: &str,
+    field: &str,
+) -> Result<Option<u64>> {
+    match parser.read_field(entry_addr, ldr_type, field) {
+        Ok(value) => Ok(Some(value)),
+        Err(
+            KdmpParserError::ProfileMissing { .. }
+            | KdmpParserError::AddrTranslation(..)
+            | KdmpParserError::PartialVirtRead,
+        ) => Ok(None),
+        Err(e) => Err(e),
+    }
 }
 
 /// Walk a LIST_ENTRY of LdrDataTableEntry. It is used to dump both the user &
-/// driver / module lists.
-fn try_read_module_map(parser: &mut KernelDumpParser, head: Gva) -> Result<Option<ModuleMap>> {
-    let mut modules = ModuleMap::new();
-    let Some(entry) = parser.try_virt_read_struct::<ListEntry>(head)? else {
-        return Ok(None);
-    };
+/// driver / module lists; `ldr_type` is the name of the type of the entries in
+/// the profile.
+fn try_read_module_list(
+    parser: &mut KernelDumpParser,
+    head: Gva,
+    ldr_type: &str,
+) -> Result<Option<ModuleList>> {
+    let _span = trace_span!("module_list");
+    trace_debug!("walking the {ldr_type} list at {head}");
+    let mut modules = ModuleList::new();
+    let mut warnings = Vec::new();
+    // `InLoadOrderLinks` is the first field of `_LDR_DATA_TABLE_ENTRY`.
+    for entry_addr in parser.walk_list(head, 0, parser.options.max_modules) {
+        // If the list is corrupted or can't be read, we'll consider that there's no
+        // module list.
+        let entry_addr = match entry_addr {
+            Ok(entry_addr) => entry_addr,
+            Err(
+                e @ (KdmpParserError::AddrTranslation(..)
+                | KdmpParserError::ListCycle(..)
+                | KdmpParserError::ListBlinkMismatch(..)),
+            ) => {
+                trace_debug!(
+                    "failed walking the module list after {} entries: {e}",
+                    modules.len()
+                );
+                return Ok(None);
+            }
+            // If the list is too long, we'll keep the modules we read so far.
+            Err(KdmpParserError::ListTooLong(_)) => {
+                warnings.push(Warning::InvalidModuleList {
+                    head,
+                    kept: modules.len(),
+                    reason: MODULE_LIST_TOO_LONG,
+                });
+                break;
+            }
+            Err(e) => return Err(e),
+        };
 
-    let mut entry_addr = entry.flink.into();
-    // We'll walk it until we hit the starting point (it is circular).
-    while entry_addr != head {
         // Read the table entry..
         let Some(data) = parser.try_virt_read_struct::<LdrDataTableEntry>(entry_addr)? else {
+            trace_debug!("failed reading the module list entry at {entry_addr}");
             return Ok(None);
         };
 
+        // ..make sure it is sane, otherwise we'll keep the modules we read so far..
+        let dll_end_addr = data.dll_base.checked_add(data.size_of_image.into());
+        let invalid = if [&data.full_dll_name, &data.base_dll_name]
+            .iter()
+            .any(|name| name.length > name.maximum_length)
+        {
+            Some(MODULE_NAME_TOO_LONG)
+        } else if dll_end_addr.is_none() {
+            Some(MODULE_WRAPS_AROUND)
+        } else {
+            None
+        };
+
+        if let Some(reason) = invalid {
+            warnings.push(Warning::InvalidModuleList {
+                head,
+                kept: modules.len(),
+                reason,
+            });
+            break;
+        }
+
         // ..and read it. We first try to read `full_dll_name` but will try
         // `base_dll_name` is we couldn't read the former.
-        let Some(dll_name) = parser
-            .try_virt_read_unicode_string(&data.full_dll_name)
+        let Some((dll_name, mangled)) = parser
+            .try_virt_read_unicode_string_lossy(&data.full_dll_name)
             .and_then(|s| {
                 if s.is_none() {
                     // If we failed to read the `full_dll_name`, give `base_dll_name` a shot.
-                    parser.try_virt_read_unicode_string(&data.base_dll_name)
+                    parser.try_virt_read_unicode_string_lossy(&data.base_dll_name)
                 } else {
                     Ok(s)
                 }
             })?
         else {
+            trace_debug!("failed reading the name of the module list entry at {entry_addr}");
             return Ok(None);
         };
 
-        // Shove it into the map.
-        let dll_end_addr = data
-            .dll_base
-            .checked_add(data.size_of_image.into())
-            .ok_or_else(|| KdmpParserError::Overflow("module address"))?;
-        let at = data.dll_base.into()..dll_end_addr.into();
-        let inserted = modules.insert(at, dll_name);
-        debug_assert!(inserted.is_none());
+        if mangled {
+            warnings.push(Warning::InvalidModuleName {
+                entry: entry_addr,
+                name: dll_name.clone(),
+            });
+        }
 
-        // Go to the next entry.
-        entry_addr = data.in_load_order_links.flink.into();
+        // Shove it into the list.
+        let dll_end_addr = dll_end_addr.ok_or(KdmpParserError::Overflow("module address"))?;
+        let mut module = ModuleEntry::new(data.dll_base.into()..dll_end_addr.into(), dll_name);
+        module.size_of_image = data.size_of_image;
+        module.entry_point = (data.entry_point != 0).then(|| data.entry_point.into());
+        let field = |name| try_read_ldr_field(parser, entry_addr, ldr_type, name);
+        module.timestamp = field("TimeDateStamp")?.map(|value| value as u32);
+        module.checksum = field("CheckSum")?.map(|value| value as u32);
+        module.load_count = field("LoadCount")?.map(|value| value as u16);
+        trace_debug!(
+            "module {} at {}-{}",
+            module.name,
+            module.at.start,
+            module.at.end
+        );
+        modules.push(module);
+    }
+
+    trace_debug!("read {} modules", modules.len());
+
+    for warning in warnings {
+        parser.warn(warning)?;
     }
 
     Ok(Some(modules))
 }
 
 /// Extract the drivers / modules out of the `PsLoadedModuleList`.
-fn try_extract_kernel_modules(parser: &mut KernelDumpParser) -> Result<Option<ModuleMap>> {
+fn try_extract_kernel_modules(parser: &mut KernelDumpParser) -> Result<Option<ModuleList>> {
     // Walk the LIST_ENTRY!
-    try_read_module_map(parser, parser.headers().ps_loaded_module_list.into())
+    try_read_module_list(
+        parser,
+        parser.headers().ps_loaded_module_list.into(),
+        "_KLDR_DATA_TABLE_ENTRY",
+    )
+}
+
+/// Recover the drivers / modules when the `PsLoadedModuleList` of the headers
+/// can't be read: `nt` is located by scanning memory, and the module list is
+/// found with its exports. If the list can't be read, only `nt` is returned.
+fn try_recover_kernel_modules(parser: &mut KernelDumpParser) -> Result<Option<ModuleList>> {
+    let _span = trace_span!("module_recovery");
+    let nt_base = match parser.find_nt_base() {
+        Ok(nt_base) => nt_base,
+        Err(KdmpParserError::NotFound(_)) => {
+            trace_debug!("nt couldn't be found in memory");
+            return Ok(None);
+        }
+        Err(e) => return Err(e),
+    };
+
+    let size_of_image = parser.pe_headers(nt_base)?.size_of_image;
+    let nt_end = nt_base
+        .u64()
+        .checked_add(size_of_image.into())
+        .ok_or(KdmpParserError::Overflow("nt end"))?;
+    let nt = ModuleEntry::new(nt_base..nt_end.into(), NT_EXPORT_NAME);
+
+    let ps_loaded_module_list = parser
+        .module_exports(&nt)
+        .ok()
+        .and_then(|exports| {
+            exports
+                .into_iter()
+                .find(|export| export.name == "PsLoadedModuleList" && export.forwarder.is_none())
+        })
+        .map(|export| export.address);
+
+    let modules = match ps_loaded_module_list {
+        Some(head) => try_read_module_list(parser, head, "_KLDR_DATA_TABLE_ENTRY")?,
+        None => None,
+    };
+
+    parser.warn(Warning::ModuleListRecovered { nt_base })?;
+    parser.nt_base = Some(nt_base);
+
+    Ok(Some(
+        modules
+            .filter(|modules| !modules.is_empty())
+            .unwrap_or_else(|| vec![nt]),
+    ))
 }
 
 /// Try to find the right `nt!_KPRCB` by walking them and finding one that has
-/// the same `Rsp` than in the dump headers' context.
+/// the same `Rsp` than in the dump headers' context. It returns its index in
+/// `nt!KiProcessorBlock` and its address.
+/// When the context of the headers is zeroed, there is nothing to compare to:
+/// the first processor whose context is valid is assumed to be the one that
+/// crashed, which is only certain when there is one processor.
 fn try_find_prcb(
     parser: &mut KernelDumpParser,
     kd_debugger_data_block: &KdDebuggerData64,
-) -> Result<Option<Gva>> {
+) -> Result<Option<(u32, Gva, Box<Context>)>> {
+    let rsp = parser.context_record().rsp;
     let mut processor_block = kd_debugger_data_block.ki_processor_block;
-    for _ in 0..parser.headers().number_processors {
+    for idx in 0..parser.headers().number_processors {
         // Read the KPRCB pointer.
-        let Some(kprcb_addr) = parser.try_virt_read_struct::<u64>(processor_block.into())? else {
-            return Ok(None);
-        };
-
-        // Calculate the address of where the CONTEXT pointer is at..
-        let kprcb_context_addr = kprcb_addr
-            .checked_add(kd_debugger_data_block.offset_prcb_context.into())
-            .ok_or_else(|| KdmpParserError::Overflow("offset_prcb"))?;
-
-        // ..and read it.
-        let Some(kprcb_context_addr) =
-            parser.try_virt_read_struct::<u64>(kprcb_context_addr.into())?
-        else {
+        let Some(kprcb_addr) = parser.try_virt_read_struct::<u64>(processor_block)? else {
             return Ok(None);
         };
 
         // Read the context..
-        let Some(kprcb_context) =
-            parser.try_virt_read_struct::<Context>(kprcb_context_addr.into())?
-        else {
+        let Some(kprcb_context) = parser.prcb_context(kprcb_addr.into())? else {
             return Ok(None);
         };
 
         // ..and compare it to ours.
         let kprcb_context = Box::new(kprcb_context);
-        if kprcb_context.rsp == parser.context_record().rsp {
+        let found = if rsp == 0 {
+            context_source::looks_valid(&kprcb_context)
+        } else {
+            kprcb_context.rsp == rsp
+        };
+
+        if found {
             // The register match so we'll assume the current KPRCB is the one describing
             // the 'foreground' processor in the crash-dump.
-            return Ok(Some(kprcb_addr.into()));
+            return Ok(Some((idx, kprcb_addr.into(), kprcb_context)));
         }
 
         // Otherwise, let's move on to the next pointer.
         processor_block = processor_block
             .checked_add(mem::size_of::<u64>() as _)
-            .ok_or_else(|| KdmpParserError::Overflow("kprcb ptr"))?;
+            .ok_or(KdmpParserError::Overflow("kprcb ptr"))?;
     }
 
     Ok(None)
@@ -147,13 +420,13 @@ fn try_extract_user_modules(
     parser: &mut KernelDumpParser,
     kd_debugger_data_block: &KdDebuggerData64,
     prcb_addr: Gva,
-) -> Result<Option<ModuleMap>> {
+) -> Result<Option<ModuleList>> {
     // Get the current _KTHREAD..
     let kthread_addr = prcb_addr
         .u64()
         .checked_add(kd_debugger_data_block.offset_prcb_current_thread.into())
         .ok_or(KdmpParserError::Overflow("offset prcb current thread"))?;
-    let Some(kthread_addr) = parser.try_virt_read_struct::<u64>(kthread_addr.into())? else {
+    let Some(kthread_addr) = parser.try_virt_read_struct::<u64>(kthread_addr)? else {
         return Ok(None);
     };
 
@@ -161,7 +434,7 @@ fn try_extract_user_modules(
     let teb_addr = kthread_addr
         .checked_add(kd_debugger_data_block.offset_kthread_teb.into())
         .ok_or(KdmpParserError::Overflow("offset kthread teb"))?;
-    let Some(teb_addr) = parser.try_virt_read_struct::<u64>(teb_addr.into())? else {
+    let Some(teb_addr) = parser.try_virt_read_struct::<u64>(teb_addr)? else {
         return Ok(None);
     };
 
@@ -179,7 +452,7 @@ fn try_extract_user_modules(
     let peb_addr = teb_addr
         .checked_add(peb_offset)
         .ok_or(KdmpParserError::Overflow("peb offset"))?;
-    let Some(peb_addr) = parser.try_virt_read_struct::<u64>(peb_addr.into())? else {
+    let Some(peb_addr) = parser.try_virt_read_struct::<u64>(peb_addr)? else {
         return Ok(None);
     };
 
@@ -192,7 +465,7 @@ fn try_extract_user_modules(
     let peb_ldr_addr = peb_addr
         .checked_add(ldr_offset)
         .ok_or(KdmpParserError::Overflow("ldr offset"))?;
-    let Some(peb_ldr_addr) = parser.try_virt_read_struct::<u64>(peb_ldr_addr.into())? else {
+    let Some(peb_ldr_addr) = parser.try_virt_read_struct::<u64>(peb_ldr_addr)? else {
         return Ok(None);
     };
 
@@ -209,7 +482,11 @@ fn try_extract_user_modules(
         ))?;
 
     // From there, we walk the list!
-    try_read_module_map(parser, module_list_entry_addr.into())
+    try_read_module_list(
+        parser,
+        module_list_entry_addr.into(),
+        "_LDR_DATA_TABLE_ENTRY",
+    )
 }
 
 /// Filter out [`AddrTranslationError`] errors and turn them into `None`. This
@@ -225,9 +502,169 @@ fn filter_addr_translation_err<T>(res: Result<T>) -> Result<Option<T>> {
     }
 }
 
-/// A module map. The key is the range of where the module lives at and the
-/// value is a path to the module or it's name if no path is available.
-pub type ModuleMap = HashMap<Range<Gva>, String>;
+/// Decode a `T` out of a buffer filled by `read_exact`. Small structures (PXEs,
+/// pointers, `LIST_ENTRY`s, etc.) are read into a buffer on the stack to avoid
+/// allocating on hot paths like the page table walk.
+fn decode_struct<T: FromLeBytes>(read_exact: impl FnOnce(&mut [u8]) -> Result<()>) -> Result<T> {
+    const STACK_BUFFER_SIZE: usize = 0x40;
+    if T::SIZE <= STACK_BUFFER_SIZE {
+        let mut buffer = [0; STACK_BUFFER_SIZE];
+        let buffer = &mut buffer[..T::SIZE];
+        read_exact(buffer)?;
+
+        return Ok(T::from_le_bytes(buffer));
+    }
+
+    let mut buffer = vec![0; T::SIZE];
+    read_exact(&mut buffer)?;
+
+    Ok(T::from_le_bytes(&buffer))
+}
+
+/// Maximum number of entries in the translation cache.
+const TLB_CAPACITY: usize = 0x1_0000;
+
+/// The upper level entries (PML4E, PDPTE and PDE) read by the last page table
+/// walk, along with where they were read from. Walking the page that follows
+/// only reads the entries whose index changed, which is usually just the
+/// PTE.
+#[derive(Debug, Default)]
+pub(crate) struct WalkCache {
+    entries: [Option<(Gpa, Pxe)>; 3],
+}
+
+/// Result of warming up a range of virtual memory with
+/// [`KernelDumpParser::prefetch_virt`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PrefetchReport {
+    /// Number of pages in the range.
+    pub pages: u64,
+    /// Number of pages that were translated and are backed by the dump.
+    pub resolved: u64,
+    /// The pages that couldn't be translated or aren't in the dump.
+    pub unresolved: Vec<Gva>,
+}
+
+impl PrefetchReport {
+    /// Are all the pages of the range readable?
+    pub fn all_resolved(&self) -> bool {
+        self.unresolved.is_empty()
+    }
+}
+
+/// What the reads that run into memory that isn't available in the dump do.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ReadMode {
+    /// The read fails.
+    #[default]
+    Strict,
+    /// The missing bytes are zero-filled.
+    ZeroFill,
+    /// The read stops at the first missing byte, and returns what could be
+    /// read until then.
+    Partial,
+}
+
+/// A span of a read planned by [`KernelDumpParser::plan_phys_read`] or
+/// [`KernelDumpParser::plan_virt_read`]: `len` bytes that are either at
+/// `file_offset` in `source`, or not available in the dump at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoSpan {
+    /// Which file the bytes are in: the dump file, or a supplemental source.
+    pub source: PageSource,
+    /// Where the bytes are in `source`; it is meaningless for a gap.
+    pub file_offset: u64,
+    /// Number of bytes of the span.
+    pub len: usize,
+    /// The bytes aren't available in the dump; [`ReadMode`] decides what
+    /// happens to them.
+    pub gap: bool,
+}
+
+/// A read of [`KernelDumpParser::virt_read_vectored`]: `buf` is filled with
+/// the virtual memory starting at `gva`.
+#[derive(Debug)]
+pub struct ReadRequest<'a> {
+    /// Where the read starts at.
+    pub gva: Gva,
+    /// Where the memory is read into.
+    pub buf: &'a mut [u8],
+}
+
+/// Default for [`ParserOptions::max_modules`].
+const DEFAULT_MAX_MODULES: usize = 0x1_0000;
+
+/// Default for [`ParserOptions::max_read_size`].
+const DEFAULT_MAX_READ_SIZE: u64 = 0x1000_0000;
+
+/// Default for [`ParserOptions::max_decompressed_size`].
+const DEFAULT_MAX_DECOMPRESSED_SIZE: u64 = 0x1_0000_0000;
+
+/// Default for [`ParserOptions::page_table_cache_entries`].
+const DEFAULT_PAGE_TABLE_CACHE_ENTRIES: usize = 0x100;
+
+/// Options to control how a dump is parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParserOptions {
+    /// Fail on the [`Warning`]s instead of working around them.
+    pub strict: bool,
+    /// The largest buffer the parser allocates when its size comes from data
+    /// read out of the dump, like the length of a string or the size of a
+    /// module. Reading more fails with [`KdmpParserError::ReadLimitExceeded`].
+    pub max_read_size: u64,
+    /// What to do with the UTF-16 strings of the dump that aren't valid.
+    pub string_policy: StringPolicy,
+    /// Size of the pages the dump is written with: the runs, the bitmap and
+    /// the page ranges of the dump count pages of this size. The headers of
+    /// the dump don't record it, and Windows always writes [`Page::size()`]
+    /// pages, but some capture tools write bigger ones. It has to be a power of
+    /// two between [`Page::size()`] and 2MB, otherwise parsing fails with
+    /// [`KdmpParserError::UnsupportedPageSize`].
+    pub page_size: u64,
+    /// The largest dump [`KernelDumpParser::new`] decompresses out of a gzip
+    /// or a zstd container, when the `flate2` or the `zstd` feature is
+    /// enabled. Decompressing more fails with
+    /// [`KdmpParserError::ReadLimitExceeded`].
+    pub max_decompressed_size: u64,
+    /// Decompress the dumps in containers into a temporary file (in
+    /// [`std::env::temp_dir`], removed when the parser is dropped) instead of
+    /// in memory.
+    pub spill_decompressed: bool,
+    /// The largest number of entries of a module list that are read; the
+    /// entries past it are left out, see [`Warning::InvalidModuleList`].
+    pub max_modules: usize,
+    /// Count what the reads cost, see [`KernelDumpParser::stats`].
+    pub collect_stats: bool,
+    /// The last physical address the page tables can point to; the
+    /// translations that lead past it fail with
+    /// [`AddrTranslationError::ImplausiblePhysicalAddress`]. It is detected
+    /// from the dump when `None` (see
+    /// [`KernelDumpParser::max_physical_address`]); `Some(Gpa::new(u64::MAX))`
+    /// turns the check off, for machines with memory the dump doesn't
+    /// describe.
+    pub max_physical_address: Option<Gpa>,
+    /// The largest number of page tables whose entries are cached, each
+    /// taking a page worth of memory; zero turns the cache off. See
+    /// [`crate::ParserStats::page_table_cache_hits`].
+    pub page_table_cache_entries: usize,
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        Self {
+            strict: false,
+            max_read_size: DEFAULT_MAX_READ_SIZE,
+            string_policy: StringPolicy::default(),
+            page_size: Page::size(),
+            max_decompressed_size: DEFAULT_MAX_DECOMPRESSED_SIZE,
+            spill_decompressed: false,
+            max_modules: DEFAULT_MAX_MODULES,
+            collect_stats: false,
+            max_physical_address: None,
+            page_table_cache_entries: DEFAULT_PAGE_TABLE_CACHE_ENTRIES,
+        }
+    }
+}
 
 /// A kernel dump parser that gives access to the physical memory space stored
 /// in the dump. It also offers virtual to physical memory translation as well
@@ -235,141 +672,610 @@ pub type ModuleMap = HashMap<Range<Gva>, String>;
 pub struct KernelDumpParser {
     /// Which type of dump is it?
     dump_type: DumpType,
-    /// Context header.
-    context: Box<Context>,
+    /// Context header, or the context of the processor that crashed if it
+    /// isn't valid.
+    pub(crate) context: Box<Context>,
+    /// Where `context` comes from.
+    pub(crate) context_source: ContextSource,
     /// The dump headers.
-    headers: Box<Header64>,
+    pub(crate) headers: Box<Header64>,
+    /// The bytes the dump headers were decoded from.
+    pub(crate) raw_header: Box<[u8]>,
+    /// The comment of the dump headers, decoded.
+    pub(crate) comment: Option<String>,
     /// This maps a physical address to a file offset. Seeking there gives the
     /// page content.
-    physmem: PhysmemMap,
+    pub(crate) physmem: PhysmemMap,
+    /// The pages of the dump that aren't in the dump file yet, when it is
+    /// still being written; see [`KernelDumpParser::refresh`].
+    pub(crate) unwritten: PhysmemMap,
+    /// Where the bytes written so far end in the dump file, when it is still
+    /// being written.
+    pub(crate) written: u64,
+    /// Does the header say that the dump is still being written?
+    pub(crate) writing: bool,
     /// The [`Reader`] object that allows us to seek / read the dump file which
     /// could be memory mapped, read from a file, etc.
-    reader: RefCell<Box<dyn Reader>>,
+    pub(crate) reader: Mutex<Box<dyn Reader + Send>>,
+    /// The bytes `reader` reads, when they stay in memory for as long as the
+    /// parser lives; see [`KernelDumpParser::physmem_pages`].
+    pub(crate) resident_bytes: Option<ResidentBytes>,
+    /// The sources of the physical memory overlaid onto the dump, sorted by
+    /// the offsets of their pages.
+    pub(crate) supplements: Vec<Supplement>,
+    /// How many pages of `physmem` are in the supplemental sources.
+    pub(crate) supplemental_pages: usize,
+    /// Cache of the page translations that have been done so far. It maps a
+    /// (directory table base, page aligned [`Gva`]) to a page aligned [`Gpa`].
+    pub(crate) tlb: Mutex<HashMap<(Gpa, Gva), Gpa>>,
+    /// Cache of the entries of the page tables, indexed by their [`Gpa`].
+    pub(crate) page_tables: PageTableCache,
+    /// The directory table base the translations use by default; it is the
+    /// one of the headers, unless it has been overridden.
+    pub(crate) dtb: Gpa,
+    /// What the reads have cost so far, if [`ParserOptions::collect_stats`]
+    /// is set.
+    pub(crate) counters: Option<Box<Counters>>,
+    /// The last physical address the page tables can point to, if it is
+    /// known.
+    pub(crate) max_physical_address: Option<Gpa>,
     /// The driver modules loaded when the crash-dump was taken. Extracted from
     /// the nt!PsLoadedModuleList.
-    kernel_modules: ModuleMap,
+    pub(crate) kernel_modules: ModuleMap,
     /// The user modules / DLLs loaded when the crash-dump was taken. Extract
     /// from the current PEB.Ldr.InLoadOrderModuleList.
-    user_modules: ModuleMap,
+    pub(crate) user_modules: ModuleMap,
+    /// The ranges of memory annotated by the user.
+    pub(crate) annotations: ModuleMap,
+    /// Size of the dump file.
+    pub(crate) file_size: u64,
+    /// Base address of `nt`. Extracted from the KDDEBUGGER_DATA_BLOCK.
+    pub(crate) nt_base: Option<Gva>,
+    /// The KDDEBUGGER_DATA_BLOCK, if it could be read.
+    pub(crate) kd_debugger_data_block: Option<Box<KdDebuggerData64>>,
+    /// The index in `nt!KiProcessorBlock` and the address of the `_KPRCB` of
+    /// the processor that crashed, if it could be found.
+    pub(crate) crashing_prcb: Option<(u32, Gva)>,
+    /// The object types, indexed by their type index. Built the first time
+    /// it is needed.
+    pub(crate) object_types: OnceLock<ObjectTypes>,
+    /// The globals of `nt` that describe the processors. Resolved the first
+    /// time they are needed.
+    pub(crate) nt_globals: OnceLock<NtGlobals>,
+    /// The ranges of physical memory the writer of the dump left out, sorted.
+    /// Found the first time they are needed.
+    pub(crate) excluded_ranges: OnceLock<Vec<(Range<Gpa>, ExclusionReason)>>,
+    /// The export tables of the modules, sorted by address and indexed by the
+    /// base of their module. Filled as they get parsed.
+    pub(crate) exports: Mutex<HashMap<Gva, Arc<[Export]>>>,
+    /// The copies of the modules, indexed by their base. Every module has a
+    /// slot, which is filled the first time the module is needed.
+    #[cfg(feature = "object")]
+    pub(crate) module_images: HashMap<Gva, OnceLock<Vec<u8>>>,
+    /// The symbols of the PDBs that were loaded, indexed by the base of their
+    /// module.
+    #[cfg(feature = "pdb")]
+    pub(crate) pdbs: HashMap<Gva, PdbSymbols>,
+    /// The layouts of the kernel structures.
+    pub(crate) profile: Profile,
+    /// The options the dump was parsed with.
+    pub(crate) options: ParserOptions,
+    /// The problems that were worked around while parsing the dump.
+    pub(crate) warnings: Vec<Warning>,
+    /// What was synthesized because the header block is corrupted.
+    pub(crate) recovery_notes: Vec<RecoveryNote>,
 }
 
 impl Debug for KernelDumpParser {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("KernelDumpParser")
             .field("dump_type", &self.dump_type)
-            .finish()
+            .field("file_size", &self.file_size)
+            .field("pages", &self.physmem.len())
+            .finish_non_exhaustive()
     }
 }
 
 impl KernelDumpParser {
     /// Create an instance from a file path. This memory maps the file and
     /// parses it.
-    pub fn with_reader(mut reader: impl Reader + 'static) -> Result<Self> {
-        // Parse the dump header and check if things look right.
-        let headers = Box::new(read_struct::<Header64>(&mut reader)?);
-        if headers.signature != DUMP_HEADER64_EXPECTED_SIGNATURE {
-            return Err(KdmpParserError::InvalidSignature(headers.signature));
-        }
+    pub fn with_reader(reader: impl Reader + Send + 'static) -> Result<Self> {
+        Self::with_options(reader, ParserOptions::default())
+    }
 
-        if headers.valid_dump != DUMP_HEADER64_EXPECTED_VALID_DUMP {
-            return Err(KdmpParserError::InvalidValidDump(headers.valid_dump));
-        }
+    /// Create an instance from a dump held in memory.
+    pub fn from_bytes(bytes: impl AsRef<[u8]> + Send + Sync + 'static) -> Result<Self> {
+        Self::with_reader(io::Cursor::new(SharedBytes::new(bytes)))
+    }
+
+    /// Create an instance from a [`Reader`], parsing the dump with `options`.
+    pub fn with_options(
+        reader: impl Reader + Send + 'static,
+        options: ParserOptions,
+    ) -> Result<Self> {
+        Self::parse(reader, options, None, false)
+    }
 
-        // Grab the dump type and make sure it is one we support.
-        let dump_type = DumpType::try_from(headers.dump_type)?;
+    /// Parse the dump of `reader` with `options`, or restore what parsing it
+    /// derives from the index at `index_path` if it has been saved for it.
+    /// When the dump is `incomplete`, the pages that aren't in the file yet
+    /// are left out of the physical memory map (see
+    /// [`KernelDumpParser::open_incomplete`]).
+    pub(crate) fn parse(
+        mut reader: impl Reader + Send + 'static,
+        options: ParserOptions,
+        index_path: Option<&Path>,
+        incomplete: bool,
+    ) -> Result<Self> {
+        let _span = trace_span!("parse");
+        // Parse the dump header and check if things look right.
+        let (raw_header, headers, dump_type, recovered_dump_type) = {
+            let _span = trace_span!("header");
+            let mut raw_header = vec![0; Header64::SIZE].into_boxed_slice();
+            reader.read_exact(&mut raw_header)?;
+            // A dump header that is corrupted is repaired unless we're in
+            // strict mode.
+            let (headers, dump_type, recovered_dump_type) = match check_header(&raw_header) {
+                Ok((headers, dump_type)) => (headers, dump_type, None),
+                Err(e) if !options.strict && recovery::recoverable(&e) => {
+                    let (headers, dump_type) =
+                        recovery::recover_header(&raw_header, &mut reader, e)?;
+
+                    (headers, dump_type, Some(dump_type))
+                }
+                Err(e) => return Err(e),
+            };
+            trace_debug!(
+                "parsed header ok: {dump_type:?} dump of {}.{} with {} processors",
+                headers.major_version,
+                headers.minor_version,
+                headers.number_processors
+            );
+
+            (raw_header, headers, dump_type, recovered_dump_type)
+        };
 
         // Let's figure out how to get physical memory out of this dump now.
-        let physmem = Self::build_physmem(dump_type, &headers, &mut reader)?;
+        check_page_size(options.page_size)?;
+        let file_size = reader.seek(io::SeekFrom::End(0))?;
+        reader.seek(io::SeekFrom::Start(Header64::SIZE as u64))?;
+
+        // An index saves building the physical memory map and finding the
+        // modules, as long as it has been saved for this dump.
+        let fingerprint = index::fingerprint(&raw_header, file_size, &options);
+        let (mut index, stale_index) = match index_path.map(|path| Index::load(path, fingerprint)) {
+            Some(Ok(index)) => (Some(index), None),
+            Some(Err(reason)) => (None, Some(Warning::InvalidIndex { reason })),
+            None => (None, None),
+        };
+
+        let (mut physmem, format_warnings) = match &mut index {
+            Some(index) => (mem::take(&mut index.physmem), Vec::new()),
+            None => Self::build_physmem(dump_type, &headers, options.page_size, &mut reader)?,
+        };
+        let writing = incomplete && !incomplete::writer_done(&headers);
+        let written = if incomplete {
+            incomplete::written_end(&mut reader, 0, file_size, writing)?
+        } else {
+            file_size
+        };
+        let unwritten = if incomplete {
+            incomplete::split_unwritten(&mut physmem, written)
+        } else {
+            PhysmemMap::new()
+        };
+        trace_debug!(
+            "indexed {} physical pages, {} not written yet",
+            physmem.len(),
+            unwritten.len()
+        );
 
         // Read the context record.
-        let context = Box::new(read_struct(&mut io::Cursor::new(
-            headers.context_record_buffer.as_slice(),
-        ))?);
+        let (context, context_source) = context_source::header_context(&headers);
 
-        let reader: RefCell<Box<dyn Reader>> = RefCell::new(Box::new(reader));
+        let resident_bytes = physmem_pages::resident_bytes(&reader);
+        let reader: Mutex<Box<dyn Reader + Send>> = Mutex::new(Box::new(reader));
         let mut parser = Self {
             dump_type,
             context,
-            headers,
+            context_source,
             physmem,
+            unwritten,
+            written,
+            writing,
             reader,
+            resident_bytes,
+            supplements: Vec::new(),
+            supplemental_pages: 0,
+            tlb: Default::default(),
+            page_tables: PageTableCache::new(options.page_table_cache_entries),
+            dtb: Gpa::new(headers.directory_table_base),
+            counters: options.collect_stats.then(Default::default),
+            max_physical_address: options.max_physical_address,
             kernel_modules: Default::default(),
             user_modules: Default::default(),
+            annotations: Default::default(),
+            file_size,
+            nt_base: None,
+            kd_debugger_data_block: None,
+            crashing_prcb: None,
+            object_types: OnceLock::new(),
+            nt_globals: OnceLock::new(),
+            excluded_ranges: OnceLock::new(),
+            exports: Default::default(),
+            #[cfg(feature = "object")]
+            module_images: Default::default(),
+            #[cfg(feature = "pdb")]
+            pdbs: Default::default(),
+            profile: Profile::new(headers.minor_version),
+            options,
+            warnings: Vec::new(),
+            recovery_notes: Vec::new(),
+            comment: header::decode_comment(&headers.comment),
+            headers,
+            raw_header,
         };
 
+        // What the corrupted header block is repaired with isn't in the index.
+        let recover = !parser.options.strict
+            && (recovered_dump_type.is_some() || parser.header_block_corrupted());
+        if recover {
+            index = None;
+        }
+
+        if let Some(index) = index {
+            parser.restore_index(index);
+
+            return Ok(parser);
+        }
+
+        if let Some(warning) = stale_index {
+            parser.warn(warning)?;
+        }
+
+        for warning in format_warnings {
+            parser.warn(warning)?;
+        }
+
+        parser.check_page_framing()?;
+        if parser.max_physical_address.is_none() {
+            parser.max_physical_address = parser.detect_max_physical_address()?;
+        }
+
+        if recover {
+            parser.recover_header_block(recovered_dump_type)?;
+        }
+
+        parser.load_modules()?;
+        if recover {
+            let source = parser.context_source;
+            parser.recovery_notes.push(RecoveryNote::Context(source));
+        }
+
+        Ok(parser)
+    }
+
+    /// Find the kernel and user modules, and what is needed to find them: the
+    /// KDDEBUGGER_DATA_BLOCK and the `_KPRCB` of the processor that crashed.
+    fn load_modules(&mut self) -> Result<()> {
         // Extract the kernel modules if we can. If it fails because of a memory
         // translation error we'll keep going, otherwise we'll error out.
-        if let Some(kernel_modules) = try_extract_kernel_modules(&mut parser)? {
-            parser.kernel_modules.extend(kernel_modules);
+        // If the list can't be read, try to recover it unless we're in strict mode.
+        let mut kernel_modules = try_extract_kernel_modules(self)?;
+        if kernel_modules.is_none() && !self.options.strict {
+            kernel_modules = try_recover_kernel_modules(self)?;
+        }
+
+        if let Some(kernel_modules) = kernel_modules {
+            self.kernel_modules = self.build_module_map(kernel_modules)?;
         }
 
         // Now let's try to find out user-modules. For that we need the
         // KDDEBUGGER_DATA_BLOCK structure to know where a bunch of things are.
         // If we can't read the block, we'll have to stop the adventure here as we won't
         // be able to read the things we need to keep going.
-        let Some(kd_debugger_data_block) = parser.try_virt_read_struct::<KdDebuggerData64>(
-            parser.headers().kd_debugger_data_block.into(),
-        )?
+        let Some(kd_debugger_data_block) =
+            self.try_virt_read_struct::<KdDebuggerData64>(self.headers().kd_debugger_data_block)?
         else {
-            return Ok(parser);
+            trace_debug!("failed reading the KDDEBUGGER_DATA64 block, no user modules");
+            return Ok(());
         };
         let kd_debugger_data_block = Box::new(kd_debugger_data_block);
+        self.nt_base = Some(kd_debugger_data_block.kern_base.into());
+        self.profile
+            .apply_kd_debugger_data_block(&kd_debugger_data_block);
+        self.kd_debugger_data_block = Some(kd_debugger_data_block.clone());
 
         // We need to figure out which PRCB is the one that crashed.
-        let Some(prcb_addr) = try_find_prcb(&mut parser, &kd_debugger_data_block)? else {
-            return Ok(parser);
+        let Some((prcb_idx, prcb_addr, prcb_context)) =
+            try_find_prcb(self, &kd_debugger_data_block)?
+        else {
+            trace_debug!("failed finding the KPRCB of the crashing processor, no user modules");
+            return Ok(());
         };
+        self.crashing_prcb = Some((prcb_idx, prcb_addr));
+        self.fall_back_on_prcb_context(prcb_idx, prcb_addr, *prcb_context);
 
         // Finally, we're ready to extract the user modules!
-        let Some(user_modules) =
-            try_extract_user_modules(&mut parser, &kd_debugger_data_block, prcb_addr)?
+        let Some(mut user_modules) =
+            try_extract_user_modules(self, &kd_debugger_data_block, prcb_addr)?
         else {
-            return Ok(parser);
+            trace_debug!("failed finding the user module list");
+            return Ok(());
         };
 
-        parser.user_modules.extend(user_modules);
+        // The 32-bit modules of a WOW64 process are in a list of their own.
+        user_modules.extend(self.crashing_wow64_modules());
 
-        Ok(parser)
+        let process = self.crashing_process_id();
+        for module in &mut user_modules {
+            module.origin = ModuleOrigin::User { process };
+        }
+
+        self.user_modules = self.build_module_map(user_modules)?;
+
+        Ok(())
+    }
+
+    /// The `UniqueProcessId` of the process of the thread that crashed, whose
+    /// user modules are the ones of the dump.
+    fn crashing_process_id(&self) -> Option<u64> {
+        let thread = self.thread_or_crashing(None).ok()?;
+        let process = self.read_field(thread, "_KTHREAD", "Process").ok()?;
+
+        self.read_field(Gva::new(process), "_EPROCESS", "UniqueProcessId")
+            .ok()
+    }
+
+    /// The last physical address the page tables can plausibly point to: the
+    /// end of the last run of the memory descriptor or of the bitmap, or else
+    /// the end of the last page of the dump, unless it has been overridden
+    /// with [`ParserOptions::max_physical_address`]. `None` if it isn't known.
+    pub fn max_physical_address(&self) -> Option<Gpa> {
+        self.max_physical_address
+    }
+
+    /// Find the last physical address of the machine, where its physical
+    /// memory ends; see [`KernelDumpParser::max_physical_address`].
+    fn detect_max_physical_address(&self) -> Result<Option<Gpa>> {
+        let runs_end = self.memory_descriptor().and_then(|descriptor| {
+            descriptor
+                .runs
+                .iter()
+                .filter_map(|run| {
+                    run.base_page
+                        .u64()
+                        .checked_add(run.page_count)?
+                        .checked_mul(self.options.page_size)
+                })
+                .max()
+        });
+
+        let end = match runs_end {
+            Some(end) => Some(end),
+            None => match self.raw().bmp_header()? {
+                Some(header) if self.dump_type() == DumpType::Bmp => {
+                    header.pages.checked_mul(self.options.page_size)
+                }
+                _ => self
+                    .physmem
+                    .keys()
+                    .next_back()
+                    .max(self.unwritten.keys().next_back())
+                    .map(|gpa| gpa.u64() + self.options.page_size),
+            },
+        };
+
+        Ok(end.and_then(|end| end.checked_sub(1)).map(Gpa::new))
+    }
+
+    /// The directory table base the translations use by default: the one of
+    /// the headers, unless it has been overridden with
+    /// [`KernelDumpParser::set_default_dtb`]. It is the address space of the
+    /// crashing context, so the virtual memory methods of the parser are
+    /// shorthand for the ones of
+    /// `parser.process_space(parser.default_dtb())`; see
+    /// [`KernelDumpParser::kernel_space`] and
+    /// [`KernelDumpParser::process_space`] to pick the address space.
+    pub fn default_dtb(&self) -> Gpa {
+        self.dtb
+    }
+
+    /// Override the directory table base the translations use by default,
+    /// when the one of the headers is wrong (see
+    /// [`KernelDumpParser::find_dtb_candidates`]). The modules are found
+    /// again with it, so the [`Warning`]s are started over.
+    pub fn set_default_dtb(&mut self, dtb: Gpa) -> Result<()> {
+        self.dtb = dtb;
+        self.kernel_modules = Default::default();
+        self.user_modules = Default::default();
+        self.nt_base = None;
+        self.kd_debugger_data_block = None;
+        self.crashing_prcb = None;
+        (self.context, self.context_source) = context_source::header_context(&self.headers);
+        self.object_types = OnceLock::new();
+        self.nt_globals = OnceLock::new();
+        self.exports.lock().unwrap().clear();
+        #[cfg(feature = "object")]
+        self.module_images.clear();
+        #[cfg(feature = "pdb")]
+        self.pdbs.clear();
+        self.warnings.clear();
+
+        self.load_modules()
     }
 
     pub fn new<P>(dump_path: &P) -> Result<Self>
     where
         P: AsRef<Path>,
     {
-        // We'll assume that if you are opening a dump file larger than 4gb, you don't
-        // want it memory mapped.
-        let size = dump_path.as_ref().metadata()?.len();
-        const FOUR_GIGS: u64 = 1_024 * 1_024 * 1_024 * 4;
+        Self::new_with_options(dump_path, ParserOptions::default())
+    }
 
-        match size {
-            0..=FOUR_GIGS => {
-                let mapped_file = MappedFileReader::new(dump_path.as_ref())?;
+    /// Create an instance from a file path, parsing the dump with `options`.
+    ///
+    /// A dump in a gzip (or a zstd) container is decompressed first when the
+    /// `flate2` (or the `zstd`) feature is enabled (see
+    /// [`ParserOptions::max_decompressed_size`] and
+    /// [`ParserOptions::spill_decompressed`]); a dump in another container is
+    /// a [`KdmpParserError::CompressedContainer`].
+    pub fn new_with_options<P>(dump_path: &P, options: ParserOptions) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Self::open(dump_path.as_ref(), options, None)
+    }
 
-                Self::with_reader(mapped_file)
-            }
-            _ => {
-                let file = File::open(dump_path)?;
+    /// Open the dump at `dump_path` and parse it with `options`, or restore
+    /// what parsing it derives from the index at `index_path`.
+    pub(crate) fn open(
+        dump_path: &Path,
+        options: ParserOptions,
+        index_path: Option<&Path>,
+    ) -> Result<Self> {
+        // A dump in a container has to be taken out of it first.
+        let mut magic = [0; ContainerKind::MAGIC_SIZE];
+        let container = File::open(dump_path)?
+            .read_exact(&mut magic)
+            .ok()
+            .and_then(|()| ContainerKind::sniff(&magic));
+        if let Some(kind) = container {
+            #[cfg(any(feature = "flate2", feature = "zstd"))]
+            if let Some(decompressor) = decompress::decompressor(kind, File::open(dump_path)?)? {
+                let limit = options.max_decompressed_size;
+                return if options.spill_decompressed {
+                    let dump = decompress::to_spill_file(decompressor, limit)?;
+
+                    Self::parse(dump, options, index_path, false)
+                } else {
+                    let dump = decompress::to_memory(decompressor, limit)?;
 
-                Self::with_reader(file)
+                    Self::parse(io::Cursor::new(dump), options, index_path, false)
+                };
             }
+
+            return Err(KdmpParserError::CompressedContainer(kind));
+        }
+
+        if memory_mapped(dump_path.metadata()?.len()) {
+            let mapped_file = MappedFileReader::new(dump_path)?;
+
+            Self::parse(mapped_file, options, index_path, false)
+        } else {
+            let file = File::open(dump_path)?;
+
+            Self::parse(file, options, index_path, false)
         }
     }
 
     /// Physical memory map that maps page aligned [`Gpa`] to `offset` where the
     /// content of the page can be found. The offset is relevant with the
-    /// associated `reader`.
+    /// associated `reader`. The pages of the supplemental sources aren't in
+    /// the dump file, so they aren't in there; see
+    /// [`KernelDumpParser::supplemental_physmem`].
     pub fn physmem(&self) -> impl ExactSizeIterator<Item = (Gpa, u64)> + '_ {
-        self.physmem.iter().map(|(&k, &v)| (k, v))
+        DumpPages {
+            pages: self.physmem.iter(),
+            left: self.physmem_len() as usize,
+        }
+    }
+
+    /// Number of [`Page::size()`] pages of [`KernelDumpParser::physmem`]. The
+    /// page counts of the dumps are 64-bit: the runs, the bitmap and the page
+    /// ranges can describe more than `u32::MAX` pages, which is more than a
+    /// `usize` holds on 32-bit hosts.
+    pub fn physmem_len(&self) -> u64 {
+        (self.physmem.len() - self.supplemental_pages) as u64
+    }
+
+    /// The pages of [`KernelDumpParser::physmem`] sorted by where they are in
+    /// the dump file, so that reading them one after the other reads the file
+    /// sequentially. Every page is there exactly once, but not in ascending
+    /// [`Gpa`] order.
+    pub fn physmem_file_order(&self) -> impl ExactSizeIterator<Item = (Gpa, u64)> {
+        let mut pages = self.physmem().collect::<Vec<_>>();
+        pages.sort_by_key(|&(_, offset)| offset);
+
+        pages.into_iter()
     }
 
     /// Kernel modules loaded when the dump was taken.
     pub fn kernel_modules(&self) -> impl ExactSizeIterator<Item = (&Range<Gva>, &str)> + '_ {
-        self.kernel_modules.iter().map(|(k, v)| (k, v.as_str()))
+        self.kernel_modules.iter()
     }
 
     /// User modules loaded when the dump was taken.
     pub fn user_modules(&self) -> impl ExactSizeIterator<Item = (&Range<Gva>, &str)> + '_ {
-        self.user_modules.iter().map(|(k, v)| (k, v.as_str()))
+        self.user_modules.iter()
+    }
+
+    /// Entries of the user and kernel modules loaded when the dump was taken,
+    /// the user modules first; [`ModuleEntry::origin`] tells where each has
+    /// been found.
+    pub fn modules(&self) -> impl Iterator<Item = &ModuleEntry> + '_ {
+        self.user_modules
+            .entries()
+            .chain(self.kernel_modules.entries())
+    }
+
+    /// Entries of the kernel modules loaded when the dump was taken.
+    pub fn kernel_module_entries(&self) -> impl ExactSizeIterator<Item = &ModuleEntry> + '_ {
+        self.kernel_modules.entries()
+    }
+
+    /// Entries of the user modules loaded when the dump was taken.
+    pub fn user_module_entries(&self) -> impl ExactSizeIterator<Item = &ModuleEntry> + '_ {
+        self.user_modules.entries()
+    }
+
+    /// Find the user or kernel module that contains `gva`; an annotation
+    /// that contains `gva` wins over the modules.
+    pub fn find_module(&self, gva: Gva) -> Option<(&Range<Gva>, &str)> {
+        self.find_annotation(gva).or_else(|| {
+            self.find_module_entry(gva)
+                .map(|module| (&module.at, module.name.as_str()))
+        })
+    }
+
+    /// Find the entry of the user or kernel module that contains `gva`.
+    pub fn find_module_entry(&self, gva: Gva) -> Option<&ModuleEntry> {
+        self.user_modules
+            .find_entry(gva)
+            .or_else(|| self.kernel_modules.find_entry(gva))
+    }
+
+    /// The problems that were worked around while parsing the dump. This is
+    /// always empty when the dump is parsed in strict mode.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Record a problem that was worked around, or fail if we're in strict
+    /// mode.
+    pub(crate) fn warn(&mut self, warning: Warning) -> Result<()> {
+        trace_warn!("{warning}");
+        if self.options.strict {
+            return Err(KdmpParserError::Strict(warning));
+        }
+
+        self.warnings.push(warning);
+
+        Ok(())
+    }
+
+    /// Build the map of the modules of a module list.
+    pub(crate) fn build_module_map(&mut self, modules: ModuleList) -> Result<ModuleMap> {
+        let (map, warnings) = ModuleMap::build(modules);
+        for warning in warnings {
+            self.warn(warning)?;
+        }
+
+        #[cfg(feature = "object")]
+        for module in map.entries() {
+            self.module_images.entry(module.at.start).or_default();
+        }
+
+        Ok(map)
     }
 
     /// What kind of dump is it?
@@ -392,62 +1298,229 @@ impl KernelDumpParser {
         &self.context
     }
 
+    /// Size of the dump file in bytes.
+    pub fn file_size(&self) -> u64 {
+        self.file_size
+    }
+
+    /// Base address of `nt`, if it could be found.
+    pub fn nt_base(&self) -> Option<Gva> {
+        self.nt_base
+    }
+
+    /// Get the layouts of the kernel structures used to walk them.
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    /// Get the layouts of the kernel structures, to override some of them.
+    pub fn profile_mut(&mut self) -> &mut Profile {
+        &mut self.profile
+    }
+
+    /// Get the KDDEBUGGER_DATA_BLOCK, if it could be read.
+    pub(crate) fn kd_debugger_data_block(&self) -> Result<&KdDebuggerData64> {
+        self.kd_debugger_data_block
+            .as_deref()
+            .ok_or(KdmpParserError::NotFound("the KDDEBUGGER_DATA_BLOCK"))
+    }
+
+    /// Get a summary of the dump; its [`std::fmt::Display`] implementation
+    /// gives something similar to `.dump /i`.
+    pub fn info(&self) -> DumpInfo {
+        DumpInfo::new(self)
+    }
+
+    /// Walk a doubly-linked `LIST_ENTRY` list starting at `head`. Every record
+    /// embeds its `LIST_ENTRY` at `entry_offset`, and the iterator yields the
+    /// address of the records. The walk stops when it gets back to `head`, and
+    /// errors out on cycles, on broken `Blink`s (see
+    /// [`ListWalker::check_blink`]) or after `max_entries` records.
+    pub fn walk_list(&self, head: Gva, entry_offset: u64, max_entries: usize) -> ListWalker<'_> {
+        ListWalker::new(self, head, entry_offset, max_entries)
+    }
+
     /// Translate a [`Gpa`] into a file offset of where the content of the page
-    /// resides in.
-    pub fn phys_translate(&self, gpa: Gpa) -> Result<u64> {
+    /// resides in. The pages of the supplemental sources aren't in the dump
+    /// file, so they fail to translate; see [`KernelDumpParser::phys_locate`].
+    pub fn phys_translate(&self, gpa: impl Into<Gpa>) -> Result<u64> {
+        match self.phys_locate(gpa)? {
+            (PageSource::Dump, offset) => Ok(offset),
+            (PageSource::Supplemental(_), _) => Err(KdmpParserError::InvalidData(
+                "the page is in a supplemental source",
+            )),
+        }
+    }
+
+    /// Find where the content of a [`Gpa`] is: the source of its page, and
+    /// the offset in the source.
+    pub fn phys_locate(&self, gpa: impl Into<Gpa>) -> Result<(PageSource, u64)> {
+        self.split_offset(self.phys_offset(gpa)?)
+    }
+
+    /// Translate a [`Gpa`] into an offset of the physical memory map; the
+    /// readers know which source it is in (see
+    /// [`KernelDumpParser::seek_reader`]).
+    pub(crate) fn phys_offset(&self, gpa: impl Into<Gpa>) -> Result<u64> {
+        let gpa = gpa.into();
         let offset = *self
             .physmem
             .get(&gpa.page_align())
-            .ok_or(AddrTranslationError::Phys(gpa))?;
+            .ok_or_else(|| self.missing_page(gpa))?;
 
         offset
             .checked_add(gpa.offset())
-            .ok_or_else(|| KdmpParserError::Overflow("w/ gpa offset"))
+            .ok_or(KdmpParserError::Overflow("w/ gpa offset"))
     }
 
     /// Read physical memory starting at `gpa` into a `buffer`.
-    pub fn phys_read(&self, gpa: Gpa, buffer: &mut [u8]) -> Result<usize> {
-        // Amount of bytes left to read.
-        let mut amount_left = buffer.len();
-        // Total amount of bytes that we have successfully read.
+    pub fn phys_read(&self, gpa: impl Into<Gpa>, buffer: &mut [u8]) -> Result<usize> {
+        let gpa = gpa.into();
+        self.count(|stats| &stats.phys_reads, 1);
+        // Fast path: if the read fits in a single page, a single translation and a
+        // single read of the dump file is all we need.
+        if fits_in_page(gpa, buffer.len()) {
+            let offset = self.count_missing_page(self.phys_offset(gpa))?;
+
+            return self.read_at(offset, buffer);
+        }
+
+        // Otherwise, figure out where every page is in the dump file, and read them.
+        let plan = self.plan_read(gpa, buffer.len(), ReadMode::Strict, |gpa| {
+            self.phys_offset(gpa)
+        })?;
+
+        self.read_plan(&plan, buffer)
+    }
+
+    /// Plan a read of `len` bytes of physical memory starting at `gpa`: get
+    /// the spans of the dump file the bytes are in, without reading them. The
+    /// spans that are contiguous in the dump file are merged, and the bytes
+    /// that aren't available in the dump are gaps. This allows to perform the
+    /// I/O in batches, see [`KernelDumpParser::read_plan`].
+    pub fn plan_phys_read(&self, gpa: impl Into<Gpa>, len: usize) -> Result<Vec<IoSpan>> {
+        let gpa = gpa.into();
+        self.plan_read(gpa, len, ReadMode::ZeroFill, |gpa| self.phys_offset(gpa))
+    }
+
+    /// Plan a read of `len` bytes of virtual memory starting at `gva`; see
+    /// [`KernelDumpParser::plan_phys_read`].
+    pub fn plan_virt_read(&self, gva: impl Into<Gva>, len: usize) -> Result<Vec<IoSpan>> {
+        let gva = gva.into();
+        self.plan_virt_read_with_dtb(gva, len, self.dtb)
+    }
+
+    /// Plan a read of `len` bytes of virtual memory starting at `gva` using a
+    /// specific directory table base; see
+    /// [`KernelDumpParser::plan_phys_read`].
+    pub fn plan_virt_read_with_dtb(
+        &self,
+        gva: impl Into<Gva>,
+        len: usize,
+        dtb: Gpa,
+    ) -> Result<Vec<IoSpan>> {
+        let gva = gva.into();
+        let mut walk = WalkCache::default();
+        self.plan_read(gva, len, ReadMode::ZeroFill, |gva| {
+            self.phys_offset(self.virt_translate_cached(gva, dtb, &mut walk)?)
+        })
+    }
+
+    /// Perform the I/O of a read planned by
+    /// [`KernelDumpParser::plan_phys_read`] or
+    /// [`KernelDumpParser::plan_virt_read`] into `buffer`; the gaps are
+    /// zero-filled. It returns less than `buffer.len()` bytes only if the end
+    /// of the dump file has been reached.
+    pub fn read_plan(&self, plan: &[IoSpan], buffer: &mut [u8]) -> Result<usize> {
         let mut total_read = 0;
-        // The current gpa we are reading from.
-        let mut addr = gpa;
-        // Let's try to read as much as the user wants.
-        while amount_left > 0 {
-            // Translate the gpa into a file offset..
-            let phy_offset = self.phys_translate(addr)?;
-            // ..and seek the reader there.
-            self.seek(io::SeekFrom::Start(phy_offset))?;
-            // We need to take care of reads that straddle different physical memory pages.
-            // So let's figure out the maximum amount of bytes we can read off this page.
-            // Either, we read it until its end, or we stop if the user wants us to read
-            // less.
-            let left_in_page = (Page::size() - gpa.offset()) as usize;
-            let amount_wanted = min(amount_left, left_in_page);
-            // Figure out where we should read into.
-            let slice = &mut buffer[total_read..total_read + amount_wanted];
-            // Read the physical memory!
-            let amount_read = self.read(slice)?;
-            // Update the total amount of read bytes and how much work we have left.
+        for span in plan {
+            let slice = buffer.get_mut(total_read..total_read + span.len).ok_or(
+                KdmpParserError::InvalidData("the plan overflows the buffer"),
+            )?;
+            let amount_read = if span.gap {
+                slice.fill(0);
+
+                span.len
+            } else {
+                self.read_at(self.join_offset(span.source, span.file_offset)?, slice)?
+            };
+
             total_read += amount_read;
-            amount_left -= amount_read;
             // If we couldn't read as much as we wanted, we're done.
-            if amount_read != amount_wanted {
-                return Ok(total_read);
+            if amount_read != span.len {
+                break;
+            }
+        }
+
+        Ok(total_read)
+    }
+
+    /// Plan a read of `len` bytes starting at `addr`, page by page;
+    /// `translate` gives the offset in the physical memory map of an address.
+    /// In [`ReadMode::Strict`] mode, the addresses that can't be translated
+    /// fail the plan, otherwise they are gaps.
+    fn plan_read<G: Gxa>(
+        &self,
+        addr: G,
+        len: usize,
+        mode: ReadMode,
+        mut translate: impl FnMut(G) -> Result<u64>,
+    ) -> Result<Vec<IoSpan>> {
+        let mut plan: Vec<IoSpan> = Vec::new();
+        let mut planned = 0;
+        let mut addr = addr;
+        while planned < len {
+            // We need to take care of reads that straddle different pages, so let's
+            // figure out the maximum amount of bytes we can read off this page.
+            let left_in_page = (Page::size() - addr.offset()) as usize;
+            let span = match self.count_missing_page(translate(addr)) {
+                Ok(offset) => {
+                    let (source, file_offset) = self.split_offset(offset)?;
+
+                    IoSpan {
+                        source,
+                        file_offset,
+                        len: min(len - planned, left_in_page),
+                        gap: false,
+                    }
+                }
+                Err(KdmpParserError::AddrTranslation(..)) if mode != ReadMode::Strict => IoSpan {
+                    source: PageSource::Dump,
+                    file_offset: 0,
+                    len: min(len - planned, left_in_page),
+                    gap: true,
+                },
+                Err(e) => return Err(e),
+            };
+
+            planned += span.len;
+            // Merge the span with the previous one if they are contiguous.
+            match plan.last_mut() {
+                Some(last)
+                    if last.gap == span.gap
+                        && (span.gap
+                            || (last.source == span.source
+                                && last.file_offset + last.len as u64 == span.file_offset)) =>
+                {
+                    last.len += span.len
+                }
+                _ => plan.push(span),
+            }
+
+            if planned == len {
+                break;
             }
 
-            // We have more work to do, so let's move to the next page.
             addr = addr.next_aligned_page();
         }
 
-        // Yay, we read as much bytes as the user wanted!
-        Ok(total_read)
+        Ok(plan)
     }
 
     /// Read an exact amount of physical memory starting at `gpa` into a
     /// `buffer`.
-    pub fn phys_read_exact(&self, gpa: Gpa, buffer: &mut [u8]) -> Result<()> {
+    pub fn phys_read_exact(&self, gpa: impl Into<Gpa>, buffer: &mut [u8]) -> Result<()> {
+        let gpa = gpa.into();
         // Read physical memory.
         let len = self.phys_read(gpa, buffer)?;
 
@@ -462,30 +1535,136 @@ impl KernelDumpParser {
     }
 
     /// Read a `T` from physical memory.
-    pub fn phys_read_struct<T>(&self, gpa: Gpa) -> Result<T> {
-        let mut t = mem::MaybeUninit::uninit();
-        let size_of_t = mem::size_of_val(&t);
-        let slice_over_t =
-            unsafe { slice::from_raw_parts_mut(t.as_mut_ptr() as *mut u8, size_of_t) };
+    pub fn phys_read_struct<T: FromLeBytes>(&self, gpa: impl Into<Gpa>) -> Result<T> {
+        let gpa = gpa.into();
+        decode_struct(|buffer| self.phys_read_exact(gpa, buffer))
+    }
 
-        self.phys_read_exact(gpa, slice_over_t)?;
+    /// Translate a [`Gva`] into a [`Gpa`] in the address space of the
+    /// crashing context; see [`KernelDumpParser::default_dtb`].
+    pub fn virt_translate(&self, gva: impl Into<Gva>) -> Result<Gpa> {
+        let gva = gva.into();
+        self.virt_translate_with_dtb(gva, self.dtb)
+    }
 
-        Ok(unsafe { t.assume_init() })
+    /// Translate a [`Gva`] into a [`Gpa`] using a specific directory table
+    /// base. Successful translations are cached, so translating an address
+    /// in a page that has already been translated is cheap.
+    pub fn virt_translate_with_dtb(&self, gva: impl Into<Gva>, dtb: Gpa) -> Result<Gpa> {
+        self.virt_translate_cached(gva.into(), dtb, &mut WalkCache::default())
     }
 
-    /// Translate a [`Gva`] into a [`Gpa`].
-    pub fn virt_translate(&self, gva: Gva) -> Result<Gpa> {
+    /// Translate `gva` like [`KernelDumpParser::virt_translate_with_dtb`],
+    /// reusing the entries of the previous walk in `walk`. It is meant to
+    /// translate the pages of a range one after the other.
+    pub(crate) fn virt_translate_cached(
+        &self,
+        gva: Gva,
+        dtb: Gpa,
+        walk: &mut WalkCache,
+    ) -> Result<Gpa> {
         // Aligning in case PCID bits are set (bits 11:0)
-        let pml4_base = Gpa::from(self.headers.directory_table_base).page_align();
+        let dtb = dtb.page_align();
+        let key = (dtb, gva.page_align());
+        if let Some(page) = self.tlb.lock().unwrap().get(&key) {
+            self.count(|stats| &stats.translation_cache_hits, 1);
+
+            return Ok(Gpa::new(page.u64() + gva.offset()));
+        }
+
+        self.count(|stats| &stats.translation_cache_misses, 1);
+        let page = self.walk_page_tables_cached(key.1, dtb, walk)?;
+        let mut tlb = self.tlb.lock().unwrap();
+        // Keep the cache from growing unbounded by starting over once it is full.
+        if tlb.len() >= TLB_CAPACITY {
+            tlb.clear();
+        }
+
+        tlb.insert(key, page);
+
+        Ok(Gpa::new(page.u64() + gva.offset()))
+    }
+
+    /// Translate every page of a range of [`Gva`]s. It yields the page aligned
+    /// [`Gva`] along with its translation.
+    pub fn translate_range(
+        &self,
+        range: Range<Gva>,
+        dtb: Option<Gpa>,
+    ) -> impl Iterator<Item = (Gva, Result<Gpa>)> + '_ {
+        let dtb = dtb.unwrap_or(self.dtb);
+        let end = range.end;
+        let mut gva = range.start.page_align();
+        let mut walk = WalkCache::default();
+
+        std::iter::from_fn(move || {
+            if gva >= end {
+                return None;
+            }
+
+            let page = gva;
+            gva = page.u64().checked_add(Page::size()).map_or(end, Gva::new);
+
+            Some((page, self.virt_translate_cached(page, dtb, &mut walk)))
+        })
+    }
+
+    /// Warm-up a range of virtual memory before reading it in a hot loop: every
+    /// page is translated (which populates the translation cache) and its
+    /// backing page in the dump file is touched (which faults it in when the
+    /// dump is memory mapped). The returned [`PrefetchReport`] tells which
+    /// pages couldn't be resolved.
+    pub fn prefetch_virt(&self, range: Range<Gva>, dtb: Option<Gpa>) -> PrefetchReport {
+        let mut report = PrefetchReport::default();
+        for (gva, gpa) in self.translate_range(range, dtb) {
+            report.pages += 1;
+            let touched = gpa
+                .and_then(|gpa| self.phys_offset(gpa))
+                .and_then(|offset| self.read_at(offset, &mut [0]));
+
+            match touched {
+                Ok(1) => report.resolved += 1,
+                _ => report.unresolved.push(gva),
+            }
+        }
+
+        report
+    }
+
+    /// Walk the page tables hierarchy starting at `dtb` to translate `gva`.
+    pub(crate) fn walk_page_tables(&self, gva: Gva, dtb: Gpa) -> Result<Gpa> {
+        self.walk_page_tables_cached(gva, dtb, &mut WalkCache::default())
+    }
+
+    /// Read the upper level entry at `gpa`, unless the previous walk read it
+    /// already; `level` is 0 for the PML4E, 1 for the PDPTE and 2 for the
+    /// PDE.
+    fn read_upper_pxe(&self, walk: &mut WalkCache, level: usize, gpa: Gpa) -> Result<Pxe> {
+        if let Some((cached_gpa, pxe)) = walk.entries[level] {
+            if cached_gpa == gpa {
+                return Ok(pxe);
+            }
+        }
+
+        let pxe = self.phys_read_pxe(gpa)?;
+        walk.entries[level] = Some((gpa, pxe));
+
+        Ok(pxe)
+    }
+
+    /// Walk the page tables hierarchy starting at `dtb` to translate `gva`,
+    /// reusing the upper level entries of the previous walk in `walk`.
+    fn walk_page_tables_cached(&self, gva: Gva, dtb: Gpa, walk: &mut WalkCache) -> Result<Gpa> {
+        let pml4_base = dtb;
         let pml4e_gpa = Gpa::new(pml4_base.u64() + (gva.pml4e_idx() * 8));
-        let pml4e = Pxe::from(self.phys_read_struct::<u64>(pml4e_gpa)?);
+        let pml4e = self.read_upper_pxe(walk, 0, pml4e_gpa)?;
         if !pml4e.present() {
             return Err(AddrTranslationError::Virt(gva, PxeNotPresent::Pml4e).into());
         }
 
-        let pdpt_base = pml4e.pfn.gpa();
+        let pdpt_base = self.plausible_gpa(gva, pml4e.pfn.gpa())?;
         let pdpte_gpa = Gpa::new(pdpt_base.u64() + (gva.pdpe_idx() * 8));
-        let pdpte = Pxe::from(self.phys_read_struct::<u64>(pdpte_gpa)?);
+        let pdpte = self.read_upper_pxe(walk, 1, pdpte_gpa)?;
         if !pdpte.present() {
             return Err(AddrTranslationError::Virt(gva, PxeNotPresent::Pdpte).into());
         }
@@ -493,13 +1672,13 @@ impl KernelDumpParser {
         // huge pages:
         // 7 (PS) - Page size; must be 1 (otherwise, this entry references a page
         // directory; see Table 4-1
-        let pd_base = pdpte.pfn.gpa();
+        let pd_base = self.plausible_gpa(gva, pdpte.pfn.gpa())?;
         if pdpte.large_page() {
-            return Ok(Gpa::new(pd_base.u64() + (gva.u64() & 0x3fff_ffff)));
+            return self.plausible_gpa(gva, Gpa::new(pd_base.u64() + (gva.u64() & 0x3fff_ffff)));
         }
 
         let pde_gpa = Gpa::new(pd_base.u64() + (gva.pde_idx() * 8));
-        let pde = Pxe::from(self.phys_read_struct::<u64>(pde_gpa)?);
+        let pde = self.read_upper_pxe(walk, 2, pde_gpa)?;
         if !pde.present() {
             return Err(AddrTranslationError::Virt(gva, PxeNotPresent::Pde).into());
         }
@@ -507,13 +1686,13 @@ impl KernelDumpParser {
         // large pages:
         // 7 (PS) - Page size; must be 1 (otherwise, this entry references a page
         // table; see Table 4-18
-        let pt_base = pde.pfn.gpa();
+        let pt_base = self.plausible_gpa(gva, pde.pfn.gpa())?;
         if pde.large_page() {
-            return Ok(Gpa::new(pt_base.u64() + (gva.u64() & 0x1f_ffff)));
+            return self.plausible_gpa(gva, Gpa::new(pt_base.u64() + (gva.u64() & 0x1f_ffff)));
         }
 
         let pte_gpa = Gpa::new(pt_base.u64() + (gva.pte_idx() * 8));
-        let pte = Pxe::from(self.phys_read_struct::<u64>(pte_gpa)?);
+        let pte = self.phys_read_pxe(pte_gpa)?;
         if !pte.present() {
             // We'll allow reading from a transition PTE, so return an error only if it's
             // not one, otherwise we'll carry on.
@@ -522,60 +1701,173 @@ impl KernelDumpParser {
             }
         }
 
-        let page_base = pte.pfn.gpa();
+        let page_base = self.plausible_gpa(gva, pte.pfn.gpa())?;
 
         Ok(Gpa::new(page_base.u64() + gva.offset()))
     }
 
-    /// Read virtual memory starting at `gva` into a `buffer`.
-    pub fn virt_read(&self, gva: Gva, buffer: &mut [u8]) -> Result<usize> {
-        // Amount of bytes left to read.
-        let mut amount_left = buffer.len();
-        // Total amount of bytes that we have successfully read.
-        let mut total_read = 0;
-        // The current gva we are reading from.
-        let mut addr = gva;
-        // Let's try to read as much as the user wants.
-        while amount_left > 0 {
-            // We need to take care of reads that straddle different virtual memory pages.
-            // So let's figure out the maximum amount of bytes we can read off this page.
-            // Either, we read it until its end, or we stop if the user wants us to read
-            // less.
-            let left_in_page = (Page::size() - addr.offset()) as usize;
-            let amount_wanted = min(amount_left, left_in_page);
-            // Figure out where we should read into.
-            let slice = &mut buffer[total_read..total_read + amount_wanted];
-            // Translate the gva into a gpa..
-            let gpa = self.virt_translate(addr)?;
-            // .. and read the physical memory!
-            let amount_read = self.phys_read(gpa, slice)?;
-            // Update the total amount of read bytes and how much work we have left.
-            total_read += amount_read;
-            amount_left -= amount_read;
-            // If we couldn't read as much as we wanted, we're done.
-            if amount_read != amount_wanted {
-                return Ok(total_read);
+    /// Make sure `gpa`, that the translation of `gva` led to, isn't past the
+    /// physical memory of the machine.
+    fn plausible_gpa(&self, gva: Gva, gpa: Gpa) -> Result<Gpa> {
+        match self.max_physical_address {
+            Some(max) if gpa > max => {
+                Err(AddrTranslationError::ImplausiblePhysicalAddress(gva, gpa).into())
             }
+            _ => Ok(gpa),
+        }
+    }
 
-            // We have more work to do, so let's move to the next page.
-            addr = addr.next_aligned_page();
+    /// Read virtual memory starting at `gva` into a `buffer`, in the address
+    /// space of the crashing context; see [`KernelDumpParser::default_dtb`].
+    pub fn virt_read(&self, gva: impl Into<Gva>, buffer: &mut [u8]) -> Result<usize> {
+        let gva = gva.into();
+        self.virt_read_with_dtb(gva, buffer, self.dtb)
+    }
+
+    /// Read virtual memory starting at `gva` into a `buffer` using a specific
+    /// directory table base; this is useful to read memory in the context of
+    /// another process.
+    pub fn virt_read_with_dtb(
+        &self,
+        gva: impl Into<Gva>,
+        buffer: &mut [u8],
+        dtb: Gpa,
+    ) -> Result<usize> {
+        let gva = gva.into();
+        self.count(|stats| &stats.virt_reads, 1);
+        // Fast path: if the read fits in a single page, translate it once and
+        // read the physical memory directly.
+        if fits_in_page(gva, buffer.len()) {
+            let offset = self.count_missing_page(
+                self.virt_translate_with_dtb(gva, dtb)
+                    .and_then(|gpa| self.phys_offset(gpa)),
+            )?;
+
+            return self.read_at(offset, buffer);
         }
 
-        // Yay, we read as much bytes as the user wanted!
-        Ok(total_read)
+        // Otherwise, translate every page down to the dump file, and read them.
+        let mut walk = WalkCache::default();
+        let plan = self.plan_read(gva, buffer.len(), ReadMode::Strict, |gva| {
+            self.phys_offset(self.virt_translate_cached(gva, dtb, &mut walk)?)
+        })?;
+
+        self.read_plan(&plan, buffer)
+    }
+
+    /// Perform a batch of virtual memory reads. Every request is translated
+    /// first, then the spans of the dump file they need are read ordered by
+    /// their offset, so that scattered reads turn into a mostly sequential
+    /// pass over the dump file. The result of every request is what
+    /// [`KernelDumpParser::virt_read`] would have returned for it.
+    pub fn virt_read_vectored(&self, requests: &mut [ReadRequest<'_>]) -> Vec<Result<usize>> {
+        // Translate every request first..
+        self.count(|stats| &stats.virt_reads, requests.len() as u64);
+        let dtb = self.dtb;
+        let plans = requests
+            .iter()
+            .map(|request| {
+                let mut walk = WalkCache::default();
+                self.plan_read(request.gva, request.buf.len(), ReadMode::Strict, |gva| {
+                    self.phys_offset(self.virt_translate_cached(gva, dtb, &mut walk)?)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // ..then order their spans by offset in the dump file..
+        let mut spans = Vec::new();
+        for (request_idx, plan) in plans.iter().enumerate() {
+            let Ok(plan) = plan else {
+                continue;
+            };
+
+            let mut position = 0;
+            for (span_idx, span) in plan.iter().enumerate() {
+                spans.push((
+                    span.source,
+                    span.file_offset,
+                    request_idx,
+                    span_idx,
+                    position,
+                    span.len,
+                ));
+                position += span.len;
+            }
+        }
+
+        spans.sort_unstable();
+
+        // ..and read them.
+        let mut amounts_read = plans
+            .iter()
+            .map(|plan| vec![0; plan.as_ref().map_or(0, Vec::len)])
+            .collect::<Vec<_>>();
+        let mut io_errors = requests.iter().map(|_| None).collect::<Vec<_>>();
+        for (source, file_offset, request_idx, span_idx, position, len) in spans {
+            if io_errors[request_idx].is_some() {
+                continue;
+            }
+
+            let buffer = &mut requests[request_idx].buf[position..position + len];
+            match self
+                .join_offset(source, file_offset)
+                .and_then(|offset| self.read_at(offset, buffer))
+            {
+                Ok(amount_read) => amounts_read[request_idx][span_idx] = amount_read,
+                Err(e) => io_errors[request_idx] = Some(e),
+            }
+        }
+
+        // A request stops at its first span that has been cut short, like
+        // `read_plan` does.
+        plans
+            .into_iter()
+            .zip(amounts_read)
+            .zip(io_errors)
+            .map(|((plan, amounts_read), io_error)| {
+                let plan = plan?;
+                if let Some(e) = io_error {
+                    return Err(e);
+                }
+
+                let mut total_read = 0;
+                for (span, amount_read) in plan.iter().zip(amounts_read) {
+                    total_read += amount_read;
+                    if amount_read != span.len {
+                        break;
+                    }
+                }
+
+                Ok(total_read)
+            })
+            .collect()
     }
 
     /// Try to read virtual memory starting at `gva` into a `buffer`.  If a
     /// memory translation error occurs, it'll return `None` instead of an
     /// error.
-    pub fn try_virt_read(&self, gva: Gva, buffer: &mut [u8]) -> Result<Option<usize>> {
+    pub fn try_virt_read(&self, gva: impl Into<Gva>, buffer: &mut [u8]) -> Result<Option<usize>> {
+        let gva = gva.into();
         filter_addr_translation_err(self.virt_read(gva, buffer))
     }
 
     /// Read an exact amount of virtual memory starting at `gva`.
-    pub fn virt_read_exact(&self, gva: Gva, buffer: &mut [u8]) -> Result<()> {
+    pub fn virt_read_exact(&self, gva: impl Into<Gva>, buffer: &mut [u8]) -> Result<()> {
+        let gva = gva.into();
+        self.virt_read_exact_with_dtb(gva, buffer, self.dtb)
+    }
+
+    /// Read an exact amount of virtual memory starting at `gva` using a
+    /// specific directory table base.
+    pub fn virt_read_exact_with_dtb(
+        &self,
+        gva: impl Into<Gva>,
+        buffer: &mut [u8],
+        dtb: Gpa,
+    ) -> Result<()> {
+        let gva = gva.into();
         // Read virtual memory.
-        let len = self.virt_read(gva, buffer)?;
+        let len = self.virt_read_with_dtb(gva, buffer, dtb)?;
 
         // If we read as many bytes as we wanted, then it's a win..
         if len == buffer.len() {
@@ -590,55 +1882,301 @@ impl KernelDumpParser {
     /// Try to read an exact amount of virtual memory starting at `gva`. If a
     /// memory translation error occurs, it'll return `None` instead of an
     /// error.
-    pub fn try_virt_read_exact(&self, gva: Gva, buffer: &mut [u8]) -> Result<Option<()>> {
+    pub fn try_virt_read_exact(
+        &self,
+        gva: impl Into<Gva>,
+        buffer: &mut [u8],
+    ) -> Result<Option<()>> {
+        let gva = gva.into();
         filter_addr_translation_err(self.virt_read_exact(gva, buffer))
     }
 
     /// Read a `T` from virtual memory.
-    pub fn virt_read_struct<T>(&self, gva: Gva) -> Result<T> {
-        let mut t = mem::MaybeUninit::uninit();
-        let size_of_t = mem::size_of_val(&t);
-        let slice_over_t =
-            unsafe { slice::from_raw_parts_mut(t.as_mut_ptr() as *mut u8, size_of_t) };
-
-        self.virt_read_exact(gva, slice_over_t)?;
+    pub fn virt_read_struct<T: FromLeBytes>(&self, gva: impl Into<Gva>) -> Result<T> {
+        let gva = gva.into();
+        decode_struct(|buffer| self.virt_read_exact(gva, buffer))
+    }
 
-        Ok(unsafe { t.assume_init() })
+    /// Read a `T` from virtual memory using a specific directory table base.
+    pub fn virt_read_struct_with_dtb<T: FromLeBytes>(
+        &self,
+        gva: impl Into<Gva>,
+        dtb: Gpa,
+    ) -> Result<T> {
+        let gva = gva.into();
+        decode_struct(|buffer| self.virt_read_exact_with_dtb(gva, buffer, dtb))
     }
 
     /// Try to read a `T` from virtual memory. If a memory translation error
     /// occurs, it'll return `None` instead of an error.
-    pub fn try_virt_read_struct<T>(&self, gva: Gva) -> Result<Option<T>> {
+    pub fn try_virt_read_struct<T: FromLeBytes>(&self, gva: impl Into<Gva>) -> Result<Option<T>> {
+        let gva = gva.into();
         filter_addr_translation_err(self.virt_read_struct::<T>(gva))
     }
 
-    fn seek(&self, pos: io::SeekFrom) -> Result<u64> {
-        Ok(self.reader.borrow_mut().seek(pos)?)
+    /// Read a pointer from virtual memory.
+    pub fn virt_read_ptr(&self, gva: impl Into<Gva>) -> Result<Gva> {
+        let gva = gva.into();
+        self.virt_read_struct::<u64>(gva).map(Gva::new)
+    }
+
+    /// Follow a chain of pointers: starting from `base`, add the offset of the
+    /// current step and read a pointer there; the last pointer read is
+    /// returned. In other words, `[[[base + a] + b] + c]` is
+    /// `virt_deref_chain(base, &[a, b, c])`.
+    ///
+    /// A null pointer met before the last step gives a
+    /// [`KdmpParserError::NullPointer`], and any other failure is wrapped in a
+    /// [`KdmpParserError::DerefChain`] that contains the step that failed as
+    /// well as the pointers read so far.
+    pub fn virt_deref_chain(&self, base: impl Into<Gva>, offsets: &[u64]) -> Result<Gva> {
+        let base = base.into();
+        let mut chain = Vec::with_capacity(offsets.len());
+        let mut ptr = base;
+        for (step, &offset) in offsets.iter().enumerate() {
+            if ptr.u64() == 0 {
+                return Err(KdmpParserError::NullPointer { step });
+            }
+
+            ptr = ptr
+                .u64()
+                .checked_add(offset)
+                .ok_or(KdmpParserError::Overflow("deref chain offset"))
+                .and_then(|addr| self.virt_read_ptr(addr))
+                .map_err(|e| KdmpParserError::DerefChain {
+                    step,
+                    chain: chain.clone(),
+                    source: Box::new(e),
+                })?;
+
+            chain.push(ptr);
+        }
+
+        Ok(ptr)
+    }
+
+    /// Read a [`Pxe`] off physical memory. A PXE is always 8 bytes aligned, so
+    /// it never straddles two pages.
+    pub(crate) fn phys_read_pxe(&self, gpa: Gpa) -> Result<Pxe> {
+        if self.page_tables.enabled() && gpa.u64() % 8 == 0 {
+            // Translating the entry first keeps the error of a missing page about the
+            // entry.
+            self.phys_offset(gpa)?;
+            if let Some(entries) = self.page_table(gpa.page_align())? {
+                return Ok(entries[(gpa.offset() / 8) as usize]);
+            }
+        }
+
+        let mut buffer = [0; 8];
+        if self.read_at(self.phys_offset(gpa)?, &mut buffer)? != buffer.len() {
+            return Err(KdmpParserError::PartialPhysRead);
+        }
+
+        Ok(Pxe::from(u64::from_le_bytes(buffer)))
+    }
+
+    /// Read as many bytes as possible into `buf` from the dump file at
+    /// `offset`. It only returns less than `buf.len()` bytes if the end of the
+    /// file has been reached.
+    pub(crate) fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        self.count_io(|| {
+            let mut reader = self.seek_reader(offset)?;
+            let mut total_read = 0;
+            while total_read < buf.len() {
+                match reader.read(&mut buf[total_read..]) {
+                    Ok(0) => break,
+                    Ok(amount_read) => total_read += amount_read,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+
+            Ok(total_read)
+        })
+    }
+
+    /// Count a translation that failed because the memory isn't available in
+    /// the dump, if the stats are collected.
+    fn count_missing_page<T>(&self, translation: Result<T>) -> Result<T> {
+        if let Err(KdmpParserError::AddrTranslation(..)) = translation {
+            self.count(|stats| &stats.missing_page_hits, 1);
+        }
+
+        translation
+    }
+
+    /// Check that a buffer of `size` bytes, sized from data read out of the
+    /// dump, is within [`ParserOptions::max_read_size`].
+    pub(crate) fn check_read_size(&self, size: u64) -> Result<usize> {
+        let limit = self.options.max_read_size;
+        if size > limit {
+            return Err(KdmpParserError::ReadLimitExceeded {
+                requested: size,
+                limit,
+            });
+        }
+
+        usize::try_from(size).map_err(|_| KdmpParserError::OffsetTooLarge(size))
+    }
+
+    /// Read `len` bytes of virtual memory starting at `gva` into a new buffer.
+    /// `len` can't exceed [`ParserOptions::max_read_size`], and the read fails
+    /// if any of the bytes isn't available in the dump.
+    pub fn virt_read_to_vec(&self, gva: impl Into<Gva>, len: u64) -> Result<Vec<u8>> {
+        let gva = gva.into();
+        self.virt_read_to_vec_with_mode(gva, len, ReadMode::Strict)
     }
 
-    fn read(&self, buf: &mut [u8]) -> Result<usize> {
-        Ok(self.reader.borrow_mut().read(buf)?)
+    /// Read `len` bytes of virtual memory starting at `gva` into a new buffer;
+    /// `mode` decides what happens with the bytes that aren't available in
+    /// the dump.
+    pub fn virt_read_to_vec_with_mode(
+        &self,
+        gva: impl Into<Gva>,
+        len: u64,
+        mode: ReadMode,
+    ) -> Result<Vec<u8>> {
+        let gva = gva.into();
+        self.virt_read_to_vec_with_dtb(gva, len, mode, self.dtb)
+    }
+
+    /// Read `len` bytes of virtual memory starting at `gva` into a new buffer
+    /// using a specific directory table base.
+    pub(crate) fn virt_read_to_vec_with_dtb(
+        &self,
+        gva: Gva,
+        len: u64,
+        mode: ReadMode,
+        dtb: Gpa,
+    ) -> Result<Vec<u8>> {
+        self.count(|stats| &stats.virt_reads, 1);
+        let mut walk = WalkCache::default();
+        self.read_to_vec(gva, len, mode, KdmpParserError::PartialVirtRead, |gva| {
+            self.phys_offset(self.virt_translate_cached(gva, dtb, &mut walk)?)
+        })
+    }
+
+    /// Read `len` bytes of physical memory starting at `gpa` into a new
+    /// buffer. `len` can't exceed [`ParserOptions::max_read_size`], and the
+    /// read fails if any of the bytes isn't available in the dump.
+    pub fn phys_read_to_vec(&self, gpa: impl Into<Gpa>, len: u64) -> Result<Vec<u8>> {
+        let gpa = gpa.into();
+        self.phys_read_to_vec_with_mode(gpa, len, ReadMode::Strict)
+    }
+
+    /// Read `len` bytes of physical memory starting at `gpa` into a new
+    /// buffer; `mode` decides what happens with the bytes that aren't
+    /// available in the dump.
+    pub fn phys_read_to_vec_with_mode(
+        &self,
+        gpa: impl Into<Gpa>,
+        len: u64,
+        mode: ReadMode,
+    ) -> Result<Vec<u8>> {
+        let gpa = gpa.into();
+        self.count(|stats| &stats.phys_reads, 1);
+        self.read_to_vec(gpa, len, mode, KdmpParserError::PartialPhysRead, |gpa| {
+            self.phys_offset(gpa)
+        })
+    }
+
+    /// Read `len` bytes starting at `addr` into a new buffer, span by span;
+    /// `translate` gives the offset in the physical memory map of an address.
+    /// The bytes are read straight into the spare capacity of the buffer,
+    /// so it is only touched once.
+    fn read_to_vec<G: Gxa>(
+        &self,
+        addr: G,
+        len: u64,
+        mode: ReadMode,
+        partial_read: KdmpParserError,
+        translate: impl FnMut(G) -> Result<u64>,
+    ) -> Result<Vec<u8>> {
+        let len = self.check_read_size(len)?;
+        let plan = self.plan_read(addr, len, mode, translate)?;
+        let mut buffer = Vec::with_capacity(len);
+        for span in plan {
+            let amount_read = if span.gap {
+                0
+            } else {
+                self.append_at(
+                    self.join_offset(span.source, span.file_offset)?,
+                    span.len,
+                    &mut buffer,
+                )?
+            };
+
+            // If the span is missing or if it is cut short, we either fail, fill the
+            // rest of it with zeros or stop.
+            if amount_read != span.len {
+                match mode {
+                    ReadMode::Strict => return Err(partial_read),
+                    ReadMode::ZeroFill => buffer.resize(buffer.len() + span.len - amount_read, 0),
+                    ReadMode::Partial => break,
+                }
+            }
+        }
+
+        Ok(buffer)
+    }
+
+    /// Read up to `len` bytes at `offset` in the dump file, appending them to
+    /// `buffer`.
+    fn append_at(&self, offset: u64, len: usize, buffer: &mut Vec<u8>) -> Result<usize> {
+        self.count_io(|| {
+            let mut reader = self.seek_reader(offset)?;
+
+            Ok((&mut **reader).take(len as u64).read_to_end(buffer)?)
+        })
     }
 
     /// Try to read a `UNICODE_STRING`.
-    fn try_virt_read_unicode_string(&self, unicode_str: &UnicodeString) -> Result<Option<String>> {
-        if (unicode_str.length % 2) != 0 {
+    pub(crate) fn try_virt_read_unicode_string(
+        &self,
+        unicode_str: &UnicodeString,
+    ) -> Result<Option<String>> {
+        Ok(self
+            .try_virt_read_unicode_string_lossy(unicode_str)?
+            .map(|(s, _)| s))
+    }
+
+    /// Try to read a `UNICODE_STRING`, following
+    /// [`ParserOptions::string_policy`]. It returns the string and whether it
+    /// had to be mangled because it isn't valid, or because its buffer is only
+    /// partially available.
+    pub(crate) fn try_virt_read_unicode_string_lossy(
+        &self,
+        unicode_str: &UnicodeString,
+    ) -> Result<Option<(String, bool)>> {
+        let policy = self.options.string_policy;
+        let odd = (unicode_str.length % 2) != 0;
+        if odd && policy == StringPolicy::Strict {
             return Err(KdmpParserError::InvalidUnicodeString);
         }
 
-        let mut buffer = vec![0; unicode_str.length.into()];
-        match self.virt_read_exact(unicode_str.buffer.into(), &mut buffer) {
-            Ok(_) => {}
+        let (mode, len) = match policy {
+            StringPolicy::Strict => (ReadMode::Strict, unicode_str.length),
+            StringPolicy::Lossy => (ReadMode::Partial, unicode_str.length & !1),
+        };
+
+        let buffer = match self.virt_read_to_vec_with_mode(unicode_str.buffer, len.into(), mode) {
+            // If nothing can be read, we don't consider this a failure.
+            Ok(buffer) if buffer.is_empty() && len != 0 => return Ok(None),
+            Ok(buffer) => buffer,
             // If we encountered a memory translation error, we don't consider this a failure.
-            Err(KdmpParserError::AddrTranslation(_)) => return Ok(None),
+            Err(KdmpParserError::AddrTranslation(_) | KdmpParserError::PartialVirtRead) => {
+                return Ok(None)
+            }
             Err(e) => return Err(e),
         };
 
-        let n = unicode_str.length / 2;
+        let truncated = buffer.len() != usize::from(len);
+        let (mut s, mangled) = utf16::decode(&utf16::units(&buffer), policy)?;
+        if truncated {
+            s.push(char::REPLACEMENT_CHARACTER);
+        }
 
-        Ok(Some(String::from_utf16(unsafe {
-            slice::from_raw_parts(buffer.as_ptr().cast(), n.into())
-        })?))
+        Ok(Some((s, mangled || truncated || odd)))
     }
 
     /// Build the physical memory map for a [`DumpType::Full`] dump.
@@ -658,29 +2196,39 @@ impl KernelDumpParser {
     /// and stored one after another. If the first page of the first run is
     /// at file offset 0x2_000, then the first page of the second run is at
     /// file offset 0x2_000+(2*0x1_000).
-    fn full_physmem(headers: &Header64, reader: &mut impl Reader) -> Result<PhysmemMap> {
+    fn full_physmem(
+        headers: &Header64,
+        page_size: u64,
+        reader: &mut impl Reader,
+    ) -> Result<PhysmemMap> {
         let mut page_offset = reader.stream_position()?;
         let mut run_cursor = io::Cursor::new(headers.physical_memory_block_buffer);
-        let physmem_desc = read_struct::<PhysmemDesc>(&mut run_cursor)?;
+        let physmem_desc = PhysmemDesc::read(&mut run_cursor)?;
         let mut physmem = PhysmemMap::new();
 
+        trace_debug!("{} runs", physmem_desc.number_of_runs);
         for run_idx in 0..physmem_desc.number_of_runs {
             let run = read_struct::<PhysmemRun>(&mut run_cursor)?;
+            trace_debug!(
+                "run {run_idx}: {:#x} pages from pfn {:#x} at offset {page_offset:#x}",
+                run.page_count,
+                run.base_page
+            );
             for page_idx in 0..run.page_count {
                 // Calculate the physical address.
                 let phys_addr = run
-                    .phys_addr(page_idx)
-                    .ok_or_else(|| KdmpParserError::PhysAddrOverflow(run_idx, page_idx))?;
+                    .phys_addr(page_idx, page_size)
+                    .ok_or(KdmpParserError::PhysAddrOverflow(run_idx, page_idx))?;
 
                 // We now know where this page lives at, insert it into the physmem map.
-                if physmem.insert(phys_addr, page_offset).is_some() {
-                    return Err(KdmpParserError::DuplicateGpa(phys_addr));
+                if let Some(gpa) = index_page(&mut physmem, phys_addr, page_offset, page_size)? {
+                    return Err(KdmpParserError::DuplicateGpa(gpa));
                 }
 
                 // Move the page offset along.
                 page_offset = page_offset
-                    .checked_add(Page::size())
-                    .ok_or_else(|| KdmpParserError::PageOffsetOverflow(run_idx, page_idx))?;
+                    .checked_add(page_size)
+                    .ok_or(KdmpParserError::PageOffsetOverflow(run_idx, page_idx))?;
             }
         }
 
@@ -688,7 +2236,8 @@ impl KernelDumpParser {
     }
 
     /// Build the physical memory map for a [`DumpType::Bmp`] dump.
-    fn bmp_physmem(reader: &mut impl Reader) -> Result<PhysmemMap> {
+    fn bmp_physmem(page_size: u64, reader: &mut impl Reader) -> Result<PhysmemMap> {
+        let _span = trace_span!("bitmap");
         let bmp_header = read_struct::<BmpHeader64>(reader)?;
         if !bmp_header.looks_good() {
             return Err(KdmpParserError::InvalidData(
@@ -700,6 +2249,10 @@ impl KernelDumpParser {
         let bitmap_size = bmp_header.pages / 8;
         let mut page_offset = bmp_header.first_page;
         let mut physmem = PhysmemMap::new();
+        trace_debug!(
+            "bitmap of {:#x} pages, first page at offset {page_offset:#x}",
+            bmp_header.pages
+        );
 
         // Walk the bitmap byte per byte..
         for bitmap_idx in 0..bitmap_size {
@@ -714,14 +2267,14 @@ impl KernelDumpParser {
                 }
 
                 // Calculate where the page is.
-                let pa = gpa_from_bitmap(bitmap_idx, bit_idx)
-                    .ok_or_else(|| KdmpParserError::Overflow("pfn in bitmap"))?;
-
-                let insert = physmem.insert(pa, page_offset);
-                debug_assert!(insert.is_none());
-                page_offset = page_offset.checked_add(Page::size()).ok_or_else(|| {
-                    KdmpParserError::BitmapPageOffsetOverflow(bitmap_idx, bit_idx)
-                })?;
+                let pa = gpa_from_bitmap(bitmap_idx, bit_idx, page_size)
+                    .ok_or(KdmpParserError::Overflow("pfn in bitmap"))?;
+
+                let duplicate = index_page(&mut physmem, pa, page_offset, page_size)?;
+                debug_assert!(duplicate.is_none());
+                page_offset = page_offset.checked_add(page_size).ok_or(
+                    KdmpParserError::BitmapPageOffsetOverflow(bitmap_idx, bit_idx),
+                )?;
             }
         }
 
@@ -730,10 +2283,16 @@ impl KernelDumpParser {
 
     /// Build the physical memory map for [`DumpType::KernelMemory`] /
     /// [`DumpType::KernelAndUserMemory`] and [`DumpType::CompleteMemory`] dump.
-    fn kernel_physmem(dump_type: DumpType, reader: &mut impl Reader) -> Result<PhysmemMap> {
+    /// The trailing bytes of the header and of the metadata the parser doesn't
+    /// know about are ignored, and returned as warnings.
+    fn kernel_physmem(
+        dump_type: DumpType,
+        page_size: u64,
+        reader: &mut impl Reader,
+    ) -> Result<(PhysmemMap, Vec<Warning>)> {
         use DumpType as D;
         let mut page_count = 0u64;
-        let (mut page_offset, metadata_size, total_number_of_pages) = match dump_type {
+        let (hdr, total_number_of_pages) = match dump_type {
             D::KernelMemory | D::KernelAndUserMemory => {
                 let kernel_hdr = read_struct::<KernelRdmpHeader64>(reader)?;
                 if !kernel_hdr.hdr.looks_good() {
@@ -742,11 +2301,7 @@ impl KernelDumpParser {
                     ));
                 }
 
-                (
-                    kernel_hdr.hdr.first_page_offset,
-                    kernel_hdr.hdr.metadata_size,
-                    0,
-                )
+                (kernel_hdr.hdr, 0)
             }
             D::CompleteMemory => {
                 let full_hdr = read_struct::<FullRdmpHeader64>(reader)?;
@@ -756,27 +2311,32 @@ impl KernelDumpParser {
                     ));
                 }
 
-                (
-                    full_hdr.hdr.first_page_offset,
-                    full_hdr.hdr.metadata_size,
-                    full_hdr.total_number_of_pages,
-                )
+                (full_hdr.hdr, full_hdr.total_number_of_pages)
             }
             _ => unreachable!(),
         };
 
+        let (mut page_offset, metadata_size) = (hdr.first_page_offset, hdr.metadata_size);
         if page_offset == 0 || metadata_size == 0 {
             return Err(KdmpParserError::InvalidData(
                 "no first page or metadata size",
             ));
         }
 
+        let extensions = [
+            (RDMP_HEADER, hdr.extension().unwrap_or_default()),
+            (RDMP_METADATA, metadata_size % PfnRange::SIZE as u64),
+        ];
+        let warnings = extensions
+            .into_iter()
+            .filter(|&(_, extra_bytes)| extra_bytes != 0)
+            .map(|(header, extra_bytes)| Warning::UnknownHeaderExtension {
+                header,
+                extra_bytes,
+            })
+            .collect();
+
         let pfn_range_size = mem::size_of::<PfnRange>();
-        if (metadata_size % pfn_range_size as u64) != 0 {
-            return Err(KdmpParserError::InvalidData(
-                "metadata size is not a multiple of 8",
-            ));
-        }
 
         let number_pfns = metadata_size / pfn_range_size as u64;
         let mut physmem = PhysmemMap::new();
@@ -801,35 +2361,45 @@ impl KernelDumpParser {
                 break;
             }
 
+            trace_debug!(
+                "run: {:#x} pages from pfn {:#x} at offset {page_offset:#x}",
+                pfn_range.number_of_pages,
+                pfn_range.page_file_number
+            );
+
             for page_idx in 0..pfn_range.number_of_pages {
-                let gpa = gpa_from_pfn_range(&pfn_range, page_idx)
-                    .ok_or_else(|| KdmpParserError::Overflow("w/ pfn_range"))?;
-                let insert = physmem.insert(gpa, page_offset);
-                debug_assert!(insert.is_none());
+                let gpa = gpa_from_pfn_range(&pfn_range, page_idx, page_size)
+                    .ok_or(KdmpParserError::Overflow("w/ pfn_range"))?;
+                let duplicate = index_page(&mut physmem, gpa, page_offset, page_size)?;
+                debug_assert!(duplicate.is_none());
                 page_offset = page_offset
-                    .checked_add(Page::size())
-                    .ok_or_else(|| KdmpParserError::Overflow("w/ page_offset"))?;
+                    .checked_add(page_size)
+                    .ok_or(KdmpParserError::Overflow("w/ page_offset"))?;
             }
 
             page_count = page_count
                 .checked_add(pfn_range.number_of_pages)
-                .ok_or_else(|| KdmpParserError::Overflow("w/ page_count"))?;
+                .ok_or(KdmpParserError::Overflow("w/ page_count"))?;
         }
 
-        Ok(physmem)
+        Ok((physmem, warnings))
     }
 
+    /// Build the physical memory map of a dump, along with the warnings about
+    /// its format.
     fn build_physmem(
         dump_type: DumpType,
         headers: &Header64,
+        page_size: u64,
         reader: &mut impl Reader,
-    ) -> Result<PhysmemMap> {
+    ) -> Result<(PhysmemMap, Vec<Warning>)> {
         use DumpType as D;
+        let _span = trace_span!("physmem");
         match dump_type {
-            D::Full => Self::full_physmem(headers, reader),
-            D::Bmp => Self::bmp_physmem(reader),
+            D::Full => Ok((Self::full_physmem(headers, page_size, reader)?, Vec::new())),
+            D::Bmp => Ok((Self::bmp_physmem(page_size, reader)?, Vec::new())),
             D::KernelMemory | D::KernelAndUserMemory | D::CompleteMemory => {
-                Self::kernel_physmem(dump_type, reader)
+                Self::kernel_physmem(dump_type, page_size, reader)
             }
         }
     }
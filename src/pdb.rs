@@ -0,0 +1,208 @@
+//! This contains what is needed to identify the PDB of the modules mapped in
+//! memory (see [`PdbId`]): the `RSDS` CodeView record their debug directory
+//! points to has the name, the GUID and the age of the PDB, which is what a
+//! symbol server indexes it with.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::KernelDumpParser;
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! for module in parser.kernel_module_entries() {
+//!     if let Ok(Some(pdb)) = parser.module_pdb_id(module) {
+//!         println!("{}: {}/{}", module.name, pdb.name, pdb.symbol_server_key());
+//!     }
+//! }
+//! ```
+use std::fmt::{self, Display};
+
+use crate::error::Result;
+use crate::module::ModuleEntry;
+use crate::pe::{u32_at, IMAGE_DIRECTORY_ENTRY_DEBUG};
+use crate::{KdmpParserError, KernelDumpParser};
+
+/// ```text
+/// kd> dt nt!_IMAGE_DEBUG_DIRECTORY Type SizeOfData AddressOfRawData
+///    +0x00c Type             : Uint4B
+///    +0x010 SizeOfData       : Uint4B
+///    +0x014 AddressOfRawData : Uint4B
+/// ```
+const DEBUG_DIRECTORY_SIZE: usize = 0x1c;
+const DEBUG_DIRECTORY_TYPE: usize = 0xc;
+const DEBUG_DIRECTORY_SIZE_OF_DATA: usize = 0x10;
+const DEBUG_DIRECTORY_ADDRESS_OF_RAW_DATA: usize = 0x14;
+
+/// `IMAGE_DEBUG_TYPE_CODEVIEW`.
+const IMAGE_DEBUG_TYPE_CODEVIEW: u32 = 2;
+
+/// The signature of the CodeView records of the PDB 7.0 files, followed by
+/// the GUID, the age and the path of the PDB.
+const CV_SIGNATURE_RSDS: &[u8; 4] = b"RSDS";
+
+/// Maximum number of entries we'll read off a debug directory.
+const MAX_DEBUG_ENTRIES: usize = 0x20;
+
+/// Maximum size of a CodeView record; the path of the PDB is at most
+/// `MAX_PATH` long.
+const MAX_CODEVIEW_SIZE: u32 = 0x400;
+
+/// The identity of the PDB of a module; see
+/// [`KernelDumpParser::module_pdb_id`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PdbId {
+    /// The path of the PDB, as it was when the module was linked.
+    pub name: String,
+    /// The GUID of the PDB, as it is laid out in memory.
+    pub guid: [u8; 16],
+    /// The age of the PDB.
+    pub age: u32,
+}
+
+impl PdbId {
+    /// Parse a `RSDS` CodeView record.
+    fn parse(record: &[u8]) -> Result<Self> {
+        if record.get(..4) != Some(CV_SIGNATURE_RSDS) {
+            return Err(KdmpParserError::InvalidData("invalid codeview signature"));
+        }
+
+        let guid = record
+            .get(4..20)
+            .ok_or(KdmpParserError::InvalidData("codeview record is too small"))?;
+        let age = u32_at(record, 20)?;
+        let name = &record[24.min(record.len())..];
+        let name = name.split(|&b| b == 0).next().unwrap_or_default();
+
+        Ok(Self {
+            name: String::from_utf8_lossy(name).into_owned(),
+            guid: guid.try_into().unwrap(),
+            age,
+        })
+    }
+
+    /// The file name of the PDB, without its path.
+    pub fn file_name(&self) -> &str {
+        self.name.rsplit(['\\', '/']).next().unwrap_or(&self.name)
+    }
+
+    /// The key a symbol server stores the PDB under: the GUID in upper case
+    /// hexadecimal followed by the age, like
+    /// `3844DBB920174967BE7AA4A2C20430FA2`.
+    pub fn symbol_server_key(&self) -> String {
+        let [a0, a1, a2, a3, b0, b1, c0, c1, rest @ ..] = self.guid;
+        let mut key = format!(
+            "{:08X}{:04X}{:04X}",
+            u32::from_le_bytes([a0, a1, a2, a3]),
+            u16::from_le_bytes([b0, b1]),
+            u16::from_le_bytes([c0, c1])
+        );
+        for byte in rest {
+            key.push_str(&format!("{byte:02X}"));
+        }
+
+        key.push_str(&format!("{:X}", self.age));
+
+        key
+    }
+}
+
+/// Format a [`PdbId`] like a path on a symbol server, like
+/// `ntkrnlmp.pdb/3844DBB920174967BE7AA4A2C20430FA2/ntkrnlmp.pdb`.
+impl Display for PdbId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let file_name = self.file_name();
+
+        write!(f, "{file_name}/{}/{file_name}", self.symbol_server_key())
+    }
+}
+
+impl KernelDumpParser {
+    /// Identify the PDB of a module, out of the `RSDS` CodeView record of its
+    /// debug directory. `None` if the module doesn't have one.
+    pub fn module_pdb_id(&self, module: &ModuleEntry) -> Result<Option<PdbId>> {
+        let base = module.at.start;
+        let Some(directory) = self
+            .pe_headers(base)?
+            .data_directory(IMAGE_DIRECTORY_ENTRY_DEBUG)
+        else {
+            return Ok(None);
+        };
+
+        let entries = (directory.size as usize / DEBUG_DIRECTORY_SIZE).min(MAX_DEBUG_ENTRIES);
+        let directory =
+            self.pe_read(base, directory.rva, (entries * DEBUG_DIRECTORY_SIZE) as u32)?;
+        for entry in directory.chunks_exact(DEBUG_DIRECTORY_SIZE) {
+            if u32_at(entry, DEBUG_DIRECTORY_TYPE)? != IMAGE_DEBUG_TYPE_CODEVIEW {
+                continue;
+            }
+
+            let size = u32_at(entry, DEBUG_DIRECTORY_SIZE_OF_DATA)?.min(MAX_CODEVIEW_SIZE);
+            let rva = u32_at(entry, DEBUG_DIRECTORY_ADDRESS_OF_RAW_DATA)?;
+            let record = self.pe_read(base, rva, size)?;
+
+            return PdbId::parse(&record).map(Some);
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gxa::Gva;
+    use crate::testing::DumpBuilder;
+    use crate::PxeFlags;
+
+    const BASE: u64 = 0xffff_f800_0000_0000;
+
+    #[test]
+    fn pdb_id() {
+        // The debug directory has a POGO entry, that is skipped, and a
+        // CodeView one that points to the record that follows it.
+        let mut headers = vec![0; 0x1_000];
+        headers[..2].copy_from_slice(b"MZ");
+        headers[0x3c..0x40].copy_from_slice(&0x80u32.to_le_bytes());
+        headers[0x80..0x84].copy_from_slice(b"PE\0\0");
+        headers[0x98..0x9a].copy_from_slice(&0x20bu16.to_le_bytes());
+        headers[0x104..0x108].copy_from_slice(&16u32.to_le_bytes());
+        let debug = 0x108 + (IMAGE_DIRECTORY_ENTRY_DEBUG * 8);
+        headers[debug..debug + 4].copy_from_slice(&0x400u32.to_le_bytes());
+        headers[debug + 4..debug + 8].copy_from_slice(&0x38u32.to_le_bytes());
+        headers[0x40c..0x410].copy_from_slice(&13u32.to_le_bytes());
+        headers[0x428..0x42c].copy_from_slice(&IMAGE_DEBUG_TYPE_CODEVIEW.to_le_bytes());
+        headers[0x42c..0x430].copy_from_slice(&0x30u32.to_le_bytes());
+        headers[0x430..0x434].copy_from_slice(&0x500u32.to_le_bytes());
+        headers[0x500..0x504].copy_from_slice(b"RSDS");
+        headers[0x504..0x514].copy_from_slice(&[
+            0xb9, 0xdb, 0x44, 0x38, 0x17, 0x20, 0x67, 0x49, 0xbe, 0x7a, 0xa4, 0xa2, 0xc2, 0x04,
+            0x30, 0xfa,
+        ]);
+        headers[0x514..0x518].copy_from_slice(&2u32.to_le_bytes());
+        headers[0x518..0x52a].copy_from_slice(b"d:\\os\\ntkrnlmp.pdb");
+
+        let dump = DumpBuilder::new()
+            .map_virt(BASE, 0x10_000, PxeFlags::Present)
+            .write_virt(BASE, &headers)
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+        let module = ModuleEntry::new(Gva::new(BASE)..Gva::new(BASE + 0x1_000), "nt");
+        let pdb = parser.module_pdb_id(&module).unwrap().unwrap();
+        assert_eq!(pdb.name, "d:\\os\\ntkrnlmp.pdb");
+        assert_eq!(pdb.age, 2);
+        assert_eq!(pdb.file_name(), "ntkrnlmp.pdb");
+        assert_eq!(pdb.symbol_server_key(), "3844DBB920174967BE7AA4A2C20430FA2");
+        assert_eq!(
+            pdb.to_string(),
+            "ntkrnlmp.pdb/3844DBB920174967BE7AA4A2C20430FA2/ntkrnlmp.pdb"
+        );
+
+        // A module without a debug directory doesn't have a PDB.
+        headers[debug..debug + 8].fill(0);
+        let dump = DumpBuilder::new()
+            .map_virt(BASE, 0x10_000, PxeFlags::Present)
+            .write_virt(BASE, &headers)
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+        assert_eq!(parser.module_pdb_id(&module).unwrap(), None);
+    }
+}
@@ -0,0 +1,460 @@
+//! This contains what is needed to symbolize the modules with their PDBs (see
+//! [`KernelDumpParser::load_pdb`]): the public symbols and the procedures of a
+//! PDB are indexed, so [`KernelDumpParser::symbolize`] names the functions
+//! that aren't exported, and [`KernelDumpParser::resolve_symbol`] finds the
+//! globals that aren't either.
+//!
+//! The PDBs are read with the `pdb` crate: only the GUID, the age, the section
+//! headers, the public and global symbols, and the procedures of the
+//! compilands are used.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use std::path::Path;
+//! # use kdmp_parser::{Gva, KernelDumpParser};
+//! let mut parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! parser.load_pdb("nt", Path::new("ntkrnlmp.pdb")).unwrap();
+//! let block = parser.resolve_symbol("nt!KiProcessorBlock").unwrap();
+//! println!("{:?}", parser.symbolize(Gva::new(0xfffff805_10877012)));
+//! ```
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use ::pdb::{FallibleIterator, PdbInternalSectionOffset, RawString, Source, SymbolData, PDB};
+
+use crate::error::Result;
+use crate::export::module_short_name;
+use crate::gxa::Gxa;
+use crate::module::ModuleEntry;
+use crate::pdb::PdbId;
+use crate::{Gva, KdmpParserError, KernelDumpParser};
+
+/// The symbols of a PDB, by RVA and by name.
+#[derive(Debug, Default)]
+pub(crate) struct PdbSymbols {
+    /// The symbols, sorted by RVA; when several are at the same RVA, the
+    /// procedures win over the public symbols, which can be decorated.
+    by_rva: Vec<(u32, String)>,
+    by_name: HashMap<String, u32>,
+}
+
+impl PdbSymbols {
+    /// Index `symbols`; the first of the ones at the same RVA wins.
+    fn new(mut symbols: Vec<(u32, String)>) -> Self {
+        let mut by_name = HashMap::new();
+        for (rva, name) in &symbols {
+            by_name.entry(name.clone()).or_insert(*rva);
+        }
+
+        symbols.sort_by_key(|&(rva, _)| rva);
+        symbols.dedup_by_key(|&mut (rva, _)| rva);
+
+        Self {
+            by_rva: symbols,
+            by_name,
+        }
+    }
+
+    /// Get the symbol that precedes `rva`, and how far `rva` is from it.
+    pub(crate) fn nearest(&self, rva: u32) -> Option<(&str, u32)> {
+        let idx = self.by_rva.partition_point(|&(start, _)| start <= rva);
+        let (start, name) = self.by_rva[..idx].last()?;
+
+        Some((name, rva - start))
+    }
+
+    /// Get the RVA of the symbol `name`.
+    pub(crate) fn rva(&self, name: &str) -> Option<u32> {
+        self.by_name.get(name).copied()
+    }
+}
+
+/// The GUID and the age of a PDB, and its symbols.
+struct Pdb {
+    guid: [u8; 16],
+    age: u32,
+    symbols: PdbSymbols,
+}
+
+impl Pdb {
+    fn parse<'s, S: Source<'s> + 's>(source: S) -> Result<Self> {
+        let mut pdb = PDB::open(source)?;
+        let info = pdb.pdb_information()?;
+
+        // The age of the DBI stream is the one of the CodeView records; the
+        // old PDBs only have the one of the info stream.
+        let dbi = pdb.debug_information()?;
+        let age = dbi.age().unwrap_or(info.age);
+        let address_map = pdb.address_map()?;
+        let mut symbols = Vec::new();
+        let mut add = |offset: PdbInternalSectionOffset, name: RawString<'_>| {
+            if let Some(rva) = offset.to_rva(&address_map) {
+                symbols.push((rva.0, name.to_string().into_owned()));
+            }
+        };
+
+        // The procedures are in the symbols of the modules..
+        let mut modules = dbi.modules()?;
+        while let Some(module) = modules.next()? {
+            let Some(module_info) = pdb.module_info(&module)? else {
+                continue;
+            };
+
+            let mut module_symbols = module_info.symbols()?;
+            while let Some(symbol) = module_symbols.next()? {
+                if let Ok(SymbolData::Procedure(procedure)) = symbol.parse() {
+                    add(procedure.offset, procedure.name);
+                }
+            }
+        }
+
+        // ..and the public and global symbols in the symbol records.
+        let global_symbols = pdb.global_symbols()?;
+        let mut global_symbols = global_symbols.iter();
+        while let Some(symbol) = global_symbols.next()? {
+            match symbol.parse() {
+                Ok(SymbolData::Public(public)) => add(public.offset, public.name),
+                Ok(SymbolData::Data(data)) => add(data.offset, data.name),
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            // The GUID is stored like in the CodeView records.
+            guid: info.guid.to_bytes_le(),
+            age,
+            symbols: PdbSymbols::new(symbols),
+        })
+    }
+}
+
+impl KernelDumpParser {
+    /// Load the PDB at `path` for the module named `module_name`, like `nt`
+    /// or `ntoskrnl.exe` (see [`KernelDumpParser::symbolize`]); its symbols
+    /// are used by [`KernelDumpParser::symbolize`] and
+    /// [`KernelDumpParser::resolve_symbol`] from then on. The PDB has to be
+    /// the one of the module: it is a [`KdmpParserError::PdbMismatch`] when
+    /// its GUID or its age aren't the ones of the CodeView record of the
+    /// module, and a [`KdmpParserError::Unavailable`] when the record can't be
+    /// read; see [`KernelDumpParser::force_load_pdb`].
+    pub fn load_pdb(&mut self, module_name: &str, path: &Path) -> Result<()> {
+        self.add_pdb(module_name, path, false)
+    }
+
+    /// Load the PDB at `path` for the module named `module_name` like
+    /// [`KernelDumpParser::load_pdb`], even if it isn't the one of the module:
+    /// the symbols are off if the module was built differently.
+    pub fn force_load_pdb(&mut self, module_name: &str, path: &Path) -> Result<()> {
+        self.add_pdb(module_name, path, true)
+    }
+
+    fn add_pdb(&mut self, module_name: &str, path: &Path, force: bool) -> Result<()> {
+        let module = self
+            .module_named(module_name)
+            .ok_or(KdmpParserError::NotFound("the module of the pdb"))?
+            .clone();
+        let pdb = Pdb::parse(fs::File::open(path)?)?;
+        if !force {
+            let expected =
+                self.module_pdb_id(&module)
+                    .ok()
+                    .flatten()
+                    .ok_or(KdmpParserError::Unavailable(
+                        "the codeview record of the module",
+                    ))?;
+            if (expected.guid, expected.age) != (pdb.guid, pdb.age) {
+                let found = PdbId {
+                    name: String::new(),
+                    guid: pdb.guid,
+                    age: pdb.age,
+                };
+
+                return Err(KdmpParserError::PdbMismatch {
+                    module: module.name,
+                    expected: expected.symbol_server_key(),
+                    found: found.symbol_server_key(),
+                });
+            }
+        }
+
+        self.pdbs.insert(module.at.start, pdb.symbols);
+        // The globals of `nt` can be in the PDB.
+        self.nt_globals = OnceLock::new();
+
+        Ok(())
+    }
+
+    /// Symbolize `gva` with the PDB of `module`, if one is loaded.
+    pub(crate) fn symbolize_with_pdb(&self, module: &ModuleEntry, gva: Gva) -> Option<String> {
+        let symbols = self.pdbs.get(&module.at.start)?;
+        let rva = u32::try_from(gva.u64() - module.at.start.u64()).ok()?;
+        let (name, offset) = symbols.nearest(rva)?;
+        let module_name = module_short_name(&module.name);
+
+        Some(match offset {
+            0 => format!("{module_name}!{name}"),
+            offset => format!("{module_name}!{name}+{offset:#x}"),
+        })
+    }
+
+    /// Get the address of the symbol `name` of `module` out of its PDB, if
+    /// one is loaded.
+    pub(crate) fn resolve_with_pdb(&self, module: &ModuleEntry, name: &str) -> Option<Gva> {
+        let rva = self.pdbs.get(&module.at.start)?.rva(name)?;
+
+        Some(Gva::new(module.at.start.u64() + u64::from(rva)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+
+    use super::*;
+    use crate::pe::IMAGE_DIRECTORY_ENTRY_DEBUG;
+    use crate::testing::DumpBuilder;
+    use crate::PxeFlags;
+
+    const NT: u64 = 0xffff_f800_0000_0000;
+    const GUID: [u8; 16] = [
+        0xb9, 0xdb, 0x44, 0x38, 0x17, 0x20, 0x67, 0x49, 0xbe, 0x7a, 0xa4, 0xa2, 0xc2, 0x04, 0x30,
+        0xfa,
+    ];
+    const BLOCK_SIZE: usize = 0x200;
+    const MSF_MAGIC: &[u8; 32] = b"Microsoft C/C++ MSF 7.00\r\n\x1aDS\0\0\0";
+    const S_PUB32: u16 = 0x110e;
+    const S_GPROC32: u16 = 0x1110;
+
+    /// Lay `streams` out in an MSF 7.0 container: the superblock and the
+    /// block map are followed by the streams, and then the directory.
+    fn msf(streams: &[Vec<u8>]) -> Vec<u8> {
+        /// Append `data` to `file`, and return the blocks it is in.
+        fn push(file: &mut Vec<u8>, data: &[u8]) -> Vec<u8> {
+            data.chunks(BLOCK_SIZE)
+                .map(|chunk| {
+                    let block = (file.len() / BLOCK_SIZE) as u32;
+                    file.extend_from_slice(chunk);
+                    file.resize((block as usize + 1) * BLOCK_SIZE, 0);
+
+                    block.to_le_bytes()
+                })
+                .collect::<Vec<_>>()
+                .concat()
+        }
+
+        let mut file = vec![0; 2 * BLOCK_SIZE];
+
+        let mut directory = (streams.len() as u32).to_le_bytes().to_vec();
+        for stream in streams {
+            directory.extend_from_slice(&(stream.len() as u32).to_le_bytes());
+        }
+
+        for stream in streams {
+            let blocks = push(&mut file, stream);
+            directory.extend_from_slice(&blocks);
+        }
+
+        let block_map = push(&mut file, &directory);
+        file[BLOCK_SIZE..BLOCK_SIZE + block_map.len()].copy_from_slice(&block_map);
+        file[..0x20].copy_from_slice(MSF_MAGIC);
+        file[0x20..0x24].copy_from_slice(&(BLOCK_SIZE as u32).to_le_bytes());
+        let blocks = (file.len() / BLOCK_SIZE) as u32;
+        file[0x28..0x2c].copy_from_slice(&blocks.to_le_bytes());
+        file[0x2c..0x30].copy_from_slice(&(directory.len() as u32).to_le_bytes());
+        file[0x34..0x38].copy_from_slice(&1u32.to_le_bytes());
+
+        file
+    }
+
+    /// Build a symbol record of `kind`.
+    fn record(kind: u16, data: &[u8]) -> Vec<u8> {
+        let mut record = ((data.len() + 2) as u16).to_le_bytes().to_vec();
+        record.extend_from_slice(&kind.to_le_bytes());
+        record.extend_from_slice(data);
+
+        record
+    }
+
+    /// Build a `S_PUB32` record.
+    fn public(segment: u16, offset: u32, name: &str) -> Vec<u8> {
+        let mut data = 0u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&offset.to_le_bytes());
+        data.extend_from_slice(&segment.to_le_bytes());
+        data.extend_from_slice(name.as_bytes());
+        data.push(0);
+
+        record(S_PUB32, &data)
+    }
+
+    /// Build a PDB whose `.text` is at 0x1000 and `.data` at 0x3000; a
+    /// compiland has the procedure `KiInternal`, whose public symbol is
+    /// decorated.
+    fn pdb(age: u32) -> Vec<u8> {
+        let mut info = vec![0; 0x20];
+        info[0x0..0x4].copy_from_slice(&20000404u32.to_le_bytes());
+        info[0x8..0xc].copy_from_slice(&1u32.to_le_bytes());
+        info[0xc..0x1c].copy_from_slice(&GUID);
+
+        let mut procedure = vec![0; 0x1c];
+        procedure.extend_from_slice(&0x100u32.to_le_bytes());
+        procedure.extend_from_slice(&1u16.to_le_bytes());
+        procedure.push(0);
+        procedure.extend_from_slice(b"KiInternal\0");
+        let mut module_symbols = 4u32.to_le_bytes().to_vec();
+        module_symbols.extend_from_slice(&record(S_GPROC32, &procedure));
+
+        let mut module_info = vec![0; 0x40];
+        module_info[0x22..0x24].copy_from_slice(&6u16.to_le_bytes());
+        module_info[0x24..0x28].copy_from_slice(&(module_symbols.len() as u32).to_le_bytes());
+        module_info.extend_from_slice(b"ki.obj\0ki.obj\0\0\0");
+
+        let mut dbi = vec![0; 0x40];
+        dbi[0x0..0x4].copy_from_slice(&u32::MAX.to_le_bytes());
+        dbi[0x4..0x8].copy_from_slice(&19990903u32.to_le_bytes());
+        dbi[0x8..0xc].copy_from_slice(&age.to_le_bytes());
+        dbi[0x14..0x16].copy_from_slice(&4u16.to_le_bytes());
+        dbi[0x18..0x1c].copy_from_slice(&(module_info.len() as u32).to_le_bytes());
+        dbi[0x30..0x34].copy_from_slice(&22u32.to_le_bytes());
+        dbi.extend_from_slice(&module_info);
+        for idx in 0..11u16 {
+            let stream = if idx == 5 { 5 } else { u16::MAX };
+            dbi.extend_from_slice(&stream.to_le_bytes());
+        }
+
+        let symbol_records = [
+            public(1, 0x10, "KeBugCheckEx"),
+            public(1, 0x100, "?KiInternal@@YAXXZ"),
+            public(2, 0x20, "PsLoadedModuleList"),
+        ]
+        .concat();
+
+        let mut section_headers = vec![0; 2 * 0x28];
+        section_headers[0xc..0x10].copy_from_slice(&0x1_000u32.to_le_bytes());
+        section_headers[0x34..0x38].copy_from_slice(&0x3_000u32.to_le_bytes());
+
+        msf(&[
+            Vec::new(),
+            info,
+            Vec::new(),
+            dbi,
+            symbol_records,
+            section_headers,
+            module_symbols,
+        ])
+    }
+
+    /// Build a dump with `nt`, whose CodeView record has [`GUID`] and an age
+    /// of 2.
+    fn dump() -> Vec<u8> {
+        let mut headers = vec![0; 0x1_000];
+        headers[..2].copy_from_slice(b"MZ");
+        headers[0x3c..0x40].copy_from_slice(&0x80u32.to_le_bytes());
+        headers[0x80..0x84].copy_from_slice(b"PE\0\0");
+        headers[0x98..0x9a].copy_from_slice(&0x20bu16.to_le_bytes());
+        headers[0xd0..0xd4].copy_from_slice(&0x4_000u32.to_le_bytes());
+        headers[0x104..0x108].copy_from_slice(&16u32.to_le_bytes());
+        let debug = 0x108 + (IMAGE_DIRECTORY_ENTRY_DEBUG * 8);
+        headers[debug..debug + 4].copy_from_slice(&0x400u32.to_le_bytes());
+        headers[debug + 4..debug + 8].copy_from_slice(&0x1cu32.to_le_bytes());
+        headers[0x40c..0x410].copy_from_slice(&2u32.to_le_bytes());
+        headers[0x410..0x414].copy_from_slice(&0x30u32.to_le_bytes());
+        headers[0x414..0x418].copy_from_slice(&0x500u32.to_le_bytes());
+        headers[0x500..0x504].copy_from_slice(b"RSDS");
+        headers[0x504..0x514].copy_from_slice(&GUID);
+        headers[0x514..0x518].copy_from_slice(&2u32.to_le_bytes());
+        headers[0x518..0x52a].copy_from_slice(b"d:\\os\\ntkrnlmp.pdb");
+
+        DumpBuilder::new()
+            .map_virt(NT, 0x10_000, PxeFlags::Present)
+            .write_virt(NT, &headers)
+            .module(NT..NT + 0x4_000, "ntoskrnl.exe")
+            .build()
+    }
+
+    /// Write `pdb` to a temporary file.
+    fn write_pdb(pdb: &[u8], suffix: &str) -> std::path::PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("kdmp-parser-{suffix}-{}.pdb", std::process::id()));
+        fs::File::create(&path).unwrap().write_all(pdb).unwrap();
+
+        path
+    }
+
+    #[test]
+    fn load_pdb() {
+        let mut parser = KernelDumpParser::from_bytes(dump()).unwrap();
+        assert_eq!(
+            parser.symbolize(Gva::new(NT + 0x1_022)).unwrap(),
+            "nt+0x1022"
+        );
+
+        // A PDB that isn't the one of the module is rejected..
+        let path = write_pdb(&pdb(3), "mismatch");
+        assert!(matches!(
+            parser.load_pdb("nt", &path),
+            Err(KdmpParserError::PdbMismatch { expected, found, .. })
+                if expected == "3844DBB920174967BE7AA4A2C20430FA2"
+                    && found == "3844DBB920174967BE7AA4A2C20430FA3"
+        ));
+        assert!(parser.pdbs.is_empty());
+
+        // ..unless it is forced..
+        parser.force_load_pdb("ntoskrnl.exe", &path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            parser.symbolize(Gva::new(NT + 0x1_022)).unwrap(),
+            "nt!KeBugCheckEx+0x12"
+        );
+
+        // ..and the one of the module is loaded.
+        let path = write_pdb(&pdb(2), "match");
+        let mut parser = KernelDumpParser::from_bytes(dump()).unwrap();
+        parser.load_pdb("nt", &path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            parser.symbolize(Gva::new(NT + 0x1_010)).unwrap(),
+            "nt!KeBugCheckEx"
+        );
+        assert_eq!(
+            parser.symbolize(Gva::new(NT + 0x1_108)).unwrap(),
+            "nt!KiInternal+0x8"
+        );
+        assert_eq!(
+            parser.resolve_symbol("nt!PsLoadedModuleList").unwrap(),
+            Gva::new(NT + 0x3_020)
+        );
+        assert_eq!(
+            parser.resolve_symbol("nt!?KiInternal@@YAXXZ").unwrap(),
+            Gva::new(NT + 0x1_100)
+        );
+        assert!(matches!(
+            parser.resolve_symbol("nt!KiNope"),
+            Err(KdmpParserError::UnknownSymbol(symbol)) if symbol == "nt!KiNope"
+        ));
+        assert!(matches!(
+            parser.load_pdb("hal", &path),
+            Err(KdmpParserError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_pdb() {
+        assert!(matches!(
+            Pdb::parse(io::Cursor::new(
+                b"Microsoft C/C++ program database 2.00\r\n"
+            )),
+            Err(KdmpParserError::InvalidPdb(_))
+        ));
+
+        // The streams can't point past the end of the file.
+        let mut pdb = pdb(2);
+        pdb.truncate(pdb.len() - BLOCK_SIZE);
+        assert!(matches!(
+            Pdb::parse(io::Cursor::new(pdb)),
+            Err(KdmpParserError::InvalidPdb(_))
+        ));
+    }
+}
@@ -0,0 +1,491 @@
+//! This contains a minimal parser for the headers of the PE images mapped in
+//! memory.
+use crate::error::Result;
+use crate::gxa::Gxa;
+use crate::structs::Page;
+use crate::{Gva, KdmpParserError, KernelDumpParser};
+
+/// Indices in the data directories.
+pub(crate) const IMAGE_DIRECTORY_ENTRY_EXPORT: usize = 0;
+pub(crate) const IMAGE_DIRECTORY_ENTRY_RESOURCE: usize = 2;
+pub(crate) const IMAGE_DIRECTORY_ENTRY_EXCEPTION: usize = 3;
+pub(crate) const IMAGE_DIRECTORY_ENTRY_BASERELOC: usize = 5;
+pub(crate) const IMAGE_DIRECTORY_ENTRY_DEBUG: usize = 6;
+pub(crate) const IMAGE_DIRECTORY_ENTRY_IAT: usize = 12;
+
+/// `IMAGE_DOS_HEADER.e_magic`.
+const IMAGE_DOS_SIGNATURE: u16 = 0x5a_4d;
+
+/// `IMAGE_NT_HEADERS.Signature`.
+const IMAGE_NT_SIGNATURE: u32 = 0x00_00_45_50;
+
+/// `IMAGE_OPTIONAL_HEADER.Magic` for 32-bit & 64-bit images.
+const IMAGE_NT_OPTIONAL_HDR32_MAGIC: u16 = 0x1_0b;
+const IMAGE_NT_OPTIONAL_HDR64_MAGIC: u16 = 0x2_0b;
+
+/// ```text
+/// kd> dt nt!_IMAGE_DOS_HEADER e_lfanew
+///    +0x03c e_lfanew         : Int4B
+/// kd> dt nt!_IMAGE_NT_HEADERS64
+///    +0x000 Signature        : Uint4B
+///    +0x004 FileHeader       : _IMAGE_FILE_HEADER
+///    +0x018 OptionalHeader   : _IMAGE_OPTIONAL_HEADER64
+/// kd> dt nt!_IMAGE_FILE_HEADER Machine TimeDateStamp
+///    +0x000 Machine          : Uint2B
+///    +0x004 TimeDateStamp    : Uint4B
+/// kd> dt nt!_IMAGE_OPTIONAL_HEADER64 Magic AddressOfEntryPoint ImageBase SizeOfImage SizeOfHeaders CheckSum NumberOfRvaAndSizes DataDirectory
+///    +0x000 Magic            : Uint2B
+///    +0x010 AddressOfEntryPoint : Uint4B
+///    +0x018 ImageBase        : Uint8B
+///    +0x038 SizeOfImage      : Uint4B
+///    +0x03c SizeOfHeaders    : Uint4B
+///    +0x040 CheckSum         : Uint4B
+///    +0x06c NumberOfRvaAndSizes : Uint4B
+///    +0x070 DataDirectory    : [16] _IMAGE_DATA_DIRECTORY
+/// kd> dt nt!_IMAGE_OPTIONAL_HEADER Magic ImageBase SizeOfImage NumberOfRvaAndSizes DataDirectory
+///    +0x000 Magic            : Uint2B
+///    +0x01c ImageBase        : Uint4B
+///    +0x038 SizeOfImage      : Uint4B
+///    +0x05c NumberOfRvaAndSizes : Uint4B
+///    +0x060 DataDirectory    : [16] _IMAGE_DATA_DIRECTORY
+/// ```
+const DOS_HEADER_E_LFANEW: usize = 0x3c;
+const NT_HEADERS_FILE_HEADER: usize = 0x4;
+const FILE_HEADER_MACHINE: usize = 0x0;
+const FILE_HEADER_TIME_DATE_STAMP: usize = 0x4;
+const NT_HEADERS_OPTIONAL_HEADER: usize = 0x18;
+const OPTIONAL_HEADER_ADDRESS_OF_ENTRY_POINT: usize = 0x10;
+const OPTIONAL_HEADER_SIZE_OF_IMAGE: usize = 0x38;
+const OPTIONAL_HEADER_SIZE_OF_HEADERS: usize = 0x3c;
+const OPTIONAL_HEADER_CHECK_SUM: usize = 0x40;
+
+/// The `IMAGE_FILE_HEADER.Machine` of the images Windows runs.
+const IMAGE_FILE_MACHINE_I386: u16 = 0x1_4c;
+const IMAGE_FILE_MACHINE_ARMNT: u16 = 0x1_c4;
+const IMAGE_FILE_MACHINE_AMD64: u16 = 0x8664;
+const IMAGE_FILE_MACHINE_ARM64: u16 = 0xaa64;
+
+/// Largest `SizeOfImage` we consider plausible.
+const MAX_SIZE_OF_IMAGE: u32 = 0x1000_0000;
+
+/// ```text
+/// kd> dt nt!_IMAGE_FILE_HEADER NumberOfSections SizeOfOptionalHeader
+///    +0x002 NumberOfSections : Uint2B
+///    +0x010 SizeOfOptionalHeader : Uint2B
+/// kd> dt nt!_IMAGE_SECTION_HEADER
+///    +0x000 Name             : [8] UChar
+///    +0x008 Misc             : <unnamed-tag>
+///    +0x00c VirtualAddress   : Uint4B
+///    +0x010 SizeOfRawData    : Uint4B
+///    +0x014 PointerToRawData : Uint4B
+///    +0x024 Characteristics  : Uint4B
+///    +0x028 Size: 0x28
+/// ```
+const FILE_HEADER_NUMBER_OF_SECTIONS: usize = 0x2;
+const FILE_HEADER_SIZE_OF_OPTIONAL_HEADER: usize = 0x10;
+const SECTION_HEADER_NAME_SIZE: usize = 0x8;
+const SECTION_HEADER_VIRTUAL_SIZE: usize = 0x8;
+const SECTION_HEADER_VIRTUAL_ADDRESS: usize = 0xc;
+const SECTION_HEADER_SIZE_OF_RAW_DATA: usize = 0x10;
+const SECTION_HEADER_POINTER_TO_RAW_DATA: usize = 0x14;
+const SECTION_HEADER_CHARACTERISTICS: usize = 0x24;
+const SECTION_HEADER_SIZE: usize = 0x28;
+
+/// The `IMAGE_SECTION_HEADER.Characteristics` that make a section discardable,
+/// executable and writable.
+pub(crate) const IMAGE_SCN_MEM_DISCARDABLE: u32 = 0x0200_0000;
+pub(crate) const IMAGE_SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+pub(crate) const IMAGE_SCN_MEM_WRITE: u32 = 0x8000_0000;
+
+/// Maximum number of data directories (`IMAGE_NUMBEROF_DIRECTORY_ENTRIES`).
+const IMAGE_NUMBEROF_DIRECTORY_ENTRIES: usize = 16;
+
+/// Read a [`u16`] at `offset` in `data`.
+pub(crate) fn u16_at(data: &[u8], offset: usize) -> Result<u16> {
+    offset
+        .checked_add(2)
+        .and_then(|end| data.get(offset..end))
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or(KdmpParserError::InvalidData("pe data is too small"))
+}
+
+/// Read a [`u32`] at `offset` in `data`.
+pub(crate) fn u32_at(data: &[u8], offset: usize) -> Result<u32> {
+    offset
+        .checked_add(4)
+        .and_then(|end| data.get(offset..end))
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or(KdmpParserError::InvalidData("pe data is too small"))
+}
+
+/// Read a [`u64`] at `offset` in `data`.
+pub(crate) fn u64_at(data: &[u8], offset: usize) -> Result<u64> {
+    offset
+        .checked_add(8)
+        .and_then(|end| data.get(offset..end))
+        .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+        .ok_or(KdmpParserError::InvalidData("pe data is too small"))
+}
+
+/// A section header of an image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SectionHeader {
+    /// Where the header is in the headers of the image.
+    pub offset: usize,
+    pub name: String,
+    pub virtual_size: u32,
+    pub virtual_address: u32,
+    pub size_of_raw_data: u32,
+    pub pointer_to_raw_data: u32,
+    pub characteristics: u32,
+}
+
+/// Parse the section headers out of the `headers` of an image.
+pub(crate) fn section_headers(headers: &[u8]) -> Result<Vec<SectionHeader>> {
+    let nt_headers = u32_at(headers, DOS_HEADER_E_LFANEW)? as usize;
+    if u32_at(headers, nt_headers)? != IMAGE_NT_SIGNATURE {
+        return Err(KdmpParserError::InvalidData("invalid nt signature"));
+    }
+
+    let file_header = nt_headers + NT_HEADERS_FILE_HEADER;
+    let count = usize::from(u16_at(
+        headers,
+        file_header + FILE_HEADER_NUMBER_OF_SECTIONS,
+    )?);
+    let size_of_optional_header = usize::from(u16_at(
+        headers,
+        file_header + FILE_HEADER_SIZE_OF_OPTIONAL_HEADER,
+    )?);
+    let sections = nt_headers + NT_HEADERS_OPTIONAL_HEADER + size_of_optional_header;
+    (0..count)
+        .map(|idx| {
+            let offset = sections + (idx * SECTION_HEADER_SIZE);
+            if offset + SECTION_HEADER_SIZE > headers.len() {
+                return Err(KdmpParserError::InvalidData("invalid section header"));
+            }
+
+            // The name is padded with zeros, unless it is 8 characters long.
+            let name = &headers[offset..offset + SECTION_HEADER_NAME_SIZE];
+            let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+
+            Ok(SectionHeader {
+                offset,
+                name: String::from_utf8_lossy(&name[..len]).into_owned(),
+                virtual_size: u32_at(headers, offset + SECTION_HEADER_VIRTUAL_SIZE)?,
+                virtual_address: u32_at(headers, offset + SECTION_HEADER_VIRTUAL_ADDRESS)?,
+                size_of_raw_data: u32_at(headers, offset + SECTION_HEADER_SIZE_OF_RAW_DATA)?,
+                pointer_to_raw_data: u32_at(headers, offset + SECTION_HEADER_POINTER_TO_RAW_DATA)?,
+                characteristics: u32_at(headers, offset + SECTION_HEADER_CHARACTERISTICS)?,
+            })
+        })
+        .collect()
+}
+
+/// The `IMAGE_BASE_RELOCATION` types of the fixups of 32-bit and 64-bit
+/// addresses; the padding entries are `IMAGE_REL_BASED_ABSOLUTE`.
+const IMAGE_REL_BASED_ABSOLUTE: u16 = 0;
+const IMAGE_REL_BASED_HIGHLOW: u16 = 3;
+const IMAGE_REL_BASED_DIR64: u16 = 10;
+
+/// Apply the base relocations in `relocations` to `image`, an image laid out
+/// like it is mapped, as if it was loaded `delta` bytes away from its
+/// `ImageBase`. The fixups of other types than the ones of 32-bit and 64-bit
+/// addresses are ignored.
+///
+/// ```text
+/// kd> dt nt!_IMAGE_BASE_RELOCATION
+///    +0x000 VirtualAddress   : Uint4B
+///    +0x004 SizeOfBlock      : Uint4B
+/// ```
+pub(crate) fn apply_relocations(image: &mut [u8], relocations: &[u8], delta: u64) -> Result<()> {
+    let mut block = 0;
+    while block + 8 <= relocations.len() {
+        let page = u32_at(relocations, block)? as usize;
+        let size = u32_at(relocations, block + 4)? as usize;
+        if size < 8 || block + size > relocations.len() {
+            return Err(KdmpParserError::InvalidData("invalid relocation block"));
+        }
+
+        for entry in (block + 8..block + size - 1).step_by(2) {
+            let entry = u16_at(relocations, entry)?;
+            let offset = page + usize::from(entry & 0xfff);
+            match entry >> 12 {
+                IMAGE_REL_BASED_ABSOLUTE => {}
+                IMAGE_REL_BASED_HIGHLOW => {
+                    let fixup = image
+                        .get_mut(offset..offset + 4)
+                        .ok_or(KdmpParserError::InvalidData("invalid relocation"))?;
+                    let value = u32::from_le_bytes(fixup.try_into().unwrap());
+                    fixup.copy_from_slice(&value.wrapping_add(delta as u32).to_le_bytes());
+                }
+                IMAGE_REL_BASED_DIR64 => {
+                    let fixup = image
+                        .get_mut(offset..offset + 8)
+                        .ok_or(KdmpParserError::InvalidData("invalid relocation"))?;
+                    let value = u64::from_le_bytes(fixup.try_into().unwrap());
+                    fixup.copy_from_slice(&value.wrapping_add(delta).to_le_bytes());
+                }
+                _ => {}
+            }
+        }
+
+        block += size;
+    }
+
+    Ok(())
+}
+
+/// Rewrite the section headers of an image copied out of memory so that each
+/// section's raw data is where it is mapped; this makes the image look like a
+/// file on disk to PE parsers.
+#[cfg(feature = "object")]
+pub(crate) fn unmap_sections(image: &mut [u8]) -> Result<()> {
+    for header in section_headers(image)? {
+        let section = header.offset;
+        let virtual_address = header.virtual_address;
+        // The data past the end of the image wasn't mapped, so leave it out.
+        let available = (image.len() as u64).saturating_sub(virtual_address.into());
+        let size =
+            u64::from(header.virtual_size.max(header.size_of_raw_data)).min(available) as u32;
+
+        let raw_data = section + SECTION_HEADER_SIZE_OF_RAW_DATA;
+        image[raw_data..raw_data + 4].copy_from_slice(&size.to_le_bytes());
+        let pointer = section + SECTION_HEADER_POINTER_TO_RAW_DATA;
+        image[pointer..pointer + 4].copy_from_slice(&virtual_address.to_le_bytes());
+    }
+
+    Ok(())
+}
+
+/// An entry of the data directories of an image.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct DataDirectory {
+    pub rva: u32,
+    pub size: u32,
+}
+
+/// What we need out of the headers of an image.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct PeHeaders {
+    pub machine: u16,
+    pub time_date_stamp: u32,
+    pub address_of_entry_point: u32,
+    pub image_base: u64,
+    pub size_of_image: u32,
+    pub size_of_headers: u32,
+    pub check_sum: u32,
+    pub data_directories: Vec<DataDirectory>,
+}
+
+impl PeHeaders {
+    /// Parse the headers of an image out of its first page.
+    pub(crate) fn parse(page: &[u8]) -> Result<Self> {
+        if u16_at(page, 0)? != IMAGE_DOS_SIGNATURE {
+            return Err(KdmpParserError::InvalidData("invalid dos signature"));
+        }
+
+        let nt_headers = u32_at(page, DOS_HEADER_E_LFANEW)? as usize;
+        if u32_at(page, nt_headers)? != IMAGE_NT_SIGNATURE {
+            return Err(KdmpParserError::InvalidData("invalid nt signature"));
+        }
+
+        let optional_header = nt_headers + NT_HEADERS_OPTIONAL_HEADER;
+        let (image_base, number_of_rva_and_sizes, data_directory) =
+            match u16_at(page, optional_header)? {
+                IMAGE_NT_OPTIONAL_HDR32_MAGIC => {
+                    (u32_at(page, optional_header + 0x1c)?.into(), 0x5c, 0x60)
+                }
+                IMAGE_NT_OPTIONAL_HDR64_MAGIC => {
+                    (u64_at(page, optional_header + 0x18)?, 0x6c, 0x70)
+                }
+                _ => {
+                    return Err(KdmpParserError::InvalidData(
+                        "invalid optional header magic",
+                    ))
+                }
+            };
+
+        let count = (u32_at(page, optional_header + number_of_rva_and_sizes)? as usize)
+            .min(IMAGE_NUMBEROF_DIRECTORY_ENTRIES);
+        let data_directories = (0..count)
+            .map(|idx| {
+                let entry = optional_header + data_directory + (idx * 8);
+
+                Ok(DataDirectory {
+                    rva: u32_at(page, entry)?,
+                    size: u32_at(page, entry + 4)?,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        let file_header = nt_headers + NT_HEADERS_FILE_HEADER;
+        let optional = |offset| u32_at(page, optional_header + offset);
+
+        Ok(Self {
+            machine: u16_at(page, file_header + FILE_HEADER_MACHINE)?,
+            time_date_stamp: u32_at(page, file_header + FILE_HEADER_TIME_DATE_STAMP)?,
+            address_of_entry_point: optional(OPTIONAL_HEADER_ADDRESS_OF_ENTRY_POINT)?,
+            image_base,
+            size_of_image: optional(OPTIONAL_HEADER_SIZE_OF_IMAGE)?,
+            size_of_headers: optional(OPTIONAL_HEADER_SIZE_OF_HEADERS)?,
+            check_sum: optional(OPTIONAL_HEADER_CHECK_SUM)?,
+            data_directories,
+        })
+    }
+
+    /// Do the headers look like the ones of an image Windows would load? The
+    /// signatures alone are common enough in data to not be enough.
+    pub(crate) fn plausible(&self) -> bool {
+        let machine = matches!(
+            self.machine,
+            IMAGE_FILE_MACHINE_I386
+                | IMAGE_FILE_MACHINE_ARMNT
+                | IMAGE_FILE_MACHINE_AMD64
+                | IMAGE_FILE_MACHINE_ARM64
+        );
+
+        machine
+            && (1..=MAX_SIZE_OF_IMAGE).contains(&self.size_of_image)
+            && self.size_of_headers < self.size_of_image
+            && self.address_of_entry_point < self.size_of_image
+    }
+
+    /// Get a data directory, if the image has it.
+    pub(crate) fn data_directory(&self, idx: usize) -> Option<DataDirectory> {
+        self.data_directories
+            .get(idx)
+            .copied()
+            .filter(|directory| directory.rva != 0 && directory.size != 0)
+    }
+}
+
+impl KernelDumpParser {
+    /// Read the headers of the image mapped at `base`.
+    pub(crate) fn pe_headers(&self, base: Gva) -> Result<PeHeaders> {
+        let mut page = vec![0; Page::size() as usize];
+        self.virt_read_exact(base, &mut page)?;
+
+        PeHeaders::parse(&page)
+    }
+
+    /// Read `size` bytes at `rva` in the image mapped at `base`.
+    pub(crate) fn pe_read(&self, base: Gva, rva: u32, size: u32) -> Result<Vec<u8>> {
+        let addr = base
+            .u64()
+            .checked_add(rva.into())
+            .map(Gva::new)
+            .ok_or(KdmpParserError::Overflow("pe rva"))?;
+        self.virt_read_to_vec(addr, size.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers() {
+        let mut page = vec![0; 0x1_000];
+        page[0..2].copy_from_slice(b"MZ");
+        page[0x3c..0x40].copy_from_slice(&0x80u32.to_le_bytes());
+        page[0x80..0x84].copy_from_slice(b"PE\0\0");
+        page[0x84..0x86].copy_from_slice(&0x8664u16.to_le_bytes());
+        page[0x88..0x8c].copy_from_slice(&0x1234_5678u32.to_le_bytes());
+        page[0x98..0x9a].copy_from_slice(&0x20bu16.to_le_bytes());
+        page[0xa8..0xac].copy_from_slice(&0x1_010u32.to_le_bytes());
+        page[0xd0..0xd4].copy_from_slice(&0x13_000u32.to_le_bytes());
+        page[0xd4..0xd8].copy_from_slice(&0x400u32.to_le_bytes());
+        page[0xd8..0xdc].copy_from_slice(&0x1_5f3du32.to_le_bytes());
+        page[0x104..0x108].copy_from_slice(&16u32.to_le_bytes());
+        let resource = 0x108 + (IMAGE_DIRECTORY_ENTRY_RESOURCE * 8);
+        page[resource..resource + 4].copy_from_slice(&0x9_000u32.to_le_bytes());
+        page[resource + 4..resource + 8].copy_from_slice(&0x400u32.to_le_bytes());
+
+        let headers = PeHeaders::parse(&page).unwrap();
+        assert_eq!(headers.machine, 0x8664);
+        assert_eq!(headers.time_date_stamp, 0x1234_5678);
+        assert_eq!(headers.address_of_entry_point, 0x1_010);
+        assert_eq!(headers.size_of_image, 0x13_000);
+        assert_eq!(headers.size_of_headers, 0x400);
+        assert_eq!(headers.check_sum, 0x1_5f3d);
+        assert!(headers.plausible());
+        assert_eq!(
+            headers.data_directory(IMAGE_DIRECTORY_ENTRY_RESOURCE),
+            Some(DataDirectory {
+                rva: 0x9_000,
+                size: 0x400
+            })
+        );
+        assert!(headers.data_directory(0).is_none());
+
+        // An entry point past the end of the image doesn't look right.
+        page[0xa8..0xac].copy_from_slice(&0x13_000u32.to_le_bytes());
+        assert!(!PeHeaders::parse(&page).unwrap().plausible());
+
+        page[0x98] = 0;
+        assert!(PeHeaders::parse(&page).is_err());
+        page[0x3c..0x40].copy_from_slice(&0xffff_fff0u32.to_le_bytes());
+        assert!(PeHeaders::parse(&page).is_err());
+    }
+
+    #[test]
+    fn relocations() {
+        let mut image = vec![0; 0x2_000];
+        image[0x1_008..0x1_010].copy_from_slice(&0x1_4000_1000u64.to_le_bytes());
+        image[0x1_ff0..0x1_ff4].copy_from_slice(&0x40_1000u32.to_le_bytes());
+
+        // A block with a 64-bit fixup and a padding entry, and one with a 32-bit
+        // fixup.
+        let mut relocations = Vec::new();
+        for (page, entries) in [(0x1_000u32, [0xa008u16, 0]), (0x1_000, [0x3ff0, 0])] {
+            relocations.extend_from_slice(&page.to_le_bytes());
+            relocations.extend_from_slice(&12u32.to_le_bytes());
+            for entry in entries {
+                relocations.extend_from_slice(&entry.to_le_bytes());
+            }
+        }
+
+        apply_relocations(&mut image, &relocations, 0x10_000).unwrap();
+        assert_eq!(u64_at(&image, 0x1_008).unwrap(), 0x1_4001_1000);
+        assert_eq!(u32_at(&image, 0x1_ff0).unwrap(), 0x41_1000);
+
+        relocations[4..8].copy_from_slice(&0x100u32.to_le_bytes());
+        assert!(apply_relocations(&mut image, &relocations, 0x10_000).is_err());
+    }
+
+    #[cfg(feature = "object")]
+    #[test]
+    fn unmap() {
+        use object::{Object, ObjectSection};
+
+        // An image with a `.text` section mapped at 0x1000, but that is at 0x400
+        // in the file.
+        let mut image = vec![0; 0x2_000];
+        image[0..2].copy_from_slice(b"MZ");
+        image[0x3c..0x40].copy_from_slice(&0x80u32.to_le_bytes());
+        image[0x80..0x84].copy_from_slice(b"PE\0\0");
+        image[0x84..0x86].copy_from_slice(&0x8664u16.to_le_bytes());
+        image[0x86..0x88].copy_from_slice(&1u16.to_le_bytes());
+        image[0x94..0x96].copy_from_slice(&0xf0u16.to_le_bytes());
+        image[0x98..0x9a].copy_from_slice(&0x20bu16.to_le_bytes());
+        image[0xb8..0xbc].copy_from_slice(&0x1_000u32.to_le_bytes());
+        image[0xbc..0xc0].copy_from_slice(&0x200u32.to_le_bytes());
+        image[0xd0..0xd4].copy_from_slice(&0x2_000u32.to_le_bytes());
+        image[0xd4..0xd8].copy_from_slice(&0x400u32.to_le_bytes());
+        image[0x104..0x108].copy_from_slice(&16u32.to_le_bytes());
+        let section = 0x188;
+        image[section..section + 5].copy_from_slice(b".text");
+        image[section + 0x8..section + 0xc].copy_from_slice(&0x10u32.to_le_bytes());
+        image[section + 0xc..section + 0x10].copy_from_slice(&0x1_000u32.to_le_bytes());
+        image[section + 0x10..section + 0x14].copy_from_slice(&0x200u32.to_le_bytes());
+        image[section + 0x14..section + 0x18].copy_from_slice(&0x400u32.to_le_bytes());
+        image[0x1_000..0x1_004].copy_from_slice(&[0xcc, 0xcc, 0xc3, 0x90]);
+
+        unmap_sections(&mut image).unwrap();
+        let file = object::File::parse(&*image).unwrap();
+        let text = file.section_by_name(".text").unwrap();
+        assert_eq!(&text.data().unwrap()[..4], &[0xcc, 0xcc, 0xc3, 0x90]);
+
+        assert!(unmap_sections(&mut image[..0x100]).is_err());
+    }
+}
@@ -0,0 +1,112 @@
+//! This contains accessors for the PFN database (`nt!MmPfnDatabase`), which
+//! describes how every physical page is used.
+use std::ops::Range;
+
+use crate::error::Result;
+use crate::gxa::Gxa;
+use crate::{Gva, KdmpParserError, KernelDumpParser, Pfn};
+
+/// Which list a physical page is on (`nt!_MMLISTS`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PageState {
+    Zeroed,
+    Free,
+    Standby,
+    Modified,
+    ModifiedNoWrite,
+    Bad,
+    Active,
+    Transition,
+}
+
+impl From<u64> for PageState {
+    fn from(value: u64) -> Self {
+        match value & 0b111 {
+            0 => Self::Zeroed,
+            1 => Self::Free,
+            2 => Self::Standby,
+            3 => Self::Modified,
+            4 => Self::ModifiedNoWrite,
+            5 => Self::Bad,
+            6 => Self::Active,
+            _ => Self::Transition,
+        }
+    }
+}
+
+/// An entry of the PFN database (`nt!_MMPFN`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PfnEntry {
+    /// The page containing the PTE that maps this page.
+    pub containing_page: Pfn,
+    /// Address of the PTE that maps this page.
+    pub pte_address: Gva,
+    /// Number of references to the page.
+    pub reference_count: u16,
+    /// Which list the page is on.
+    pub page_state: PageState,
+    /// The content of the PTE before the page was made valid.
+    pub original_pte: u64,
+}
+
+impl KernelDumpParser {
+    /// Read the PFN database entry that describes `pfn`.
+    pub fn pfn_entry(&self, pfn: Pfn) -> Result<PfnEntry> {
+        const MMPFN: &str = "_MMPFN";
+        let database = self.pfn_database()?;
+        let entry = pfn
+            .u64()
+            .checked_mul(self.profile().size(MMPFN)?)
+            .and_then(|offset| database.u64().checked_add(offset))
+            .map(Gva::new)
+            .ok_or(KdmpParserError::Overflow("pfn entry"))?;
+
+        // The low bits of `PteAddress` are used as a lock.
+        let pte_address = self.read_field(entry, MMPFN, "PteAddress")? & !0b111;
+
+        Ok(PfnEntry {
+            containing_page: Pfn::new(self.read_field(entry, MMPFN, "PteFrame")?),
+            pte_address: Gva::new(pte_address),
+            reference_count: self.read_field(entry, MMPFN, "ReferenceCount")? as u16,
+            page_state: self.read_field(entry, MMPFN, "PageLocation")?.into(),
+            original_pte: self.read_field(entry, MMPFN, "OriginalPte")?,
+        })
+    }
+
+    /// Read the PFN database entries of a range of PFNs. Every entry is read
+    /// independently, so an entry that isn't in the dump doesn't stop the
+    /// iteration.
+    pub fn pfn_entries(
+        &self,
+        pfns: Range<Pfn>,
+    ) -> impl Iterator<Item = (Pfn, Result<PfnEntry>)> + '_ {
+        (pfns.start.u64()..pfns.end.u64()).map(|pfn| {
+            let pfn = Pfn::new(pfn);
+
+            (pfn, self.pfn_entry(pfn))
+        })
+    }
+
+    /// Get the address of the PFN database. It is stored in the dump headers,
+    /// but we fall back to `nt!MmPfnDatabase` if it isn't there.
+    fn pfn_database(&self) -> Result<Gva> {
+        match self.headers().pfn_database {
+            0 => self.virt_read_ptr(self.kd_debugger_data_block()?.mm_pfn_database),
+            pfn_database => Ok(Gva::new(pfn_database)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_state() {
+        assert_eq!(PageState::from(0), PageState::Zeroed);
+        assert_eq!(PageState::from(2), PageState::Standby);
+        assert_eq!(PageState::from(6), PageState::Active);
+        assert_eq!(PageState::from(7), PageState::Transition);
+        assert_eq!(PageState::from(0b1010), PageState::Standby);
+    }
+}
@@ -0,0 +1,147 @@
+//! This contains [`PhysmemPages`], an iterator over the pages of physical
+//! memory that borrows their content from the dump instead of copying it;
+//! see [`KernelDumpParser::physmem_pages`].
+use std::any::Any;
+use std::collections::btree_map;
+use std::io;
+
+use crate::map::{MappedFileReader, Reader, SharedBytes};
+use crate::structs::Page;
+use crate::supplement::is_supplemental;
+use crate::{Gpa, KernelDumpParser};
+
+/// The bytes of a dump that stay in memory for as long as the parser lives.
+pub(crate) type ResidentBytes = Box<dyn AsRef<[u8]> + Send + Sync>;
+
+/// Get the bytes of the dump `reader` reads, if they stay in memory: when it
+/// is a [`MappedFileReader`] or a cursor over [`SharedBytes`], whose bytes are
+/// shared with the parser, or a cursor over bytes that live forever.
+pub(crate) fn resident_bytes(reader: &(impl Reader + 'static)) -> Option<ResidentBytes> {
+    let reader: &dyn Any = reader;
+    if let Some(mapped_file) = reader.downcast_ref::<MappedFileReader<'static>>() {
+        return Some(Box::new(mapped_file.mapping()));
+    }
+
+    if let Some(cursor) = reader.downcast_ref::<io::Cursor<SharedBytes>>() {
+        return Some(Box::new(cursor.get_ref().clone()));
+    }
+
+    reader
+        .downcast_ref::<io::Cursor<&'static [u8]>>()
+        .map(|cursor| Box::new(*cursor.get_ref()) as ResidentBytes)
+}
+
+/// An iterator over the pages of physical memory of a dump, in ascending
+/// [`Gpa`] order, along with their content. It is created by
+/// [`KernelDumpParser::physmem_pages`].
+///
+/// # Examples
+///
+/// ```no_run
+/// # use kdmp_parser::KernelDumpParser;
+/// let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+/// let pages = parser.physmem_pages().expect("the dump is memory mapped");
+/// let needle = b"MZ";
+/// for (gpa, page) in pages {
+///     if page.starts_with(needle) {
+///         println!("{gpa}");
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct PhysmemPages<'parser> {
+    pages: btree_map::Iter<'parser, Gpa, u64>,
+    bytes: &'parser [u8],
+}
+
+impl<'parser> Iterator for PhysmemPages<'parser> {
+    type Item = (Gpa, &'parser [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.pages.find_map(|(&gpa, &offset)| {
+            if is_supplemental(offset) {
+                return None;
+            }
+
+            let start = usize::try_from(offset).ok()?;
+            let end = start.checked_add(Page::size() as usize)?;
+
+            Some((gpa, self.bytes.get(start..end)?))
+        })
+    }
+}
+
+impl KernelDumpParser {
+    /// Iterate over the pages of physical memory in ascending [`Gpa`] order,
+    /// along with their [`Page::size()`] bytes, without copying them. The
+    /// bytes are borrowed from the dump, so this is only available when it
+    /// stays in memory: when it is memory mapped ([`KernelDumpParser::new`]
+    /// maps the dumps up to 4GB), held by the parser
+    /// ([`KernelDumpParser::from_bytes`]), or read out of a `&'static [u8]`;
+    /// it is `None` otherwise, and [`KernelDumpParser::phys_read`] has to be
+    /// used.
+    ///
+    /// The pages of the supplemental sources, and the ones past the end of a
+    /// truncated dump file, aren't in the dump's bytes and are left out.
+    pub fn physmem_pages(&self) -> Option<PhysmemPages<'_>> {
+        Some(PhysmemPages {
+            pages: self.physmem.iter(),
+            bytes: self.resident_bytes.as_deref()?.as_ref(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::Write;
+
+    use super::*;
+    use crate::testing::DumpBuilder;
+
+    /// Check that the pages of `parser` are the ones it reads.
+    fn check(parser: &KernelDumpParser) {
+        let pages = parser.physmem_pages().unwrap().collect::<Vec<_>>();
+        assert_eq!(pages.len() as u64, parser.physmem_len());
+        assert!(pages.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        for (gpa, page) in pages {
+            let mut expected = vec![0; Page::size() as usize];
+            parser.phys_read_exact(gpa, &mut expected).unwrap();
+            assert_eq!(page, expected);
+        }
+
+        let (_, page) = parser
+            .physmem_pages()
+            .unwrap()
+            .find(|&(gpa, _)| gpa == Gpa::new(0x3_000))
+            .unwrap();
+        assert!(page.starts_with(b"second"));
+    }
+
+    #[test]
+    fn physmem_pages() {
+        let dump = DumpBuilder::new()
+            .write_phys(0x1_000, b"first")
+            .write_phys(0x3_000, b"second")
+            .build();
+
+        // The pages are there when the dump is memory mapped..
+        let path = std::env::temp_dir().join(format!(
+            "kdmp-parser-physmem-pages-{}.dmp",
+            std::process::id()
+        ));
+        File::create(&path).unwrap().write_all(&dump).unwrap();
+        let parser = KernelDumpParser::new(&path).unwrap();
+        check(&parser);
+        drop(parser);
+        std::fs::remove_file(&path).unwrap();
+
+        // ..when the parser holds it..
+        let parser = KernelDumpParser::from_bytes(dump.clone()).unwrap();
+        check(&parser);
+
+        // ..but not when they are read out of something else.
+        let parser = KernelDumpParser::with_reader(io::Cursor::new(dump)).unwrap();
+        assert!(parser.physmem_pages().is_none());
+    }
+}
@@ -0,0 +1,318 @@
+//! This contains what is needed to tell whether a file is a kernel dump at all
+//! without parsing it (see [`probe`]): only its first pages are read, so it is
+//! cheap enough to run on every file an intake pipeline receives.
+//!
+//! A file that isn't a kernel dump is told apart from a kernel dump that is
+//! corrupt: the former comes with the format it looks like instead (see
+//! [`DetectedFormat`]), the latter with what is wrong with its headers.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::{probe, Verdict};
+//! match probe("upload.bin").unwrap().verdict {
+//!     Verdict::NotAKernelDump(format) => println!("not a kernel dump: {format}"),
+//!     Verdict::KernelDump { dump_type, likely_valid: true, .. } => {
+//!         println!("a {dump_type:?} kernel dump")
+//!     }
+//!     Verdict::KernelDump { issues, .. } => println!("a corrupt kernel dump: {issues:?}"),
+//! }
+//! ```
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use crate::error::Result;
+use crate::nt::KERNEL_SPACE_START;
+use crate::structs::{
+    BmpHeader64, FromLeBytes, Header64, Page, PhysmemDesc, PhysmemRun, RdmpHeader64,
+    DUMP_HEADER64_EXPECTED_SIGNATURE, DUMP_HEADER64_EXPECTED_VALID_DUMP,
+};
+use crate::{header, ContainerKind, DumpAttributes, DumpType};
+
+/// Number of bytes read off a file: the dump header, and the header of the
+/// physical memory that follows it.
+const PROBE_SIZE: usize = Header64::SIZE + Page::size() as usize;
+
+/// The `ValidDump` of the 32-bit dumps, which follows the `PAGE` signature.
+const VALID_DUMP32: &[u8; 4] = b"DUMP";
+
+/// Maximum number of processors a dump can have.
+const MAX_PROCESSORS: u32 = 2_048;
+
+/// The machine type of the dumps of x64 systems.
+const MACHINE_AMD64: u32 = 0x8664;
+
+/// A format a file that isn't a kernel dump looks like.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DetectedFormat {
+    /// A container a dump might be in; see [`ContainerKind`].
+    Container(ContainerKind),
+    /// A user-mode minidump (`MDMP`).
+    Minidump,
+    /// An ELF file, like the core dumps of Linux.
+    Elf,
+    /// A program database (PDB).
+    Pdb,
+    /// A hibernation file (`hiberfil.sys`).
+    Hibernation,
+    /// Nothing known.
+    Unknown,
+}
+
+impl DetectedFormat {
+    /// Recognize the format of the file that starts with `magic`.
+    fn sniff(magic: &[u8]) -> Self {
+        if let Some(kind) = ContainerKind::sniff(magic) {
+            return Self::Container(kind);
+        }
+
+        match magic {
+            [b'M', b'D', b'M', b'P', ..] => Self::Minidump,
+            [0x7f, b'E', b'L', b'F', ..] => Self::Elf,
+            _ if magic.starts_with(b"Microsoft C/C++ MSF 7.00\r\n\x1aDS") => Self::Pdb,
+            [b'H', b'I', b'B', b'R', ..]
+            | [b'h', b'i', b'b', b'r', ..]
+            | [b'W', b'A', b'K', b'E', ..]
+            | [b'w', b'a', b'k', b'e', ..] => Self::Hibernation,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+impl Display for DetectedFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Container(kind) => write!(f, "{kind}"),
+            Self::Minidump => write!(f, "user-mode minidump"),
+            Self::Elf => write!(f, "ELF file"),
+            Self::Pdb => write!(f, "program database"),
+            Self::Hibernation => write!(f, "hibernation file"),
+            Self::Unknown => write!(f, "unknown format"),
+        }
+    }
+}
+
+/// What [`probe`] thinks of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    /// The file doesn't start like a kernel dump.
+    NotAKernelDump(DetectedFormat),
+    /// The file starts like a kernel dump.
+    KernelDump {
+        /// The type of the dump, if it is one that is known.
+        dump_type: Option<DumpType>,
+        /// Whether nothing is wrong with the headers, which doesn't mean that
+        /// the dump parses.
+        likely_valid: bool,
+        /// What is wrong with the headers.
+        issues: Vec<String>,
+    },
+}
+
+/// The result of [`probe`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbeResult {
+    pub verdict: Verdict,
+}
+
+impl ProbeResult {
+    /// Is the file a kernel dump, corrupt or not?
+    pub fn is_kernel_dump(&self) -> bool {
+        matches!(self.verdict, Verdict::KernelDump { .. })
+    }
+}
+
+/// Tell whether the file at `path` is a kernel dump, reading only its first
+/// pages; see [`probe_bytes`].
+pub fn probe(path: impl AsRef<Path>) -> Result<ProbeResult> {
+    let file = File::open(path)?;
+    let file_size = file.metadata()?.len();
+    let mut start = Vec::with_capacity(PROBE_SIZE);
+    file.take(PROBE_SIZE as u64).read_to_end(&mut start)?;
+
+    Ok(probe_start(&start, file_size))
+}
+
+/// Tell whether `bytes`, the content of a file, are a kernel dump. The
+/// headers are checked, but none of the memory they describe is read.
+pub fn probe_bytes(bytes: &[u8]) -> ProbeResult {
+    probe_start(&bytes[..bytes.len().min(PROBE_SIZE)], bytes.len() as u64)
+}
+
+/// Probe the file of `file_size` bytes that starts with `start`.
+fn probe_start(start: &[u8], file_size: u64) -> ProbeResult {
+    let signature = DUMP_HEADER64_EXPECTED_SIGNATURE.to_le_bytes();
+    if !start.starts_with(&signature) {
+        return ProbeResult {
+            verdict: Verdict::NotAKernelDump(DetectedFormat::sniff(start)),
+        };
+    }
+
+    let mut issues = Vec::new();
+    let dump_type = check(start, file_size, &mut issues);
+
+    ProbeResult {
+        verdict: Verdict::KernelDump {
+            dump_type,
+            likely_valid: issues.is_empty(),
+            issues,
+        },
+    }
+}
+
+/// Check the headers of the kernel dump that starts with `start`, and return
+/// its type if it is known.
+fn check(start: &[u8], file_size: u64, issues: &mut Vec<String>) -> Option<DumpType> {
+    if start.get(4..8) == Some(VALID_DUMP32) {
+        issues.push("32-bit dumps aren't supported".into());
+        return None;
+    }
+
+    if start.len() < Header64::SIZE {
+        issues.push(format!(
+            "the file is too small for a dump header: {file_size:#x} bytes"
+        ));
+        return None;
+    }
+
+    let headers = Header64::from_le_bytes(start);
+    if headers.valid_dump != DUMP_HEADER64_EXPECTED_VALID_DUMP {
+        issues.push(format!("unexpected ValidDump {:#x}", headers.valid_dump));
+    }
+
+    let dump_type = DumpType::try_from(headers.dump_type).ok();
+    if dump_type.is_none() {
+        issues.push(format!("unknown DumpType {:#x}", headers.dump_type));
+    }
+
+    if let Some(attributes) =
+        header::attributes(headers.attributes).filter(DumpAttributes::encrypted)
+    {
+        issues.push(format!("the dump is encrypted ({attributes:?})"));
+    }
+
+    if headers.machine_image_type != MACHINE_AMD64 {
+        issues.push(format!(
+            "unexpected MachineImageType {:#x}",
+            headers.machine_image_type
+        ));
+    }
+
+    if !(1..=MAX_PROCESSORS).contains(&headers.number_processors) {
+        issues.push(format!(
+            "unexpected NumberProcessors {}",
+            headers.number_processors
+        ));
+    }
+
+    let dtb = headers.directory_table_base;
+    if dtb == 0 || dtb % Page::size() != 0 {
+        issues.push(format!("unexpected DirectoryTableBase {dtb:#x}"));
+    }
+
+    if headers.ps_loaded_module_list < KERNEL_SPACE_START {
+        issues.push(format!(
+            "PsLoadedModuleList isn't a kernel address: {:#x}",
+            headers.ps_loaded_module_list
+        ));
+    }
+
+    let sub_header = &start[Header64::SIZE..];
+    match dump_type {
+        Some(DumpType::Full) => {
+            let desc = PhysmemDesc::from_le_bytes(&headers.physical_memory_block_buffer);
+            let max_runs =
+                (headers.physical_memory_block_buffer.len() - PhysmemDesc::SIZE) / PhysmemRun::SIZE;
+            if desc.number_of_runs == 0 || desc.number_of_runs as usize > max_runs {
+                issues.push(format!(
+                    "unexpected number of physical memory runs {:#x}",
+                    desc.number_of_runs
+                ));
+            }
+        }
+        Some(DumpType::Bmp)
+            if sub_header.len() < BmpHeader64::SIZE
+                || !BmpHeader64::from_le_bytes(sub_header).looks_good() =>
+        {
+            issues.push("the bitmap header doesn't look right".into());
+        }
+        Some(DumpType::KernelMemory | DumpType::KernelAndUserMemory | DumpType::CompleteMemory)
+            if sub_header.len() < RdmpHeader64::SIZE
+                || !RdmpHeader64::from_le_bytes(sub_header).looks_good() =>
+        {
+            issues.push("the RDMP header doesn't look right".into());
+        }
+        _ => {}
+    }
+
+    match u64::try_from(headers.required_dump_space) {
+        Ok(required) if required > file_size => issues.push(format!(
+            "the dump is truncated: {file_size:#x} bytes out of {required:#x}"
+        )),
+        Ok(_) => {}
+        Err(_) => issues.push(format!(
+            "unexpected RequiredDumpSpace {:#x}",
+            headers.required_dump_space
+        )),
+    }
+
+    dump_type
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DumpBuilder;
+
+    /// The issues of the kernel dump `bytes`.
+    fn issues(bytes: &[u8]) -> Vec<String> {
+        match probe_bytes(bytes).verdict {
+            Verdict::KernelDump { issues, .. } => issues,
+            verdict => panic!("not a kernel dump: {verdict:?}"),
+        }
+    }
+
+    #[test]
+    fn verdicts() {
+        // A dump is a kernel dump, that looks good..
+        let nt = 0xffff_f800_0000_0000;
+        let dump = DumpBuilder::new().module(nt..nt + 0x1_000, "nt").build();
+        assert_eq!(probe_bytes(&dump).verdict, Verdict::KernelDump {
+            dump_type: Some(DumpType::Bmp),
+            likely_valid: true,
+            issues: Vec::new()
+        });
+
+        // ..until it is truncated, or its headers are corrupt..
+        assert_eq!(issues(&dump[..dump.len() - 1]), [format!(
+            "the dump is truncated: {:#x} bytes out of {:#x}",
+            dump.len() - 1,
+            dump.len()
+        )]);
+        let mut corrupt = dump.clone();
+        corrupt[0x30..0x34].copy_from_slice(&0x14cu32.to_le_bytes());
+        corrupt[0x2000..0x2004].copy_from_slice(b"XXXX");
+        assert_eq!(issues(&corrupt), [
+            "unexpected MachineImageType 0x14c",
+            "the bitmap header doesn't look right"
+        ]);
+        assert_eq!(issues(&dump[..0x100]).len(), 1);
+        assert_eq!(issues(b"PAGEDUMP"), ["32-bit dumps aren't supported"]);
+
+        // ..and the other files are told apart.
+        let not_a_dump = |bytes: &[u8]| match probe_bytes(bytes).verdict {
+            Verdict::NotAKernelDump(format) => format,
+            verdict => panic!("a kernel dump: {verdict:?}"),
+        };
+        assert_eq!(not_a_dump(b"MDMP\x93\xa7"), DetectedFormat::Minidump);
+        assert_eq!(not_a_dump(b"\x7fELF\x02\x01"), DetectedFormat::Elf);
+        assert_eq!(not_a_dump(b"hibr"), DetectedFormat::Hibernation);
+        assert_eq!(
+            not_a_dump(b"PK\x03\x04"),
+            DetectedFormat::Container(ContainerKind::Zip)
+        );
+        assert_eq!(not_a_dump(b""), DetectedFormat::Unknown);
+    }
+}
@@ -0,0 +1,602 @@
+//! This contains what is needed to know about the processors of the dump: the
+//! index and the IRQL of the processor that crashed, like `!analyze` shows
+//! them, its segment bases and its MSRs (see [`Msrs`]), and what every
+//! processor was doing (see [`CpuState`]).
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::KernelDumpParser;
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! if let (Some(processor), Some(irql)) = (parser.crashing_processor(), parser.crash_irql()) {
+//!     println!("processor {processor} bugchecked at IRQL {irql}");
+//! }
+//!
+//! for cpu in parser.cpu_summary() {
+//!     println!("{cpu}");
+//! }
+//!
+//! // gs:[0x188] is the current thread when running kernel code.
+//! let mut thread = [0; 8];
+//! parser.read_gs_relative(0x188, &mut thread).unwrap();
+//! ```
+use std::fmt::{self, Display};
+
+use crate::error::Result;
+use crate::gxa::{Gpa, Gxa};
+use crate::{Gva, KdmpParserError, KernelDumpParser};
+
+/// `IRQL_NOT_LESS_OR_EQUAL`; its second parameter is the IRQL.
+const IRQL_NOT_LESS_OR_EQUAL: u32 = 0xa;
+
+/// `DRIVER_IRQL_NOT_LESS_OR_EQUAL`; its second parameter is the IRQL.
+const DRIVER_IRQL_NOT_LESS_OR_EQUAL: u32 = 0xd1;
+
+/// Where the `_KPRCB` of a processor is in its `_KPCR`.
+///
+/// ```text
+/// kd> dt nt!_KPCR Prcb
+///    +0x180 Prcb             : _KPRCB
+/// ```
+pub(crate) const KPCR_PRCB: u64 = 0x180;
+
+/// The numbers of the MSRs that end up in [`Msrs::extra`].
+const MSR_DEBUGCTL: u32 = 0x1d9;
+const MSR_FS_BASE: u32 = 0xc000_0100;
+
+/// The MSRs of the processor that crashed, out of the special registers
+/// saved in its `_KPRCB`; see [`KernelDumpParser::msrs`]. The ones that
+/// couldn't be read are zero.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Msrs {
+    /// `IA32_LSTAR`: where `syscall` goes in 64-bit mode, which is
+    /// `nt!KiSystemCall64`.
+    pub lstar: u64,
+    /// `IA32_STAR`: the segment selectors of `syscall` and `sysret`.
+    pub star: u64,
+    /// `IA32_CSTAR`: where `syscall` goes in compatibility mode.
+    pub cstar: u64,
+    /// `IA32_FMASK`: the bits of `RFLAGS` that `syscall` clears.
+    pub sfmask: u64,
+    /// `IA32_KERNEL_GS_BASE`: the `GS` base `swapgs` swaps in.
+    pub kernel_gs_base: u64,
+    /// `IA32_GS_BASE`.
+    pub gs_base: u64,
+    /// The other MSRs that have been saved, by number: `IA32_DEBUGCTL` and
+    /// `IA32_FS_BASE`; the ones that are zero are left out.
+    pub extra: Vec<(u32, u64)>,
+}
+
+/// What a processor was doing when the dump was taken. The fields that
+/// couldn't be read are `None` (or null for `current_thread`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuState {
+    /// Index of the processor.
+    pub processor: u32,
+    /// The `_KTHREAD` running on the processor.
+    pub current_thread: Gva,
+    /// Name of the process the thread is attached to.
+    pub current_process: Option<String>,
+    /// Last known instruction pointer of the thread.
+    pub rip: Option<Gva>,
+    /// `rip` symbolized with [`KernelDumpParser::symbolize`].
+    pub symbolized: Option<String>,
+    /// Is the processor running its idle thread?
+    pub idle: bool,
+}
+
+impl Display for CpuState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: thread {}", self.processor, self.current_thread)?;
+        if self.idle {
+            write!(f, " (idle)")?;
+        }
+
+        if let Some(process) = &self.current_process {
+            write!(f, " in {process}")?;
+        }
+
+        match (&self.symbolized, self.rip) {
+            (Some(symbolized), _) => write!(f, " at {symbolized}"),
+            (None, Some(rip)) => write!(f, " at {rip}"),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+impl KernelDumpParser {
+    /// Address of the `_KPRCB` of the processor that crashed, which is the one
+    /// whose saved context matches the context of the dump headers.
+    pub fn crashing_prcb(&self) -> Option<Gva> {
+        self.crashing_prcb.map(|(_, prcb)| prcb)
+    }
+
+    /// Index of the processor that bugchecked. It comes from, in order:
+    /// - the `Number` of the `_KPRCB` of the processor that crashed (see
+    ///   [`KernelDumpParser::crashing_prcb`]),
+    /// - the index of that `_KPRCB` in `nt!KiProcessorBlock` if its `Number`
+    ///   can't be read,
+    /// - zero if the dump only has one processor.
+    pub fn crashing_processor(&self) -> Option<u32> {
+        if let Some((idx, prcb)) = self.crashing_prcb {
+            return Some(self.processor_number(idx, prcb));
+        }
+
+        (self.headers().number_processors == 1).then_some(0)
+    }
+
+    /// IRQL of the processor that bugchecked, at the time of the bugcheck. It
+    /// comes from, in order:
+    /// - `CR8` (which holds the IRQL on x64) in the special registers that
+    ///   `nt!KeBugCheckEx` saves in the `_KPRCB` of the processor that crashed
+    ///   before raising the IRQL,
+    /// - the second parameter of the `IRQL_NOT_LESS_OR_EQUAL` and
+    ///   `DRIVER_IRQL_NOT_LESS_OR_EQUAL` bugchecks, which is the IRQL the
+    ///   faulting access happened at.
+    pub fn crash_irql(&self) -> Option<u8> {
+        let cr8 = self.crashing_prcb().and_then(|prcb| {
+            self.read_field(prcb, "_KPRCB", "ProcessorState.SpecialRegisters.Cr8")
+                .ok()
+        });
+
+        if let Some(cr8) = cr8 {
+            // Only the low 4 bits of `CR8` are defined.
+            return Some((cr8 & 0xf) as u8);
+        }
+
+        let headers = self.headers();
+        match headers.bug_check_code {
+            IRQL_NOT_LESS_OR_EQUAL | DRIVER_IRQL_NOT_LESS_OR_EQUAL => {
+                u8::try_from(headers.bug_check_code_parameters[1]).ok()
+            }
+            _ => None,
+        }
+    }
+
+    /// `GS` base of the kernel on the processor that crashed, which is the
+    /// address of its `_KPCR`. It comes from, in order:
+    /// - the special registers saved in its `_KPRCB`: `MsrGsBase` if it was
+    ///   running kernel code, `MsrGsSwap` if it was running user code as
+    ///   `swapgs` swapped them,
+    /// - the address of its `_KPRCB`, which is in its `_KPCR`.
+    pub fn gs_base_kernel(&self) -> Option<Gva> {
+        let field = if self.crashed_in_user_mode() {
+            "ProcessorState.SpecialRegisters.MsrGsSwap"
+        } else {
+            "ProcessorState.SpecialRegisters.MsrGsBase"
+        };
+
+        self.special_register(field).or_else(|| {
+            self.crashing_prcb()
+                .and_then(|prcb| prcb.u64().checked_sub(KPCR_PRCB))
+                .map(Gva::new)
+        })
+    }
+
+    /// `GS` base of the user code on the processor that crashed, which is
+    /// the address of the TEB of the thread it was running; it is the other
+    /// register of the pair [`KernelDumpParser::gs_base_kernel`] reads.
+    pub fn gs_base_user(&self) -> Option<Gva> {
+        let field = if self.crashed_in_user_mode() {
+            "ProcessorState.SpecialRegisters.MsrGsBase"
+        } else {
+            "ProcessorState.SpecialRegisters.MsrGsSwap"
+        };
+
+        self.special_register(field)
+    }
+
+    /// `FS` base of the processor that crashed, saved in the special
+    /// registers of its `_KPRCB`. The kernel doesn't use it, so it is the one
+    /// of the user code; for a WOW64 thread, it is the address of its 32-bit
+    /// TEB.
+    pub fn fs_base(&self) -> Option<Gva> {
+        self.special_register("ProcessorState.SpecialRegisters.MsrFsBase")
+    }
+
+    /// Get the MSRs of the processor that crashed, which `nt!KeBugCheckEx`
+    /// saves in the special registers of its `_KPRCB`; they are all zero if
+    /// that processor isn't known. The MSRs some dump writers store in the
+    /// secondary data of the dump aren't available, as it isn't parsed.
+    pub fn msrs(&self) -> Msrs {
+        let msr = |name: &str| {
+            self.crashing_prcb()
+                .and_then(|prcb| {
+                    self.read_field(
+                        prcb,
+                        "_KPRCB",
+                        &format!("ProcessorState.SpecialRegisters.{name}"),
+                    )
+                    .ok()
+                })
+                .unwrap_or_default()
+        };
+
+        let extra = [(MSR_DEBUGCTL, "DebugControl"), (MSR_FS_BASE, "MsrFsBase")]
+            .into_iter()
+            .map(|(number, name)| (number, msr(name)))
+            .filter(|&(_, value)| value != 0)
+            .collect();
+
+        Msrs {
+            lstar: msr("MsrLStar"),
+            star: msr("MsrStar"),
+            cstar: msr("MsrCStar"),
+            sfmask: msr("MsrSyscallMask"),
+            kernel_gs_base: msr("MsrGsSwap"),
+            gs_base: msr("MsrGsBase"),
+            extra,
+        }
+    }
+
+    /// Read `gs:[offset]` like the processor that crashed would have: with the
+    /// `GS` base of the kernel if it was running kernel code, and with the one
+    /// of the user code in the address space of its thread otherwise.
+    pub fn read_gs_relative(&self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        if self.crashed_in_user_mode() {
+            let base = self
+                .gs_base_user()
+                .ok_or(KdmpParserError::NotFound("the user gs base"))?;
+
+            self.read_user_relative(base, offset, buf)
+        } else {
+            let base = self
+                .gs_base_kernel()
+                .ok_or(KdmpParserError::NotFound("the kernel gs base"))?;
+            let addr = base
+                .u64()
+                .checked_add(offset)
+                .ok_or(KdmpParserError::Overflow("gs relative address"))?;
+
+            self.virt_read_exact(Gva::new(addr), buf)
+        }
+    }
+
+    /// Read `fs:[offset]` like the processor that crashed would have, in the
+    /// address space of its thread; see [`KernelDumpParser::fs_base`].
+    pub fn read_fs_relative(&self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        let base = self
+            .fs_base()
+            .ok_or(KdmpParserError::NotFound("the fs base"))?;
+
+        self.read_user_relative(base, offset, buf)
+    }
+
+    /// Was the processor that crashed running user code? It is what the
+    /// requested privilege level of its `CS` selector says.
+    fn crashed_in_user_mode(&self) -> bool {
+        self.context_record().seg_cs & 3 == 3
+    }
+
+    /// Read a special register saved in the `_KPRCB` of the processor that
+    /// crashed; the ones that are zero aren't set.
+    pub(crate) fn special_register(&self, field: &str) -> Option<Gva> {
+        let prcb = self.crashing_prcb()?;
+
+        self.read_field(prcb, "_KPRCB", field)
+            .ok()
+            .filter(|&value| value != 0)
+            .map(Gva::new)
+    }
+
+    /// Read `buf.len()` bytes `offset` bytes after the user segment base
+    /// `base`, in the address space of the thread that crashed.
+    fn read_user_relative(&self, base: Gva, offset: u64, buf: &mut [u8]) -> Result<()> {
+        let addr = base
+            .u64()
+            .checked_add(offset)
+            .ok_or(KdmpParserError::Overflow("segment relative address"))?;
+        let dtb = self
+            .thread_or_crashing(None)
+            .and_then(|thread| self.thread_address_space(thread))
+            .map_or_else(|_| self.default_dtb(), |(_, dtb): (_, Gpa)| dtb);
+
+        self.virt_read_exact_with_dtb(Gva::new(addr), buf, dtb)
+    }
+
+    /// Summarize what every processor was doing: the thread it was running,
+    /// the process that thread is attached to, and where it was executing.
+    /// The instruction pointer comes from, in order:
+    /// - the context of the dump headers for the processor that crashed,
+    /// - the context saved in the `_KPRCB` of the processor when it was frozen
+    ///   for the bugcheck,
+    /// - the trap frame of the thread.
+    ///
+    /// The structures of a processor that can't be read leave its entry
+    /// partially filled.
+    pub fn cpu_summary(&self) -> Vec<CpuState> {
+        let crashing_idx = match self.crashing_prcb {
+            Some((idx, _)) => Some(idx),
+            None => (self.headers().number_processors == 1).then_some(0),
+        };
+
+        self.prcbs()
+            .into_iter()
+            .zip(0..)
+            .map(|(prcb, idx)| {
+                let mut state = self.cpu_state(idx, prcb);
+                if crashing_idx == Some(idx) {
+                    state.rip = Some(Gva::new(self.context_record().rip));
+                }
+
+                state.rip = state.rip.filter(|rip| rip.u64() != 0);
+                state.symbolized = state.rip.and_then(|rip| self.symbolize(rip));
+
+                state
+            })
+            .collect()
+    }
+
+    /// Get the address of the `_KPRCB` of every processor out of
+    /// `nt!KiProcessorBlock` (see [`KernelDumpParser::nt_globals`]); the ones
+    /// that can't be read are `None`.
+    pub(crate) fn prcbs(&self) -> Vec<Option<Gva>> {
+        self.nt_globals().prcbs.clone()
+    }
+
+    /// Get the `Number` of the processor whose `_KPRCB` is at `prcb`, or `idx`
+    /// if it can't be read.
+    pub(crate) fn processor_number(&self, idx: u32, prcb: Gva) -> u32 {
+        self.read_field(prcb, "_KPRCB", "Number")
+            .map_or(idx, |number| number as u32)
+    }
+
+    /// Read what the processor `idx`, whose `_KPRCB` is at `prcb`, was doing.
+    fn cpu_state(&self, idx: u32, prcb: Option<Gva>) -> CpuState {
+        let mut state = CpuState {
+            processor: idx,
+            current_thread: Gva::new(0),
+            current_process: None,
+            rip: None,
+            symbolized: None,
+            idle: false,
+        };
+
+        let Some(prcb) = prcb else {
+            return state;
+        };
+
+        state.processor = self.processor_number(idx, prcb);
+        let field = |name| self.read_field(prcb, "_KPRCB", name).ok();
+
+        state.rip = field("ProcessorState.ContextFrame.Rip").map(Gva::new);
+        let Some(thread) = field("CurrentThread").map(Gva::new) else {
+            return state;
+        };
+
+        state.current_thread = thread;
+        state.idle = field("IdleThread") == Some(thread.u64());
+        state.current_process = self
+            .read_field(thread, "_KTHREAD", "ApcState.Process")
+            .and_then(|process| self.process_name(Gva::new(process)))
+            .ok();
+        if state.rip.map_or(true, |rip| rip.u64() == 0) {
+            state.rip = self
+                .read_field(thread, "_KTHREAD", "TrapFrame")
+                .and_then(|trap_frame| self.read_field(Gva::new(trap_frame), "_KTRAP_FRAME", "Rip"))
+                .map(Gva::new)
+                .ok();
+        }
+
+        state
+    }
+
+    /// Read the `ImageFileName` of the `_EPROCESS` at `eprocess`.
+    pub(crate) fn process_name(&self, eprocess: Gva) -> Result<String> {
+        let name = self.field_addr(eprocess, "_EPROCESS", "ImageFileName")?;
+        let name = self.virt_read_struct::<[u8; 15]>(name)?;
+        let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+
+        Ok(String::from_utf8_lossy(&name[..len]).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{DumpBuilder, KdDebuggerData, Register};
+    use crate::PxeFlags;
+
+    const KDBG: u64 = 0xffff_f800_0100_0000;
+    const PRCB: u64 = 0xffff_f800_0200_0000;
+    const THREADS: u64 = 0xffff_f800_0300_0000;
+    const EPROCESS: u64 = 0xffff_f800_0400_0000;
+    const NT: u64 = 0xffff_f800_0500_0000;
+
+    /// Build a dump whose processors have a `_KPRCB` described by `prcbs`:
+    /// its `Number`, the `Rsp` of its context and its `Cr8`. The processor
+    /// whose `Rsp` is 0x1337 is the one that crashed.
+    fn with_prcbs(prcbs: &[(u32, u64, u64)]) -> DumpBuilder {
+        let prcb = |idx: usize| PRCB + (idx as u64 * 0x1_000);
+        let mut builder = DumpBuilder::new()
+            .processors(prcbs.len() as u32)
+            .register(Register::Rsp, 0x1337)
+            .map_virt(KDBG, 0x10_000, PxeFlags::Present)
+            .kd_debugger_data(KDBG, KdDebuggerData {
+                prcbs: (0..prcbs.len()).map(prcb).collect(),
+                offset_prcb_number: 0x24,
+                offset_prcb_proc_state_special_reg: 0x40,
+                offset_prcb_context: 0x400,
+                ..Default::default()
+            });
+        for (idx, (number, rsp, cr8)) in prcbs.iter().enumerate() {
+            let prcb = prcb(idx);
+            let context = prcb + 0x800;
+            builder = builder
+                .map_virt(prcb, 0x11_000 + (idx as u64 * 0x1_000), PxeFlags::Present)
+                .write_virt(prcb + 0x24, &number.to_le_bytes())
+                .write_virt(prcb + 0x40 + 0xa0, &cr8.to_le_bytes())
+                .write_virt(prcb + 0x400, &context.to_le_bytes())
+                .write_virt(context + 0x98, &rsp.to_le_bytes());
+        }
+
+        builder
+    }
+
+    #[test]
+    fn crashing_processor() {
+        // Two processors; the second one crashed, and it is the fifth one.
+        let dump = with_prcbs(&[(0, 0, 0), (5, 0x1337, 2)]).build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+        assert_eq!(parser.crashing_prcb(), Some(Gva::new(PRCB + 0x1_000)));
+        assert_eq!(parser.crashing_processor(), Some(5));
+        assert_eq!(parser.crash_irql(), Some(2));
+    }
+
+    #[test]
+    fn segment_bases() {
+        let prcb = PRCB + 0x1_000;
+        let thread = THREADS + 0x400;
+        let teb = 0x7ff6_0000_0000u64;
+        let builder = with_prcbs(&[(0, 0, 0), (5, 0x1337, 2)])
+            .map_virt(teb, 0x13_000, PxeFlags::Present | PxeFlags::UserAccessible)
+            .write_virt(prcb + 0x8, &thread.to_le_bytes())
+            .write_virt(teb + 0x30, &teb.to_le_bytes());
+
+        // Without the special registers, the kernel base is the `_KPCR` the
+        // `_KPRCB` is in.
+        let parser = KernelDumpParser::from_bytes(builder.clone().build()).unwrap();
+        assert_eq!(parser.gs_base_kernel(), Some(Gva::new(prcb - 0x180)));
+        assert_eq!(parser.gs_base_user(), None);
+        assert_eq!(parser.fs_base(), None);
+        let mut buffer = [0; 8];
+        parser.read_gs_relative(0x188, &mut buffer).unwrap();
+        assert_eq!(u64::from_le_bytes(buffer), thread);
+        assert!(matches!(
+            parser.read_fs_relative(0, &mut buffer),
+            Err(KdmpParserError::NotFound(_))
+        ));
+
+        // The processor was running user code, so `swapgs` hasn't happened.
+        let pcr = 0xffff_f800_0600_0000u64;
+        let mut dump = builder
+            .write_virt(prcb + 0x40 + 0xa8, &teb.to_le_bytes())
+            .write_virt(prcb + 0x40 + 0xb0, &pcr.to_le_bytes())
+            .write_virt(prcb + 0x40 + 0xe0, &(teb + 0x2_000).to_le_bytes())
+            .build();
+        dump[0x348 + 0x38..0x348 + 0x3a].copy_from_slice(&0x33u16.to_le_bytes());
+        let parser = KernelDumpParser::from_bytes(dump.clone()).unwrap();
+        assert_eq!(parser.gs_base_kernel(), Some(Gva::new(pcr)));
+        assert_eq!(parser.gs_base_user(), Some(Gva::new(teb)));
+        assert_eq!(parser.fs_base(), Some(Gva::new(teb + 0x2_000)));
+        parser.read_gs_relative(0x30, &mut buffer).unwrap();
+        assert_eq!(u64::from_le_bytes(buffer), teb);
+
+        // And the other way around for kernel code.
+        dump[0x348 + 0x38..0x348 + 0x3a].copy_from_slice(&0x10u16.to_le_bytes());
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+        assert_eq!(parser.gs_base_kernel(), Some(Gva::new(teb)));
+        assert_eq!(parser.gs_base_user(), Some(Gva::new(pcr)));
+    }
+
+    #[test]
+    fn msrs() {
+        // Without a processor that crashed, there are no MSRs..
+        let builder = with_prcbs(&[(0, 0, 0), (5, 0x1337, 2)]);
+        let dump = with_prcbs(&[(0, 0, 0)]).build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+        assert_eq!(parser.msrs(), Msrs::default());
+
+        // ..and the ones of the one that did come from its special registers.
+        let prcb = PRCB + 0x1_000;
+        let special_registers = prcb + 0x40;
+        let dump = builder
+            .write_virt(special_registers + 0x78, &1u64.to_le_bytes())
+            .write_virt(special_registers + 0xa8, &0xa8u64.to_le_bytes())
+            .write_virt(special_registers + 0xb0, &0xb0u64.to_le_bytes())
+            .write_virt(special_registers + 0xb8, &0xb8u64.to_le_bytes())
+            .write_virt(special_registers + 0xc0, &0xc0u64.to_le_bytes())
+            .write_virt(special_registers + 0xc8, &0xc8u64.to_le_bytes())
+            .write_virt(special_registers + 0xd0, &0xd0u64.to_le_bytes())
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+        assert_eq!(parser.msrs(), Msrs {
+            lstar: 0xc0,
+            star: 0xb8,
+            cstar: 0xc8,
+            sfmask: 0xd0,
+            kernel_gs_base: 0xb0,
+            gs_base: 0xa8,
+            extra: vec![(MSR_DEBUGCTL, 1)]
+        });
+    }
+
+    #[test]
+    fn summary() {
+        // The first processor is idle and its rip comes from the trap frame of its
+        // thread, the second one crashed and the third one can't be read.
+        let idle = THREADS;
+        let thread = THREADS + 0x400;
+        let trap_frame = THREADS + 0x800;
+        let dump = with_prcbs(&[(0, 0, 0), (5, 0x1337, 2)])
+            .processors(3)
+            .register(Register::Rip, NT + 0x10)
+            .module(NT..NT + 0x1_000, "ntoskrnl.exe")
+            .map_virt(THREADS, 0x13_000, PxeFlags::Present)
+            .map_virt(EPROCESS, 0x14_000, PxeFlags::Present)
+            .write_virt(PRCB + 0x8, &idle.to_le_bytes())
+            .write_virt(PRCB + 0x18, &idle.to_le_bytes())
+            .write_virt(PRCB + 0x1_008, &thread.to_le_bytes())
+            .write_virt(PRCB + 0x1_018, &idle.to_le_bytes())
+            .write_virt(PRCB + 0x1_228, &(NT + 0x30).to_le_bytes())
+            .write_virt(idle + 0x90, &trap_frame.to_le_bytes())
+            .write_virt(trap_frame + 0x168, &(NT + 0x20).to_le_bytes())
+            .write_virt(thread + 0xb8, &EPROCESS.to_le_bytes())
+            .write_virt(EPROCESS + 0x5a8, b"System\0")
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+
+        let summary = parser.cpu_summary();
+        assert_eq!(summary, [
+            CpuState {
+                processor: 0,
+                current_thread: Gva::new(idle),
+                current_process: None,
+                rip: Some(Gva::new(NT + 0x20)),
+                symbolized: Some("nt+0x20".into()),
+                idle: true,
+            },
+            CpuState {
+                processor: 5,
+                current_thread: Gva::new(thread),
+                current_process: Some("System".into()),
+                rip: Some(Gva::new(NT + 0x10)),
+                symbolized: Some("nt+0x10".into()),
+                idle: false,
+            },
+            CpuState {
+                processor: 2,
+                current_thread: Gva::new(0),
+                current_process: None,
+                rip: None,
+                symbolized: None,
+                idle: false,
+            }
+        ]);
+        assert_eq!(
+            summary[1].to_string(),
+            "5: thread Gva:0xfffff80003000400 in System at nt+0x10"
+        );
+    }
+
+    #[test]
+    fn fallbacks() {
+        // Without a KDDEBUGGER_DATA_BLOCK, the only processor is the one that
+        // crashed and the IRQL is in the bugcheck parameters.
+        let dump = DumpBuilder::new()
+            .bug_check(0xd1, [0xfffff, 2, 0, 0xffff_f805_1234_5678])
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+        assert_eq!(parser.crashing_prcb(), None);
+        assert_eq!(parser.crashing_processor(), Some(0));
+        assert_eq!(parser.crash_irql(), Some(2));
+
+        let dump = DumpBuilder::new()
+            .processors(2)
+            .bug_check(0x7e, [0xc0000005, 0, 0, 0])
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+        assert_eq!(parser.crashing_processor(), None);
+        assert_eq!(parser.crash_irql(), None);
+    }
+}
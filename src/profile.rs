@@ -0,0 +1,1407 @@
+//! This contains [`Profile`], which describes the layout of the kernel
+//! structures (`nt!_EPROCESS`, `nt!_MMPFN`, etc.) the parser needs to walk.
+//!
+//! The default profile describes Windows 10 / 11 x64 (build 19041 and
+//! above); some of its offsets are refined using the KDDEBUGGER_DATA_BLOCK when
+//! the dump has one. Older builds only get the layouts that haven't changed
+//! across builds, plus the fields the KDDEBUGGER_DATA_BLOCK has: reading the
+//! others fails with [`KdmpParserError::ProfileMissing`] until they are added,
+//! with [`Profile::set_layout`] or from JSON with the `json` feature.
+//!
+//! # Examples
+//!
+//! ```
+//! # use kdmp_parser::{FieldKind, Profile, StructLayout};
+//! let mut profile = Profile::new(19_041);
+//! assert_eq!(profile.offset("_EPROCESS", "ActiveProcessLinks").unwrap(), 0x448);
+//!
+//! profile.set_layout(
+//!     "_MY_DRIVER_CONTEXT",
+//!     StructLayout::new(0x10).with_field("Next", 0x8, FieldKind::Pointer),
+//! );
+//! assert_eq!(profile.offset("_MY_DRIVER_CONTEXT", "Next").unwrap(), 0x8);
+//! ```
+//!
+//! The structures the profile describes can be read as a whole, which is
+//! handy to walk the ones there is no dedicated API for:
+//!
+//! ```no_run
+//! # use kdmp_parser::{Gva, KernelDumpParser};
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! let process = parser.read_struct("_EPROCESS", Gva::new(0xffffb083_5d2b2080)).unwrap();
+//! for (name, value) in &process.fields {
+//!     println!("{name}: {value:x?}");
+//! }
+//! ```
+use std::collections::{BTreeMap, HashMap};
+
+use crate::error::Result;
+use crate::gxa::{Gpa, Gxa};
+use crate::structs::{FromLeBytes, KdDebuggerData64, UnicodeString};
+use crate::{Gva, KdmpParserError, KernelDumpParser};
+
+/// The first build the default layouts are known to be right for.
+const DEFAULT_PROFILE_BUILD: u32 = 19_041;
+
+/// The layouts of the default profile that are the same for the builds older
+/// than [`DEFAULT_PROFILE_BUILD`].
+const BUILD_INDEPENDENT_LAYOUTS: [&str; 5] = [
+    "_SID_AND_ATTRIBUTES",
+    "_UNLOADED_DRIVERS",
+    "_PEB32",
+    "_PEB_LDR_DATA32",
+    "_LDR_DATA_TABLE_ENTRY32",
+];
+
+/// The type of a field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldKind {
+    U8,
+    U16,
+    U32,
+    U64,
+    /// A 64-bit pointer.
+    Pointer,
+    /// An embedded `UNICODE_STRING`.
+    UnicodeString,
+    /// An embedded `LIST_ENTRY`.
+    ListEntry,
+    /// A fixed size array.
+    Array {
+        kind: Box<FieldKind>,
+        count: usize,
+    },
+    /// A bitfield of `width` bits starting at bit `position` of an integer
+    /// that is `size` bytes long.
+    Bits {
+        size: u8,
+        position: u8,
+        width: u8,
+    },
+}
+
+impl FieldKind {
+    /// Get the size of an integer field (or a pointer, or a bitfield).
+    pub(crate) fn int_size(&self) -> Result<usize> {
+        match self {
+            Self::U8 => Ok(1),
+            Self::U16 => Ok(2),
+            Self::U32 => Ok(4),
+            Self::U64 | Self::Pointer => Ok(8),
+            Self::Bits { size, .. } => Ok(usize::from(*size).min(8)),
+            _ => Err(KdmpParserError::InvalidData("field isn't an integer")),
+        }
+    }
+
+    /// Get the size of a field, if it fits in the address space.
+    pub(crate) fn size(&self) -> Option<usize> {
+        match self {
+            Self::UnicodeString => Some(UnicodeString::SIZE),
+            Self::ListEntry => Some(0x10),
+            Self::Array { kind, count } => kind.size()?.checked_mul(*count),
+            _ => self.int_size().ok(),
+        }
+    }
+
+    /// Extract the value of an integer field out of its raw content.
+    fn decode(&self, raw: u64) -> u64 {
+        let Self::Bits {
+            position, width, ..
+        } = self
+        else {
+            return raw;
+        };
+
+        let value = raw >> position;
+        match width {
+            64.. => value,
+            _ => value & ((1 << width) - 1),
+        }
+    }
+}
+
+/// Where a field is in a structure and what its type is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldLayout {
+    pub offset: u64,
+    pub kind: FieldKind,
+}
+
+/// The layout of a structure.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct StructLayout {
+    /// Size of the structure in bytes.
+    pub size: u64,
+    /// The fields of the structure, by name.
+    pub fields: HashMap<String, FieldLayout>,
+}
+
+impl StructLayout {
+    /// Create a layout without any fields.
+    pub fn new(size: u64) -> Self {
+        Self {
+            size,
+            fields: HashMap::new(),
+        }
+    }
+
+    /// Add a field to the layout.
+    pub fn with_field(mut self, name: impl Into<String>, offset: u64, kind: FieldKind) -> Self {
+        self.fields
+            .insert(name.into(), FieldLayout { offset, kind });
+
+        self
+    }
+}
+
+/// The value of a field, decoded according to its [`FieldKind`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldValue {
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    Pointer(Gva),
+    /// An embedded `UNICODE_STRING`; `None` if its buffer isn't in the dump.
+    UnicodeString(Option<String>),
+    /// An embedded `LIST_ENTRY`.
+    ListEntry {
+        flink: Gva,
+        blink: Gva,
+    },
+    /// A fixed size array.
+    Array(Vec<FieldValue>),
+    /// A bitfield, shifted and masked.
+    Bits(u64),
+}
+
+impl FieldValue {
+    /// Get the value of an integer field (or a pointer, or a bitfield).
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Self::U8(value) => Some(value.into()),
+            Self::U16(value) => Some(value.into()),
+            Self::U32(value) => Some(value.into()),
+            Self::U64(value) | Self::Bits(value) => Some(value),
+            Self::Pointer(value) => Some(value.u64()),
+            _ => None,
+        }
+    }
+}
+
+/// A structure read according to its layout in the profile; see
+/// [`KernelDumpParser::read_struct`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructValue {
+    /// The name of the type of the structure, like `_EPROCESS`.
+    pub type_name: String,
+    /// Where the structure is.
+    pub address: Gva,
+    /// The values of the fields of the structure, by name.
+    pub fields: BTreeMap<String, FieldValue>,
+}
+
+impl StructValue {
+    /// Get the value of a field.
+    pub fn field(&self, field: &str) -> Result<&FieldValue> {
+        self.fields
+            .get(field)
+            .ok_or_else(|| missing(&self.type_name, Some(field)))
+    }
+}
+
+/// The layouts of the kernel structures for a given build.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    /// Build number of the operating system.
+    build: u32,
+    /// The layouts, by type name (`_EPROCESS`, etc.).
+    layouts: HashMap<String, StructLayout>,
+}
+
+impl Profile {
+    /// Create the default profile for `build`. The builds older than 19041
+    /// only get the layouts that don't depend on the build.
+    pub fn new(build: u32) -> Self {
+        use FieldKind as K;
+        let mut profile = Self::empty(build);
+
+        // ```text
+        // kd> dt nt!_EPROCESS Pcb.DirectoryTableBase UniqueProcessId ActiveProcessLinks Token Peb WoW64Process ImageFileName
+        //    +0x000 Pcb                    :
+        //       +0x028 DirectoryTableBase     : Uint8B
+        //    +0x440 UniqueProcessId        : Ptr64 Void
+        //    +0x448 ActiveProcessLinks     : _LIST_ENTRY
+        //    +0x4b8 Token                  : _EX_FAST_REF
+        //    +0x550 Peb                    : Ptr64 _PEB
+        //    +0x580 WoW64Process           : Ptr64 _EWOW64PROCESS
+        //    +0x5a8 ImageFileName          : [15] UChar
+        // ```
+        profile.set_layout(
+            "_EPROCESS",
+            StructLayout::new(0xa40)
+                .with_field("DirectoryTableBase", 0x28, K::U64)
+                .with_field("UniqueProcessId", 0x440, K::U64)
+                .with_field("ActiveProcessLinks", 0x448, K::ListEntry)
+                .with_field("Token", 0x4b8, K::Pointer)
+                .with_field("Peb", 0x550, K::Pointer)
+                .with_field("WoW64Process", 0x580, K::Pointer)
+                .with_field("ImageFileName", 0x5a8, K::Array {
+                    kind: Box::new(K::U8),
+                    count: 15,
+                }),
+        );
+
+        // ```text
+        // kd> dt nt!_TOKEN Privileges UserAndGroupCount UserAndGroups IntegrityLevelIndex
+        //    +0x040 Privileges       : _SEP_TOKEN_PRIVILEGES
+        //       +0x000 Present          : Uint8B
+        //       +0x008 Enabled          : Uint8B
+        //    +0x07c UserAndGroupCount : Uint4B
+        //    +0x098 UserAndGroups    : Ptr64 _SID_AND_ATTRIBUTES
+        //    +0x0d0 IntegrityLevelIndex : Uint4B
+        // kd> dt nt!_SID_AND_ATTRIBUTES
+        //    +0x000 Sid              : Ptr64 Void
+        //    +0x008 Attributes       : Uint4B
+        // ```
+        profile.set_layout(
+            "_TOKEN",
+            StructLayout::new(0x498)
+                .with_field("Privileges.Present", 0x40, K::U64)
+                .with_field("Privileges.Enabled", 0x48, K::U64)
+                .with_field("UserAndGroupCount", 0x7c, K::U32)
+                .with_field("UserAndGroups", 0x98, K::Pointer)
+                .with_field("IntegrityLevelIndex", 0xd0, K::U32),
+        );
+        profile.set_layout(
+            "_SID_AND_ATTRIBUTES",
+            StructLayout::new(0x10)
+                .with_field("Sid", 0x0, K::Pointer)
+                .with_field("Attributes", 0x8, K::U32),
+        );
+
+        // The fields of the module list entries that aren't in `LdrDataTableEntry`.
+        // User module entries don't have a `CheckSum`, and their load count is
+        // obsolete.
+        //
+        // ```text
+        // kd> dt nt!_KLDR_DATA_TABLE_ENTRY LoadCount CheckSum TimeDateStamp
+        //    +0x06c LoadCount        : Uint2B
+        //    +0x078 CheckSum         : Uint4B
+        //    +0x09c TimeDateStamp    : Uint4B
+        // kd> dt ntdll!_LDR_DATA_TABLE_ENTRY TimeDateStamp
+        //    +0x080 TimeDateStamp    : Uint4B
+        // ```
+        profile.set_layout(
+            "_KLDR_DATA_TABLE_ENTRY",
+            StructLayout::new(0xa0)
+                .with_field("LoadCount", 0x6c, K::U16)
+                .with_field("CheckSum", 0x78, K::U32)
+                .with_field("TimeDateStamp", 0x9c, K::U32),
+        );
+        profile.set_layout(
+            "_LDR_DATA_TABLE_ENTRY",
+            StructLayout::new(0x120).with_field("TimeDateStamp", 0x80, K::U32),
+        );
+
+        // ```text
+        // kd> dt nt!_UNLOADED_DRIVERS
+        //    +0x000 Name             : _UNICODE_STRING
+        //    +0x010 StartAddress     : Ptr64 Void
+        //    +0x018 EndAddress       : Ptr64 Void
+        //    +0x020 CurrentTime      : _LARGE_INTEGER
+        // ```
+        profile.set_layout(
+            "_UNLOADED_DRIVERS",
+            StructLayout::new(0x28)
+                .with_field("Name", 0x0, K::UnicodeString)
+                .with_field("StartAddress", 0x10, K::Pointer)
+                .with_field("EndAddress", 0x18, K::Pointer)
+                .with_field("CurrentTime", 0x20, K::U64),
+        );
+
+        // The 32-bit structures of the WOW64 processes.
+        //
+        // ```text
+        // kd> dt nt!_EWOW64PROCESS Peb
+        //    +0x000 Peb              : Ptr64 Void
+        // kd> dt nt!_PEB32 Ldr
+        //    +0x00c Ldr              : Uint4B
+        // kd> dt nt!_PEB_LDR_DATA32 InLoadOrderModuleList
+        //    +0x00c InLoadOrderModuleList : LIST_ENTRY32
+        // kd> dt nt!_LDR_DATA_TABLE_ENTRY32 DllBase EntryPoint SizeOfImage FullDllName BaseDllName TimeDateStamp
+        //    +0x018 DllBase          : Uint4B
+        //    +0x01c EntryPoint       : Uint4B
+        //    +0x020 SizeOfImage      : Uint4B
+        //    +0x024 FullDllName      : _STRING32
+        //       +0x000 Length           : Uint2B
+        //       +0x004 Buffer           : Uint4B
+        //    +0x02c BaseDllName      : _STRING32
+        //    +0x044 TimeDateStamp    : Uint4B
+        // ```
+        profile.set_layout(
+            "_EWOW64PROCESS",
+            StructLayout::new(0x10).with_field("Peb", 0x0, K::Pointer),
+        );
+        profile.set_layout(
+            "_PEB32",
+            StructLayout::new(0x480).with_field("Ldr", 0xc, K::U32),
+        );
+        profile.set_layout(
+            "_PEB_LDR_DATA32",
+            StructLayout::new(0x30).with_field("InLoadOrderModuleList.Flink", 0xc, K::U32),
+        );
+        profile.set_layout(
+            "_LDR_DATA_TABLE_ENTRY32",
+            StructLayout::new(0xa8)
+                .with_field("DllBase", 0x18, K::U32)
+                .with_field("EntryPoint", 0x1c, K::U32)
+                .with_field("SizeOfImage", 0x20, K::U32)
+                .with_field("FullDllName.Length", 0x24, K::U16)
+                .with_field("FullDllName.Buffer", 0x28, K::U32)
+                .with_field("BaseDllName.Length", 0x2c, K::U16)
+                .with_field("BaseDllName.Buffer", 0x30, K::U32)
+                .with_field("TimeDateStamp", 0x44, K::U32),
+        );
+
+        // ```text
+        // kd> dt nt!_MMPFN PteAddress OriginalPte u3.ReferenceCount u3.e1.PageLocation u4.PteFrame
+        //    +0x008 PteAddress       : Ptr64 _MMPTE
+        //    +0x010 OriginalPte      : _MMPTE
+        //    +0x020 u3               :
+        //       +0x000 ReferenceCount   : Uint2B
+        //       +0x002 e1               :
+        //          +0x000 PageLocation     : Pos 0, 3 Bits
+        //    +0x028 u4               :
+        //       +0x000 PteFrame         : Pos 0, 40 Bits
+        // ```
+        profile.set_layout(
+            "_MMPFN",
+            StructLayout::new(0x30)
+                .with_field("PteAddress", 0x8, K::Pointer)
+                .with_field("OriginalPte", 0x10, K::U64)
+                .with_field("ReferenceCount", 0x20, K::U16)
+                .with_field("PageLocation", 0x22, K::Bits {
+                    size: 1,
+                    position: 0,
+                    width: 3,
+                })
+                .with_field("PteFrame", 0x28, K::Bits {
+                    size: 8,
+                    position: 0,
+                    width: 40,
+                }),
+        );
+
+        // ```text
+        // kd> dt nt!_FILE_OBJECT Type Size FsContext SectionObjectPointer FileName Event.Header.WaitListHead IrpList
+        //    +0x000 Type             : Int2B
+        //    +0x002 Size             : Int2B
+        //    +0x018 FsContext        : Ptr64 Void
+        //    +0x028 SectionObjectPointer : Ptr64 _SECTION_OBJECT_POINTERS
+        //    +0x058 FileName         : _UNICODE_STRING
+        //    +0x098 Event            : _KEVENT
+        //       +0x000 Header           : _DISPATCHER_HEADER
+        //          +0x008 WaitListHead     : _LIST_ENTRY
+        //    +0x0c0 IrpList          : _LIST_ENTRY
+        // ```
+        profile.set_layout(
+            "_FILE_OBJECT",
+            StructLayout::new(0xd8)
+                .with_field("Type", 0x0, K::U16)
+                .with_field("Size", 0x2, K::U16)
+                .with_field("FsContext", 0x18, K::Pointer)
+                .with_field("SectionObjectPointer", 0x28, K::Pointer)
+                .with_field("FileName", 0x58, K::UnicodeString)
+                .with_field("Event.Header.WaitListHead", 0xa0, K::ListEntry)
+                .with_field("IrpList", 0xc0, K::ListEntry),
+        );
+
+        // ```text
+        // kd> dt nt!_FSRTL_COMMON_FCB_HEADER FileSize
+        //    +0x020 FileSize         : _LARGE_INTEGER
+        // ```
+        profile.set_layout(
+            "_FSRTL_COMMON_FCB_HEADER",
+            StructLayout::new(0x30).with_field("FileSize", 0x20, K::U64),
+        );
+
+        // ```text
+        // kd> dt nt!_SECTION_OBJECT_POINTERS
+        //    +0x000 DataSectionObject : Ptr64 Void
+        //    +0x008 SharedCacheMap   : Ptr64 Void
+        //    +0x010 ImageSectionObject : Ptr64 Void
+        // ```
+        profile.set_layout(
+            "_SECTION_OBJECT_POINTERS",
+            StructLayout::new(0x18)
+                .with_field("DataSectionObject", 0x0, K::Pointer)
+                .with_field("SharedCacheMap", 0x8, K::Pointer)
+                .with_field("ImageSectionObject", 0x10, K::Pointer),
+        );
+
+        // ```text
+        // kd> dt nt!_SHARED_CACHE_MAP NodeTypeCode FileSize SectionSize Vacbs
+        //    +0x000 NodeTypeCode     : Int2B
+        //    +0x008 FileSize         : _LARGE_INTEGER
+        //    +0x020 SectionSize      : _LARGE_INTEGER
+        //    +0x058 Vacbs            : Ptr64 Ptr64 _VACB
+        // ```
+        profile.set_layout(
+            "_SHARED_CACHE_MAP",
+            StructLayout::new(0x1f8)
+                .with_field("NodeTypeCode", 0x0, K::U16)
+                .with_field("FileSize", 0x8, K::U64)
+                .with_field("SectionSize", 0x20, K::U64)
+                .with_field("Vacbs", 0x58, K::Pointer),
+        );
+
+        // ```text
+        // kd> dt nt!_VACB
+        //    +0x000 BaseAddress      : Ptr64 Void
+        //    +0x008 SharedCacheMap   : Ptr64 _SHARED_CACHE_MAP
+        //    +0x010 Overlay          : <anonymous-tag>
+        //       +0x000 FileOffset       : _LARGE_INTEGER
+        // ```
+        profile.set_layout(
+            "_VACB",
+            StructLayout::new(0x28)
+                .with_field("BaseAddress", 0x0, K::Pointer)
+                .with_field("SharedCacheMap", 0x8, K::Pointer)
+                .with_field("FileOffset", 0x10, K::U64),
+        );
+
+        // The first `_SUBSECTION` of a file backed section directly follows its
+        // `_CONTROL_AREA`.
+        //
+        // ```text
+        // kd> dt nt!_CONTROL_AREA Segment
+        //    +0x000 Segment          : Ptr64 _SEGMENT
+        // ```
+        profile.set_layout(
+            "_CONTROL_AREA",
+            StructLayout::new(0x80).with_field("Segment", 0x0, K::Pointer),
+        );
+
+        // ```text
+        // kd> dt nt!_SUBSECTION ControlArea SubsectionBase NextSubsection StartingSector PtesInSubsection
+        //    +0x000 ControlArea      : Ptr64 _CONTROL_AREA
+        //    +0x008 SubsectionBase   : Ptr64 _MMPTE
+        //    +0x010 NextSubsection   : Ptr64 _SUBSECTION
+        //    +0x024 StartingSector   : Uint4B
+        //    +0x02c PtesInSubsection : Uint4B
+        // ```
+        profile.set_layout(
+            "_SUBSECTION",
+            StructLayout::new(0x38)
+                .with_field("ControlArea", 0x0, K::Pointer)
+                .with_field("SubsectionBase", 0x8, K::Pointer)
+                .with_field("NextSubsection", 0x10, K::Pointer)
+                .with_field("StartingSector", 0x24, K::U32)
+                .with_field("PtesInSubsection", 0x2c, K::U32),
+        );
+
+        // The size of `_KPRCB` varies a lot across builds, so it is only known
+        // once the KDDEBUGGER_DATA_BLOCK has been read.
+        //
+        // ```text
+        // kd> dt nt!_KPRCB CurrentThread IdleThread Number ProcessorState.SpecialRegisters.Cr0 ProcessorState.SpecialRegisters.Cr4 ProcessorState.SpecialRegisters.DebugControl ProcessorState.SpecialRegisters.Cr8 ProcessorState.SpecialRegisters.MsrGsBase ProcessorState.SpecialRegisters.MsrGsSwap ProcessorState.SpecialRegisters.MsrStar ProcessorState.SpecialRegisters.MsrLStar ProcessorState.SpecialRegisters.MsrCStar ProcessorState.SpecialRegisters.MsrSyscallMask ProcessorState.SpecialRegisters.MsrFsBase ProcessorState.ContextFrame.Rip ParentNode DpcData TimerTable.TimerEntries
+        //    +0x008 CurrentThread    : Ptr64 _KTHREAD
+        //    +0x018 IdleThread       : Ptr64 _KTHREAD
+        //    +0x024 Number           : Uint4B
+        //    +0x040 ProcessorState   : _KPROCESSOR_STATE
+        //       +0x000 SpecialRegisters : _KSPECIAL_REGISTERS
+        //          +0x000 Cr0              : Uint8B
+        //          +0x018 Cr4              : Uint8B
+        //          +0x078 DebugControl     : Uint8B
+        //          +0x0a0 Cr8              : Uint8B
+        //          +0x0a8 MsrGsBase        : Uint8B
+        //          +0x0b0 MsrGsSwap        : Uint8B
+        //          +0x0b8 MsrStar          : Uint8B
+        //          +0x0c0 MsrLStar         : Uint8B
+        //          +0x0c8 MsrCStar         : Uint8B
+        //          +0x0d0 MsrSyscallMask   : Uint8B
+        //          +0x0e0 MsrFsBase        : Uint8B
+        //       +0x0f0 ContextFrame     : _CONTEXT
+        //          +0x0f8 Rip              : Uint8B
+        //    +0x0c8 ParentNode       : Ptr64 _KNODE
+        //    +0x3200 DpcData          : [2] _KDPC_DATA
+        //    +0x3940 TimerTable       : _KTIMER_TABLE
+        //       +0x200 TimerEntries     : [2] [256] _KTIMER_TABLE_ENTRY
+        // kd> dt nt!_KDPC_DATA DpcList.ListHead
+        //    +0x000 DpcList          : _KDPC_LIST
+        //       +0x000 ListHead         : _SINGLE_LIST_ENTRY
+        // kd> dt nt!_KTIMER_TABLE_ENTRY Entry
+        //    +0x008 Entry            : _LIST_ENTRY
+        // ```
+        profile.set_layout(
+            "_KPRCB",
+            StructLayout::new(0)
+                .with_field("CurrentThread", 0x8, K::Pointer)
+                .with_field("IdleThread", 0x18, K::Pointer)
+                .with_field("Number", 0x24, K::U32)
+                .with_field("ProcessorState.SpecialRegisters.Cr0", 0x40, K::U64)
+                .with_field("ProcessorState.SpecialRegisters.Cr4", 0x58, K::U64)
+                .with_field("ProcessorState.SpecialRegisters.DebugControl", 0xb8, K::U64)
+                .with_field("ProcessorState.SpecialRegisters.Cr8", 0xe0, K::U64)
+                .with_field("ProcessorState.SpecialRegisters.MsrGsBase", 0xe8, K::U64)
+                .with_field("ProcessorState.SpecialRegisters.MsrGsSwap", 0xf0, K::U64)
+                .with_field("ProcessorState.SpecialRegisters.MsrStar", 0xf8, K::U64)
+                .with_field("ProcessorState.SpecialRegisters.MsrLStar", 0x100, K::U64)
+                .with_field("ProcessorState.SpecialRegisters.MsrCStar", 0x108, K::U64)
+                .with_field(
+                    "ProcessorState.SpecialRegisters.MsrSyscallMask",
+                    0x110,
+                    K::U64,
+                )
+                .with_field("ProcessorState.SpecialRegisters.MsrFsBase", 0x120, K::U64)
+                .with_field("ProcessorState.ContextFrame.Rip", 0x228, K::U64)
+                .with_field("ParentNode", 0xc8, K::Pointer)
+                .with_field("DpcData[0].DpcList.ListHead", 0x3200, K::Pointer)
+                .with_field("DpcData[1].DpcList.ListHead", 0x3228, K::Pointer)
+                .with_field("TimerTable.TimerEntries[0][0].Entry", 0x3b48, K::ListEntry),
+        );
+        profile.set_layout("_KTIMER_TABLE_ENTRY", StructLayout::new(0x20));
+
+        // The 32-bit TEB of a WOW64 thread is `WowTebOffset` bytes after its
+        // 64-bit TEB.
+        //
+        // ```text
+        // kd> dt nt!_TEB NtTib.StackBase NtTib.StackLimit NtTib.SubSystemTib LastErrorValue TlsSlots WowTebOffset
+        //    +0x000 NtTib            : _NT_TIB
+        //       +0x008 StackBase        : Ptr64 Void
+        //       +0x010 StackLimit       : Ptr64 Void
+        //       +0x018 SubSystemTib     : Ptr64 Void
+        //    +0x068 LastErrorValue   : Uint4B
+        //    +0x1480 TlsSlots         : [64] Ptr64 Void
+        //    +0x180c WowTebOffset     : Int4B
+        // kd> dt nt!_TEB32 NtTib.StackBase NtTib.StackLimit NtTib.SubSystemTib LastErrorValue TlsSlots
+        //    +0x000 NtTib            : _NT_TIB32
+        //       +0x004 StackBase        : Uint4B
+        //       +0x008 StackLimit       : Uint4B
+        //       +0x00c SubSystemTib     : Uint4B
+        //    +0x034 LastErrorValue   : Uint4B
+        //    +0xe10 TlsSlots         : [64] Uint4B
+        // ```
+        profile.set_layout(
+            "_TEB",
+            StructLayout::new(0x1838)
+                .with_field("NtTib.StackBase", 0x8, K::Pointer)
+                .with_field("NtTib.StackLimit", 0x10, K::Pointer)
+                .with_field("NtTib.SubSystemTib", 0x18, K::Pointer)
+                .with_field("LastErrorValue", 0x68, K::U32)
+                .with_field("TlsSlots", 0x1480, K::Array {
+                    kind: Box::new(K::Pointer),
+                    count: 64,
+                })
+                .with_field("WowTebOffset", 0x180c, K::U32),
+        );
+        profile.set_layout(
+            "_TEB32",
+            StructLayout::new(0x1000)
+                .with_field("NtTib.StackBase", 0x4, K::U32)
+                .with_field("NtTib.StackLimit", 0x8, K::U32)
+                .with_field("NtTib.SubSystemTib", 0xc, K::U32)
+                .with_field("LastErrorValue", 0x34, K::U32)
+                .with_field("TlsSlots", 0xe10, K::Array {
+                    kind: Box::new(K::U32),
+                    count: 64,
+                }),
+        );
+
+        // The heaps of the processes: the PEB points to them, and they are
+        // either NT heaps or segment heaps.
+        //
+        // ```text
+        // kd> dt nt!_PEB NumberOfHeaps ProcessHeaps
+        //    +0x0e8 NumberOfHeaps    : Uint4B
+        //    +0x0f0 ProcessHeaps     : Ptr64 Ptr64 Void
+        // kd> dt ntdll!_HEAP_SEGMENT SegmentSignature SegmentListEntry BaseAddress NumberOfPages NumberOfUnCommittedPages
+        //    +0x010 SegmentSignature : Uint4B
+        //    +0x018 SegmentListEntry : _LIST_ENTRY
+        //    +0x030 BaseAddress      : Ptr64 Void
+        //    +0x038 NumberOfPages    : Uint4B
+        //    +0x050 NumberOfUnCommittedPages : Uint4B
+        // kd> dt ntdll!_HEAP SegmentList
+        //    +0x120 SegmentList      : _LIST_ENTRY
+        // kd> dt ntdll!_SEGMENT_HEAP Signature MemStats.TotalCommittedPages SegContexts
+        //    +0x010 Signature        : Uint4B
+        //    +0x0c0 MemStats         : _HEAP_RUNTIME_MEMORY_STATS
+        //       +0x008 TotalCommittedPages : Uint8B
+        //    +0x100 SegContexts      : [2] _HEAP_SEG_CONTEXT
+        // kd> dt ntdll!_HEAP_SEG_CONTEXT SegmentMask SegmentListHead
+        //    +0x000 SegmentMask      : Uint8B
+        //    +0x048 SegmentListHead  : _LIST_ENTRY
+        // kd> ?? sizeof(ntdll!_HEAP_SEG_CONTEXT)
+        // unsigned int64 0xc0
+        // ```
+        profile.set_layout(
+            "_PEB",
+            StructLayout::new(0x7c8)
+                .with_field("NumberOfHeaps", 0xe8, K::U32)
+                .with_field("ProcessHeaps", 0xf0, K::Pointer),
+        );
+        profile.set_layout(
+            "_HEAP_SEGMENT",
+            StructLayout::new(0x70)
+                .with_field("SegmentSignature", 0x10, K::U32)
+                .with_field("SegmentListEntry", 0x18, K::ListEntry)
+                .with_field("BaseAddress", 0x30, K::Pointer)
+                .with_field("NumberOfPages", 0x38, K::U32)
+                .with_field("NumberOfUnCommittedPages", 0x50, K::U32),
+        );
+        profile.set_layout(
+            "_HEAP",
+            StructLayout::new(0x2c0).with_field("SegmentList", 0x120, K::ListEntry),
+        );
+        profile.set_layout(
+            "_SEGMENT_HEAP",
+            StructLayout::new(0x800)
+                .with_field("Signature", 0x10, K::U32)
+                .with_field("MemStats.TotalCommittedPages", 0xc8, K::U64)
+                .with_field("SegContexts[0].SegmentMask", 0x100, K::U64)
+                .with_field("SegContexts[0].SegmentListHead", 0x148, K::ListEntry)
+                .with_field("SegContexts[1].SegmentMask", 0x1c0, K::U64)
+                .with_field("SegContexts[1].SegmentListHead", 0x208, K::ListEntry),
+        );
+
+        // ```text
+        // kd> dt nt!_KDPC Type Importance DpcListEntry DeferredRoutine DeferredContext
+        //    +0x000 Type             : UChar
+        //    +0x001 Importance       : UChar
+        //    +0x008 DpcListEntry     : _SINGLE_LIST_ENTRY
+        //    +0x018 DeferredRoutine  : Ptr64     void
+        //    +0x020 DeferredContext  : Ptr64 Void
+        // kd> dt nt!_KTIMER DueTime TimerListEntry Dpc Period
+        //    +0x018 DueTime          : _ULARGE_INTEGER
+        //    +0x020 TimerListEntry   : _LIST_ENTRY
+        //    +0x030 Dpc              : Ptr64 _KDPC
+        //    +0x03c Period           : Uint4B
+        // ```
+        profile.set_layout(
+            "_KDPC",
+            StructLayout::new(0x40)
+                .with_field("Type", 0x0, K::U8)
+                .with_field("Importance", 0x1, K::U8)
+                .with_field("DpcListEntry", 0x8, K::Pointer)
+                .with_field("DeferredRoutine", 0x18, K::Pointer)
+                .with_field("DeferredContext", 0x20, K::Pointer),
+        );
+        profile.set_layout(
+            "_KTIMER",
+            StructLayout::new(0x40)
+                .with_field("DueTime", 0x18, K::U64)
+                .with_field("TimerListEntry", 0x20, K::ListEntry)
+                .with_field("Dpc", 0x30, K::Pointer)
+                .with_field("Period", 0x3c, K::U32),
+        );
+
+        // ```text
+        // kd> dt nt!_KTHREAD StackLimit StackBase KernelStack TrapFrame ApcState.ApcListHead ApcState.Process Teb Process
+        //    +0x030 StackLimit       : Ptr64 Void
+        //    +0x038 StackBase        : Ptr64 Void
+        //    +0x058 KernelStack      : Ptr64 Void
+        //    +0x090 TrapFrame        : Ptr64 _KTRAP_FRAME
+        //    +0x098 ApcState         : _KAPC_STATE
+        //       +0x000 ApcListHead      : [2] _LIST_ENTRY
+        //       +0x020 Process          : Ptr64 _KPROCESS
+        //    +0x0f0 Teb              : Ptr64 Void
+        //    +0x220 Process          : Ptr64 _KPROCESS
+        // kd> dt nt!_KTRAP_FRAME Rax Rcx Rdx R8 R9 R10 R11 Rbp Rip SegCs EFlags Rsp SegSs
+        //    +0x038 Rax              : Uint8B
+        //    +0x040 Rcx              : Uint8B
+        //    +0x048 Rdx              : Uint8B
+        //    +0x050 R8               : Uint8B
+        //    +0x058 R9               : Uint8B
+        //    +0x060 R10              : Uint8B
+        //    +0x068 R11              : Uint8B
+        //    +0x158 Rbp              : Uint8B
+        //    +0x168 Rip              : Uint8B
+        //    +0x170 SegCs            : Uint2B
+        //    +0x178 EFlags           : Uint4B
+        //    +0x180 Rsp              : Uint8B
+        //    +0x188 SegSs            : Uint2B
+        // kd> dt nt!_KAPC ApcListEntry KernelRoutine NormalRoutine ApcMode
+        //    +0x010 ApcListEntry     : _LIST_ENTRY
+        //    +0x020 KernelRoutine    : Ptr64     void
+        //    +0x030 NormalRoutine    : Ptr64     void
+        //    +0x051 ApcMode          : Char
+        // ```
+        profile.set_layout(
+            "_KTHREAD",
+            StructLayout::new(0x430)
+                .with_field("StackLimit", 0x30, K::Pointer)
+                .with_field("StackBase", 0x38, K::Pointer)
+                .with_field("KernelStack", 0x58, K::Pointer)
+                .with_field("TrapFrame", 0x90, K::Pointer)
+                .with_field("ApcState.ApcListHead[0]", 0x98, K::ListEntry)
+                .with_field("ApcState.ApcListHead[1]", 0xa8, K::ListEntry)
+                .with_field("ApcState.Process", 0xb8, K::Pointer)
+                .with_field("Teb", 0xf0, K::Pointer)
+                .with_field("Process", 0x220, K::Pointer),
+        );
+        profile.set_layout(
+            "_KTRAP_FRAME",
+            StructLayout::new(0x190)
+                .with_field("Rax", 0x38, K::U64)
+                .with_field("Rcx", 0x40, K::U64)
+                .with_field("Rdx", 0x48, K::U64)
+                .with_field("R8", 0x50, K::U64)
+                .with_field("R9", 0x58, K::U64)
+                .with_field("R10", 0x60, K::U64)
+                .with_field("R11", 0x68, K::U64)
+                .with_field("Rbp", 0x158, K::U64)
+                .with_field("Rip", 0x168, K::U64)
+                .with_field("SegCs", 0x170, K::U16)
+                .with_field("EFlags", 0x178, K::U32)
+                .with_field("Rsp", 0x180, K::U64)
+                .with_field("SegSs", 0x188, K::U16),
+        );
+        profile.set_layout(
+            "_KAPC",
+            StructLayout::new(0x58)
+                .with_field("ApcListEntry", 0x10, K::ListEntry)
+                .with_field("KernelRoutine", 0x20, K::Pointer)
+                .with_field("NormalRoutine", 0x30, K::Pointer)
+                .with_field("ApcMode", 0x51, K::U8),
+        );
+
+        // The work queues hang off the node of the processors; `_KNODE` is the
+        // first field of `_ENODE`, whose size isn't needed.
+        //
+        // ```text
+        // kd> dt nt!_ENODE ExWorkQueue.WorkPriQueue.EntryListHead
+        //    +0x100 ExWorkQueue      : _EX_WORK_QUEUE
+        //       +0x000 WorkPriQueue     : _KPRIQUEUE
+        //          +0x018 EntryListHead    : [32] _LIST_ENTRY
+        // kd> dt nt!_WORK_QUEUE_ITEM
+        //    +0x000 List             : _LIST_ENTRY
+        //    +0x010 WorkerRoutine    : Ptr64     void
+        //    +0x018 Parameter        : Ptr64 Void
+        // kd> dt nt!_IO_WORKITEM Routine Context
+        //    +0x020 Routine          : Ptr64     void
+        //    +0x030 Context          : Ptr64 Void
+        // ```
+        profile.set_layout(
+            "_ENODE",
+            StructLayout::new(0).with_field(
+                "ExWorkQueue.WorkPriQueue.EntryListHead[0]",
+                0x118,
+                K::ListEntry,
+            ),
+        );
+        profile.set_layout(
+            "_WORK_QUEUE_ITEM",
+            StructLayout::new(0x20)
+                .with_field("List", 0x0, K::ListEntry)
+                .with_field("WorkerRoutine", 0x10, K::Pointer)
+                .with_field("Parameter", 0x18, K::Pointer),
+        );
+        profile.set_layout(
+            "_IO_WORKITEM",
+            StructLayout::new(0x58)
+                .with_field("Routine", 0x20, K::Pointer)
+                .with_field("Context", 0x30, K::Pointer),
+        );
+
+        // The network structures are private to `tcpip.sys`.
+        //
+        // ```text
+        // kd> dt tcpip!_TCP_ENDPOINT InetAF AddrInfo State LocalPort RemotePort Owner
+        //    +0x010 InetAF           : Ptr64 _INETAF
+        //    +0x018 AddrInfo         : Ptr64 _ADDRINFO
+        //    +0x06c State            : _TCP_STATE
+        //    +0x070 LocalPort        : Uint2B
+        //    +0x072 RemotePort       : Uint2B
+        //    +0x2d8 Owner            : Ptr64 _EPROCESS
+        // ```
+        profile.set_layout(
+            "_TCP_ENDPOINT",
+            StructLayout::new(0x2f0)
+                .with_field("InetAF", 0x10, K::Pointer)
+                .with_field("AddrInfo", 0x18, K::Pointer)
+                .with_field("State", 0x6c, K::U32)
+                .with_field("LocalPort", 0x70, K::U16)
+                .with_field("RemotePort", 0x72, K::U16)
+                .with_field("Owner", 0x2d8, K::Pointer),
+        );
+
+        // ```text
+        // kd> dt tcpip!_TCP_LISTENER Owner InetAF LocalAddr Port
+        //    +0x028 InetAF           : Ptr64 _INETAF
+        //    +0x030 Owner            : Ptr64 _EPROCESS
+        //    +0x060 LocalAddr        : Ptr64 _LOCAL_ADDRESS_WIN10_UDP
+        //    +0x072 Port             : Uint2B
+        // ```
+        profile.set_layout(
+            "_TCP_LISTENER",
+            StructLayout::new(0x78)
+                .with_field("InetAF", 0x28, K::Pointer)
+                .with_field("Owner", 0x30, K::Pointer)
+                .with_field("LocalAddr", 0x60, K::Pointer)
+                .with_field("Port", 0x72, K::U16),
+        );
+
+        // ```text
+        // kd> dt tcpip!_UDP_ENDPOINT InetAF Owner LocalAddr Port
+        //    +0x020 InetAF           : Ptr64 _INETAF
+        //    +0x028 Owner            : Ptr64 _EPROCESS
+        //    +0x080 LocalAddr        : Ptr64 _LOCAL_ADDRESS_WIN10_UDP
+        //    +0x0a0 Port             : Uint2B
+        // ```
+        profile.set_layout(
+            "_UDP_ENDPOINT",
+            StructLayout::new(0xa8)
+                .with_field("InetAF", 0x20, K::Pointer)
+                .with_field("Owner", 0x28, K::Pointer)
+                .with_field("LocalAddr", 0x80, K::Pointer)
+                .with_field("Port", 0xa0, K::U16),
+        );
+
+        // ```text
+        // kd> dt tcpip!_INETAF AddressFamily
+        //    +0x018 AddressFamily    : Uint2B
+        // kd> dt tcpip!_ADDRINFO
+        //    +0x000 Local            : Ptr64 _LOCAL_ADDRESS
+        //    +0x010 Remote           : Ptr64 _IN_ADDR
+        // kd> dt tcpip!_LOCAL_ADDRESS pData
+        //    +0x010 pData            : Ptr64 Ptr64 _IN_ADDR
+        // kd> dt tcpip!_LOCAL_ADDRESS_WIN10_UDP pData
+        //    +0x000 pData            : Ptr64 _IN_ADDR
+        // ```
+        profile.set_layout(
+            "_INETAF",
+            StructLayout::new(0x20).with_field("AddressFamily", 0x18, K::U16),
+        );
+        profile.set_layout(
+            "_ADDRINFO",
+            StructLayout::new(0x18)
+                .with_field("Local", 0x0, K::Pointer)
+                .with_field("Remote", 0x10, K::Pointer),
+        );
+        profile.set_layout(
+            "_LOCAL_ADDRESS",
+            StructLayout::new(0x18).with_field("pData", 0x10, K::Pointer),
+        );
+        profile.set_layout(
+            "_LOCAL_ADDRESS_WIN10_UDP",
+            StructLayout::new(0x8).with_field("pData", 0x0, K::Pointer),
+        );
+
+        // ```text
+        // kd> dt nt!_POOL_TRACKER_BIG_PAGES
+        //    +0x000 Va               : Uint8B
+        //    +0x008 Key              : Uint4B
+        //    +0x00c Pattern          : Pos 0, 8 Bits
+        //    +0x00c PoolType         : Pos 8, 12 Bits
+        //    +0x00c SlushSize        : Pos 20, 12 Bits
+        //    +0x010 NumberOfBytes    : Uint8B
+        //    +0x018 ProcessBilled    : Ptr64 _EPROCESS
+        // ```
+        profile.set_layout(
+            "_POOL_TRACKER_BIG_PAGES",
+            StructLayout::new(0x20)
+                .with_field("Va", 0x0, K::U64)
+                .with_field("Key", 0x8, K::U32)
+                .with_field("PoolType", 0xc, K::Bits {
+                    size: 4,
+                    position: 8,
+                    width: 12,
+                })
+                .with_field("NumberOfBytes", 0x10, K::U64),
+        );
+
+        if build < DEFAULT_PROFILE_BUILD {
+            profile
+                .layouts
+                .retain(|type_name, _| BUILD_INDEPENDENT_LAYOUTS.contains(&type_name.as_str()));
+        }
+
+        profile
+    }
+
+    /// Create a profile for `build` without any layouts.
+    pub(crate) fn empty(build: u32) -> Self {
+        Self {
+            build,
+            layouts: HashMap::new(),
+        }
+    }
+
+    /// Refine the profile with the offsets the KDDEBUGGER_DATA_BLOCK has.
+    pub(crate) fn apply_kd_debugger_data_block(&mut self, kdbg: &KdDebuggerData64) {
+        // The KDDEBUGGER_DATA_BLOCK has the offsets of the special registers and
+        // of the context, not of the fields we read in them.
+        //
+        // ```text
+        // kd> dt nt!_KSPECIAL_REGISTERS Cr0 Cr4 Cr8 MsrGsBase MsrGsSwap MsrFsBase
+        //    +0x000 Cr0              : Uint8B
+        //    +0x018 Cr4              : Uint8B
+        //    +0x0a0 Cr8              : Uint8B
+        //    +0x0a8 MsrGsBase        : Uint8B
+        //    +0x0b0 MsrGsSwap        : Uint8B
+        //    +0x0e0 MsrFsBase        : Uint8B
+        // kd> dt nt!_CONTEXT Rip
+        //    +0x0f8 Rip              : Uint8B
+        // ```
+        let within = |offset: u16, field: u64| match offset {
+            0 => 0,
+            offset => u64::from(offset) + field,
+        };
+        let mut template = None;
+
+        for (type_name, size, fields) in [
+            ("_EPROCESS", kdbg.size_eprocess, vec![
+                (
+                    "DirectoryTableBase",
+                    u64::from(kdbg.offset_eprocess_directory_table_base),
+                ),
+                ("Peb", kdbg.offset_eprocess_peb.into()),
+            ]),
+            ("_KPRCB", kdbg.size_prcb, vec![
+                ("CurrentThread", kdbg.offset_prcb_current_thread.into()),
+                ("Number", kdbg.offset_prcb_number.into()),
+                (
+                    "ProcessorState.SpecialRegisters.Cr0",
+                    within(kdbg.offset_prcb_proc_state_special_reg, 0x0),
+                ),
+                (
+                    "ProcessorState.SpecialRegisters.Cr4",
+                    within(kdbg.offset_prcb_proc_state_special_reg, 0x18),
+                ),
+                (
+                    "ProcessorState.SpecialRegisters.DebugControl",
+                    within(kdbg.offset_prcb_proc_state_special_reg, 0x78),
+                ),
+                (
+                    "ProcessorState.SpecialRegisters.Cr8",
+                    within(kdbg.offset_prcb_proc_state_special_reg, 0xa0),
+                ),
+                (
+                    "ProcessorState.SpecialRegisters.MsrGsBase",
+                    within(kdbg.offset_prcb_proc_state_special_reg, 0xa8),
+                ),
+                (
+                    "ProcessorState.SpecialRegisters.MsrGsSwap",
+                    within(kdbg.offset_prcb_proc_state_special_reg, 0xb0),
+                ),
+                (
+                    "ProcessorState.SpecialRegisters.MsrStar",
+                    within(kdbg.offset_prcb_proc_state_special_reg, 0xb8),
+                ),
+                (
+                    "ProcessorState.SpecialRegisters.MsrLStar",
+                    within(kdbg.offset_prcb_proc_state_special_reg, 0xc0),
+                ),
+                (
+                    "ProcessorState.SpecialRegisters.MsrCStar",
+                    within(kdbg.offset_prcb_proc_state_special_reg, 0xc8),
+                ),
+                (
+                    "ProcessorState.SpecialRegisters.MsrSyscallMask",
+                    within(kdbg.offset_prcb_proc_state_special_reg, 0xd0),
+                ),
+                (
+                    "ProcessorState.SpecialRegisters.MsrFsBase",
+                    within(kdbg.offset_prcb_proc_state_special_reg, 0xe0),
+                ),
+                (
+                    "ProcessorState.ContextFrame.Rip",
+                    within(kdbg.offset_prcb_proc_state_context, 0xf8),
+                ),
+            ]),
+            ("_KTHREAD", 0, vec![(
+                "ApcState.Process",
+                kdbg.offset_kthread_apc_process.into(),
+            )]),
+        ] {
+            let Some(layout) = self.layouts.get_mut(type_name) else {
+                // If the profile doesn't know about the structure (the build is too
+                // old), the fields are created with the types they have in the
+                // default profile.
+                let known = fields
+                    .iter()
+                    .filter(|(_, offset)| *offset != 0)
+                    .filter_map(|(name, offset)| {
+                        let kind = template
+                            .get_or_insert_with(|| Self::new(DEFAULT_PROFILE_BUILD))
+                            .field(type_name, name)
+                            .ok()?
+                            .kind
+                            .clone();
+
+                        Some((name.to_string(), FieldLayout {
+                            offset: *offset,
+                            kind,
+                        }))
+                    })
+                    .collect::<HashMap<_, _>>();
+
+                if known.is_empty() {
+                    continue;
+                }
+
+                self.set_layout(type_name, StructLayout {
+                    size: size.into(),
+                    fields: known,
+                });
+                continue;
+            };
+
+            if size != 0 {
+                layout.size = size.into();
+            }
+
+            for (name, offset) in fields {
+                if let (Some(field), 1..) = (layout.fields.get_mut(name), offset) {
+                    field.offset = offset;
+                }
+            }
+        }
+    }
+
+    /// Build number the profile is for.
+    pub fn build(&self) -> u32 {
+        self.build
+    }
+
+    /// Get the layout of a structure.
+    pub fn layout(&self, type_name: &str) -> Option<&StructLayout> {
+        self.layouts.get(type_name)
+    }
+
+    /// Add or replace the layout of a structure.
+    pub fn set_layout(&mut self, type_name: impl Into<String>, layout: StructLayout) {
+        self.layouts.insert(type_name.into(), layout);
+    }
+
+    /// Get the layout of a field.
+    pub fn field(&self, type_name: &str, field: &str) -> Result<&FieldLayout> {
+        self.layout(type_name)
+            .ok_or_else(|| missing(type_name, None))?
+            .fields
+            .get(field)
+            .ok_or_else(|| missing(type_name, Some(field)))
+    }
+
+    /// Get the offset of a field.
+    pub fn offset(&self, type_name: &str, field: &str) -> Result<u64> {
+        self.field(type_name, field).map(|field| field.offset)
+    }
+
+    /// Decode an integer field (or a pointer, or a bitfield) out of `data`,
+    /// which is the content of a `type_name` structure.
+    pub(crate) fn decode_field(&self, data: &[u8], type_name: &str, field: &str) -> Result<u64> {
+        let layout = self.field(type_name, field)?;
+        let size = layout.kind.int_size()?;
+        let bytes = usize::try_from(layout.offset)
+            .ok()
+            .and_then(|offset| data.get(offset..offset.checked_add(size)?))
+            .ok_or(KdmpParserError::InvalidData("field is out of bounds"))?;
+        let mut buffer = [0; 8];
+        buffer[..size].copy_from_slice(bytes);
+
+        Ok(layout.kind.decode(u64::from_le_bytes(buffer)))
+    }
+
+    /// Get the size of a structure.
+    pub fn size(&self, type_name: &str) -> Result<u64> {
+        self.layout(type_name)
+            .map(|layout| layout.size)
+            .ok_or_else(|| missing(type_name, None))
+    }
+}
+
+/// Build the error for a type or a field missing from the profile.
+fn missing(type_name: &str, field: Option<&str>) -> KdmpParserError {
+    KdmpParserError::ProfileMissing {
+        type_name: type_name.into(),
+        field: field.map(Into::into),
+    }
+}
+
+impl KernelDumpParser {
+    /// Read the `type_name` structure at `gva`, and decode all the fields its
+    /// layout in the [`Profile`] has. The structure is read at once, with the
+    /// fields that are past its size if it has any, so it has to be entirely
+    /// in the dump.
+    pub fn read_struct(&self, type_name: &str, gva: Gva) -> Result<StructValue> {
+        let layout = self
+            .profile()
+            .layout(type_name)
+            .ok_or_else(|| missing(type_name, None))?;
+
+        let mut len = layout.size;
+        for field in layout.fields.values() {
+            let end = field
+                .kind
+                .size()
+                .and_then(|size| field.offset.checked_add(size as u64));
+
+            len = len.max(end.ok_or(KdmpParserError::Overflow("profile field"))?);
+        }
+
+        let mut buffer = vec![0; self.check_read_size(len)?];
+        self.virt_read_exact(gva, &mut buffer)?;
+
+        let mut fields = BTreeMap::new();
+        for (name, field) in &layout.fields {
+            // Both fit as the whole structure has been read.
+            let offset = field.offset as usize;
+            let size = field.kind.size().unwrap_or_default();
+            let value = self.field_value(&field.kind, &buffer[offset..offset + size])?;
+            fields.insert(name.clone(), value);
+        }
+
+        Ok(StructValue {
+            type_name: type_name.into(),
+            address: gva,
+            fields,
+        })
+    }
+
+    /// Decode the value of a `kind` field out of its content, `bytes`.
+    fn field_value(&self, kind: &FieldKind, bytes: &[u8]) -> Result<FieldValue> {
+        let int = |bytes: &[u8]| {
+            let mut buffer = [0; 8];
+            buffer[..bytes.len()].copy_from_slice(bytes);
+
+            u64::from_le_bytes(buffer)
+        };
+
+        Ok(match kind {
+            FieldKind::U8 => FieldValue::U8(bytes[0]),
+            FieldKind::U16 => FieldValue::U16(int(bytes) as u16),
+            FieldKind::U32 => FieldValue::U32(int(bytes) as u32),
+            FieldKind::U64 => FieldValue::U64(int(bytes)),
+            FieldKind::Pointer => FieldValue::Pointer(Gva::new(int(bytes))),
+            FieldKind::Bits { .. } => FieldValue::Bits(kind.decode(int(bytes))),
+            FieldKind::UnicodeString => FieldValue::UnicodeString(
+                self.try_virt_read_unicode_string(&UnicodeString::from_le_bytes(bytes))?,
+            ),
+            FieldKind::ListEntry => FieldValue::ListEntry {
+                flink: Gva::new(int(&bytes[..8])),
+                blink: Gva::new(int(&bytes[8..])),
+            },
+            FieldKind::Array { kind, count } => {
+                let size = kind.size().unwrap_or_default();
+                FieldValue::Array(
+                    (0..*count)
+                        .map(|idx| self.field_value(kind, &bytes[idx * size..(idx + 1) * size]))
+                        .collect::<Result<_>>()?,
+                )
+            }
+        })
+    }
+
+    /// Compute the address of the field `field` of the `type_name` structure
+    /// at `base`.
+    pub(crate) fn field_addr(&self, base: Gva, type_name: &str, field: &str) -> Result<Gva> {
+        base.u64()
+            .checked_add(self.profile().offset(type_name, field)?)
+            .map(Gva::new)
+            .ok_or(KdmpParserError::Overflow("profile field"))
+    }
+
+    /// Read an integer field (or a pointer, or a bitfield) of the `type_name`
+    /// structure at `base`.
+    pub(crate) fn read_field(&self, base: Gva, type_name: &str, field: &str) -> Result<u64> {
+        let dtb = self.default_dtb();
+
+        self.read_field_with_dtb(base, type_name, field, dtb)
+    }
+
+    /// Read an integer field (or a pointer, or a bitfield) of the `type_name`
+    /// structure at `base` using a specific directory table base.
+    pub(crate) fn read_field_with_dtb(
+        &self,
+        base: Gva,
+        type_name: &str,
+        field: &str,
+        dtb: Gpa,
+    ) -> Result<u64> {
+        let layout = self.profile().field(type_name, field)?;
+        let mut buffer = [0; 8];
+        let size = layout.kind.int_size()?;
+        self.virt_read_exact_with_dtb(
+            self.field_addr(base, type_name, field)?,
+            &mut buffer[..size],
+            dtb,
+        )?;
+
+        Ok(layout.kind.decode(u64::from_le_bytes(buffer)))
+    }
+
+    /// Read an array of integers (or pointers) field of the `type_name`
+    /// structure at `base` using a specific directory table base.
+    pub(crate) fn read_array_with_dtb(
+        &self,
+        base: Gva,
+        type_name: &str,
+        field: &str,
+        dtb: Gpa,
+    ) -> Result<Vec<u64>> {
+        let FieldKind::Array { kind, count } = &self.profile().field(type_name, field)?.kind else {
+            return Err(KdmpParserError::InvalidData("field isn't an array"));
+        };
+
+        let size = kind.int_size()?;
+        let len = size
+            .checked_mul(*count)
+            .ok_or(KdmpParserError::Overflow("array field"))?;
+        let mut buffer = vec![0; self.check_read_size(len as u64)?];
+        self.virt_read_exact_with_dtb(self.field_addr(base, type_name, field)?, &mut buffer, dtb)?;
+
+        Ok(buffer
+            .chunks_exact(size)
+            .map(|chunk| {
+                let mut element = [0; 8];
+                element[..size].copy_from_slice(chunk);
+
+                kind.decode(u64::from_le_bytes(element))
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DumpBuilder;
+    use crate::PxeFlags;
+
+    const CONTEXT: u64 = 0xffff_c000_0100_0000;
+
+    #[test]
+    fn read_struct() {
+        let mut name = [0; 0x10];
+        name[..2].copy_from_slice(&8u16.to_le_bytes());
+        name[8..].copy_from_slice(&(CONTEXT + 0x100).to_le_bytes());
+        let dump = DumpBuilder::new()
+            .map_virt(CONTEXT, 0x10_000, PxeFlags::Present)
+            .write_virt(CONTEXT, &[0x41, 0x00, 0x37, 0x13, 0xef, 0xbe, 0xad, 0xde])
+            .write_virt(CONTEXT + 0x8, &(CONTEXT + 0x20).to_le_bytes())
+            .write_virt(CONTEXT + 0x10, &name)
+            .write_virt(CONTEXT + 0x20, &CONTEXT.to_le_bytes())
+            .write_virt(CONTEXT + 0x28, &CONTEXT.to_le_bytes())
+            .write_virt(CONTEXT + 0x30, &[1, 2, 3, 0b1010_0000])
+            .write_virt(
+                CONTEXT + 0x100,
+                "kdmp"
+                    .encode_utf16()
+                    .flat_map(u16::to_le_bytes)
+                    .collect::<Vec<_>>()
+                    .as_slice(),
+            )
+            .build();
+
+        let mut parser = KernelDumpParser::from_bytes(dump).unwrap();
+        parser.profile_mut().set_layout(
+            "_MY_DRIVER_CONTEXT",
+            StructLayout::new(0x30)
+                .with_field("Signature", 0x0, FieldKind::U8)
+                .with_field("Version", 0x2, FieldKind::U16)
+                .with_field("Magic", 0x4, FieldKind::U32)
+                .with_field("Next", 0x8, FieldKind::Pointer)
+                .with_field("Name", 0x10, FieldKind::UnicodeString)
+                .with_field("Links", 0x20, FieldKind::ListEntry)
+                .with_field("Slots", 0x30, FieldKind::Array {
+                    kind: Box::new(FieldKind::U8),
+                    count: 3,
+                })
+                .with_field("Flags", 0x33, FieldKind::Bits {
+                    size: 1,
+                    position: 5,
+                    width: 3,
+                }),
+        );
+
+        let value = parser
+            .read_struct("_MY_DRIVER_CONTEXT", Gva::new(CONTEXT))
+            .unwrap();
+        assert_eq!(value.address, Gva::new(CONTEXT));
+        assert_eq!(value.field("Signature").unwrap(), &FieldValue::U8(0x41));
+        assert_eq!(value.field("Version").unwrap(), &FieldValue::U16(0x1337));
+        assert_eq!(value.field("Magic").unwrap().as_u64(), Some(0xdead_beef));
+        assert_eq!(
+            value.field("Next").unwrap(),
+            &FieldValue::Pointer(Gva::new(CONTEXT + 0x20))
+        );
+        assert_eq!(
+            value.field("Name").unwrap(),
+            &FieldValue::UnicodeString(Some("kdmp".into()))
+        );
+        assert_eq!(value.field("Links").unwrap(), &FieldValue::ListEntry {
+            flink: Gva::new(CONTEXT),
+            blink: Gva::new(CONTEXT),
+        });
+        assert_eq!(
+            value.field("Slots").unwrap(),
+            &FieldValue::Array(vec![
+                FieldValue::U8(1),
+                FieldValue::U8(2),
+                FieldValue::U8(3)
+            ])
+        );
+        assert_eq!(value.field("Flags").unwrap(), &FieldValue::Bits(0b101));
+
+        // What the profile doesn't know about is named in the errors.
+        assert!(matches!(
+            value.field("Prev"),
+            Err(KdmpParserError::ProfileMissing { type_name, field: Some(field) })
+                if type_name == "_MY_DRIVER_CONTEXT" && field == "Prev"
+        ));
+        assert!(matches!(
+            parser.read_struct("_UNKNOWN", Gva::new(CONTEXT)),
+            Err(KdmpParserError::ProfileMissing { type_name, field: None })
+                if type_name == "_UNKNOWN"
+        ));
+
+        // The structure has to be entirely in the dump.
+        assert!(parser
+            .read_struct("_MY_DRIVER_CONTEXT", Gva::new(CONTEXT + 0xf_ff0))
+            .is_err());
+    }
+
+    #[test]
+    fn old_builds() {
+        // The layouts that depend on the build aren't guessed for older builds..
+        let mut profile = Profile::new(17_763);
+        assert_eq!(profile.build(), 17_763);
+        assert!(matches!(
+            profile.offset("_EPROCESS", "ActiveProcessLinks"),
+            Err(KdmpParserError::ProfileMissing { type_name, field: None })
+                if type_name == "_EPROCESS"
+        ));
+        assert!(profile.layout("_KLDR_DATA_TABLE_ENTRY").is_none());
+        assert_eq!(profile.offset("_PEB32", "Ldr").unwrap(), 0xc);
+
+        // ..but the fields the KDDEBUGGER_DATA_BLOCK has are.
+        profile.apply_kd_debugger_data_block(&KdDebuggerData64 {
+            size_eprocess: 0x850,
+            offset_eprocess_peb: 0x3f8,
+            offset_eprocess_directory_table_base: 0x28,
+            ..Default::default()
+        });
+        assert_eq!(
+            profile.layout("_EPROCESS").unwrap(),
+            &StructLayout::new(0x850)
+                .with_field("DirectoryTableBase", 0x28, FieldKind::U64)
+                .with_field("Peb", 0x3f8, FieldKind::Pointer)
+        );
+        assert!(profile.layout("_KPRCB").is_none());
+
+        // The newer builds get the default layouts, refined the same way.
+        let mut profile = Profile::new(22_621);
+        profile.apply_kd_debugger_data_block(&KdDebuggerData64 {
+            offset_eprocess_peb: 0x558,
+            ..Default::default()
+        });
+        assert_eq!(profile.offset("_EPROCESS", "Peb").unwrap(), 0x558);
+        assert_eq!(profile.size("_EPROCESS").unwrap(), 0xa40);
+        assert_eq!(
+            profile.offset("_EPROCESS", "ActiveProcessLinks").unwrap(),
+            0x448
+        );
+    }
+}
@@ -0,0 +1,206 @@
+//! This gives access to the structures of the dump file as they are stored,
+//! before the parser normalizes them (see [`RawDump`]); it is meant for the
+//! tools that validate the files written by a dump writer.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::KernelDumpParser;
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! let raw = parser.raw();
+//! if let (Some(header), Some(bitmap)) = (raw.bmp_header().unwrap(), raw.bitmap().unwrap()) {
+//!     let set = bitmap.iter().map(|byte| u64::from(byte.count_ones())).sum::<u64>();
+//!     if set != header.total_present_pages {
+//!         println!("the bitmap has {set} pages, the header says {}", header.total_present_pages);
+//!     }
+//! }
+//! ```
+use crate::error::Result;
+pub use crate::structs::{
+    BmpHeader64, FullRdmpHeader64, KernelRdmpHeader64, PfnRange, PhysmemDesc, PhysmemRun,
+    RdmpHeader64,
+};
+use crate::structs::{FromLeBytes, Header64};
+use crate::{DumpType, KdmpParserError, KernelDumpParser};
+
+/// A view over the structures of the dump file, as they are stored. Get one
+/// with [`KernelDumpParser::raw`].
+///
+/// The accessors return `None` when the dump isn't of the type that has the
+/// structure; the structures aren't validated.
+#[derive(Debug, Clone, Copy)]
+pub struct RawDump<'parser> {
+    parser: &'parser KernelDumpParser,
+}
+
+impl<'parser> RawDump<'parser> {
+    /// The bytes of the `DUMP_HEADER64` that starts the dump.
+    pub fn header_bytes(&self) -> &'parser [u8] {
+        &self.parser.raw_header
+    }
+
+    /// The physical memory descriptor of a [`DumpType::Full`] dump.
+    pub fn physical_memory_descriptor(&self) -> Option<PhysmemDesc> {
+        (self.parser.dump_type() == DumpType::Full).then(|| {
+            PhysmemDesc::from_le_bytes(&self.parser.headers().physical_memory_block_buffer)
+        })
+    }
+
+    /// The runs of a [`DumpType::Full`] dump, as many as its descriptor says
+    /// there are and that fit in the header.
+    pub fn runs(&self) -> Option<Vec<PhysmemRun>> {
+        let desc = self.physical_memory_descriptor()?;
+        let buffer = &self.parser.headers().physical_memory_block_buffer[PhysmemDesc::SIZE..];
+
+        Some(
+            buffer
+                .chunks_exact(PhysmemRun::SIZE)
+                .take(desc.number_of_runs as usize)
+                .map(PhysmemRun::from_le_bytes)
+                .collect(),
+        )
+    }
+
+    /// The bitmap header of a [`DumpType::Bmp`] dump.
+    pub fn bmp_header(&self) -> Result<Option<BmpHeader64>> {
+        if self.parser.dump_type() != DumpType::Bmp {
+            return Ok(None);
+        }
+
+        self.read_struct(Header64::SIZE as u64).map(Some)
+    }
+
+    /// The bitmap of a [`DumpType::Bmp`] dump: one bit per page, as many as
+    /// its header says there are. Its size can't exceed
+    /// [`crate::ParserOptions::max_read_size`].
+    pub fn bitmap(&self) -> Result<Option<Vec<u8>>> {
+        let Some(header) = self.bmp_header()? else {
+            return Ok(None);
+        };
+
+        let offset = (Header64::SIZE + BmpHeader64::SIZE) as u64;
+        let size = (header.pages / 8) + u64::from(header.pages % 8 != 0);
+
+        self.read(offset, size).map(Some)
+    }
+
+    /// The header of a [`DumpType::KernelMemory`] or
+    /// [`DumpType::KernelAndUserMemory`] dump.
+    pub fn kernel_rdmp_header(&self) -> Result<Option<KernelRdmpHeader64>> {
+        match self.parser.dump_type() {
+            DumpType::KernelMemory | DumpType::KernelAndUserMemory => {
+                self.read_struct(Header64::SIZE as u64).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// The header of a [`DumpType::CompleteMemory`] dump.
+    pub fn full_rdmp_header(&self) -> Result<Option<FullRdmpHeader64>> {
+        match self.parser.dump_type() {
+            DumpType::CompleteMemory => self.read_struct(Header64::SIZE as u64).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// The page ranges of a kernel memory or complete memory dump: as many as
+    /// the metadata size of its header says there are, including the ones
+    /// past the ranges the parser uses.
+    pub fn pfn_ranges(&self) -> Result<Option<Vec<PfnRange>>> {
+        let (header, offset) = if let Some(header) = self.kernel_rdmp_header()? {
+            (header.hdr, Header64::SIZE + KernelRdmpHeader64::SIZE)
+        } else if let Some(header) = self.full_rdmp_header()? {
+            (header.hdr, Header64::SIZE + FullRdmpHeader64::SIZE)
+        } else {
+            return Ok(None);
+        };
+
+        let size = header.metadata_size - (header.metadata_size % PfnRange::SIZE as u64);
+        let ranges = self.read(offset as u64, size)?;
+
+        Ok(Some(
+            ranges
+                .chunks_exact(PfnRange::SIZE)
+                .map(PfnRange::from_le_bytes)
+                .collect(),
+        ))
+    }
+
+    /// Read `size` bytes at `offset` in the dump file.
+    fn read(&self, offset: u64, size: u64) -> Result<Vec<u8>> {
+        let mut buffer = vec![0; self.parser.check_read_size(size)?];
+        if self.parser.read_at(offset, &mut buffer)? != buffer.len() {
+            return Err(KdmpParserError::InvalidData("the dump is truncated"));
+        }
+
+        Ok(buffer)
+    }
+
+    /// Read a `T` at `offset` in the dump file.
+    fn read_struct<T: FromLeBytes>(&self, offset: u64) -> Result<T> {
+        self.read(offset, T::SIZE as u64)
+            .map(|buffer| T::from_le_bytes(&buffer))
+    }
+}
+
+impl KernelDumpParser {
+    /// Get a view over the structures of the dump file, as they are stored.
+    pub fn raw(&self) -> RawDump<'_> {
+        RawDump { parser: self }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DumpBuilder;
+
+    #[test]
+    fn raw() {
+        let dump = DumpBuilder::new().write_phys(0x5_000, b"raw").build();
+        let parser = KernelDumpParser::from_bytes(dump.clone()).unwrap();
+        let raw = parser.raw();
+
+        assert_eq!(raw.header_bytes(), &dump[..Header64::SIZE]);
+        assert!(raw.physical_memory_descriptor().is_none());
+        assert!(raw.runs().is_none());
+        assert!(raw.pfn_ranges().unwrap().is_none());
+
+        // Every page of the dump has its bit set in the bitmap.
+        let header = raw.bmp_header().unwrap().unwrap();
+        let bitmap = raw.bitmap().unwrap().unwrap();
+        assert_eq!(bitmap.len() as u64, header.pages / 8);
+        let set = bitmap
+            .iter()
+            .map(|byte| u64::from(byte.count_ones()))
+            .sum::<u64>();
+        assert_eq!(set, header.total_present_pages);
+        assert_eq!(set, parser.physmem_len());
+        assert_eq!(bitmap[0] & (1 << 5), 1 << 5);
+
+        // The runs of a full dump are in its header; its descriptor claims two runs
+        // but only has one.
+        let mut dump = vec![0; Header64::SIZE + 0x1_000];
+        dump[0x0..0x4].copy_from_slice(b"PAGE");
+        dump[0x4..0x8].copy_from_slice(b"DU64");
+        dump[0x88..0x8c].copy_from_slice(&2u32.to_le_bytes());
+        dump[0x90..0x98].copy_from_slice(&1u64.to_le_bytes());
+        dump[0x98..0xa0].copy_from_slice(&2u64.to_le_bytes());
+        dump[0xa0..0xa8].copy_from_slice(&1u64.to_le_bytes());
+        dump[0xf98..0xf9c].copy_from_slice(&1u32.to_le_bytes());
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+        let raw = parser.raw();
+
+        let desc = raw.physical_memory_descriptor().unwrap();
+        assert_eq!((desc.number_of_runs, desc.number_of_pages), (2, 1));
+        let runs = raw
+            .runs()
+            .unwrap()
+            .into_iter()
+            .map(|run| (run.base_page, run.page_count))
+            .collect::<Vec<_>>();
+        assert_eq!(runs, [(2, 1), (0, 0)]);
+        assert!(raw.bmp_header().unwrap().is_none());
+        assert!(raw.bitmap().unwrap().is_none());
+    }
+}
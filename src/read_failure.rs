@@ -0,0 +1,340 @@
+//! This contains what is needed to explain why a [`Gva`] can't be read in a
+//! dump: see [`KernelDumpParser::explain_read_failure`]. The page tables are
+//! walked, and the first thing that stops the translation, or the read of the
+//! page it leads to, is what [`ReadFailureExplanation`] describes.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::{Gva, KernelDumpParser};
+//! let parser = KernelDumpParser::new(&"bmp.dmp").unwrap();
+//! let gva = Gva::new(0x1a4_2ea3_0240);
+//! println!("{gva}: {}", parser.explain_read_failure(gva, None));
+//! ```
+use std::fmt::{self, Display};
+
+use crate::exclusion::ExclusionReason;
+use crate::gxa::Gxa;
+use crate::nt::KERNEL_SPACE_START;
+use crate::page_tables::WalkLevel;
+use crate::pxe::Pxe;
+use crate::structs::{DumpType, Page};
+use crate::supplement::PageSource;
+use crate::{Gpa, Gva, KernelDumpParser};
+
+/// Bit of a not present PTE that is set when it is a prototype PTE.
+const PTE_PROTOTYPE: u64 = 1 << 10;
+
+/// The paging file of the virtual store, where the memory manager keeps the
+/// pages it compresses, on Windows 10 and later.
+const VIRTUAL_STORE_FILE: u8 = 2;
+
+/// What a not present entry of a paging structure describes, decoded as a
+/// `_MMPTE_SOFTWARE`, a `_MMPTE_PROTOTYPE` or a `_MMPTE_TRANSITION`. The
+/// swizzling of the invalid PTEs isn't undone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SoftwarePte {
+    /// The entry is zero: nothing is mapped there.
+    Unmapped,
+    /// The page is allocated, zeroed, the first time it is touched.
+    DemandZero { protection: u8 },
+    /// The page has been written to the paging file `file`, at `offset`.
+    PageFile {
+        file: u8,
+        offset: u64,
+        protection: u8,
+    },
+    /// The page has been compressed in the virtual store; `offset` is where it
+    /// is in the store.
+    Compressed { offset: u64, protection: u8 },
+    /// The page is described by the prototype PTE at `address`.
+    Prototype { address: Gva },
+    /// The page is in transition: it is still at `gpa` in physical memory.
+    Transition { gpa: Gpa },
+}
+
+impl SoftwarePte {
+    /// Decode the not present entry `raw`.
+    pub fn decode(raw: u64) -> Self {
+        if raw == 0 {
+            return Self::Unmapped;
+        }
+
+        if Pxe::from(raw).transition() && raw & PTE_PROTOTYPE == 0 {
+            return Self::Transition {
+                gpa: Pxe::from(raw).pfn.gpa(),
+            };
+        }
+
+        if raw & PTE_PROTOTYPE != 0 {
+            // `ProtoAddress` is the 48 bits at the top of the entry.
+            return Self::Prototype {
+                address: Gva::new(((raw as i64) >> 16) as u64),
+            };
+        }
+
+        let protection = ((raw >> 5) & 0x1f) as u8;
+        let file = ((raw >> 12) & 0xf) as u8;
+        let offset = (raw >> 32) * Page::size();
+        match (file, offset) {
+            (_, 0) => Self::DemandZero { protection },
+            (VIRTUAL_STORE_FILE, _) => Self::Compressed { offset, protection },
+            _ => Self::PageFile {
+                file,
+                offset,
+                protection,
+            },
+        }
+    }
+}
+
+impl Display for SoftwarePte {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unmapped => write!(f, "nothing is mapped there"),
+            Self::DemandZero { protection } => {
+                write!(f, "demand zero page (protection {protection:#x})")
+            }
+            Self::PageFile {
+                file,
+                offset,
+                protection,
+            } => write!(
+                f,
+                "paged out to the paging file {file} at {offset:#x} (protection {protection:#x})"
+            ),
+            Self::Compressed { offset, protection } => write!(
+                f,
+                "compressed in the virtual store at {offset:#x} (protection {protection:#x})"
+            ),
+            Self::Prototype { address } => write!(f, "described by the prototype PTE at {address}"),
+            Self::Transition { gpa } => write!(f, "in transition at {gpa}"),
+        }
+    }
+}
+
+/// Why a [`Gva`] can't be read; see [`KernelDumpParser::explain_read_failure`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadFailureExplanation {
+    /// Nothing: the page is at `offset` in `source`, the dump or a
+    /// supplemental source.
+    Readable {
+        gpa: Gpa,
+        source: PageSource,
+        offset: u64,
+    },
+    /// The address isn't canonical, so nothing can be mapped there.
+    NonCanonical,
+    /// The page of the paging structure of `level` that the walk needs,
+    /// which is at `table`, isn't in the dump.
+    TableMissing { level: WalkLevel, table: Gpa },
+    /// The entry of the paging structure of `level` isn't present; `entry` is
+    /// its value, and `software` what it describes.
+    NotPresent {
+        level: WalkLevel,
+        entry: u64,
+        software: SoftwarePte,
+    },
+    /// The translation led to `gpa`, past the physical memory of the machine;
+    /// see [`KernelDumpParser::max_physical_address`].
+    ImplausiblePhysicalAddress { gpa: Gpa },
+    /// The translation led to `gpa`, that the writer of the dump left out.
+    Excluded { gpa: Gpa, reason: ExclusionReason },
+    /// The translation led to `gpa`, a page of user memory, that the type of
+    /// the dump doesn't save.
+    ExcludedByDumpType { gpa: Gpa, dump_type: DumpType },
+    /// The translation led to `gpa`, that isn't in the dump.
+    Absent { gpa: Gpa },
+}
+
+impl Display for ReadFailureExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Readable {
+                gpa,
+                source,
+                offset,
+            } => {
+                write!(f, "readable: {gpa} is at {offset:#x} in {source}")
+            }
+            Self::NonCanonical => write!(f, "the address isn't canonical"),
+            Self::TableMissing { level, table } => {
+                write!(f, "the {level} at {table} isn't in the dump")
+            }
+            Self::NotPresent {
+                level,
+                entry,
+                software,
+            } => write!(f, "the {level} entry {entry:#x} isn't present: {software}"),
+            Self::ImplausiblePhysicalAddress { gpa } => {
+                write!(f, "the translation leads to {gpa}, past physical memory")
+            }
+            Self::Excluded { gpa, reason } => {
+                write!(f, "the translation leads to {gpa}, {reason}")
+            }
+            Self::ExcludedByDumpType { gpa, dump_type } => write!(
+                f,
+                "the translation leads to {gpa}, a user page that {dump_type:?} dumps don't save"
+            ),
+            Self::Absent { gpa } => {
+                write!(f, "the translation leads to {gpa}, that isn't in the dump")
+            }
+        }
+    }
+}
+
+impl KernelDumpParser {
+    /// Explain why `gva` can't be read in the address space `dtb` (or the one
+    /// of the dump if `None`): the page tables are walked like a read does,
+    /// and the first thing that stops it is returned. It is
+    /// [`ReadFailureExplanation::Readable`] if nothing does.
+    pub fn explain_read_failure(
+        &self,
+        gva: impl Into<Gva>,
+        dtb: Option<Gpa>,
+    ) -> ReadFailureExplanation {
+        let gva = gva.into();
+        if ((gva.u64() as i64) << 16 >> 16) as u64 != gva.u64() {
+            return ReadFailureExplanation::NonCanonical;
+        }
+
+        let mut table = dtb.unwrap_or(self.dtb).page_align();
+        let mut level = WalkLevel::Pml4;
+        let gpa = loop {
+            let idx = match level {
+                WalkLevel::Pml4 => gva.pml4e_idx(),
+                WalkLevel::Pdpt => gva.pdpe_idx(),
+                WalkLevel::Pd => gva.pde_idx(),
+                WalkLevel::Pt => gva.pte_idx(),
+            };
+
+            let Ok(entry) = self.phys_read_struct::<u64>(Gpa::new(table.u64() + (idx * 8))) else {
+                return ReadFailureExplanation::TableMissing { level, table };
+            };
+
+            // A transition PTE is read like a present one.
+            let pxe = Pxe::from(entry);
+            if !(pxe.present() || level == WalkLevel::Pt && pxe.transition()) {
+                return ReadFailureExplanation::NotPresent {
+                    level,
+                    entry,
+                    software: SoftwarePte::decode(entry),
+                };
+            }
+
+            let base = pxe.pfn.gpa();
+            if self.max_physical_address.is_some_and(|max| base > max) {
+                return ReadFailureExplanation::ImplausiblePhysicalAddress { gpa: base };
+            }
+
+            level = match level {
+                WalkLevel::Pdpt if pxe.large_page() => {
+                    break Gpa::new(base.u64() + (gva.u64() & 0x3fff_ffff));
+                }
+                WalkLevel::Pd if pxe.large_page() => {
+                    break Gpa::new(base.u64() + (gva.u64() & 0x1f_ffff));
+                }
+                WalkLevel::Pt => break Gpa::new(base.u64() + gva.offset()),
+                WalkLevel::Pml4 => WalkLevel::Pdpt,
+                WalkLevel::Pdpt => WalkLevel::Pd,
+                WalkLevel::Pd => WalkLevel::Pt,
+            };
+            table = base;
+        };
+
+        if let Ok((source, offset)) = self.phys_locate(gpa) {
+            return ReadFailureExplanation::Readable {
+                gpa,
+                source,
+                offset,
+            };
+        }
+
+        if let Some(reason) = self.exclusion_reason(gpa) {
+            return ReadFailureExplanation::Excluded { gpa, reason };
+        }
+
+        // The kernel dumps leave the user memory out by design.
+        if self.dump_type() == DumpType::KernelMemory && gva.u64() < KERNEL_SPACE_START {
+            return ReadFailureExplanation::ExcludedByDumpType {
+                gpa,
+                dump_type: self.dump_type(),
+            };
+        }
+
+        ReadFailureExplanation::Absent { gpa }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DumpBuilder;
+    use crate::PxeFlags;
+
+    const GVA: u64 = 0xffff_f800_0000_0000;
+
+    #[test]
+    fn explain_read_failure() {
+        let prototype = PxeFlags::from_bits_retain(PTE_PROTOTYPE);
+        let dump = DumpBuilder::new()
+            .map_virt(GVA, 0x10_000, PxeFlags::Present)
+            .write_phys(0x10_000, &[0xaa; 0x10])
+            .map_virt(GVA + 0x1_000, 0x11_000, PxeFlags::Present)
+            .map_virt(GVA + 0x2_000, 0x5_0000_0000, PxeFlags::empty())
+            .map_virt(GVA + 0x3_000, 0x7_0000_2000, PxeFlags::empty())
+            .map_virt(GVA + 0x4_000, 0x8000_1234_5678_0000, prototype)
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+        let explain = |gva: u64| parser.explain_read_failure(gva, None);
+
+        // The page is there..
+        let offset = parser.phys_translate(Gpa::new(0x10_000)).unwrap();
+        assert_eq!(explain(GVA + 0x10), ReadFailureExplanation::Readable {
+            gpa: Gpa::new(0x10_010),
+            source: PageSource::Dump,
+            offset: offset + 0x10
+        });
+
+        // ..the translation works, but the page isn't in the dump..
+        assert_eq!(explain(GVA + 0x1_000), ReadFailureExplanation::Absent {
+            gpa: Gpa::new(0x11_000)
+        });
+
+        // ..the PTE is paged out, or compressed..
+        assert_eq!(explain(GVA + 0x2_000), ReadFailureExplanation::NotPresent {
+            level: WalkLevel::Pt,
+            entry: 0x5_0000_0000,
+            software: SoftwarePte::PageFile {
+                file: 0,
+                offset: 0x5_000,
+                protection: 0
+            }
+        });
+        assert_eq!(
+            explain(GVA + 0x3_000).to_string(),
+            "the PT entry 0x700002000 isn't present: compressed in the virtual store at 0x7000 \
+             (protection 0x0)"
+        );
+
+        // ..it is a prototype PTE..
+        assert!(matches!(
+            explain(GVA + 0x4_000),
+            ReadFailureExplanation::NotPresent {
+                software: SoftwarePte::Prototype { address },
+                ..
+            } if address == Gva::new(0xffff_8000_1234_5678)
+        ));
+
+        // ..or nothing is mapped at all.
+        assert_eq!(explain(0x1_0000), ReadFailureExplanation::NotPresent {
+            level: WalkLevel::Pml4,
+            entry: 0,
+            software: SoftwarePte::Unmapped
+        });
+        assert_eq!(
+            explain(0x8000_0000_0000),
+            ReadFailureExplanation::NonCanonical
+        );
+    }
+}
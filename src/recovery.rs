@@ -0,0 +1,376 @@
+//! This contains what is needed to salvage a dump whose header block is
+//! corrupted, like a dump whose first page was partly overwritten: the
+//! physical memory is intact, but the signature, the directory table base or
+//! the context of the headers are garbage. Unless the dump is parsed in strict
+//! mode, what the headers should have had is synthesized out of memory, and
+//! [`KernelDumpParser::recovery_notes`] tells what (see [`RecoveryNote`]).
+//!
+//! The header block is considered corrupted when the dump header is invalid
+//! but the bitmap header that follows it is, or when the directory table base
+//! of the headers isn't in the dump. Then:
+//! - the PML4 is found by looking for the self-referencing ones (see
+//!   [`KernelDumpParser::find_dtb_candidates`]),
+//! - `nt` is found by scanning memory for its image (see
+//!   [`KernelDumpParser::carve_pe`]), and the `KDDEBUGGER_DATA_BLOCK` and
+//!   `nt!PsLoadedModuleList` are found with it,
+//! - the context of the headers is discarded, and the one of the `_KPRCB` of
+//!   the processor that crashed is used (see [`ContextSource::Prcb`]).
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::KernelDumpParser;
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! for note in parser.recovery_notes() {
+//!     println!("{note}");
+//! }
+//! ```
+use std::fmt::{self, Display};
+
+use crate::error::Result;
+use crate::gxa::Gxa;
+use crate::map::Reader;
+use crate::module::ModuleEntry;
+use crate::nt::NT_EXPORT_NAME;
+use crate::structs::{
+    peek_struct, BmpHeader64, DumpType, FromLeBytes, Header64, KdDebuggerData64,
+    DUMP_HEADER64_EXPECTED_SIGNATURE, DUMP_HEADER64_EXPECTED_VALID_DUMP,
+};
+use crate::{context_source, ContextSource, Gpa, Gva, KdmpParserError, KernelDumpParser};
+
+/// The tag the `KDDEBUGGER_DATA_BLOCK` is owned by.
+///
+/// ```text
+/// kd> dt nt!_DBGKD_DEBUG_DATA_HEADER64 OwnerTag
+///    +0x010 OwnerTag         : Uint4B
+/// kd> dt nt!_KDDEBUGGER_DATA64 KernBase
+///    +0x018 KernBase         : Uint8B
+/// ```
+const KDBG_OWNER_TAG: &[u8; 4] = b"KDBG";
+const KDBG_OWNER_TAG_OFFSET: usize = 0x10;
+const KDBG_KERN_BASE_OFFSET: usize = 0x18;
+
+/// What was synthesized to parse a dump whose header block is corrupted; see
+/// [`KernelDumpParser::recovery_notes`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecoveryNote {
+    /// The dump header is invalid; the type of the dump comes from the bitmap
+    /// header that follows it.
+    DumpType(DumpType),
+    /// The directory table base of the headers isn't in the dump; the PML4 at
+    /// `recovered` is used instead.
+    Dtb { header: Gpa, recovered: Gpa },
+    /// `nt` was found at this address by scanning memory for its image.
+    NtBase(Gva),
+    /// The `KDDEBUGGER_DATA_BLOCK` was found at this address in `nt`.
+    KdDebuggerDataBlock(Gva),
+    /// `nt!PsLoadedModuleList` was found at this address, with the
+    /// `KDDEBUGGER_DATA_BLOCK` or the exports of `nt`.
+    PsLoadedModuleList(Gva),
+    /// The context of the headers was discarded; the one that is used comes
+    /// from there.
+    Context(ContextSource),
+}
+
+impl Display for RecoveryNote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DumpType(dump_type) => write!(
+                f,
+                "the dump header is invalid, the dump type ({dump_type:?}) comes from the bitmap header"
+            ),
+            Self::Dtb { header, recovered } => write!(
+                f,
+                "the directory table base of the headers ({header}) isn't in the dump, the PML4 at {recovered} is used instead"
+            ),
+            Self::NtBase(nt_base) => write!(f, "nt was found at {nt_base} by scanning memory"),
+            Self::KdDebuggerDataBlock(kdbg) => {
+                write!(f, "the KDDEBUGGER_DATA_BLOCK was found at {kdbg}")
+            }
+            Self::PsLoadedModuleList(head) => {
+                write!(f, "nt!PsLoadedModuleList was found at {head}")
+            }
+            Self::Context(source) => write!(
+                f,
+                "the context of the headers was discarded, the one that is used comes from {source}"
+            ),
+        }
+    }
+}
+
+/// Can a dump header that failed to parse with `error` be repaired?
+pub(crate) fn recoverable(error: &KdmpParserError) -> bool {
+    matches!(
+        error,
+        KdmpParserError::InvalidSignature(_)
+            | KdmpParserError::InvalidValidDump(_)
+            | KdmpParserError::UnknownDumpType(_)
+    )
+}
+
+/// Repair the dump header `raw_header`, which failed to parse with `error`,
+/// when the bitmap header that follows it (where `reader` is) is valid; it is
+/// `error` otherwise.
+pub(crate) fn recover_header(
+    raw_header: &[u8],
+    reader: &mut impl Reader,
+    error: KdmpParserError,
+) -> Result<(Box<Header64>, DumpType)> {
+    if !peek_struct::<BmpHeader64>(reader).is_ok_and(|bmp_header| bmp_header.looks_good()) {
+        return Err(error);
+    }
+
+    let mut headers = Box::new(Header64::from_le_bytes(raw_header));
+    headers.signature = DUMP_HEADER64_EXPECTED_SIGNATURE;
+    headers.valid_dump = DUMP_HEADER64_EXPECTED_VALID_DUMP;
+    headers.dump_type = DumpType::Bmp as u32;
+
+    Ok((headers, DumpType::Bmp))
+}
+
+impl KernelDumpParser {
+    /// What was synthesized to parse the dump because its header block is
+    /// corrupted, in the order it was; it is empty when the header block is
+    /// fine, and always when the dump is parsed in strict mode. See the
+    /// [`RecoveryNote`]s.
+    pub fn recovery_notes(&self) -> &[RecoveryNote] {
+        &self.recovery_notes
+    }
+
+    /// Is the header block corrupted: is the directory table base of the
+    /// headers not in the dump?
+    pub(crate) fn header_block_corrupted(&self) -> bool {
+        self.phys_offset(self.dtb.page_align()).is_err()
+    }
+
+    /// Synthesize what the corrupted header block should have had, before
+    /// the modules are found with it; `dump_type` is the type of the dump if
+    /// it had to be inferred.
+    pub(crate) fn recover_header_block(&mut self, dump_type: Option<DumpType>) -> Result<()> {
+        if let Some(dump_type) = dump_type {
+            self.recovery_notes.push(RecoveryNote::DumpType(dump_type));
+        }
+
+        // The context is next to the directory table base, so it can't be
+        // trusted either.
+        self.headers.context_record_buffer.fill(0);
+        (self.context, self.context_source) = context_source::header_context(&self.headers);
+
+        if self.header_block_corrupted() {
+            if let Some(candidate) = self.find_dtb_candidates()?.first() {
+                self.recovery_notes.push(RecoveryNote::Dtb {
+                    header: self.dtb,
+                    recovered: candidate.gpa,
+                });
+                self.dtb = candidate.gpa;
+                self.headers.directory_table_base = candidate.gpa.u64();
+            }
+        }
+
+        let Some(nt_base) = self
+            .carve_pe()
+            .find(|pe| {
+                pe.export_name
+                    .as_deref()
+                    .is_some_and(|name| name.eq_ignore_ascii_case(NT_EXPORT_NAME))
+            })
+            .and_then(|pe| pe.gva)
+        else {
+            return Ok(());
+        };
+
+        self.recovery_notes.push(RecoveryNote::NtBase(nt_base));
+        self.nt_base = Some(nt_base);
+        let kd_debugger_data_block = match self
+            .kd_debugger_data_block_at(Gva::new(self.headers.kd_debugger_data_block), nt_base)
+        {
+            Some(kdbg) => Some(kdbg),
+            None => {
+                let found = self.find_kd_debugger_data_block(nt_base);
+                if let Some((kdbg_addr, _)) = found {
+                    self.recovery_notes
+                        .push(RecoveryNote::KdDebuggerDataBlock(kdbg_addr));
+                    self.headers.kd_debugger_data_block = kdbg_addr.u64();
+                }
+
+                found.map(|(_, kdbg)| kdbg)
+            }
+        };
+
+        let ps_loaded_module_list = match kd_debugger_data_block {
+            Some(kdbg) => Some(Gva::new(kdbg.ps_loaded_module_list)),
+            None => self.exported_module_list(nt_base),
+        };
+
+        if let Some(head) =
+            ps_loaded_module_list.filter(|head| head.u64() != self.headers.ps_loaded_module_list)
+        {
+            self.recovery_notes
+                .push(RecoveryNote::PsLoadedModuleList(head));
+            self.headers.ps_loaded_module_list = head.u64();
+        }
+
+        Ok(())
+    }
+
+    /// Read the `KDDEBUGGER_DATA_BLOCK` at `kdbg`, if there is one of `nt` at
+    /// `nt_base` there.
+    fn kd_debugger_data_block_at(&self, kdbg: Gva, nt_base: Gva) -> Option<KdDebuggerData64> {
+        self.try_virt_read_struct::<KdDebuggerData64>(kdbg)
+            .ok()
+            .flatten()
+            .filter(|kdbg| {
+                kdbg.header.owner_tag == u32::from_le_bytes(*KDBG_OWNER_TAG)
+                    && kdbg.kern_base == nt_base.u64()
+            })
+    }
+
+    /// Find the `KDDEBUGGER_DATA_BLOCK` in the image of `nt`, at `nt_base`:
+    /// it is owned by `KDBG`, and it points to `nt`.
+    fn find_kd_debugger_data_block(&self, nt_base: Gva) -> Option<(Gva, KdDebuggerData64)> {
+        self.nt_pages().find_map(|(page, content)| {
+            (0..content.len()).step_by(8).find_map(|offset| {
+                let tag =
+                    content.get(offset + KDBG_OWNER_TAG_OFFSET..offset + KDBG_OWNER_TAG_OFFSET + 4);
+                let kern_base = content
+                    .get(offset + KDBG_KERN_BASE_OFFSET..offset + KDBG_KERN_BASE_OFFSET + 8)
+                    .map(|slot| u64::from_le_bytes(slot.try_into().unwrap()));
+                if tag != Some(&KDBG_OWNER_TAG[..]) || kern_base != Some(nt_base.u64()) {
+                    return None;
+                }
+
+                let kdbg = Gva::new(page.u64() + offset as u64);
+
+                Some((kdbg, self.kd_debugger_data_block_at(kdbg, nt_base)?))
+            })
+        })
+    }
+
+    /// Get `nt!PsLoadedModuleList` out of the exports of `nt`, at `nt_base`.
+    fn exported_module_list(&self, nt_base: Gva) -> Option<Gva> {
+        let size_of_image = self.pe_headers(nt_base).ok()?.size_of_image;
+        let nt_end = nt_base.u64().checked_add(size_of_image.into())?;
+        let nt = ModuleEntry::new(nt_base..nt_end.into(), NT_EXPORT_NAME);
+
+        self.module_exports(&nt)
+            .ok()?
+            .into_iter()
+            .find(|export| export.name == "PsLoadedModuleList" && export.forwarder.is_none())
+            .map(|export| export.address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::testing::{DumpBuilder, KdDebuggerData, MODULE_LIST_BASE};
+    use crate::{ParserOptions, PxeFlags};
+
+    const NT: u64 = 0xffff_f800_0000_0000;
+    const KDBG: u64 = NT + 0x1_000;
+    const PRCB: u64 = 0xffff_f800_0200_0000;
+
+    /// Build a dump whose `nt` has a `KDDEBUGGER_DATA_BLOCK` that points to the
+    /// module list and to a processor with a valid context, and whose PML4 has
+    /// a self-referencing entry.
+    fn dump() -> Vec<u8> {
+        let mut headers = vec![0; 0x400];
+        headers[0..2].copy_from_slice(b"MZ");
+        headers[0x3c..0x40].copy_from_slice(&0x80u32.to_le_bytes());
+        headers[0x80..0x84].copy_from_slice(b"PE\0\0");
+        headers[0x84..0x86].copy_from_slice(&0x8664u16.to_le_bytes());
+        headers[0x98..0x9a].copy_from_slice(&0x20bu16.to_le_bytes());
+        headers[0xd0..0xd4].copy_from_slice(&0x3_000u32.to_le_bytes());
+        headers[0xd4..0xd8].copy_from_slice(&0x400u32.to_le_bytes());
+        headers[0x104..0x108].copy_from_slice(&16u32.to_le_bytes());
+        headers[0x108..0x10c].copy_from_slice(&0x300u32.to_le_bytes());
+        headers[0x10c..0x110].copy_from_slice(&0x28u32.to_le_bytes());
+        headers[0x30c..0x310].copy_from_slice(&0x340u32.to_le_bytes());
+        headers[0x340..0x34d].copy_from_slice(b"ntoskrnl.exe\0");
+
+        let context = PRCB + 0x800;
+        let mut dump = DumpBuilder::new()
+            .map_virt(NT, 0x10_000, PxeFlags::Present)
+            .map_virt(KDBG, 0x11_000, PxeFlags::Present)
+            .map_virt(PRCB, 0x12_000, PxeFlags::Present)
+            .write_virt(NT, &headers)
+            .kd_debugger_data(KDBG, KdDebuggerData {
+                kern_base: NT,
+                ps_loaded_module_list: MODULE_LIST_BASE,
+                prcbs: vec![PRCB],
+                offset_prcb_context: 0x400,
+                ..Default::default()
+            })
+            .write_virt(PRCB + 0x400, &context.to_le_bytes())
+            .write_virt(context + 0x38, &0x10u16.to_le_bytes())
+            .write_virt(context + 0xf8, &(NT + 0x1_337).to_le_bytes())
+            .module(NT..NT + 0x3_000, "ntoskrnl.exe")
+            .build();
+
+        // Give the PML4 its self-referencing entry.
+        let parser = KernelDumpParser::from_bytes(dump.clone()).unwrap();
+        let dtb = parser.default_dtb();
+        let self_entry = parser
+            .phys_translate(Gpa::new(dtb.u64() + 0x1ed * 8))
+            .unwrap() as usize;
+        dump[self_entry..self_entry + 8].copy_from_slice(&(dtb.u64() | 0b11).to_le_bytes());
+
+        dump
+    }
+
+    #[test]
+    fn recover_header_block() {
+        let dump = dump();
+        let parser = KernelDumpParser::from_bytes(dump.clone()).unwrap();
+        assert!(parser.recovery_notes().is_empty());
+        let dtb = parser.default_dtb();
+
+        // Garbage over the signature, the directory table base, the module
+        // list, the KDDEBUGGER_DATA_BLOCK and the context..
+        let mut corrupted = dump;
+        corrupted[0x0..0x28].fill(0x41);
+        corrupted[0x80..0x88].fill(0x41);
+        corrupted[0x348..0x348 + 0x4d0].fill(0x41);
+        let parser = KernelDumpParser::from_bytes(corrupted.clone()).unwrap();
+        let garbage = Gpa::new(0x4141_4141_4141_4141);
+        assert_eq!(parser.recovery_notes(), [
+            RecoveryNote::DumpType(DumpType::Bmp),
+            RecoveryNote::Dtb {
+                header: garbage,
+                recovered: dtb.page_align(),
+            },
+            RecoveryNote::NtBase(Gva::new(NT)),
+            RecoveryNote::KdDebuggerDataBlock(Gva::new(KDBG)),
+            RecoveryNote::PsLoadedModuleList(Gva::new(MODULE_LIST_BASE)),
+            RecoveryNote::Context(ContextSource::Prcb(0)),
+        ]);
+
+        // ..are recovered from memory..
+        assert_eq!(parser.dump_type(), DumpType::Bmp);
+        assert_eq!(parser.default_dtb(), dtb.page_align());
+        assert_eq!(parser.nt_base(), Some(Gva::new(NT)));
+        assert_eq!(parser.context_record().rip, NT + 0x1_337);
+        assert_eq!(
+            parser
+                .kernel_modules()
+                .map(|(_, name)| name)
+                .collect::<Vec<_>>(),
+            ["ntoskrnl.exe"]
+        );
+        assert!(parser.recovery_notes()[1]
+            .to_string()
+            .contains("isn't in the dump"));
+
+        // ..unless we're in strict mode.
+        let options = ParserOptions {
+            strict: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            KernelDumpParser::with_options(Cursor::new(corrupted), options),
+            Err(KdmpParserError::InvalidSignature(0x4141_4141))
+        ));
+    }
+}
@@ -0,0 +1,329 @@
+//! This contains typed wrappers for the registers made of flags: [`Rflags`],
+//! [`Cr0`] and [`Cr4`], with an accessor for every architecturally defined
+//! bit.
+//!
+//! # Examples
+//!
+//! ```
+//! # use kdmp_parser::Rflags;
+//! let rflags = Rflags::from_raw(0x40202);
+//! assert!(rflags.interrupts_enabled());
+//! assert_eq!(rflags.to_string(), "nv up ei pl nz na po nc");
+//! ```
+use std::fmt::{self, Display};
+
+use crate::bits::Bits;
+use crate::gxa::Gxa;
+use crate::structs::Context;
+use crate::KernelDumpParser;
+
+/// Define a register made of flags: a wrapper around its raw value, with an
+/// accessor for each of its flags, named after their bit.
+macro_rules! flags_register {
+    (
+        $(#[$meta:meta])*
+        $name:ident {
+            $($(#[$flag_meta:meta])* $flag:ident = $bit:literal, $mnemonic:literal;)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+        pub struct $name(u64);
+
+        impl $name {
+            /// The flags, with their mnemonic.
+            const FLAGS: &'static [(usize, &'static str)] = &[$(($bit, $mnemonic)),*];
+
+            /// Wrap the raw value of the register.
+            pub fn from_raw(raw: u64) -> Self {
+                Self(raw)
+            }
+
+            /// The raw value of the register.
+            pub fn raw(&self) -> u64 {
+                self.0
+            }
+
+            /// The mnemonics of the flags that are set, from the lowest bit.
+            pub fn set_flags(&self) -> impl Iterator<Item = &'static str> + '_ {
+                Self::FLAGS
+                    .iter()
+                    .filter(|(bit, _)| self.0.bit(*bit) == 1)
+                    .map(|(_, mnemonic)| *mnemonic)
+            }
+
+            $(
+                $(#[$flag_meta])*
+                pub fn $flag(&self) -> bool {
+                    self.0.bit($bit) == 1
+                }
+            )*
+        }
+    };
+}
+
+flags_register! {
+    /// The `RFLAGS` register.
+    Rflags {
+        /// `CF`: the carry flag.
+        carry = 0, "cf";
+        /// `PF`: the parity flag, set when the low byte of the result has an
+        /// even number of bits set.
+        parity = 2, "pf";
+        /// `AF`: the auxiliary carry flag.
+        auxiliary_carry = 4, "af";
+        /// `ZF`: the zero flag.
+        zero = 6, "zf";
+        /// `SF`: the sign flag.
+        sign = 7, "sf";
+        /// `TF`: the trap flag, that single-steps.
+        trap = 8, "tf";
+        /// `IF`: the interrupt enable flag.
+        interrupts_enabled = 9, "if";
+        /// `DF`: the direction flag, set when the string instructions go down.
+        direction = 10, "df";
+        /// `OF`: the overflow flag.
+        overflow = 11, "of";
+        /// `NT`: the nested task flag.
+        nested_task = 14, "nt";
+        /// `RF`: the resume flag.
+        resume = 16, "rf";
+        /// `VM`: the virtual-8086 mode flag.
+        virtual_8086 = 17, "vm";
+        /// `AC`: the alignment check flag, that also allows the supervisor
+        /// mode accesses to user pages when `SMAP` is on.
+        alignment_check = 18, "ac";
+        /// `VIF`: the virtual interrupt flag.
+        virtual_interrupt = 19, "vif";
+        /// `VIP`: the virtual interrupt pending flag.
+        virtual_interrupt_pending = 20, "vip";
+        /// `ID`: the identification flag.
+        id = 21, "id";
+    }
+}
+
+impl Rflags {
+    /// `IOPL`: the I/O privilege level.
+    pub fn iopl(&self) -> u8 {
+        self.0.bits(12..=13) as u8
+    }
+}
+
+/// Format [`Rflags`] like WinDbg does: the state of `OF`, `DF`, `IF`, `SF`,
+/// `ZF`, `AF`, `PF` and `CF`, like `nv up ei pl nz na po nc`.
+impl Display for Rflags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flags = [
+            (self.overflow(), "ov", "nv"),
+            (self.direction(), "dn", "up"),
+            (self.interrupts_enabled(), "ei", "di"),
+            (self.sign(), "ng", "pl"),
+            (self.zero(), "zr", "nz"),
+            (self.auxiliary_carry(), "ac", "na"),
+            (self.parity(), "pe", "po"),
+            (self.carry(), "cy", "nc"),
+        ];
+
+        for (idx, (set, on, off)) in flags.into_iter().enumerate() {
+            if idx != 0 {
+                f.write_str(" ")?;
+            }
+
+            f.write_str(if set { on } else { off })?;
+        }
+
+        Ok(())
+    }
+}
+
+flags_register! {
+    /// The `CR0` register.
+    Cr0 {
+        /// `PE`: protected mode is on.
+        protection_enabled = 0, "pe";
+        /// `MP`: `wait` checks `TS`.
+        monitor_coprocessor = 1, "mp";
+        /// `EM`: there's no x87 unit.
+        emulation = 2, "em";
+        /// `TS`: a task switch happened, the x87 state is lazily saved.
+        task_switched = 3, "ts";
+        /// `ET`: the x87 unit is a 387.
+        extension_type = 4, "et";
+        /// `NE`: the x87 errors are reported natively.
+        numeric_error = 5, "ne";
+        /// `WP`: the supervisor mode writes honor the read-only pages.
+        write_protect = 16, "wp";
+        /// `AM`: the alignment checks are on when `AC` is set.
+        alignment_mask = 18, "am";
+        /// `NW`: not write-through.
+        not_write_through = 29, "nw";
+        /// `CD`: the caches are disabled.
+        cache_disable = 30, "cd";
+        /// `PG`: paging is on.
+        paging = 31, "pg";
+    }
+}
+
+flags_register! {
+    /// The `CR4` register.
+    Cr4 {
+        /// `VME`: the virtual-8086 mode extensions are on.
+        vme = 0, "vme";
+        /// `PVI`: the protected mode virtual interrupts are on.
+        pvi = 1, "pvi";
+        /// `TSD`: `rdtsc` is restricted to the supervisor mode.
+        tsd = 2, "tsd";
+        /// `DE`: the debugging extensions are on.
+        debugging_extensions = 3, "de";
+        /// `PSE`: the 4MB pages are on.
+        pse = 4, "pse";
+        /// `PAE`: the physical address extension is on.
+        pae = 5, "pae";
+        /// `MCE`: the machine-check exceptions are on.
+        mce = 6, "mce";
+        /// `PGE`: the global pages are on.
+        global_pages = 7, "pge";
+        /// `PCE`: `rdpmc` is allowed in user mode.
+        pce = 8, "pce";
+        /// `OSFXSR`: the OS supports `fxsave` and `fxrstor`.
+        osfxsr = 9, "osfxsr";
+        /// `OSXMMEXCPT`: the OS supports the SIMD floating-point exceptions.
+        osxmmexcpt = 10, "osxmmexcpt";
+        /// `UMIP`: the descriptor table instructions are restricted to the
+        /// supervisor mode.
+        umip = 11, "umip";
+        /// `LA57`: 5-level paging is on.
+        la57 = 12, "la57";
+        /// `VMXE`: VMX is on.
+        vmxe = 13, "vmxe";
+        /// `SMXE`: SMX is on.
+        smxe = 14, "smxe";
+        /// `FSGSBASE`: the `rdfsbase` family of instructions is on.
+        fsgsbase = 16, "fsgsbase";
+        /// `PCIDE`: the process-context identifiers are on.
+        pcide = 17, "pcide";
+        /// `OSXSAVE`: the OS supports `xsave`.
+        osxsave = 18, "osxsave";
+        /// `KL`: Key Locker is on.
+        key_locker = 19, "kl";
+        /// `SMEP`: the supervisor mode can't execute user pages.
+        smep = 20, "smep";
+        /// `SMAP`: the supervisor mode can't access user pages, unless `AC`
+        /// is set.
+        smap = 21, "smap";
+        /// `PKE`: the protection keys are on for the user pages.
+        pke = 22, "pke";
+        /// `CET`: the control-flow enforcement technology is on.
+        cet = 23, "cet";
+        /// `PKS`: the protection keys are on for the supervisor pages.
+        pks = 24, "pks";
+    }
+}
+
+/// Format the control registers as the list of the mnemonics of their flags
+/// that are set, like `pe et ne wp am pg`.
+macro_rules! display_set_flags {
+    ($($name:ident),*) => {
+        $(
+            impl Display for $name {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    let mut set = self.set_flags();
+                    if let Some(first) = set.next() {
+                        f.write_str(first)?;
+                    }
+
+                    for mnemonic in set {
+                        write!(f, " {mnemonic}")?;
+                    }
+
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+display_set_flags!(Cr0, Cr4);
+
+impl Context {
+    /// The `RFLAGS` register.
+    pub fn rflags(&self) -> Rflags {
+        Rflags::from_raw(self.eflags.into())
+    }
+}
+
+impl KernelDumpParser {
+    /// `CR0` of the processor that crashed, saved in the special registers of
+    /// its `_KPRCB`.
+    pub fn cr0(&self) -> Option<Cr0> {
+        self.special_register("ProcessorState.SpecialRegisters.Cr0")
+            .map(|cr0| Cr0::from_raw(cr0.u64()))
+    }
+
+    /// `CR4` of the processor that crashed, saved in the special registers of
+    /// its `_KPRCB`.
+    pub fn cr4(&self) -> Option<Cr4> {
+        self.special_register("ProcessorState.SpecialRegisters.Cr4")
+            .map(|cr4| Cr4::from_raw(cr4.u64()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gxa::Gva;
+    use crate::testing::DumpBuilder;
+    use crate::PxeFlags;
+
+    #[test]
+    fn rflags() {
+        // The `efl` of the context of the regression dumps.
+        let rflags = Rflags::from_raw(0x40202);
+        assert!(rflags.interrupts_enabled());
+        assert!(rflags.alignment_check());
+        assert!(!rflags.parity() && !rflags.zero() && !rflags.carry());
+        assert_eq!(rflags.iopl(), 0);
+        assert_eq!(rflags.to_string(), "nv up ei pl nz na po nc");
+        assert_eq!(
+            Rflags::from_raw(0x3ed7).to_string(),
+            "ov dn ei ng zr ac pe cy"
+        );
+        assert_eq!(Rflags::from_raw(0x3000).iopl(), 3);
+        assert_eq!(Rflags::from_raw(0x1337).raw(), 0x1337);
+        assert_eq!(rflags.set_flags().collect::<Vec<_>>(), ["if", "ac"]);
+    }
+
+    #[test]
+    fn control_registers() {
+        let cr0 = Cr0::from_raw(0x8005_0033);
+        assert!(cr0.paging() && cr0.write_protect() && cr0.protection_enabled());
+        assert!(!cr0.cache_disable());
+        assert_eq!(cr0.to_string(), "pe mp et ne wp am pg");
+
+        let cr4 = Cr4::from_raw(0x37_06f8);
+        assert!(cr4.smep() && cr4.smap() && cr4.pcide() && cr4.osxsave());
+        assert!(!cr4.la57());
+        assert_eq!(
+            cr4.to_string(),
+            "de pse pae mce pge osfxsr osxmmexcpt fsgsbase pcide osxsave smep smap"
+        );
+        assert_eq!(Cr4::default().to_string(), "");
+    }
+
+    #[test]
+    fn special_registers() {
+        let prcb = 0xffff_f800_1000_0000u64;
+        let dump = DumpBuilder::new()
+            .map_virt(prcb, 0x10_000, PxeFlags::Present)
+            .write_virt(prcb + 0x40, &0x8005_0033u64.to_le_bytes())
+            .write_virt(prcb + 0x58, &0x37_06f8u64.to_le_bytes())
+            .build();
+        let mut parser = KernelDumpParser::from_bytes(dump).unwrap();
+        assert_eq!(parser.cr0(), None);
+
+        parser.crashing_prcb = Some((0, Gva::new(prcb)));
+        assert_eq!(parser.cr0(), Some(Cr0::from_raw(0x8005_0033)));
+        assert!(parser.cr4().unwrap().smep());
+        assert!(!parser.context_record().rflags().trap());
+    }
+}
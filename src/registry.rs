@@ -0,0 +1,820 @@
+//! This contains a minimal registry reader: it finds the hives that were
+//! loaded when the dump was taken, walks their keys and reads their values.
+//!
+//! The hives are described by `nt!_CMHIVE` structures that are linked together
+//! off `nt!CmpHiveListHead`. As the KDDEBUGGER_DATA_BLOCK doesn't point to the
+//! list, a hive is first found by scanning physical memory for the `_HHIVE`
+//! signature and the list is walked from there. A lot of the `_CMHIVE` /
+//! `_HHIVE` fields move around between builds, so their offsets are found by
+//! validating candidates instead of being hardcoded.
+//!
+//! Only the stable storage of a hive is supported; volatile keys are reported
+//! as missing.
+use crate::error::Result;
+use crate::gxa::Gxa;
+use crate::structs::{Page, UnicodeString};
+use crate::utf16::{self, StringPolicy};
+use crate::{Gpa, Gva, KdmpParserError, KernelDumpParser};
+
+/// Value of `_HHIVE.Signature`.
+const HHIVE_SIGNATURE: u32 = 0xbe_e0_be_e0;
+
+/// Value of `_HBASE_BLOCK.Signature`.
+const HBASE_BLOCK_SIGNATURE: &[u8; 4] = b"regf";
+
+/// Maximum size of a `_CMHIVE`; it bounds the searches for its fields.
+const CMHIVE_MAX_SIZE: u64 = 0x1_000;
+
+/// Maximum size of a `_HHIVE`; it bounds the search for its storage map.
+const HHIVE_MAX_SIZE: u64 = 0x800;
+
+/// Maximum number of hives we'll walk.
+const MAX_HIVES: usize = 0x400;
+
+/// Maximum number of processes we'll walk.
+const MAX_PROCESSES: usize = 0x10_000;
+
+/// Maximum size of a cell we'll read.
+const MAX_CELL_SIZE: usize = 0x10_0000;
+
+/// Maximum size of a value we'll read.
+const MAX_VALUE_SIZE: usize = 0x100_0000;
+
+/// Size of the segments of a big data value (`_CM_BIG_DATA`).
+const BIG_DATA_SEGMENT_SIZE: usize = 16_344;
+
+/// ```text
+/// kd> dt nt!_HHIVE BaseBlock
+///    +0x040 BaseBlock : Ptr64 _HBASE_BLOCK
+/// ```
+const HHIVE_BASE_BLOCK: u64 = 0x40;
+
+/// ```text
+/// kd> dt nt!_HBASE_BLOCK RootCell FileName
+///    +0x024 RootCell : Uint4B
+///    +0x030 FileName : [64] UChar
+/// ```
+const HBASE_BLOCK_ROOT_CELL: u64 = 0x24;
+const HBASE_BLOCK_FILE_NAME: u64 = 0x30;
+
+/// ```text
+/// kd> dt nt!_HMAP_ENTRY
+///    +0x000 BlockOffset         : Uint8B
+///    +0x008 PermanentBinAddress : Uint8B
+///    +0x010 MemAlloc            : Uint4B
+/// ```
+const HMAP_ENTRY_SIZE: u64 = 0x18;
+const HMAP_ENTRY_PERMANENT_BIN_ADDRESS: u64 = 0x8;
+
+/// `KEY_HIVE_ENTRY`: the key is the root of a hive.
+const KEY_HIVE_ENTRY: u16 = 0x4;
+
+/// `KEY_COMP_NAME`: the key name is stored as ASCII.
+const KEY_COMP_NAME: u16 = 0x20;
+
+/// `VALUE_COMP_NAME`: the value name is stored as ASCII.
+const VALUE_COMP_NAME: u16 = 0x1;
+
+/// Cell indices with this bit set live in the volatile storage.
+const CELL_VOLATILE: u32 = 0x8000_0000;
+
+/// Values with this bit set in their length have their data stored inline.
+const VALUE_DATA_INLINE: u32 = 0x8000_0000;
+
+/// A registry hive loaded when the dump was taken.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hive {
+    /// Name of the hive, like `\REGISTRY\MACHINE\SYSTEM`.
+    pub name: String,
+    /// Address of the `nt!_CMHIVE`.
+    pub base: Gva,
+    /// Directory table base of the `Registry` process; on recent builds the
+    /// hive bins are mapped in its address space.
+    dtb: Option<Gpa>,
+}
+
+/// A registry value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegValue {
+    /// `REG_SZ`.
+    String(String),
+    /// `REG_EXPAND_SZ`.
+    ExpandString(String),
+    /// `REG_MULTI_SZ`.
+    MultiString(Vec<String>),
+    /// `REG_DWORD` & `REG_DWORD_BIG_ENDIAN`.
+    Dword(u32),
+    /// `REG_QWORD`.
+    Qword(u64),
+    /// `REG_BINARY`.
+    Binary(Vec<u8>),
+    /// Any other type of value.
+    Other { kind: u32, data: Vec<u8> },
+}
+
+impl RegValue {
+    /// Decode the data of a value of type `kind`.
+    fn new(kind: u32, data: Vec<u8>, policy: StringPolicy) -> Result<Self> {
+        Ok(match (kind, data.len()) {
+            (1, _) => Self::String(utf16_string(&data, policy)?),
+            (2, _) => Self::ExpandString(utf16_string(&data, policy)?),
+            (3, _) => Self::Binary(data),
+            (4, 4..) => Self::Dword(u32::from_le_bytes(data[..4].try_into().unwrap())),
+            (5, 4..) => Self::Dword(u32::from_be_bytes(data[..4].try_into().unwrap())),
+            (7, _) => Self::MultiString(
+                utf16::units(&data)
+                    .split(|&c| c == 0)
+                    .take_while(|s| !s.is_empty())
+                    .map(|s| Ok(utf16::decode(s, policy)?.0))
+                    .collect::<Result<_>>()?,
+            ),
+            (11, 8..) => Self::Qword(u64::from_le_bytes(data[..8].try_into().unwrap())),
+            _ => Self::Other { kind, data },
+        })
+    }
+}
+
+/// A registry key.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Key {
+    /// Names of the sub keys.
+    pub subkeys: Vec<String>,
+    /// Names and values of the values.
+    pub values: Vec<(String, RegValue)>,
+    /// Is any sub key or value missing because it couldn't be read?
+    pub partial: bool,
+}
+
+/// Is this an address in kernel space?
+fn is_kernel_address(addr: u64) -> bool {
+    addr >> 47 == 0x1_ff_ff
+}
+
+/// Compute the address of a field at `offset` from `base`.
+fn field(base: Gva, offset: u64) -> Result<Gva> {
+    base.u64()
+        .checked_add(offset)
+        .map(Gva::new)
+        .ok_or(KdmpParserError::Overflow("registry field"))
+}
+
+/// Is this an error that can be caused by a paged out or corrupted cell?
+fn is_missing(e: &KdmpParserError) -> bool {
+    matches!(
+        e,
+        KdmpParserError::AddrTranslation(..)
+            | KdmpParserError::PartialVirtRead
+            | KdmpParserError::PartialPhysRead
+            | KdmpParserError::InvalidData(..)
+    )
+}
+
+/// Read a [`u16`] at `offset` in a cell.
+fn cell_u16(data: &[u8], offset: usize) -> Result<u16> {
+    data.get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or(KdmpParserError::InvalidData("truncated cell"))
+}
+
+/// Read a [`u32`] at `offset` in a cell.
+fn cell_u32(data: &[u8], offset: usize) -> Result<u32> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or(KdmpParserError::InvalidData("truncated cell"))
+}
+
+/// Decode a NUL-terminated UTF-16LE string.
+fn utf16_string(data: &[u8], policy: StringPolicy) -> Result<String> {
+    let units = utf16::units(data);
+    let len = units.iter().position(|&c| c == 0).unwrap_or(units.len());
+
+    Ok(utf16::decode(&units[..len], policy)?.0)
+}
+
+/// Decode the name of a key or a value; compressed names are stored as ASCII.
+fn decode_name(data: &[u8], compressed: bool, policy: StringPolicy) -> Result<String> {
+    if compressed {
+        Ok(data.iter().map(|&b| char::from(b)).collect())
+    } else {
+        utf16_string(data, policy)
+    }
+}
+
+/// The fields of a key node (`_CM_KEY_NODE`) we care about.
+struct KeyNode {
+    flags: u16,
+    subkey_counts: [u32; 2],
+    subkey_lists: [u32; 2],
+    value_count: u32,
+    value_list: u32,
+    name: String,
+}
+
+/// Gives access to the cells of a hive.
+struct HiveReader<'parser> {
+    parser: &'parser KernelDumpParser,
+    /// Directory table base of the `Registry` process.
+    dtb: Option<Gpa>,
+    /// `_HHIVE.Storage[Stable].Map`, the `_HMAP_DIRECTORY` of the hive.
+    map: Gva,
+}
+
+impl HiveReader<'_> {
+    /// How the strings of the hive are decoded.
+    fn policy(&self) -> StringPolicy {
+        self.parser.options.string_policy
+    }
+
+    /// Read a bin. They're either mapped in kernel space, or in the `Registry`
+    /// process.
+    fn read(&self, gva: Gva, buffer: &mut [u8]) -> Result<()> {
+        match self.dtb {
+            Some(dtb) if !is_kernel_address(gva.u64()) => {
+                self.parser.virt_read_exact_with_dtb(gva, buffer, dtb)
+            }
+            _ => self.parser.virt_read_exact(gva, buffer),
+        }
+    }
+
+    /// Turn a cell index into the address of the cell.
+    fn cell_addr(&self, index: u32) -> Result<Gva> {
+        if index & CELL_VOLATILE != 0 {
+            return Err(KdmpParserError::InvalidData("volatile cell"));
+        }
+
+        // A cell index is made of a directory index (bits 30:21), a table index (bits
+        // 20:12) and an offset in the block (bits 11:0).
+        let directory_idx = u64::from((index >> 21) & 0x3_ff);
+        let table_idx = u64::from((index >> 12) & 0x1_ff);
+        let offset = u64::from(index & 0xf_ff);
+        let table = self
+            .parser
+            .virt_read_ptr(field(self.map, directory_idx * 8)?)?;
+        let entry = field(table, table_idx * HMAP_ENTRY_SIZE)?;
+        let block_offset = self.parser.virt_read_struct::<u64>(entry)?;
+        let bin = self
+            .parser
+            .virt_read_struct::<u64>(field(entry, HMAP_ENTRY_PERMANENT_BIN_ADDRESS)?)?;
+
+        // The low bits of the bin address are flags.
+        let bin = bin & !0xf;
+        if bin == 0 {
+            return Err(KdmpParserError::InvalidData("unmapped bin"));
+        }
+
+        field(Gva::new(bin), block_offset)
+            .and_then(|block| field(block, offset))
+            .map_err(|_| KdmpParserError::Overflow("cell address"))
+    }
+
+    /// Read the content of a cell.
+    fn cell(&self, index: u32) -> Result<Vec<u8>> {
+        let addr = self.cell_addr(index)?;
+        let mut size = [0; 4];
+        self.read(addr, &mut size)?;
+
+        // Allocated cells have a negative size, and it accounts for the size field.
+        let size = i32::from_le_bytes(size).unsigned_abs() as usize;
+        let len = size
+            .checked_sub(4)
+            .filter(|&len| len <= MAX_CELL_SIZE)
+            .ok_or(KdmpParserError::InvalidData("invalid cell size"))?;
+
+        let mut data = vec![0; len];
+        self.read(field(addr, 4)?, &mut data)?;
+
+        Ok(data)
+    }
+
+    /// Read a key node.
+    fn key_node(&self, index: u32) -> Result<KeyNode> {
+        // ```text
+        // kd> dt nt!_CM_KEY_NODE
+        //    +0x000 Signature        : Uint2B
+        //    +0x002 Flags            : Uint2B
+        //    +0x014 SubKeyCounts     : [2] Uint4B
+        //    +0x01c SubKeyLists      : [2] Uint4B
+        //    +0x024 ValueList        : _CHILD_LIST
+        //    +0x048 NameLength       : Uint2B
+        //    +0x04c Name             : [1] Wchar
+        // ```
+        let data = self.cell(index)?;
+        if !data.starts_with(b"nk") {
+            return Err(KdmpParserError::InvalidData("invalid key node"));
+        }
+
+        let flags = cell_u16(&data, 0x2)?;
+        let name_len = usize::from(cell_u16(&data, 0x48)?);
+        let name = data
+            .get(0x4c..0x4c + name_len)
+            .ok_or(KdmpParserError::InvalidData("truncated key name"))?;
+
+        Ok(KeyNode {
+            flags,
+            subkey_counts: [cell_u32(&data, 0x14)?, cell_u32(&data, 0x18)?],
+            subkey_lists: [cell_u32(&data, 0x1c)?, cell_u32(&data, 0x20)?],
+            value_count: cell_u32(&data, 0x24)?,
+            value_list: cell_u32(&data, 0x28)?,
+            name: decode_name(name, flags & KEY_COMP_NAME != 0, self.policy())?,
+        })
+    }
+
+    /// Collect the key node indices of a sub key list.
+    fn subkey_list(
+        &self,
+        index: u32,
+        indices: &mut Vec<u32>,
+        nested: bool,
+        partial: &mut bool,
+    ) -> Result<()> {
+        let data = match self.cell(index) {
+            Ok(data) => data,
+            Err(e) if is_missing(&e) => {
+                *partial = true;
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+
+        // `lf` & `lh` lists have a hash after every index, `li` don't and `ri` are
+        // lists of lists.
+        let stride = match data.get(..2) {
+            Some(b"lf" | b"lh") => 8,
+            Some(b"li") | Some(b"ri") if !nested => 4,
+            _ => {
+                *partial = true;
+                return Ok(());
+            }
+        };
+
+        let count = usize::from(cell_u16(&data, 0x2)?);
+        for idx in 0..count {
+            let Ok(entry) = cell_u32(&data, 4 + (idx * stride)) else {
+                *partial = true;
+                break;
+            };
+
+            if data.starts_with(b"ri") {
+                self.subkey_list(entry, indices, true, partial)?;
+            } else {
+                indices.push(entry);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the names and indices of the sub keys of a key.
+    fn subkeys(&self, node: &KeyNode, partial: &mut bool) -> Result<Vec<(String, u32)>> {
+        // Volatile sub keys aren't supported.
+        if node.subkey_counts[1] != 0 {
+            *partial = true;
+        }
+
+        let mut indices = Vec::new();
+        if node.subkey_counts[0] != 0 {
+            self.subkey_list(node.subkey_lists[0], &mut indices, false, partial)?;
+        }
+
+        let mut subkeys = Vec::with_capacity(indices.len());
+        for index in indices {
+            match self.key_node(index) {
+                Ok(subkey) => subkeys.push((subkey.name, index)),
+                Err(e) if is_missing(&e) => *partial = true,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(subkeys)
+    }
+
+    /// Read the data of a value.
+    fn value_data(&self, index: u32, len: usize) -> Result<Vec<u8>> {
+        if len > MAX_VALUE_SIZE {
+            return Err(KdmpParserError::InvalidData("value too large"));
+        }
+
+        self.parser.check_read_size(len as u64)?;
+
+        let mut data = self.cell(index)?;
+
+        // Large values are split in segments: the cell is a `_CM_BIG_DATA` that points
+        // to a list of segments.
+        if len > BIG_DATA_SEGMENT_SIZE && data.starts_with(b"db") {
+            let count = usize::from(cell_u16(&data, 0x2)?);
+            let segments = self.cell(cell_u32(&data, 0x4)?)?;
+            data = Vec::with_capacity(len);
+            for segment in segments.chunks_exact(4).take(count) {
+                let segment = self.cell(u32::from_le_bytes(segment.try_into().unwrap()))?;
+                let wanted = (len - data.len()).min(BIG_DATA_SEGMENT_SIZE);
+                data.extend_from_slice(segment.get(..wanted).unwrap_or(&segment));
+            }
+        }
+
+        if data.len() < len {
+            return Err(KdmpParserError::InvalidData("truncated value"));
+        }
+
+        data.truncate(len);
+
+        Ok(data)
+    }
+
+    /// Read a value.
+    fn value(&self, index: u32) -> Result<(String, RegValue)> {
+        // ```text
+        // kd> dt nt!_CM_KEY_VALUE
+        //    +0x000 Signature        : Uint2B
+        //    +0x002 NameLength       : Uint2B
+        //    +0x004 DataLength       : Uint4B
+        //    +0x008 Data             : Uint4B
+        //    +0x00c Type             : Uint4B
+        //    +0x010 Flags            : Uint2B
+        //    +0x014 Name             : [1] Wchar
+        // ```
+        let data = self.cell(index)?;
+        if !data.starts_with(b"vk") {
+            return Err(KdmpParserError::InvalidData("invalid key value"));
+        }
+
+        let name_len = usize::from(cell_u16(&data, 0x2)?);
+        let data_len = cell_u32(&data, 0x4)?;
+        let kind = cell_u32(&data, 0xc)?;
+        let flags = cell_u16(&data, 0x10)?;
+        let name = data
+            .get(0x14..0x14 + name_len)
+            .ok_or(KdmpParserError::InvalidData("truncated value name"))?;
+        let name = decode_name(name, flags & VALUE_COMP_NAME != 0, self.policy())?;
+
+        // Small values are stored directly in the `Data` field.
+        let len = (data_len & !VALUE_DATA_INLINE) as usize;
+        let value = if data_len & VALUE_DATA_INLINE != 0 {
+            data[0x8..0xc][..len.min(4)].to_vec()
+        } else if len == 0 {
+            Vec::new()
+        } else {
+            self.value_data(cell_u32(&data, 0x8)?, len)?
+        };
+
+        Ok((name, RegValue::new(kind, value, self.policy())?))
+    }
+
+    /// Get the values of a key.
+    fn values(&self, node: &KeyNode, partial: &mut bool) -> Result<Vec<(String, RegValue)>> {
+        if node.value_count == 0 {
+            return Ok(Vec::new());
+        }
+
+        let list = match self.cell(node.value_list) {
+            Ok(list) => list,
+            Err(e) if is_missing(&e) => {
+                *partial = true;
+                return Ok(Vec::new());
+            }
+            Err(e) => return Err(e),
+        };
+
+        // The count comes from the dump: only what the list has room for is
+        // read.
+        let count = node.value_count as usize;
+        if list.len() / 4 < count {
+            *partial = true;
+        }
+
+        let count = count.min(list.len() / 4);
+        let mut values = Vec::with_capacity(count);
+        for index in list.chunks_exact(4).take(count) {
+            match self.value(u32::from_le_bytes(index.try_into().unwrap())) {
+                Ok(value) => values.push(value),
+                Err(e) if is_missing(&e) => *partial = true,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(values)
+    }
+}
+
+impl KernelDumpParser {
+    /// Get the registry hives that were loaded when the dump was taken.
+    ///
+    /// Finding the list of hives requires scanning physical memory, so this
+    /// can take a little while on large dumps.
+    pub fn hives(&self) -> Result<Vec<Hive>> {
+        let dtb = self.registry_process_dtb()?;
+        let (anchor, list_offset) = self
+            .find_hive_list()?
+            .ok_or(KdmpParserError::NotFound("the hive list"))?;
+
+        // Walk the list starting from the hive we found; the only entry that isn't a
+        // hive is the list head, `nt!CmpHiveListHead`.
+        let mut bases = vec![anchor];
+        for base in self.walk_list(field(anchor, list_offset)?, list_offset, MAX_HIVES) {
+            bases.push(base?);
+        }
+
+        // Start from the list head so that the hives are in the list order.
+        let head = bases
+            .iter()
+            .map(|&base| self.is_hive(base))
+            .collect::<Result<Vec<_>>>()?
+            .iter()
+            .position(|is_hive| !is_hive)
+            .unwrap_or_default();
+        bases.rotate_left(head);
+
+        let mut hives = Vec::with_capacity(bases.len());
+        for base in bases {
+            if !self.is_hive(base)? {
+                continue;
+            }
+
+            hives.push(Hive {
+                name: self.hive_name(base)?,
+                base,
+                dtb,
+            });
+        }
+
+        Ok(hives)
+    }
+
+    /// Read a registry key; `path` is relative to the root of the hive, like
+    /// `ControlSet001\Control`. Sub keys or values that can't be read (because
+    /// they are paged out for example) are skipped and the key is marked as
+    /// [`Key::partial`].
+    pub fn hive_read_key(&self, hive: &Hive, path: &str) -> Result<Key> {
+        let (reader, root) = self.hive_reader(hive)?;
+        let mut node = reader.key_node(root)?;
+        for name in path.split('\\').filter(|name| !name.is_empty()) {
+            let name = name.to_lowercase();
+            let mut partial = false;
+            let Some((_, index)) = reader
+                .subkeys(&node, &mut partial)?
+                .into_iter()
+                .find(|(subkey, _)| subkey.to_lowercase() == name)
+            else {
+                return Err(KdmpParserError::RegistryKeyNotFound(path.into()));
+            };
+
+            node = reader.key_node(index)?;
+        }
+
+        let mut key = Key::default();
+        key.subkeys = reader
+            .subkeys(&node, &mut key.partial)?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        key.values = reader.values(&node, &mut key.partial)?;
+
+        Ok(key)
+    }
+
+    /// Does `gva` point to a `_HHIVE`?
+    fn is_hive(&self, gva: Gva) -> Result<bool> {
+        Ok(self.try_virt_read_struct::<u32>(gva)? == Some(HHIVE_SIGNATURE))
+    }
+
+    /// Find the directory table base of the `Registry` process, if there's
+    /// one.
+    fn registry_process_dtb(&self) -> Result<Option<Gpa>> {
+        let head = self.headers().ps_active_process_head.into();
+        let links = self.profile().offset("_EPROCESS", "ActiveProcessLinks")?;
+        for eprocess in self.walk_list(head, links, MAX_PROCESSES) {
+            // If we can't walk the process list, we'll carry on without it; hives might
+            // be mapped in kernel space anyways.
+            let Ok(eprocess) = eprocess else {
+                return Ok(None);
+            };
+
+            let name = self.field_addr(eprocess, "_EPROCESS", "ImageFileName")?;
+            let Some(name) = self.try_virt_read_struct::<[u8; 15]>(name)? else {
+                continue;
+            };
+
+            if name.starts_with(b"Registry\0") {
+                let dtb = self.field_addr(eprocess, "_EPROCESS", "DirectoryTableBase")?;
+
+                return Ok(self.try_virt_read_struct::<u64>(dtb)?.map(Gpa::new));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Find a hive by scanning physical memory. It returns the address of the
+    /// `_CMHIVE` as well as the offset of its `HiveList` field.
+    fn find_hive_list(&self) -> Result<Option<(Gva, u64)>> {
+        let mut page = vec![0; Page::size() as usize];
+        for (gpa, _) in self.physmem() {
+            if self.phys_read(gpa, &mut page)? != page.len() {
+                continue;
+            }
+
+            // Pool allocations are 16 bytes aligned.
+            for offset in (0..page.len()).step_by(0x10) {
+                if page[offset..offset + 4] != HHIVE_SIGNATURE.to_le_bytes() {
+                    continue;
+                }
+
+                let hive = Gpa::new(gpa.u64() + offset as u64);
+                if let Some(found) = self.find_hive_list_offset(&page[offset..], hive)? {
+                    return Ok(Some(found));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Find the offset of `_CMHIVE.HiveList` for the hive at `hive`; `data` is
+    /// its content until the end of its physical page.
+    fn find_hive_list_offset(&self, data: &[u8], hive: Gpa) -> Result<Option<(Gva, u64)>> {
+        for list_offset in (8..CMHIVE_MAX_SIZE).step_by(8) {
+            let Some(flink) = data.get(list_offset as usize..list_offset as usize + 8) else {
+                break;
+            };
+
+            let flink = u64::from_le_bytes(flink.try_into().unwrap());
+            if !is_kernel_address(flink) {
+                continue;
+            }
+
+            // The `Blink` of the next entry points back to us, which gives us our own
+            // virtual address..
+            let Some(blink) = self.try_virt_read_struct::<u64>(field(flink.into(), 8)?)? else {
+                continue;
+            };
+
+            // ..unless the list is empty.
+            if blink == flink {
+                continue;
+            }
+
+            let Some(base) = blink.checked_sub(list_offset).map(Gva::new) else {
+                continue;
+            };
+
+            match self.virt_translate(base) {
+                Ok(gpa) if gpa == hive => {}
+                Ok(_) | Err(KdmpParserError::AddrTranslation(..)) => continue,
+                Err(e) => return Err(e),
+            }
+
+            // Make sure this is the hive list: every entry but the list head has to be
+            // a hive.
+            let mut not_hives = 0;
+            for entry in self.walk_list(blink.into(), list_offset, MAX_HIVES) {
+                let Ok(entry) = entry else {
+                    not_hives = usize::MAX;
+                    break;
+                };
+
+                if !self.is_hive(entry)? {
+                    not_hives += 1;
+                }
+            }
+
+            if not_hives == 1 {
+                return Ok(Some((base, list_offset)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Get the name of a hive. We look for the `_CMHIVE.HiveRootPath` string,
+    /// and fall back to the file name stored in its base block.
+    fn hive_name(&self, base: Gva) -> Result<String> {
+        for offset in (0..CMHIVE_MAX_SIZE).step_by(8) {
+            let Some(s) = self.try_virt_read_struct::<UnicodeString>(field(base, offset)?)? else {
+                continue;
+            };
+
+            if s.length == 0
+                || s.length % 2 != 0
+                || s.length > s.maximum_length
+                || s.maximum_length > 0x4_00
+                || !is_kernel_address(s.buffer)
+            {
+                continue;
+            }
+
+            let Ok(Some(name)) = self.try_virt_read_unicode_string(&s) else {
+                continue;
+            };
+
+            if name.to_uppercase().starts_with("\\REGISTRY\\") {
+                return Ok(name);
+            }
+        }
+
+        let Some(base_block) = self.try_virt_read_struct::<u64>(field(base, HHIVE_BASE_BLOCK)?)?
+        else {
+            return Ok(String::new());
+        };
+
+        let file_name = field(base_block.into(), HBASE_BLOCK_FILE_NAME)?;
+        let Some(file_name) = self.try_virt_read_struct::<[u8; 64]>(file_name)? else {
+            return Ok(String::new());
+        };
+
+        utf16_string(&file_name, self.options.string_policy)
+    }
+
+    /// Get a [`HiveReader`] for a hive, as well as the index of its root cell.
+    fn hive_reader(&self, hive: &Hive) -> Result<(HiveReader<'_>, u32)> {
+        let base_block = self.virt_read_ptr(field(hive.base, HHIVE_BASE_BLOCK)?)?;
+        if &self.virt_read_struct::<[u8; 4]>(base_block)? != HBASE_BLOCK_SIGNATURE {
+            return Err(KdmpParserError::InvalidData("invalid hive base block"));
+        }
+
+        let root = self.virt_read_struct::<u32>(field(base_block, HBASE_BLOCK_ROOT_CELL)?)?;
+
+        // Look for `_HHIVE.Storage[Stable].Map`; the right one is the one that gives us
+        // the root key of the hive.
+        for offset in (HHIVE_BASE_BLOCK + 8..HHIVE_MAX_SIZE).step_by(8) {
+            let Some(map) = self.try_virt_read_struct::<u64>(field(hive.base, offset)?)? else {
+                continue;
+            };
+
+            if !is_kernel_address(map) {
+                continue;
+            }
+
+            let reader = HiveReader {
+                parser: self,
+                dtb: hive.dtb,
+                map: map.into(),
+            };
+
+            if reader
+                .key_node(root)
+                .is_ok_and(|node| node.flags & KEY_HIVE_ENTRY != 0)
+            {
+                return Ok((reader, root));
+            }
+        }
+
+        Err(KdmpParserError::NotFound("the hive storage map"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16(s: &str) -> Vec<u8> {
+        s.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    fn value(kind: u32, data: Vec<u8>) -> RegValue {
+        RegValue::new(kind, data, StringPolicy::Lossy).unwrap()
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(
+            value(1, utf16("Windows 10 Pro\0")),
+            RegValue::String("Windows 10 Pro".into())
+        );
+        assert_eq!(
+            value(2, utf16("%SystemRoot%\0\0")),
+            RegValue::ExpandString("%SystemRoot%".into())
+        );
+        assert_eq!(
+            value(7, utf16("a\0bc\0\0")),
+            RegValue::MultiString(vec!["a".into(), "bc".into()])
+        );
+        assert_eq!(value(4, vec![0x37, 0x13, 0, 0]), RegValue::Dword(0x13_37));
+        assert_eq!(value(5, vec![0, 0, 0x13, 0x37]), RegValue::Dword(0x13_37));
+        assert_eq!(
+            value(11, 0x1122_3344_5566_7788u64.to_le_bytes().to_vec()),
+            RegValue::Qword(0x1122_3344_5566_7788)
+        );
+        assert_eq!(value(3, vec![1, 2]), RegValue::Binary(vec![1, 2]));
+        assert_eq!(value(4, vec![1, 2]), RegValue::Other {
+            kind: 4,
+            data: vec![1, 2]
+        });
+    }
+
+    #[test]
+    fn names() {
+        let lossy = StringPolicy::Lossy;
+        assert_eq!(
+            decode_name(b"ControlSet001", true, lossy).unwrap(),
+            "ControlSet001"
+        );
+        assert_eq!(
+            decode_name(&utf16("Caf\u{e9}"), false, lossy).unwrap(),
+            "Caf\u{e9}"
+        );
+
+        // A lone surrogate.
+        let invalid = [0x61, 0, 0x00, 0xd8];
+        assert_eq!(decode_name(&invalid, false, lossy).unwrap(), "a\u{fffd}");
+        assert!(decode_name(&invalid, false, StringPolicy::Strict).is_err());
+    }
+}
@@ -0,0 +1,300 @@
+//! This contains what is needed to compare the protection the pages of a
+//! module are mapped with to the one its section headers ask for (see
+//! [`SectionProtection`]); code pages that became writable, or data pages that
+//! became executable, are what inline hooks and patches leave behind.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::{Gxa, KernelDumpParser};
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! for module in parser.kernel_module_entries() {
+//!     let Ok(report) = parser.module_protection_report(module) else {
+//!         continue;
+//!     };
+//!
+//!     for section in report {
+//!         for (range, protection) in section.mismatches() {
+//!             println!(
+//!                 "{}!{}: {:#x}-{:#x} is {protection}, expected {}",
+//!                 module.name,
+//!                 section.name,
+//!                 range.start.u64(),
+//!                 range.end.u64(),
+//!                 section.expected
+//!             );
+//!         }
+//!     }
+//! }
+//! ```
+use std::ops::Range;
+
+use crate::error::Result;
+use crate::gxa::Gxa;
+use crate::memory_map::Protection;
+use crate::pe::{section_headers, IMAGE_SCN_MEM_EXECUTE, IMAGE_SCN_MEM_WRITE};
+use crate::structs::Page;
+use crate::{Gpa, Gva, KernelDumpParser, ModuleEntry};
+
+/// The name of the entry of the headers of the image.
+const HEADERS: &str = "(headers)";
+
+/// The name of the entries of the pages of the image no section covers.
+const OUTSIDE_SECTIONS: &str = "(outside sections)";
+
+/// How a section of a module is mapped, compared to what its header asks for;
+/// see [`KernelDumpParser::module_protection_report`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionProtection {
+    /// Name of the section, `(headers)` for the headers of the image and
+    /// `(outside sections)` for the pages of the image no section covers.
+    pub name: String,
+    /// Where the section is mapped, rounded to pages.
+    pub range: Range<Gva>,
+    /// The protection the section header asks for.
+    pub expected: Protection,
+    /// The protection of the pages of the section that are mapped, coalesced
+    /// in ranges; the pages that aren't mapped aren't in there.
+    pub actual: Vec<(Range<Gva>, Protection)>,
+}
+
+impl SectionProtection {
+    /// The ranges of the section that are mapped with more permissions than
+    /// expected, like code that is writable.
+    pub fn mismatches(&self) -> impl Iterator<Item = &(Range<Gva>, Protection)> + '_ {
+        self.actual.iter().filter(|(_, actual)| {
+            (actual.writable && !self.expected.writable)
+                || (actual.executable && !self.expected.executable)
+        })
+    }
+
+    /// Number of bytes of the section that aren't mapped, or whose page tables
+    /// aren't in the dump.
+    pub fn missing(&self) -> u64 {
+        let mapped = self
+            .actual
+            .iter()
+            .map(|(range, _)| range.end.u64() - range.start.u64())
+            .sum::<u64>();
+
+        (self.range.end.u64() - self.range.start.u64()) - mapped
+    }
+}
+
+/// Round `size` up to the next page.
+fn page_round_up(size: u64) -> u64 {
+    (size + (Page::size() - 1)) & !(Page::size() - 1)
+}
+
+impl KernelDumpParser {
+    /// Compare the protection the pages of `module` are mapped with in the
+    /// default address space to the one its section headers ask for. There is
+    /// an entry for the headers, one for every section, and one for every
+    /// range of the image no section covers, sorted by address.
+    ///
+    /// The pages of a module that aren't mapped, like the ones of the
+    /// discardable sections, or whose page tables aren't in the dump are
+    /// left out of [`SectionProtection::actual`]
+    /// (see [`SectionProtection::missing`]); the headers have to be though.
+    pub fn module_protection_report(&self, module: &ModuleEntry) -> Result<Vec<SectionProtection>> {
+        let base = module.at.start;
+        let end = module.at.end.u64();
+        let pe_headers = self.pe_headers(base)?;
+        let size_of_headers = page_round_up(pe_headers.size_of_headers.into())
+            .max(Page::size())
+            .min(end - base.u64());
+        let headers = self.virt_read_to_vec(base, size_of_headers)?;
+
+        let read_only = Protection {
+            writable: false,
+            executable: false,
+        };
+
+        let mut sections = vec![(
+            HEADERS.to_string(),
+            base.u64()..base.u64() + size_of_headers,
+            read_only,
+        )];
+
+        for header in section_headers(&headers)? {
+            // The loader maps `SizeOfRawData` bytes when `VirtualSize` is zero.
+            let size = match header.virtual_size {
+                0 => header.size_of_raw_data,
+                size => size,
+            };
+
+            let start = base.u64().saturating_add(header.virtual_address.into());
+            let section_end = start.saturating_add(page_round_up(size.into())).min(end);
+            if start >= section_end {
+                continue;
+            }
+
+            let expected = Protection {
+                writable: header.characteristics & IMAGE_SCN_MEM_WRITE != 0,
+                executable: header.characteristics & IMAGE_SCN_MEM_EXECUTE != 0,
+            };
+
+            sections.push((header.name, start..section_end, expected));
+        }
+
+        sections.sort_by_key(|(_, range, _)| range.start);
+
+        // Account for the pages no section covers.
+        let mut outside = Vec::new();
+        let mut covered = base.u64();
+        for (_, range, _) in &sections {
+            if range.start > covered {
+                outside.push((
+                    OUTSIDE_SECTIONS.to_string(),
+                    covered..range.start,
+                    read_only,
+                ));
+            }
+
+            covered = covered.max(range.end);
+        }
+
+        if covered < end {
+            outside.push((OUTSIDE_SECTIONS.to_string(), covered..end, read_only));
+        }
+
+        sections.extend(outside);
+        sections.sort_by_key(|(_, range, _)| range.start);
+
+        let dtb = self.default_dtb().page_align();
+        sections
+            .into_iter()
+            .map(|(name, range, expected)| {
+                Ok(SectionProtection {
+                    name,
+                    range: Gva::new(range.start)..Gva::new(range.end),
+                    expected,
+                    actual: self.range_protections(range, dtb)?,
+                })
+            })
+            .collect()
+    }
+
+    /// The protection of the pages of `range` mapped in the address space
+    /// whose PML4 is at `dtb`, coalesced in ranges.
+    fn range_protections(
+        &self,
+        range: Range<u64>,
+        dtb: Gpa,
+    ) -> Result<Vec<(Range<Gva>, Protection)>> {
+        let mut protections = Vec::<(Range<Gva>, Protection)>::new();
+        let mut gva = range.start;
+        while gva < range.end {
+            let Some((protection, size)) = self.page_protection(Gva::new(gva), dtb)? else {
+                gva += Page::size();
+                continue;
+            };
+
+            // A large page can start before the range, and end after it.
+            let next = ((gva & !(size - 1)) + size).min(range.end);
+            match protections.last_mut() {
+                Some((last, last_protection))
+                    if last.end.u64() == gva && *last_protection == protection =>
+                {
+                    last.end = Gva::new(next);
+                }
+                _ => protections.push((Gva::new(gva)..Gva::new(next), protection)),
+            }
+
+            gva = next;
+        }
+
+        Ok(protections)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::DumpBuilder;
+    use crate::{Gva, KernelDumpParser, Protection, PxeFlags};
+
+    /// An image with a `.text` section at 0x1000, and a `.data` section at
+    /// 0x2000 that is 0x800 bytes long; the page at 0x3000 isn't in any
+    /// section.
+    fn image() -> Vec<u8> {
+        let mut page = vec![0; 0x1_000];
+        page[0..2].copy_from_slice(b"MZ");
+        page[0x3c..0x40].copy_from_slice(&0x80u32.to_le_bytes());
+        page[0x80..0x84].copy_from_slice(b"PE\0\0");
+        page[0x84..0x86].copy_from_slice(&0x8664u16.to_le_bytes());
+        page[0x86..0x88].copy_from_slice(&2u16.to_le_bytes());
+        page[0x94..0x96].copy_from_slice(&0xf0u16.to_le_bytes());
+        page[0x98..0x9a].copy_from_slice(&0x20bu16.to_le_bytes());
+        page[0xd0..0xd4].copy_from_slice(&0x4_000u32.to_le_bytes());
+        page[0xd4..0xd8].copy_from_slice(&0x400u32.to_le_bytes());
+        let sections: [(&[u8], u32, u32, u32); 2] = [
+            (b".text", 0x1_000, 0x1_000, 0x6000_0020),
+            (b".data", 0x800, 0x2_000, 0xc000_0040),
+        ];
+
+        for (idx, (name, size, rva, characteristics)) in sections.into_iter().enumerate() {
+            let section = 0x188 + (idx * 0x28);
+            page[section..section + name.len()].copy_from_slice(name);
+            page[section + 0x8..section + 0xc].copy_from_slice(&size.to_le_bytes());
+            page[section + 0xc..section + 0x10].copy_from_slice(&rva.to_le_bytes());
+            page[section + 0x24..section + 0x28].copy_from_slice(&characteristics.to_le_bytes());
+        }
+
+        page
+    }
+
+    #[test]
+    fn module_protection_report() {
+        let nt = 0xffff_f800_0000_0000;
+        let read_only = PxeFlags::Present | PxeFlags::NoExecute;
+        let dump = DumpBuilder::new()
+            .write_phys(0x10_000, &image())
+            .map_virt(nt, 0x10_000, read_only)
+            // The code has been made writable..
+            .map_virt(
+                nt + 0x1_000,
+                0x11_000,
+                PxeFlags::Present | PxeFlags::Writable,
+            )
+            // ..the data isn't mapped..
+            // ..and there is code outside of the sections.
+            .map_virt(nt + 0x3_000, 0x13_000, PxeFlags::Present)
+            .module(nt..nt + 0x4_000, "ntoskrnl.exe")
+            .build();
+
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+        let module = parser.kernel_module_entries().next().unwrap();
+        let report = parser.module_protection_report(module).unwrap();
+        let protection = |writable, executable| Protection {
+            writable,
+            executable,
+        };
+
+        assert_eq!(
+            report
+                .iter()
+                .map(|section| (section.name.as_str(), section.range.start, section.expected))
+                .collect::<Vec<_>>(),
+            [
+                ("(headers)", Gva::new(nt), protection(false, false)),
+                (".text", Gva::new(nt + 0x1_000), protection(false, true)),
+                (".data", Gva::new(nt + 0x2_000), protection(true, false)),
+                (
+                    "(outside sections)",
+                    Gva::new(nt + 0x3_000),
+                    protection(false, false)
+                ),
+            ]
+        );
+
+        assert_eq!(report[0].mismatches().count(), 0);
+        assert_eq!(report[1].mismatches().collect::<Vec<_>>(), [&(
+            Gva::new(nt + 0x1_000)..Gva::new(nt + 0x2_000),
+            protection(true, true)
+        )]);
+        assert!(report[2].actual.is_empty());
+        assert_eq!(report[2].missing(), 0x1_000);
+        assert_eq!(report[3].mismatches().count(), 1);
+        assert_eq!(report[3].missing(), 0);
+    }
+}
@@ -0,0 +1,286 @@
+//! This contains what is needed to dump the kernel stack of a thread (see
+//! [`ThreadStackDump`]), and to guess its call stack by looking for the return
+//! addresses on it, which is what hang analyses are about.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::{Gva, KernelDumpParser};
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! let stack = parser.dump_thread_stack(Gva::new(0xffffb083_5d2b2080)).unwrap();
+//! for frame in &stack.frames {
+//!     println!("{frame}");
+//! }
+//! ```
+use std::fmt::{self, Display};
+use std::ops::Range;
+
+use crate::error::Result;
+use crate::gxa::Gxa;
+use crate::teb::available;
+use crate::{Gva, KdmpParserError, KernelDumpParser};
+
+/// Largest kernel stack we consider plausible; the stacks are 24KB, unless
+/// they have been expanded.
+const MAX_KERNEL_STACK_SIZE: u64 = 0x10_0000;
+
+/// Number of frames [`KernelDumpParser::dump_thread_stack`] finds at most.
+const MAX_FRAMES: usize = 0x100;
+
+/// A return address found on a stack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackFrame {
+    /// Where the return address is on the stack.
+    pub slot: Gva,
+    /// The return address.
+    pub return_address: Gva,
+    /// The return address symbolized with [`KernelDumpParser::symbolize`], like
+    /// `nt!KeWaitForSingleObject+0x1a3`.
+    pub symbol: Option<String>,
+}
+
+/// Format [`StackFrame`] like a line of `k`.
+impl Display for StackFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:#018x} {:#018x}",
+            self.slot.u64(),
+            self.return_address.u64()
+        )?;
+
+        match &self.symbol {
+            Some(symbol) => write!(f, " {symbol}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The kernel stack of a thread; see [`KernelDumpParser::dump_thread_stack`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThreadStackDump {
+    /// Where the stack is, from its `StackLimit` to its `StackBase`.
+    pub bounds: Range<Gva>,
+    /// The content of the stack; the pages that aren't in the dump are
+    /// zero-filled.
+    pub bytes: Vec<u8>,
+    /// The ranges of the stack that aren't in the dump.
+    pub gaps: Vec<Range<Gva>>,
+    /// The stack pointer of the thread, if it is known.
+    pub sp: Option<Gva>,
+    /// The return addresses found on the stack, from the top of the stack.
+    pub frames: Vec<StackFrame>,
+}
+
+/// Do the 7 bytes before a return address end with a `call`? The forms
+/// compilers emit are `call rel32` and the `call r/m64` (`ff /2`) ones.
+fn follows_call(code: &[u8; 7]) -> bool {
+    // The `ModR/M` byte of a `ff /2`, with the `mod` it is expected to have.
+    let call = |modrm: u8, mode: u8| (modrm & 0x38) == 0x10 && (modrm >> 6) == mode;
+
+    // call rel32
+    code[2] == 0xe8
+        // call reg
+        || (code[5] == 0xff && call(code[6], 3))
+        // call [reg+disp8]
+        || (code[4] == 0xff && call(code[5], 1) && (code[5] & 7) != 4)
+        // call [reg+index+disp8]
+        || (code[3] == 0xff && call(code[4], 1) && (code[4] & 7) == 4)
+        // call [reg+disp32], call [rip+disp32]
+        || (code[1] == 0xff
+            && (call(code[2], 2) || code[2] == 0x15)
+            && (code[2] & 7) != 4)
+        // call [reg+index+disp32]
+        || (code[0] == 0xff && call(code[1], 2) && (code[1] & 7) == 4)
+}
+
+impl KernelDumpParser {
+    /// Dump the kernel stack of the `_ETHREAD` at `ethread`, from its
+    /// `StackLimit` to its `StackBase`, and look for the return addresses on
+    /// it to guess its call stack.
+    ///
+    /// The stack pointer comes from, in order:
+    /// - the context of the dump headers for the thread that crashed,
+    /// - the `KernelStack` saved when the thread was switched out,
+    /// - the trap frame of the thread.
+    ///
+    /// The return addresses are looked for from the stack pointer, or from
+    /// the top of the stack if it isn't known: they are the values that point
+    /// right after a `call` in a module. As this is a heuristic, stale frames
+    /// show up as well.
+    pub fn dump_thread_stack(&self, ethread: Gva) -> Result<ThreadStackDump> {
+        let field = |name| self.read_field(ethread, "_KTHREAD", name);
+        let (limit, base) = (field("StackLimit")?, field("StackBase")?);
+        if limit >= base || (base - limit) > MAX_KERNEL_STACK_SIZE {
+            return Err(KdmpParserError::InvalidData("invalid stack bounds"));
+        }
+
+        let bounds = Gva::new(limit)..Gva::new(base);
+        let plan = self.plan_virt_read(bounds.start, (base - limit) as usize)?;
+        let mut bytes = vec![0; (base - limit) as usize];
+        self.read_plan(&plan, &mut bytes)?;
+
+        let mut gaps = Vec::<Range<Gva>>::new();
+        let mut addr = limit;
+        for span in &plan {
+            let end = addr + span.len as u64;
+            if span.gap {
+                match gaps.last_mut() {
+                    Some(last) if last.end.u64() == addr => last.end = Gva::new(end),
+                    _ => gaps.push(Gva::new(addr)..Gva::new(end)),
+                }
+            }
+
+            addr = end;
+        }
+
+        let in_bounds = |sp: u64| (limit..base).contains(&sp);
+        let crashing = self.thread_or_crashing(None).ok() == Some(ethread);
+        let trap_frame_sp = || -> Result<Option<u64>> {
+            let Some(trap_frame) = available(field("TrapFrame"))?.filter(|&frame| frame != 0)
+            else {
+                return Ok(None);
+            };
+
+            available(self.read_field(Gva::new(trap_frame), "_KTRAP_FRAME", "Rsp"))
+        };
+
+        let sp = if crashing && in_bounds(self.context_record().rsp) {
+            Some(self.context_record().rsp)
+        } else if let Some(sp) = available(field("KernelStack"))?.filter(|&sp| in_bounds(sp)) {
+            Some(sp)
+        } else {
+            trap_frame_sp()?.filter(|&sp| in_bounds(sp))
+        }
+        .map(Gva::new);
+
+        let mut frames = Vec::new();
+        let first = sp.map_or(limit, |sp| (sp.u64() + 7) & !7);
+        for slot in (first..base).step_by(8) {
+            if frames.len() == MAX_FRAMES {
+                break;
+            }
+
+            let offset = (slot - limit) as usize;
+            let Some(value) = bytes.get(offset..offset + 8) else {
+                break;
+            };
+
+            let return_address = Gva::new(u64::from_le_bytes(value.try_into().unwrap()));
+            if !self.is_return_address(return_address) {
+                continue;
+            }
+
+            frames.push(StackFrame {
+                slot: Gva::new(slot),
+                return_address,
+                symbol: self.symbolize(return_address),
+            });
+        }
+
+        Ok(ThreadStackDump {
+            bounds,
+            bytes,
+            gaps,
+            sp,
+            frames,
+        })
+    }
+
+    /// Does `gva` look like a return address: is it in a module, right after
+    /// a `call`?
+    fn is_return_address(&self, gva: Gva) -> bool {
+        if self.find_module_entry(gva).is_none() {
+            return false;
+        }
+
+        let Some(call) = gva.u64().checked_sub(7) else {
+            return false;
+        };
+
+        let mut code = [0; 7];
+        self.virt_read_exact(Gva::new(call), &mut code).is_ok() && follows_call(&code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DumpBuilder;
+    use crate::PxeFlags;
+
+    const THREAD: u64 = 0xffff_c000_0100_0000;
+    const STACK: u64 = 0xffff_c000_0200_0000;
+    const NT: u64 = 0xffff_f800_0000_0000;
+
+    #[test]
+    fn calls() {
+        let calls: [&[u8]; 7] = [
+            &[0xe8, 0x10, 0x20, 0x30, 0x40],
+            &[0xff, 0xd0],
+            &[0xff, 0x50, 0x18],
+            &[0xff, 0x54, 0x24, 0x20],
+            &[0xff, 0x15, 0x10, 0x20, 0x30, 0x40],
+            &[0xff, 0x90, 0x10, 0x20, 0x30, 0x40],
+            &[0xff, 0x94, 0x24, 0x10, 0x20, 0x30, 0x40],
+        ];
+
+        for call in calls {
+            let mut code = [0x90; 7];
+            code[7 - call.len()..].copy_from_slice(call);
+            assert!(follows_call(&code), "{call:x?}");
+        }
+
+        assert!(!follows_call(&[0x90; 7]));
+        // jmp [rip+disp32]
+        assert!(!follows_call(&[0x90, 0xff, 0x25, 0x10, 0x20, 0x30, 0x40]));
+    }
+
+    #[test]
+    fn dump_thread_stack() {
+        // `nt` calls a function at 0x10, and the return address is on the stack,
+        // along with a pointer in `nt` that doesn't follow a call.
+        let mut code = vec![0x90; 0x1_000];
+        code[0xb..0x10].copy_from_slice(&[0xe8, 0, 0, 0, 0]);
+        let sp = STACK + 0x1_f00;
+        let dump = DumpBuilder::new()
+            .map_virt(THREAD, 0x10_000, PxeFlags::Present)
+            .map_virt(STACK + 0x1_000, 0x12_000, PxeFlags::Present)
+            .map_virt(NT, 0x13_000, PxeFlags::Present)
+            .write_phys(0x13_000, &code)
+            .module(NT..NT + 0x1_000, "ntoskrnl.exe")
+            .write_virt(THREAD + 0x30, &STACK.to_le_bytes())
+            .write_virt(THREAD + 0x38, &(STACK + 0x2_000).to_le_bytes())
+            .write_virt(THREAD + 0x58, &sp.to_le_bytes())
+            .write_virt(STACK + 0x1_ef8, &(NT + 0x10).to_le_bytes())
+            .write_virt(sp + 0x28, &(NT + 0x20).to_le_bytes())
+            .write_virt(sp + 0x48, &(NT + 0x10).to_le_bytes())
+            .build();
+
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+        let stack = parser.dump_thread_stack(Gva::new(THREAD)).unwrap();
+        assert_eq!(stack.bounds, Gva::new(STACK)..Gva::new(STACK + 0x2_000));
+        assert_eq!(stack.bytes.len(), 0x2_000);
+        assert_eq!(stack.gaps, [Gva::new(STACK)..Gva::new(STACK + 0x1_000)]);
+        assert_eq!(stack.sp, Some(Gva::new(sp)));
+
+        // The return address above the stack pointer is stale, so it is left out.
+        assert_eq!(stack.frames, [StackFrame {
+            slot: Gva::new(sp + 0x48),
+            return_address: Gva::new(NT + 0x10),
+            symbol: Some("nt+0x10".into()),
+        }]);
+
+        // Bogus bounds aren't read.
+        let dump = DumpBuilder::new()
+            .map_virt(THREAD, 0x10_000, PxeFlags::Present)
+            .write_virt(THREAD + 0x30, &(STACK + 0x2_000).to_le_bytes())
+            .write_virt(THREAD + 0x38, &STACK.to_le_bytes())
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+        assert!(matches!(
+            parser.dump_thread_stack(Gva::new(THREAD)),
+            Err(KdmpParserError::InvalidData(_))
+        ));
+    }
+}
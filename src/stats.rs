@@ -0,0 +1,225 @@
+//! This contains what is needed to count what the reads of a dump cost (see
+//! [`ParserStats`]), to tell whether an analysis is bound by the address
+//! translations, by the I/O or by the memory missing from the dump. The
+//! counters are only kept when [`crate::ParserOptions::collect_stats`] is set.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::{Gva, KernelDumpParser, ParserOptions};
+//! let options = ParserOptions {
+//!     collect_stats: true,
+//!     ..Default::default()
+//! };
+//! let parser = KernelDumpParser::new_with_options(&"full.dmp", options).unwrap();
+//! parser.reset_stats();
+//! let _ = parser.virt_read_to_vec(Gva::new(0xfffff803_1e600000), 0x1_000);
+//! let stats = parser.stats();
+//! println!("{} bytes read in {:?}", stats.bytes_read, stats.io_time);
+//! ```
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::KernelDumpParser;
+
+/// What the reads of a dump have cost since it was parsed, or since
+/// [`KernelDumpParser::reset_stats`] was last called.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ParserStats {
+    /// Number of reads of virtual memory.
+    pub virt_reads: u64,
+    /// Number of reads of physical memory.
+    pub phys_reads: u64,
+    /// Number of bytes read out of the dump file, page tables included.
+    pub bytes_read: u64,
+    /// Number of address translations answered by the translation cache.
+    pub translation_cache_hits: u64,
+    /// Number of address translations that walked the page tables.
+    pub translation_cache_misses: u64,
+    /// Number of pages the reads ran into that aren't available in the dump.
+    pub missing_page_hits: u64,
+    /// Number of page tables read out of the cache of the page tables; see
+    /// [`crate::ParserOptions::page_table_cache_entries`].
+    pub page_table_cache_hits: u64,
+    /// Number of page tables read out of the dump.
+    pub page_table_cache_misses: u64,
+    /// Time spent reading the dump file.
+    pub io_time: Duration,
+}
+
+/// The counters behind [`ParserStats`]; they are atomics so that they can be
+/// updated by reads running concurrently.
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    pub(crate) virt_reads: AtomicU64,
+    pub(crate) phys_reads: AtomicU64,
+    pub(crate) bytes_read: AtomicU64,
+    pub(crate) translation_cache_hits: AtomicU64,
+    pub(crate) translation_cache_misses: AtomicU64,
+    pub(crate) missing_page_hits: AtomicU64,
+    pub(crate) page_table_cache_hits: AtomicU64,
+    pub(crate) page_table_cache_misses: AtomicU64,
+    /// In nanoseconds.
+    pub(crate) io_time: AtomicU64,
+}
+
+impl Counters {
+    fn all(&self) -> [&AtomicU64; 9] {
+        [
+            &self.virt_reads,
+            &self.phys_reads,
+            &self.bytes_read,
+            &self.translation_cache_hits,
+            &self.translation_cache_misses,
+            &self.missing_page_hits,
+            &self.page_table_cache_hits,
+            &self.page_table_cache_misses,
+            &self.io_time,
+        ]
+    }
+}
+
+impl KernelDumpParser {
+    /// Get what the reads of the dump have cost so far; everything is zero
+    /// unless [`crate::ParserOptions::collect_stats`] is set.
+    pub fn stats(&self) -> ParserStats {
+        let Some(counters) = &self.counters else {
+            return ParserStats::default();
+        };
+
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+
+        ParserStats {
+            virt_reads: load(&counters.virt_reads),
+            phys_reads: load(&counters.phys_reads),
+            bytes_read: load(&counters.bytes_read),
+            translation_cache_hits: load(&counters.translation_cache_hits),
+            translation_cache_misses: load(&counters.translation_cache_misses),
+            missing_page_hits: load(&counters.missing_page_hits),
+            page_table_cache_hits: load(&counters.page_table_cache_hits),
+            page_table_cache_misses: load(&counters.page_table_cache_misses),
+            io_time: Duration::from_nanos(load(&counters.io_time)),
+        }
+    }
+
+    /// Start counting over.
+    pub fn reset_stats(&self) {
+        if let Some(counters) = &self.counters {
+            for counter in counters.all() {
+                counter.store(0, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Add `amount` to a counter, if the stats are collected.
+    pub(crate) fn count(&self, counter: impl FnOnce(&Counters) -> &AtomicU64, amount: u64) {
+        if let Some(counters) = &self.counters {
+            counter(counters).fetch_add(amount, Ordering::Relaxed);
+        }
+    }
+
+    /// Time the I/O `io` does, and count the bytes it reads, if the stats are
+    /// collected.
+    pub(crate) fn count_io(
+        &self,
+        io: impl FnOnce() -> crate::Result<usize>,
+    ) -> crate::Result<usize> {
+        let Some(counters) = &self.counters else {
+            return io();
+        };
+
+        let start = Instant::now();
+        let result = io();
+        let elapsed = u64::try_from(start.elapsed().as_nanos()).unwrap_or(u64::MAX);
+        counters.io_time.fetch_add(elapsed, Ordering::Relaxed);
+        if let Ok(amount_read) = result {
+            counters
+                .bytes_read
+                .fetch_add(amount_read as u64, Ordering::Relaxed);
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::testing::DumpBuilder;
+    use crate::{Gpa, Gva, KernelDumpParser, ParserOptions, PxeFlags};
+
+    #[test]
+    fn stats() {
+        let gva = 0xffff_f800_0000_0000;
+        let dump = DumpBuilder::new()
+            .write_phys(0x1_000, b"stats")
+            .map_virt(gva, 0x1_000, PxeFlags::Present)
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump.clone()).unwrap();
+        let mut buffer = [0; 5];
+        parser.virt_read_exact(Gva::new(gva), &mut buffer).unwrap();
+        assert_eq!(parser.stats(), Default::default());
+
+        let options = ParserOptions {
+            collect_stats: true,
+            ..Default::default()
+        };
+        let parser = KernelDumpParser::with_options(Cursor::new(dump), options).unwrap();
+        parser.reset_stats();
+        for _ in 0..2 {
+            parser.virt_read_exact(Gva::new(gva), &mut buffer).unwrap();
+        }
+
+        assert_eq!(&buffer, b"stats");
+        parser
+            .phys_read_exact(Gpa::new(0x1_000), &mut buffer)
+            .unwrap();
+        assert!(parser.virt_read_to_vec(Gva::new(gva + 0x1_000), 1).is_err());
+
+        // The first translation walks the 4 levels of page tables, the second one is
+        // cached.
+        let stats = parser.stats();
+        assert_eq!(stats.virt_reads, 3);
+        assert_eq!(stats.phys_reads, 1);
+        assert_eq!(stats.translation_cache_hits, 1);
+        assert_eq!(stats.translation_cache_misses, 2);
+        assert_eq!(stats.missing_page_hits, 1);
+        assert!(stats.bytes_read >= (3 * 5) + (4 * 8));
+
+        parser.reset_stats();
+        assert_eq!(parser.stats(), Default::default());
+    }
+
+    #[test]
+    fn multi_page_walk() {
+        // Reading 4 pages walks the upper levels of the page tables once, and reads a
+        // PTE per page (without the cache of the page tables, which reads whole
+        // tables).
+        let gva = 0xffff_f800_0000_0000;
+        let mut builder = DumpBuilder::new();
+        for page in 0..4 {
+            builder = builder.map_virt(
+                gva + page * 0x1_000,
+                0x1_000 + page * 0x1_000,
+                PxeFlags::Present,
+            );
+        }
+
+        let options = ParserOptions {
+            collect_stats: true,
+            page_table_cache_entries: 0,
+            ..Default::default()
+        };
+        let dump = builder.write_virt(gva, &[0x41; 0x4_000]).build();
+        let parser = KernelDumpParser::with_options(Cursor::new(dump), options).unwrap();
+        parser.reset_stats();
+        let mut buffer = vec![0; 0x4_000];
+        parser.virt_read_exact(Gva::new(gva), &mut buffer).unwrap();
+        assert_eq!(buffer, [0x41; 0x4_000]);
+
+        let stats = parser.stats();
+        assert_eq!(stats.translation_cache_misses, 4);
+        assert_eq!(stats.bytes_read, 0x4_000 + (3 * 8) + (4 * 8));
+    }
+}
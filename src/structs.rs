@@ -2,7 +2,7 @@
 //! This has all the raw structures that makes up Windows kernel crash-dumps.
 use std::collections::BTreeMap;
 use std::fmt::Debug;
-use std::{io, mem, slice};
+use std::{io, mem};
 
 use crate::error::Result;
 use crate::{Gpa, KdmpParserError, Reader};
@@ -17,8 +17,167 @@ impl Page {
     }
 }
 
+/// A type that can be decoded from its little-endian on-disk representation.
+///
+/// Crash-dumps are always little-endian, so every structure read off the dump
+/// file or out of the dump's memory goes through this trait instead of being
+/// reinterpreted from raw bytes; this keeps the parser correct on big-endian
+/// hosts.
+///
+/// # Examples
+///
+/// ```
+/// # use kdmp_parser::{FromLeBytes, LeCursor};
+/// struct Pair {
+///     a: u32,
+///     b: u64,
+/// }
+///
+/// impl FromLeBytes for Pair {
+///     const SIZE: usize = 0x10;
+///
+///     fn from_le_bytes(bytes: &[u8]) -> Self {
+///         let mut c = LeCursor::new(bytes);
+///
+///         Self {
+///             a: c.u32(),
+///             b: c.align(8).u64(),
+///         }
+///     }
+/// }
+///
+/// let pair = Pair::from_le_bytes(&[1, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0]);
+/// assert_eq!(pair.a, 1);
+/// assert_eq!(pair.b, 2);
+/// ```
+pub trait FromLeBytes: Sized {
+    /// Size in bytes of the on-disk representation.
+    const SIZE: usize;
+
+    /// Decode a `Self` out of `bytes`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes` is smaller than [`FromLeBytes::SIZE`].
+    fn from_le_bytes(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_from_le_bytes {
+    ($($ty:ty),*) => {
+        $(
+            impl FromLeBytes for $ty {
+                const SIZE: usize = mem::size_of::<$ty>();
+
+                fn from_le_bytes(bytes: &[u8]) -> Self {
+                    <$ty>::from_le_bytes(bytes[..Self::SIZE].try_into().unwrap())
+                }
+            }
+        )*
+    };
+}
+
+impl_from_le_bytes!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl<T, const N: usize> FromLeBytes for [T; N]
+where
+    T: FromLeBytes,
+{
+    const SIZE: usize = T::SIZE * N;
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut c = LeCursor::new(bytes);
+
+        std::array::from_fn(|_| c.read())
+    }
+}
+
+/// A cursor over a slice of bytes that decodes little-endian values one after
+/// another. It is the building block used to implement [`FromLeBytes`].
+///
+/// # Examples
+///
+/// ```
+/// # use kdmp_parser::LeCursor;
+/// let mut c = LeCursor::new(&[0x37, 0x13, 0xaa, 0xef, 0xbe, 0xad, 0xde]);
+/// assert_eq!(c.u16(), 0x13_37);
+/// assert_eq!(c.skip(1).u32(), 0xde_ad_be_ef);
+/// assert_eq!(c.position(), 7);
+/// ```
+pub struct LeCursor<'bytes> {
+    bytes: &'bytes [u8],
+    pos: usize,
+}
+
+impl<'bytes> LeCursor<'bytes> {
+    /// Create a cursor at the beginning of `bytes`.
+    pub fn new(bytes: &'bytes [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    /// Get the current position of the cursor.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Skip over `n` bytes (padding, unused fields, etc.).
+    pub fn skip(&mut self, n: usize) -> &mut Self {
+        self.pos += n;
+
+        self
+    }
+
+    /// Skip over the padding needed to bring the cursor to an `alignment`
+    /// boundary, like a C compiler would do.
+    pub fn align(&mut self, alignment: usize) -> &mut Self {
+        let misalignment = self.pos % alignment;
+        if misalignment != 0 {
+            self.pos += alignment - misalignment;
+        }
+
+        self
+    }
+
+    /// Decode a `T` and move the cursor past it.
+    pub fn read<T: FromLeBytes>(&mut self) -> T {
+        let t = T::from_le_bytes(&self.bytes[self.pos..]);
+        self.pos += T::SIZE;
+
+        t
+    }
+
+    /// Decode a [`u8`].
+    pub fn u8(&mut self) -> u8 {
+        self.read()
+    }
+
+    /// Decode a [`u16`].
+    pub fn u16(&mut self) -> u16 {
+        self.read()
+    }
+
+    /// Decode a [`u32`].
+    pub fn u32(&mut self) -> u32 {
+        self.read()
+    }
+
+    /// Decode a [`u64`].
+    pub fn u64(&mut self) -> u64 {
+        self.read()
+    }
+
+    /// Decode a [`i64`].
+    pub fn i64(&mut self) -> i64 {
+        self.read()
+    }
+
+    /// Decode a [`u128`].
+    pub fn u128(&mut self) -> u128 {
+        self.read()
+    }
+}
+
 /// Types of kernel crash dump.
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u32)]
 pub enum DumpType {
     // Old dump types from dbgeng.dll
@@ -64,6 +223,24 @@ pub struct ExceptionRecord64 {
     pub exception_information: [u64; 15],
 }
 
+impl FromLeBytes for ExceptionRecord64 {
+    const SIZE: usize = mem::size_of::<Self>();
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut c = LeCursor::new(bytes);
+
+        Self {
+            exception_code: c.u32(),
+            exception_flags: c.u32(),
+            exception_record: c.u64(),
+            exception_address: c.u64(),
+            number_parameters: c.u32(),
+            unused_alignment1: c.u32(),
+            exception_information: c.read(),
+        }
+    }
+}
+
 pub const DUMP_HEADER64_EXPECTED_SIGNATURE: u32 = 0x45_47_41_50; // 'EGAP'
 pub const DUMP_HEADER64_EXPECTED_VALID_DUMP: u32 = 0x34_36_55_44; // '46UD'
 
@@ -110,6 +287,53 @@ pub struct Header64 {
     reserved1: [u8; 4008],
 }
 
+impl FromLeBytes for Header64 {
+    const SIZE: usize = mem::size_of::<Self>();
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut c = LeCursor::new(bytes);
+
+        Self {
+            signature: c.u32(),
+            valid_dump: c.u32(),
+            major_version: c.u32(),
+            minor_version: c.u32(),
+            directory_table_base: c.u64(),
+            pfn_database: c.u64(),
+            ps_loaded_module_list: c.u64(),
+            ps_active_process_head: c.u64(),
+            machine_image_type: c.u32(),
+            number_processors: c.u32(),
+            bug_check_code: c.u32(),
+            padding1: c.u32(),
+            bug_check_code_parameters: c.read(),
+            version_user: c.read(),
+            kd_debugger_data_block: c.u64(),
+            physical_memory_block_buffer: c.read(),
+            padding2: c.u32(),
+            context_record_buffer: c.read(),
+            exception: c.read(),
+            dump_type: c.u32(),
+            padding3: c.u32(),
+            required_dump_space: c.i64(),
+            system_time: c.i64(),
+            comment: c.read(),
+            system_up_time: c.i64(),
+            minidump_fields: c.u32(),
+            secondary_data_state: c.u32(),
+            product_type: c.u32(),
+            suite_mask: c.u32(),
+            writer_status: c.u32(),
+            unused1: c.u8(),
+            kd_secondary_version: c.u8(),
+            unused2: c.read(),
+            attributes: c.u32(),
+            boot_id: c.u32(),
+            reserved1: c.read(),
+        }
+    }
+}
+
 impl Debug for Header64 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Header64")
@@ -149,6 +373,7 @@ const BMPHEADER64_EXPECTED_SIGNATURE: u32 = 0x50_4D_44_53; // 'PMDS'
 const BMPHEADER64_EXPECTED_SIGNATURE2: u32 = 0x50_4D_44_46; // 'PMDF'
 const BMPHEADER64_EXPECTED_VALID_DUMP: u32 = 0x50_4D_55_44; // 'PMUD'
 
+/// The header of a [`DumpType::Bmp`] dump, followed by its bitmap.
 #[derive(Debug, Default)]
 #[repr(C)]
 pub struct BmpHeader64 {
@@ -173,7 +398,30 @@ pub struct BmpHeader64 {
     // Bitmap follows
 }
 
+impl FromLeBytes for BmpHeader64 {
+    const SIZE: usize = mem::size_of::<Self>();
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut c = LeCursor::new(bytes);
+
+        Self {
+            signature: c.u32(),
+            valid_dump: c.u32(),
+            padding1: c.read(),
+            first_page: c.u64(),
+            total_present_pages: c.u64(),
+            pages: c.u64(),
+        }
+    }
+}
+
 impl BmpHeader64 {
+    /// Is it the header of a summary dump (`SDMP`), which only has the kernel
+    /// memory, rather than of a full dump (`FDMP`)?
+    pub(crate) fn summary(&self) -> bool {
+        self.signature == BMPHEADER64_EXPECTED_SIGNATURE
+    }
+
     pub fn looks_good(&self) -> bool {
         (self.signature == BMPHEADER64_EXPECTED_SIGNATURE
             || self.signature == BMPHEADER64_EXPECTED_SIGNATURE2)
@@ -181,6 +429,7 @@ impl BmpHeader64 {
     }
 }
 
+/// A run of consecutive physical pages of a [`DumpType::Full`] dump.
 #[derive(Debug, Default)]
 #[repr(C)]
 pub struct PhysmemRun {
@@ -188,20 +437,35 @@ pub struct PhysmemRun {
     pub page_count: u64,
 }
 
+impl FromLeBytes for PhysmemRun {
+    const SIZE: usize = mem::size_of::<Self>();
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut c = LeCursor::new(bytes);
+
+        Self {
+            base_page: c.u64(),
+            page_count: c.u64(),
+        }
+    }
+}
+
 impl PhysmemRun {
     /// Calculate a physical address from a run and an index.
     ///
-    /// The formulae is: (`base_page` + `page_idx`) * `Page::size()`.
-    pub fn phys_addr(&self, page_idx: u64) -> Option<Gpa> {
+    /// The formulae is: (`base_page` + `page_idx`) * `page_size`.
+    pub fn phys_addr(&self, page_idx: u64, page_size: u64) -> Option<Gpa> {
         debug_assert!(page_idx < self.page_count);
 
         self.base_page
             .checked_add(page_idx)?
-            .checked_mul(Page::size())
+            .checked_mul(page_size)
             .map(Gpa::new)
     }
 }
 
+/// The physical memory descriptor of a [`DumpType::Full`] dump, followed by
+/// its runs.
 #[derive(Debug, Default)]
 #[repr(C)]
 pub struct PhysmemDesc {
@@ -211,23 +475,38 @@ pub struct PhysmemDesc {
     // PHYSMEM_RUN Run[1]; follows
 }
 
-impl TryFrom<&[u8]> for PhysmemDesc {
-    type Error = KdmpParserError;
+impl PhysmemDesc {
+    /// The largest number of runs a dump header has room for: the descriptor
+    /// and its runs are in [`Header64::physical_memory_block_buffer`]. The
+    /// format makes `number_of_runs` a `u32`, but it can't be more than this;
+    /// the runs hold 64-bit page counts.
+    pub(crate) const MAX_RUNS: u32 = ((700 - Self::SIZE) / PhysmemRun::SIZE) as u32;
 
-    fn try_from(slice: &[u8]) -> Result<Self> {
-        let expected_len = mem::size_of::<Self>();
-        if slice.len() < expected_len {
-            return Err(KdmpParserError::InvalidData("physmem desc is too small"));
+    /// Read a descriptor off `reader`, checking that its runs fit in the
+    /// header.
+    pub(crate) fn read(reader: &mut impl Reader) -> Result<Self> {
+        let desc = read_struct::<Self>(reader)?;
+        if desc.number_of_runs > Self::MAX_RUNS {
+            return Err(KdmpParserError::InvalidData(
+                "the physical memory descriptor has more runs than fit in the header",
+            ));
         }
 
-        let number_of_runs = u32::from_le_bytes((&slice[0..4]).try_into().unwrap());
-        let number_of_pages = u64::from_le_bytes((&slice[4..12]).try_into().unwrap());
+        Ok(desc)
+    }
+}
+
+impl FromLeBytes for PhysmemDesc {
+    const SIZE: usize = mem::size_of::<Self>();
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut c = LeCursor::new(bytes);
 
-        Ok(Self {
-            number_of_runs,
-            number_of_pages,
-            ..Default::default()
-        })
+        Self {
+            number_of_runs: c.u32(),
+            padding1: c.u32(),
+            number_of_pages: c.u64(),
+        }
     }
 }
 
@@ -297,6 +576,78 @@ pub struct Context {
     pub last_exception_from_rip: u64,
 }
 
+impl FromLeBytes for Context {
+    const SIZE: usize = mem::size_of::<Self>();
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut c = LeCursor::new(bytes);
+
+        Self {
+            p1_home: c.u64(),
+            p2_home: c.u64(),
+            p3_home: c.u64(),
+            p4_home: c.u64(),
+            p5_home: c.u64(),
+            p6_home: c.u64(),
+            context_flags: c.u32(),
+            mxcsr: c.u32(),
+            seg_cs: c.u16(),
+            seg_ds: c.u16(),
+            seg_es: c.u16(),
+            seg_fs: c.u16(),
+            seg_gs: c.u16(),
+            seg_ss: c.u16(),
+            eflags: c.u32(),
+            dr0: c.u64(),
+            dr1: c.u64(),
+            dr2: c.u64(),
+            dr3: c.u64(),
+            dr6: c.u64(),
+            dr7: c.u64(),
+            rax: c.u64(),
+            rcx: c.u64(),
+            rdx: c.u64(),
+            rbx: c.u64(),
+            rsp: c.u64(),
+            rbp: c.u64(),
+            rsi: c.u64(),
+            rdi: c.u64(),
+            r8: c.u64(),
+            r9: c.u64(),
+            r10: c.u64(),
+            r11: c.u64(),
+            r12: c.u64(),
+            r13: c.u64(),
+            r14: c.u64(),
+            r15: c.u64(),
+            rip: c.u64(),
+            control_word: c.u16(),
+            status_word: c.u16(),
+            tag_word: c.u8(),
+            reserved1: c.u8(),
+            error_opcode: c.u16(),
+            error_offset: c.u32(),
+            error_selector: c.u16(),
+            reserved2: c.u16(),
+            data_offset: c.u32(),
+            data_selector: c.u16(),
+            reserved3: c.u16(),
+            mxcsr2: c.u32(),
+            mxcsr_mask: c.u32(),
+            float_registers: c.read(),
+            xmm_registers: c.read(),
+            reserved4: c.read(),
+            vector_register: c.read(),
+            vector_control: c.u64(),
+            debug_control: c.u64(),
+            last_branch_to_rip: c.u64(),
+            last_branch_from_rip: c.u64(),
+            last_exception_to_rip: c.u64(),
+            last_exception_from_rip: c.u64(),
+        }
+    }
+}
+
 impl Debug for Context {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Context")
@@ -361,25 +712,87 @@ impl Debug for Context {
     }
 }
 
-/// Peek for a `T` from the cursor.
-pub fn peek_struct<T>(reader: &mut impl Reader) -> Result<T> {
-    let mut s = mem::MaybeUninit::uninit();
-    let size_of_s = mem::size_of_val(&s);
-    let slice_over_s = unsafe { slice::from_raw_parts_mut(s.as_mut_ptr() as *mut u8, size_of_s) };
+/// The 32-bit context of a WOW64 thread (`WOW64_CONTEXT`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[repr(C)]
+pub struct Wow64Context {
+    pub context_flags: u32,
+    pub dr0: u32,
+    pub dr1: u32,
+    pub dr2: u32,
+    pub dr3: u32,
+    pub dr6: u32,
+    pub dr7: u32,
+    pub float_save: [u8; 112],
+    pub seg_gs: u32,
+    pub seg_fs: u32,
+    pub seg_es: u32,
+    pub seg_ds: u32,
+    pub edi: u32,
+    pub esi: u32,
+    pub ebx: u32,
+    pub edx: u32,
+    pub ecx: u32,
+    pub eax: u32,
+    pub ebp: u32,
+    pub eip: u32,
+    pub seg_cs: u32,
+    pub eflags: u32,
+    pub esp: u32,
+    pub seg_ss: u32,
+    pub extended_registers: [u8; 512],
+}
+
+impl FromLeBytes for Wow64Context {
+    const SIZE: usize = mem::size_of::<Self>();
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut c = LeCursor::new(bytes);
 
+        Self {
+            context_flags: c.u32(),
+            dr0: c.u32(),
+            dr1: c.u32(),
+            dr2: c.u32(),
+            dr3: c.u32(),
+            dr6: c.u32(),
+            dr7: c.u32(),
+            float_save: c.read(),
+            seg_gs: c.u32(),
+            seg_fs: c.u32(),
+            seg_es: c.u32(),
+            seg_ds: c.u32(),
+            edi: c.u32(),
+            esi: c.u32(),
+            ebx: c.u32(),
+            edx: c.u32(),
+            ecx: c.u32(),
+            eax: c.u32(),
+            ebp: c.u32(),
+            eip: c.u32(),
+            seg_cs: c.u32(),
+            eflags: c.u32(),
+            esp: c.u32(),
+            seg_ss: c.u32(),
+            extended_registers: c.read(),
+        }
+    }
+}
+
+/// Peek for a `T` from the cursor.
+pub fn peek_struct<T: FromLeBytes>(reader: &mut impl Reader) -> Result<T> {
+    let mut buffer = vec![0; T::SIZE];
     let pos = reader.stream_position()?;
-    reader.read_exact(slice_over_s)?;
+    reader.read_exact(&mut buffer)?;
     reader.seek(io::SeekFrom::Start(pos))?;
 
-    Ok(unsafe { s.assume_init() })
+    Ok(T::from_le_bytes(&buffer))
 }
 
 /// Read a `T` from the cursor.
-pub fn read_struct<T>(reader: &mut impl Reader) -> Result<T> {
+pub fn read_struct<T: FromLeBytes>(reader: &mut impl Reader) -> Result<T> {
     let s = peek_struct(reader)?;
-    let size_of_s = mem::size_of_val(&s);
-
-    reader.seek(io::SeekFrom::Current(size_of_s.try_into().unwrap()))?;
+    reader.seek(io::SeekFrom::Current(T::SIZE.try_into().unwrap()))?;
 
     Ok(s)
 }
@@ -388,6 +801,7 @@ const RDMP_HEADER64_EXPECTED_MARKER: u32 = 0x40;
 const RDMP_HEADER64_EXPECTED_SIGNATURE: u32 = 0x50_4D_44_52; // 'PMDR'
 const RDMP_HEADER64_EXPECTED_VALID_DUMP: u32 = 0x50_4D_55_44; // 'PMUD'
 
+/// The header shared by the kernel memory and complete memory dumps.
 #[repr(C)]
 #[derive(Debug, Default)]
 pub struct RdmpHeader64 {
@@ -400,6 +814,23 @@ pub struct RdmpHeader64 {
     // Bitmap follows
 }
 
+impl FromLeBytes for RdmpHeader64 {
+    const SIZE: usize = mem::size_of::<Self>();
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut c = LeCursor::new(bytes);
+
+        Self {
+            marker: c.u32(),
+            signature: c.u32(),
+            valid_dump: c.u32(),
+            reserved1: c.u32(),
+            metadata_size: c.u64(),
+            first_page_offset: c.u64(),
+        }
+    }
+}
+
 impl RdmpHeader64 {
     pub fn looks_good(&self) -> bool {
         if self.marker != RDMP_HEADER64_EXPECTED_MARKER {
@@ -414,14 +845,24 @@ impl RdmpHeader64 {
             return false;
         }
 
-        if self.metadata_size - 0x20 != self.first_page_offset - 0x20_40 {
-            return false;
-        }
+        // The pages can't start before the end of the metadata; they start right
+        // after it unless the header has been extended.
+        self.extension().is_some()
+    }
+
+    /// Number of bytes between the end of the metadata and the first page,
+    /// which a build newer than the parser could have extended the header
+    /// with; they are zero in the dumps the parser knows about. It is `None`
+    /// if the pages start before the end of the metadata.
+    pub(crate) fn extension(&self) -> Option<u64> {
+        let metadata_end = self.metadata_size.checked_sub(0x20)?.checked_add(0x20_40)?;
 
-        true
+        self.first_page_offset.checked_sub(metadata_end)
     }
 }
 
+/// The header of the [`DumpType::KernelMemory`] and
+/// [`DumpType::KernelAndUserMemory`] dumps, followed by their page ranges.
 #[repr(C)]
 #[derive(Debug, Default)]
 pub struct KernelRdmpHeader64 {
@@ -431,6 +872,22 @@ pub struct KernelRdmpHeader64 {
     // Bitmap follows
 }
 
+impl FromLeBytes for KernelRdmpHeader64 {
+    const SIZE: usize = mem::size_of::<Self>();
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut c = LeCursor::new(bytes);
+
+        Self {
+            hdr: c.read(),
+            unknown1: c.u64(),
+            unknown2: c.u64(),
+        }
+    }
+}
+
+/// The header of a [`DumpType::CompleteMemory`] dump, followed by its page
+/// ranges.
 #[repr(C)]
 #[derive(Debug, Default)]
 pub struct FullRdmpHeader64 {
@@ -442,6 +899,24 @@ pub struct FullRdmpHeader64 {
     // Bitmap follows
 }
 
+impl FromLeBytes for FullRdmpHeader64 {
+    const SIZE: usize = mem::size_of::<Self>();
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut c = LeCursor::new(bytes);
+
+        Self {
+            hdr: c.read(),
+            number_of_ranges: c.u32(),
+            reserved1: c.u16(),
+            reserved2: c.u16(),
+            total_number_of_pages: c.u64(),
+        }
+    }
+}
+
+/// A range of consecutive physical pages of a kernel memory or complete memory
+/// dump.
 #[repr(C)]
 #[derive(Debug, Default)]
 pub struct PfnRange {
@@ -449,13 +924,39 @@ pub struct PfnRange {
     pub number_of_pages: u64,
 }
 
+impl FromLeBytes for PfnRange {
+    const SIZE: usize = mem::size_of::<Self>();
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut c = LeCursor::new(bytes);
+
+        Self {
+            page_file_number: c.u64(),
+            number_of_pages: c.u64(),
+        }
+    }
+}
+
 #[repr(C)]
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct ListEntry {
     pub flink: u64,
     pub blink: u64,
 }
 
+impl FromLeBytes for ListEntry {
+    const SIZE: usize = mem::size_of::<Self>();
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut c = LeCursor::new(bytes);
+
+        Self {
+            flink: c.u64(),
+            blink: c.u64(),
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Default)]
 pub struct UnicodeString {
@@ -464,6 +965,20 @@ pub struct UnicodeString {
     pub buffer: u64,
 }
 
+impl FromLeBytes for UnicodeString {
+    const SIZE: usize = mem::size_of::<Self>();
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut c = LeCursor::new(bytes);
+
+        Self {
+            length: c.u16(),
+            maximum_length: c.u16(),
+            buffer: c.align(8).u64(),
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Default)]
 pub struct LdrDataTableEntry {
@@ -478,9 +993,29 @@ pub struct LdrDataTableEntry {
     pub base_dll_name: UnicodeString,
 }
 
+impl FromLeBytes for LdrDataTableEntry {
+    const SIZE: usize = mem::size_of::<Self>();
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut c = LeCursor::new(bytes);
+
+        Self {
+            in_load_order_links: c.read(),
+            in_memory_order_links: c.read(),
+            in_initialization_order_links: c.read(),
+            dll_base: c.u64(),
+            entry_point: c.u64(),
+            size_of_image: c.u32(),
+            reserved1: c.u32(),
+            full_dll_name: c.read(),
+            base_dll_name: c.read(),
+        }
+    }
+}
+
 // Copied from `WDBGEXTS.H`.
 #[repr(C)]
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct DbgKdDebugDataHeader64 {
     /// Link to other blocks
     pub list: ListEntry,
@@ -492,9 +1027,23 @@ pub struct DbgKdDebugDataHeader64 {
     pub size: u32,
 }
 
+impl FromLeBytes for DbgKdDebugDataHeader64 {
+    const SIZE: usize = mem::size_of::<Self>();
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut c = LeCursor::new(bytes);
+
+        Self {
+            list: c.read(),
+            owner_tag: c.u32(),
+            size: c.u32(),
+        }
+    }
+}
+
 // https://github.com/tpn/winsdk-10/blob/9b69fd26ac0c7d0b83d378dba01080e93349c2ed/Include/10.0.14393.0/um/WDBGEXTS.H#L1206C16-L1206C34
 #[repr(C)]
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct KdDebuggerData64 {
     pub header: DbgKdDebugDataHeader64,
     /// Base address of kernel image
@@ -658,11 +1207,165 @@ pub struct KdDebuggerData64 {
     // ...
 }
 
+impl FromLeBytes for KdDebuggerData64 {
+    const SIZE: usize = mem::size_of::<Self>();
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut c = LeCursor::new(bytes);
+
+        Self {
+            header: c.read(),
+            kern_base: c.u64(),
+            breakpoint_with_status: c.u64(),
+            saved_context: c.u64(),
+            th_callback_stack: c.u16(),
+            next_callback: c.u16(),
+            frame_pointer: c.u16(),
+            pae_enabled: c.u16(),
+            ki_call_user_mode: c.u64(),
+            ke_user_callback_dispatcher: c.u64(),
+            ps_loaded_module_list: c.u64(),
+            ps_active_process_head: c.u64(),
+            psp_cid_table: c.u64(),
+            exp_system_resources_list: c.u64(),
+            exp_paged_pool_descriptor: c.u64(),
+            exp_number_of_paged_pools: c.u64(),
+            ke_time_increment: c.u64(),
+            ke_bug_check_callback_list_head: c.u64(),
+            ki_bugcheck_data: c.u64(),
+            iop_error_log_list_head: c.u64(),
+            obp_root_directory_object: c.u64(),
+            obp_type_object_type: c.u64(),
+            mm_system_cache_start: c.u64(),
+            mm_system_cache_end: c.u64(),
+            mm_system_cache_ws: c.u64(),
+            mm_pfn_database: c.u64(),
+            mm_system_ptes_start: c.u64(),
+            mm_system_ptes_end: c.u64(),
+            mm_subsection_base: c.u64(),
+            mm_number_of_paging_files: c.u64(),
+            mm_lowest_physical_page: c.u64(),
+            mm_highest_physical_page: c.u64(),
+            mm_number_of_physical_pages: c.u64(),
+            mm_maximum_non_paged_pool_in_bytes: c.u64(),
+            mm_non_paged_system_start: c.u64(),
+            mm_non_paged_pool_start: c.u64(),
+            mm_non_paged_pool_end: c.u64(),
+            mm_paged_pool_start: c.u64(),
+            mm_paged_pool_end: c.u64(),
+            mm_paged_pool_information: c.u64(),
+            mm_page_size: c.u64(),
+            mm_size_of_paged_pool_in_bytes: c.u64(),
+            mm_total_commit_limit: c.u64(),
+            mm_total_committed_pages: c.u64(),
+            mm_shared_commit: c.u64(),
+            mm_driver_commit: c.u64(),
+            mm_process_commit: c.u64(),
+            mm_paged_pool_commit: c.u64(),
+            mm_extended_commit: c.u64(),
+            mm_zeroed_page_list_head: c.u64(),
+            mm_free_page_list_head: c.u64(),
+            mm_standby_page_list_head: c.u64(),
+            mm_modified_page_list_head: c.u64(),
+            mm_modified_no_write_page_list_head: c.u64(),
+            mm_available_pages: c.u64(),
+            mm_resident_available_pages: c.u64(),
+            pool_track_table: c.u64(),
+            non_paged_pool_descriptor: c.u64(),
+            mm_highest_user_address: c.u64(),
+            mm_system_range_start: c.u64(),
+            mm_user_probe_address: c.u64(),
+            kd_print_circular_buffer: c.u64(),
+            kd_print_circular_buffer_end: c.u64(),
+            kd_print_write_pointer: c.u64(),
+            kd_print_rollover_count: c.u64(),
+            mm_loaded_user_image_list: c.u64(),
+            nt_build_lab: c.u64(),
+            ki_normal_system_call: c.u64(),
+            ki_processor_block: c.u64(),
+            mm_unloaded_drivers: c.u64(),
+            mm_last_unloaded_driver: c.u64(),
+            mm_triage_action_taken: c.u64(),
+            mm_special_pool_tag: c.u64(),
+            kernel_verifier: c.u64(),
+            mm_verifier_data: c.u64(),
+            mm_allocated_non_paged_pool: c.u64(),
+            mm_peak_commitment: c.u64(),
+            mm_total_commit_limit_maximum: c.u64(),
+            cm_nt_csd_version: c.u64(),
+            mm_physical_memory_block: c.u64(),
+            mm_session_base: c.u64(),
+            mm_session_size: c.u64(),
+            mm_system_parent_table_page: c.u64(),
+            mm_virtual_translation_base: c.u64(),
+            offset_kthread_next_processor: c.u16(),
+            offset_kthread_teb: c.u16(),
+            offset_kthread_kernel_stack: c.u16(),
+            offset_kthread_initial_stack: c.u16(),
+            offset_kthread_apc_process: c.u16(),
+            offset_kthread_state: c.u16(),
+            offset_kthread_b_store: c.u16(),
+            offset_kthread_b_store_limit: c.u16(),
+            size_eprocess: c.u16(),
+            offset_eprocess_peb: c.u16(),
+            offset_eprocess_parent_cid: c.u16(),
+            offset_eprocess_directory_table_base: c.u16(),
+            size_prcb: c.u16(),
+            offset_prcb_dpc_routine: c.u16(),
+            offset_prcb_current_thread: c.u16(),
+            offset_prcb_mhz: c.u16(),
+            offset_prcb_cpu_type: c.u16(),
+            offset_prcb_vendor_string: c.u16(),
+            offset_prcb_proc_state_context: c.u16(),
+            offset_prcb_number: c.u16(),
+            size_ethread: c.u16(),
+            kd_print_circular_buffer_ptr: c.align(8).u64(),
+            kd_print_buffer_size: c.u64(),
+            ke_loader_block: c.u64(),
+            size_pcr: c.u16(),
+            offset_pcr_self_pcr: c.u16(),
+            offset_pcr_current_prcb: c.u16(),
+            offset_pcr_contained_prcb: c.u16(),
+            offset_pcr_initial_b_store: c.u16(),
+            offset_pcr_b_store_limit: c.u16(),
+            offset_pcr_initial_stack: c.u16(),
+            offset_pcr_stack_limit: c.u16(),
+            offset_prcb_pcr_page: c.u16(),
+            offset_prcb_proc_state_special_reg: c.u16(),
+            gdt_r0_code: c.u16(),
+            gdt_r0_data: c.u16(),
+            gdt_r0_pcr: c.u16(),
+            gdt_r3_code: c.u16(),
+            gdt_r3_data: c.u16(),
+            gdt_r3_teb: c.u16(),
+            gdt_ldt: c.u16(),
+            gdt_tss: c.u16(),
+            gdt64_r3_cm_code: c.u16(),
+            gdt64_r3_cm_teb: c.u16(),
+            iop_num_triage_dump_data_blocks: c.u64(),
+            iop_triage_dump_data_blocks: c.u64(),
+            vf_crash_data_block: c.u64(),
+            mm_bad_pages_detected: c.u64(),
+            mm_zeroed_page_single_bit_errors_detected: c.u64(),
+            etwp_debugger_data: c.u64(),
+            offset_prcb_context: c.u16(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::mem;
 
-    use crate::structs::{Context, Header64, PhysmemDesc, PhysmemRun};
+    use crate::structs::{
+        BmpHeader64, Context, FromLeBytes, Header64, KdDebuggerData64, LdrDataTableEntry, PfnRange,
+        PhysmemDesc, PhysmemRun, RdmpHeader64, UnicodeString, Wow64Context,
+    };
+
+    /// Write `bytes` at `offset` in `buffer`.
+    fn put(buffer: &mut [u8], offset: usize, bytes: &[u8]) {
+        buffer[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
 
     /// Ensure that the sizes of key structures are right.
     #[test]
@@ -671,5 +1374,254 @@ mod tests {
         assert_eq!(mem::size_of::<PhysmemRun>(), 0x10);
         assert_eq!(mem::size_of::<Header64>(), 0x2_000);
         assert_eq!(mem::size_of::<Context>(), 0x4d0);
+        assert_eq!(mem::size_of::<Wow64Context>(), 0x2cc);
+        assert_eq!(mem::size_of::<LdrDataTableEntry>(), 0x68);
+        assert_eq!(mem::size_of::<KdDebuggerData64>(), 0x340);
+    }
+
+    /// Decode a synthetic header laid out byte per byte. Every multi-byte
+    /// field uses a different value for each of its bytes, so this fails on
+    /// any host if a field isn't explicitly decoded as little-endian or if it
+    /// is read at the wrong offset.
+    #[test]
+    fn header_le() {
+        let mut raw = vec![0; Header64::SIZE];
+        put(&mut raw, 0x0, b"PAGE");
+        put(&mut raw, 0x4, b"DU64");
+        put(&mut raw, 0x8, &[0x0f, 0, 0, 0]);
+        put(&mut raw, 0xc, &[0x61, 0x4a, 0, 0]);
+        put(&mut raw, 0x10, &[0x02, 0x10, 0xaa, 0x6d, 0, 0, 0, 0]);
+        put(&mut raw, 0x18, &[
+            0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01,
+        ]);
+        put(&mut raw, 0x20, &[
+            0x10, 0x0f, 0x0e, 0x0d, 0x0c, 0x0b, 0x0a, 0x09,
+        ]);
+        put(&mut raw, 0x28, &[
+            0x18, 0x17, 0x16, 0x15, 0x14, 0x13, 0x12, 0x11,
+        ]);
+        put(&mut raw, 0x30, &[0x64, 0x86, 0, 0]);
+        put(&mut raw, 0x34, &[0x04, 0, 0, 0]);
+        put(&mut raw, 0x38, &[0xe2, 0, 0, 0]);
+        for (i, param) in (0x40..0x60).step_by(8).enumerate() {
+            put(&mut raw, param, &[i as u8 + 1, 0, 0, 0, 0, 0, 0, 0xf0]);
+        }
+        put(&mut raw, 0x80, &[
+            0x20, 0x1f, 0x1e, 0x1d, 0x1c, 0x1b, 0x1a, 0x19,
+        ]);
+        put(&mut raw, 0x88, &[0x33; 700]);
+        put(&mut raw, 0x348, &[0x44; 3_000]);
+        put(&mut raw, 0xf00, &[0x05, 0x00, 0x00, 0xc0]);
+        put(&mut raw, 0xf10, &[
+            0x21, 0x43, 0x65, 0x87, 0x78, 0x56, 0x34, 0x12,
+        ]);
+        put(&mut raw, 0xf18, &[0x02, 0, 0, 0]);
+        put(&mut raw, 0xf20, &[0x01, 0, 0, 0, 0, 0, 0, 0]);
+        put(&mut raw, 0xf98, &[0x05, 0, 0, 0]);
+        put(&mut raw, 0xfa0, &[0x00, 0x10, 0, 0, 0, 0, 0, 0]);
+        put(&mut raw, 0xfa8, &[
+            0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x01,
+        ]);
+        put(&mut raw, 0xfb0, b"comment");
+        put(&mut raw, 0x1030, &[0x11, 0x22, 0x33, 0x44, 0, 0, 0, 0]);
+        put(&mut raw, 0x1040, &[0x01, 0, 0, 0]);
+        put(&mut raw, 0x1044, &[0x10, 0x01, 0, 0]);
+        put(&mut raw, 0x104d, &[0x02]);
+        put(&mut raw, 0x1050, &[0x01, 0x02, 0, 0]);
+        put(&mut raw, 0x1054, &[0x78, 0x56, 0x34, 0x12]);
+
+        let hdr = Header64::from_le_bytes(&raw);
+        assert_eq!(hdr.signature, 0x45_47_41_50);
+        assert_eq!(hdr.valid_dump, 0x34_36_55_44);
+        assert_eq!(hdr.major_version, 0xf);
+        assert_eq!(hdr.minor_version, 19_041);
+        assert_eq!(hdr.directory_table_base, 0x6d_aa_10_02);
+        assert_eq!(hdr.pfn_database, 0x01_02_03_04_05_06_07_08);
+        assert_eq!(hdr.ps_loaded_module_list, 0x09_0a_0b_0c_0d_0e_0f_10);
+        assert_eq!(hdr.ps_active_process_head, 0x11_12_13_14_15_16_17_18);
+        assert_eq!(hdr.machine_image_type, 0x8664);
+        assert_eq!(hdr.number_processors, 4);
+        assert_eq!(hdr.bug_check_code, 0xe2);
+        assert_eq!(hdr.bug_check_code_parameters, [
+            0xf0_00_00_00_00_00_00_01,
+            0xf0_00_00_00_00_00_00_02,
+            0xf0_00_00_00_00_00_00_03,
+            0xf0_00_00_00_00_00_00_04
+        ]);
+        assert_eq!(hdr.version_user, [0; 32]);
+        assert_eq!(hdr.kd_debugger_data_block, 0x19_1a_1b_1c_1d_1e_1f_20);
+        assert_eq!(hdr.physical_memory_block_buffer, [0x33; 700]);
+        assert_eq!(hdr.context_record_buffer, [0x44; 3_000]);
+        assert_eq!(hdr.exception.exception_code, 0xc0_00_00_05);
+        assert_eq!(hdr.exception.exception_flags, 0);
+        assert_eq!(hdr.exception.exception_record, 0);
+        assert_eq!(hdr.exception.exception_address, 0x12_34_56_78_87_65_43_21);
+        assert_eq!(hdr.exception.number_parameters, 2);
+        assert_eq!(hdr.exception.exception_information[0], 1);
+        assert_eq!(hdr.exception.exception_information[1..], [0; 14]);
+        assert_eq!(hdr.dump_type, 5);
+        assert_eq!(hdr.required_dump_space, 0x10_00);
+        assert_eq!(hdr.system_time, 0x01_07_06_05_04_03_02_01);
+        assert_eq!(&hdr.comment[..8], b"comment\0");
+        assert_eq!(hdr.system_up_time, 0x44_33_22_11);
+        assert_eq!(hdr.minidump_fields, 0);
+        assert_eq!(hdr.secondary_data_state, 0);
+        assert_eq!(hdr.product_type, 1);
+        assert_eq!(hdr.suite_mask, 0x1_10);
+        assert_eq!(hdr.writer_status, 0);
+        assert_eq!(hdr.kd_secondary_version, 2);
+        assert_eq!(hdr.attributes, 0x2_01);
+        assert_eq!(hdr.boot_id, 0x12_34_56_78);
+    }
+
+    /// Decode a synthetic `CONTEXT`.
+    #[test]
+    fn context_le() {
+        let mut raw = vec![0; Context::SIZE];
+        put(&mut raw, 0x30, &[0x1f, 0x00, 0x10, 0x00]);
+        put(&mut raw, 0x34, &[0x80, 0x1f, 0, 0]);
+        put(&mut raw, 0x38, &[
+            0x10, 0, 0x2b, 0, 0x2b, 0, 0x53, 0, 0x2b, 0, 0x18, 0,
+        ]);
+        put(&mut raw, 0x44, &[0x02, 0x02, 0x04, 0x00]);
+        for (i, gpr) in (0x78..0x100).step_by(8).enumerate() {
+            put(&mut raw, gpr, &[
+                i as u8, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77,
+            ]);
+        }
+        put(&mut raw, 0x100, &[0x7f, 0x02]);
+        put(&mut raw, 0x104, &[0xff]);
+        put(&mut raw, 0x118, &[0x80, 0x1f, 0, 0, 0xff, 0xff, 0, 0]);
+        put(&mut raw, 0x1a0, &(1..=16).collect::<Vec<u8>>());
+        put(&mut raw, 0x4c8, &[
+            0xa0, 0x76, 0x87, 0x10, 0x05, 0xf8, 0xff, 0xff,
+        ]);
+
+        let ctx = Context::from_le_bytes(&raw);
+        assert_eq!(ctx.context_flags, 0x10_00_1f);
+        assert_eq!(ctx.mxcsr, 0x1f_80);
+        assert_eq!(ctx.seg_cs, 0x10);
+        assert_eq!(ctx.seg_ds, 0x2b);
+        assert_eq!(ctx.seg_es, 0x2b);
+        assert_eq!(ctx.seg_fs, 0x53);
+        assert_eq!(ctx.seg_gs, 0x2b);
+        assert_eq!(ctx.seg_ss, 0x18);
+        assert_eq!(ctx.eflags, 0x04_02_02);
+        let gprs = [
+            ctx.rax, ctx.rcx, ctx.rdx, ctx.rbx, ctx.rsp, ctx.rbp, ctx.rsi, ctx.rdi, ctx.r8, ctx.r9,
+            ctx.r10, ctx.r11, ctx.r12, ctx.r13, ctx.r14, ctx.r15, ctx.rip,
+        ];
+        for (i, gpr) in gprs.into_iter().enumerate() {
+            assert_eq!(gpr, 0x77_66_55_44_33_22_11_00 | i as u64);
+        }
+        assert_eq!(ctx.control_word, 0x02_7f);
+        assert_eq!(ctx.tag_word, 0xff);
+        assert_eq!(ctx.mxcsr2, 0x1f_80);
+        assert_eq!(ctx.mxcsr_mask, 0xff_ff);
+        assert_eq!(
+            ctx.xmm_registers[0],
+            0x10_0f_0e_0d_0c_0b_0a_09_08_07_06_05_04_03_02_01
+        );
+        assert_eq!(ctx.last_exception_from_rip, 0xff_ff_f8_05_10_87_76_a0);
+    }
+
+    /// Decode the synthetic headers / records used to build the physical memory
+    /// map.
+    #[test]
+    fn physmem_structs_le() {
+        let raw = [
+            0x03, 0x00, 0x00, 0x00, 0xaa, 0xbb, 0xcc, 0xdd, 0x00, 0x01, 0x02, 0x03, 0x00, 0x00,
+            0x00, 0x00,
+        ];
+        let desc = PhysmemDesc::from_le_bytes(&raw);
+        assert_eq!(desc.number_of_runs, 3);
+        assert_eq!(desc.number_of_pages, 0x03_02_01_00);
+
+        let raw = [
+            0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x11, 0x12, 0x13, 0x14, 0x00, 0x00,
+            0x00, 0x00,
+        ];
+        let run = PhysmemRun::from_le_bytes(&raw);
+        assert_eq!(run.base_page, 0x08_07_06_05_04_03_02_01);
+        assert_eq!(run.page_count, 0x14_13_12_11);
+        let range = PfnRange::from_le_bytes(&raw);
+        assert_eq!(range.page_file_number, 0x08_07_06_05_04_03_02_01);
+        assert_eq!(range.number_of_pages, 0x14_13_12_11);
+
+        let mut raw = vec![0; BmpHeader64::SIZE];
+        put(&mut raw, 0x0, b"SDMP");
+        put(&mut raw, 0x4, b"DUMP");
+        put(&mut raw, 0x20, &[0x00, 0x20, 0x01, 0, 0, 0, 0, 0]);
+        put(&mut raw, 0x28, &[0x4b, 0x54, 0, 0, 0, 0, 0, 0]);
+        put(&mut raw, 0x30, &[0x00, 0x00, 0x08, 0, 0, 0, 0, 0]);
+        let bmp = BmpHeader64::from_le_bytes(&raw);
+        assert!(bmp.looks_good());
+        assert_eq!(bmp.first_page, 0x1_20_00);
+        assert_eq!(bmp.total_present_pages, 0x54_4b);
+        assert_eq!(bmp.pages, 0x8_00_00);
+
+        let mut raw = vec![0; RdmpHeader64::SIZE];
+        put(&mut raw, 0x0, &[0x40, 0, 0, 0]);
+        put(&mut raw, 0x4, b"RDMP");
+        put(&mut raw, 0x8, b"DUMP");
+        put(&mut raw, 0x10, &[0x20, 0x10, 0, 0, 0, 0, 0, 0]);
+        put(&mut raw, 0x18, &[0x40, 0x30, 0, 0, 0, 0, 0, 0]);
+        let rdmp = RdmpHeader64::from_le_bytes(&raw);
+        assert!(rdmp.looks_good());
+        assert_eq!(rdmp.metadata_size, 0x10_20);
+        assert_eq!(rdmp.first_page_offset, 0x30_40);
+        assert_eq!(rdmp.extension(), Some(0));
+    }
+
+    /// Decode the structures read out of the dump's virtual memory; this
+    /// covers the implicit padding inside `UNICODE_STRING` and
+    /// `KDDEBUGGER_DATA64`.
+    #[test]
+    fn memory_structs_le() {
+        let mut raw = vec![0; LdrDataTableEntry::SIZE];
+        put(&mut raw, 0x0, &[
+            0x10, 0x32, 0x54, 0x76, 0x98, 0xba, 0xdc, 0xfe,
+        ]);
+        put(&mut raw, 0x30, &[
+            0x00, 0x00, 0x61, 0x10, 0x05, 0xf8, 0xff, 0xff,
+        ]);
+        put(&mut raw, 0x40, &[0x00, 0x30, 0x0a, 0x00]);
+        put(&mut raw, 0x48, &[
+            0x3e, 0x00, 0x40, 0x00, 0xaa, 0xaa, 0xaa, 0xaa,
+        ]);
+        put(&mut raw, 0x50, &[
+            0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01,
+        ]);
+        let entry = LdrDataTableEntry::from_le_bytes(&raw);
+        assert_eq!(entry.in_load_order_links.flink, 0xfe_dc_ba_98_76_54_32_10);
+        assert_eq!(entry.dll_base, 0xff_ff_f8_05_10_61_00_00);
+        assert_eq!(entry.size_of_image, 0xa_30_00);
+        assert_eq!(entry.full_dll_name.length, 0x3e);
+        assert_eq!(entry.full_dll_name.maximum_length, 0x40);
+        assert_eq!(entry.full_dll_name.buffer, 0x01_02_03_04_05_06_07_08);
+
+        let us = UnicodeString::from_le_bytes(&raw[0x48..]);
+        assert_eq!(us.buffer, 0x01_02_03_04_05_06_07_08);
+
+        let mut raw = vec![0; KdDebuggerData64::SIZE];
+        put(&mut raw, 0x14, &[0x40, 0x03, 0, 0]);
+        put(&mut raw, 0x18, &[
+            0x00, 0x00, 0x6b, 0x10, 0x05, 0xf8, 0xff, 0xff,
+        ]);
+        put(&mut raw, 0x218, &[
+            0x80, 0x9f, 0x4e, 0x0f, 0x05, 0xf8, 0xff, 0xff,
+        ]);
+        put(&mut raw, 0x29a, &[0xf0, 0x00]);
+        put(&mut raw, 0x2c8, &[
+            0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88,
+        ]);
+        put(&mut raw, 0x338, &[0x18, 0x61]);
+        let kdbg = KdDebuggerData64::from_le_bytes(&raw);
+        assert_eq!(kdbg.header.size, 0x3_40);
+        assert_eq!(kdbg.kern_base, 0xff_ff_f8_05_10_6b_00_00);
+        assert_eq!(kdbg.ki_processor_block, 0xff_ff_f8_05_0f_4e_9f_80);
+        assert_eq!(kdbg.offset_kthread_teb, 0xf0);
+        assert_eq!(kdbg.kd_print_circular_buffer_ptr, 0x88_77_66_55_44_33_22_11);
+        assert_eq!(kdbg.offset_prcb_context, 0x61_18);
     }
 }
@@ -0,0 +1,426 @@
+//! This contains what is needed to overlay physical memory captured apart from
+//! the dump onto it: see [`KernelDumpParser::add_supplemental_physmem`].
+//!
+//! The pages of a supplemental source are indexed like the pages of the dump,
+//! with offsets that have their top bit set ([`SUPPLEMENTAL_OFFSETS`]); the
+//! reads at those offsets go to the source instead of the dump file, so every
+//! API reads them transparently. Those offsets never leave the parser: the
+//! APIs that hand out offsets tell which [`PageSource`] they are in.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use std::fs::File;
+//! # use kdmp_parser::{Gpa, KernelDumpParser};
+//! let mut parser = KernelDumpParser::new(&"bmp.dmp").unwrap();
+//! // The sidecar has two ranges, one after the other.
+//! let sidecar = File::open("bmp.sidecar").unwrap();
+//! let ranges = [
+//!     (Gpa::new(0x1_000), 0x2_000),
+//!     (Gpa::new(0x10_000), 0x1_000),
+//! ];
+//! let added = parser.add_supplemental_physmem(&ranges, sidecar).unwrap();
+//! println!("{added} pages added");
+//! ```
+use std::collections::btree_map::{self, Entry};
+use std::fmt::{self, Display};
+use std::io;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use crate::error::{Result, Warning};
+use crate::gxa::Gxa;
+use crate::map::Reader;
+use crate::structs::Page;
+use crate::{Gpa, KdmpParserError, KernelDumpParser};
+
+/// The offsets at and past which the pages are in a supplemental source.
+pub(crate) const SUPPLEMENTAL_OFFSETS: u64 = 1 << 63;
+
+/// A source of supplemental physical memory, and the range of offsets its
+/// pages are at.
+pub(crate) struct Supplement {
+    base: u64,
+    end: u64,
+    reader: Mutex<Box<dyn Reader + Send>>,
+}
+
+/// Where the content of a page of physical memory is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PageSource {
+    /// The dump file.
+    Dump,
+    /// A supplemental source, by the order it was added in (see
+    /// [`KernelDumpParser::add_supplemental_physmem`]).
+    Supplemental(usize),
+}
+
+impl Display for PageSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Dump => write!(f, "the dump"),
+            Self::Supplemental(idx) => write!(f, "the supplemental source #{idx}"),
+        }
+    }
+}
+
+/// Is the page at `offset` in a supplemental source rather than in the dump
+/// file?
+pub(crate) fn is_supplemental(offset: u64) -> bool {
+    offset >= SUPPLEMENTAL_OFFSETS
+}
+
+/// The pages of the physical memory map that are in the dump file; see
+/// [`KernelDumpParser::physmem`].
+pub(crate) struct DumpPages<'parser> {
+    pub(crate) pages: btree_map::Iter<'parser, Gpa, u64>,
+    /// How many of `pages` are in the dump file.
+    pub(crate) left: usize,
+}
+
+impl Iterator for DumpPages<'_> {
+    type Item = (Gpa, u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let page = self
+            .pages
+            .find(|&(_, &offset)| !is_supplemental(offset))
+            .map(|(&gpa, &offset)| (gpa, offset))?;
+        self.left -= 1;
+
+        Some(page)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.left, Some(self.left))
+    }
+}
+
+impl ExactSizeIterator for DumpPages<'_> {}
+
+impl KernelDumpParser {
+    /// Overlay physical memory captured apart from the dump onto it: `source`
+    /// holds the pages of `ranges`, one range after the other. A range is a
+    /// page aligned [`Gpa`] and its length in bytes, a multiple of the size
+    /// of a page; the ranges can't overlap.
+    ///
+    /// The pages the dump doesn't have are added to it, and every API reads
+    /// them like the other pages; they are listed by
+    /// [`KernelDumpParser::supplemental_physmem`] rather than
+    /// [`KernelDumpParser::physmem`]. The dump wins for the pages it
+    /// already has, which is a warning (see [`Warning::SupplementalConflict`]);
+    /// in strict mode, it fails before any page is added.
+    /// The modules are found when the dump is parsed; call
+    /// [`KernelDumpParser::set_default_dtb`] to look for them again.
+    ///
+    /// It returns how many pages have been added.
+    pub fn add_supplemental_physmem(
+        &mut self,
+        ranges: &[(Gpa, u64)],
+        source: impl Reader + Send + 'static,
+    ) -> Result<usize> {
+        let base = self.supplemental_end()?;
+
+        // Check all the ranges before adding any page.
+        let mut pages = Vec::new();
+        let mut source_offset = 0u64;
+        for &(gpa, len) in ranges {
+            if !gpa.page_aligned() || len % Page::size() != 0 {
+                return Err(KdmpParserError::InvalidData(
+                    "supplemental range isn't page aligned",
+                ));
+            }
+
+            let end = gpa
+                .u64()
+                .checked_add(len)
+                .ok_or(KdmpParserError::Overflow("supplemental range"))?;
+            let offset = base
+                .checked_add(source_offset)
+                .ok_or(KdmpParserError::Overflow("supplemental offset"))?;
+            pages.push((Gpa::new(gpa.u64())..Gpa::new(end), offset));
+            source_offset = source_offset
+                .checked_add(len)
+                .ok_or(KdmpParserError::Overflow("supplemental offset"))?;
+        }
+
+        // The ranges can't overlap, otherwise a page would be in the source twice.
+        let mut sorted = pages.iter().map(|(range, _)| range).collect::<Vec<_>>();
+        sorted.sort_by_key(|range| range.start);
+        if sorted.windows(2).any(|pair| pair[0].end > pair[1].start) {
+            return Err(KdmpParserError::InvalidData("supplemental ranges overlap"));
+        }
+
+        // The conflicts are warned about before anything is added, so that the
+        // parser is left untouched if one fails in strict mode.
+        let conflicts = pages
+            .iter()
+            .filter_map(|(range, _)| {
+                let pages = self.physmem.range(range.clone()).count() as u64;
+
+                (pages != 0).then(|| Warning::SupplementalConflict {
+                    range: range.clone(),
+                    pages,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for warning in conflicts {
+            self.warn(warning)?;
+        }
+
+        let end = base
+            .checked_add(source_offset)
+            .ok_or(KdmpParserError::Overflow("supplemental offset"))?;
+        self.supplements.push(Supplement {
+            base,
+            end,
+            reader: Mutex::new(Box::new(source)),
+        });
+
+        let mut added = 0;
+        for (range, offset) in pages {
+            for (idx, gpa) in (range.start.u64()..range.end.u64())
+                .step_by(Page::size() as usize)
+                .enumerate()
+            {
+                if let Entry::Vacant(entry) = self.physmem.entry(Gpa::new(gpa)) {
+                    entry.insert(offset + (idx as u64 * Page::size()));
+                    added += 1;
+                }
+            }
+        }
+
+        self.supplemental_pages += added;
+
+        // What has been built out of memory is built again the next time it is
+        // needed.
+        self.excluded_ranges = OnceLock::new();
+        self.object_types = OnceLock::new();
+        self.nt_globals = OnceLock::new();
+        self.exports.lock().unwrap().clear();
+
+        Ok(added)
+    }
+
+    /// Where the offsets of the next supplemental source start; a page is
+    /// left in between the sources so that their pages are never read at
+    /// once.
+    fn supplemental_end(&self) -> Result<u64> {
+        match self.supplements.last() {
+            Some(last) => last
+                .end
+                .checked_add(Page::size())
+                .ok_or(KdmpParserError::Overflow("supplemental offset")),
+            None => Ok(SUPPLEMENTAL_OFFSETS),
+        }
+    }
+
+    /// The pages of the supplemental sources that the dump doesn't have, in
+    /// ascending [`Gpa`] order, along with the source they are in and their
+    /// offset in it.
+    pub fn supplemental_physmem(&self) -> impl Iterator<Item = (Gpa, PageSource, u64)> + '_ {
+        self.physmem
+            .iter()
+            .filter(|&(_, &offset)| is_supplemental(offset))
+            .filter_map(|(&gpa, &offset)| {
+                let (source, offset) = self.split_offset(offset).ok()?;
+
+                Some((gpa, source, offset))
+            })
+    }
+
+    /// Split an offset of the physical memory map into the source it is in,
+    /// and the offset in the source.
+    pub(crate) fn split_offset(&self, offset: u64) -> Result<(PageSource, u64)> {
+        if !is_supplemental(offset) {
+            return Ok((PageSource::Dump, offset));
+        }
+
+        let idx = self
+            .supplements
+            .partition_point(|supplement| supplement.base <= offset)
+            .checked_sub(1)
+            .ok_or(KdmpParserError::InvalidData("no supplemental source"))?;
+
+        Ok((
+            PageSource::Supplemental(idx),
+            offset - self.supplements[idx].base,
+        ))
+    }
+
+    /// Get the offset of the physical memory map of `offset` in `source`; it
+    /// is the reverse of [`KernelDumpParser::split_offset`].
+    pub(crate) fn join_offset(&self, source: PageSource, offset: u64) -> Result<u64> {
+        match source {
+            PageSource::Dump if !is_supplemental(offset) => Ok(offset),
+            PageSource::Dump => Err(KdmpParserError::Overflow("dump offset")),
+            PageSource::Supplemental(idx) => self
+                .supplements
+                .get(idx)
+                .and_then(|supplement| supplement.base.checked_add(offset))
+                .ok_or(KdmpParserError::InvalidData("no supplemental source")),
+        }
+    }
+
+    /// Lock the reader that has the content at `offset`: the dump file, or a
+    /// supplemental source, and seek it there.
+    pub(crate) fn seek_reader(
+        &self,
+        offset: u64,
+    ) -> Result<MutexGuard<'_, Box<dyn Reader + Send>>> {
+        let (mut reader, offset) = match self.split_offset(offset)? {
+            (PageSource::Dump, offset) => (self.reader.lock().unwrap(), offset),
+            (PageSource::Supplemental(idx), offset) => {
+                (self.supplements[idx].reader.lock().unwrap(), offset)
+            }
+        };
+
+        reader.seek(io::SeekFrom::Start(offset))?;
+
+        Ok(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DumpBuilder;
+    use crate::{Gva, IoSpan, ParserOptions, PxeFlags, ReadFailureExplanation};
+
+    const GVA: u64 = 0xffff_f800_0000_0000;
+
+    #[test]
+    fn supplemental_physmem() {
+        // The page is mapped, but the dump doesn't have it..
+        let dump = DumpBuilder::new()
+            .write_phys(0x10_000, &[0xaa; 0x1_000])
+            .map_virt(GVA, 0x11_000, PxeFlags::Present)
+            .map_virt(GVA + 0x1_000, 0x12_000, PxeFlags::Present)
+            .build();
+        let mut parser = KernelDumpParser::from_bytes(dump).unwrap();
+        assert!(parser.virt_read_exact(Gva::new(GVA), &mut [0; 8]).is_err());
+
+        // ..until the sidecar that has it, and the page the dump already has,
+        // is overlaid.
+        let mut sidecar = vec![0xbb; 0x1_000];
+        sidecar.extend_from_slice(&[0xcc; 0x2_000]);
+        let added = parser
+            .add_supplemental_physmem(
+                &[(Gpa::new(0x10_000), 0x1_000), (Gpa::new(0x11_000), 0x2_000)],
+                io::Cursor::new(sidecar),
+            )
+            .unwrap();
+        assert_eq!(added, 2);
+        assert_eq!(parser.warnings(), [Warning::SupplementalConflict {
+            range: Gpa::new(0x10_000)..Gpa::new(0x11_000),
+            pages: 1
+        }]);
+        assert_eq!(
+            parser.phys_locate(Gpa::new(0x11_000)).unwrap(),
+            (PageSource::Supplemental(0), 0x1_000)
+        );
+        assert!(parser.phys_translate(Gpa::new(0x11_000)).is_err());
+
+        // The offsets in the sources are never mixed with the ones of the dump..
+        let dump_pages = parser.physmem_len();
+        assert_eq!(parser.physmem().len() as u64, dump_pages);
+        assert!(parser.physmem().all(|(gpa, _)| gpa != Gpa::new(0x11_000)));
+        assert_eq!(parser.supplemental_physmem().collect::<Vec<_>>(), [
+            (Gpa::new(0x11_000), PageSource::Supplemental(0), 0x1_000),
+            (Gpa::new(0x12_000), PageSource::Supplemental(0), 0x2_000)
+        ]);
+        let plan = parser.plan_phys_read(Gpa::new(0x10_ff8), 0x10).unwrap();
+        assert_eq!(plan[1], IoSpan {
+            source: PageSource::Supplemental(0),
+            file_offset: 0x1_000,
+            len: 8,
+            gap: false
+        });
+        assert_eq!(
+            parser.explain_read_failure(Gva::new(GVA + 0x8), None),
+            ReadFailureExplanation::Readable {
+                gpa: Gpa::new(0x11_008),
+                source: PageSource::Supplemental(0),
+                offset: 0x1_008
+            }
+        );
+
+        // The reads that straddle the dump and the sidecar go through..
+        let mut buffer = [0; 0x2_000];
+        parser.virt_read_exact(Gva::new(GVA), &mut buffer).unwrap();
+        assert_eq!(buffer, [0xcc; 0x2_000]);
+        let mut buffer = [0; 0x10];
+        parser
+            .phys_read_exact(Gpa::new(0x10_ff8), &mut buffer)
+            .unwrap();
+        assert_eq!(buffer[..8], [0xaa; 8]);
+        assert_eq!(buffer[8..], [0xcc; 8]);
+
+        // ..and the pages of another source don't mix with the first one.
+        let added = parser
+            .add_supplemental_physmem(
+                &[(Gpa::new(0x13_000), 0x1_000)],
+                io::Cursor::new([0xdd; 0x1_000]),
+            )
+            .unwrap();
+        assert_eq!(added, 1);
+        let mut buffer = [0; 0x2_000];
+        parser
+            .phys_read_exact(Gpa::new(0x12_000), &mut buffer)
+            .unwrap();
+        assert_eq!(buffer[..0x1_000], [0xcc; 0x1_000]);
+        assert_eq!(buffer[0x1_000..], [0xdd; 0x1_000]);
+
+        // ..even once there are several sources.
+        assert_eq!(parser.physmem_len(), dump_pages);
+        assert_eq!(
+            parser.phys_locate(Gpa::new(0x13_000)).unwrap(),
+            (PageSource::Supplemental(1), 0)
+        );
+        let pages = parser.physmem_file_order().collect::<Vec<_>>();
+        assert_eq!(pages.len() as u64, dump_pages);
+        assert!(pages.windows(2).all(|pair| pair[0].1 < pair[1].1));
+
+        // The ranges have to be page aligned, and can't overlap.
+        assert!(matches!(
+            parser.add_supplemental_physmem(&[(Gpa::new(0x20_000), 0x10)], io::Cursor::new([])),
+            Err(KdmpParserError::InvalidData(_))
+        ));
+        assert!(matches!(
+            parser.add_supplemental_physmem(
+                &[(Gpa::new(0x21_000), 0x2_000), (Gpa::new(0x20_000), 0x2_000)],
+                io::Cursor::new([0; 0x4_000])
+            ),
+            Err(KdmpParserError::InvalidData(_))
+        ));
+    }
+
+    #[test]
+    fn strict_conflicts() {
+        // In strict mode, a conflict fails before any page is added.
+        let dump = DumpBuilder::new()
+            .write_phys(0x11_000, &[0xaa; 0x1_000])
+            .build();
+        let options = ParserOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let mut parser = KernelDumpParser::with_options(io::Cursor::new(dump), options).unwrap();
+        let pages = parser.physmem_len();
+        assert!(matches!(
+            parser.add_supplemental_physmem(
+                &[(Gpa::new(0x10_000), 0x1_000), (Gpa::new(0x11_000), 0x1_000)],
+                io::Cursor::new([0xbb; 0x2_000])
+            ),
+            Err(KdmpParserError::Strict(Warning::SupplementalConflict {
+                pages: 1,
+                ..
+            }))
+        ));
+        assert_eq!(parser.physmem_len(), pages);
+        assert!(parser
+            .phys_read_exact(Gpa::new(0x10_000), &mut [0; 8])
+            .is_err());
+        assert!(parser.supplements.is_empty());
+    }
+}
@@ -0,0 +1,224 @@
+//! This contains what is needed to decode the TEB of a thread (see
+//! [`TebInfo`]), which is what user-mode rooted crashes are about.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::KernelDumpParser;
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! let teb = parser.teb(None).unwrap();
+//! if let (Some(base), Some(limit)) = (teb.stack_base, teb.stack_limit) {
+//!     println!("the crashing thread's stack is {limit}-{base}");
+//! }
+//! ```
+use crate::error::Result;
+use crate::gxa::{Gpa, Gxa};
+use crate::trace::trace_debug;
+use crate::{Gva, KdmpParserError, KernelDumpParser};
+
+/// Where the 32-bit TEB of a WOW64 thread is relative to its 64-bit TEB when
+/// `WowTebOffset` can't be read.
+const WOW64_TEB_OFFSET: u64 = 0x2_000;
+
+/// The fields of a TEB (`nt!_TEB` or `nt!_TEB32`). The fields that couldn't be
+/// read, because the TEB is paged out for example, are `None`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TebInfo {
+    /// Address of the TEB.
+    pub teb: Gva,
+    /// The `NtTib.StackBase` of the TEB.
+    pub stack_base: Option<Gva>,
+    /// The `NtTib.StackLimit` of the TEB.
+    pub stack_limit: Option<Gva>,
+    /// The `NtTib.SubSystemTib` of the TEB.
+    pub sub_system_tib: Option<Gva>,
+    /// The `LastErrorValue` of the TEB.
+    pub last_error_value: Option<u32>,
+    /// The `TlsSlots` of the TEB.
+    pub tls_slots: Option<Vec<u64>>,
+    /// The 32-bit TEB of a WOW64 thread.
+    pub teb32: Option<Box<TebInfo>>,
+}
+
+/// Turn the errors caused by memory that isn't in the dump into `None`.
+pub(crate) fn available<T>(res: Result<T>) -> Result<Option<T>> {
+    match res {
+        Ok(value) => Ok(Some(value)),
+        Err(
+            KdmpParserError::AddrTranslation(..)
+            | KdmpParserError::PartialVirtRead
+            | KdmpParserError::PartialPhysRead,
+        ) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+impl KernelDumpParser {
+    /// Decode the TEB of the `_ETHREAD` at `ethread`, or of the thread that
+    /// crashed if `None`. The TEB is read in the address space of the process
+    /// the thread belongs to; for a WOW64 thread, its 32-bit TEB is decoded as
+    /// well.
+    ///
+    /// The TEB pointer comes from the `_KTHREAD`; for the thread that crashed,
+    /// it falls back to the `GS` base saved in the `_KPRCB` of its processor.
+    pub fn teb(&self, ethread: Option<Gva>) -> Result<TebInfo> {
+        let thread = self.thread_or_crashing(ethread)?;
+        let teb = available(self.read_field(thread, "_KTHREAD", "Teb"))?
+            .filter(|&teb| teb != 0)
+            .map(Gva::new)
+            .or_else(|| ethread.is_none().then(|| self.gs_base_user()).flatten())
+            .ok_or(KdmpParserError::NotFound("the teb"))?;
+
+        let (process, dtb) = self.thread_address_space(thread)?;
+        let mut info = self.decode_teb(teb, "_TEB", dtb)?;
+        let wow64 = match process {
+            Some(process) => available(self.is_wow64(process))?,
+            None => None,
+        };
+
+        if wow64 == Some(true) {
+            let offset = available(self.read_field_with_dtb(teb, "_TEB", "WowTebOffset", dtb))?
+                // `WowTebOffset` is signed.
+                .map(|offset| i64::from(offset as u32 as i32) as u64)
+                .filter(|&offset| offset != 0)
+                .unwrap_or(WOW64_TEB_OFFSET);
+
+            let teb32 = Gva::new(teb.u64().wrapping_add(offset));
+            info.teb32 = Some(Box::new(self.decode_teb(teb32, "_TEB32", dtb)?));
+        }
+
+        Ok(info)
+    }
+
+    /// Get `ethread`, or the `_ETHREAD` of the thread that crashed if `None`.
+    pub(crate) fn thread_or_crashing(&self, ethread: Option<Gva>) -> Result<Gva> {
+        match ethread {
+            Some(ethread) => Ok(ethread),
+            None => self
+                .crashing_prcb()
+                .and_then(|prcb| self.read_field(prcb, "_KPRCB", "CurrentThread").ok())
+                .map(Gva::new)
+                .ok_or(KdmpParserError::NotFound("the crashing thread")),
+        }
+    }
+
+    /// Get the process the `_ETHREAD` at `thread` belongs to, and the directory
+    /// table base of its address space. If the process can't be read, it is
+    /// the directory table base of the dump.
+    pub(crate) fn thread_address_space(&self, thread: Gva) -> Result<(Option<Gva>, Gpa)> {
+        let process = available(self.read_field(thread, "_KTHREAD", "Process"))?.map(Gva::new);
+        let dtb = match process {
+            Some(process) => {
+                available(self.read_field(process, "_EPROCESS", "DirectoryTableBase"))?
+            }
+            None => None,
+        };
+
+        let dtb = dtb.map(Gpa::new).unwrap_or_else(|| {
+            trace_debug!("failed reading the directory table base of {thread}'s process");
+
+            self.default_dtb()
+        });
+
+        Ok((process, dtb))
+    }
+
+    /// Decode the `type_name` (`_TEB` or `_TEB32`) structure at `teb`.
+    fn decode_teb(&self, teb: Gva, type_name: &str, dtb: Gpa) -> Result<TebInfo> {
+        let field = |name| available(self.read_field_with_dtb(teb, type_name, name, dtb));
+
+        Ok(TebInfo {
+            teb,
+            stack_base: field("NtTib.StackBase")?.map(Gva::new),
+            stack_limit: field("NtTib.StackLimit")?.map(Gva::new),
+            sub_system_tib: field("NtTib.SubSystemTib")?.map(Gva::new),
+            last_error_value: field("LastErrorValue")?.map(|value| value as u32),
+            tls_slots: available(self.read_array_with_dtb(teb, type_name, "TlsSlots", dtb))?,
+            teb32: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DumpBuilder;
+    use crate::PxeFlags;
+
+    const THREAD: u64 = 0xffff_c000_0100_0000;
+    const PROCESS: u64 = 0xffff_c000_0200_0000;
+    const TEB: u64 = 0x7ff6_0000_0000;
+
+    /// Build a dump with a WOW64 thread whose process has `dtb` as directory
+    /// table base. The second page of its 64-bit TEB is paged out.
+    fn wow64_thread(dtb: Option<u64>) -> DumpBuilder {
+        let user = PxeFlags::Present | PxeFlags::UserAccessible;
+        let teb32 = TEB + 0x2_000;
+        let mut tls = vec![0; 64 * 4];
+        tls[4..8].copy_from_slice(&0x1234u32.to_le_bytes());
+        DumpBuilder::new()
+            .map_virt(THREAD, 0x10_000, PxeFlags::Present)
+            .map_virt(PROCESS, 0x11_000, PxeFlags::Present)
+            .map_virt(TEB, 0x12_000, user)
+            .map_virt(TEB + 0x1_000, 0x13_000, PxeFlags::empty())
+            .map_virt(teb32, 0x14_000, user)
+            .write_virt(THREAD + 0xf0, &TEB.to_le_bytes())
+            .write_virt(THREAD + 0x220, &PROCESS.to_le_bytes())
+            .write_virt(PROCESS + 0x28, &dtb.unwrap_or(0).to_le_bytes())
+            .write_virt(PROCESS + 0x580, &0xffff_c000_0300_0000u64.to_le_bytes())
+            .write_virt(TEB + 0x8, &0x7ff6_0010_0000u64.to_le_bytes())
+            .write_virt(TEB + 0x10, &0x7ff6_000f_0000u64.to_le_bytes())
+            .write_virt(TEB + 0x18, &0u64.to_le_bytes())
+            .write_virt(TEB + 0x68, &5u32.to_le_bytes())
+            .write_virt(teb32 + 0x4, &0x30_0000u32.to_le_bytes())
+            .write_virt(teb32 + 0x8, &0x2f_0000u32.to_le_bytes())
+            .write_virt(teb32 + 0xc, &0x1000u32.to_le_bytes())
+            .write_virt(teb32 + 0x34, &2u32.to_le_bytes())
+            .write_virt(teb32 + 0xe10, &tls)
+    }
+
+    #[test]
+    fn wow64_teb() {
+        // The process uses the same directory table base as the dump, which is only
+        // known once the dump is built.
+        let dtb = KernelDumpParser::from_bytes(wow64_thread(None).build())
+            .unwrap()
+            .headers()
+            .directory_table_base;
+        let parser = KernelDumpParser::from_bytes(wow64_thread(Some(dtb)).build()).unwrap();
+
+        let mut tls = vec![0; 64];
+        tls[1] = 0x1234;
+        assert_eq!(parser.teb(Some(Gva::new(THREAD))).unwrap(), TebInfo {
+            teb: Gva::new(TEB),
+            stack_base: Some(Gva::new(0x7ff6_0010_0000)),
+            stack_limit: Some(Gva::new(0x7ff6_000f_0000)),
+            sub_system_tib: Some(Gva::new(0)),
+            last_error_value: Some(5),
+            tls_slots: None,
+            teb32: Some(Box::new(TebInfo {
+                teb: Gva::new(TEB + 0x2_000),
+                stack_base: Some(Gva::new(0x30_0000)),
+                stack_limit: Some(Gva::new(0x2f_0000)),
+                sub_system_tib: Some(Gva::new(0x1000)),
+                last_error_value: Some(2),
+                tls_slots: Some(tls),
+                teb32: None
+            }))
+        });
+
+        // The TEB isn't read in the address space of the dump but in the one of
+        // the process, which isn't in the dump.
+        let parser = KernelDumpParser::from_bytes(wow64_thread(Some(0x50_000)).build()).unwrap();
+        let teb = parser.teb(Some(Gva::new(THREAD))).unwrap();
+        assert_eq!(teb.stack_base, None);
+        assert_eq!(teb.last_error_value, None);
+        assert_eq!(teb.teb32.unwrap().stack_base, None);
+
+        // Without a thread, the crashing thread can't be found.
+        assert!(matches!(
+            parser.teb(None),
+            Err(KdmpParserError::NotFound(_))
+        ));
+    }
+}
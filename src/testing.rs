@@ -0,0 +1,706 @@
+//! This contains [`DumpBuilder`] which builds small, deterministic, BMP dumps
+//! in memory; it is meant for testing code that consumes dumps without having
+//! to ship real ones.
+//!
+//! # Examples
+//!
+//! ```
+//! use kdmp_parser::testing::{DumpBuilder, Register};
+//! use kdmp_parser::{Gva, KernelDumpParser, PxeFlags};
+//!
+//! let dump = DumpBuilder::new()
+//!     .write_phys(0x1_000, b"hello")
+//!     .map_virt(0xffff_f800_0000_0000, 0x1_000, PxeFlags::Present)
+//!     .register(Register::Rip, 0xffff_f800_0000_0000)
+//!     .build();
+//!
+//! let parser = KernelDumpParser::from_bytes(dump).unwrap();
+//! let mut buffer = [0; 5];
+//! parser
+//!     .virt_read_exact(Gva::new(0xffff_f800_0000_0000), &mut buffer)
+//!     .unwrap();
+//! assert_eq!(&buffer, b"hello");
+//! assert_eq!(parser.context_record().rip, 0xffff_f800_0000_0000);
+//! ```
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use crate::gxa::{Gva, Gxa};
+use crate::pxe::PxeFlags;
+use crate::structs::{
+    BmpHeader64, FromLeBytes, Header64, KdDebuggerData64, LdrDataTableEntry, Page,
+};
+
+/// Where [`DumpBuilder`] lays out the module list in virtual memory.
+pub const MODULE_LIST_BASE: u64 = 0xffff_fa80_0000_0000;
+
+/// The build number the dumps are made for.
+const MINOR_VERSION: u32 = 19_041;
+
+/// Size of a `_KLDR_DATA_TABLE_ENTRY`.
+const LDR_ENTRY_SIZE: u64 = 0xa0;
+
+/// Size of a `_LIST_ENTRY`.
+const LIST_ENTRY_SIZE: u64 = 0x10;
+
+/// Size of a large page.
+const LARGE_PAGE_SIZE: u64 = 0x20_0000;
+
+/// Offset of the page frame number in a page table entry.
+const PFN_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+/// Where [`DumpBuilder::kd_debugger_data`] lays out `KiProcessorBlock`,
+/// relative to the `KDDEBUGGER_DATA64`.
+const PROCESSOR_BLOCK_OFFSET: u64 = 0x800;
+
+/// Page-align a physical address.
+fn page_align(gpa: u64) -> u64 {
+    gpa & !(Page::size() - 1)
+}
+
+/// The registers of the context record that can be set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Register {
+    Rax,
+    Rcx,
+    Rdx,
+    Rbx,
+    Rsp,
+    Rbp,
+    Rsi,
+    Rdi,
+    R8,
+    R9,
+    R10,
+    R11,
+    R12,
+    R13,
+    R14,
+    R15,
+    Rip,
+}
+
+impl Register {
+    /// Offset of the register in the context record.
+    ///
+    /// ```text
+    /// kd> dt nt!_CONTEXT Rax Rip
+    ///    +0x078 Rax              : Uint8B
+    ///    +0x0f8 Rip              : Uint8B
+    /// ```
+    fn offset(self) -> usize {
+        // The general purpose registers are laid out in that order, from `Rax`
+        // to `Rip`.
+        0x78 + (self as usize * 8)
+    }
+}
+
+/// The fields of a `KDDEBUGGER_DATA64` that [`DumpBuilder::kd_debugger_data`]
+/// writes; the others are zero.
+///
+/// ```text
+/// kd> dt nt!_KDDEBUGGER_DATA64 KernBase PsLoadedModuleList KiProcessorBlock MmUnloadedDrivers
+///    +0x018 KernBase           : Uint8B
+///    +0x048 PsLoadedModuleList : Uint8B
+///    +0x218 KiProcessorBlock   : Uint8B
+///    +0x220 MmUnloadedDrivers  : Uint8B
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct KdDebuggerData {
+    /// `KernBase`.
+    pub kern_base: u64,
+    /// `PsLoadedModuleList`.
+    pub ps_loaded_module_list: u64,
+    /// `MmUnloadedDrivers`.
+    pub mm_unloaded_drivers: u64,
+    /// The `_KPRCB` of every processor; they are laid out in a
+    /// `KiProcessorBlock` in the page of the structure.
+    pub prcbs: Vec<u64>,
+    /// `OffsetPrcbNumber`.
+    pub offset_prcb_number: u16,
+    /// `OffsetPrcbProcStateSpecialReg`.
+    pub offset_prcb_proc_state_special_reg: u16,
+    /// `OffsetPrcbContext`.
+    pub offset_prcb_context: u16,
+}
+
+impl KdDebuggerData {
+    /// The structure, at `gva`.
+    fn to_bytes(&self, gva: u64) -> Vec<u8> {
+        let mut data = vec![0; KdDebuggerData64::SIZE];
+        data[0x10..0x14].copy_from_slice(b"KDBG");
+        data[0x18..0x20].copy_from_slice(&self.kern_base.to_le_bytes());
+        data[0x48..0x50].copy_from_slice(&self.ps_loaded_module_list.to_le_bytes());
+        if !self.prcbs.is_empty() {
+            let processor_block = gva + PROCESSOR_BLOCK_OFFSET;
+            data[0x218..0x220].copy_from_slice(&processor_block.to_le_bytes());
+        }
+
+        data[0x220..0x228].copy_from_slice(&self.mm_unloaded_drivers.to_le_bytes());
+        data[0x2be..0x2c0].copy_from_slice(&self.offset_prcb_number.to_le_bytes());
+        data[0x2f2..0x2f4].copy_from_slice(&self.offset_prcb_proc_state_special_reg.to_le_bytes());
+        data[0x338..0x33a].copy_from_slice(&self.offset_prcb_context.to_le_bytes());
+
+        data
+    }
+}
+
+/// A virtual to physical mapping.
+#[derive(Debug, Clone, Copy)]
+struct Mapping {
+    gpa: u64,
+    flags: PxeFlags,
+    large: bool,
+}
+
+impl Mapping {
+    fn size(&self) -> u64 {
+        if self.large {
+            LARGE_PAGE_SIZE
+        } else {
+            Page::size()
+        }
+    }
+}
+
+/// Build a BMP dump in memory that [`KernelDumpParser::from_bytes`] accepts.
+/// The physical pages, the virtual mappings, the registers and the kernel
+/// modules are all chosen by the caller; the page tables and the module list
+/// are laid out in the lowest physical pages the caller doesn't use.
+///
+/// [`KernelDumpParser::from_bytes`]: crate::KernelDumpParser::from_bytes
+#[derive(Debug, Default, Clone)]
+pub struct DumpBuilder {
+    pages: BTreeMap<u64, Vec<u8>>,
+    mappings: BTreeMap<u64, Mapping>,
+    registers: Vec<(Register, u64)>,
+    modules: Vec<(Range<Gva>, String)>,
+    processors: Option<u32>,
+    bug_check: Option<(u32, [u64; 4])>,
+    kd_debugger_data_block: u64,
+}
+
+impl DumpBuilder {
+    /// Create a builder for an empty dump.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write `data` in physical memory at `gpa`; the pages it spans are added
+    /// to the dump (zeroed) if they aren't already.
+    pub fn write_phys(mut self, gpa: u64, data: &[u8]) -> Self {
+        self.write_phys_mut(gpa, data);
+
+        self
+    }
+
+    /// Write `data` in virtual memory at `gva`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a page spanned by `data` hasn't been mapped with
+    /// [`DumpBuilder::map_virt`] or [`DumpBuilder::map_virt_large`] first.
+    pub fn write_virt(mut self, gva: u64, data: &[u8]) -> Self {
+        self.write_virt_mut(gva, data);
+
+        self
+    }
+
+    /// Map the page at `gva` to the page at `gpa` with a page table entry that
+    /// has `flags`; leave out [`PxeFlags::Present`] to get a non present entry
+    /// (add [`PxeFlags::Transition`] for a transition one). The page at `gpa`
+    /// doesn't have to be in the dump.
+    pub fn map_virt(mut self, gva: u64, gpa: u64, flags: PxeFlags) -> Self {
+        self.mappings
+            .insert(Gva::new(gva).page_align().u64(), Mapping {
+                gpa: page_align(gpa),
+                flags,
+                large: false,
+            });
+
+        self
+    }
+
+    /// Map the large page (2MB) at `gva` to the one at `gpa` with a page
+    /// directory entry that has `flags` (and [`PxeFlags::LargePage`]).
+    pub fn map_virt_large(mut self, gva: u64, gpa: u64, flags: PxeFlags) -> Self {
+        self.mappings.insert(gva & !(LARGE_PAGE_SIZE - 1), Mapping {
+            gpa: gpa & !(LARGE_PAGE_SIZE - 1),
+            flags: flags | PxeFlags::LargePage,
+            large: true,
+        });
+
+        self
+    }
+
+    /// Set a register of the context record.
+    pub fn register(mut self, register: Register, value: u64) -> Self {
+        self.registers.push((register, value));
+
+        self
+    }
+
+    /// Set the number of processors of the dump; it is one by default.
+    pub fn processors(mut self, count: u32) -> Self {
+        self.processors = Some(count);
+
+        self
+    }
+
+    /// Set the bugcheck code and its parameters.
+    pub fn bug_check(mut self, code: u32, parameters: [u64; 4]) -> Self {
+        self.bug_check = Some((code, parameters));
+
+        self
+    }
+
+    /// Point the headers to a `KDDEBUGGER_DATA64` at `gva`; the structure
+    /// itself is written by the caller.
+    pub fn kd_debugger_data_block(mut self, gva: u64) -> Self {
+        self.kd_debugger_data_block = gva;
+
+        self
+    }
+
+    /// Write the `KDDEBUGGER_DATA64` described by `data` at `gva`, followed by
+    /// its `KiProcessorBlock`, and point the headers to it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the page at `gva` hasn't been mapped first.
+    pub fn kd_debugger_data(mut self, gva: u64, data: KdDebuggerData) -> Self {
+        self.kd_debugger_data_block = gva;
+        self.write_virt_mut(gva, &data.to_bytes(gva));
+        let processor_block = data
+            .prcbs
+            .iter()
+            .flat_map(|prcb| prcb.to_le_bytes())
+            .collect::<Vec<_>>();
+        self.write_virt_mut(gva + PROCESSOR_BLOCK_OFFSET, &processor_block);
+
+        self
+    }
+
+    /// Add a kernel module named `name` spanning `at` to the module list that
+    /// `PsLoadedModuleList` points to. The list is laid out at
+    /// [`MODULE_LIST_BASE`].
+    pub fn module(mut self, at: Range<u64>, name: &str) -> Self {
+        self.modules
+            .push((Gva::new(at.start)..Gva::new(at.end), name.to_string()));
+
+        self
+    }
+
+    /// Build the dump.
+    pub fn build(mut self) -> Vec<u8> {
+        // What the builder lays out in physical memory can't overlap what the caller
+        // uses, even the pages that are mapped but not in the dump.
+        let used = self
+            .mappings
+            .values()
+            .map(|mapping| {
+                let gpa = mapping.gpa & PFN_MASK;
+
+                gpa..gpa + mapping.size()
+            })
+            .collect::<Vec<_>>();
+
+        let ps_loaded_module_list = self.lay_out_modules(&used);
+        let dtb = self.lay_out_page_tables(&used);
+
+        // The main header..
+        let mut dump = vec![0; Header64::SIZE];
+        dump[0x0..0x4].copy_from_slice(b"PAGE");
+        dump[0x4..0x8].copy_from_slice(b"DU64");
+        dump[0x8..0xc].copy_from_slice(&0xfu32.to_le_bytes());
+        dump[0xc..0x10].copy_from_slice(&MINOR_VERSION.to_le_bytes());
+        dump[0x10..0x18].copy_from_slice(&dtb.to_le_bytes());
+        dump[0x20..0x28].copy_from_slice(&ps_loaded_module_list.to_le_bytes());
+        dump[0x30..0x34].copy_from_slice(&0x8664u32.to_le_bytes());
+        dump[0x34..0x38].copy_from_slice(&self.processors.unwrap_or(1).to_le_bytes());
+        if let Some((code, parameters)) = self.bug_check {
+            dump[0x38..0x3c].copy_from_slice(&code.to_le_bytes());
+            for (idx, parameter) in parameters.iter().enumerate() {
+                let offset = 0x40 + (idx * 8);
+                dump[offset..offset + 8].copy_from_slice(&parameter.to_le_bytes());
+            }
+        }
+
+        dump[0x80..0x88].copy_from_slice(&self.kd_debugger_data_block.to_le_bytes());
+        for (register, value) in &self.registers {
+            let offset = 0x348 + register.offset();
+            dump[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+        }
+
+        dump[0xf98..0xf9c].copy_from_slice(&5u32.to_le_bytes());
+
+        // ..the bitmap header..
+        let max_pfn = self
+            .pages
+            .keys()
+            .next_back()
+            .map_or(0, |gpa| gpa / Page::size());
+        let bitmap_size = (max_pfn / 64 + 1) * 8;
+        let first_page = Header64::SIZE as u64 + BmpHeader64::SIZE as u64 + bitmap_size;
+        let mut bmp = vec![0; BmpHeader64::SIZE];
+        bmp[0x0..0x4].copy_from_slice(b"SDMP");
+        bmp[0x4..0x8].copy_from_slice(b"DUMP");
+        bmp[0x20..0x28].copy_from_slice(&first_page.to_le_bytes());
+        bmp[0x28..0x30].copy_from_slice(&(self.pages.len() as u64).to_le_bytes());
+        bmp[0x30..0x38].copy_from_slice(&(bitmap_size * 8).to_le_bytes());
+        dump.extend_from_slice(&bmp);
+
+        // ..the bitmap itself..
+        let mut bitmap = vec![0u8; usize::try_from(bitmap_size).unwrap()];
+        for gpa in self.pages.keys() {
+            let pfn = usize::try_from(gpa / Page::size()).unwrap();
+            bitmap[pfn / 8] |= 1 << (pfn % 8);
+        }
+        dump.extend_from_slice(&bitmap);
+
+        // ..and the pages, in ascending physical address order.
+        for page in self.pages.values() {
+            dump.extend_from_slice(page);
+        }
+
+        // Finally, record how large the dump is.
+        let required_dump_space = dump.len() as u64;
+        dump[0xfa0..0xfa8].copy_from_slice(&required_dump_space.to_le_bytes());
+
+        dump
+    }
+
+    fn write_phys_mut(&mut self, gpa: u64, data: &[u8]) {
+        for (idx, byte) in data.iter().enumerate() {
+            let gpa = gpa + idx as u64;
+            let page = self
+                .pages
+                .entry(page_align(gpa))
+                .or_insert_with(|| vec![0; Page::size() as usize]);
+
+            page[(gpa % Page::size()) as usize] = *byte;
+        }
+    }
+
+    fn write_virt_mut(&mut self, gva: u64, data: &[u8]) {
+        let mut gva = gva;
+        let mut data = data;
+        while !data.is_empty() {
+            let (start, mapping) = self
+                .mappings
+                .range(..=gva)
+                .next_back()
+                .filter(|(start, mapping)| gva - *start < mapping.size())
+                .map(|(start, mapping)| (*start, *mapping))
+                .unwrap_or_else(|| panic!("{gva:#x} isn't mapped"));
+
+            let offset = gva - start;
+            let len = data
+                .len()
+                .min(usize::try_from(mapping.size() - offset).unwrap());
+            let (chunk, rest) = data.split_at(len);
+            self.write_phys_mut((mapping.gpa & PFN_MASK) + offset, chunk);
+            gva += len as u64;
+            data = rest;
+        }
+    }
+
+    fn read_phys_u64(&self, gpa: u64) -> u64 {
+        self.pages.get(&page_align(gpa)).map_or(0, |page| {
+            let offset = (gpa % Page::size()) as usize;
+
+            u64::from_le_bytes(page[offset..offset + 8].try_into().unwrap())
+        })
+    }
+
+    /// Allocate a zeroed page of physical memory in the first free spot.
+    fn alloc_page(&mut self, used: &[Range<u64>]) -> u64 {
+        let gpa = (1..)
+            .map(|pfn| pfn * Page::size())
+            .find(|gpa| {
+                !self.pages.contains_key(gpa) && !used.iter().any(|range| range.contains(gpa))
+            })
+            .unwrap();
+
+        self.pages.insert(gpa, vec![0; Page::size() as usize]);
+
+        gpa
+    }
+
+    /// Lay out the module list in memory and return the address of its head,
+    /// or zero if there are no modules.
+    fn lay_out_modules(&mut self, used: &[Range<u64>]) -> u64 {
+        if self.modules.is_empty() {
+            return 0;
+        }
+
+        // The head comes first, then the entries and then their names.
+        let head = MODULE_LIST_BASE;
+        let entry_addr = |idx: usize| head + LIST_ENTRY_SIZE + (idx as u64 * LDR_ENTRY_SIZE);
+        let count = self.modules.len();
+        let mut name_addr = entry_addr(count);
+        let mut list = vec![0; usize::try_from(name_addr - head).unwrap()];
+        let last = entry_addr(count - 1);
+        list[0x0..0x8].copy_from_slice(&entry_addr(0).to_le_bytes());
+        list[0x8..0x10].copy_from_slice(&last.to_le_bytes());
+        for (idx, (at, name)) in self.modules.iter().enumerate() {
+            let flink = if idx + 1 == count {
+                head
+            } else {
+                entry_addr(idx + 1)
+            };
+            let blink = if idx == 0 { head } else { entry_addr(idx - 1) };
+            let name = name
+                .encode_utf16()
+                .flat_map(u16::to_le_bytes)
+                .collect::<Vec<_>>();
+            let name_len = u16::try_from(name.len()).unwrap();
+
+            // ```text
+            // kd> dt nt!_KLDR_DATA_TABLE_ENTRY InLoadOrderLinks DllBase SizeOfImage FullDllName BaseDllName
+            //    +0x000 InLoadOrderLinks : _LIST_ENTRY
+            //    +0x030 DllBase          : Ptr64 Void
+            //    +0x040 SizeOfImage      : Uint4B
+            //    +0x048 FullDllName      : _UNICODE_STRING
+            //    +0x058 BaseDllName      : _UNICODE_STRING
+            // ```
+            let mut entry = vec![0; LDR_ENTRY_SIZE as usize];
+            let size_of_image = u32::try_from(at.end.u64() - at.start.u64()).unwrap();
+            entry[0x0..0x8].copy_from_slice(&flink.to_le_bytes());
+            entry[0x8..0x10].copy_from_slice(&blink.to_le_bytes());
+            entry[0x30..0x38].copy_from_slice(&at.start.u64().to_le_bytes());
+            entry[0x40..0x44].copy_from_slice(&size_of_image.to_le_bytes());
+            for string in [0x48, 0x58] {
+                entry[string..string + 2].copy_from_slice(&name_len.to_le_bytes());
+                entry[string + 2..string + 4].copy_from_slice(&name_len.to_le_bytes());
+                entry[string + 8..string + 16].copy_from_slice(&name_addr.to_le_bytes());
+            }
+
+            let offset = usize::try_from(entry_addr(idx) - head).unwrap();
+            list[offset..offset + entry.len()].copy_from_slice(&entry);
+            list.extend_from_slice(&name);
+            list.resize((list.len() + 7) & !7, 0);
+            name_addr = head + list.len() as u64;
+        }
+
+        debug_assert!(LdrDataTableEntry::SIZE as u64 <= LDR_ENTRY_SIZE);
+        for offset in (0..list.len() as u64).step_by(Page::size() as usize) {
+            let gpa = self.alloc_page(used);
+            self.mappings.insert(head + offset, Mapping {
+                gpa,
+                flags: PxeFlags::Present | PxeFlags::Writable,
+                large: false,
+            });
+        }
+
+        self.write_virt_mut(head, &list);
+
+        head
+    }
+
+    /// Lay out the page tables that describe the mappings and return the
+    /// directory table base.
+    fn lay_out_page_tables(&mut self, used: &[Range<u64>]) -> u64 {
+        let pml4 = self.alloc_page(used);
+        let mappings = self
+            .mappings
+            .iter()
+            .map(|(gva, mapping)| (*gva, *mapping))
+            .collect::<Vec<_>>();
+
+        for (gva, mapping) in mappings {
+            let gva = Gva::new(gva);
+            let user = mapping.flags.contains(PxeFlags::UserAccessible);
+            let pdpt = self.next_table(pml4, gva.pml4e_idx(), user, used);
+            let pd = self.next_table(pdpt, gva.pdpe_idx(), user, used);
+            let entry = mapping.gpa | mapping.flags.bits();
+            if mapping.large {
+                self.write_phys_mut(pd + (gva.pde_idx() * 8), &entry.to_le_bytes());
+                continue;
+            }
+
+            let pt = self.next_table(pd, gva.pde_idx(), user, used);
+            self.write_phys_mut(pt + (gva.pte_idx() * 8), &entry.to_le_bytes());
+        }
+
+        pml4
+    }
+
+    /// Get the table that the entry `idx` of `table` points to, allocating it
+    /// if needed.
+    fn next_table(&mut self, table: u64, idx: u64, user: bool, used: &[Range<u64>]) -> u64 {
+        let entry_gpa = table + (idx * 8);
+        let entry = self.read_phys_u64(entry_gpa);
+        if entry != 0 {
+            if user {
+                let entry = entry | PxeFlags::UserAccessible.bits();
+                self.write_phys_mut(entry_gpa, &entry.to_le_bytes());
+            }
+
+            return entry & PFN_MASK;
+        }
+
+        let next = self.alloc_page(used);
+        let mut flags = PxeFlags::Present | PxeFlags::Writable;
+        if user {
+            flags |= PxeFlags::UserAccessible;
+        }
+
+        self.write_phys_mut(entry_gpa, &(next | flags.bits()).to_le_bytes());
+
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AddrTranslationError, Gpa, KdmpParserError, KernelDumpParser, PxeNotPresent};
+
+    const GVA: u64 = 0xffff_f805_1060_0000;
+
+    #[test]
+    fn large_page() {
+        let dump = DumpBuilder::new()
+            .write_phys(0x40_1337, b"large")
+            .map_virt_large(GVA, 0x40_0000, PxeFlags::Present)
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+
+        assert_eq!(
+            parser.virt_translate(Gva::new(GVA + 0x1337)).unwrap(),
+            Gpa::new(0x40_1337)
+        );
+        let mut buffer = [0; 5];
+        parser
+            .virt_read_exact(Gva::new(GVA + 0x1337), &mut buffer)
+            .unwrap();
+        assert_eq!(&buffer, b"large");
+    }
+
+    #[test]
+    fn transition_pte() {
+        let dump = DumpBuilder::new()
+            .write_phys(0x1_000, b"transition")
+            .write_phys(0x2_000, b"nope")
+            .map_virt(GVA, 0x1_000, PxeFlags::Transition)
+            .map_virt(GVA + 0x1_000, 0x2_000, PxeFlags::empty())
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+
+        let mut buffer = [0; 10];
+        parser.virt_read_exact(Gva::new(GVA), &mut buffer).unwrap();
+        assert_eq!(&buffer, b"transition");
+        assert!(matches!(
+            parser.virt_translate(Gva::new(GVA + 0x1_000)),
+            Err(KdmpParserError::AddrTranslation(
+                AddrTranslationError::Virt(_, PxeNotPresent::Pte)
+            ))
+        ));
+    }
+
+    #[test]
+    fn missing_page() {
+        // The page is mapped, but isn't in the dump.
+        let dump = DumpBuilder::new()
+            .map_virt(GVA, 0x1_000, PxeFlags::Present)
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+
+        assert_eq!(
+            parser.virt_translate(Gva::new(GVA)).unwrap(),
+            Gpa::new(0x1_000)
+        );
+        assert!(matches!(
+            parser.virt_read_exact(Gva::new(GVA), &mut [0; 8]),
+            Err(KdmpParserError::AddrTranslation(
+                AddrTranslationError::Phys(_)
+            ))
+        ));
+    }
+
+    #[test]
+    fn truncated() {
+        // The page tables are laid out in the low pages, so the data page is the
+        // last one of the dump.
+        let mut dump = DumpBuilder::new()
+            .write_phys(0x10_000, &[0xaa; 0x1_000])
+            .map_virt(GVA, 0x10_000, PxeFlags::Present)
+            .build();
+        dump.truncate(dump.len() - 0x800);
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+
+        let mut buffer = [0; 0x1_000];
+        assert_eq!(parser.virt_read(Gva::new(GVA), &mut buffer).unwrap(), 0x800);
+        assert!(buffer[..0x800].iter().all(|&b| b == 0xaa));
+        assert!(matches!(
+            parser.virt_read_exact(Gva::new(GVA), &mut buffer),
+            Err(KdmpParserError::PartialVirtRead)
+        ));
+    }
+
+    #[test]
+    fn registers_and_modules() {
+        let nt = 0xffff_f800_0000_0000;
+        let dump = DumpBuilder::new()
+            .map_virt(nt, 0x1_000, PxeFlags::Present)
+            .write_virt(nt, b"MZ")
+            .register(Register::Rip, nt + 0x10)
+            .register(Register::Rsp, 0x1337)
+            .register(Register::R15, 0xdead)
+            .module(nt..nt + 0x1_000, "ntoskrnl.exe")
+            .module(GVA..GVA + 0x2_000, "hal.dll")
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+
+        let context = parser.context_record();
+        assert_eq!(context.rip, nt + 0x10);
+        assert_eq!(context.rsp, 0x1337);
+        assert_eq!(context.r15, 0xdead);
+        let modules = parser
+            .kernel_modules()
+            .map(|(at, name)| (at.clone(), name.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(modules, [
+            (Gva::new(nt)..Gva::new(nt + 0x1_000), "ntoskrnl.exe".into()),
+            (Gva::new(GVA)..Gva::new(GVA + 0x2_000), "hal.dll".into()),
+        ]);
+        assert!(parser.warnings().is_empty());
+        assert!(parser.verify().unwrap().checks[..3]
+            .iter()
+            .all(|check| check.outcome == crate::CheckOutcome::Passed));
+    }
+
+    #[test]
+    fn kd_debugger_data() {
+        let kdbg = GVA;
+        let dump = DumpBuilder::new()
+            .map_virt(kdbg, 0x10_000, PxeFlags::Present)
+            .kd_debugger_data(kdbg, KdDebuggerData {
+                kern_base: 0xffff_f800_0000_0000,
+                ps_loaded_module_list: MODULE_LIST_BASE,
+                mm_unloaded_drivers: 0xffff_f800_0100_0000,
+                prcbs: vec![0xffff_f800_0200_0000, 0xffff_f800_0300_0000],
+                offset_prcb_number: 0x24,
+                offset_prcb_proc_state_special_reg: 0x40,
+                offset_prcb_context: 0x400,
+            })
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+
+        let data = parser.kd_debugger_data_block().unwrap();
+        assert_eq!(&data.header.owner_tag.to_le_bytes(), b"KDBG");
+        assert_eq!(data.kern_base, 0xffff_f800_0000_0000);
+        assert_eq!(data.ps_loaded_module_list, MODULE_LIST_BASE);
+        assert_eq!(data.mm_unloaded_drivers, 0xffff_f800_0100_0000);
+        assert_eq!(data.offset_prcb_number, 0x24);
+        assert_eq!(data.offset_prcb_proc_state_special_reg, 0x40);
+        assert_eq!(data.offset_prcb_context, 0x400);
+        let mut prcbs = [0; 0x10];
+        parser
+            .virt_read_exact(Gva::new(data.ki_processor_block), &mut prcbs)
+            .unwrap();
+        assert_eq!(prcbs[..8], 0xffff_f800_0200_0000u64.to_le_bytes());
+        assert_eq!(prcbs[8..], 0xffff_f800_0300_0000u64.to_le_bytes());
+    }
+}
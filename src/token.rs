@@ -0,0 +1,240 @@
+//! This contains what is needed to inspect the access token (`nt!_TOKEN`) of a
+//! process: who it runs as, its groups, its privileges and its integrity
+//! level.
+use crate::error::Result;
+use crate::gxa::Gxa;
+use crate::{Gva, KdmpParserError, KernelDumpParser};
+
+const TOKEN: &str = "_TOKEN";
+const SID_AND_ATTRIBUTES: &str = "_SID_AND_ATTRIBUTES";
+
+/// `_EPROCESS.Token` is an `_EX_FAST_REF`; its low bits are a reference
+/// count.
+const EX_FAST_REF_MASK: u64 = 0xf;
+
+/// Maximum number of groups we'll read off a token.
+const MAX_GROUPS: u64 = 0x1_000;
+
+/// Maximum number of sub authorities of a SID (`SID_MAX_SUB_AUTHORITIES`).
+const SID_MAX_SUB_AUTHORITIES: u8 = 15;
+
+/// Names of the privileges indexed by their LUID; the first two LUIDs aren't
+/// used.
+const PRIVILEGES: [&str; 37] = [
+    "",
+    "",
+    "SeCreateTokenPrivilege",
+    "SeAssignPrimaryTokenPrivilege",
+    "SeLockMemoryPrivilege",
+    "SeIncreaseQuotaPrivilege",
+    "SeMachineAccountPrivilege",
+    "SeTcbPrivilege",
+    "SeSecurityPrivilege",
+    "SeTakeOwnershipPrivilege",
+    "SeLoadDriverPrivilege",
+    "SeSystemProfilePrivilege",
+    "SeSystemtimePrivilege",
+    "SeProfileSingleProcessPrivilege",
+    "SeIncreaseBasePriorityPrivilege",
+    "SeCreatePagefilePrivilege",
+    "SeCreatePermanentPrivilege",
+    "SeBackupPrivilege",
+    "SeRestorePrivilege",
+    "SeShutdownPrivilege",
+    "SeDebugPrivilege",
+    "SeAuditPrivilege",
+    "SeSystemEnvironmentPrivilege",
+    "SeChangeNotifyPrivilege",
+    "SeRemoteShutdownPrivilege",
+    "SeUndockPrivilege",
+    "SeSyncAgentPrivilege",
+    "SeEnableDelegationPrivilege",
+    "SeManageVolumePrivilege",
+    "SeImpersonatePrivilege",
+    "SeCreateGlobalPrivilege",
+    "SeTrustedCredManAccessPrivilege",
+    "SeRelabelPrivilege",
+    "SeIncreaseWorkingSetPrivilege",
+    "SeTimeZonePrivilege",
+    "SeCreateSymbolicLinkPrivilege",
+    "SeDelegateSessionUserImpersonatePrivilege",
+];
+
+/// The security context of a process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenInfo {
+    /// SID of the user, like `S-1-5-18`.
+    pub user_sid: String,
+    /// SIDs of the groups.
+    pub groups: Vec<String>,
+    /// Bitmap of the privileges the token has; bit `n` is the privilege which
+    /// LUID is `n`.
+    pub privileges_present: u64,
+    /// Bitmap of the privileges that are enabled.
+    pub privileges_enabled: u64,
+    /// Integrity level, like `0x3000` for high.
+    pub integrity_level: u32,
+}
+
+impl TokenInfo {
+    /// Names of the privileges the token has.
+    pub fn present_privileges(&self) -> Vec<&'static str> {
+        privilege_names(self.privileges_present)
+    }
+
+    /// Names of the privileges that are enabled.
+    pub fn enabled_privileges(&self) -> Vec<&'static str> {
+        privilege_names(self.privileges_enabled)
+    }
+}
+
+/// Decode a privilege bitmap into the names of the well-known privileges it
+/// has.
+///
+/// # Examples
+///
+/// ```
+/// # use kdmp_parser::privilege_names;
+/// assert_eq!(
+///     privilege_names((1 << 20) | (1 << 23)),
+///     vec!["SeDebugPrivilege", "SeChangeNotifyPrivilege"]
+/// );
+/// ```
+pub fn privilege_names(bitmap: u64) -> Vec<&'static str> {
+    PRIVILEGES
+        .iter()
+        .enumerate()
+        .filter(|&(luid, name)| !name.is_empty() && bitmap & (1 << luid) != 0)
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+/// Format a SID in its text form, like `S-1-5-21-1004336348-1177238915-...`.
+fn format_sid(revision: u8, authority: [u8; 6], sub_authorities: &[u32]) -> String {
+    let mut authority_value = [0; 8];
+    authority_value[2..].copy_from_slice(&authority);
+    let authority_value = u64::from_be_bytes(authority_value);
+
+    // Large authorities are formatted in hexadecimal.
+    let mut sid = match authority_value {
+        0..=0xffff_ffff => format!("S-{revision}-{authority_value}"),
+        _ => format!("S-{revision}-0x{authority_value:012X}"),
+    };
+
+    for sub_authority in sub_authorities {
+        sid.push_str(&format!("-{sub_authority}"));
+    }
+
+    sid
+}
+
+impl KernelDumpParser {
+    /// Get the user, the groups, the privileges and the integrity level of
+    /// the token of the process `eprocess`.
+    pub fn process_token(&self, eprocess: Gva) -> Result<TokenInfo> {
+        let token = self.read_field(eprocess, "_EPROCESS", "Token")? & !EX_FAST_REF_MASK;
+        if token == 0 {
+            return Err(KdmpParserError::InvalidData("process has no token"));
+        }
+
+        let token = Gva::new(token);
+        let count = self.read_field(token, TOKEN, "UserAndGroupCount")?;
+        if count == 0 || count > MAX_GROUPS {
+            return Err(KdmpParserError::InvalidData("invalid token group count"));
+        }
+
+        let entries = Gva::new(self.read_field(token, TOKEN, "UserAndGroups")?);
+        let entry_size = self.profile().size(SID_AND_ATTRIBUTES)?;
+        let mut sids = Vec::with_capacity(count as usize);
+        for index in 0..count {
+            let entry = index
+                .checked_mul(entry_size)
+                .and_then(|offset| entries.u64().checked_add(offset))
+                .map(Gva::new)
+                .ok_or(KdmpParserError::Overflow("sid and attributes"))?;
+            let sid = self.read_field(entry, SID_AND_ATTRIBUTES, "Sid")?;
+            sids.push(self.read_sid(sid.into())?);
+        }
+
+        // The integrity level is the last sub authority of the mandatory label SID
+        // (`S-1-16-X`).
+        let integrity_level = match self.read_field(token, TOKEN, "IntegrityLevelIndex")? {
+            index if index < count => sids[index as usize].1.last().copied().unwrap_or_default(),
+            _ => 0,
+        };
+
+        let mut sids = sids.into_iter().map(|(sid, _)| sid);
+        let user_sid = sids.next().unwrap_or_default();
+
+        Ok(TokenInfo {
+            user_sid,
+            groups: sids.collect(),
+            privileges_present: self.read_field(token, TOKEN, "Privileges.Present")?,
+            privileges_enabled: self.read_field(token, TOKEN, "Privileges.Enabled")?,
+            integrity_level,
+        })
+    }
+
+    /// Read a SID; it returns its text form as well as its sub authorities.
+    fn read_sid(&self, sid: Gva) -> Result<(String, Vec<u32>)> {
+        // ```text
+        // kd> dt nt!_SID
+        //    +0x000 Revision         : UChar
+        //    +0x001 SubAuthorityCount : UChar
+        //    +0x002 IdentifierAuthority : _SID_IDENTIFIER_AUTHORITY
+        //    +0x008 SubAuthority     : [1] Uint4B
+        // ```
+        let mut header = [0; 8];
+        self.virt_read_exact(sid, &mut header)?;
+        let [revision, count, authority @ ..] = header;
+        if count > SID_MAX_SUB_AUTHORITIES {
+            return Err(KdmpParserError::InvalidData("invalid sid"));
+        }
+
+        let mut sub_authorities = vec![0; usize::from(count) * 4];
+        self.virt_read_exact(
+            sid.u64()
+                .checked_add(header.len() as u64)
+                .map(Gva::new)
+                .ok_or(KdmpParserError::Overflow("sid"))?,
+            &mut sub_authorities,
+        )?;
+
+        let sub_authorities = sub_authorities
+            .chunks_exact(4)
+            .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .collect::<Vec<_>>();
+
+        Ok((
+            format_sid(revision, authority, &sub_authorities),
+            sub_authorities,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sid() {
+        assert_eq!(format_sid(1, [0, 0, 0, 0, 0, 5], &[18]), "S-1-5-18");
+        assert_eq!(
+            format_sid(1, [0, 0, 0, 0, 0, 5], &[21, 1004336348, 1177238915, 1001]),
+            "S-1-5-21-1004336348-1177238915-1001"
+        );
+        assert_eq!(
+            format_sid(1, [0, 0, 0, 0, 0, 16], &[0x3000]),
+            "S-1-16-12288"
+        );
+        assert_eq!(format_sid(1, [1, 2, 3, 4, 5, 6], &[]), "S-1-0x010203040506");
+    }
+
+    #[test]
+    fn privileges() {
+        assert!(privilege_names(0b11).is_empty());
+        assert_eq!(privilege_names(1 << 36), vec![
+            "SeDelegateSessionUserImpersonatePrivilege"
+        ]);
+    }
+}
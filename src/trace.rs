@@ -0,0 +1,62 @@
+//! This contains the macros used to instrument the parser with `tracing`. When
+//! the `tracing` feature is disabled the events are only type-checked and never
+//! evaluated, and the spans are zero-sized, so they don't cost anything.
+//!
+//! The events only take a format string and its arguments, no fields.
+
+/// Enter a debug span that is exited when the returned guard is dropped.
+#[cfg(feature = "tracing")]
+macro_rules! trace_span {
+    ($($arg:tt)*) => {
+        ::tracing::debug_span!($($arg)*).entered()
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_span {
+    ($($arg:tt)*) => {
+        $crate::trace::NoSpan
+    };
+}
+
+/// Emit a debug event.
+#[cfg(feature = "tracing")]
+macro_rules! trace_debug {
+    ($($arg:tt)*) => {
+        ::tracing::debug!($($arg)*)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_debug {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = ::std::format_args!($($arg)*);
+        }
+    };
+}
+
+/// Emit a warn event.
+#[cfg(feature = "tracing")]
+macro_rules! trace_warn {
+    ($($arg:tt)*) => {
+        ::tracing::warn!($($arg)*)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_warn {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = ::std::format_args!($($arg)*);
+        }
+    };
+}
+
+pub(crate) use trace_debug;
+pub(crate) use trace_span;
+pub(crate) use trace_warn;
+
+/// What [`trace_span`] evaluates to when the `tracing` feature is disabled.
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct NoSpan;
@@ -0,0 +1,512 @@
+//! This contains what is needed to unwind x64 stacks like the debugger does
+//! (see [`KernelDumpParser::unwind_stack`]): the `RUNTIME_FUNCTION`s of the
+//! exception directory (`.pdata`) of the modules say how to undo the prolog of
+//! every function, so the frames can be walked precisely instead of guessed.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::{Gva, KernelDumpParser, StackState};
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! let context = parser.context_record();
+//! let start = StackState {
+//!     rip: Gva::new(context.rip),
+//!     rsp: Gva::new(context.rsp),
+//!     rbp: Gva::new(context.rbp),
+//! };
+//!
+//! for frame in parser.unwind_stack(start, None) {
+//!     println!("{frame}");
+//! }
+//! ```
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::sync::Arc;
+
+use crate::error::Result;
+use crate::gxa::{Gpa, Gxa};
+use crate::pe::{u16_at, u32_at, IMAGE_DIRECTORY_ENTRY_EXCEPTION};
+use crate::trace::trace_debug;
+use crate::{Gva, KdmpParserError, KernelDumpParser};
+
+/// Number of frames [`KernelDumpParser::unwind_stack`] unwinds at most.
+const MAX_FRAMES: usize = 0x100;
+
+/// Number of `RUNTIME_FUNCTION`s a chain of unwind information is made of at
+/// most.
+const MAX_CHAIN: usize = 32;
+
+/// Size of a `RUNTIME_FUNCTION`.
+///
+/// ```text
+/// kd> dt nt!_IMAGE_RUNTIME_FUNCTION_ENTRY
+///    +0x000 BeginAddress     : Uint4B
+///    +0x004 EndAddress       : Uint4B
+///    +0x008 UnwindInfo       : Uint4B
+/// ```
+const RUNTIME_FUNCTION_SIZE: usize = 12;
+
+/// `UNWIND_INFO.Flags` of the unwind information that continues the one of
+/// another `RUNTIME_FUNCTION`.
+const UNW_FLAG_CHAININFO: u8 = 0x4;
+
+/// The unwind operations, see `UNWIND_CODE.UnwindOp`.
+const UWOP_PUSH_NONVOL: u8 = 0;
+const UWOP_ALLOC_LARGE: u8 = 1;
+const UWOP_ALLOC_SMALL: u8 = 2;
+const UWOP_SET_FPREG: u8 = 3;
+const UWOP_SAVE_NONVOL: u8 = 4;
+const UWOP_SAVE_NONVOL_FAR: u8 = 5;
+const UWOP_PUSH_MACHFRAME: u8 = 10;
+
+/// Number of `UNWIND_CODE` slots every unwind operation takes.
+const UWOP_SLOTS: [usize; 11] = [1, 2, 1, 1, 2, 3, 2, 3, 2, 3, 1];
+
+/// Index of `rsp` in the registers, in the order the unwind operations number
+/// them.
+const RSP: usize = 4;
+
+/// Where to start unwinding from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackState {
+    pub rip: Gva,
+    pub rsp: Gva,
+    pub rbp: Gva,
+}
+
+/// A frame unwound by [`KernelDumpParser::unwind_stack`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnwoundFrame {
+    /// Where the frame is executing.
+    pub rip: Gva,
+    /// The stack pointer of the frame.
+    pub rsp: Gva,
+    /// `rip` symbolized with [`KernelDumpParser::symbolize`], like
+    /// `nt!KeWaitForSingleObject+0x1a3`.
+    pub symbol: Option<String>,
+}
+
+/// Format [`UnwoundFrame`] like a line of `k`.
+impl Display for UnwoundFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#018x} {:#018x}", self.rsp.u64(), self.rip.u64())?;
+
+        match &self.symbol {
+            Some(symbol) => write!(f, " {symbol}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A `RUNTIME_FUNCTION`: the range of a function, relative to its module,
+/// and where its unwind information is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RuntimeFunction {
+    begin: u32,
+    end: u32,
+    unwind_info: u32,
+}
+
+impl RuntimeFunction {
+    fn parse(data: &[u8], offset: usize) -> Result<Self> {
+        Ok(Self {
+            begin: u32_at(data, offset)?,
+            end: u32_at(data, offset + 4)?,
+            unwind_info: u32_at(data, offset + 8)?,
+        })
+    }
+}
+
+/// The registers the unwind operations restore.
+#[derive(Debug, Clone, Copy)]
+struct Registers {
+    rip: u64,
+    gprs: [u64; 16],
+}
+
+impl KernelDumpParser {
+    /// Unwind the stack that `start` is the top of, in the address space whose
+    /// PML4 is at `dtb`, or the one of the dump header if it is `None`. The
+    /// first frame is `start` itself.
+    ///
+    /// The frames are unwound with the `RUNTIME_FUNCTION`s of the exception
+    /// directory of their module, whose unwind operations undo the part of
+    /// the prolog that ran; the functions that don't have one are leaf
+    /// functions, whose return address is on the top of the stack. The walk
+    /// stops at the first frame that isn't in a module, and at the first one
+    /// that can't be unwound, like when its stack isn't in the dump.
+    ///
+    /// The epilogs aren't recognized, so a frame interrupted in one is
+    /// unwound as if it was in the body of its function.
+    pub fn unwind_stack(&self, start: StackState, dtb: Option<Gpa>) -> Vec<UnwoundFrame> {
+        let dtb = dtb.unwrap_or_else(|| self.default_dtb());
+        let mut registers = Registers {
+            rip: start.rip.u64(),
+            gprs: [0; 16],
+        };
+
+        registers.gprs[RSP] = start.rsp.u64();
+        registers.gprs[5] = start.rbp.u64();
+
+        let mut functions = HashMap::<Gva, Arc<[RuntimeFunction]>>::new();
+        let mut frames = Vec::new();
+        while frames.len() < MAX_FRAMES && registers.rip != 0 {
+            let rip = Gva::new(registers.rip);
+            let rsp = registers.gprs[RSP];
+            frames.push(UnwoundFrame {
+                rip,
+                rsp: Gva::new(rsp),
+                symbol: self.symbolize(rip),
+            });
+
+            let Some(module) = self.find_module_entry(rip) else {
+                break;
+            };
+
+            let base = module.at.start;
+            let module_functions = match functions.get(&base) {
+                Some(module_functions) => module_functions.clone(),
+                None => {
+                    let module_functions = self
+                        .runtime_functions(base)
+                        .unwrap_or_else(|e| {
+                            trace_debug!("failed reading the runtime functions of {base}: {e}");
+
+                            Vec::new()
+                        })
+                        .into();
+                    functions.insert(base, Arc::clone(&module_functions));
+
+                    module_functions
+                }
+            };
+
+            if let Err(e) = self.unwind_frame(&mut registers, base, &module_functions, dtb) {
+                trace_debug!("failed unwinding the frame of {rip}: {e}");
+                break;
+            }
+
+            // The stack grows down, so a frame that doesn't move up is bogus.
+            if registers.gprs[RSP] <= rsp {
+                break;
+            }
+        }
+
+        frames
+    }
+
+    /// Read the `RUNTIME_FUNCTION`s of the exception directory of the module
+    /// at `base`; they are sorted by address.
+    fn runtime_functions(&self, base: Gva) -> Result<Vec<RuntimeFunction>> {
+        let headers = self.pe_headers(base)?;
+        let Some(directory) = headers.data_directory(IMAGE_DIRECTORY_ENTRY_EXCEPTION) else {
+            return Ok(Vec::new());
+        };
+
+        let pdata = self.pe_read(base, directory.rva, directory.size)?;
+
+        (0..pdata.len() / RUNTIME_FUNCTION_SIZE)
+            .map(|idx| RuntimeFunction::parse(&pdata, idx * RUNTIME_FUNCTION_SIZE))
+            .collect()
+    }
+
+    /// Unwind the frame `registers` describes, in the module at `base` whose
+    /// `RUNTIME_FUNCTION`s are `functions`.
+    fn unwind_frame(
+        &self,
+        registers: &mut Registers,
+        base: Gva,
+        functions: &[RuntimeFunction],
+        dtb: Gpa,
+    ) -> Result<()> {
+        let read_u64 = |gva: u64| self.virt_read_struct_with_dtb::<u64>(Gva::new(gva), dtb);
+        let rva = (registers.rip - base.u64()) as u32;
+        let idx = functions.partition_point(|function| function.end <= rva);
+        let function = functions
+            .get(idx)
+            .filter(|function| function.begin <= rva)
+            .copied();
+
+        // A leaf function doesn't touch the stack, so its return address is on
+        // the top of it.
+        let Some(mut function) = function else {
+            registers.rip = read_u64(registers.gprs[RSP])?;
+            registers.gprs[RSP] += 8;
+
+            return Ok(());
+        };
+
+        // Only the part of the prolog that ran is undone; the functions the
+        // unwind information is chained to have run their whole prolog.
+        let mut offset_in_function = rva - function.begin;
+        for _ in 0..MAX_CHAIN {
+            // ```text
+            // typedef struct _UNWIND_INFO {
+            //     UBYTE Version       : 3;
+            //     UBYTE Flags         : 5;
+            //     UBYTE SizeOfProlog;
+            //     UBYTE CountOfCodes;
+            //     UBYTE FrameRegister : 4;
+            //     UBYTE FrameOffset   : 4;
+            //     UNWIND_CODE UnwindCode[1];
+            // } UNWIND_INFO, *PUNWIND_INFO;
+            // ```
+            let header = self.pe_read(base, function.unwind_info, 4)?;
+            let flags = header[0] >> 3;
+            let count = usize::from(header[2]);
+            let frame_register = usize::from(header[3] & 0xf);
+            let frame_offset = u64::from(header[3] >> 4) * 16;
+            // The codes are followed by the `RUNTIME_FUNCTION` the information is
+            // chained to; they are padded to an even count.
+            let size = (count + (count & 1)) * 2 + RUNTIME_FUNCTION_SIZE;
+            let codes = self.pe_read(base, function.unwind_info + 4, size as u32)?;
+
+            let mut slot = 0;
+            while slot < count {
+                let code_offset = u32::from(codes[slot * 2]);
+                let op = codes[(slot * 2) + 1] & 0xf;
+                let info = codes[(slot * 2) + 1] >> 4;
+                let slots = match (op, info) {
+                    (UWOP_ALLOC_LARGE, 1..) => 3,
+                    _ => *UWOP_SLOTS
+                        .get(usize::from(op))
+                        .ok_or(KdmpParserError::InvalidData("invalid unwind operation"))?,
+                };
+
+                let operand = |idx: usize| u16_at(&codes, (slot + idx) * 2).map(u64::from);
+                if code_offset > offset_in_function {
+                    slot += slots;
+                    continue;
+                }
+
+                let rsp = registers.gprs[RSP];
+                match op {
+                    UWOP_PUSH_NONVOL => {
+                        registers.gprs[usize::from(info)] = read_u64(rsp)?;
+                        registers.gprs[RSP] += 8;
+                    }
+                    UWOP_ALLOC_LARGE if info == 0 => registers.gprs[RSP] += operand(1)? * 8,
+                    UWOP_ALLOC_LARGE => {
+                        registers.gprs[RSP] += operand(1)? | (operand(2)? << 16);
+                    }
+                    UWOP_ALLOC_SMALL => registers.gprs[RSP] += (u64::from(info) * 8) + 8,
+                    UWOP_SET_FPREG => {
+                        registers.gprs[RSP] =
+                            registers.gprs[frame_register].wrapping_sub(frame_offset);
+                    }
+                    UWOP_SAVE_NONVOL => {
+                        registers.gprs[usize::from(info)] = read_u64(rsp + (operand(1)? * 8))?;
+                    }
+                    UWOP_SAVE_NONVOL_FAR => {
+                        let offset = operand(1)? | (operand(2)? << 16);
+                        registers.gprs[usize::from(info)] = read_u64(rsp + offset)?;
+                    }
+                    // The processor pushed a machine frame, optionally preceded by
+                    // an error code; it has the `rip` and `rsp` that were
+                    // interrupted.
+                    //
+                    // ```text
+                    // kd> dt nt!_MACHINE_FRAME
+                    //    +0x000 Rip              : Uint8B
+                    //    +0x018 Rsp              : Uint8B
+                    // ```
+                    UWOP_PUSH_MACHFRAME => {
+                        let frame = rsp + (u64::from(info) * 8);
+                        registers.rip = read_u64(frame)?;
+                        registers.gprs[RSP] = read_u64(frame + 0x18)?;
+
+                        return Ok(());
+                    }
+                    // The XMM registers aren't tracked.
+                    _ => {}
+                }
+
+                slot += slots;
+            }
+
+            if flags & UNW_FLAG_CHAININFO == 0 {
+                registers.rip = read_u64(registers.gprs[RSP])?;
+                registers.gprs[RSP] += 8;
+
+                return Ok(());
+            }
+
+            function = RuntimeFunction::parse(&codes, size - RUNTIME_FUNCTION_SIZE)?;
+            offset_in_function = u32::MAX;
+        }
+
+        Err(KdmpParserError::InvalidData(
+            "unwind information chain is too long",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DumpBuilder;
+    use crate::PxeFlags;
+
+    const NT: u64 = 0xffff_f800_0000_0000;
+    const STACK: u64 = 0xffff_c000_0000_0000;
+    const USER: u64 = 0x7ff6_0000_1000;
+
+    /// The headers of an image whose exception directory is at 0x2000, with
+    /// `count` `RUNTIME_FUNCTION`s.
+    fn headers(count: u32) -> Vec<u8> {
+        let mut page = vec![0; 0x1_000];
+        page[0..2].copy_from_slice(b"MZ");
+        page[0x3c..0x40].copy_from_slice(&0x80u32.to_le_bytes());
+        page[0x80..0x84].copy_from_slice(b"PE\0\0");
+        page[0x84..0x86].copy_from_slice(&0x8664u16.to_le_bytes());
+        page[0x98..0x9a].copy_from_slice(&0x20bu16.to_le_bytes());
+        page[0xd0..0xd4].copy_from_slice(&0x3_000u32.to_le_bytes());
+        page[0xd4..0xd8].copy_from_slice(&0x400u32.to_le_bytes());
+        page[0x104..0x108].copy_from_slice(&16u32.to_le_bytes());
+        let exception = 0x108 + (IMAGE_DIRECTORY_ENTRY_EXCEPTION * 8);
+        page[exception..exception + 4].copy_from_slice(&0x2_000u32.to_le_bytes());
+        page[exception + 4..exception + 8].copy_from_slice(&(count * 12).to_le_bytes());
+
+        page
+    }
+
+    /// Encode an `UNWIND_INFO` with its `codes`, and the `RUNTIME_FUNCTION` it
+    /// is chained to.
+    fn unwind_info(prolog: u8, frame: u8, codes: &[u16], chained: Option<[u32; 3]>) -> Vec<u8> {
+        let flags = if chained.is_some() {
+            UNW_FLAG_CHAININFO
+        } else {
+            0
+        };
+
+        let mut info = vec![1 | (flags << 3), prolog, codes.len() as u8, frame];
+        for code in codes {
+            info.extend_from_slice(&code.to_le_bytes());
+        }
+
+        if codes.len() % 2 == 1 {
+            info.extend_from_slice(&[0, 0]);
+        }
+
+        for field in chained.into_iter().flatten() {
+            info.extend_from_slice(&field.to_le_bytes());
+        }
+
+        info
+    }
+
+    /// An `UNWIND_CODE`.
+    fn code(offset: u8, op: u8, info: u8) -> u16 {
+        u16::from(offset) | (u16::from(op | (info << 4)) << 8)
+    }
+
+    #[test]
+    fn unwind_stack() {
+        // `f1` pushes `rbp`, allocates 0x20 bytes and points `rbp` 0x10 bytes in
+        // them, `f2` pushes `rbx` and allocates 0x100 bytes, `f2b` is a part of
+        // `f2` and `trap` is an interrupt handler that received an error code.
+        let f1 = [0x1_000u32, 0x1_100, 0x2_100];
+        let f2 = [0x1_200, 0x1_300, 0x2_140];
+        let f2b = [0x1_300, 0x1_340, 0x2_180];
+        let trap = [0x1_400, 0x1_500, 0x2_1c0];
+        let mut pdata = Vec::new();
+        for field in [f1, f2, f2b, trap].into_iter().flatten() {
+            pdata.extend_from_slice(&field.to_le_bytes());
+        }
+
+        let unwind_infos = [
+            (
+                0x100,
+                unwind_info(
+                    9,
+                    5 | (1 << 4),
+                    &[
+                        code(9, UWOP_SET_FPREG, 0),
+                        code(5, UWOP_ALLOC_SMALL, 3),
+                        code(1, UWOP_PUSH_NONVOL, 5),
+                    ],
+                    None,
+                ),
+            ),
+            (
+                0x140,
+                unwind_info(
+                    8,
+                    0,
+                    &[
+                        code(8, UWOP_ALLOC_LARGE, 0),
+                        0x20,
+                        code(1, UWOP_PUSH_NONVOL, 3),
+                    ],
+                    None,
+                ),
+            ),
+            (0x180, unwind_info(0, 0, &[], Some(f2))),
+            (
+                0x1c0,
+                unwind_info(0, 0, &[code(0, UWOP_PUSH_MACHFRAME, 1)], None),
+            ),
+        ];
+
+        let mut data = vec![0; 0x1_000];
+        data[..pdata.len()].copy_from_slice(&pdata);
+        for (offset, info) in unwind_infos {
+            data[offset..offset + info.len()].copy_from_slice(&info);
+        }
+
+        // `f1` has been called by `f2b`, that has been called by `trap`, that
+        // interrupted the leaf function at 0x1600 which has been called by user
+        // code.
+        let sp = STACK + 0x100;
+        let dump = DumpBuilder::new()
+            .map_virt(NT, 0x10_000, PxeFlags::Present)
+            .map_virt(NT + 0x2_000, 0x12_000, PxeFlags::Present)
+            .write_phys(0x10_000, &headers(4))
+            .write_phys(0x12_000, &data)
+            .module(NT..NT + 0x3_000, "ntoskrnl.exe")
+            .map_virt(STACK, 0x13_000, PxeFlags::Present)
+            .write_virt(sp + 0x20, &0x1337u64.to_le_bytes())
+            .write_virt(sp + 0x28, &(NT + 0x1_310).to_le_bytes())
+            .write_virt(sp + 0x130, &0xb0bu64.to_le_bytes())
+            .write_virt(sp + 0x138, &(NT + 0x1_410).to_le_bytes())
+            .write_virt(sp + 0x148, &(NT + 0x1_600).to_le_bytes())
+            .write_virt(sp + 0x160, &(sp + 0x200).to_le_bytes())
+            .write_virt(sp + 0x200, &USER.to_le_bytes())
+            .build();
+
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+        let start = StackState {
+            rip: Gva::new(NT + 0x1_050),
+            rsp: Gva::new(sp),
+            rbp: Gva::new(sp + 0x10),
+        };
+
+        let frames = parser.unwind_stack(start, None);
+        assert_eq!(
+            frames
+                .iter()
+                .map(|frame| (frame.rip.u64(), frame.rsp.u64()))
+                .collect::<Vec<_>>(),
+            [
+                (NT + 0x1_050, sp),
+                (NT + 0x1_310, sp + 0x30),
+                (NT + 0x1_410, sp + 0x140),
+                (NT + 0x1_600, sp + 0x200),
+                (USER, sp + 0x208),
+            ]
+        );
+        assert_eq!(frames[0].symbol.as_deref(), Some("nt+0x1050"));
+        assert_eq!(frames[4].symbol, None);
+
+        // Only `push rbp` ran in the prolog of `f1`.
+        let start = StackState {
+            rip: Gva::new(NT + 0x1_001),
+            rsp: Gva::new(sp + 0x20),
+            rbp: Gva::new(0),
+        };
+
+        let frames = parser.unwind_stack(start, None);
+        assert_eq!(frames[1].rip, Gva::new(NT + 0x1_310));
+        assert_eq!(frames[1].rsp, Gva::new(sp + 0x30));
+    }
+}
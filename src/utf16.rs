@@ -0,0 +1,110 @@
+//! This contains what is needed to decode the UTF-16 strings read out of a
+//! dump according to a [`StringPolicy`].
+use crate::error::Result;
+
+/// What to do with the UTF-16 strings read out of a dump that aren't valid,
+/// like a name whose buffer is partially paged out.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StringPolicy {
+    /// The invalid parts are replaced with `U+FFFD`; a truncated string ends
+    /// with one.
+    #[default]
+    Lossy,
+    /// Decoding fails.
+    Strict,
+}
+
+/// Turn UTF-16LE bytes into code units; a trailing odd byte is dropped.
+pub(crate) fn units(data: &[u8]) -> Vec<u16> {
+    data.chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect()
+}
+
+/// Decode `units` according to `policy`; it returns the string and whether it
+/// had to be mangled.
+pub(crate) fn decode(units: &[u16], policy: StringPolicy) -> Result<(String, bool)> {
+    match (String::from_utf16(units), policy) {
+        (Ok(s), _) => Ok((s, false)),
+        (Err(_), StringPolicy::Lossy) => Ok((String::from_utf16_lossy(units), true)),
+        (Err(e), StringPolicy::Strict) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KdmpParserError;
+
+    #[test]
+    fn decode_policies() {
+        let valid = units(b"a\0b\0c");
+        assert_eq!(valid, [u16::from(b'a'), u16::from(b'b')]);
+        assert_eq!(
+            decode(&valid, StringPolicy::Strict).unwrap(),
+            ("ab".into(), false)
+        );
+
+        // A lone surrogate.
+        let invalid = [u16::from(b'a'), 0xd800, u16::from(b'b')];
+        assert_eq!(
+            decode(&invalid, StringPolicy::Lossy).unwrap(),
+            ("a\u{fffd}b".into(), true)
+        );
+        assert!(matches!(
+            decode(&invalid, StringPolicy::Strict),
+            Err(KdmpParserError::Utf16(_))
+        ));
+    }
+
+    #[test]
+    fn truncated_module_name() {
+        use crate::testing::{DumpBuilder, MODULE_LIST_BASE};
+        use crate::{Gva, KernelDumpParser, ParserOptions, PxeFlags, Warning};
+
+        // Point the names of the module to a buffer whose second half isn't mapped.
+        let nt = 0xffff_f800_0000_0000;
+        let name: u64 = 0xffff_f800_0010_0000;
+        let mut unicode_string = vec![];
+        unicode_string.extend_from_slice(&8u16.to_le_bytes());
+        unicode_string.extend_from_slice(&8u16.to_le_bytes());
+        unicode_string.extend_from_slice(&[0; 4]);
+        unicode_string.extend_from_slice(&(name - 4).to_le_bytes());
+        let entry = MODULE_LIST_BASE + 0x10;
+        let dump = DumpBuilder::new()
+            .module(nt..nt + 0x1_000, "ntoskrnl.exe")
+            .map_virt(name - 0x1_000, 0x1_000, PxeFlags::Present)
+            .write_virt(name - 4, &[b'n', 0, b't', 0])
+            .build();
+
+        // The module list is mapped by the builder; patch its entry.
+        let parser = KernelDumpParser::from_bytes(dump.clone()).unwrap();
+        let offset = parser
+            .phys_translate(parser.virt_translate(Gva::new(entry + 0x48)).unwrap())
+            .unwrap() as usize;
+        let mut dump = dump;
+        dump[offset..offset + 0x10].copy_from_slice(&unicode_string);
+        dump[offset + 0x10..offset + 0x20].copy_from_slice(&unicode_string);
+
+        let parser = KernelDumpParser::from_bytes(dump.clone()).unwrap();
+        assert_eq!(
+            parser
+                .kernel_modules()
+                .map(|(_, name)| name)
+                .collect::<Vec<_>>(),
+            ["nt\u{fffd}"]
+        );
+        assert_eq!(parser.warnings(), [Warning::InvalidModuleName {
+            entry: Gva::new(entry),
+            name: "nt\u{fffd}".into()
+        }]);
+
+        // With the strict policy, the name can't be read so neither can the list.
+        let options = ParserOptions {
+            string_policy: StringPolicy::Strict,
+            ..Default::default()
+        };
+        let parser = KernelDumpParser::with_options(std::io::Cursor::new(dump), options).unwrap();
+        assert_eq!(parser.kernel_modules().len(), 0);
+    }
+}
@@ -0,0 +1,379 @@
+//! This contains what is needed to check the internal consistency of a dump
+//! and, with the `sha2` feature, to hash the physical memory it holds.
+use std::fmt::{self, Display};
+
+use crate::error::{Result, Warning};
+use crate::gxa::{Gpa, Gva, Gxa, GxaRange};
+use crate::structs::{BmpHeader64, DumpType, FromLeBytes, Header64, Page, PhysmemDesc};
+use crate::supplement::is_supplemental;
+use crate::{KdmpParserError, KernelDumpParser};
+
+/// The outcome of a check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckOutcome {
+    /// The dump is consistent.
+    Passed,
+    /// The dump is inconsistent; this describes how.
+    Failed(String),
+    /// The check doesn't apply to this dump; this describes why.
+    Skipped(&'static str),
+}
+
+/// A consistency check done by [`KernelDumpParser::verify`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    /// Name of the check.
+    pub name: &'static str,
+    /// How it went.
+    pub outcome: CheckOutcome,
+}
+
+/// The outcome of every check done by [`KernelDumpParser::verify`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// The checks, in the order they were done.
+    pub checks: Vec<Check>,
+}
+
+impl VerifyReport {
+    /// Did every check pass (or was skipped)?
+    pub fn is_ok(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    /// The checks that failed.
+    pub fn failures(&self) -> impl Iterator<Item = &Check> + '_ {
+        self.checks
+            .iter()
+            .filter(|check| matches!(check.outcome, CheckOutcome::Failed(_)))
+    }
+
+    fn push(&mut self, name: &'static str, outcome: CheckOutcome) {
+        self.checks.push(Check { name, outcome });
+    }
+}
+
+impl Display for VerifyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, check) in self.checks.iter().enumerate() {
+            if idx != 0 {
+                writeln!(f)?;
+            }
+
+            match &check.outcome {
+                CheckOutcome::Passed => write!(f, "{:<20}: ok", check.name)?,
+                CheckOutcome::Failed(why) => write!(f, "{:<20}: failed ({why})", check.name)?,
+                CheckOutcome::Skipped(why) => write!(f, "{:<20}: skipped ({why})", check.name)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A page whose virtual and physical views disagree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoherenceMismatch {
+    /// The page in virtual memory.
+    pub gva: Gva,
+    /// What the page tables translate it to.
+    pub gpa: Gpa,
+    /// How the views disagree.
+    pub what: String,
+}
+
+/// The outcome of [`KernelDumpParser::coherence_check`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CoherenceReport {
+    /// How many pages were sampled.
+    pub sampled: usize,
+    /// The sampled pages that aren't mapped.
+    pub unmapped: Vec<Gva>,
+    /// The sampled pages whose views disagree.
+    pub mismatches: Vec<CoherenceMismatch>,
+}
+
+impl CoherenceReport {
+    /// Did every mapped page read the same through both views?
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// Turn a comparison into an outcome.
+fn expect_eq(what: &str, expected: u64, found: u64) -> CheckOutcome {
+    if expected == found {
+        CheckOutcome::Passed
+    } else {
+        CheckOutcome::Failed(format!("{what}: expected {expected:#x}, found {found:#x}"))
+    }
+}
+
+impl KernelDumpParser {
+    /// Check the internal consistency of the dump: the sizes and counts
+    /// recorded in its headers against what the dump actually holds, and that
+    /// its module list can be read. Every check is reported, whether it
+    /// passes or not.
+    pub fn verify(&self) -> Result<VerifyReport> {
+        let mut report = VerifyReport::default();
+        let headers = self.headers();
+        let file_size = self.file_size();
+
+        // The header records how large the dump file is.
+        let outcome = match u64::try_from(headers.required_dump_space) {
+            Ok(size) if size != 0 => expect_eq("file size", size, file_size),
+            _ => CheckOutcome::Skipped("the header doesn't record the dump size"),
+        };
+        report.push("required dump space", outcome);
+
+        // The bitmap header records how many pages are present, and the physical
+        // memory descriptor how many pages the runs describe; both count pages of
+        // the size the dump is written with.
+        let pages = self
+            .physmem()
+            .filter(|&(_, offset)| !is_supplemental(offset))
+            .count() as u64
+            / (self.options.page_size / Page::size());
+        let outcome = match self.dump_type() {
+            DumpType::Bmp => {
+                let mut buffer = [0; BmpHeader64::SIZE];
+                if self.read_at(Header64::SIZE as u64, &mut buffer)? != buffer.len() {
+                    return Err(KdmpParserError::PartialPhysRead);
+                }
+
+                let bmp_header = BmpHeader64::from_le_bytes(&buffer);
+                expect_eq("present pages", bmp_header.total_present_pages, pages)
+            }
+            DumpType::Full => {
+                let desc = PhysmemDesc::from_le_bytes(&headers.physical_memory_block_buffer);
+                expect_eq("pages", desc.number_of_pages, pages)
+            }
+            _ => CheckOutcome::Skipped("the dump doesn't record its page count"),
+        };
+        report.push("page count", outcome);
+
+        // Every page has to be in the file.
+        let outcome = match self.physmem().find(|&(_, offset)| {
+            !is_supplemental(offset) && offset.saturating_add(Page::size()) > file_size
+        }) {
+            None => CheckOutcome::Passed,
+            Some((gpa, offset)) => CheckOutcome::Failed(format!(
+                "page {:#x} is at {offset:#x}, past the end of the file",
+                gpa.u64()
+            )),
+        };
+        report.push("page offsets", outcome);
+
+        // The module list has to be readable, and the modules' headers too.
+        let recovered = self
+            .warnings()
+            .iter()
+            .any(|warning| matches!(warning, Warning::ModuleListRecovered { .. }));
+        let unreadable = self.kernel_module_entries().find(|module| {
+            let mut magic = [0; 2];
+            !matches!(
+                self.try_virt_read_exact(module.at.start, &mut magic),
+                Ok(Some(()))
+            ) || &magic != b"MZ"
+        });
+        let outcome = match (self.kernel_modules().len(), recovered, unreadable) {
+            (0, _, _) => CheckOutcome::Failed("no kernel module could be read".into()),
+            (_, true, _) => CheckOutcome::Failed("the module list had to be recovered".into()),
+            (_, _, Some(module)) => CheckOutcome::Failed(format!(
+                "the headers of {} at {:#x} can't be read",
+                module.name,
+                module.at.start.u64()
+            )),
+            _ => CheckOutcome::Passed,
+        };
+        report.push("kernel module list", outcome);
+
+        Ok(report)
+    }
+
+    /// Check that reading virtual memory agrees with walking the page tables
+    /// and reading physical memory. Up to `sample_pages` pages are sampled,
+    /// evenly spread over the kernel modules; each is read with
+    /// [`KernelDumpParser::virt_read`], and again by translating it without
+    /// the translation cache and reading the result with
+    /// [`KernelDumpParser::phys_read`].
+    pub fn coherence_check(&self, sample_pages: usize) -> CoherenceReport {
+        let mut report = CoherenceReport::default();
+        let pages = self
+            .kernel_modules()
+            .map(|(at, _)| at.page_count())
+            .sum::<u64>();
+        let samples = pages.min(sample_pages as u64);
+        if samples == 0 {
+            return report;
+        }
+
+        let dtb = self.default_dtb();
+        let mut virt_page = vec![0; Page::size() as usize];
+        let mut phys_page = vec![0; Page::size() as usize];
+        for sample in 0..samples {
+            // Spread the samples evenly over the pages of the modules.
+            let Some(gva) = self.nth_module_page(sample * pages / samples) else {
+                break;
+            };
+
+            report.sampled += 1;
+            let Ok(gpa) = self.walk_page_tables(gva, dtb) else {
+                report.unmapped.push(gva);
+                continue;
+            };
+
+            let virt = self.virt_read(gva, &mut virt_page);
+            let phys = self.phys_read(gpa, &mut phys_page);
+            let what = match (virt, phys) {
+                (Ok(virt), Ok(phys)) if virt != phys => {
+                    format!("read {virt:#x} bytes virtually but {phys:#x} physically")
+                }
+                (Ok(len), Ok(_)) => match virt_page[..len]
+                    .iter()
+                    .zip(&phys_page[..len])
+                    .position(|(virt, phys)| virt != phys)
+                {
+                    Some(offset) => format!("the views differ at offset {offset:#x}"),
+                    None => continue,
+                },
+                // Neither view can read the page, so they agree.
+                (Err(_), Err(_)) => continue,
+                (virt, phys) => format!(
+                    "the virtual read returned {:?} but the physical one {:?}",
+                    virt.map_err(|e| e.to_string()),
+                    phys.map_err(|e| e.to_string())
+                ),
+            };
+
+            report.mismatches.push(CoherenceMismatch { gva, gpa, what });
+        }
+
+        report
+    }
+
+    /// Get the `nth` page spanned by the kernel modules.
+    fn nth_module_page(&self, mut nth: u64) -> Option<Gva> {
+        for (at, _) in self.kernel_modules() {
+            let pages = at.page_count();
+            if nth < pages {
+                return at.pages().nth(usize::try_from(nth).ok()?);
+            }
+
+            nth -= pages;
+        }
+
+        None
+    }
+}
+
+#[cfg(feature = "sha2")]
+mod hash {
+    use sha2::{Digest, Sha256};
+
+    use crate::cancel::CancellationToken;
+    use crate::error::Result;
+    use crate::gxa::Gxa;
+    use crate::structs::Page;
+    use crate::{KdmpParserError, KernelDumpParser};
+
+    /// The algorithms [`KernelDumpParser::content_hash`] can use.
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub enum HashAlgorithm {
+        /// SHA-256.
+        #[default]
+        Sha256,
+    }
+
+    impl KernelDumpParser {
+        /// Hash the physical memory held by the dump. Every page is hashed
+        /// along with its physical address, in ascending order, so that two
+        /// dumps of the same memory hash the same regardless of how their
+        /// files are laid out; the holes of the physical address space don't
+        /// count. This is why the pages aren't read in the order of the file
+        /// (see [`KernelDumpParser::physmem_file_order`]), which is the same
+        /// for the dumps written by Windows anyway.
+        pub fn content_hash(&self, algo: HashAlgorithm) -> Result<[u8; 32]> {
+            self.content_hash_with_cancellation(algo, &CancellationToken::new())
+        }
+
+        /// Hash the physical memory held by the dump, like
+        /// [`KernelDumpParser::content_hash`], until `cancel` is cancelled.
+        pub fn content_hash_with_cancellation(
+            &self,
+            algo: HashAlgorithm,
+            cancel: &CancellationToken,
+        ) -> Result<[u8; 32]> {
+            let HashAlgorithm::Sha256 = algo;
+            let mut hasher = Sha256::new();
+            let mut page = vec![0; Page::size() as usize];
+            for (gpa, offset) in self.physmem() {
+                cancel.check()?;
+                if self.read_at(offset, &mut page)? != page.len() {
+                    return Err(KdmpParserError::PartialPhysRead);
+                }
+
+                hasher.update(gpa.u64().to_le_bytes());
+                hasher.update(&page);
+            }
+
+            Ok(hasher.finalize().into())
+        }
+    }
+}
+
+#[cfg(feature = "sha2")]
+pub use hash::HashAlgorithm;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report() {
+        let mut report = VerifyReport::default();
+        report.push("a", CheckOutcome::Passed);
+        report.push("b", CheckOutcome::Skipped("nope"));
+        assert!(report.is_ok());
+
+        report.push("c", expect_eq("pages", 1, 2));
+        assert!(!report.is_ok());
+        assert_eq!(report.failures().count(), 1);
+        assert_eq!(
+            report.to_string(),
+            "a                   : ok\nb                   : skipped (nope)\nc                   : failed (pages: expected 0x1, found 0x2)"
+        );
+    }
+
+    #[test]
+    fn coherence() {
+        use crate::testing::DumpBuilder;
+        use crate::PxeFlags;
+
+        let nt = 0xffff_f800_0000_0000;
+        let mut builder = DumpBuilder::new().module(nt..nt + 0x4_800, "ntoskrnl.exe");
+        // Leave the third page of the module unmapped.
+        for page in [0, 1, 3, 4] {
+            builder = builder
+                .map_virt(
+                    nt + page * 0x1_000,
+                    0x10_000 + page * 0x1_000,
+                    PxeFlags::Present,
+                )
+                .write_phys(0x10_000 + page * 0x1_000, &[page as u8 + 1; 0x1_000]);
+        }
+
+        let parser = KernelDumpParser::from_bytes(builder.build()).unwrap();
+        let report = parser.coherence_check(0x100);
+        assert!(report.is_ok());
+        assert_eq!(report.sampled, 5);
+        assert_eq!(report.unmapped, [Gva::new(nt + 0x2_000)]);
+
+        // The samples are the first, second and fourth pages.
+        let report = parser.coherence_check(3);
+        assert_eq!(report.sampled, 3);
+        assert!(report.unmapped.is_empty());
+        assert!(parser.coherence_check(0).mismatches.is_empty());
+    }
+}
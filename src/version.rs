@@ -0,0 +1,344 @@
+//! This contains what is needed to extract the version resource
+//! (`VS_VERSION_INFO`) of a module mapped in memory, which identifies exactly
+//! which build of a module was loaded.
+use crate::error::Result;
+use crate::module::ModuleEntry;
+use crate::pe::{u16_at, u32_at, IMAGE_DIRECTORY_ENTRY_RESOURCE};
+use crate::utf16::{self, StringPolicy};
+use crate::{KdmpParserError, KernelDumpParser};
+
+/// The type id of the version resources (`RT_VERSION`).
+const RT_VERSION: u32 = 16;
+
+/// ```text
+/// kd> dt nt!_IMAGE_RESOURCE_DIRECTORY NumberOfNamedEntries NumberOfIdEntries
+///    +0x00c NumberOfNamedEntries : Uint2B
+///    +0x00e NumberOfIdEntries : Uint2B
+/// kd> dt nt!_IMAGE_RESOURCE_DIRECTORY_ENTRY
+///    +0x000 Name             : Uint4B
+///    +0x004 OffsetToData     : Uint4B
+/// kd> dt nt!_IMAGE_RESOURCE_DATA_ENTRY
+///    +0x000 OffsetToData     : Uint4B
+///    +0x004 Size             : Uint4B
+/// ```
+const RESOURCE_DIRECTORY_NAMED_ENTRIES: usize = 0xc;
+const RESOURCE_DIRECTORY_ID_ENTRIES: usize = 0xe;
+const RESOURCE_DIRECTORY_SIZE: usize = 0x10;
+const RESOURCE_DIRECTORY_ENTRY_SIZE: usize = 0x8;
+
+/// The high bit of `OffsetToData` is set when it points to a sub directory.
+const RESOURCE_DATA_IS_DIRECTORY: u32 = 1 << 31;
+
+/// The resource tree has three levels: type, name and language.
+const RESOURCE_LEVELS: usize = 3;
+
+/// `VS_FIXEDFILEINFO.dwSignature`.
+const VS_FFI_SIGNATURE: u32 = 0xfe_ef_04_bd;
+
+/// Maximum size of a version resource we'll read.
+const MAX_VERSION_RESOURCE_SIZE: u32 = 0x1_0000;
+
+/// The version information of a module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionInfo {
+    /// Version of the file, like `10.0.19041.1`.
+    pub file_version: String,
+    /// Version of the product the file ships with.
+    pub product_version: String,
+    /// The `CompanyName` string.
+    pub company: Option<String>,
+    /// The `OriginalFilename` string.
+    pub original_filename: Option<String>,
+}
+
+/// A block of a version resource: `VS_VERSIONINFO`, `StringFileInfo`,
+/// `StringTable`, `String`, etc. all share the same layout.
+///
+/// ```text
+/// WORD  wLength;
+/// WORD  wValueLength;
+/// WORD  wType;
+/// WCHAR szKey[];
+/// WORD  Padding[];
+/// ...   Value;
+/// WORD  Padding[];
+/// ...   Children[];
+/// ```
+#[derive(Debug)]
+struct Block<'data> {
+    key: String,
+    value: &'data [u8],
+    children: &'data [u8],
+}
+
+/// Round up `offset` to the next 32-bit boundary.
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+/// Decode a NULL terminated UTF-16 string; it returns the string and how many
+/// bytes it took, including its terminator.
+fn utf16_cstr(data: &[u8], policy: StringPolicy) -> Result<(String, usize)> {
+    let units = utf16::units(data);
+    let units = &units[..units
+        .iter()
+        .position(|&unit| unit == 0)
+        .unwrap_or(units.len())];
+    let len = (units.len() + 1) * 2;
+
+    Ok((utf16::decode(units, policy)?.0, len.min(data.len())))
+}
+
+/// Parse the sibling blocks in `data`.
+fn blocks(mut data: &[u8], policy: StringPolicy) -> Result<Vec<Block<'_>>> {
+    let mut blocks = Vec::new();
+    while data.len() >= 6 {
+        let len = usize::from(u16_at(data, 0)?);
+        let value_len = usize::from(u16_at(data, 2)?);
+        let text = u16_at(data, 4)? == 1;
+        // Every block is at least as large as its header, which guarantees that we
+        // make progress.
+        if len < 6 || len > data.len() {
+            return Err(KdmpParserError::InvalidData("invalid version block length"));
+        }
+
+        let block = &data[..len];
+        let (key, key_len) = utf16_cstr(&block[6..], policy)?;
+        let value_start = align4(6 + key_len).min(len);
+        // The length of text values is in characters.
+        let value_len = if text { value_len * 2 } else { value_len };
+        let value_end = value_start
+            .checked_add(value_len)
+            .filter(|&end| end <= len)
+            .ok_or(KdmpParserError::InvalidData("invalid version value length"))?;
+
+        blocks.push(Block {
+            key,
+            value: &block[value_start..value_end],
+            children: &block[align4(value_end).min(len)..],
+        });
+
+        data = &data[align4(len).min(data.len())..];
+    }
+
+    Ok(blocks)
+}
+
+/// Format a version stored as two `DWORD`s, like `10.0.19041.1`.
+fn format_version(ms: u32, ls: u32) -> String {
+    format!("{}.{}.{}.{}", ms >> 16, ms & 0xffff, ls >> 16, ls & 0xffff)
+}
+
+/// Parse a `VS_VERSIONINFO` resource.
+fn parse_version_info(data: &[u8], policy: StringPolicy) -> Result<VersionInfo> {
+    let root = blocks(data, policy)?
+        .into_iter()
+        .find(|block| block.key == "VS_VERSION_INFO")
+        .ok_or(KdmpParserError::InvalidData("no VS_VERSION_INFO block"))?;
+
+    // ```text
+    // kd> dt VS_FIXEDFILEINFO
+    //    +0x000 dwSignature        : Uint4B
+    //    +0x008 dwFileVersionMS    : Uint4B
+    //    +0x00c dwFileVersionLS    : Uint4B
+    //    +0x010 dwProductVersionMS : Uint4B
+    //    +0x014 dwProductVersionLS : Uint4B
+    // ```
+    let fixed = root.value;
+    if u32_at(fixed, 0)? != VS_FFI_SIGNATURE {
+        return Err(KdmpParserError::InvalidData("invalid VS_FIXEDFILEINFO"));
+    }
+
+    let mut info = VersionInfo {
+        file_version: format_version(u32_at(fixed, 0x8)?, u32_at(fixed, 0xc)?),
+        product_version: format_version(u32_at(fixed, 0x10)?, u32_at(fixed, 0x14)?),
+        company: None,
+        original_filename: None,
+    };
+
+    // The strings are in the first `StringTable` of the `StringFileInfo` block.
+    let Some(string_file_info) = blocks(root.children, policy)?
+        .into_iter()
+        .find(|block| block.key == "StringFileInfo")
+    else {
+        return Ok(info);
+    };
+
+    let Some(string_table) = blocks(string_file_info.children, policy)?
+        .into_iter()
+        .next()
+    else {
+        return Ok(info);
+    };
+
+    for string in blocks(string_table.children, policy)? {
+        let value = Some(utf16_cstr(string.value, policy)?.0);
+        match string.key.as_str() {
+            "CompanyName" => info.company = value,
+            "OriginalFilename" => info.original_filename = value,
+            _ => {}
+        }
+    }
+
+    Ok(info)
+}
+
+/// Walk down a resource tree following `ids` (one per level; `None` takes the
+/// first entry), and return the `OffsetToData` of the data entry. `rsrc` is
+/// the content of the resource section.
+fn find_resource(rsrc: &[u8], ids: [Option<u32>; RESOURCE_LEVELS]) -> Result<usize> {
+    let mut directory = 0;
+    for id in ids {
+        let named = usize::from(u16_at(rsrc, directory + RESOURCE_DIRECTORY_NAMED_ENTRIES)?);
+        let count = named + usize::from(u16_at(rsrc, directory + RESOURCE_DIRECTORY_ID_ENTRIES)?);
+        let mut found = None;
+        for idx in 0..count {
+            let entry = directory + RESOURCE_DIRECTORY_SIZE + (idx * RESOURCE_DIRECTORY_ENTRY_SIZE);
+            let name = u32_at(rsrc, entry)?;
+            if id.map_or(true, |id| id == name) {
+                found = Some(u32_at(rsrc, entry + 4)?);
+                break;
+            }
+        }
+
+        let offset = found.ok_or(KdmpParserError::NotFound("the version resource"))?;
+
+        // We know how deep the tree is, so the walk always terminates even if
+        // entries point back to their parents.
+        directory = (offset & !RESOURCE_DATA_IS_DIRECTORY) as usize;
+        if directory >= rsrc.len() {
+            return Err(KdmpParserError::InvalidData("invalid resource offset"));
+        }
+    }
+
+    Ok(directory)
+}
+
+impl KernelDumpParser {
+    /// Extract the version information out of the version resource of a
+    /// module.
+    ///
+    /// If the part of the image that is needed isn't in the dump (because it
+    /// was paged out, or because the dump doesn't include it), this returns
+    /// [`KdmpParserError::Unavailable`].
+    pub fn module_version_info(&self, module: &ModuleEntry) -> Result<VersionInfo> {
+        match self.try_module_version_info(module) {
+            Err(
+                KdmpParserError::AddrTranslation(..)
+                | KdmpParserError::PartialVirtRead
+                | KdmpParserError::PartialPhysRead,
+            ) => Err(KdmpParserError::Unavailable("the version resource")),
+            res => res,
+        }
+    }
+
+    fn try_module_version_info(&self, module: &ModuleEntry) -> Result<VersionInfo> {
+        let base = module.at.start;
+        let headers = self.pe_headers(base)?;
+        let directory = headers
+            .data_directory(IMAGE_DIRECTORY_ENTRY_RESOURCE)
+            .ok_or(KdmpParserError::NotFound("the resource directory"))?;
+
+        if directory.rva.saturating_add(directory.size) > headers.size_of_image {
+            return Err(KdmpParserError::InvalidData("invalid resource directory"));
+        }
+
+        let rsrc = self.pe_read(base, directory.rva, directory.size)?;
+        let data_entry = find_resource(&rsrc, [Some(RT_VERSION), None, None])?;
+        let rva = u32_at(&rsrc, data_entry)?;
+        let size = u32_at(&rsrc, data_entry + 4)?;
+        if size > MAX_VERSION_RESOURCE_SIZE || rva.saturating_add(size) > headers.size_of_image {
+            return Err(KdmpParserError::InvalidData("invalid version resource"));
+        }
+
+        parse_version_info(&self.pe_read(base, rva, size)?, self.options.string_policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serialize a version block.
+    fn block(key: &str, text: bool, value: &[u8], children: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = vec![0; 6];
+        for unit in key.encode_utf16().chain([0]) {
+            data.extend_from_slice(&unit.to_le_bytes());
+        }
+
+        data.resize(align4(data.len()), 0);
+        data.extend_from_slice(value);
+        for child in children {
+            data.resize(align4(data.len()), 0);
+            data.extend_from_slice(child);
+        }
+
+        let value_len = if text { value.len() / 2 } else { value.len() };
+        let len = data.len() as u16;
+        data[0..2].copy_from_slice(&len.to_le_bytes());
+        data[2..4].copy_from_slice(&(value_len as u16).to_le_bytes());
+        data[4..6].copy_from_slice(&u16::from(text).to_le_bytes());
+
+        data
+    }
+
+    fn string(key: &str, value: &str) -> Vec<u8> {
+        let value = value
+            .encode_utf16()
+            .chain([0])
+            .flat_map(u16::to_le_bytes)
+            .collect::<Vec<_>>();
+
+        block(key, true, &value, &[])
+    }
+
+    #[test]
+    fn version_info() {
+        let mut fixed = vec![0; 0x34];
+        fixed[0..4].copy_from_slice(&VS_FFI_SIGNATURE.to_le_bytes());
+        fixed[0x8..0xc].copy_from_slice(&0x000a_0000u32.to_le_bytes());
+        fixed[0xc..0x10].copy_from_slice(&0x4a61_0001u32.to_le_bytes());
+        fixed[0x10..0x14].copy_from_slice(&0x000a_0000u32.to_le_bytes());
+        fixed[0x14..0x18].copy_from_slice(&0x4a61_0000u32.to_le_bytes());
+
+        let table = block("040904b0", false, &[], &[
+            string("CompanyName", "Microsoft Corporation"),
+            string("OriginalFilename", "ntoskrnl.exe.mui"),
+        ]);
+        let strings = block("StringFileInfo", false, &[], &[table]);
+        let data = block("VS_VERSION_INFO", false, &fixed, &[strings]);
+
+        assert_eq!(
+            parse_version_info(&data, StringPolicy::Lossy).unwrap(),
+            VersionInfo {
+                file_version: "10.0.19041.1".into(),
+                product_version: "10.0.19041.0".into(),
+                company: Some("Microsoft Corporation".into()),
+                original_filename: Some("ntoskrnl.exe.mui".into()),
+            }
+        );
+
+        // Truncated / corrupted blocks are errors.
+        assert!(parse_version_info(&data[..data.len() / 2], StringPolicy::Lossy).is_err());
+        let mut corrupted = data.clone();
+        corrupted[0..2].copy_from_slice(&2u16.to_le_bytes());
+        assert!(parse_version_info(&corrupted, StringPolicy::Lossy).is_err());
+    }
+
+    #[test]
+    fn resource_tree() {
+        // A type directory with an `RT_VERSION` entry that points back to itself.
+        let mut rsrc = vec![0; 0x20];
+        rsrc[0xe..0x10].copy_from_slice(&1u16.to_le_bytes());
+        rsrc[0x10..0x14].copy_from_slice(&RT_VERSION.to_le_bytes());
+        rsrc[0x14..0x18].copy_from_slice(&RESOURCE_DATA_IS_DIRECTORY.to_le_bytes());
+        assert_eq!(
+            find_resource(&rsrc, [Some(RT_VERSION), None, None]).unwrap(),
+            0
+        );
+        assert!(find_resource(&rsrc, [Some(RT_VERSION + 1), None, None]).is_err());
+
+        rsrc[0x14..0x18].copy_from_slice(&0x1_000u32.to_le_bytes());
+        assert!(find_resource(&rsrc, [None, None, None]).is_err());
+    }
+}
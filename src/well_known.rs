@@ -0,0 +1,191 @@
+//! This contains [`WellKnown`], which reads the locations every triage
+//! starts with: the thread that was running on the processor that crashed
+//! (`gs:[0x188]`), its process, and the idle thread of that processor. They
+//! are read the first time they are asked for, and cached.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::KernelDumpParser;
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! let well_known = parser.well_known();
+//! println!(
+//!     "crash occurred in process {}, thread {}",
+//!     well_known.current_process_name().unwrap(),
+//!     well_known.current_ethread().unwrap()
+//! );
+//! ```
+use std::sync::OnceLock;
+
+use crate::error::Result;
+use crate::gxa::Gxa;
+use crate::processor::KPCR_PRCB;
+use crate::{Gva, KdmpParserError, KernelDumpParser};
+
+/// The well-known locations of a dump; see [`KernelDumpParser::well_known`].
+/// The locations that can't be read are errors, which aren't cached.
+#[derive(Debug)]
+pub struct WellKnown<'parser> {
+    parser: &'parser KernelDumpParser,
+    current_ethread: OnceLock<Gva>,
+    current_eprocess: OnceLock<Gva>,
+    current_process_name: OnceLock<String>,
+    idle_thread: OnceLock<Gva>,
+}
+
+/// Get the value cached in `cell`, or cache what `init` returns if it
+/// succeeds.
+fn cached<T>(cell: &OnceLock<T>, init: impl FnOnce() -> Result<T>) -> Result<&T> {
+    if let Some(value) = cell.get() {
+        return Ok(value);
+    }
+
+    let value = init()?;
+
+    Ok(cell.get_or_init(|| value))
+}
+
+impl<'parser> WellKnown<'parser> {
+    fn new(parser: &'parser KernelDumpParser) -> Self {
+        Self {
+            parser,
+            current_ethread: OnceLock::new(),
+            current_eprocess: OnceLock::new(),
+            current_process_name: OnceLock::new(),
+            idle_thread: OnceLock::new(),
+        }
+    }
+
+    /// The `_KPRCB` of the processor that crashed, or the one in the `_KPCR`
+    /// its kernel `GS` base points to.
+    fn prcb(&self) -> Result<Gva> {
+        self.parser
+            .crashing_prcb()
+            .or_else(|| {
+                self.parser
+                    .gs_base_kernel()
+                    .and_then(|pcr| pcr.u64().checked_add(KPCR_PRCB))
+                    .map(Gva::new)
+            })
+            .ok_or(KdmpParserError::NotFound("the processor that crashed"))
+    }
+
+    /// Read the pointer `field` of the `_KPRCB` of the processor that
+    /// crashed; a null pointer is `what` not being found.
+    fn prcb_pointer(&self, field: &str, what: &'static str) -> Result<Gva> {
+        match self.parser.read_field(self.prcb()?, "_KPRCB", field)? {
+            0 => Err(KdmpParserError::NotFound(what)),
+            pointer => Ok(Gva::new(pointer)),
+        }
+    }
+
+    /// The `_ETHREAD` that was running on the processor that crashed, which
+    /// is the `CurrentThread` of its `_KPRCB` (`gs:[0x188]`).
+    pub fn current_ethread(&self) -> Result<Gva> {
+        cached(&self.current_ethread, || {
+            self.prcb_pointer("CurrentThread", "the current thread")
+        })
+        .copied()
+    }
+
+    /// The `_EPROCESS` the current thread is attached to, which is the
+    /// `ApcState.Process` of its `_KTHREAD`.
+    pub fn current_eprocess(&self) -> Result<Gva> {
+        cached(&self.current_eprocess, || {
+            let thread = self.current_ethread()?;
+            match self
+                .parser
+                .read_field(thread, "_KTHREAD", "ApcState.Process")?
+            {
+                0 => Err(KdmpParserError::NotFound("the current process")),
+                process => Ok(Gva::new(process)),
+            }
+        })
+        .copied()
+    }
+
+    /// The `ImageFileName` of the current process, like `chrome.exe`.
+    pub fn current_process_name(&self) -> Result<String> {
+        cached(&self.current_process_name, || {
+            self.parser.process_name(self.current_eprocess()?)
+        })
+        .cloned()
+    }
+
+    /// The idle thread of the processor that crashed, which is the
+    /// `IdleThread` of its `_KPRCB`. The processor was idle if it is the
+    /// current thread.
+    pub fn idle_thread(&self) -> Result<Gva> {
+        cached(&self.idle_thread, || {
+            self.prcb_pointer("IdleThread", "the idle thread")
+        })
+        .copied()
+    }
+}
+
+impl KernelDumpParser {
+    /// Get the [`WellKnown`] locations of the dump, which are read when they
+    /// are first asked for.
+    pub fn well_known(&self) -> WellKnown<'_> {
+        WellKnown::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{DumpBuilder, KdDebuggerData, Register};
+    use crate::PxeFlags;
+
+    const KDBG: u64 = 0xffff_f800_0100_0000;
+    const PRCB: u64 = 0xffff_f800_0200_0000;
+    const THREAD: u64 = 0xffff_f800_0300_0000;
+    const EPROCESS: u64 = 0xffff_f800_0400_0000;
+
+    #[test]
+    fn well_known() {
+        // One processor, that crashed while running a thread of `chrome.exe`.
+        let context = PRCB + 0x800;
+        let builder = DumpBuilder::new()
+            .register(Register::Rsp, 0x1337)
+            .map_virt(KDBG, 0x10_000, PxeFlags::Present)
+            .map_virt(PRCB, 0x11_000, PxeFlags::Present)
+            .map_virt(THREAD, 0x12_000, PxeFlags::Present)
+            .kd_debugger_data(KDBG, KdDebuggerData {
+                prcbs: vec![PRCB],
+                offset_prcb_context: 0x400,
+                ..Default::default()
+            })
+            .write_virt(PRCB + 0x400, &context.to_le_bytes())
+            .write_virt(context + 0x98, &0x1337u64.to_le_bytes())
+            .write_virt(PRCB + 0x8, &THREAD.to_le_bytes())
+            .write_virt(PRCB + 0x18, &(THREAD + 0x800).to_le_bytes())
+            .write_virt(THREAD + 0xb8, &EPROCESS.to_le_bytes());
+
+        // The process can't be read..
+        let parser = KernelDumpParser::from_bytes(builder.clone().build()).unwrap();
+        let well_known = parser.well_known();
+        assert_eq!(well_known.current_ethread().unwrap(), Gva::new(THREAD));
+        assert_eq!(well_known.idle_thread().unwrap(), Gva::new(THREAD + 0x800));
+        assert_eq!(well_known.current_eprocess().unwrap(), Gva::new(EPROCESS));
+        assert!(well_known.current_process_name().is_err());
+
+        // ..until it is mapped.
+        let dump = builder
+            .map_virt(EPROCESS, 0x13_000, PxeFlags::Present)
+            .write_virt(EPROCESS + 0x5a8, b"chrome.exe\0")
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+        let well_known = parser.well_known();
+        assert_eq!(well_known.current_process_name().unwrap(), "chrome.exe");
+        assert_eq!(well_known.current_ethread.get(), Some(&Gva::new(THREAD)));
+        assert_eq!(well_known.idle_thread.get(), None);
+
+        // Without a processor that crashed, nothing can be found.
+        let parser = KernelDumpParser::from_bytes(DumpBuilder::new().build()).unwrap();
+        assert!(matches!(
+            parser.well_known().current_ethread(),
+            Err(KdmpParserError::NotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,227 @@
+//! This contains what is needed to inspect the work items queued to the
+//! system worker threads (see [`WorkItem`]).
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::KernelDumpParser;
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! for item in parser.work_items().unwrap() {
+//!     println!(
+//!         "{:?}: {} ({:?})",
+//!         item.queue,
+//!         item.routine,
+//!         item.module.as_deref().unwrap_or("?")
+//!     );
+//! }
+//! ```
+use crate::error::Result;
+use crate::gxa::Gxa;
+use crate::trace::trace_debug;
+use crate::{Gva, KdmpParserError, KernelDumpParser};
+
+/// Number of priority lists of a `_KPRIQUEUE`.
+const PRIORITIES: u64 = 32;
+
+/// Size of a `_LIST_ENTRY`.
+const LIST_ENTRY_SIZE: u64 = 0x10;
+
+/// Maximum number of work items we'll read off a list.
+const MAX_WORK_ITEMS: usize = 0x1_000;
+
+/// The queue a work item was queued to (`nt!_WORK_QUEUE_TYPE`). Since Windows
+/// 10, the queues of a node are the priority lists of a single `_KPRIQUEUE`,
+/// so the queue is deduced from the priority of the list the item is in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkQueueType {
+    /// `CriticalWorkQueue`, at priority 13.
+    Critical,
+    /// `DelayedWorkQueue`, at priority 12.
+    Delayed,
+    /// `HyperCriticalWorkQueue`, at priority 15.
+    HyperCritical,
+    /// `NormalWorkQueue`, at priority 8.
+    Normal,
+    /// `BackgroundWorkQueue`, at priority 7.
+    Background,
+    /// `RealTimeWorkQueue`, at priority 18.
+    RealTime,
+    /// `SuperCriticalWorkQueue`, at priority 14.
+    SuperCritical,
+    /// A priority that doesn't belong to any of the queues above.
+    Other(u8),
+}
+
+impl WorkQueueType {
+    fn from_priority(priority: u8) -> Self {
+        match priority {
+            7 => Self::Background,
+            8 => Self::Normal,
+            12 => Self::Delayed,
+            13 => Self::Critical,
+            14 => Self::SuperCritical,
+            15 => Self::HyperCritical,
+            18 => Self::RealTime,
+            priority => Self::Other(priority),
+        }
+    }
+}
+
+/// A work item queued to the system worker threads (`nt!_WORK_QUEUE_ITEM`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkItem {
+    /// The routine called by the worker thread; for the items queued with
+    /// `IoQueueWorkItem` it is the `Routine` of the `_IO_WORKITEM`.
+    pub routine: Gva,
+    /// The parameter passed to the routine; for the items queued with
+    /// `IoQueueWorkItem` it is the `Context` of the `_IO_WORKITEM`.
+    pub parameter: Gva,
+    /// The queue the item is in.
+    pub queue: WorkQueueType,
+    /// Name of the module the routine belongs to.
+    pub module: Option<String>,
+}
+
+impl KernelDumpParser {
+    /// Get the work items queued to the work queues of the nodes of every
+    /// processor. A list that can't be walked (unreadable memory, a cycle,
+    /// etc.) doesn't prevent walking the others.
+    pub fn work_items(&self) -> Result<Vec<WorkItem>> {
+        self.ki_processor_block()?;
+        let lists = self
+            .profile()
+            .offset("_ENODE", "ExWorkQueue.WorkPriQueue.EntryListHead[0]")?;
+        let link = self.profile().offset("_WORK_QUEUE_ITEM", "List")?;
+
+        // The processors of a node share its queues, so only walk them once.
+        let mut nodes = Vec::new();
+        for prcb in self.prcbs().into_iter().flatten() {
+            match self.read_field(prcb, "_KPRCB", "ParentNode").map(Gva::new) {
+                Ok(node) if node.u64() != 0 && !nodes.contains(&node) => nodes.push(node),
+                Ok(_) => {}
+                Err(e) => trace_debug!("failed reading the node of {prcb}: {e}"),
+            }
+        }
+
+        let mut items = Vec::new();
+        for node in nodes {
+            for priority in 0..PRIORITIES {
+                let head = (priority * LIST_ENTRY_SIZE)
+                    .checked_add(lists)
+                    .and_then(|offset| node.u64().checked_add(offset))
+                    .map(Gva::new)
+                    .ok_or(KdmpParserError::Overflow("work queue list"))?;
+
+                let queue = WorkQueueType::from_priority(priority as u8);
+                for item in self.walk_list(head, link, MAX_WORK_ITEMS) {
+                    match item.and_then(|item| self.read_work_item(item, queue)) {
+                        Ok(item) => items.push(item),
+                        Err(e) => {
+                            trace_debug!("failed walking the work items at {head}: {e}");
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// Read the `_WORK_QUEUE_ITEM` at `item`.
+    fn read_work_item(&self, item: Gva, queue: WorkQueueType) -> Result<WorkItem> {
+        let mut routine = Gva::new(self.read_field(item, "_WORK_QUEUE_ITEM", "WorkerRoutine")?);
+        let mut parameter = Gva::new(self.read_field(item, "_WORK_QUEUE_ITEM", "Parameter")?);
+
+        // `IoQueueWorkItem` queues the `_IO_WORKITEM` itself with
+        // `nt!IopProcessWorkItem`, which calls the actual routine.
+        if parameter == item {
+            routine = Gva::new(self.read_field(item, "_IO_WORKITEM", "Routine")?);
+            parameter = Gva::new(self.read_field(item, "_IO_WORKITEM", "Context")?);
+        }
+
+        Ok(WorkItem {
+            routine,
+            parameter,
+            queue,
+            module: self.find_module(routine).map(|(_, name)| name.to_string()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{DumpBuilder, KdDebuggerData};
+    use crate::PxeFlags;
+
+    const KDBG: u64 = 0xffff_f800_0100_0000;
+    const PRCB: u64 = 0xffff_f800_0200_0000;
+    const NODE: u64 = 0xffff_f800_0300_0000;
+    const NT: u64 = 0xffff_f800_0400_0000;
+    const DRIVER: u64 = 0xffff_f800_0500_0000;
+
+    #[test]
+    fn work_items() {
+        // Both processors belong to the same node; a plain work item is in the
+        // critical list, an `_IO_WORKITEM` in the delayed one, and the list of
+        // priority 20 links to a page that isn't mapped.
+        let (item, io_item) = (NODE + 0x1_000, NODE + 0x1_100);
+        let head = |priority: u64| NODE + 0x118 + priority * 0x10;
+        let mut builder = DumpBuilder::new()
+            .processors(2)
+            .module(NT..NT + 0x1_000, "ntoskrnl.exe")
+            .module(DRIVER..DRIVER + 0x1_000, "\\SystemRoot\\evil.sys")
+            .map_virt(KDBG, 0x10_000, PxeFlags::Present)
+            .map_virt(PRCB, 0x11_000, PxeFlags::Present)
+            .map_virt(NODE, 0x12_000, PxeFlags::Present)
+            .map_virt(NODE + 0x1_000, 0x13_000, PxeFlags::Present)
+            .kd_debugger_data(KDBG, KdDebuggerData {
+                prcbs: vec![PRCB, PRCB + 0x800],
+                ..Default::default()
+            })
+            .write_virt(PRCB + 0xc8, &NODE.to_le_bytes())
+            .write_virt(PRCB + 0x800 + 0xc8, &NODE.to_le_bytes())
+            .write_virt(head(20), &0xdead_0000u64.to_le_bytes());
+        for (at, priority, routine, parameter) in [
+            (item, 13, NT + 0x10, 0x1234u64),
+            (io_item, 12, NT + 0x20, io_item),
+        ] {
+            builder = builder
+                .write_virt(head(priority), &at.to_le_bytes())
+                .write_virt(head(priority) + 8, &at.to_le_bytes())
+                .write_virt(at, &head(priority).to_le_bytes())
+                .write_virt(at + 8, &head(priority).to_le_bytes())
+                .write_virt(at + 0x10, &routine.to_le_bytes())
+                .write_virt(at + 0x18, &parameter.to_le_bytes());
+        }
+
+        let dump = builder
+            .write_virt(io_item + 0x20, &(DRIVER + 0x30).to_le_bytes())
+            .write_virt(io_item + 0x30, &0x5678u64.to_le_bytes())
+            .build();
+        let parser = KernelDumpParser::from_bytes(dump).unwrap();
+        assert_eq!(parser.work_items().unwrap(), [
+            WorkItem {
+                routine: Gva::new(DRIVER + 0x30),
+                parameter: Gva::new(0x5678),
+                queue: WorkQueueType::Delayed,
+                module: Some("\\SystemRoot\\evil.sys".into())
+            },
+            WorkItem {
+                routine: Gva::new(NT + 0x10),
+                parameter: Gva::new(0x1234),
+                queue: WorkQueueType::Critical,
+                module: Some("ntoskrnl.exe".into())
+            }
+        ]);
+        assert_eq!(WorkQueueType::from_priority(20), WorkQueueType::Other(20));
+
+        // Without `nt!KiProcessorBlock`, the processors can't be found.
+        let parser = KernelDumpParser::from_bytes(DumpBuilder::new().build()).unwrap();
+        assert!(matches!(
+            parser.work_items(),
+            Err(KdmpParserError::NotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,339 @@
+//! This contains what is needed to interpret the 32-bit user structures of the
+//! WOW64 processes: their PEB32, their 32-bit module list and the 32-bit
+//! context of their threads. Translation is unaffected, it is still x64
+//! paging.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::{Gva, KernelDumpParser};
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! let eprocess = Gva::new(0xffff_c000_0000_0000);
+//! if parser.is_wow64(eprocess).unwrap() {
+//!     for module in parser.wow64_modules(eprocess).unwrap() {
+//!         println!("{}: {}-{}", module.name, module.at.start, module.at.end);
+//!     }
+//! }
+//!
+//! if let Some(context) = parser.wow64_context(None).unwrap() {
+//!     println!("eip: {:#x}", context.eip);
+//! }
+//! ```
+use std::collections::HashSet;
+
+use crate::error::Result;
+use crate::gxa::{Gpa, Gxa};
+use crate::structs::Wow64Context;
+use crate::teb::available;
+use crate::trace::trace_debug;
+use crate::{utf16, Gva, KdmpParserError, KernelDumpParser, ModuleEntry, ModuleOrigin};
+
+/// `WOW64_TLS_CPURESERVED`, the TLS slot of the 64-bit TEB that points to the
+/// CPU area of a WOW64 thread.
+const WOW64_TLS_CPURESERVED: usize = 1;
+
+/// The `WOW64_CONTEXT` follows the `Flags` and the `Machine` of the CPU area
+/// (`WOW64_CPURESERVED`).
+const WOW64_CONTEXT_OFFSET: u64 = 4;
+
+/// Maximum number of modules we'll read off a 32-bit module list.
+const MAX_MODULES: usize = 0x1_000;
+
+impl KernelDumpParser {
+    /// Is the `_EPROCESS` at `eprocess` a WOW64 process? It is if its
+    /// `WoW64Process` isn't null.
+    pub fn is_wow64(&self, eprocess: Gva) -> Result<bool> {
+        Ok(self.read_field(eprocess, "_EPROCESS", "WoW64Process")? != 0)
+    }
+
+    /// Get the address of the PEB32 of the `_EPROCESS` at `eprocess`; it is
+    /// `None` if it isn't a WOW64 process.
+    pub fn peb32(&self, eprocess: Gva) -> Result<Option<Gva>> {
+        let wow64 = Gva::new(self.read_field(eprocess, "_EPROCESS", "WoW64Process")?);
+        if wow64.u64() == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(Gva::new(self.read_field(
+            wow64,
+            "_EWOW64PROCESS",
+            "Peb",
+        )?)))
+    }
+
+    /// Get the modules of the 32-bit module list (`PEB32.Ldr`) of the
+    /// `_EPROCESS` at `eprocess`, which is read in the address space of the
+    /// process; their [`ModuleEntry::origin`] has its `UniqueProcessId`. It
+    /// is empty if it isn't a WOW64 process; the modules read before the list
+    /// turns out to be broken are returned, and the entries whose name can't
+    /// be read are skipped.
+    pub fn wow64_modules(&self, eprocess: Gva) -> Result<Vec<ModuleEntry>> {
+        let Some(peb32) = self.peb32(eprocess)? else {
+            return Ok(Vec::new());
+        };
+
+        let dtb = Gpa::new(self.read_field(eprocess, "_EPROCESS", "DirectoryTableBase")?);
+        let process = available(self.read_field(eprocess, "_EPROCESS", "UniqueProcessId"))?;
+        let ldr = Gva::new(self.read_field_with_dtb(peb32, "_PEB32", "Ldr", dtb)?);
+        let head = self.field_addr(ldr, "_PEB_LDR_DATA32", "InLoadOrderModuleList.Flink")?;
+        let mut modules = Vec::new();
+        let mut visited = HashSet::new();
+        // `InLoadOrderLinks` is the first field of `_LDR_DATA_TABLE_ENTRY32`, and
+        // `Flink` the first field of a `LIST_ENTRY32`.
+        let mut entry = self.read_flink32(head, dtb);
+        while let Ok(entry_addr) = entry {
+            if entry_addr == head {
+                break;
+            }
+
+            if !visited.insert(entry_addr) {
+                trace_debug!("the 32-bit module list loops back to {entry_addr}");
+                break;
+            }
+
+            if visited.len() > MAX_MODULES {
+                trace_debug!("the 32-bit module list is too long");
+                break;
+            }
+
+            match self.read_module32(entry_addr, dtb) {
+                Ok(Some(mut module)) => {
+                    module.origin = ModuleOrigin::User { process };
+                    modules.push(module);
+                }
+                Ok(None) => trace_debug!("failed reading the name of the module at {entry_addr}"),
+                Err(e) => {
+                    trace_debug!("failed reading the 32-bit module at {entry_addr}: {e}");
+                    break;
+                }
+            }
+
+            entry = self.read_flink32(entry_addr, dtb);
+        }
+
+        if let Err(e) = entry {
+            trace_debug!("failed walking the 32-bit module list: {e}");
+        }
+
+        Ok(modules)
+    }
+
+    /// Get the 32-bit context of the WOW64 thread `ethread`, or of the thread
+    /// that crashed if `None`, out of its CPU area. It is `None` if the thread
+    /// doesn't belong to a WOW64 process.
+    ///
+    /// The CPU area holds the context of the 32-bit code the thread was
+    /// running when it last switched to 64-bit mode, which it does to call
+    /// into the kernel; for a crash that originated in the 32-bit user code,
+    /// it is where the crash happened.
+    pub fn wow64_context(&self, ethread: Option<Gva>) -> Result<Option<Wow64Context>> {
+        let teb = self.teb(ethread)?;
+        if teb.teb32.is_none() {
+            return Ok(None);
+        }
+
+        let cpu_area = teb
+            .tls_slots
+            .and_then(|slots| slots.get(WOW64_TLS_CPURESERVED).copied())
+            .filter(|&cpu_area| cpu_area != 0)
+            .ok_or(KdmpParserError::Unavailable("the wow64 cpu area"))?;
+
+        let (_, dtb) = self.thread_address_space(self.thread_or_crashing(ethread)?)?;
+        let context = cpu_area
+            .checked_add(WOW64_CONTEXT_OFFSET)
+            .ok_or(KdmpParserError::Overflow("wow64 context"))?;
+
+        self.virt_read_struct_with_dtb(Gva::new(context), dtb)
+            .map(Some)
+    }
+
+    /// Get the 32-bit modules of the process the crashing thread belongs to;
+    /// it is empty if it isn't a WOW64 process or if they can't be read.
+    pub(crate) fn crashing_wow64_modules(&self) -> Vec<ModuleEntry> {
+        let process = self
+            .thread_or_crashing(None)
+            .and_then(|thread| self.read_field(thread, "_KTHREAD", "Process"));
+
+        match process.and_then(|process| self.wow64_modules(Gva::new(process))) {
+            Ok(modules) => modules,
+            Err(e) => {
+                trace_debug!("failed reading the 32-bit modules: {e}");
+
+                Vec::new()
+            }
+        }
+    }
+
+    /// Read the `Flink` of the `LIST_ENTRY32` at `entry`.
+    fn read_flink32(&self, entry: Gva, dtb: Gpa) -> Result<Gva> {
+        self.virt_read_struct_with_dtb::<u32>(entry, dtb)
+            .map(|flink| Gva::new(flink.into()))
+    }
+
+    /// Read the `_LDR_DATA_TABLE_ENTRY32` at `entry`; it is `None` if its name
+    /// can't be read.
+    fn read_module32(&self, entry: Gva, dtb: Gpa) -> Result<Option<ModuleEntry>> {
+        let field =
+            |name: &str| self.read_field_with_dtb(entry, "_LDR_DATA_TABLE_ENTRY32", name, dtb);
+        let dll_base = field("DllBase")?;
+        let size_of_image = field("SizeOfImage")? as u32;
+        let entry_point = field("EntryPoint")?;
+
+        // We first try to read `FullDllName` but will try `BaseDllName` if we
+        // couldn't read the former.
+        let mut name = None;
+        for string in ["FullDllName", "BaseDllName"] {
+            let length = field(&format!("{string}.Length"))?;
+            let buffer = Gva::new(field(&format!("{string}.Buffer"))?);
+            let mut data = vec![0; length as usize];
+            if available(self.virt_read_exact_with_dtb(buffer, &mut data, dtb))?.is_some() {
+                let policy = self.options.string_policy;
+                name = Some(utf16::decode(&utf16::units(&data), policy)?.0);
+                break;
+            }
+        }
+
+        let Some(name) = name else {
+            return Ok(None);
+        };
+
+        let mut module = ModuleEntry::new(
+            Gva::new(dll_base)..Gva::new(dll_base + u64::from(size_of_image)),
+            name,
+        );
+        module.size_of_image = size_of_image;
+        module.entry_point = (entry_point != 0).then(|| Gva::new(entry_point));
+        module.timestamp = available(field("TimeDateStamp"))?.map(|value| value as u32);
+
+        Ok(Some(module))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DumpBuilder;
+    use crate::PxeFlags;
+
+    const THREAD: u64 = 0xffff_c000_0100_0000;
+    const PROCESS: u64 = 0xffff_c000_0200_0000;
+    const WOW64: u64 = 0xffff_c000_0300_0000;
+    const TEB: u64 = 0x7ff6_0000_0000;
+    const PEB32: u64 = 0x30_0000;
+    const CPU_AREA: u64 = 0x40_0000;
+
+    /// Build a dump with a WOW64 thread whose process has `dtb` as directory
+    /// table base, and which has two 32-bit modules.
+    fn wow64_process(dtb: u64) -> DumpBuilder {
+        let user = PxeFlags::Present | PxeFlags::UserAccessible;
+        let ldr = PEB32 + 0x100;
+        let head = ldr + 0xc;
+        let (a, b) = (PEB32 + 0x200, PEB32 + 0x300);
+        let mut tls = vec![0; 64 * 8];
+        tls[8..16].copy_from_slice(&CPU_AREA.to_le_bytes());
+        let mut builder = DumpBuilder::new()
+            .map_virt(THREAD, 0x10_000, PxeFlags::Present)
+            .map_virt(PROCESS, 0x11_000, PxeFlags::Present)
+            .map_virt(WOW64, 0x12_000, PxeFlags::Present)
+            .map_virt(TEB, 0x13_000, user)
+            .map_virt(TEB + 0x1_000, 0x14_000, user)
+            .map_virt(PEB32, 0x15_000, user)
+            .map_virt(CPU_AREA, 0x16_000, user)
+            .write_virt(THREAD + 0xf0, &TEB.to_le_bytes())
+            .write_virt(THREAD + 0x220, &PROCESS.to_le_bytes())
+            .write_virt(PROCESS + 0x28, &dtb.to_le_bytes())
+            .write_virt(PROCESS + 0x440, &0x1338u64.to_le_bytes())
+            .write_virt(PROCESS + 0x580, &WOW64.to_le_bytes())
+            .write_virt(WOW64, &PEB32.to_le_bytes())
+            .write_virt(TEB + 0x1480, &tls)
+            .write_virt(PEB32 + 0xc, &(ldr as u32).to_le_bytes())
+            .write_virt(head, &(a as u32).to_le_bytes())
+            // The name of the second module is only available in `BaseDllName`.
+            .write_virt(PEB32 + 0x800, &[
+                b'a', 0, b'.', 0, b'd', 0, b'l', 0, b'l', 0,
+            ])
+            .write_virt(PEB32 + 0x900, &[b'b', 0])
+            .write_virt(CPU_AREA + 4, &0x1_0007u32.to_le_bytes())
+            .write_virt(CPU_AREA + 4 + 0xb0, &0x1337u32.to_le_bytes())
+            .write_virt(CPU_AREA + 4 + 0xb8, &0x7700_1000u32.to_le_bytes());
+        for (entry, next, base, name, base_name) in [
+            (
+                a,
+                b,
+                0x7700_0000u32,
+                (10u16, PEB32 as u32 + 0x800),
+                (0u16, 0u32),
+            ),
+            (
+                b,
+                head,
+                0x7600_0000,
+                (2, 0xdead_0000),
+                (2, PEB32 as u32 + 0x900),
+            ),
+        ] {
+            builder = builder
+                .write_virt(entry, &(next as u32).to_le_bytes())
+                .write_virt(entry + 0x18, &base.to_le_bytes())
+                .write_virt(entry + 0x1c, &(base + 0x10).to_le_bytes())
+                .write_virt(entry + 0x20, &0x1_000u32.to_le_bytes())
+                .write_virt(entry + 0x24, &name.0.to_le_bytes())
+                .write_virt(entry + 0x28, &name.1.to_le_bytes())
+                .write_virt(entry + 0x2c, &base_name.0.to_le_bytes())
+                .write_virt(entry + 0x30, &base_name.1.to_le_bytes())
+                .write_virt(entry + 0x44, &0x1234u32.to_le_bytes());
+        }
+
+        builder
+    }
+
+    #[test]
+    fn wow64() {
+        // The process uses the same directory table base as the dump, which is only
+        // known once the dump is built.
+        let dtb = KernelDumpParser::from_bytes(wow64_process(0).build())
+            .unwrap()
+            .headers()
+            .directory_table_base;
+        let parser = KernelDumpParser::from_bytes(wow64_process(dtb).build()).unwrap();
+
+        let process = Gva::new(PROCESS);
+        assert!(parser.is_wow64(process).unwrap());
+        assert_eq!(parser.peb32(process).unwrap(), Some(Gva::new(PEB32)));
+
+        let module = |base: u64, name: &str| {
+            let mut module = ModuleEntry::new(Gva::new(base)..Gva::new(base + 0x1_000), name);
+            module.entry_point = Some(Gva::new(base + 0x10));
+            module.timestamp = Some(0x1234);
+            module.origin = ModuleOrigin::User {
+                process: Some(0x1338),
+            };
+
+            module
+        };
+        assert_eq!(parser.wow64_modules(process).unwrap(), [
+            module(0x7700_0000, "a.dll"),
+            module(0x7600_0000, "b")
+        ]);
+
+        let context = parser
+            .wow64_context(Some(Gva::new(THREAD)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(context.context_flags, 0x1_0007);
+        assert_eq!(context.eax, 0x1337);
+        assert_eq!(context.eip, 0x7700_1000);
+
+        // A native process doesn't have any of that.
+        let parser = KernelDumpParser::from_bytes(
+            wow64_process(dtb)
+                .write_virt(PROCESS + 0x580, &0u64.to_le_bytes())
+                .build(),
+        )
+        .unwrap();
+        assert!(!parser.is_wow64(process).unwrap());
+        assert_eq!(parser.peb32(process).unwrap(), None);
+        assert_eq!(parser.wow64_modules(process).unwrap(), []);
+        assert_eq!(parser.wow64_context(Some(Gva::new(THREAD))).unwrap(), None);
+    }
+}
@@ -0,0 +1,307 @@
+//! This contains the decompressors of the two Xpress formats described in
+//! [MS-XCA]: the plain LZ77 one, and the LZ77+Huffman one.
+//!
+//! [MS-XCA]: https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-xca
+use crate::error::Result;
+use crate::KdmpParserError;
+
+/// Number of bytes a block of the LZ77+Huffman format decompresses to.
+const HUFFMAN_BLOCK_SIZE: usize = 0x1_0000;
+
+/// Number of bytes of the table of the bit lengths of a LZ77+Huffman block:
+/// one nibble for each of the 512 symbols.
+const HUFFMAN_TABLE_SIZE: usize = 0x100;
+
+/// Longest code of the LZ77+Huffman format.
+const HUFFMAN_MAX_BITS: u32 = 15;
+
+/// Read a little endian `u16` at `offset` in `input`.
+fn read_u16(input: &[u8], offset: usize) -> Result<u16> {
+    input
+        .get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(KdmpParserError::InvalidData("xpress stream is truncated"))
+}
+
+/// Read a little endian `u32` at `offset` in `input`.
+fn read_u32(input: &[u8], offset: usize) -> Result<u32> {
+    input
+        .get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(KdmpParserError::InvalidData("xpress stream is truncated"))
+}
+
+/// Read the byte at `offset` in `input`.
+fn read_u8(input: &[u8], offset: usize) -> Result<u8> {
+    input
+        .get(offset)
+        .copied()
+        .ok_or(KdmpParserError::InvalidData("xpress stream is truncated"))
+}
+
+/// Copy a match of `length` bytes that starts `offset` bytes before the end of
+/// `output`, without growing `output` past `size`; the match can overlap what
+/// it copies.
+fn copy_match(output: &mut Vec<u8>, offset: usize, length: usize, size: usize) -> Result<()> {
+    let start = output
+        .len()
+        .checked_sub(offset)
+        .ok_or(KdmpParserError::InvalidData(
+            "xpress match is out of bounds",
+        ))?;
+
+    let length = length.min(size - output.len());
+    for idx in start..start + length {
+        output.push(output[idx]);
+    }
+
+    Ok(())
+}
+
+/// Decompress `input`, compressed with the plain LZ77 format, to `size`
+/// bytes.
+pub(crate) fn decompress_lz77(input: &[u8], size: usize) -> Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(size);
+    let mut flags = 0u32;
+    let mut flag_count = 0;
+    let mut position = 0;
+    let mut last_length_half_byte = None;
+
+    while output.len() < size {
+        if flag_count == 0 {
+            flags = read_u32(input, position)?;
+            position += 4;
+            flag_count = 32;
+        }
+
+        flag_count -= 1;
+        if flags & (1 << flag_count) == 0 {
+            output.push(read_u8(input, position)?);
+            position += 1;
+            continue;
+        }
+
+        let match_bytes = usize::from(read_u16(input, position)?);
+        position += 2;
+        let offset = (match_bytes / 8) + 1;
+        let mut length = match_bytes % 8;
+        if length == 7 {
+            // Two lengths share a byte, one nibble each.
+            length = match last_length_half_byte.take() {
+                Some(half_byte) => usize::from(read_u8(input, half_byte)? / 16),
+                None => {
+                    last_length_half_byte = Some(position);
+                    position += 1;
+
+                    usize::from(read_u8(input, position - 1)? % 16)
+                }
+            };
+
+            if length == 15 {
+                length = usize::from(read_u8(input, position)?);
+                position += 1;
+                if length == 255 {
+                    length = usize::from(read_u16(input, position)?);
+                    position += 2;
+                    if length == 0 {
+                        length = read_u32(input, position)? as usize;
+                        position += 4;
+                    }
+
+                    length = length
+                        .checked_sub(15 + 7)
+                        .ok_or(KdmpParserError::InvalidData("xpress match is too short"))?;
+                }
+
+                length += 15;
+            }
+
+            length += 7;
+        }
+
+        copy_match(&mut output, offset, length + 3, size)?;
+    }
+
+    Ok(output)
+}
+
+/// Build the decoding table of a LZ77+Huffman block out of the bit lengths
+/// of its symbols: the entry of the first 15 bits of a code is its symbol.
+fn huffman_table(lengths: &[u8]) -> Result<Vec<u16>> {
+    let lengths = lengths
+        .iter()
+        .flat_map(|byte| [byte & 0xf, byte >> 4])
+        .collect::<Vec<_>>();
+
+    let size = 1 << HUFFMAN_MAX_BITS;
+    let mut table = Vec::with_capacity(size);
+    for bit_length in 1..=HUFFMAN_MAX_BITS {
+        for (symbol, _) in lengths
+            .iter()
+            .enumerate()
+            .filter(|(_, &length)| u32::from(length) == bit_length)
+        {
+            let entries = 1 << (HUFFMAN_MAX_BITS - bit_length);
+            if table.len() + entries > size {
+                return Err(KdmpParserError::InvalidData(
+                    "xpress huffman table overflows",
+                ));
+            }
+
+            table.extend(std::iter::repeat(symbol as u16).take(entries));
+        }
+    }
+
+    if table.len() != size {
+        return Err(KdmpParserError::InvalidData(
+            "xpress huffman table is incomplete",
+        ));
+    }
+
+    Ok(table)
+}
+
+/// Decompress `input`, compressed with the LZ77+Huffman format, to `size`
+/// bytes; `size` can't be more than a block.
+pub(crate) fn decompress_huffman(input: &[u8], size: usize) -> Result<Vec<u8>> {
+    if size > HUFFMAN_BLOCK_SIZE {
+        return Err(KdmpParserError::InvalidData(
+            "xpress huffman stream spans more than a block",
+        ));
+    }
+
+    let lengths = input
+        .get(..HUFFMAN_TABLE_SIZE)
+        .ok_or(KdmpParserError::InvalidData("xpress stream is truncated"))?;
+    let table = huffman_table(lengths)?;
+    let bit_length = |symbol: u16| {
+        let byte = lengths[usize::from(symbol / 2)];
+
+        u32::from(if symbol % 2 == 0 {
+            byte & 0xf
+        } else {
+            byte >> 4
+        })
+    };
+
+    // The bits are read 16 at a time, past the end of the stream too as the
+    // last symbol can be shorter than what is buffered.
+    let read_bits = |position: usize| u32::from(read_u16(input, position).unwrap_or(0));
+    let mut position = HUFFMAN_TABLE_SIZE;
+    let mut next_bits = (read_bits(position) << 16) | read_bits(position + 2);
+    position += 4;
+    let mut extra_bits = 16i32;
+    let mut consume = |next_bits: &mut u32, count: u32, position: &mut usize| {
+        *next_bits = next_bits.checked_shl(count).unwrap_or(0);
+        extra_bits -= count as i32;
+        if extra_bits < 0 {
+            *next_bits |= read_bits(*position) << -extra_bits;
+            extra_bits += 16;
+            *position += 2;
+        }
+    };
+
+    let mut output = Vec::with_capacity(size);
+    while output.len() < size {
+        let symbol = table[(next_bits >> (32 - HUFFMAN_MAX_BITS)) as usize];
+        consume(&mut next_bits, bit_length(symbol), &mut position);
+        if symbol < 0x100 {
+            output.push(symbol as u8);
+            continue;
+        }
+
+        let symbol = symbol - 0x100;
+        let offset_bits = u32::from(symbol / 16);
+        let mut length = usize::from(symbol % 16);
+        if length == 15 {
+            length = usize::from(read_u8(input, position)?);
+            position += 1;
+            if length == 255 {
+                length = usize::from(read_u16(input, position)?);
+                position += 2;
+                length = length
+                    .checked_sub(15)
+                    .ok_or(KdmpParserError::InvalidData("xpress match is too short"))?;
+            }
+
+            length += 15;
+        }
+
+        let offset = next_bits.checked_shr(32 - offset_bits).unwrap_or(0) | (1 << offset_bits);
+        consume(&mut next_bits, offset_bits, &mut position);
+        copy_match(&mut output, offset as usize, length + 3, size)?;
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lz77() {
+        // Three literals, then a match of 6 bytes 3 bytes back.
+        let input = [0, 0, 0, 0x10, b'a', b'b', b'c', 0x13, 0];
+        assert_eq!(decompress_lz77(&input, 9).unwrap(), b"abcabcabc");
+
+        // A literal, then two matches whose lengths share a byte, then a match whose
+        // length takes a byte and a word.
+        let input = [
+            0, 0, 0, 0x70, b'a', 7, 0, 0x2a, 7, 0, 7, 0, 0xf, 0xff, 0x36, 0x1,
+        ];
+        let output = decompress_lz77(&input, 1 + 20 + 12 + 0x139).unwrap();
+        assert!(output.iter().all(|&byte| byte == b'a'));
+
+        assert!(decompress_lz77(&input[..6], 0x100).is_err());
+        assert!(decompress_lz77(&[0, 0, 0, 0x80, 0x8, 0], 4).is_err());
+    }
+
+    /// Encode `symbols` (with the bits that follow them) with 9 bits codes.
+    fn huffman_stream(symbols: &[(u16, u32, u32)]) -> Vec<u8> {
+        let mut bits = Vec::new();
+        for &(symbol, extra, extra_bits) in symbols {
+            bits.extend((0..9).rev().map(|bit| (symbol >> bit) & 1 == 1));
+            bits.extend((0..extra_bits).rev().map(|bit| (extra >> bit) & 1 == 1));
+        }
+
+        let mut stream = vec![0x99; HUFFMAN_TABLE_SIZE];
+        for word in bits.chunks(16) {
+            let word = word.iter().enumerate().fold(0u16, |word, (idx, &bit)| {
+                word | (u16::from(bit) << (15 - idx))
+            });
+            stream.extend_from_slice(&word.to_le_bytes());
+        }
+
+        stream
+    }
+
+    #[test]
+    fn huffman() {
+        // Every symbol has a 9 bits code, which is its value. Three literals, then a
+        // match of 6 bytes 3 bytes back (an offset of 1 bit).
+        let stream = huffman_stream(&[
+            (u16::from(b'a'), 0, 0),
+            (u16::from(b'b'), 0, 0),
+            (u16::from(b'c'), 0, 0),
+            (0x100 + 0x10 + 3, 1, 1),
+        ]);
+        assert_eq!(decompress_huffman(&stream, 9).unwrap(), b"abcabcabc");
+
+        // A match of 0x1_000 bytes 1 byte back (an offset of 0 bits) has its length in
+        // a byte and a word.
+        // The byte and the word follow the 16 bits read when the match symbol is.
+        let mut stream = huffman_stream(&[(0, 0, 0), (0x100 + 0xf, 0, 0)]);
+        stream.extend_from_slice(&[0, 0, 0xff, 0xfd, 0xf]);
+        let output = decompress_huffman(&stream, 0x1_001).unwrap();
+        assert_eq!(output, vec![0; 0x1_001]);
+
+        let mut stream = huffman_stream(&[(0, 0, 0)]);
+        assert!(decompress_huffman(&stream[..0x80], 1).is_err());
+        assert!(decompress_huffman(&stream, 0x1_0001).is_err());
+
+        // The bit lengths have to describe a complete code.
+        stream[0] = 0x88;
+        assert!(decompress_huffman(&stream, 1).is_err());
+    }
+}
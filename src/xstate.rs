@@ -0,0 +1,248 @@
+//! This contains what is needed to read the extended state of the processor
+//! (see [`XSaveState`]) out of the context record of the dump headers, when it
+//! has been captured with `CONTEXT_XSTATE`: the upper halves of the `ymm`
+//! registers, and the `zmm` and opmask registers of AVX-512.
+//!
+//! The `CONTEXT` is followed by a `CONTEXT_EX`, whose `XState` chunk points to
+//! the `XSAVE_AREA_HEADER` and to the components that follow it, in the
+//! standard or in the compacted format of `XSAVE`.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use kdmp_parser::KernelDumpParser;
+//! let parser = KernelDumpParser::new(&"full.dmp").unwrap();
+//! if let Some(state) = parser.extended_state() {
+//!     for (idx, high) in state.ymm_high.iter().enumerate() {
+//!         println!("ymm{idx}[255:128] = {high:02x?}");
+//!     }
+//! }
+//! ```
+use crate::structs::{Context, FromLeBytes};
+use crate::KernelDumpParser;
+
+/// `CONTEXT_AMD64 | 0x40`: the context has a `CONTEXT_EX` with an `XState`
+/// chunk.
+const CONTEXT_XSTATE: u32 = 0x10_0040;
+
+/// Offset of the `XState` chunk in the `CONTEXT_EX`, after the `All` and
+/// `Legacy` ones.
+const CONTEXT_EX_XSTATE: usize = 0x10;
+
+/// Size of the `XSAVE_AREA_HEADER`.
+const XSAVE_HEADER_SIZE: usize = 0x40;
+
+/// Offset of the first extended component in an `XSAVE` area, after the
+/// legacy area and the header.
+const XSAVE_EXTENDED_AREA: usize = 0x240;
+
+/// Bit of `XCOMP_BV` that says the area is in the compacted format.
+const XCOMP_BV_COMPACTED: u64 = 1 << 63;
+
+/// The components of the `XSAVE` area.
+const AVX: usize = 2;
+const OPMASK: usize = 5;
+const ZMM_HI256: usize = 6;
+const HI16_ZMM: usize = 7;
+
+/// Offset (in the standard format) and size of the extended components, up to
+/// [`HI16_ZMM`], indexed by their number.
+const COMPONENTS: [(usize, usize); 8] = [
+    (0, 0),
+    (0, 0),
+    (0x240, 0x100),
+    (0x3c0, 0x40),
+    (0x400, 0x40),
+    (0x440, 0x40),
+    (0x480, 0x200),
+    (0x680, 0x400),
+];
+
+/// The AVX-512 state of the processor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZmmState {
+    /// The opmask registers, `k0` to `k7`.
+    pub opmask: [u64; 8],
+    /// The upper 256 bits of `zmm0` to `zmm15`.
+    pub zmm_high: [[u8; 32]; 16],
+    /// The `zmm16` to `zmm31` registers.
+    pub hi16_zmm: [[u8; 64]; 16],
+}
+
+/// The extended state of the processor, saved by `XSAVE` in the context
+/// record; see [`KernelDumpParser::extended_state`]. The components that are
+/// in their initial state aren't saved, and are zeroes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XSaveState {
+    /// `XSTATE_BV`: the components that have been saved.
+    pub component_mask: u64,
+    /// The upper 128 bits of `ymm0` to `ymm15`; the lower ones are the `xmm`
+    /// registers of the context.
+    pub ymm_high: [[u8; 16]; 16],
+    /// The AVX-512 state, if it has been saved and fits in the context record.
+    pub zmm: Option<ZmmState>,
+}
+
+/// Copy the `N`-byte chunks of `bytes` into an array.
+fn chunks<const N: usize, const M: usize>(bytes: &[u8]) -> [[u8; N]; M] {
+    let mut array = [[0; N]; M];
+    for (dst, src) in array.iter_mut().zip(bytes.chunks_exact(N)) {
+        dst.copy_from_slice(src);
+    }
+
+    array
+}
+
+/// The `XSAVE` area of the context record `buffer`, from its header, if it
+/// has one.
+fn xsave_area(buffer: &[u8]) -> Option<&[u8]> {
+    let context = Context::from_le_bytes(buffer);
+    if context.context_flags & CONTEXT_XSTATE != CONTEXT_XSTATE {
+        return None;
+    }
+
+    // The offset of the chunk is relative to the `CONTEXT_EX`.
+    let chunk =
+        buffer.get(Context::SIZE + CONTEXT_EX_XSTATE..Context::SIZE + CONTEXT_EX_XSTATE + 8)?;
+    let offset = i32::from_le_bytes(chunk[..4].try_into().unwrap());
+    let length = u32::from_le_bytes(chunk[4..].try_into().unwrap());
+    let start = Context::SIZE.checked_add_signed(offset.try_into().ok()?)?;
+    let end = start.checked_add(length as usize)?.min(buffer.len());
+    let area = buffer.get(start..end)?;
+
+    (area.len() >= XSAVE_HEADER_SIZE).then_some(area)
+}
+
+impl XSaveState {
+    /// Parse the `XSAVE` area that starts with its header in `area`.
+    fn parse(area: &[u8]) -> Self {
+        let component_mask = u64::from_le_bytes(area[..8].try_into().unwrap());
+        let compaction_mask = u64::from_le_bytes(area[8..16].try_into().unwrap());
+
+        // Where the components are, relative to the header; in the compacted
+        // format only the enabled ones are there, one after the other.
+        let mut offsets = [None; COMPONENTS.len()];
+        let mut compacted = XSAVE_EXTENDED_AREA;
+        for (idx, &(standard, size)) in COMPONENTS.iter().enumerate().skip(AVX) {
+            let offset = if compaction_mask & XCOMP_BV_COMPACTED != 0 {
+                if compaction_mask & (1 << idx) == 0 {
+                    continue;
+                }
+
+                compacted += size;
+                compacted - size
+            } else {
+                standard
+            };
+
+            offsets[idx] = Some(offset - (XSAVE_EXTENDED_AREA - XSAVE_HEADER_SIZE));
+        }
+
+        // A component that isn't saved is in its initial state, zeroes;
+        // otherwise it has to be in the area.
+        let component = |idx: usize| -> Option<&[u8]> {
+            if component_mask & (1 << idx) == 0 {
+                return Some(&[]);
+            }
+
+            let offset = offsets[idx]?;
+            area.get(offset..offset + COMPONENTS[idx].1)
+        };
+
+        let ymm_high = component(AVX).map_or([[0; 16]; 16], chunks);
+        let zmm = if component_mask & ((1 << OPMASK) | (1 << ZMM_HI256) | (1 << HI16_ZMM)) != 0 {
+            match (component(OPMASK), component(ZMM_HI256), component(HI16_ZMM)) {
+                (Some(opmask), Some(zmm_high), Some(hi16_zmm)) => Some(ZmmState {
+                    opmask: chunks::<8, 8>(opmask).map(u64::from_le_bytes),
+                    zmm_high: chunks(zmm_high),
+                    hi16_zmm: chunks(hi16_zmm),
+                }),
+                _ => None,
+            }
+        } else {
+            None
+        };
+
+        Self {
+            component_mask,
+            ymm_high,
+            zmm,
+        }
+    }
+}
+
+impl KernelDumpParser {
+    /// The extended state of the processor that crashed, out of the context
+    /// record of the headers; `None` if it hasn't been captured with
+    /// `CONTEXT_XSTATE`.
+    pub fn extended_state(&self) -> Option<XSaveState> {
+        xsave_area(&self.headers().context_record_buffer).map(XSaveState::parse)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::DumpBuilder;
+
+    /// Offset of the context record in the dump.
+    const CONTEXT: usize = 0x348;
+
+    /// A dump whose context record has an `XSAVE` area, `area`, right after
+    /// its `CONTEXT_EX`.
+    fn dump(area: &[u8]) -> Vec<u8> {
+        let mut dump = DumpBuilder::new().build();
+        dump[CONTEXT + 0x30..CONTEXT + 0x34].copy_from_slice(&0x10_005fu32.to_le_bytes());
+        let context_ex = CONTEXT + Context::SIZE;
+        dump[context_ex + 0x10..context_ex + 0x14].copy_from_slice(&0x20i32.to_le_bytes());
+        dump[context_ex + 0x14..context_ex + 0x18]
+            .copy_from_slice(&(area.len() as u32).to_le_bytes());
+        dump[context_ex + 0x20..context_ex + 0x20 + area.len()].copy_from_slice(area);
+
+        dump
+    }
+
+    #[test]
+    fn extended_state() {
+        // There is no `XSAVE` area without `CONTEXT_XSTATE`..
+        let parser = KernelDumpParser::from_bytes(DumpBuilder::new().build()).unwrap();
+        assert_eq!(parser.extended_state(), None);
+
+        // ..and the upper halves of the `ymm` registers are after the header,
+        // in the standard format..
+        let mut area = vec![0; XSAVE_HEADER_SIZE + 0x100];
+        area[..8].copy_from_slice(&0b111u64.to_le_bytes());
+        area[XSAVE_HEADER_SIZE..XSAVE_HEADER_SIZE + 16].copy_from_slice(&[0x41; 16]);
+        area[XSAVE_HEADER_SIZE + 0xf0..].copy_from_slice(&[0x42; 16]);
+        let parser = KernelDumpParser::from_bytes(dump(&area)).unwrap();
+        let state = parser.extended_state().unwrap();
+        assert_eq!(state.component_mask, 0b111);
+        assert_eq!(state.ymm_high[0], [0x41; 16]);
+        assert_eq!(state.ymm_high[15], [0x42; 16]);
+        assert_eq!(state.zmm, None);
+
+        // ..as well as in the compacted one, where only the enabled components
+        // are; the opmask registers are right after the `ymm` ones, and the
+        // `zmm` ones are in their initial state.
+        let mut area = vec![0; XSAVE_HEADER_SIZE + 0x140];
+        area[..8].copy_from_slice(&0b10_0100u64.to_le_bytes());
+        area[8..16].copy_from_slice(&(XCOMP_BV_COMPACTED | 0b1110_0111).to_le_bytes());
+        area[XSAVE_HEADER_SIZE..XSAVE_HEADER_SIZE + 16].copy_from_slice(&[0x41; 16]);
+        area[XSAVE_HEADER_SIZE + 0x108..XSAVE_HEADER_SIZE + 0x110]
+            .copy_from_slice(&0x1337u64.to_le_bytes());
+        let parser = KernelDumpParser::from_bytes(dump(&area)).unwrap();
+        let state = parser.extended_state().unwrap();
+        assert_eq!(state.ymm_high[0], [0x41; 16]);
+        let zmm = state.zmm.unwrap();
+        assert_eq!(zmm.opmask[1], 0x1337);
+        assert_eq!(zmm.zmm_high, [[0; 32]; 16]);
+        assert_eq!(zmm.hi16_zmm, [[0; 64]; 16]);
+
+        // The AVX-512 state that doesn't fit in the context record is left
+        // out.
+        let mut area = vec![0; XSAVE_HEADER_SIZE + 0x100];
+        area[..8].copy_from_slice(&0b1110_0100u64.to_le_bytes());
+        let parser = KernelDumpParser::from_bytes(dump(&area)).unwrap();
+        assert_eq!(parser.extended_state().unwrap().zmm, None);
+    }
+}
@@ -0,0 +1,93 @@
+//! Helpers shared by the tests that run against synthetic dumps.
+// Not every test uses every helper.
+#![allow(dead_code)]
+
+use kdmp_parser::Gva;
+
+/// Where the page tables live at.
+const PML4: u64 = 0x1_000;
+const PDPT: u64 = 0x2_000;
+const PD: u64 = 0x3_000;
+const PT: u64 = 0x4_000;
+/// Where the mapped pages start at in physical memory.
+pub const DATA: u64 = 0x10_000;
+/// How many pages are mapped.
+pub const DATA_PAGES: u64 = 0x10;
+/// Where the mapped pages start at in virtual memory.
+pub const BASE: u64 = 0xffff_f805_1060_0000;
+/// Offset of the first page in the bitmap header.
+pub const BMP_FIRST_PAGE: usize = 0x2_000 + 0x20;
+/// Offset of the total number of present pages in the bitmap header.
+pub const BMP_TOTAL_PRESENT_PAGES: usize = 0x2_000 + 0x28;
+
+/// Build a small BMP dump in memory that maps [`DATA_PAGES`] zeroed pages at
+/// [`BASE`]; `patches` are written at their offset from [`BASE`].
+pub fn synthetic_dump(patches: &[(usize, &[u8])]) -> Vec<u8> {
+    let gva = Gva::new(BASE);
+    let mut pages = vec![
+        (PML4, gva.pml4e_idx(), PDPT),
+        (PDPT, gva.pdpe_idx(), PD),
+        (PD, gva.pde_idx(), PT),
+    ]
+    .into_iter()
+    .map(|(gpa, idx, next)| {
+        let mut page = vec![0; 0x1_000];
+        let idx = usize::try_from(idx).unwrap() * 8;
+        page[idx..idx + 8].copy_from_slice(&(next | 0b11).to_le_bytes());
+
+        (gpa, page)
+    })
+    .collect::<Vec<_>>();
+
+    let mut pt = vec![0; 0x1_000];
+    for page_idx in 0..DATA_PAGES {
+        let pte = (DATA + (page_idx * 0x1_000)) | 0b11;
+        let idx = usize::try_from(gva.pte_idx() + page_idx).unwrap() * 8;
+        pt[idx..idx + 8].copy_from_slice(&pte.to_le_bytes());
+    }
+    pages.push((PT, pt));
+
+    let mut data = vec![0; usize::try_from(DATA_PAGES).unwrap() * 0x1_000];
+    for (offset, patch) in patches {
+        data[*offset..offset + patch.len()].copy_from_slice(patch);
+    }
+
+    for (page_idx, page) in data.chunks(0x1_000).enumerate() {
+        pages.push((DATA + (page_idx as u64 * 0x1_000), page.to_vec()));
+    }
+
+    // The main header..
+    let mut dump = vec![0; 0x2_000];
+    dump[0x0..0x4].copy_from_slice(b"PAGE");
+    dump[0x4..0x8].copy_from_slice(b"DU64");
+    dump[0x10..0x18].copy_from_slice(&PML4.to_le_bytes());
+    dump[0xf98..0xf9c].copy_from_slice(&5u32.to_le_bytes());
+
+    // ..the bitmap header..
+    let max_pfn = DATA / 0x1_000 + DATA_PAGES;
+    let bitmap_size = (max_pfn + 7) / 8;
+    let first_page = 0x2_000 + 0x38 + bitmap_size;
+    let mut bmp = vec![0; 0x38];
+    bmp[0x0..0x4].copy_from_slice(b"SDMP");
+    bmp[0x4..0x8].copy_from_slice(b"DUMP");
+    bmp[0x20..0x28].copy_from_slice(&first_page.to_le_bytes());
+    bmp[0x28..0x30].copy_from_slice(&(pages.len() as u64).to_le_bytes());
+    bmp[0x30..0x38].copy_from_slice(&(bitmap_size * 8).to_le_bytes());
+    dump.extend_from_slice(&bmp);
+
+    // ..the bitmap itself..
+    pages.sort_by_key(|(gpa, _)| *gpa);
+    let mut bitmap = vec![0u8; bitmap_size.try_into().unwrap()];
+    for (gpa, _) in &pages {
+        let pfn = usize::try_from(gpa / 0x1_000).unwrap();
+        bitmap[pfn / 8] |= 1 << (pfn % 8);
+    }
+    dump.extend_from_slice(&bitmap);
+
+    // ..and the pages, in ascending physical address order.
+    for (_, page) in pages {
+        dump.extend_from_slice(&page);
+    }
+
+    dump
+}
@@ -0,0 +1,119 @@
+use std::io;
+
+use kdmp_parser::{Gpa, KdmpParserError, KernelDumpParser, ParserOptions, Warning};
+
+/// Build a complete dump with a page at pfn 2 and one at pfn 5, whose header
+/// is followed by `extension` bytes the parser doesn't know about, and whose
+/// metadata by `trailing` of them. Every page starts with its physical
+/// address.
+fn complete_dump(extension: u64, trailing: u64) -> Vec<u8> {
+    let present = [2u64, 5];
+    // The ranges of the pages are followed by an empty one.
+    let metadata_size = ((present.len() as u64 + 1) * 0x10) + trailing;
+    let first_page = 0x2_020 + metadata_size + extension;
+    let mut dump = vec![0; 0x2_000];
+    dump[0x0..0x4].copy_from_slice(b"PAGE");
+    dump[0x4..0x8].copy_from_slice(b"DU64");
+    dump[0xf98..0xf9c].copy_from_slice(&0xau32.to_le_bytes());
+    let mut rdmp = vec![0; 0x30];
+    rdmp[0x0..0x4].copy_from_slice(&0x40u32.to_le_bytes());
+    rdmp[0x4..0xc].copy_from_slice(b"RDMPDUMP");
+    rdmp[0x10..0x18].copy_from_slice(&metadata_size.to_le_bytes());
+    rdmp[0x18..0x20].copy_from_slice(&first_page.to_le_bytes());
+    rdmp[0x28..0x30].copy_from_slice(&(present.len() as u64).to_le_bytes());
+    dump.extend_from_slice(&rdmp);
+    for pfn in present {
+        dump.extend_from_slice(&pfn.to_le_bytes());
+        dump.extend_from_slice(&1u64.to_le_bytes());
+    }
+
+    // The unknown bytes aren't zeroes, so that they can't pass for an empty range.
+    dump.resize(first_page as usize, 0xaa);
+    for pfn in present {
+        let mut page = vec![0; 0x1_000];
+        page[..8].copy_from_slice(&(pfn * 0x1_000).to_le_bytes());
+        dump.extend_from_slice(&page);
+    }
+
+    dump
+}
+
+fn parse(dump: Vec<u8>, strict: bool) -> Result<KernelDumpParser, KdmpParserError> {
+    let options = ParserOptions {
+        strict,
+        ..Default::default()
+    };
+
+    KernelDumpParser::with_options(io::Cursor::new(dump), options)
+}
+
+fn read_page(parser: &KernelDumpParser, gpa: u64) -> u64 {
+    parser.phys_read_struct::<u64>(Gpa::new(gpa)).unwrap()
+}
+
+#[test]
+fn known_format() {
+    for strict in [false, true] {
+        let parser = parse(complete_dump(0, 0), strict).unwrap();
+        assert_eq!(parser.warnings(), []);
+        assert_eq!(read_page(&parser, 0x5_000), 0x5_000);
+    }
+}
+
+/// The extended headers are parsed with what the parser knows about them..
+#[test]
+fn extended_headers() {
+    let parser = parse(complete_dump(0x30, 0x8), false).unwrap();
+    assert_eq!(parser.warnings(), [
+        Warning::UnknownHeaderExtension {
+            header: "RDMP header",
+            extra_bytes: 0x30,
+        },
+        Warning::UnknownHeaderExtension {
+            header: "RDMP metadata",
+            extra_bytes: 0x8,
+        },
+    ]);
+    assert_eq!(parser.physmem_len(), 2);
+    assert_eq!(read_page(&parser, 0x2_000), 0x2_000);
+    assert_eq!(read_page(&parser, 0x5_000), 0x5_000);
+    assert_eq!(
+        KernelDumpParser::from_bytes(complete_dump(0x1_000, 0))
+            .unwrap()
+            .warnings(),
+        [Warning::UnknownHeaderExtension {
+            header: "RDMP header",
+            extra_bytes: 0x1_000,
+        }]
+    );
+}
+
+/// ..unless the dump is parsed in strict mode, to detect the format changes..
+#[test]
+fn strict() {
+    assert!(matches!(
+        parse(complete_dump(0x30, 0), true),
+        Err(KdmpParserError::Strict(Warning::UnknownHeaderExtension {
+            header: "RDMP header",
+            extra_bytes: 0x30,
+        }))
+    ));
+    assert!(matches!(
+        parse(complete_dump(0, 0x8), true),
+        Err(KdmpParserError::Strict(Warning::UnknownHeaderExtension {
+            header: "RDMP metadata",
+            extra_bytes: 0x8,
+        }))
+    ));
+}
+
+/// ..but the pages still can't start in the middle of the metadata.
+#[test]
+fn truncated_header() {
+    let mut dump = complete_dump(0, 0);
+    dump[0x2_018..0x2_020].copy_from_slice(&0x2_040u64.to_le_bytes());
+    assert!(matches!(
+        parse(dump, false),
+        Err(KdmpParserError::InvalidData(_))
+    ));
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use std::path::PathBuf;
+use std::process::Command;
+
+use common::{synthetic_dump, BASE, DATA};
+
+/// Write a synthetic dump to disk, with a marker at the start of its second
+/// page.
+fn dump(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("kdmp-parser-{name}-{}.dmp", std::process::id()));
+    std::fs::write(&path, synthetic_dump(&[(0x1_000, b"kdmp-parser\0")])).unwrap();
+
+    path
+}
+
+/// Run `kdmp` with `args`, and return what it printed.
+fn run(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_kdmp"))
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn cli() {
+    let path = dump("cli");
+    let dump = path.to_str().unwrap();
+
+    let info = run(&[dump, "info"]);
+    assert!(info.contains("Dump type       : Bmp"), "{info}");
+    assert!(info.contains("rip=0000000000000000"), "{info}");
+
+    // The memory is dumped like WinDbg's `db` does.
+    let marker = format!("{:#x}", BASE + 0x1_000);
+    assert_eq!(
+        run(&[dump, "read", &marker, "0xc"]),
+        format!(
+            "{:08x}`{:08x}  6b 64 6d 70 2d 70 61 72-73 65 72 00{}  kdmp-parser.\n",
+            (BASE + 0x1_000) >> 32,
+            (BASE + 0x1_000) & 0xffff_ffff,
+            " ".repeat(12)
+        )
+    );
+
+    let data = format!("{:#x}", DATA + 0x1_000);
+    assert!(run(&[dump, "read", "--phys", &data, "4"]).ends_with("  kdmp\n"));
+    assert!(run(&[dump, "translate", &marker])
+        .starts_with(&format!("Gva:{marker} -> GPA:{data} (file offset")));
+    assert_eq!(
+        run(&[dump, "search", "6b646d70 2d"]),
+        format!("GPA:{data}\n")
+    );
+
+    let raw = std::env::temp_dir().join(format!("kdmp-parser-cli-{}.raw", std::process::id()));
+    run(&[dump, "export-raw", raw.to_str().unwrap()]);
+    let image = std::fs::read(&raw).unwrap();
+    let offset = usize::try_from(DATA + 0x1_000).unwrap();
+    assert_eq!(&image[offset..offset + 11], b"kdmp-parser");
+
+    std::fs::remove_file(raw).unwrap();
+    std::fs::remove_file(path).unwrap();
+}
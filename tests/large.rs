@@ -0,0 +1,227 @@
+mod common;
+
+use std::io::{self, Read, Seek};
+
+use common::{synthetic_dump, BASE, BMP_FIRST_PAGE, DATA};
+use kdmp_parser::{Gpa, Gva, KdmpParserError, KernelDumpParser};
+
+/// Where the pages are moved to in the dump file.
+const FIVE_GIGS: u64 = 5 * 1_024 * 1_024 * 1_024;
+
+/// A dump file where the pages live past [`FIVE_GIGS`]; the space between the
+/// headers and the pages reads as zeros without being backed by anything.
+struct SparseDump {
+    headers: Vec<u8>,
+    pages: Vec<u8>,
+    pos: u64,
+}
+
+impl SparseDump {
+    fn new(patches: &[(usize, &[u8])]) -> Self {
+        let mut headers = synthetic_dump(patches);
+        let first_page = u64::from_le_bytes(
+            headers[BMP_FIRST_PAGE..BMP_FIRST_PAGE + 8]
+                .try_into()
+                .unwrap(),
+        );
+        let pages = headers.split_off(first_page as usize);
+        headers[BMP_FIRST_PAGE..BMP_FIRST_PAGE + 8].copy_from_slice(&FIVE_GIGS.to_le_bytes());
+
+        Self {
+            headers,
+            pages,
+            pos: 0,
+        }
+    }
+
+    fn len(&self) -> u64 {
+        FIVE_GIGS + self.pages.len() as u64
+    }
+}
+
+impl Read for SparseDump {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (data, offset) = match self.pos {
+            pos if pos < self.headers.len() as u64 => (&self.headers[..], pos),
+            pos if pos >= FIVE_GIGS => (&self.pages[..], pos - FIVE_GIGS),
+            // The hole.
+            pos => {
+                let len = (FIVE_GIGS - pos).min(buf.len() as u64) as usize;
+                buf[..len].fill(0);
+                self.pos += len as u64;
+
+                return Ok(len);
+            }
+        };
+
+        let data = data.get(offset as usize..).unwrap_or_default();
+        let len = data.len().min(buf.len());
+        buf[..len].copy_from_slice(&data[..len]);
+        self.pos += len as u64;
+
+        Ok(len)
+    }
+}
+
+impl Seek for SparseDump {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.pos = match pos {
+            io::SeekFrom::Start(pos) => pos,
+            io::SeekFrom::End(delta) => self.len().saturating_add_signed(delta),
+            io::SeekFrom::Current(delta) => self.pos.saturating_add_signed(delta),
+        };
+
+        Ok(self.pos)
+    }
+}
+
+#[test]
+fn pages_past_four_gigs() {
+    let dump = SparseDump::new(&[(0x1_337, b"above 4gb")]);
+    let size = dump.len();
+    let parser = KernelDumpParser::with_reader(dump).unwrap();
+    assert_eq!(parser.file_size(), size);
+    assert!(parser.physmem().all(|(_, offset)| offset >= FIVE_GIGS));
+
+    let mut buffer = [0; 9];
+    parser
+        .virt_read_exact(Gva::new(BASE + 0x1_337), &mut buffer)
+        .unwrap();
+    assert_eq!(&buffer, b"above 4gb");
+    assert_eq!(
+        parser
+            .phys_read_to_vec(Gpa::new(DATA + 0x1_337), 9)
+            .unwrap(),
+        b"above 4gb"
+    );
+}
+
+/// Same as above, but with a sparse file on disk which is too large to be
+/// memory mapped.
+#[cfg(unix)]
+#[test]
+fn sparse_file_past_four_gigs() {
+    use std::fs::File;
+    use std::io::Write;
+
+    let dump = SparseDump::new(&[(0x1_337, b"above 4gb")]);
+    let path = std::env::temp_dir().join(format!("kdmp-parser-large-{}.dmp", std::process::id()));
+    let mut file = File::create(&path).unwrap();
+    file.write_all(&dump.headers).unwrap();
+    file.seek(io::SeekFrom::Start(FIVE_GIGS)).unwrap();
+    file.write_all(&dump.pages).unwrap();
+    drop(file);
+
+    let parser = KernelDumpParser::new(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    let mut buffer = [0; 9];
+    parser
+        .unwrap()
+        .virt_read_exact(Gva::new(BASE + 0x1_337), &mut buffer)
+        .unwrap();
+    assert_eq!(&buffer, b"above 4gb");
+}
+
+/// Number of pages of the second run of [`huge_descriptor`], more than a
+/// `u32` counts.
+const HUGE_RUN_PAGES: u64 = 0x1_0000_0010;
+
+/// First page of the second run of [`huge_descriptor`].
+const HUGE_RUN_BASE: u64 = 0x1_0000_0000;
+
+/// The headers of a dump of `dump_type` whose memory descriptor has a run of
+/// one page at `0x2_000`, and a run of [`HUGE_RUN_PAGES`] pages at
+/// [`HUGE_RUN_BASE`].
+fn huge_descriptor(dump_type: u32) -> Vec<u8> {
+    let mut dump = vec![0; 0x2_000];
+    dump[0x0..0x4].copy_from_slice(b"PAGE");
+    dump[0x4..0x8].copy_from_slice(b"DU64");
+    dump[0x88..0x8c].copy_from_slice(&2u32.to_le_bytes());
+    dump[0x90..0x98].copy_from_slice(&(1 + HUGE_RUN_PAGES).to_le_bytes());
+    for (idx, (base_page, page_count)) in [(2, 1), (HUGE_RUN_BASE, HUGE_RUN_PAGES)]
+        .into_iter()
+        .enumerate()
+    {
+        let offset = 0x98 + (idx * 0x10);
+        dump[offset..offset + 8].copy_from_slice(&base_page.to_le_bytes());
+        dump[offset + 8..offset + 0x10].copy_from_slice(&page_count.to_le_bytes());
+    }
+
+    dump[0xf98..0xf9c].copy_from_slice(&dump_type.to_le_bytes());
+
+    dump
+}
+
+/// The page counts past `u32::MAX` are counted with 64-bit arithmetic, without
+/// a multi-terabyte dump: a complete dump only has a couple of the pages its
+/// descriptor describes, and estimating a full dump only reads its runs.
+#[test]
+fn page_counts_past_u32() {
+    // A complete dump with the first page of each run..
+    let mut dump = huge_descriptor(0xa);
+    let present = [2, HUGE_RUN_BASE];
+    let metadata_size = (present.len() as u64 + 1) * 0x10;
+    let mut rdmp = vec![0; 0x30];
+    rdmp[0x0..0x4].copy_from_slice(&0x40u32.to_le_bytes());
+    rdmp[0x4..0xc].copy_from_slice(b"RDMPDUMP");
+    rdmp[0x10..0x18].copy_from_slice(&metadata_size.to_le_bytes());
+    rdmp[0x18..0x20].copy_from_slice(&(0x2_020 + metadata_size).to_le_bytes());
+    rdmp[0x28..0x30].copy_from_slice(&(present.len() as u64).to_le_bytes());
+    dump.extend_from_slice(&rdmp);
+    for pfn in present {
+        dump.extend_from_slice(&pfn.to_le_bytes());
+        dump.extend_from_slice(&1u64.to_le_bytes());
+    }
+
+    dump.resize(dump.len() + 0x10 + (present.len() * 0x1_000), 0);
+    let parser = KernelDumpParser::from_bytes(dump).unwrap();
+    assert_eq!(parser.physmem_len(), 2);
+    assert_eq!(parser.info().pages, 2);
+
+    // ..describes more than `u32::MAX` pages..
+    let descriptor = parser.memory_descriptor().unwrap();
+    assert_eq!(descriptor.number_of_pages, 1 + HUGE_RUN_PAGES);
+    assert_eq!(
+        descriptor
+            .runs
+            .iter()
+            .map(|run| run.page_count)
+            .sum::<u64>(),
+        1 + HUGE_RUN_PAGES
+    );
+    let end = (HUGE_RUN_BASE + HUGE_RUN_PAGES) * 0x1_000;
+    assert_eq!(parser.max_physical_address(), Some(Gpa::new(end - 1)));
+
+    // ..which it doesn't have but the first one.
+    let start = (HUGE_RUN_BASE + 1) * 0x1_000;
+    assert_eq!(
+        parser
+            .excluded_ranges()
+            .into_iter()
+            .map(|(range, _)| range)
+            .collect::<Vec<_>>(),
+        [Gpa::new(start)..Gpa::new(end)]
+    );
+
+    // A full dump with the same descriptor is estimated without indexing it..
+    let path = std::env::temp_dir().join(format!("kdmp-parser-huge-{}.dmp", std::process::id()));
+    std::fs::write(&path, huge_descriptor(1)).unwrap();
+    let estimate = KernelDumpParser::estimate_resources(&path).unwrap();
+    assert_eq!(estimate.pages, 1 + HUGE_RUN_PAGES);
+    assert_eq!(estimate.index_bytes, 48 * (1 + HUGE_RUN_PAGES));
+
+    // ..and its runs have to fit in the header.
+    let mut dump = huge_descriptor(1);
+    dump[0x88..0x8c].copy_from_slice(&u32::MAX.to_le_bytes());
+    std::fs::write(&path, &dump).unwrap();
+    assert!(matches!(
+        KernelDumpParser::estimate_resources(&path),
+        Err(KdmpParserError::InvalidData(_))
+    ));
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(
+        KernelDumpParser::from_bytes(dump),
+        Err(KdmpParserError::InvalidData(_))
+    ));
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use std::io;
+
+use common::{synthetic_dump, BASE};
+use kdmp_parser::{Gva, KdmpParserError, KernelDumpParser, ModuleEntry, ParserOptions};
+
+/// The limit the dumps are parsed with.
+const LIMIT: u64 = 0x1_000;
+
+fn parser(patches: &[(usize, &[u8])]) -> KernelDumpParser {
+    let options = ParserOptions {
+        max_read_size: LIMIT,
+        ..Default::default()
+    };
+
+    KernelDumpParser::with_options(io::Cursor::new(synthetic_dump(patches)), options).unwrap()
+}
+
+fn is_limit(res: Result<impl std::fmt::Debug, KdmpParserError>, requested: u64) -> bool {
+    matches!(
+        res,
+        Err(KdmpParserError::ReadLimitExceeded { requested: r, limit: LIMIT }) if r == requested
+    )
+}
+
+#[test]
+fn read_to_vec() {
+    let parser = parser(&[]);
+    assert_eq!(
+        parser.virt_read_to_vec(Gva::new(BASE), LIMIT).unwrap(),
+        vec![0; LIMIT as usize]
+    );
+    assert!(is_limit(
+        parser.virt_read_to_vec(Gva::new(BASE), LIMIT + 1),
+        LIMIT + 1
+    ));
+    assert!(is_limit(
+        parser.virt_read_to_vec(Gva::new(BASE), u64::MAX),
+        u64::MAX
+    ));
+
+    // Reads into caller-sized buffers aren't limited.
+    let mut buffer = vec![0; 2 * LIMIT as usize];
+    parser.virt_read_exact(Gva::new(BASE), &mut buffer).unwrap();
+}
+
+#[test]
+fn hostile_directories() {
+    // An image which export and resource directories claim to be huge.
+    let size_of_image = 0xffff_ffffu32.to_le_bytes();
+    let directory_size = 0x7fff_0000u32.to_le_bytes();
+    let rva = 0x1_000u32.to_le_bytes();
+    let parser = parser(&[
+        (0x0, b"MZ"),
+        (0x3c, &0x80u32.to_le_bytes()),
+        (0x80, b"PE\0\0"),
+        (0x98, &0x20bu16.to_le_bytes()),
+        (0xd0, &size_of_image),
+        (0x104, &16u32.to_le_bytes()),
+        // Export directory.
+        (0x108, &rva),
+        (0x10c, &directory_size),
+        // Resource directory.
+        (0x118, &rva),
+        (0x11c, &directory_size),
+    ]);
+
+    let module = ModuleEntry::new(Gva::new(BASE)..Gva::new(BASE + 0x10_000), "hostile.sys");
+    assert!(is_limit(parser.module_exports(&module), 0x7fff_0000));
+    assert!(is_limit(parser.module_version_info(&module), 0x7fff_0000));
+}
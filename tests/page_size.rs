@@ -0,0 +1,95 @@
+use std::io;
+
+use kdmp_parser::{
+    CheckOutcome, Gpa, Gxa, KdmpParserError, KernelDumpParser, ParserOptions, Result,
+};
+
+/// Size of the pages the dump is written with.
+const PAGE_SIZE: u64 = 0x2_000;
+
+/// Build a full dump written with [`PAGE_SIZE`] pages, that has two runs: one
+/// page at pfn 1, and two pages at pfn 4. Every 0x1_000 bytes of a page start
+/// with their physical address.
+fn full_dump() -> Vec<u8> {
+    let runs = [(1u64, 1u64), (4, 2)];
+    let mut dump = vec![0; 0x2_000];
+    dump[0x0..0x4].copy_from_slice(b"PAGE");
+    dump[0x4..0x8].copy_from_slice(b"DU64");
+    dump[0x88..0x8c].copy_from_slice(&(runs.len() as u32).to_le_bytes());
+    dump[0x90..0x98].copy_from_slice(&3u64.to_le_bytes());
+    for (idx, (base_page, page_count)) in runs.iter().enumerate() {
+        let offset = 0x98 + (idx * 0x10);
+        dump[offset..offset + 8].copy_from_slice(&base_page.to_le_bytes());
+        dump[offset + 8..offset + 0x10].copy_from_slice(&page_count.to_le_bytes());
+    }
+
+    dump[0xf98..0xf9c].copy_from_slice(&1u32.to_le_bytes());
+    for (base_page, page_count) in runs {
+        let start = base_page * PAGE_SIZE;
+        for gpa in (start..start + (page_count * PAGE_SIZE)).step_by(0x1_000) {
+            let mut page = vec![0; 0x1_000];
+            page[..8].copy_from_slice(&gpa.to_le_bytes());
+            dump.extend_from_slice(&page);
+        }
+    }
+
+    dump
+}
+
+fn parse(page_size: u64) -> Result<KernelDumpParser> {
+    let options = ParserOptions {
+        page_size,
+        ..Default::default()
+    };
+
+    KernelDumpParser::with_options(io::Cursor::new(full_dump()), options)
+}
+
+#[test]
+fn page_size() {
+    let parser = parse(PAGE_SIZE).unwrap();
+    assert_eq!(
+        parser
+            .physmem()
+            .map(|(gpa, _)| gpa.u64())
+            .collect::<Vec<_>>(),
+        [0x2_000, 0x3_000, 0x8_000, 0x9_000, 0xa_000, 0xb_000]
+    );
+
+    for gpa in [0x3_000, 0x8_000, 0xb_000] {
+        let mut buffer = [0; 8];
+        parser.phys_read_exact(Gpa::new(gpa), &mut buffer).unwrap();
+        assert_eq!(u64::from_le_bytes(buffer), gpa);
+    }
+
+    // A read can straddle the two halves of a page, and two pages of a run.
+    let mut buffer = [0; 0x1_008];
+    parser
+        .phys_read_exact(Gpa::new(0x9_ff8), &mut buffer)
+        .unwrap();
+    assert_eq!(&buffer[8..0x10], &0xa_000u64.to_le_bytes());
+
+    // The descriptor counts pages of the size the dump is written with.
+    let report = parser.verify().unwrap();
+    let check = report
+        .checks
+        .iter()
+        .find(|check| check.name == "page count")
+        .unwrap();
+    assert_eq!(check.outcome, CheckOutcome::Passed);
+
+    // Parsed with the wrong page size, the pages end up at the wrong addresses.
+    let parser = KernelDumpParser::with_reader(io::Cursor::new(full_dump())).unwrap();
+    let mut buffer = [0; 8];
+    parser
+        .phys_read_exact(Gpa::new(0x1_000), &mut buffer)
+        .unwrap();
+    assert_eq!(u64::from_le_bytes(buffer), 0x2_000);
+
+    for page_size in [0, 0x800, 0x3_000, 0x40_0000] {
+        assert!(matches!(
+            parse(page_size),
+            Err(KdmpParserError::UnsupportedPageSize(size)) if size == page_size
+        ));
+    }
+}
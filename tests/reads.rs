@@ -0,0 +1,186 @@
+mod common;
+
+use std::io;
+
+use common::{synthetic_dump, BASE, DATA, DATA_PAGES};
+use kdmp_parser::{
+    AddrTranslationError, Gpa, Gva, IoSpan, KdmpParserError, KernelDumpParser, PageSource,
+    ReadMode, ReadRequest,
+};
+
+/// Offset of the last 0x10 bytes that are mapped.
+const END: usize = (DATA_PAGES as usize * 0x1_000) - 0x10;
+
+fn parser() -> KernelDumpParser {
+    let dump = synthetic_dump(&[(0x0, &[0x41; 0x10]), (END, &[0x42; 0x10])]);
+
+    KernelDumpParser::with_reader(io::Cursor::new(dump)).unwrap()
+}
+
+#[test]
+fn virt_read_to_vec() {
+    let parser = parser();
+    let mut expected = vec![0x41; 0x10];
+    expected.resize(0x1_800, 0);
+    assert_eq!(
+        parser.virt_read_to_vec(Gva::new(BASE), 0x1_800).unwrap(),
+        expected
+    );
+
+    // The read straddles the end of the mapped pages.
+    let gva = Gva::new(BASE + END as u64);
+    assert!(matches!(
+        parser.virt_read_to_vec(gva, 0x20),
+        Err(KdmpParserError::AddrTranslation(
+            AddrTranslationError::Virt(..)
+        ))
+    ));
+
+    let mut expected = vec![0x42; 0x10];
+    assert_eq!(
+        parser
+            .virt_read_to_vec_with_mode(gva, 0x10, ReadMode::Partial)
+            .unwrap(),
+        expected
+    );
+    assert_eq!(
+        parser
+            .virt_read_to_vec_with_mode(gva, 0x20, ReadMode::Partial)
+            .unwrap(),
+        expected
+    );
+
+    expected.resize(0x20, 0);
+    assert_eq!(
+        parser
+            .virt_read_to_vec_with_mode(gva, 0x20, ReadMode::ZeroFill)
+            .unwrap(),
+        expected
+    );
+}
+
+#[test]
+fn phys_read_to_vec() {
+    let parser = parser();
+    assert_eq!(
+        parser.phys_read_to_vec(Gpa::new(DATA), 0x10).unwrap(),
+        vec![0x41; 0x10]
+    );
+
+    let gpa = Gpa::new(DATA + END as u64);
+    assert!(parser.phys_read_to_vec(gpa, 0x11).is_err());
+    assert_eq!(
+        parser
+            .phys_read_to_vec_with_mode(gpa, 0x1_020, ReadMode::Partial)
+            .unwrap(),
+        vec![0x42; 0x10]
+    );
+
+    let mut expected = vec![0x42; 0x10];
+    expected.resize(0x1_020, 0);
+    assert_eq!(
+        parser
+            .phys_read_to_vec_with_mode(gpa, 0x1_020, ReadMode::ZeroFill)
+            .unwrap(),
+        expected
+    );
+
+    // Nothing is readable.
+    assert!(parser
+        .phys_read_to_vec_with_mode(Gpa::new(0x1_0000_0000), 0x10, ReadMode::Partial)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn plan_read() {
+    let parser = parser();
+    // The mapped pages are contiguous in the dump file, so they are a single span.
+    let file_offset = parser.phys_translate(Gpa::new(DATA + 0x10)).unwrap();
+    let plan = parser
+        .plan_virt_read(Gva::new(BASE + 0x10), END - 0x10)
+        .unwrap();
+    assert_eq!(plan, vec![IoSpan {
+        source: PageSource::Dump,
+        file_offset,
+        len: END - 0x10,
+        gap: false
+    }]);
+    assert_eq!(
+        parser
+            .plan_phys_read(Gpa::new(DATA + 0x10), END - 0x10)
+            .unwrap(),
+        plan
+    );
+
+    // The bytes past the mapped pages are a gap, which gets zero-filled.
+    let gva = Gva::new(BASE + END as u64);
+    let plan = parser.plan_virt_read(gva, 0x2_010).unwrap();
+    assert_eq!(plan.len(), 2);
+    assert_eq!(plan[0].len, 0x10);
+    assert!(!plan[0].gap);
+    assert_eq!(plan[1].len, 0x2_000);
+    assert!(plan[1].gap);
+
+    let mut buffer = vec![0xff; 0x2_010];
+    assert_eq!(parser.read_plan(&plan, &mut buffer).unwrap(), 0x2_010);
+    assert_eq!(
+        buffer,
+        parser
+            .virt_read_to_vec_with_mode(gva, 0x2_010, ReadMode::ZeroFill)
+            .unwrap()
+    );
+
+    // The default reads still fail on the gap.
+    assert!(matches!(
+        parser.virt_read(gva, &mut buffer),
+        Err(KdmpParserError::AddrTranslation(
+            AddrTranslationError::Virt(..)
+        ))
+    ));
+    assert!(parser.read_plan(&plan, &mut buffer[..0x10]).is_err());
+}
+
+#[test]
+fn virt_read_vectored() {
+    let parser = parser();
+    let mut first = [0; 0x20];
+    let mut last = [0; 0x10];
+    let mut straddling = [0; 0x20];
+    let mut middle = [0xff; 0x1_010];
+    let mut requests = [
+        ReadRequest {
+            gva: Gva::new(BASE + END as u64),
+            buf: &mut last,
+        },
+        ReadRequest {
+            gva: Gva::new(BASE + END as u64),
+            buf: &mut straddling,
+        },
+        ReadRequest {
+            gva: Gva::new(BASE + 0x1_ff0),
+            buf: &mut middle,
+        },
+        ReadRequest {
+            gva: Gva::new(BASE),
+            buf: &mut first,
+        },
+    ];
+
+    let results = parser.virt_read_vectored(&mut requests);
+    assert_eq!(results.len(), 4);
+    assert_eq!(*results[0].as_ref().unwrap(), 0x10);
+    assert!(matches!(
+        results[1],
+        Err(KdmpParserError::AddrTranslation(
+            AddrTranslationError::Virt(..)
+        ))
+    ));
+    assert_eq!(*results[2].as_ref().unwrap(), 0x1_010);
+    assert_eq!(*results[3].as_ref().unwrap(), 0x20);
+    assert_eq!(last, [0x42; 0x10]);
+    assert!(middle.iter().all(|&byte| byte == 0));
+    assert_eq!(first[..0x10], [0x41; 0x10]);
+    assert_eq!(first[0x10..], [0; 0x10]);
+    assert!(parser.virt_read_vectored(&mut []).is_empty());
+}
@@ -28,11 +28,11 @@ struct Module {
     at: Range<Gva>,
 }
 
-impl Into<Module> for M {
-    fn into(self) -> Module {
+impl From<M> for Module {
+    fn from(m: M) -> Self {
         Module {
-            name: self.name,
-            at: hex_str(&self.start).into()..hex_str(&self.end).into(),
+            name: m.name,
+            at: hex_str(&m.start).into()..hex_str(&m.end).into(),
         }
     }
 }
@@ -67,9 +67,9 @@ struct TestcaseValues<'test> {
 
 fn compare_modules(parser: &KernelDumpParser, modules: &[Module]) -> bool {
     eprintln!("{parser:?}");
-    let parser_modules = parser.user_modules().chain(parser.kernel_modules());
     let mut seen = HashSet::new();
-    for (r, name) in parser_modules {
+    for module in parser.modules() {
+        let (r, name) = (&module.at, module.name.as_str());
         if seen.contains(&r.start) {
             eprintln!("already seen {}", r.start);
             return false;
@@ -78,7 +78,7 @@ fn compare_modules(parser: &KernelDumpParser, modules: &[Module]) -> bool {
         let found_mod = modules.iter().find(|m| m.at == *r).unwrap();
         seen.insert(r.start);
 
-        let filename = name.rsplit_once('\\').map(|(_, s)| s).unwrap_or(&name);
+        let filename = name.rsplit_once('\\').map(|(_, s)| s).unwrap_or(name);
         if filename.to_lowercase() != found_mod.name.to_lowercase() {
             if found_mod.name == "nt" && filename == "ntoskrnl.exe" {
                 continue;
@@ -301,7 +301,7 @@ fn regressions() {
     for test in tests {
         let parser = KernelDumpParser::new(&test.file).unwrap();
         assert_eq!(parser.dump_type(), test.dump_type);
-        assert_eq!(parser.physmem().len(), test.size as usize);
+        assert_eq!(parser.physmem_len(), test.size);
         let mut buffer = [0; 16];
         parser
             .phys_read_exact(Gpa::new(test.phys_addr), &mut buffer)
@@ -345,13 +345,10 @@ fn regressions() {
     // Protect: 4 - ReadWrite
     // ```
     let parser = KernelDumpParser::new(&kernel_user_dump.file).unwrap();
-    let mut buffer = [0; 16];
-    let expected_buffer = [
-        0xe0, 0x07, 0xa3, 0x2e, 0xa4, 0x01, 0x00, 0x00, 0x80, 0xf2, 0xa2, 0x2e, 0xa4, 0x01, 0x00,
-        0x00,
-    ];
-    assert!(parser.virt_read(0x1a42ea30240.into(), &mut buffer).is_ok());
-    assert_eq!(buffer, expected_buffer);
+    assert_eq!(
+        parser.hexdump_virt(0x1a42ea30240, 0x10).unwrap(),
+        "000001a4`2ea30240  e0 07 a3 2e a4 01 00 00-80 f2 a2 2e a4 01 00 00  ................\n"
+    );
     // Example of a valid PTE that don't have a physical page backing it (in
     // kerneldump.dmp):
     // ```
@@ -376,14 +373,14 @@ fn regressions() {
     let parser = KernelDumpParser::new(&kernel_dump.file).unwrap();
     let mut buffer = [0];
     assert!(matches!(
-        parser.virt_read(0x1a42ea30240.into(), &mut buffer),
+        parser.virt_read(0x1a42ea30240, &mut buffer),
         Err(KdmpParserError::AddrTranslation(
             AddrTranslationError::Phys(gpa)
         )) if gpa == 0x166b7240.into()
     ));
 
     assert!(matches!(
-        parser.virt_read(0x16e23fa060.into(), &mut buffer),
+        parser.virt_read(0x16e23fa060, &mut buffer),
         Err(KdmpParserError::AddrTranslation(
             AddrTranslationError::Phys(gpa)
         )) if gpa == 0x1bc4060.into()
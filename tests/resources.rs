@@ -0,0 +1,111 @@
+mod common;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use common::synthetic_dump;
+use kdmp_parser::{DumpType, KernelDumpParser};
+
+/// An allocator that keeps track of the heap that is allocated, and of its
+/// peak.
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+        PEAK.fetch_max(allocated, Ordering::SeqCst);
+
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::SeqCst);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Number of pages of [`full_dump`].
+const FULL_DUMP_PAGES: u64 = 0x8_000;
+
+/// Write a full dump of [`FULL_DUMP_PAGES`] zeroed pages at `path`; the pages
+/// aren't backed by anything on filesystems with sparse files.
+fn full_dump(path: &Path) {
+    let mut headers = synthetic_dump(&[]);
+    headers.truncate(0x2_000);
+    headers[0xf98..0xf9c].copy_from_slice(&1u32.to_le_bytes());
+    headers[0x88..0x8c].copy_from_slice(&1u32.to_le_bytes());
+    headers[0x90..0x98].copy_from_slice(&FULL_DUMP_PAGES.to_le_bytes());
+    headers[0x98..0xa0].copy_from_slice(&0u64.to_le_bytes());
+    headers[0xa0..0xa8].copy_from_slice(&FULL_DUMP_PAGES.to_le_bytes());
+
+    let mut file = File::create(path).unwrap();
+    file.write_all(&headers).unwrap();
+    file.set_len(0x2_000 + FULL_DUMP_PAGES * 0x1_000).unwrap();
+}
+
+/// Open the dump at `path` and make sure that the heap it took stays under
+/// its estimate, without the estimate being far off.
+fn check_estimate(path: &Path, dump_type: DumpType) {
+    let estimate = KernelDumpParser::estimate_resources(&path).unwrap();
+    assert_eq!(estimate.dump_type, dump_type);
+
+    let before = ALLOCATED.load(Ordering::SeqCst);
+    PEAK.store(before, Ordering::SeqCst);
+    let parser = KernelDumpParser::new(&path).unwrap();
+    let peak = PEAK.load(Ordering::SeqCst) - before;
+    let retained = ALLOCATED.load(Ordering::SeqCst) - before;
+
+    eprintln!(
+        "{}: {estimate:?}, peak {peak:#x}, retained {retained:#x}",
+        path.display()
+    );
+    assert_eq!(estimate.pages, parser.physmem_len());
+    assert!(peak as u64 <= estimate.peak_open_bytes);
+    assert!(retained <= peak);
+    // The estimate is an upper bound, but one that is in the same ballpark as
+    // what parsing takes.
+    assert!(estimate.index_bytes <= 2 * peak as u64);
+}
+
+/// The estimates of the synthetic dumps, and of the regression dumps when
+/// `TESTDATAS` points to them. It is the only test of this file, so that no
+/// other test allocates while the heap is measured.
+#[test]
+fn estimates() {
+    let path = env::temp_dir().join(format!("kdmp-parser-resources-{}.dmp", std::process::id()));
+    full_dump(&path);
+    check_estimate(&path, DumpType::Full);
+    File::create(&path)
+        .unwrap()
+        .write_all(&synthetic_dump(&[]))
+        .unwrap();
+    let estimate = KernelDumpParser::estimate_resources(&path).unwrap();
+    assert_eq!(estimate.mapped_bytes, estimate.file_size);
+    check_estimate(&path, DumpType::Bmp);
+    std::fs::remove_file(&path).unwrap();
+
+    let Ok(testdatas) = env::var("TESTDATAS") else {
+        return;
+    };
+
+    let testdatas = PathBuf::from(testdatas);
+    for (file, dump_type) in [
+        ("bmp.dmp", DumpType::Bmp),
+        ("full.dmp", DumpType::Full),
+        ("kerneldump.dmp", DumpType::KernelMemory),
+        ("kerneluserdump.dmp", DumpType::KernelAndUserMemory),
+        ("completedump.dmp", DumpType::CompleteMemory),
+    ] {
+        check_estimate(&testdatas.join(file), dump_type);
+    }
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use std::io;
+
+use common::{synthetic_dump, BMP_TOTAL_PRESENT_PAGES};
+use kdmp_parser::{CheckOutcome, KernelDumpParser};
+
+fn outcome(parser: &KernelDumpParser, name: &str) -> CheckOutcome {
+    let report = parser.verify().unwrap();
+
+    report
+        .checks
+        .into_iter()
+        .find(|check| check.name == name)
+        .unwrap()
+        .outcome
+}
+
+#[test]
+fn verify() {
+    let mut dump = synthetic_dump(&[]);
+    let parser = KernelDumpParser::with_reader(io::Cursor::new(dump.clone())).unwrap();
+    assert_eq!(outcome(&parser, "page count"), CheckOutcome::Passed);
+    assert_eq!(outcome(&parser, "page offsets"), CheckOutcome::Passed);
+    assert!(matches!(
+        outcome(&parser, "required dump space"),
+        CheckOutcome::Skipped(_)
+    ));
+    // The synthetic dumps don't have modules.
+    assert!(matches!(
+        outcome(&parser, "kernel module list"),
+        CheckOutcome::Failed(_)
+    ));
+
+    // Claim there are more pages than there are, and cut the last one short.
+    dump[BMP_TOTAL_PRESENT_PAGES] += 1;
+    dump.truncate(dump.len() - 1);
+    let parser = KernelDumpParser::with_reader(io::Cursor::new(dump)).unwrap();
+    let report = parser.verify().unwrap();
+    assert!(!report.is_ok());
+    assert!(matches!(
+        outcome(&parser, "page count"),
+        CheckOutcome::Failed(_)
+    ));
+    assert!(matches!(
+        outcome(&parser, "page offsets"),
+        CheckOutcome::Failed(_)
+    ));
+}
+
+#[cfg(feature = "sha2")]
+#[test]
+fn content_hash() {
+    use common::BMP_FIRST_PAGE;
+    use kdmp_parser::HashAlgorithm;
+
+    let hash = |dump: Vec<u8>| {
+        KernelDumpParser::with_reader(io::Cursor::new(dump))
+            .unwrap()
+            .content_hash(HashAlgorithm::Sha256)
+            .unwrap()
+    };
+
+    // The same memory, laid out differently in the file, hashes the same..
+    let dump = synthetic_dump(&[(0x1_337, b"hello")]);
+    let first_page =
+        u64::from_le_bytes(dump[BMP_FIRST_PAGE..BMP_FIRST_PAGE + 8].try_into().unwrap());
+    let mut moved = dump.clone();
+    let pages = moved.split_off(first_page as usize);
+    moved.resize(moved.len() + 0x1_000, 0xcc);
+    moved.extend_from_slice(&pages);
+    moved[BMP_FIRST_PAGE..BMP_FIRST_PAGE + 8]
+        .copy_from_slice(&(first_page + 0x1_000).to_le_bytes());
+    assert_eq!(hash(dump.clone()), hash(moved));
+
+    // ..but different memory doesn't.
+    assert_ne!(hash(dump), hash(synthetic_dump(&[(0x1_337, b"world")])));
+}